@@ -0,0 +1,100 @@
+//! Timings for the operations that get slow as lists grow: saving and loading
+//! the file, searching, sorting, completing a todo and drawing a frame
+//! Run with `cargo bench`, or `cargo bench -- search` for the ones whose name
+//! contains "search"; each is run on a small, a big and a huge list
+//! Criterion keeps the last run's results in `target/criterion` and says how
+//! much each benchmark changed since
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{backend::TestBackend, Terminal};
+use std::{env, fs, hint::black_box, path::PathBuf, process, time::Duration};
+use todo_tui::{ui, update, App, Date, Message, Priority, Status, TodoItem};
+
+/// How many todos the lists have
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// How long each benchmark is measured for
+/// Criterion's default of five seconds, times fifteen benchmarks, makes for a
+/// long wait for what's meant to be run before and after every change
+const TARGET: Duration = Duration::from_secs(2);
+
+fn core(c: &mut Criterion) {
+    let dir = env::temp_dir().join(format!("todo-tui-bench-{}", process::id()));
+    fs::create_dir_all(&dir).expect("creating the bench directory");
+
+    for size in SIZES {
+        let path = dir.join(format!("todos-{}.json", size));
+        write_list(path.clone(), size);
+        // Loaded like the app does at startup, so the view is ready to draw
+        let mut app = App::load(path.clone()).expect("loading the bench file");
+        let name = |name: &str| format!("{}/{}", name, size);
+
+        c.bench_function(&name("save"), |b| b.iter(|| app.save().expect("saving")));
+        c.bench_function(&name("load"), |b| b.iter(|| black_box(App::load(path.clone()).expect("loading"))));
+
+        // Typing a whole query, one refresh per key, then clearing it again
+        c.bench_function(&name("search"), |b| {
+            b.iter(|| {
+                press(&mut app, KeyCode::Char('/'));
+                for c in "todo 42".chars() {
+                    press(&mut app, KeyCode::Char(c));
+                }
+                press(&mut app, KeyCode::Esc);
+            })
+        });
+        // Each press moves on to the next sort order and sorts the list again
+        c.bench_function(&name("sort"), |b| b.iter(|| press(&mut app, KeyCode::Char('s'))));
+        // Completing the selected todo and taking it back, an edit that
+        // leaves every row where it was
+        c.bench_function(&name("toggle"), |b| b.iter(|| update(&mut app, Message::Toggle)));
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).expect("creating the test terminal");
+        // Moving down each frame, like holding `j`, so the list has to scroll
+        c.bench_function(&name("frame"), |b| {
+            b.iter(|| {
+                press(&mut app, KeyCode::Char('j'));
+                terminal.draw(|frame| ui(frame, &mut app)).expect("drawing");
+            })
+        });
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+criterion_group! {
+    name = benches;
+    // Ten samples is the fewest criterion takes - the huge list's saves are
+    // slow enough that more would run well past TARGET
+    config = Criterion::default().sample_size(10).measurement_time(TARGET);
+    targets = core
+}
+criterion_main!(benches);
+
+/// Writes a list of `size` todos, varied like a real list: tags, projects,
+/// priorities, due dates, some of it done, and subtasks under every tenth todo
+fn write_list(path: PathBuf, size: usize) {
+    let mut app = App::new(path);
+    let today = Date::today();
+    let mut todos = Vec::with_capacity(size);
+    while todos.len() < size {
+        let n = todos.len();
+        let mut todo = TodoItem::parse(&format!("Todo {} about something that has to happen", n));
+        todo.tags = vec![["work", "home", "errands"][n % 3].to_string()];
+        todo.project = (n % 4 == 0).then(|| format!("Project {}", n % 7));
+        todo.priority = [Priority::None, Priority::Low, Priority::Medium, Priority::High][n % 4];
+        todo.due = (n % 5 != 0).then(|| today.add_days((n % 60) as i64 - 20));
+        if n % 3 == 0 {
+            todo.status = Status::Done;
+        }
+        if n % 10 == 0 {
+            todo.children = (0..3).map(|i| TodoItem::parse(&format!("Step {} of todo {}", i, n))).collect();
+        }
+        todos.push(todo);
+    }
+    app.lists[0].todos = todos;
+    app.save().expect("writing the bench file");
+}
+
+fn press(app: &mut App, code: KeyCode) {
+    update(app, Message::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+}
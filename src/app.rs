@@ -0,0 +1,5724 @@
+//! The todo data model and the application state the interface works on
+
+use ratatui::{layout::Rect, widgets::ListState};
+
+// Serde provides serialization/deserialization for saving todos to disk
+// We import the derive macros to automatically implement these traits
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_segmentation::UnicodeSegmentation;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    crypto::Cipher,
+    email::{EmailConfig, MailCheck},
+    error::AppError,
+    github::{GitHubConfig, Refreshed},
+    history::{GitMode, HistoryView},
+    hooks::{HookRunner, HooksConfig},
+    i18n::{fill, Language},
+    input::{Action, Keymap, KeymapPreset, PendingKeys, Repeat},
+    logging::LogLevel,
+    plugins::Plugins,
+    row_format::RowFormat,
+    search::{children, Found, Matches, SearchIndex, Shape},
+    storage::{Config, DiskWatcher, FileStamp, OpLog, Recovery, Saver, Unsaved, DEFAULT_BACKUPS},
+    sync::{Remote, SyncOutcome, SyncStatus},
+    ui::{IconSet, Icons, RowCache, TagStyle, Theme},
+};
+
+/// Represents a single todo item in our list
+/// We derive Clone because we need to copy TodoItems when rendering the UI
+/// Serialize and Deserialize allow us to save/load todos from JSON files
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TodoItem {
+    pub text: String,
+    /// Where the todo stands - files from before statuses existed have a
+    /// `completed` bool instead, which the alias and deserializer still accept
+    #[serde(default, alias = "completed", deserialize_with = "deserialize_status")]
+    pub status: Status,
+    /// How urgent this todo is - defaults to None so files saved before
+    /// priorities existed still load without migration
+    #[serde(default)]
+    pub priority: Priority,
+    /// Starred todos come first among their siblings whatever the sort - a
+    /// lighter way than priorities to keep a few things in view
+    #[serde(default)]
+    pub starred: bool,
+    /// Free-form labels like "errands" or "work", stored without the leading '#'
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Where or with what the todo can be done, like "phone" or "office" -
+    /// todo.txt's @contexts, stored without the leading '@'
+    #[serde(default)]
+    pub contexts: Vec<String>,
+    /// The project the todo belongs to - unlike tags a todo has at most one,
+    /// which is what lets the list be grouped by it
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Subtasks nested under this todo - they can have children of their own
+    #[serde(default)]
+    pub children: Vec<TodoItem>,
+    /// Whether the subtasks are hidden in the list
+    /// Persisted so big trees stay folded the way the user left them
+    #[serde(default)]
+    pub collapsed: bool,
+    /// Longer free-form description, may span multiple lines
+    /// Only shown in the detail pane so list rows stay one line each
+    #[serde(default)]
+    pub notes: String,
+    /// Day the todo should be done by, if any
+    #[serde(default)]
+    pub due: Option<Date>,
+    /// Time of day on the due date, None when it's due some time that day
+    #[serde(default)]
+    pub due_time: Option<Time>,
+    /// How often the todo repeats - completing it moves `due` forward instead
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Day of the month a monthly todo belongs on when a shorter month moved
+    /// its due date earlier, so it goes back to that day afterwards
+    #[serde(default)]
+    pub repeat_day: Option<u32>,
+    /// Day the todo becomes actionable - the list leaves it out until then
+    /// Set with `start:` when adding, or from an org-mode SCHEDULED date
+    #[serde(default)]
+    pub scheduled: Option<Date>,
+    /// Until when the todo is snoozed - the list leaves it out until then,
+    /// and it comes back by itself
+    #[serde(default)]
+    pub snoozed_until: Option<Timestamp>,
+    /// When the todo was created - None for todos from older files
+    /// The alias reads the day-only field earlier versions wrote for todo.txt
+    #[serde(default, alias = "created")]
+    pub created_at: Option<Timestamp>,
+    /// When the todo was last marked done, None while it's open
+    #[serde(default, alias = "completed_on")]
+    pub completed_at: Option<Timestamp>,
+    /// Number of focus intervals finished on this todo with the pomodoro timer
+    #[serde(default)]
+    pub pomodoros: u32,
+    /// Seconds logged with the time tracker, not counting a session that's running
+    #[serde(default)]
+    pub time_spent: u64,
+    /// When the running time tracking session started, None when not tracking
+    /// Kept in the todo so tracking carries on across restarts
+    #[serde(default)]
+    pub tracking_since: Option<Timestamp>,
+    /// When the todo was last added or changed, which sync compares with the
+    /// other side to settle conflicts - None until the first change
+    #[serde(default)]
+    pub modified_at: Option<Timestamp>,
+    /// What was changed on the todo and when, oldest first - kept to the last
+    /// `HISTORY_LIMIT` changes, and like the links below not synced
+    #[serde(default)]
+    pub history: Vec<Change>,
+    /// Id of the task the todo is synced with on each service, by the
+    /// service's key - "todoist", "caldav", "google", or "server" for the id
+    /// it has on every device syncing through a `serve` server
+    #[serde(default)]
+    pub remote_ids: BTreeMap<String, String>,
+    /// The GitHub issue the todo shows, as "owner/name#number"
+    #[serde(default)]
+    pub github_issue: Option<String>,
+}
+
+/// Changes a todo's history keeps before dropping the oldest
+pub(crate) const HISTORY_LIMIT: usize = 20;
+
+/// One entry in a todo's history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub at: Timestamp,
+    #[serde(flatten)]
+    pub field: Field,
+}
+
+/// What part of a todo changed, with the new value where it's short enough to
+/// show - texts, notes and tags only say that they changed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "field", content = "to", rename_all = "snake_case")]
+pub enum Field {
+    Text,
+    Notes,
+    Status(Status),
+    Priority(Priority),
+    Starred(bool),
+    Due(Option<Date>),
+    Recurrence(Option<Recurrence>),
+    Tags,
+    Project(Option<String>),
+}
+
+/// Priority levels a todo can have, ordered from least to most urgent
+/// Deriving Ord lets us sort by priority directly, so variant order matters here
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    /// Returns the next priority in the cycle None -> Low -> Medium -> High -> None
+    /// Wrapping back to None lets a single key both raise and clear priority
+    pub(crate) fn cycle(self) -> Priority {
+        match self {
+            Priority::None => Priority::Low,
+            Priority::Low => Priority::Medium,
+            Priority::Medium => Priority::High,
+            Priority::High => Priority::None,
+        }
+    }
+
+    /// Short marker shown in front of the todo text
+    /// Items without a priority get blank padding so the text column stays aligned
+    pub fn marker(self) -> &'static str {
+        match self {
+            Priority::None => "    ",
+            Priority::Low => "!   ",
+            Priority::Medium => "!!  ",
+            Priority::High => "!!! ",
+        }
+    }
+
+    /// Parses the quick-add form of a priority: `!high`, `!medium`, `!low` or
+    /// their first letters, and `!med` for people who type it that way
+    pub(crate) fn parse(word: &str) -> Option<Priority> {
+        match word.strip_prefix('!')?.to_lowercase().as_str() {
+            "high" | "h" => Some(Priority::High),
+            "medium" | "med" | "m" => Some(Priority::Medium),
+            "low" | "l" => Some(Priority::Low),
+            _ => None,
+        }
+    }
+
+    /// Name shown in the detail pane
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Priority::None => "None",
+            Priority::Low => "Low",
+            Priority::Medium => "Medium",
+            Priority::High => "High",
+        }
+    }
+
+    /// Lowercase name, as used in queries and exports
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+
+    /// The quick-add token that `parse` reads back, None when there's no priority
+    fn token(self) -> Option<&'static str> {
+        match self {
+            Priority::None => None,
+            Priority::Low => Some("!low"),
+            Priority::Medium => Some("!medium"),
+            Priority::High => Some("!high"),
+        }
+    }
+}
+
+/// Progress of a todo, which is also the column it sits in on the board
+/// Done and Cancelled todos are closed, the others still open
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    #[default]
+    Pending,
+    InProgress,
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+impl Status {
+    /// Every status, in the order the status key cycles through them
+    pub(crate) const ALL: [Status; 5] = [Status::Pending, Status::InProgress, Status::Blocked, Status::Done, Status::Cancelled];
+
+    /// The status after this one when cycling, wrapping back to Pending
+    pub(crate) fn next(self) -> Status {
+        let i = Status::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Status::ALL[(i + 1) % Status::ALL.len()]
+    }
+
+    /// Name shown in the detail pane and as the board column title
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Status::Pending => "Todo",
+            Status::InProgress => "In Progress",
+            Status::Blocked => "Blocked",
+            Status::Done => "Done",
+            Status::Cancelled => "Cancelled",
+        }
+    }
+
+    /// Name used in files and the config, the same as in JSON
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::InProgress => "in_progress",
+            Status::Blocked => "blocked",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    /// Parses the name `key` returns
+    pub(crate) fn from_key(key: &str) -> Option<Status> {
+        Status::ALL.into_iter().find(|status| status.key() == key)
+    }
+
+    /// Whether the todo still needs doing - false for done and cancelled todos
+    fn is_open(self) -> bool {
+        !matches!(self, Status::Done | Status::Cancelled)
+    }
+}
+
+/// Reads a status, or the `completed` bool older files have in its place
+fn deserialize_status<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Status, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Completed(bool),
+        Status(Status),
+    }
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::Completed(true) => Status::Done,
+        Repr::Completed(false) => Status::Pending,
+        Repr::Status(status) => status,
+    })
+}
+
+/// A calendar day without time of day or timezone
+/// We only need day granularity for due dates, and a hand-rolled type saves a
+/// heavy date/time dependency for a few dozen lines of arithmetic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Builds a date, returning None for impossible days like February 30th
+    pub(crate) fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        if (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month) {
+            Some(Date { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    /// Today's date in the user's local timezone
+    pub fn today() -> Date {
+        Timestamp::now().date()
+    }
+
+    /// Converts a count of days since 1970-01-01 into a date
+    /// Uses Howard Hinnant's civil_from_days algorithm, which is exact for any day
+    pub(crate) fn from_days(days: i64) -> Date {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+
+    /// Converts the date into days since 1970-01-01 - the inverse of `from_days`
+    fn to_days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// Returns the date `n` days later (or earlier for negative `n`)
+    pub fn add_days(self, n: i64) -> Date {
+        Date::from_days(self.to_days() + n)
+    }
+
+    /// Returns the same day `n` months later, clamped to the end of shorter months
+    /// so January 31st plus one month is the last day of February
+    /// None when the year would no longer fit, which only typed counts get to
+    fn add_months(self, n: i32) -> Option<Date> {
+        let total = i64::from(self.year) * 12 + i64::from(self.month) - 1 + i64::from(n);
+        let year = i32::try_from(total.div_euclid(12)).ok()?;
+        let month = total.rem_euclid(12) as u32 + 1;
+        Some(Date { year, month, day: 1 }.with_day(self.day))
+    }
+
+    /// The same month on `day`, or on its last day when it's shorter
+    fn with_day(self, day: u32) -> Date {
+        Date { day: day.min(days_in_month(self.year, self.month)), ..self }
+    }
+
+    /// Parses an ISO `YYYY-MM-DD` date
+    pub(crate) fn parse(s: &str) -> Option<Date> {
+        let mut parts = s.trim().splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        Date::new(year, month, day)
+    }
+
+    /// Parses a date the way people type it, relative to `today`: ISO dates,
+    /// `today`, `tomorrow`, `in 3 days` (or weeks, months), `next week`,
+    /// `next month`, and weekday names (`fri`, `next monday`) meaning the next
+    /// such day after today
+    fn parse_relative(s: &str, today: Date) -> Option<Date> {
+        let s = s.trim().to_lowercase();
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["today" | "tod"] => Some(today),
+            ["tomorrow" | "tom"] => Some(today.add_days(1)),
+            ["next", "week"] => Some(today.add_days(7)),
+            ["next", "month"] => today.add_months(1),
+            ["in", count, unit] => {
+                let count: i32 = count.parse().ok()?;
+                match unit.trim_end_matches('s') {
+                    "day" => Some(today.add_days(i64::from(count))),
+                    "week" => Some(today.add_days(7 * i64::from(count))),
+                    "month" => today.add_months(count),
+                    _ => None,
+                }
+            }
+            // "next friday" reads the same as "friday" - the first one after today
+            ["next", day] | [day] if weekday_from_name(day).is_some() => {
+                let target = weekday_from_name(day)?;
+                let ahead = (i64::from(target) - i64::from(today.weekday()) - 1).rem_euclid(7) + 1;
+                Some(today.add_days(ahead))
+            }
+            [date] => Date::parse(date),
+            _ => None,
+        }
+    }
+
+    /// Day of the week, 0 for Monday through 6 for Sunday
+    pub(crate) fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.to_days() + 3).rem_euclid(7) as u32
+    }
+
+    /// Short English name of the weekday, for previews
+    pub(crate) fn weekday_name(self) -> &'static str {
+        ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"][self.weekday() as usize]
+    }
+}
+
+/// Weekday number (0 for Monday) from a full or abbreviated English name
+/// Three letters are enough to tell weekdays apart, so shorter prefixes don't count
+fn weekday_from_name(name: &str) -> Option<u32> {
+    const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+    if name.len() < 3 {
+        return None;
+    }
+    WEEKDAYS.iter().position(|day| day.starts_with(name)).map(|i| i as u32)
+}
+
+/// Parses a due date with an optional time of day: `friday 5pm`, `tomorrow at 9:30`,
+/// `2025-06-01 17:00`, or just a time like `5pm` for later today
+pub(crate) fn parse_due(s: &str, today: Date) -> Option<(Date, Option<Time>)> {
+    let s = s.trim();
+    if let Some(time) = Time::parse(s) {
+        return Some((today, Some(time)));
+    }
+    if let Some((date, time)) = s.rsplit_once(char::is_whitespace)
+        && let Some(time) = Time::parse(time)
+    {
+        let date = date.trim_end();
+        let date = date.strip_suffix(" at").unwrap_or(date);
+        return Some((Date::parse_relative(date, today)?, Some(time)));
+    }
+    Some((Date::parse_relative(s, today)?, None))
+}
+
+/// A due date and optional time as shown in lists and files: `2025-06-01 17:00`
+pub(crate) fn format_due(date: Date, time: Option<Time>) -> String {
+    match time {
+        Some(time) => format!("{} {}", date, time),
+        None => date.to_string(),
+    }
+}
+
+/// How dates are shown, set by "date_format" in the config
+/// Only for showing them - dates are always typed and stored as 2025-06-01
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateFormat {
+    /// 2025-06-01
+    #[default]
+    #[serde(rename = "iso")]
+    Iso,
+    /// 01.06.2025
+    #[serde(rename = "dd.mm.yyyy")]
+    DayDotMonth,
+    /// 01/06/2025
+    #[serde(rename = "dd/mm/yyyy")]
+    DaySlashMonth,
+    /// 06/01/2025
+    #[serde(rename = "mm/dd/yyyy")]
+    MonthSlashDay,
+    /// "today", "in 2 days" or "3 weeks ago", and the ISO date further out
+    #[serde(rename = "relative")]
+    Relative,
+}
+
+/// Whether times are shown as 17:30 or 5:30pm, set by "clock" in the config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Clock {
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+/// A day of the week, for "week_start" in the config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    #[default]
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Its number as `Date::weekday` counts, 0 for Monday
+    pub(crate) fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// The config's choices for showing dates and times, applied everywhere a
+/// date is shown to the user: the list, the details, the board, the agenda
+/// and the `list` subcommand - but not in files, which stay readable to
+/// other tools
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DateStyle {
+    pub(crate) format: DateFormat,
+    pub(crate) clock: Clock,
+    /// The day weeks start on in the completions calendar
+    pub(crate) week_start: Weekday,
+}
+
+impl DateStyle {
+    /// A date the way the config says
+    pub fn date(&self, date: Date) -> String {
+        match self.format {
+            DateFormat::Iso => date.to_string(),
+            DateFormat::DayDotMonth => format!("{:02}.{:02}.{:04}", date.day, date.month, date.year),
+            DateFormat::DaySlashMonth => format!("{:02}/{:02}/{:04}", date.day, date.month, date.year),
+            DateFormat::MonthSlashDay => format!("{:02}/{:02}/{:04}", date.month, date.day, date.year),
+            DateFormat::Relative => {
+                let days = date.to_days() - Date::today().to_days();
+                match days {
+                    0 => "today".to_string(),
+                    1 => "tomorrow".to_string(),
+                    -1 => "yesterday".to_string(),
+                    2..=13 => format!("in {} days", days),
+                    -13..=-2 => format!("{} days ago", -days),
+                    14..=62 => format!("in {} weeks", days / 7),
+                    -62..=-14 => format!("{} weeks ago", -days / 7),
+                    // Months are too vague for a due date
+                    _ => date.to_string(),
+                }
+            }
+        }
+    }
+
+    /// A time of day the way the config says, 12-hour ones like "5:30pm"
+    pub fn time(&self, time: Time) -> String {
+        match self.clock {
+            Clock::TwentyFourHour => time.to_string(),
+            Clock::TwelveHour => {
+                let suffix = if time.hour < 12 { "am" } else { "pm" };
+                let hour = match time.hour % 12 {
+                    0 => 12,
+                    hour => hour,
+                };
+                match time.minute {
+                    0 => format!("{}{}", hour, suffix),
+                    minute => format!("{}:{:02}{}", hour, minute, suffix),
+                }
+            }
+        }
+    }
+
+    /// A due date and optional time, like `format_due` but the config's way
+    pub fn due(&self, date: Date, time: Option<Time>) -> String {
+        match time {
+            Some(time) => format!("{} {}", self.date(date), self.time(time)),
+            None => self.date(date),
+        }
+    }
+
+    /// When a todo is due, None if it isn't
+    pub fn due_label(&self, todo: &TodoItem) -> Option<String> {
+        todo.due.map(|date| self.due(date, todo.due_time))
+    }
+
+    /// A moment as a local date and time
+    pub fn timestamp(&self, at: Timestamp) -> String {
+        let local = at.0 + local_offset_seconds(at.0);
+        let minutes = local.rem_euclid(86_400) / 60;
+        let time = Time { hour: (minutes / 60) as u32, minute: (minutes % 60) as u32 };
+        format!("{} {}", self.date(at.date()), self.time(time))
+    }
+
+    /// A moment typed like a due date, the time left out at midnight the way
+    /// `Timestamp::at` reads a day without one
+    pub(crate) fn moment(&self, at: Timestamp) -> String {
+        match at.time() {
+            Time { hour: 0, minute: 0 } => self.date(at.date()),
+            _ => self.timestamp(at),
+        }
+    }
+}
+
+/// A time of day with minute precision, used for due times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time {
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl Time {
+    /// Parses 24-hour `17:00`, 12-hour `5pm` or `5:30pm`, or `noon`
+    /// A bare number isn't a time, so "due:tomorrow 3 apples" keeps its text
+    pub(crate) fn parse(s: &str) -> Option<Time> {
+        let s = s.trim().to_lowercase();
+        if s == "noon" {
+            return Some(Time { hour: 12, minute: 0 });
+        }
+        let (clock, pm) = match (s.strip_suffix("am"), s.strip_suffix("pm")) {
+            (Some(clock), _) => (clock, Some(false)),
+            (_, Some(clock)) => (clock, Some(true)),
+            _ => (s.as_str(), None),
+        };
+        let (hour, minute) = match clock.split_once(':') {
+            Some((hour, minute)) if minute.len() == 2 => (hour.parse().ok()?, minute.parse().ok()?),
+            None if pm.is_some() => (clock.parse().ok()?, 0),
+            _ => return None,
+        };
+        let hour = match pm {
+            // 12am is midnight and 12pm is noon
+            Some(pm) if (1..=12).contains(&hour) => hour % 12 + if pm { 12 } else { 0 },
+            Some(_) => return None,
+            None => hour,
+        };
+        (hour < 24 && minute < 60).then_some(Time { hour, minute })
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+// Stored as "HH:MM" like dates are stored as readable strings
+impl Serialize for Time {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Time {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Time, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Time::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid time '{}'", s)))
+    }
+}
+
+/// Number of days in a month, accounting for leap years
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Offset of local time from UTC in seconds at the given Unix time, so "today"
+/// flips at local midnight and past timestamps use the DST rules of their day
+/// The standard library has no timezone support, so we ask the C library
+#[cfg(unix)]
+fn local_offset_seconds(at: i64) -> i64 {
+    // SAFETY: localtime_r only writes into the tm struct we own, and a zeroed
+    // tm is a valid value for it to overwrite
+    unsafe {
+        let time = at as libc::time_t;
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff
+    }
+}
+
+/// Non-unix fallback - dates are computed in UTC
+#[cfg(not(unix))]
+fn local_offset_seconds(_at: i64) -> i64 {
+    0
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+// Dates are stored as "YYYY-MM-DD" strings so the JSON stays readable and hand-editable
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Date::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid date '{}'", s)))
+    }
+}
+
+/// A moment in time as whole seconds since the Unix epoch
+/// Used for when todos were created and completed - like `Date`, hand-rolled
+/// to avoid a date/time dependency
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub(crate) i64);
+
+impl Timestamp {
+    /// The current time
+    pub fn now() -> Timestamp {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Timestamp(secs)
+    }
+
+    /// Local midnight at the start of the given day
+    /// For formats like todo.txt that only record the date
+    pub fn from_date(date: Date) -> Timestamp {
+        let midnight_utc = date.to_days() * 86_400;
+        Timestamp(midnight_utc - local_offset_seconds(midnight_utc))
+    }
+
+    /// The moment a local day and time of day fall on, the start of the day
+    /// without a time
+    pub(crate) fn at(date: Date, time: Option<Time>) -> Timestamp {
+        let time = time.map_or(0, |t| i64::from(t.hour * 3600 + t.minute * 60));
+        Timestamp(Timestamp::from_date(date).0 + time)
+    }
+
+    /// The local calendar day this moment falls on
+    pub(crate) fn date(self) -> Date {
+        Date::from_days((self.0 + local_offset_seconds(self.0)).div_euclid(86_400))
+    }
+
+    /// The local time of day of this moment
+    pub(crate) fn time(self) -> Time {
+        let seconds = (self.0 + local_offset_seconds(self.0)).rem_euclid(86_400) as u32;
+        Time { hour: seconds / 3600, minute: seconds % 3600 / 60 }
+    }
+
+    /// Parses the UTC `YYYY-MM-DDTHH:MM:SSZ` form we write, or a bare date
+    pub(crate) fn parse(s: &str) -> Option<Timestamp> {
+        let Some((date, time)) = s.split_once('T') else {
+            return Date::parse(s).map(Timestamp::from_date);
+        };
+        let mut parts = time.strip_suffix('Z')?.splitn(3, ':');
+        let hour: i64 = parts.next()?.parse().ok().filter(|h| (0..24).contains(h))?;
+        let minute: i64 = parts.next()?.parse().ok().filter(|m| (0..60).contains(m))?;
+        let second: i64 = parts.next()?.parse().ok().filter(|s| (0..61).contains(s))?;
+        let days = Date::parse(date)?.to_days();
+        Some(Timestamp(days * 86_400 + hour * 3600 + minute * 60 + second))
+    }
+}
+
+/// Shown in local time to the minute, which is all the detail pane needs
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let local = self.0 + local_offset_seconds(self.0);
+        let secs = local.rem_euclid(86_400);
+        write!(f, "{} {:02}:{:02}", self.date(), secs / 3600, secs % 3600 / 60)
+    }
+}
+
+// Timestamps are stored as UTC "YYYY-MM-DDTHH:MM:SSZ" strings, readable and
+// independent of the timezone the file was written in
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let secs = self.0.rem_euclid(86_400);
+        serializer.collect_str(&format_args!(
+            "{}T{:02}:{:02}:{:02}Z",
+            Date::from_days(self.0.div_euclid(86_400)),
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Timestamp::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp '{}'", s)))
+    }
+}
+
+/// How often a recurring todo comes back
+/// Files and synced todos go through `RawRecurrence`, so every zero days
+/// can't sneak in around `parse`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawRecurrence")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+    EveryNDays(u32),
+}
+
+/// How a recurrence is written in files, before the day count is checked
+#[derive(Deserialize)]
+enum RawRecurrence {
+    Daily,
+    Weekly,
+    Monthly,
+    EveryNDays(u32),
+}
+
+impl TryFrom<RawRecurrence> for Recurrence {
+    type Error = String;
+
+    fn try_from(raw: RawRecurrence) -> Result<Recurrence, String> {
+        match raw {
+            RawRecurrence::Daily => Ok(Recurrence::Daily),
+            RawRecurrence::Weekly => Ok(Recurrence::Weekly),
+            RawRecurrence::Monthly => Ok(Recurrence::Monthly),
+            RawRecurrence::EveryNDays(0) => Err("a todo can't repeat every 0 days".to_string()),
+            RawRecurrence::EveryNDays(n) => Ok(Recurrence::EveryNDays(n)),
+        }
+    }
+}
+
+impl Recurrence {
+    /// Repeating every `days` days, None for zero days, which would never
+    /// get anywhere
+    pub(crate) fn every(days: u32) -> Option<Recurrence> {
+        match days {
+            0 => None,
+            1 => Some(Recurrence::Daily),
+            n => Some(Recurrence::EveryNDays(n)),
+        }
+    }
+
+    /// The occurrence after `date` according to this rule
+    /// Monthly todos fall on `day` of the month, or the month's last day when
+    /// it's shorter, so a todo of the 31st goes back to the 31st after February
+    pub(crate) fn next(self, date: Date, day: u32) -> Date {
+        match self {
+            Recurrence::Daily => date.add_days(1),
+            Recurrence::Weekly => date.add_days(7),
+            // Only a date at the end of time has no next month, and stays put
+            Recurrence::Monthly => date.add_months(1).map_or(date, |next| next.with_day(day)),
+            Recurrence::EveryNDays(n) => date.add_days(i64::from(n)),
+        }
+    }
+
+    /// Parses what the user typed into the recurrence prompt
+    /// Accepts "daily", "weekly", "monthly", and "every N days" / "N days" / "Nd"
+    pub(crate) fn parse(s: &str) -> Option<Recurrence> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "daily" | "day" | "every day" => return Some(Recurrence::Daily),
+            "weekly" | "week" | "every week" => return Some(Recurrence::Weekly),
+            "monthly" | "month" | "every month" => return Some(Recurrence::Monthly),
+            _ => {}
+        }
+        let count = s.strip_prefix("every").unwrap_or(&s).trim();
+        let count = count
+            .strip_suffix("days")
+            .or_else(|| count.strip_suffix("day"))
+            .or_else(|| count.strip_suffix('d'))
+            .unwrap_or(count)
+            .trim();
+        count.parse().ok().and_then(Recurrence::every)
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly => write!(f, "weekly"),
+            Recurrence::Monthly => write!(f, "monthly"),
+            Recurrence::EveryNDays(n) => write!(f, "every {} days", n),
+        }
+    }
+}
+
+/// When a saved filter wants todos to be due
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DueFilter {
+    /// Past their due date or time
+    Overdue,
+    /// Due today, or overdue
+    Today,
+    /// Due within the next seven days, or overdue
+    Week,
+    /// Due at all
+    Any,
+    /// Without a due date
+    None,
+}
+
+/// A named filter - a smart list - defined in the config or saved from the app
+/// Every criterion that's set has to match, and unset ones match everything
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SavedFilter {
+    pub(crate) name: String,
+    /// Tag the todo must carry, or '@' and a context
+    pub(crate) tag: Option<String>,
+    /// Only todos without any tags or contexts
+    pub(crate) untagged: bool,
+    /// Project the todo must belong to
+    pub(crate) project: Option<String>,
+    /// Status the todo must have
+    pub(crate) status: Option<Status>,
+    /// When the todo must be due
+    pub(crate) due: Option<DueFilter>,
+    /// Text the todo must contain, ignoring case like a search
+    pub(crate) text: String,
+    /// The plugin filter deciding as well, by its index among the plugins'
+    /// filters - those come from the plugins each start, so it isn't saved
+    #[serde(skip)]
+    pub(crate) plugin: Option<usize>,
+}
+
+impl SavedFilter {
+    /// A filter with just a name, for building the defaults
+    pub(crate) fn named(name: &str) -> SavedFilter {
+        SavedFilter { name: name.to_string(), ..SavedFilter::default() }
+    }
+
+    /// Whether the todo itself passes the filter
+    pub(crate) fn matches(&self, todo: &TodoItem, today: Date) -> bool {
+        let due_ok = match self.due {
+            None => true,
+            Some(DueFilter::Overdue) => todo.is_overdue(),
+            Some(DueFilter::Today) => todo.due == Some(today) || todo.is_overdue(),
+            Some(DueFilter::Week) => todo.is_overdue() || todo.due.is_some_and(|due| (today..=today.add_days(6)).contains(&due)),
+            Some(DueFilter::Any) => todo.due.is_some(),
+            Some(DueFilter::None) => todo.due.is_none(),
+        };
+        due_ok
+            && self.tag.as_ref().is_none_or(|tag| todo.has_label(tag))
+            && (!self.untagged || (todo.tags.is_empty() && todo.contexts.is_empty()))
+            && self.project.as_ref().is_none_or(|project| todo.project.as_ref() == Some(project))
+            && self.status.is_none_or(|status| todo.status == status)
+            && (self.text.is_empty() || todo.text.to_lowercase().contains(&self.text.to_lowercase()))
+    }
+}
+
+/// A comparison in a filter query, like the `<` in `due<2025-01-01`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Whether a value that compares to the operand as `ordering` passes
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Comparison::Less => ordering == Less,
+            Comparison::LessOrEqual => ordering != Greater,
+            Comparison::Equal => ordering == Equal,
+            Comparison::GreaterOrEqual => ordering != Less,
+            Comparison::Greater => ordering == Greater,
+        }
+    }
+}
+
+/// A single condition of a filter query
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Condition {
+    /// `#tag` or `tag:NAME`
+    Tag(String),
+    /// `@context` or `context:NAME`
+    Context(String),
+    /// `+project` or `project:NAME`
+    Project(String),
+    /// `status:KEY`
+    Status(Status),
+    /// `done` - marked done
+    Done,
+    /// `open` - neither done nor cancelled
+    Open,
+    /// `overdue` - open and past its due date or time
+    Overdue,
+    /// `priority>=medium` and friends
+    Priority(Comparison, Priority),
+    /// `due<2025-01-01`, `due<=tomorrow` and friends
+    Due(Comparison, Date),
+    /// `due:any` (true) or `due:none` (false)
+    HasDue(bool),
+    /// A bare word or `text:WORD`, stored lowercase
+    Text(String),
+}
+
+/// A parsed filter query, as typed after `:filter`
+/// NOT binds tighter than AND, which binds tighter than OR, and terms next to
+/// each other are ANDed like in a search engine
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Query {
+    Condition(Condition),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+/// A token of a filter query
+#[derive(Debug, PartialEq)]
+enum QueryToken {
+    Open,
+    Close,
+    /// A word with any quotes removed, so `text:"buy milk"` is one word
+    Word(String),
+}
+
+impl Query {
+    /// Parses a query, with errors saying what's wrong and at which column
+    pub(crate) fn parse(input: &str, today: Date) -> Result<Query, String> {
+        let tokens = tokenize_query(input)?;
+        if tokens.is_empty() {
+            return Err("empty query".to_string());
+        }
+        if let Some((_, column)) = tokens.iter().filter(|(token, _)| matches!(token, QueryToken::Word(_))).nth(QUERY_WORD_LIMIT) {
+            return Err(format!("column {}: more than {} words", column, QUERY_WORD_LIMIT));
+        }
+        let mut parser = QueryParser { tokens: &tokens, position: 0, today, end: input.chars().count() + 1, depth: 0 };
+        let query = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(query),
+            Some((QueryToken::Close, column)) => Err(format!("column {}: unmatched ')'", column)),
+            Some((_, column)) => Err(format!("column {}: expected AND, OR or the end", column)),
+        }
+    }
+
+    /// Whether the todo itself passes the query
+    /// Relative dates were fixed when parsing, so this doesn't need today's date
+    pub(crate) fn matches(&self, todo: &TodoItem) -> bool {
+        match self {
+            Query::Condition(condition) => condition.matches(todo),
+            Query::Not(query) => !query.matches(todo),
+            Query::And(left, right) => left.matches(todo) && right.matches(todo),
+            Query::Or(left, right) => left.matches(todo) || right.matches(todo),
+        }
+    }
+}
+
+impl Condition {
+    /// Whether the todo passes this one condition
+    pub(crate) fn matches(&self, todo: &TodoItem) -> bool {
+        match self {
+            Condition::Tag(tag) => todo.tags.contains(tag),
+            Condition::Context(context) => todo.contexts.contains(context),
+            Condition::Project(project) => todo.project.as_ref() == Some(project),
+            Condition::Status(status) => todo.status == *status,
+            Condition::Done => todo.is_done(),
+            Condition::Open => !todo.is_closed(),
+            Condition::Overdue => todo.is_overdue(),
+            Condition::Priority(comparison, priority) => comparison.holds(todo.priority.cmp(priority)),
+            Condition::Due(comparison, date) => todo.due.is_some_and(|due| comparison.holds(due.cmp(date))),
+            Condition::HasDue(has) => todo.due.is_some() == *has,
+            Condition::Text(text) => todo.text.to_lowercase().contains(text),
+        }
+    }
+
+    /// Parses one word of a query into a condition
+    pub(crate) fn parse(word: &str, column: usize, today: Date) -> Result<Condition, String> {
+        let error = |message: String| Err(format!("column {}: {}", column, message));
+        match word.to_lowercase().as_str() {
+            "done" => return Ok(Condition::Done),
+            "open" => return Ok(Condition::Open),
+            "overdue" => return Ok(Condition::Overdue),
+            _ => {}
+        }
+        if let Some(tag) = word.strip_prefix('#').filter(|t| !t.is_empty()) {
+            return Ok(Condition::Tag(tag.to_string()));
+        }
+        if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            return Ok(Condition::Context(context.to_string()));
+        }
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            return Ok(Condition::Project(project.to_string()));
+        }
+
+        // Anything else is either FIELD OP VALUE or plain text
+        let Some(at) = word.find([':', '<', '>', '=']).filter(|&at| at > 0 && word[..at].chars().all(char::is_alphabetic)) else {
+            return Ok(Condition::Text(word.to_lowercase()));
+        };
+        let field = word[..at].to_lowercase();
+        let rest = &word[at..];
+        let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+            (Comparison::LessOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Comparison::Less, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Comparison::Greater, value)
+        } else {
+            // ':' and '=' both mean equal
+            (Comparison::Equal, &rest[1..])
+        };
+        if value.is_empty() {
+            return error(format!("missing value after '{}'", &word[..word.len() - value.len()]));
+        }
+        let ordered = comparison != Comparison::Equal;
+        match field.as_str() {
+            "tag" | "context" | "project" | "status" | "text" if ordered => {
+                error(format!("'{}' can only be compared with ':'", field))
+            }
+            "tag" => Ok(Condition::Tag(value.to_string())),
+            "context" => Ok(Condition::Context(value.to_string())),
+            "project" => Ok(Condition::Project(value.to_string())),
+            "text" => Ok(Condition::Text(value.to_lowercase())),
+            "status" => match Status::from_key(&value.to_lowercase()) {
+                Some(status) => Ok(Condition::Status(status)),
+                None => error(format!(
+                    "unknown status '{}' (expected pending, in_progress, blocked, done or cancelled)",
+                    value
+                )),
+            },
+            "priority" | "prio" => {
+                let priority = match value.to_lowercase().as_str() {
+                    "none" => Some(Priority::None),
+                    other => Priority::parse(&format!("!{}", other)),
+                };
+                match priority {
+                    Some(priority) => Ok(Condition::Priority(comparison, priority)),
+                    None => error(format!("unknown priority '{}' (expected none, low, medium or high)", value)),
+                }
+            }
+            "due" => match value.to_lowercase().as_str() {
+                "none" | "any" if ordered => error("due:none and due:any can't be compared".to_string()),
+                "none" => Ok(Condition::HasDue(false)),
+                "any" => Ok(Condition::HasDue(true)),
+                _ => match Date::parse_relative(value, today) {
+                    Some(date) => Ok(Condition::Due(comparison, date)),
+                    None => error(format!("invalid date '{}'", value)),
+                },
+            },
+            _ => error(format!(
+                "unknown field '{}' (expected tag, project, status, priority, due or text)",
+                field
+            )),
+        }
+    }
+}
+
+/// Splits a query into parentheses and words, with 1-based columns
+/// Double quotes group words with spaces or parentheses in them
+fn tokenize_query(input: &str) -> Result<Vec<(QueryToken, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        let column = i + 1;
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push((QueryToken::Open, column));
+            }
+            ')' => {
+                chars.next();
+                tokens.push((QueryToken::Close, column));
+            }
+            _ => {
+                let mut word = String::new();
+                let mut quote = None;
+                while let Some(&(j, c)) = chars.peek() {
+                    match (c, quote) {
+                        ('"', None) => quote = Some(j + 1),
+                        ('"', Some(_)) => quote = None,
+                        (c, None) if c.is_whitespace() || c == '(' || c == ')' => break,
+                        (c, _) => word.push(c),
+                    }
+                    chars.next();
+                }
+                if let Some(start) = quote {
+                    return Err(format!("column {}: unterminated quote", start));
+                }
+                tokens.push((QueryToken::Word(word), column));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// How deep parentheses and NOTs can nest in a query, which keeps a long run
+/// of either from overflowing the stack of the parser
+const QUERY_DEPTH_LIMIT: usize = 64;
+
+/// How many words a query can have - every AND and OR nests the query one
+/// level deeper, and matching walks it recursively too
+const QUERY_WORD_LIMIT: usize = 256;
+
+/// Recursive descent parser over the tokens of a query
+struct QueryParser<'a> {
+    tokens: &'a [(QueryToken, usize)],
+    pub(crate) position: usize,
+    pub(crate) today: Date,
+    /// Column just past the end of the input, for "expected ... at the end" errors
+    pub(crate) end: usize,
+    /// Parentheses and NOTs the parser is inside of
+    depth: usize,
+}
+
+impl QueryParser<'_> {
+    /// Parses what follows a '(' or NOT at `column`, one level deeper
+    fn nested(&mut self, column: usize, parse: impl FnOnce(&mut Self) -> Result<Query, String>) -> Result<Query, String> {
+        if self.depth == QUERY_DEPTH_LIMIT {
+            return Err(format!("column {}: nested more than {} deep", column, QUERY_DEPTH_LIMIT));
+        }
+        self.depth += 1;
+        let query = parse(self);
+        self.depth -= 1;
+        query
+    }
+
+    /// Whether the next token is the keyword, in any case
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some((QueryToken::Word(w), _)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    /// or := and (OR and)*
+    pub(crate) fn or(&mut self) -> Result<Query, String> {
+        let mut query = self.and()?;
+        while self.at_keyword("or") {
+            self.position += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    /// and := not ([AND] not)*
+    pub(crate) fn and(&mut self) -> Result<Query, String> {
+        let mut query = self.not()?;
+        loop {
+            if self.at_keyword("and") {
+                self.position += 1;
+            } else if matches!(self.tokens.get(self.position), None | Some((QueryToken::Close, _))) || self.at_keyword("or") {
+                return Ok(query);
+            }
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+    }
+
+    /// not := NOT not | ( or ) | condition
+    pub(crate) fn not(&mut self) -> Result<Query, String> {
+        let Some((token, column)) = self.tokens.get(self.position) else {
+            return Err(format!("column {}: expected a condition at the end", self.end));
+        };
+        self.position += 1;
+        match token {
+            QueryToken::Word(word) if word.eq_ignore_ascii_case("not") => {
+                Ok(Query::Not(Box::new(self.nested(*column, Self::not)?)))
+            }
+            QueryToken::Word(word) if word.eq_ignore_ascii_case("and") || word.eq_ignore_ascii_case("or") => {
+                Err(format!("column {}: expected a condition before {}", column, word.to_uppercase()))
+            }
+            QueryToken::Word(word) => Ok(Query::Condition(Condition::parse(word, *column, self.today)?)),
+            QueryToken::Close => Err(format!("column {}: expected a condition before ')'", column)),
+            QueryToken::Open => {
+                let query = self.nested(*column, Self::or)?;
+                match self.tokens.get(self.position) {
+                    Some((QueryToken::Close, _)) => {
+                        self.position += 1;
+                        Ok(query)
+                    }
+                    _ => Err(format!("column {}: '(' is never closed", column)),
+                }
+            }
+        }
+    }
+}
+
+/// How the visible list is ordered
+/// Sorting only changes the view - the underlying Vec keeps the user's manual order
+/// Every sort is stable, so ties keep their manual order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SortMode {
+    /// Order in which the todos were added
+    #[default]
+    Manual,
+    /// By text, ignoring case
+    Alphabetical,
+    /// Earliest due date first, todos without one last
+    DueDate,
+    /// Highest priority first
+    Priority,
+    /// Newest first, todos without a creation time last
+    Created,
+    /// By status, in the order the status key cycles through them
+    Status,
+    /// By tags, ignoring case, untagged todos last
+    Tags,
+}
+
+impl SortMode {
+    /// The mode after this one, in the order `s` cycles through them
+    pub(crate) fn next(self) -> SortMode {
+        match self {
+            SortMode::Manual => SortMode::Alphabetical,
+            SortMode::Alphabetical => SortMode::DueDate,
+            SortMode::DueDate => SortMode::Priority,
+            SortMode::Priority => SortMode::Created,
+            SortMode::Created => SortMode::Status,
+            SortMode::Status => SortMode::Tags,
+            SortMode::Tags => SortMode::Manual,
+        }
+    }
+
+    /// Name shown in the status bar
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            SortMode::Manual => "manual",
+            SortMode::Alphabetical => "alphabetical",
+            SortMode::DueDate => "due date",
+            SortMode::Priority => "priority",
+            SortMode::Created => "newest first",
+            SortMode::Status => "status",
+            SortMode::Tags => "tags",
+        }
+    }
+
+    /// One-word name used by the `:sort` command
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SortMode::Manual => "manual",
+            SortMode::Alphabetical => "alpha",
+            SortMode::DueDate => "due",
+            SortMode::Priority => "priority",
+            SortMode::Created => "created",
+            SortMode::Status => "status",
+            SortMode::Tags => "tags",
+        }
+    }
+
+    /// Whether two versions of a todo sort the same in this mode, by what
+    /// `collect_rows` sorts on
+    fn sorts_alike(self, a: &TodoItem, b: &TodoItem) -> bool {
+        match self {
+            SortMode::Manual => true,
+            SortMode::Alphabetical => a.text.to_lowercase() == b.text.to_lowercase(),
+            SortMode::DueDate => (a.due, a.due_time) == (b.due, b.due_time),
+            SortMode::Priority => a.priority == b.priority,
+            SortMode::Created => a.created_at == b.created_at,
+            SortMode::Status => a.status == b.status,
+            SortMode::Tags => {
+                let lowercase = |todo: &TodoItem| todo.labels().iter().map(|tag| tag.to_lowercase()).collect::<Vec<_>>();
+                lowercase(a) == lowercase(b)
+            }
+        }
+    }
+
+    /// Every mode, in cycling order
+    pub(crate) const ALL: [SortMode; 7] = [
+        SortMode::Manual,
+        SortMode::Alphabetical,
+        SortMode::DueDate,
+        SortMode::Priority,
+        SortMode::Created,
+        SortMode::Status,
+        SortMode::Tags,
+    ];
+}
+
+/// A column of the table view, numbered from 1 by the keys that sort by it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TableColumn {
+    Status,
+    Title,
+    Priority,
+    Due,
+    Tags,
+}
+
+impl TableColumn {
+    /// Every column, left to right
+    pub(crate) const ALL: [TableColumn; 5] =
+        [TableColumn::Status, TableColumn::Title, TableColumn::Priority, TableColumn::Due, TableColumn::Tags];
+
+    /// Title shown at the top of the column
+    pub(crate) fn title(self) -> &'static str {
+        match self {
+            TableColumn::Status => "Status",
+            TableColumn::Title => "Title",
+            TableColumn::Priority => "Priority",
+            TableColumn::Due => "Due",
+            TableColumn::Tags => "Tags",
+        }
+    }
+
+    /// The order the list takes when sorted by the column
+    pub(crate) fn sort(self) -> SortMode {
+        match self {
+            TableColumn::Status => SortMode::Status,
+            TableColumn::Title => SortMode::Alphabetical,
+            TableColumn::Priority => SortMode::Priority,
+            TableColumn::Due => SortMode::DueDate,
+            TableColumn::Tags => SortMode::Tags,
+        }
+    }
+}
+
+impl TodoItem {
+    /// Creates a fresh, uncompleted todo with no priority
+    /// Centralizing construction means new fields only need a default in one place
+    pub(crate) fn new(text: &str) -> TodoItem {
+        TodoItem {
+            text: text.to_string(),
+            status: Status::Pending,
+            priority: Priority::None,
+            starred: false,
+            tags: Vec::new(),
+            contexts: Vec::new(),
+            project: None,
+            children: Vec::new(),
+            collapsed: false,
+            notes: String::new(),
+            due: None,
+            due_time: None,
+            recurrence: None,
+            repeat_day: None,
+            scheduled: None,
+            snoozed_until: None,
+            created_at: Some(Timestamp::now()),
+            completed_at: None,
+            pomodoros: 0,
+            time_spent: 0,
+            tracking_since: None,
+            modified_at: None,
+            history: Vec::new(),
+            remote_ids: BTreeMap::new(),
+            github_issue: None,
+        }
+    }
+
+    /// Adds what changed since `before` to the history, dropping the oldest
+    /// entries past the limit
+    /// Views, timers and sync links aren't changes worth listing, so folding
+    /// a todo or tracking time on it leaves the history as it was
+    pub(crate) fn record(&mut self, before: &TodoItem, at: Timestamp) {
+        let changed = [
+            (self.text != before.text).then_some(Field::Text),
+            (self.notes != before.notes).then_some(Field::Notes),
+            (self.status != before.status).then_some(Field::Status(self.status)),
+            (self.priority != before.priority).then_some(Field::Priority(self.priority)),
+            (self.starred != before.starred).then_some(Field::Starred(self.starred)),
+            (self.due != before.due).then_some(Field::Due(self.due)),
+            (self.recurrence != before.recurrence).then_some(Field::Recurrence(self.recurrence)),
+            (self.tags != before.tags || self.contexts != before.contexts).then_some(Field::Tags),
+            (self.project != before.project).then(|| Field::Project(self.project.clone())),
+        ];
+        self.history.extend(changed.into_iter().flatten().map(|field| Change { at, field }));
+        let excess = self.history.len().saturating_sub(HISTORY_LIMIT);
+        self.history.drain(..excess);
+    }
+
+    /// Moves a recurring todo to its next occurrence after today
+    /// Skips occurrences that are already in the past, so a daily todo that was
+    /// neglected for a week comes back tomorrow rather than seven times
+    pub fn reschedule(&mut self, rule: Recurrence) {
+        let today = Date::today();
+        let due = self.due.unwrap_or(today);
+        // A due date moved by hand since starts the months over from its own day
+        let day = self.repeat_day.filter(|&day| due.with_day(day) == due).unwrap_or(due.day);
+        let mut next = rule.next(due, day);
+        while next <= today {
+            next = rule.next(next, day);
+        }
+        self.due = Some(next);
+        self.repeat_day = (rule == Recurrence::Monthly && next.day != day).then_some(day);
+        // Subtasks form a checklist that starts over with each occurrence
+        for child in &mut self.children {
+            child.set_completed(false);
+        }
+    }
+
+    /// Marks the todo done or open, recording when it was completed
+    pub fn set_completed(&mut self, completed: bool) {
+        self.set_status(if completed { Status::Done } else { Status::Pending });
+    }
+
+    /// Changes the status, stamping `completed_at` when the todo becomes done
+    pub(crate) fn set_status(&mut self, status: Status) {
+        self.status = status;
+        self.completed_at = (status == Status::Done).then(Timestamp::now);
+        // Finishing a todo ends the work on it
+        if status == Status::Done {
+            self.stop_tracking(Timestamp::now());
+        }
+    }
+
+    /// Whether the todo is done
+    pub(crate) fn is_done(&self) -> bool {
+        self.status == Status::Done
+    }
+
+    /// Whether the todo is done or cancelled
+    pub(crate) fn is_closed(&self) -> bool {
+        !self.status.is_open()
+    }
+
+    /// Changes the status the way the user means it: a recurring todo that's
+    /// done moves on to its next occurrence and starts over as pending
+    fn change_status(&mut self, status: Status) {
+        match self.recurrence {
+            Some(rule) if status == Status::Done => {
+                self.reschedule(rule);
+                self.set_status(Status::Pending);
+            }
+            _ => self.set_status(status),
+        }
+    }
+
+    /// Total tracked time in seconds, including the running session up to `now`
+    pub fn tracked_seconds(&self, now: Timestamp) -> u64 {
+        self.time_spent + self.tracking_since.map_or(0, |since| (now.0 - since.0).max(0) as u64)
+    }
+
+    /// Ends the running tracking session, adding it to `time_spent`
+    fn stop_tracking(&mut self, now: Timestamp) {
+        self.time_spent = self.tracked_seconds(now);
+        self.tracking_since = None;
+    }
+
+    /// Counts (completed, total) direct subtasks, or None for a todo without any
+    /// Shown next to parents so their status reflects how far along the children are
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        if self.children.is_empty() {
+            return None;
+        }
+        // Cancelled subtasks are settled too, so they count towards the total
+        let done = self.children.iter().filter(|c| c.is_closed()).count();
+        Some((done, self.children.len()))
+    }
+
+    /// The tags, then the contexts with their '@' - the tag picker, filter
+    /// and list show both side by side
+    pub(crate) fn labels(&self) -> Vec<String> {
+        self.tags.iter().cloned().chain(self.contexts.iter().map(|context| format!("@{}", context))).collect()
+    }
+
+    /// Whether the todo carries the tag, or the context for a label starting with '@'
+    pub(crate) fn has_label(&self, label: &str) -> bool {
+        match label.strip_prefix('@') {
+            Some(context) => self.contexts.iter().any(|c| c == context),
+            None => self.tags.iter().any(|t| t == label),
+        }
+    }
+
+    /// Builds a todo from raw input, pulling out inline `#tag` words
+    /// "buy milk #errands" becomes text "buy milk" tagged "errands"
+    /// Quick-add also understands `!high`/`!medium`/`!low`, `@context` like
+    /// todo.txt contexts and `due:DATE` where DATE can be
+    /// anything `parse_due` accepts, including phrases like `due:next friday 5pm`
+    /// Tokens that don't parse, like `due:someday`, stay part of the text
+    pub fn parse(input: &str) -> TodoItem {
+        let mut words = Vec::new();
+        let mut tags: Vec<String> = Vec::new();
+        let mut contexts: Vec<String> = Vec::new();
+        let mut project = None;
+        let mut priority = Priority::None;
+        let mut due = None;
+        let mut start = None;
+        let all: Vec<&str> = input.split_whitespace().collect();
+        let mut rest = all.as_slice();
+        // A date phrase can run over several words, so take the longest that parses
+        let phrase = |first: &str, after: &[&str]| {
+            (0..=after.len().min(3)).rev().find_map(|n| {
+                let phrase = std::iter::once(first).chain(after[..n].iter().copied()).collect::<Vec<_>>().join(" ");
+                parse_due(&phrase, Date::today()).map(|parsed| (n, parsed))
+            })
+        };
+        while let [word, after @ ..] = rest {
+            rest = after;
+            if let Some(first) = word.strip_prefix("due:")
+                && let Some((taken, parsed)) = phrase(first, after)
+            {
+                due = Some(parsed);
+                rest = &after[taken..];
+                continue;
+            }
+            // Starting is a matter of days, so a time would mean nothing
+            if let Some(first) = word.strip_prefix("start:")
+                && let Some((taken, (date, None))) = phrase(first, after)
+            {
+                start = Some(date);
+                rest = &after[taken..];
+                continue;
+            }
+            let word = *word;
+            // Requiring a letter keeps things like "+1" as text
+            // A todo has one project, so a second one replaces the first
+            if let Some(name) = word.strip_prefix('+').filter(|name| name.starts_with(char::is_alphabetic)) {
+                project = Some(name.to_string());
+                continue;
+            }
+            // A lone '#' or '@' is just text, not an empty tag
+            let label = match (word.strip_prefix('#'), word.strip_prefix('@')) {
+                (Some(tag), _) => Some((&mut tags, tag)),
+                (_, Some(context)) => Some((&mut contexts, context)),
+                _ => None,
+            };
+            match label {
+                Some((labels, label)) if !label.is_empty() => {
+                    // Avoid duplicates when the same one is typed twice
+                    if !labels.iter().any(|l| l == label) {
+                        labels.push(label.to_string());
+                    }
+                }
+                _ => {
+                    if let Some(level) = Priority::parse(word) {
+                        priority = level;
+                    } else {
+                        words.push(word);
+                    }
+                }
+            }
+        }
+        let mut todo = TodoItem::new(&words.join(" "));
+        todo.tags = tags;
+        todo.contexts = contexts;
+        todo.project = project;
+        todo.priority = priority;
+        if let Some((date, time)) = due {
+            todo.due = Some(date);
+            todo.due_time = time;
+        }
+        todo.scheduled = start;
+        todo
+    }
+
+    /// The due date (and time, if set) as shown in the list and written to files
+    pub fn due_label(&self) -> Option<String> {
+        self.due.map(|date| format_due(date, self.due_time))
+    }
+
+    /// The moment the todo falls due: its due time, or the start of the due day
+    fn due_at(&self) -> Option<Timestamp> {
+        Some(Timestamp::at(self.due?, self.due_time))
+    }
+
+    /// Whether the todo's start date has come, or it has none
+    pub(crate) fn is_started(&self, today: Date) -> bool {
+        self.scheduled.is_none_or(|start| start <= today)
+    }
+
+    /// Whether the todo is snoozed at this moment, which keeps it out of the list
+    pub(crate) fn is_snoozed(&self, now: Timestamp) -> bool {
+        self.snoozed_until.is_some_and(|until| until > now)
+    }
+
+    /// Whether the todo is still open past its due date, or its due time on the day
+    pub(crate) fn is_overdue(&self) -> bool {
+        let (Some(due), false) = (self.due, self.is_closed()) else {
+            return false;
+        };
+        let now = Timestamp::now();
+        match self.due_time {
+            Some(time) => (due, time) < (now.date(), now.time()),
+            None => due < now.date(),
+        }
+    }
+
+    /// The todo's text with its tags, priority and due date in quick-add syntax,
+    /// so that `parse` turns it back into the same fields
+    pub(crate) fn quick_add_text(&self) -> String {
+        let mut text = self.text.clone();
+        if let Some(project) = &self.project {
+            text.push_str(" +");
+            text.push_str(project);
+        }
+        for tag in &self.tags {
+            text.push_str(" #");
+            text.push_str(tag);
+        }
+        for context in &self.contexts {
+            text.push_str(" @");
+            text.push_str(context);
+        }
+        if let Some(token) = self.priority.token() {
+            text.push(' ');
+            text.push_str(token);
+        }
+        if let Some(due) = self.due_label() {
+            text.push_str(&format!(" due:{}", due));
+        }
+        if let Some(start) = self.scheduled {
+            text.push_str(&format!(" start:{}", start));
+        }
+        text
+    }
+}
+
+/// Which part of the UI is currently receiving keypresses
+/// Modal interface pattern - the same key means different things in different modes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// Navigating and acting on the list
+    Normal,
+    /// Typing into the input buffer - `App::input_target` says what for
+    Input,
+    /// Choosing a tag to filter by from the popup
+    TagPicker,
+    /// Choosing a project to jump to from the popup
+    ProjectPicker,
+    /// Choosing a saved filter from the popup
+    FilterPicker,
+    /// Typing a search query that filters the list live
+    Search,
+    /// Editing the multi-line notes of a todo in the detail pane
+    Notes,
+    /// Waiting for y/n on the action in `App::pending`
+    Confirm,
+    /// Showing the keybinding help popup
+    Help,
+    /// Browsing the trash of the current list in a popup
+    Trash,
+    /// Browsing the archive of old todos in a popup
+    Archive,
+    /// Browsing the snoozed todos of the current list in a popup
+    Snoozed,
+    /// Showing the statistics dashboard
+    Stats,
+    /// Showing the todos as cards in one column per status
+    Board,
+    /// Deciding what to do about a todo file that couldn't be loaded
+    Recovery,
+    /// Browsing the versions of the todo file committed to git
+    History,
+    /// Typing the passphrase of an encrypted todo file at startup
+    Unlock,
+    /// The popup with the first keys to know, shown on the first run
+    Welcome,
+    /// Going through the todos left overdue, one at a time, on the first
+    /// start of a day
+    Review,
+}
+
+impl Mode {
+    /// Indicator shown at the left of the status bar, vim style
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Input | Mode::Notes => "INSERT",
+            Mode::Search => "SEARCH",
+            Mode::TagPicker => "TAGS",
+            Mode::ProjectPicker => "PROJECTS",
+            Mode::FilterPicker => "FILTERS",
+            Mode::Confirm => "CONFIRM",
+            Mode::Help => "HELP",
+            Mode::Trash => "TRASH",
+            Mode::Archive => "ARCHIVE",
+            Mode::Snoozed => "SNOOZED",
+            Mode::Stats => "STATS",
+            Mode::Board => "BOARD",
+            Mode::Recovery => "RECOVERY",
+            Mode::History => "HISTORY",
+            Mode::Unlock => "LOCKED",
+            Mode::Welcome => "WELCOME",
+            Mode::Review => "REVIEW",
+        }
+    }
+}
+
+/// A destructive action waiting for the user to confirm it
+/// Deleting a single todo isn't one of them - it only moves the todo to the trash
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum Confirm {
+    /// Delete the current list
+    DeleteList,
+    /// Permanently delete the entry at this index of the current list's trash
+    Purge(usize),
+    /// Permanently delete everything in the current list's trash
+    EmptyTrash,
+    /// Move every completed todo of the current list to the archive, or else the trash
+    ClearDone { archive: bool },
+    /// Replace unsaved changes with the todo file, which changed on disk -
+    /// no keeps them and saves over the file instead
+    Reload,
+}
+
+/// What pressing Enter in input mode will do with the buffer
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum InputTarget {
+    /// Append a brand new top-level todo
+    New,
+    /// Add a subtask under the todo at this path
+    Child(TodoPath),
+    /// Replace the text of the todo at this path
+    Edit(TodoPath),
+    /// Replace the notes of the todo at this path
+    Notes(TodoPath),
+    /// Set the due date of the todo at this path
+    Due(TodoPath),
+    /// Snooze the todo at this path until the typed moment
+    Snooze(TodoPath),
+    /// Set the recurrence rule of the todo at this path
+    Recurrence(TodoPath),
+    /// Create a new list with the typed name
+    NewList,
+    /// Rename the current list
+    RenameList,
+    /// Add (or with a leading '-' remove) a tag on the marked or selected todos
+    Tag,
+    /// Move the marked or selected todos to the list with the typed number or name
+    MoveToList,
+    /// Save the current search and tag filter as a filter with the typed name
+    SaveFilter,
+    /// Give this tag the typed color and icon
+    TagStyle(String),
+    /// Run a command like `filter QUERY`
+    Command,
+    /// Type a passphrase for `:encrypt` - then again, holding the first, to
+    /// be sure it was typed right
+    Passphrase(Option<String>),
+}
+
+/// Location of a todo in the tree - one index per nesting level
+/// `[2]` is the third top-level todo, `[2, 0]` is its first subtask
+pub type TodoPath = Vec<usize>;
+
+/// The earliest moment after `now` one of these todos or their subtasks
+/// stops being snoozed or reaches its start date
+fn next_wake(todos: &[TodoItem], now: Timestamp) -> Option<Timestamp> {
+    todos
+        .iter()
+        .flat_map(|todo| {
+            let start = todo.scheduled.map(Timestamp::from_date);
+            [todo.snoozed_until, start].into_iter().flatten().filter(|&at| at > now).chain(next_wake(&todo.children, now))
+        })
+        .min()
+}
+
+/// The paths of these todos and their subtasks that are snoozed, the one
+/// coming back first first
+fn snoozed(todos: &[TodoItem], now: Timestamp) -> Vec<TodoPath> {
+    fn collect(todos: &[TodoItem], now: Timestamp, prefix: &mut TodoPath, paths: &mut Vec<TodoPath>) {
+        for (i, todo) in todos.iter().enumerate() {
+            prefix.push(i);
+            if todo.is_snoozed(now) {
+                paths.push(prefix.clone());
+            }
+            collect(&todo.children, now, prefix, paths);
+            prefix.pop();
+        }
+    }
+    let mut paths = Vec::new();
+    collect(todos, now, &mut Vec::new(), &mut paths);
+    paths.sort_by_key(|path| item_at(todos, path).snoozed_until);
+    paths
+}
+
+/// How many of these todos and their subtasks have a start date after today
+fn count_unstarted(todos: &[TodoItem], today: Date) -> usize {
+    todos.iter().map(|todo| usize::from(!todo.is_started(today)) + count_unstarted(&todo.children, today)).sum()
+}
+
+/// Appends the paths of the open todos and subtasks that fell due on a day
+/// from `since` up to yesterday and aren't snoozed
+fn collect_stale(todos: &[TodoItem], since: Date, today: Date, now: Timestamp, prefix: &mut TodoPath, out: &mut Vec<TodoPath>) {
+    for (i, todo) in todos.iter().enumerate() {
+        prefix.push(i);
+        if !todo.is_closed() && !todo.is_snoozed(now) && todo.due.is_some_and(|due| since <= due && due < today) {
+            out.push(prefix.clone());
+        }
+        collect_stale(&todo.children, since, today, now, prefix, out);
+        prefix.pop();
+    }
+}
+
+/// Returns the todo at `path`
+/// Callers only pass paths taken from the current view, so they're always valid
+pub(crate) fn item_at<'a>(todos: &'a [TodoItem], path: &[usize]) -> &'a TodoItem {
+    let (first, rest) = path.split_first().expect("todo paths are never empty");
+    rest.iter().fold(&todos[*first], |item, &i| &item.children[i])
+}
+
+/// Mutable version of `item_at`
+pub(crate) fn item_at_mut<'a>(todos: &'a mut [TodoItem], path: &[usize]) -> &'a mut TodoItem {
+    let (first, rest) = path.split_first().expect("todo paths are never empty");
+    rest.iter().fold(&mut todos[*first], |item, &i| &mut item.children[i])
+}
+
+/// Returns the Vec holding the todo at `path` together with its siblings
+/// For top-level todos that's the list itself, otherwise the parent's children
+fn siblings_mut<'a>(todos: &'a mut Vec<TodoItem>, path: &[usize]) -> &'a mut Vec<TodoItem> {
+    match path.split_last() {
+        Some((_, parent)) if !parent.is_empty() => &mut item_at_mut(todos, parent).children,
+        _ => todos,
+    }
+}
+
+/// Maximum number of undo steps we remember
+/// Bounded so a long session doesn't keep every past version of every todo in memory
+const UNDO_LIMIT: usize = 100;
+
+/// A reversible change to the todo lists - the command pattern behind undo/redo
+/// Every mutation goes through one of these so it can be inverted later
+/// Commands store whole items rather than diffs, which keeps inversion trivial
+/// Todo commands carry the index of the list they apply to, so history spans lists
+#[derive(Clone)]
+pub enum Command {
+    /// Put `item` at `path`, shifting later siblings down
+    Insert { list: usize, path: TodoPath, item: TodoItem },
+    /// Take the todo at `path` (and its subtasks) out - `item` is kept for undo
+    Remove { list: usize, path: TodoPath, item: TodoItem },
+    /// Swap the todo at `path` from `before` to `after` (toggle, edit, priority...)
+    Replace { list: usize, path: TodoPath, before: Box<TodoItem>, after: Box<TodoItem> },
+    /// Move a todo from `from` to `to` - both paths share the same parent
+    Move { list: usize, from: TodoPath, to: TodoPath },
+    /// Add a whole list at `index`
+    InsertList { index: usize, list: TodoList },
+    /// Delete the list at `index` with all its todos - `list` is kept for undo
+    RemoveList { index: usize, list: TodoList },
+    /// Change the name of the list at `index`
+    RenameList { index: usize, before: String, after: String },
+    /// Take the todo at `path` out of the list and put it at `index` in the trash
+    Trash { list: usize, path: TodoPath, index: usize },
+    /// Take entry `index` out of the trash and put it back at `path`
+    Restore { list: usize, index: usize, path: TodoPath },
+    /// Swap the whole trash from `before` to `after` - used to delete from it for good
+    SetTrash { list: usize, before: Vec<TodoItem>, after: Vec<TodoItem> },
+    /// Take the todo at `path` out of the list and store it as `entry` at `index` in the archive
+    Archive { list: usize, path: TodoPath, index: usize, entry: ArchivedTodo },
+    /// Take archive entry `index` out of the archive and put its todo back at `path`
+    Unarchive { list: usize, path: TodoPath, index: usize, entry: ArchivedTodo },
+    /// Swap every list from `before` to `after` - restoring a snapshot
+    SetLists { before: Vec<TodoList>, after: Vec<TodoList> },
+    /// Several commands applied in order as a single undo step
+    Batch(Vec<Command>),
+}
+
+/// What a command changed, so the UI can bring it into view afterwards
+pub struct Affected {
+    /// List that should be shown
+    pub(crate) list: usize,
+    /// Todo that should be selected, or None when it no longer exists
+    pub(crate) path: Option<TodoPath>,
+}
+
+impl Command {
+    /// Performs the change on the lists and archive and reports what it touched
+    pub fn apply(&self, lists: &mut Vec<TodoList>, archive: &mut Vec<ArchivedTodo>) -> Affected {
+        match self {
+            Command::Insert { list, path, item } => {
+                let item = TodoItem { modified_at: Some(Timestamp::now()), ..item.clone() };
+                siblings_mut(&mut lists[*list].todos, path).insert(path[path.len() - 1], item);
+                Affected { list: *list, path: Some(path.clone()) }
+            }
+            Command::Remove { list, path, .. } => {
+                siblings_mut(&mut lists[*list].todos, path).remove(path[path.len() - 1]);
+                Affected { list: *list, path: None }
+            }
+            Command::Replace { list, path, after, .. } => {
+                let item = item_at_mut(&mut lists[*list].todos, path);
+                // The links to synced tasks aren't part of the edit, so undoing
+                // back to before the todo was synced doesn't lose them
+                let mut remote_ids = after.remote_ids.clone();
+                remote_ids.append(&mut item.remote_ids);
+                let links = TodoItem { remote_ids, ..after.as_ref().clone() };
+                // The history carries on the same way, and undoing is recorded
+                // as one more change, since it's about the todo rather than the
+                // undo stack
+                let now = Timestamp::now();
+                let history = std::mem::take(&mut item.history);
+                let mut replaced = TodoItem { modified_at: Some(now), history, ..links };
+                replaced.record(item, now);
+                *item = replaced;
+                Affected { list: *list, path: Some(path.clone()) }
+            }
+            Command::Move { list, from, to } => {
+                let siblings = siblings_mut(&mut lists[*list].todos, from);
+                let item = siblings.remove(from[from.len() - 1]);
+                siblings.insert(to[to.len() - 1], item);
+                Affected { list: *list, path: Some(to.clone()) }
+            }
+            Command::InsertList { index, list } => {
+                lists.insert(*index, list.clone());
+                Affected { list: *index, path: None }
+            }
+            Command::RemoveList { index, .. } => {
+                lists.remove(*index);
+                // Show the list that slid into its place, or the new last one
+                Affected { list: (*index).min(lists.len() - 1), path: None }
+            }
+            Command::RenameList { index, after, .. } => {
+                lists[*index].name = after.clone();
+                Affected { list: *index, path: None }
+            }
+            Command::Trash { list, path, index } => {
+                let item = siblings_mut(&mut lists[*list].todos, path).remove(path[path.len() - 1]);
+                lists[*list].trash.insert(*index, item);
+                Affected { list: *list, path: None }
+            }
+            Command::Restore { list, index, path } => {
+                let mut item = lists[*list].trash.remove(*index);
+                item.modified_at = Some(Timestamp::now());
+                siblings_mut(&mut lists[*list].todos, path).insert(path[path.len() - 1], item);
+                Affected { list: *list, path: Some(path.clone()) }
+            }
+            Command::SetTrash { list, after, .. } => {
+                lists[*list].trash = after.clone();
+                Affected { list: *list, path: None }
+            }
+            Command::Archive { list, path, index, entry } => {
+                siblings_mut(&mut lists[*list].todos, path).remove(path[path.len() - 1]);
+                archive.insert(*index, entry.clone());
+                Affected { list: *list, path: None }
+            }
+            Command::Unarchive { list, path, index, entry } => {
+                archive.remove(*index);
+                siblings_mut(&mut lists[*list].todos, path).insert(path[path.len() - 1], entry.todo.clone());
+                Affected { list: *list, path: Some(path.clone()) }
+            }
+            // The lists are different ones now, so start from the first
+            Command::SetLists { after, .. } => {
+                *lists = after.clone();
+                Affected { list: 0, path: None }
+            }
+            // Callers never build an empty batch, so there's always a last command
+            Command::Batch(commands) => commands
+                .iter()
+                .map(|command| command.apply(lists, archive))
+                .last()
+                .expect("batches are never empty"),
+        }
+    }
+
+    /// Returns the command that undoes this one
+    pub(crate) fn inverse(&self) -> Command {
+        match self {
+            Command::Insert { list, path, item } => Command::Remove { list: *list, path: path.clone(), item: item.clone() },
+            Command::Remove { list, path, item } => Command::Insert { list: *list, path: path.clone(), item: item.clone() },
+            Command::Replace { list, path, before, after } => Command::Replace {
+                list: *list,
+                path: path.clone(),
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Command::Move { list, from, to } => Command::Move { list: *list, from: to.clone(), to: from.clone() },
+            Command::InsertList { index, list } => Command::RemoveList { index: *index, list: list.clone() },
+            Command::RemoveList { index, list } => Command::InsertList { index: *index, list: list.clone() },
+            Command::RenameList { index, before, after } => Command::RenameList {
+                index: *index,
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Command::Trash { list, path, index } => Command::Restore { list: *list, index: *index, path: path.clone() },
+            Command::Restore { list, index, path } => Command::Trash { list: *list, path: path.clone(), index: *index },
+            Command::SetTrash { list, before, after } => Command::SetTrash {
+                list: *list,
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Command::Archive { list, path, index, entry } => Command::Unarchive {
+                list: *list,
+                path: path.clone(),
+                index: *index,
+                entry: entry.clone(),
+            },
+            Command::Unarchive { list, path, index, entry } => Command::Archive {
+                list: *list,
+                path: path.clone(),
+                index: *index,
+                entry: entry.clone(),
+            },
+            Command::SetLists { before, after } => Command::SetLists { before: after.clone(), after: before.clone() },
+            // Undo the steps in reverse so each one sees the state it was made in
+            Command::Batch(commands) => Command::Batch(commands.iter().rev().map(Command::inverse).collect()),
+        }
+    }
+
+    /// Whether applying the command changes the archive, which lives in its own file
+    fn touches_archive(&self) -> bool {
+        match self {
+            Command::Archive { .. } | Command::Unarchive { .. } => true,
+            Command::Batch(commands) => commands.iter().any(Command::touches_archive),
+            _ => false,
+        }
+    }
+
+    /// One line saying what the command did, for git commit messages
+    /// Called after `apply`, since moves and trash entries only know where
+    /// their todo is, not what it says
+    pub(crate) fn describe(&self, lists: &[TodoList]) -> String {
+        match self {
+            Command::Insert { item, .. } => format!("Add {}", quote(&item.text)),
+            Command::Remove { item, .. } => format!("Delete {}", quote(&item.text)),
+            Command::Replace { before, after, .. } => {
+                if before.status != after.status {
+                    format!("Mark {} {}", quote(&after.text), after.status.label().to_lowercase())
+                } else if before.text != after.text {
+                    format!("Edit {} to {}", quote(&before.text), quote(&after.text))
+                } else if before.due != after.due && after.recurrence.is_some() {
+                    format!("Complete {} and schedule the next one", quote(&after.text))
+                } else {
+                    format!("Update {}", quote(&after.text))
+                }
+            }
+            Command::Move { list, to, .. } => format!("Move {}", quote(&item_at(&lists[*list].todos, to).text)),
+            Command::InsertList { list, .. } => format!("Add list {}", quote(&list.name)),
+            Command::RemoveList { list, .. } => format!("Delete list {}", quote(&list.name)),
+            Command::RenameList { before, after, .. } => format!("Rename list {} to {}", quote(before), quote(after)),
+            Command::Trash { list, index, .. } => {
+                format!("Move {} to the trash", quote(&lists[*list].trash[*index].text))
+            }
+            Command::Restore { list, path, .. } => {
+                format!("Restore {} from the trash", quote(&item_at(&lists[*list].todos, path).text))
+            }
+            Command::SetTrash { list, after, .. } if after.is_empty() => {
+                format!("Empty the trash of {}", quote(&lists[*list].name))
+            }
+            Command::SetTrash { list, .. } => format!("Delete from the trash of {}", quote(&lists[*list].name)),
+            Command::Archive { entry, .. } => format!("Archive {}", quote(&entry.todo.text)),
+            Command::Unarchive { entry, .. } => format!("Restore {} from the archive", quote(&entry.todo.text)),
+            Command::SetLists { .. } => "Restore an earlier version".to_string(),
+            // Batches come from bulk actions, which do the same to every todo
+            // The last command is the one the lists are right after, so it's
+            // the one whose paths still point at its todo
+            Command::Batch(commands) => {
+                let last = commands.last().expect("batches are never empty").describe(lists);
+                match commands.len() {
+                    1 => last,
+                    n => format!("{} and {} more", last, n - 1),
+                }
+            }
+        }
+    }
+}
+
+/// Todo text in quotes for a commit message, cut short so the subject
+/// line stays readable
+fn quote(text: &str) -> String {
+    const LIMIT: usize = 40;
+    match text.char_indices().nth(LIMIT) {
+        Some((end, _)) => format!("\"{}…\"", &text[..end]),
+        None => format!("\"{}\"", text),
+    }
+}
+
+/// A named list of todos such as "work" or "groceries"
+/// Each list remembers its own selection so switching back lands where you left off
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TodoList {
+    pub name: String,
+    pub todos: Vec<TodoItem>,
+    /// Deleted todos, most recently deleted first, kept until the trash is emptied
+    /// Saved with the list so deletions can be taken back in a later session too
+    #[serde(default)]
+    pub trash: Vec<TodoItem>,
+    /// Id of the project, calendar or task list the list is synced with on
+    /// each service, by the service's key - a CalDAV calendar's is its path
+    /// Lists on a `serve` server go by their names, so they have none
+    #[serde(default)]
+    pub remote_ids: BTreeMap<String, String>,
+    /// Whether the list shows the GitHub issues, which only GitHub changes
+    #[serde(default)]
+    pub github: bool,
+    /// Whether this is the someday list, where todos that may happen one day
+    /// wait out of the way of the others
+    #[serde(default)]
+    pub someday: bool,
+    /// Selection is UI state, so it's never written to disk
+    #[serde(skip)]
+    pub state: ListState,
+}
+
+impl TodoList {
+    /// Creates an empty list with the given name
+    pub(crate) fn new(name: &str) -> TodoList {
+        TodoList {
+            name: name.to_string(),
+            todos: Vec::new(),
+            trash: Vec::new(),
+            remote_ids: BTreeMap::new(),
+            github: false,
+            someday: false,
+            state: ListState::default(),
+        }
+    }
+}
+
+/// A todo moved out of its list into the archive
+/// Kept with its whole subtree so restoring it brings everything back
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArchivedTodo {
+    /// Name of the list it was archived from, so restoring can put it back there
+    pub list: String,
+    pub archived_at: Timestamp,
+    pub todo: TodoItem,
+}
+
+/// Length of a pomodoro focus interval in seconds
+const POMODORO_FOCUS: i64 = 25 * 60;
+
+/// Length of the break that follows a focus interval in seconds
+const POMODORO_BREAK: i64 = 5 * 60;
+
+/// The two halves of a pomodoro
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PomodoroPhase {
+    Focus,
+    Break,
+}
+
+/// A running pomodoro timer and the todo it's attached to
+pub(crate) struct Pomodoro {
+    pub(crate) phase: PomodoroPhase,
+    pub(crate) ends_at: Timestamp,
+    /// List and tree path of the todo, plus its text to find it again if
+    /// todos were moved around while the timer ran
+    pub(crate) list: usize,
+    pub(crate) path: TodoPath,
+    pub(crate) text: String,
+}
+
+/// How long a toast stays up before it goes away by itself
+pub(crate) const TOAST_DURATION: Duration = Duration::from_millis(2500);
+
+/// How many toasts show at once - a burst of them drops the oldest
+pub(crate) const TOAST_LIMIT: usize = 3;
+
+/// What a toast reports, which picks its color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ToastKind {
+    Info,
+    Success,
+    Error,
+}
+
+/// A short message in the corner of the screen that dismisses itself, for
+/// things worth a glance but not a key press, like a save or a failed sync
+pub(crate) struct Toast {
+    pub(crate) text: String,
+    pub(crate) kind: ToastKind,
+    pub(crate) until: Instant,
+}
+
+/// Main application state container
+/// This struct holds everything needed to render the UI and respond to user actions
+pub struct App {
+    /// All todo lists - there is always at least one
+    /// Each list carries its own ListState, whose selection indexes into `visible`
+    pub lists: Vec<TodoList>,
+
+    /// Index of the list currently shown
+    pub current: usize,
+
+    /// Paths of the displayed todos in display order, subtasks right after their parent
+    /// Keeping a separate view lets us sort and fold without reordering the saved list
+    pub(crate) visible: Vec<TodoPath>,
+
+    /// The rows of the list as drawn, rebuilt along with `visible` so a frame
+    /// only has to look at the rows on screen, however long the list is
+    pub(crate) rows: Vec<ListRow>,
+
+    /// The drawn row of each visible todo, by its index in `visible` - they
+    /// differ once project headers take rows of their own
+    pub(crate) row_of: Vec<usize>,
+
+    /// Currently active ordering of the visible list
+    pub(crate) sort_mode: SortMode,
+
+    /// Whether completed todos are left out of the view
+    pub(crate) hide_completed: bool,
+
+    /// Whether todos whose start date is still ahead are shown - they're left
+    /// out unless asked for
+    pub(crate) show_unstarted: bool,
+
+    /// How many todos of the current list haven't started yet, subtasks included
+    pub(crate) unstarted_count: usize,
+
+    /// Whether top-level todos are grouped under a header per project
+    /// Within a group the sort order still applies
+    pub(crate) group_by_project: bool,
+
+    /// Todos marked with `v` for a bulk operation, by path in the current list
+    /// Cleared after every change since paths can shift when todos move
+    pub(crate) marked: BTreeSet<TodoPath>,
+
+    /// How many completed todos the current view leaves out because of `hide_completed`
+    pub(crate) hidden_completed: usize,
+
+    /// The snoozed todos of the current list as of the last change, for `snoozed()`
+    snoozed_paths: Vec<TodoPath>,
+
+    /// Where the view settings like the sort order are remembered between sessions
+    /// None keeps them in memory only
+    pub(crate) view_state_path: Option<PathBuf>,
+
+    /// When set, only todos carrying this tag are shown
+    pub(crate) tag_filter: Option<String>,
+
+    /// Query set with `:filter`, kept with its text for the status bar
+    /// Composes with the other filters like they do with each other
+    pub(crate) query: Option<(String, Query)>,
+
+    /// Saved filters from the config, offered first in the filter picker
+    config_filters: Vec<SavedFilter>,
+
+    /// Saved filters created in the app, remembered with the view state
+    pub(crate) saved_filters: Vec<SavedFilter>,
+
+    /// Filters the plugins added, offered last in the filter picker
+    pub(crate) plugin_filters: Vec<SavedFilter>,
+
+    /// Colors and icons of tags from the config
+    config_tag_styles: BTreeMap<String, TagStyle>,
+    /// Colors and icons given to tags in the app, remembered with the view
+    /// state - they win over the config's
+    pub(crate) tag_styles: BTreeMap<String, TagStyle>,
+
+    /// The saved filter in effect, composing with the tag filter and search
+    /// A copy, so deleting the saved filter doesn't pull it out from under the view
+    pub(crate) active_filter: Option<SavedFilter>,
+
+    /// Selection inside the filter picker popup
+    /// Row 0 turns the filter off, rows after that map to `filters()`
+    pub(crate) filter_picker: ListState,
+
+    /// Case-insensitive text filter typed after `/` - empty means no search
+    /// Composes with the tag filter, so both must match for a todo to show
+    pub(crate) search: String,
+
+    /// Lowercased texts and their trigrams, so searching doesn't scan every todo
+    pub(crate) search_index: SearchIndex,
+
+    /// Selection inside the tag picker popup
+    /// Row 0 is "all todos", rows after that map to `all_tags()`
+    pub(crate) tag_picker: ListState,
+
+    /// Selection inside the project picker popup, indexing `projects()`
+    pub(crate) project_picker: ListState,
+
+    /// Selection inside the trash popup, indexing the current list's trash
+    pub(crate) trash_state: ListState,
+
+    /// Selection inside the snoozed todos popup, indexing `snoozed()`
+    pub(crate) snoozed_state: ListState,
+
+    /// When the next snoozed todo of the current list comes back, so a tick
+    /// knows when to show it again
+    pub(crate) next_wake: Option<Timestamp>,
+
+    /// Todos archived from any list, oldest first
+    /// Stored in archive.json next to the todo file so the main file stays small
+    pub archive: Vec<ArchivedTodo>,
+
+    /// Selection inside the archive popup - row 0 is the newest entry
+    pub(crate) archive_state: ListState,
+    
+    /// Buffer for user input when adding new todos
+    /// Separate from todos because it's temporary data before committing
+    pub(crate) input: String,
+
+    /// Commands typed at the `:` prompt, oldest first, for Up/Down recall
+    pub(crate) command_history: Vec<String>,
+
+    /// Which history entry Up/Down last recalled, None while typing a new command
+    pub(crate) history_position: Option<usize>,
+
+    /// Outcome of the last command, like where an export went
+    /// Shown in the status bar until the next key press
+    pub(crate) notice: Option<String>,
+
+    /// Last problem reading or writing a file, shown in a bar under the
+    /// status bar until dismissed with Esc - unlike a notice, it stays put
+    /// so a failed save can't scroll by unseen
+    pub(crate) error: Option<AppError>,
+
+    /// Toasts on screen, oldest first, each until its time is up
+    pub(crate) toasts: VecDeque<Toast>,
+
+    /// Set when the todo file couldn't be loaded, so nothing is saved over it
+    pub(crate) saving_disabled: bool,
+
+    /// Changes waiting for the user to pause before they're saved
+    pub(crate) unsaved: Option<Unsaved>,
+
+    /// Writes the files in the background
+    pub(crate) saver: Saver,
+
+    /// How the todo file looked after we last read or wrote it - any other
+    /// change was made outside the app
+    pub(crate) disk_stamp: Option<FileStamp>,
+
+    /// When the todo file was last checked for outside changes
+    pub(crate) last_disk_check: Instant,
+
+    /// Says when the todo file changes, None while it's polled instead
+    pub(crate) watcher: Option<DiskWatcher>,
+
+    /// The lock that makes this the instance saving the todo file, released
+    /// when dropped
+    pub(crate) lock: Option<File>,
+
+    /// The log of the todo file, see `oplog`, kept by the instance that
+    /// has it locked
+    pub(crate) oplog: Option<Arc<Mutex<OpLog>>>,
+
+    /// The changes of the log the todo file said it was written from, kept
+    /// when writing it without the log
+    pub(crate) heads: Vec<String>,
+
+    /// Set while another instance has the todo file locked - changes are
+    /// refused, and the other instance's saves are reloaded as they happen
+    pub(crate) read_only: bool,
+
+    /// How many snapshots of the todo file to keep, from the config
+    pub(crate) backups: usize,
+
+    /// Whether this session's snapshot was taken - once, before the first change
+    pub(crate) snapshot_taken: bool,
+
+    /// When to commit the todo file to git, from the config
+    pub(crate) git: GitMode,
+
+    /// What changed since the last commit, one line per change, for the
+    /// commit message
+    pub(crate) changes: Vec<String>,
+
+    /// The versions of the todo file in git, while the history popup is open
+    pub(crate) history: Option<HistoryView>,
+
+    /// Key the todo file and archive are encrypted with, None when they're
+    /// plain JSON
+    pub(crate) cipher: Option<Cipher>,
+
+    /// Todoist account, CalDAV server or Google account to sync with, from
+    /// the config
+    pub(crate) remote: Option<Remote>,
+
+    /// The sync running on its own thread, if any
+    pub(crate) sync: Option<Receiver<Result<SyncOutcome, String>>>,
+
+    /// When the last sync started, for the interval between them
+    pub(crate) sync_started: Option<Instant>,
+
+    /// Whether the running sync was asked for, which reports even when
+    /// nothing changed
+    pub(crate) sync_manual: bool,
+
+    /// How the last sync went, for the status bar
+    pub(crate) sync_status: SyncStatus,
+
+    /// GitHub repos to show the assigned issues of, from the config
+    pub(crate) github: Option<GitHubConfig>,
+
+    /// The refresh of the issues running on its own thread, if any
+    pub(crate) issues: Option<Receiver<Result<Refreshed, String>>>,
+
+    /// When the issues were last refreshed, for the interval between refreshes
+    pub(crate) issues_started: Option<Instant>,
+
+    /// Whether the running refresh was asked for, which reports how it went
+    pub(crate) issues_manual: bool,
+
+    /// Whether the last refresh failed, so failing again isn't reported again
+    pub(crate) issues_failed: bool,
+
+    /// Mail folder whose unread mail becomes todos, from the config
+    pub(crate) email: Option<EmailConfig>,
+
+    /// The check of the mail, running on its own thread or not
+    pub(crate) mail: MailCheck,
+
+    /// Shell commands to run when todos are added, completed or deleted
+    pub(crate) hooks: HooksConfig,
+
+    /// The hooks running in the background
+    pub(crate) hook_runner: HookRunner,
+
+    /// Lua plugins from the plugins directory
+    pub(crate) plugins: Plugins,
+
+    /// The desktop clipboard, opened the first time something is copied and
+    /// kept open - on X11 a copy only lasts while its program holds on to it
+    pub(crate) clipboard: Option<arboard::Clipboard>,
+
+    /// What the plugins put in the status bar for the current list, worked
+    /// out with the view rather than every frame
+    pub(crate) plugin_status: Vec<String>,
+
+    /// Set by `:quit` so the event loop exits after the key is handled
+    pub(crate) should_quit: bool,
+
+    /// Todo to open in the external editor - the event loop owns the
+    /// terminal, so it does the suspending once the key is handled
+    pub(crate) external_edit: Option<TodoPath>,
+
+    /// File to open in the external editor as it is, for fixing a broken
+    /// todo file by hand from the recovery dialog
+    pub(crate) raw_edit: Option<PathBuf>,
+
+    /// The file that couldn't be loaded, while the recovery dialog is open
+    pub(crate) recovery: Option<Recovery>,
+
+    /// Count and first key typed so far of a multi-key command in normal mode
+    pub(crate) pending_keys: PendingKeys,
+
+    /// Normal-mode bindings from the keymap preset and the config
+    pub(crate) keymap: Keymap,
+
+    /// The last change `.` repeats
+    pub(crate) last_change: Option<Repeat>,
+
+    /// Why the input couldn't be applied, shown until the input is edited
+    /// Lets a mistyped command be fixed instead of retyped
+    pub(crate) input_error: Option<String>,
+
+    /// Byte offset of the cursor in `input`, or None for the end of the buffer
+    /// Defaulting to the end means code that fills the buffer doesn't have to
+    /// place the cursor - use `cursor()` to read it
+    pub(crate) input_cursor: Option<usize>,
+    
+    /// Which mode we're in - determines how we interpret keypresses
+    pub(crate) mode: Mode,
+
+    /// What the input buffer is for - a new todo, a subtask, an edit, notes, etc.
+    pub(crate) input_target: InputTarget,
+
+    /// Whether the right-hand detail pane is shown
+    pub(crate) show_details: bool,
+
+    /// Commands that can be undone, most recent last
+    pub(crate) undo_stack: Vec<Command>,
+
+    /// Commands that were undone and can be redone, most recent last
+    /// Cleared whenever a new change is made, like every editor does
+    pub(crate) redo_stack: Vec<Command>,
+
+    /// File the lists are loaded from and saved to
+    pub(crate) save_path: PathBuf,
+
+    /// Available color themes, built-in ones first
+    pub(crate) themes: Vec<(String, Theme)>,
+
+    /// Index into `themes` of the theme in use
+    pub(crate) theme: usize,
+    /// Symbols rows are drawn with, resolved from the config's "icons"
+    pub(crate) icon_set: IconSet,
+
+    /// Whether deletions ask for confirmation first
+    pub(crate) confirm_delete: bool,
+
+    /// How dates and times are shown, from the config
+    pub(crate) dates: DateStyle,
+
+    /// The language the interface is shown in
+    pub(crate) language: Language,
+
+    /// Whether todos falling due trigger desktop notifications
+    pub(crate) notifications: bool,
+
+    /// When we last looked for todos falling due
+    /// Only todos due after this are notified, so nothing is announced twice and
+    /// todos that were already due at startup don't fire a burst of notifications
+    last_due_check: Timestamp,
+
+    /// Action shown in the confirmation popup while in Confirm mode
+    pub(crate) pending: Option<Confirm>,
+
+    /// How many lines the help popup is scrolled down
+    pub(crate) help_scroll: u16,
+
+    /// Whether the welcome popup was dismissed, ever - remembered with the
+    /// view state so it only shows on the first run
+    pub(crate) welcomed: bool,
+
+    /// Whether the first start of a day goes through the todos left overdue,
+    /// from the config
+    pub(crate) daily_review: bool,
+
+    /// Day the daily review last ran, remembered with the view state so it
+    /// only runs once a day
+    pub(crate) reviewed: Option<Date>,
+
+    /// Todos the daily review has yet to ask about, as list index and path,
+    /// the one being asked about first
+    pub(crate) review: VecDeque<(usize, TodoPath)>,
+
+    /// How much the interface writes to its log, from the config
+    pub(crate) log_level: LogLevel,
+
+    /// Whether the debug overlay with the recent log lines is open
+    /// Toggled with F12 in any mode and left out of help - it's for
+    /// troubleshooting, not for everyday use
+    pub(crate) show_debug: bool,
+
+    /// How long the last frames took to draw, oldest first, for the debug overlay
+    pub(crate) frame_times: VecDeque<Duration>,
+
+    /// Number of todo rows that fit in the list on the last frame
+    /// Used to size page up/down jumps and the scrollbar
+    pub(crate) list_height: usize,
+
+    /// Screen area the list was drawn in on the last frame, for mouse hit testing
+    pub(crate) list_area: Rect,
+
+    /// The drawn row on each line inside the list's border on the last frame
+    /// A wrapped todo takes several lines, so clicks can't count rows from the top
+    pub(crate) list_lines: Vec<usize>,
+
+    /// Changes whenever the rows may draw differently than before: the view
+    /// was rebuilt, or the config or a tag's style changed
+    pub(crate) row_revision: u64,
+
+    /// The rows drawn lately, kept until `row_revision` moves on
+    pub(crate) row_cache: RowCache,
+
+    /// Whether long todos wrap onto more lines instead of being cut off at the
+    /// edge of the list
+    pub(crate) wrap: bool,
+    /// What each list row shows, from the `row_format` template
+    pub(crate) row_format: RowFormat,
+    /// Whether the list is drawn as a table with a column per field instead
+    pub(crate) table: bool,
+    /// Where each table column starts and ends on screen on the last frame,
+    /// for clicks on the column titles
+    pub(crate) table_columns: Vec<(u16, u16)>,
+
+    /// Whether the terminal is below the minimum size, so the interface is
+    /// replaced by a notice - keys wait for it to be bigger, except quitting
+    pub(crate) too_small: bool,
+
+    /// Statuses shown as board columns, in order
+    pub(crate) board_statuses: Vec<Status>,
+
+    /// Column (index into `board_statuses`) and row of the selected board card
+    pub(crate) board_column: usize,
+    pub(crate) board_row: usize,
+
+    /// The pomodoro timer, if one is running
+    pub(crate) pomodoro: Option<Pomodoro>,
+
+    /// Until when the status bar flashes to announce the end of an interval
+    pub(crate) pomodoro_flash: Option<Timestamp>,
+}
+
+/// A row of the list as drawn - `App::list_rows` builds them
+#[derive(Debug, PartialEq)]
+pub(crate) enum ListRow {
+    /// Title of a project group, None for the todos without a project
+    Header(Option<String>),
+    /// A todo, by its index in `App::visible`
+    Todo(usize),
+}
+
+/// Sort key placing a todo in its project group: projects alphabetically,
+/// todos without a project last
+fn project_key(todo: &TodoItem) -> (bool, String) {
+    (todo.project.is_none(), todo.project.as_deref().unwrap_or_default().to_lowercase())
+}
+
+impl App {
+    /// Creates a new App instance with sensible defaults
+    /// The list starts out empty - first-time users get the welcome popup
+    /// instead of example todos that would end up in their file
+    pub fn new(save_path: PathBuf) -> App {
+        App {
+            lists: vec![TodoList::new("Todos")],
+            current: 0,
+            visible: Vec::new(),
+            rows: Vec::new(),
+            row_of: Vec::new(),
+            sort_mode: SortMode::Manual,
+            hide_completed: false,
+            show_unstarted: false,
+            unstarted_count: 0,
+            group_by_project: false,
+            marked: BTreeSet::new(),
+            hidden_completed: 0,
+            snoozed_paths: Vec::new(),
+            view_state_path: None,
+            tag_filter: None,
+            search: String::new(),
+            search_index: SearchIndex::new(),
+            tag_picker: ListState::default(),
+            project_picker: ListState::default(),
+            query: None,
+            config_filters: Config::default().filters,
+            saved_filters: Vec::new(),
+            plugin_filters: Vec::new(),
+            config_tag_styles: BTreeMap::new(),
+            tag_styles: BTreeMap::new(),
+            active_filter: None,
+            filter_picker: ListState::default(),
+            trash_state: ListState::default(),
+            snoozed_state: ListState::default(),
+            next_wake: None,
+            archive: Vec::new(),
+            archive_state: ListState::default(),
+            input: String::new(),
+            command_history: Vec::new(),
+            history_position: None,
+            notice: None,
+            error: None,
+            toasts: VecDeque::new(),
+            saving_disabled: false,
+            unsaved: None,
+            saver: Saver::default(),
+            disk_stamp: None,
+            last_disk_check: Instant::now(),
+            watcher: None,
+            lock: None,
+            oplog: None,
+            heads: Vec::new(),
+            read_only: false,
+            backups: DEFAULT_BACKUPS,
+            snapshot_taken: false,
+            git: GitMode::Off,
+            changes: Vec::new(),
+            history: None,
+            cipher: None,
+            remote: None,
+            sync: None,
+            sync_started: None,
+            sync_manual: false,
+            sync_status: SyncStatus::Idle,
+            github: None,
+            issues: None,
+            issues_started: None,
+            issues_manual: false,
+            issues_failed: false,
+            email: None,
+            mail: MailCheck::default(),
+            hooks: HooksConfig::default(),
+            hook_runner: HookRunner::default(),
+            plugins: Plugins::default(),
+            clipboard: None,
+            plugin_status: Vec::new(),
+            should_quit: false,
+            external_edit: None,
+            raw_edit: None,
+            recovery: None,
+            pending_keys: PendingKeys::default(),
+            keymap: Keymap::new(KeymapPreset::Vim),
+            last_change: None,
+            input_error: None,
+            input_cursor: None,
+            mode: Mode::Normal,
+            input_target: InputTarget::New,
+            show_details: false,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            save_path,
+            themes: Config::default().themes(),
+            theme: 0,
+            icon_set: IconSet::Unicode,
+            confirm_delete: true,
+            dates: DateStyle::default(),
+            language: Language::English,
+            pending: None,
+            notifications: false,
+            last_due_check: Timestamp::now(),
+            help_scroll: 0,
+            welcomed: false,
+            daily_review: true,
+            reviewed: None,
+            review: VecDeque::new(),
+            log_level: LogLevel::Info,
+            show_debug: false,
+            frame_times: VecDeque::new(),
+            list_height: 0,
+            list_area: Rect::default(),
+            list_lines: Vec::new(),
+            row_revision: 0,
+            row_cache: RowCache::default(),
+            wrap: false,
+            row_format: RowFormat::default(),
+            table: false,
+            table_columns: Vec::new(),
+            too_small: false,
+            board_statuses: Config::default().board_columns,
+            board_column: 0,
+            board_row: 0,
+            pomodoro: None,
+            pomodoro_flash: None,
+        }
+    }
+
+    /// Starts or stops time tracking on the selected todo
+    /// Only one todo is tracked at a time, so starting one stops any other -
+    /// both happen in a single undo step
+    pub(crate) fn toggle_tracking(&mut self) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let now = Timestamp::now();
+        let starting = item_at(self.todos(), &path).tracking_since.is_none();
+        let mut commands = Vec::new();
+        for (list, l) in self.lists.iter().enumerate() {
+            let mut tracked = Vec::new();
+            collect_tracked(&l.todos, &mut Vec::new(), &mut tracked);
+            for path in tracked {
+                let before = item_at(&l.todos, &path).clone();
+                let mut after = before.clone();
+                after.stop_tracking(now);
+                commands.push(Command::Replace { list, path, before: Box::new(before), after: Box::new(after) });
+            }
+        }
+        if starting {
+            let before = item_at(self.todos(), &path).clone();
+            let mut after = before.clone();
+            after.tracking_since = Some(now);
+            commands.push(Command::Replace { list: self.current, path, before: Box::new(before), after: Box::new(after) });
+        }
+        self.execute_all(commands);
+    }
+
+    /// The todo being time tracked, in any list
+    pub(crate) fn tracked_todo(&self) -> Option<&TodoItem> {
+        self.lists.iter().find_map(|list| {
+            let mut tracked = Vec::new();
+            collect_tracked(&list.todos, &mut Vec::new(), &mut tracked);
+            tracked.first().map(|path| item_at(&list.todos, path))
+        })
+    }
+
+    /// Starts a focus interval on the selected todo, or stops the running timer
+    /// Stopping early doesn't count the pomodoro
+    pub(crate) fn toggle_pomodoro(&mut self) {
+        if self.pomodoro.take().is_some() {
+            return;
+        }
+        if let Some(path) = self.selected_path() {
+            self.pomodoro = Some(Pomodoro {
+                phase: PomodoroPhase::Focus,
+                ends_at: Timestamp(Timestamp::now().0 + POMODORO_FOCUS),
+                list: self.current,
+                text: item_at(self.todos(), &path).text.clone(),
+                path,
+            });
+        }
+    }
+
+    /// Advances the pomodoro timer - called from the event loop
+    /// A finished focus interval is logged on its todo and followed by a break;
+    /// the end of the break stops the timer. Both ring the bell and flash the status bar
+    /// Returns whether the status bar changed, beyond the countdown itself
+    pub(crate) fn tick_pomodoro(&mut self) -> bool {
+        let now = Timestamp::now();
+        let flash_ended = self.pomodoro_flash.is_some_and(|until| now >= until);
+        if flash_ended {
+            self.pomodoro_flash = None;
+        }
+        let Some(pomodoro) = &mut self.pomodoro else {
+            return flash_ended;
+        };
+        if now < pomodoro.ends_at {
+            return flash_ended;
+        }
+        match pomodoro.phase {
+            PomodoroPhase::Focus => {
+                pomodoro.phase = PomodoroPhase::Break;
+                pomodoro.ends_at = Timestamp(now.0 + POMODORO_BREAK);
+                self.log_pomodoro();
+            }
+            PomodoroPhase::Break => self.pomodoro = None,
+        }
+        self.pomodoro_flash = Some(Timestamp(now.0 + 3));
+        // The terminal bell - shown as a visual bell by terminals configured that way
+        print!("\x07");
+        let _ = io::stdout().flush();
+        true
+    }
+
+    /// Counts a finished focus interval on the timer's todo
+    /// This isn't an edit the user made, so it bypasses the undo history and
+    /// doesn't move the selection - it's saved directly
+    fn log_pomodoro(&mut self) {
+        let Some(pomodoro) = &self.pomodoro else {
+            return;
+        };
+        let Some(list) = self.lists.get_mut(pomodoro.list) else {
+            return;
+        };
+        let todo = match find_item(&list.todos, &pomodoro.path) {
+            Some(todo) if todo.text == pomodoro.text => Some(item_at_mut(&mut list.todos, &pomodoro.path)),
+            // The todo moved - fall back to the first top-level todo with its text
+            _ => list.todos.iter_mut().find(|t| t.text == pomodoro.text),
+        };
+        if let Some(todo) = todo {
+            todo.pomodoros += 1;
+            self.autosave(false);
+        }
+    }
+
+    /// Sends a desktop notification for every open todo that fell due since the
+    /// last check - called from the event loop, so it only looks twice a minute
+    pub(crate) fn check_due_notifications(&mut self) {
+        let now = Timestamp::now();
+        if !self.notifications || now.0 - self.last_due_check.0 < 30 {
+            return;
+        }
+        let mut due = Vec::new();
+        // Todos parked on the someday list aren't something to be reminded of
+        for list in self.lists.iter().filter(|list| !list.someday) {
+            collect_due(&list.todos, &self.dates, |at| self.last_due_check < at && at <= now, &mut due);
+        }
+        self.last_due_check = now;
+        for todo in due {
+            // Without a notification service the reminders never show, which
+            // is worth knowing - the toast for it replaces itself rather than piling up
+            if let Err(err) = send_notification(self.tr("Todo due"), &todo) {
+                let text = self.trf("Desktop notification failed: {}", &[&err]);
+                self.toast(ToastKind::Error, text);
+            }
+        }
+    }
+
+    /// Interface text in the user's language
+    pub(crate) fn tr(&self, english: &'static str) -> &'static str {
+        self.language.translate(english)
+    }
+
+    /// Interface text with values in it, like "{} todos", in the user's language
+    pub(crate) fn trf(&self, template: &'static str, values: &[&dyn fmt::Display]) -> String {
+        fill(self.tr(template), values)
+    }
+
+    /// Interface text about a number of things, from the template for one or
+    /// the one for more by `count`, like "{} todo" and "{} todos"
+    /// The count is among the values wherever the template has it
+    pub(crate) fn trn(&self, one: &'static str, many: &'static str, count: usize, values: &[&dyn fmt::Display]) -> String {
+        fill(self.tr(if count == 1 { one } else { many }), values)
+    }
+
+    /// How the config wants dates and times shown, for the subcommands
+    pub fn date_style(&self) -> DateStyle {
+        self.dates
+    }
+
+    /// Applies settings from the config file
+    /// An unknown theme name is reported so typos don't go unnoticed
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), String> {
+        self.row_revision += 1;
+        self.confirm_delete = config.confirm_delete;
+        self.log_level = config.log;
+        self.wrap = config.wrap;
+        self.daily_review = config.daily_review;
+        self.row_format = match &config.row_format {
+            Some(template) => RowFormat::parse(template).map_err(|e| format!("row_format {}", e))?,
+            None => RowFormat::default(),
+        };
+        self.language = config.language.unwrap_or_else(Language::from_env);
+        self.dates = DateStyle { format: config.date_format, clock: config.clock, week_start: config.week_start };
+        self.notifications = config.notifications;
+        self.backups = config.backups;
+        self.git = config.git;
+        self.remote = match (&config.todoist, &config.caldav, &config.google, &config.remote) {
+            (Some(todoist), None, None, None) if todoist.token.trim().is_empty() => return Err("todoist needs a token".to_string()),
+            (None, Some(caldav), None, None) if caldav.url.trim().is_empty() => return Err("caldav needs a url".to_string()),
+            (None, None, Some(google), None) if google.client_id.trim().is_empty() => {
+                return Err("google needs a client_id".to_string());
+            }
+            (None, None, None, Some(remote)) if remote.url.trim().is_empty() => return Err("remote needs a url".to_string()),
+            (Some(todoist), None, None, None) => Some(Remote::Todoist(todoist.clone())),
+            (None, Some(caldav), None, None) => Some(Remote::CalDav(caldav.clone())),
+            (None, None, Some(google), None) => Some(Remote::Google(google.clone())),
+            (None, None, None, Some(remote)) => Some(Remote::Server(remote.clone())),
+            (None, None, None, None) => None,
+            _ => return Err("sync with only one of todoist, caldav, google and remote".to_string()),
+        };
+        if let Some(github) = &config.github {
+            github.validate()?;
+        }
+        self.github = config.github.clone();
+        if let Some(email) = &config.email {
+            email.validate()?;
+        }
+        self.email = config.email.clone();
+        self.hooks = config.hooks.clone();
+        if config.board_columns.is_empty() {
+            return Err("board_columns needs at least one status".to_string());
+        }
+        self.board_statuses = config.board_columns.clone();
+        self.config_filters = config.filters.clone();
+        self.config_tag_styles = config.tags.clone();
+        self.keymap = Keymap::with_overrides(config.keymap, &config.keys)?;
+        self.themes = config.themes();
+        self.icon_set = config.icons.resolve();
+        if let Some(name) = &config.theme {
+            self.theme = self
+                .themes
+                .iter()
+                .position(|(n, _)| n == name)
+                .ok_or_else(|| format!("unknown theme '{}'", name))?;
+        }
+        Ok(())
+    }
+
+    /// The color theme in use
+    pub(crate) fn theme(&self) -> &Theme {
+        &self.themes[self.theme].1
+    }
+
+    /// The symbols of the icon set in use
+    pub(crate) fn icons(&self) -> &'static Icons {
+        self.icon_set.icons()
+    }
+
+    /// Switches to the next theme, wrapping around
+    pub(crate) fn cycle_theme(&mut self) {
+        self.theme = (self.theme + 1) % self.themes.len();
+    }
+
+    /// The todos of the list currently shown
+    pub fn todos(&self) -> &Vec<TodoItem> {
+        &self.lists[self.current].todos
+    }
+
+    /// Mutable access to the todos of the list currently shown
+    fn todos_mut(&mut self) -> &mut Vec<TodoItem> {
+        &mut self.lists[self.current].todos
+    }
+
+    /// Selection state of the list currently shown
+    pub(crate) fn state(&self) -> &ListState {
+        &self.lists[self.current].state
+    }
+
+    /// Mutable selection state of the list currently shown
+    pub(crate) fn state_mut(&mut self) -> &mut ListState {
+        &mut self.lists[self.current].state
+    }
+
+    /// Rebuilds the list of visible todo paths according to sort, filters and folding
+    /// Must be called after any change that could affect ordering or item count
+    pub(crate) fn refresh_view(&mut self) {
+        // Anything but typing a search may have changed the todos, so the
+        // search index catches up the next time it's used, and what's snoozed
+        // or not started yet is worked out again here rather than per key
+        self.search_index.invalidate();
+        let now = Timestamp::now();
+        self.next_wake = next_wake(self.todos(), now);
+        self.snoozed_paths = snoozed(self.todos(), now);
+        self.unstarted_count = count_unstarted(self.todos(), now.date());
+        self.refresh_plugin_status();
+        self.filter_view();
+    }
+
+    /// Asks the plugins for their status bar segments again
+    fn refresh_plugin_status(&mut self) {
+        match self.plugins.status(&self.lists[self.current]) {
+            Ok(segments) => self.plugin_status = segments,
+            Err(err) => self.notice = Some(self.trf("Plugin failed: {}", &[&err])),
+        }
+    }
+
+    /// Rebuilds the visible rows for the todos as the search index last saw them
+    /// Typing a search only changes the query, so it skips bringing the index
+    /// up to date - a walk over every todo on each key press
+    fn filter_view(&mut self) {
+        // Lowercase once here rather than per todo
+        let query = self.search.to_lowercase();
+        if !query.is_empty() {
+            self.search_index.update(&self.lists);
+        }
+        let matches = self.search_index.search(&query);
+        let candidates = self.search_index.candidates(self.current, &matches);
+        let shape = self.search_index.shape(self.current);
+        let mut visible = Vec::new();
+        let mut hidden = 0;
+        let found = Found::new(&matches, candidates.as_ref());
+        self.collect_rows(self.todos(), shape, &mut Vec::new(), found, &mut visible, &mut hidden);
+        if let Some(err) = self.plugins.take_failure() {
+            self.notice = Some(self.trf("Plugin failed: {}", &[&err]));
+        }
+        self.row_revision += 1;
+        self.visible = visible;
+        self.hidden_completed = hidden;
+        self.rows = self.list_rows();
+        self.row_of = self
+            .rows
+            .iter()
+            .enumerate()
+            .filter_map(|(row, list_row)| matches!(list_row, ListRow::Todo(_)).then_some(row))
+            .collect();
+    }
+
+    /// Appends the rows for one level of siblings, recursing into expanded children
+    /// `prefix` is the path of their parent and is restored before returning
+    /// `hidden` counts completed todos left out because completed ones are hidden
+    /// `shape` is where the search index has the todos' texts, and `found`
+    /// which of them a search can show, so only those are looked at
+    fn collect_rows(
+        &self,
+        todos: &[TodoItem],
+        shape: &[Shape],
+        prefix: &mut TodoPath,
+        found: Found,
+        rows: &mut Vec<TodoPath>,
+        hidden: &mut usize,
+    ) {
+        let now = Timestamp::now();
+        let today = now.date();
+        let mut order: Vec<usize> = found.positions(todos.len()).collect();
+        // The sorts are stable, so ties keep their manual order
+        // Sorting happens per level so subtasks stay under their parent
+        match self.sort_mode {
+            SortMode::Manual => {}
+            SortMode::Alphabetical => order.sort_by_cached_key(|&i| todos[i].text.to_lowercase()),
+            SortMode::DueDate => order.sort_by_key(|&i| (todos[i].due.is_none(), todos[i].due, todos[i].due_time)),
+            SortMode::Priority => order.sort_by(|&a, &b| todos[b].priority.cmp(&todos[a].priority)),
+            // None sorts before any timestamp, so reversing puts unknown ones last
+            SortMode::Created => order.sort_by(|&a, &b| todos[b].created_at.cmp(&todos[a].created_at)),
+            SortMode::Status => order.sort_by_key(|&i| Status::ALL.iter().position(|&s| s == todos[i].status)),
+            SortMode::Tags => order.sort_by_cached_key(|&i| {
+                let tags: Vec<String> = todos[i].labels().iter().map(|tag| tag.to_lowercase()).collect();
+                (tags.is_empty(), tags)
+            }),
+        }
+        // Stars go on top of whatever the sort put first
+        order.sort_by_key(|&i| !todos[i].starred);
+        // Grouping is applied last so the sort order holds within each group
+        if self.group_by_project && prefix.is_empty() {
+            order.sort_by_cached_key(|&i| project_key(&todos[i]));
+        }
+        for i in order {
+            let todo = &todos[i];
+            // Out of sight, subtasks and all, until it comes back
+            if todo.is_snoozed(now) || (!self.show_unstarted && !todo.is_started(today)) {
+                continue;
+            }
+            let shape = shape.get(i);
+            // Parents of a match stay visible so the match keeps its context
+            if !self.subtree_matches(todo, shape, found.below(i)) {
+                if self.hide_completed && todo.is_closed() {
+                    *hidden += 1;
+                }
+                continue;
+            }
+            prefix.push(i);
+            rows.push(prefix.clone());
+            if !todo.collapsed {
+                self.collect_rows(&todo.children, children(shape), prefix, found.below(i), rows, hidden);
+            }
+            prefix.pop();
+        }
+    }
+
+    /// Shows the snoozed todos whose time has come and the todos whose start
+    /// date has, returning whether any did
+    pub(crate) fn tick_snoozes(&mut self) -> bool {
+        if self.next_wake.is_none_or(|at| at > Timestamp::now()) {
+            return false;
+        }
+        let selected = self.selected_path();
+        self.refresh_and_reselect(selected);
+        true
+    }
+
+    /// The snoozed todos of the current list, subtasks included, the one
+    /// coming back first first
+    pub(crate) fn snoozed(&self) -> &[TodoPath] {
+        &self.snoozed_paths
+    }
+
+    /// The rows of the list as drawn: the visible todos, with a header in front of
+    /// each project when grouping by project
+    fn list_rows(&self) -> Vec<ListRow> {
+        let mut rows = Vec::new();
+        let mut group = None;
+        for (i, path) in self.visible.iter().enumerate() {
+            // Subtasks belong to their parent's group whatever their own project is
+            if self.group_by_project && path.len() == 1 {
+                let project = &item_at(self.todos(), path).project;
+                if group != Some(project) {
+                    rows.push(ListRow::Header(project.clone()));
+                    group = Some(project);
+                }
+            }
+            rows.push(ListRow::Todo(i));
+        }
+        rows
+    }
+
+    /// How many of the current list's top-level todos in a project are closed,
+    /// and how many there are - filters don't change the counts
+    pub(crate) fn project_progress(&self, project: &Option<String>) -> (usize, usize) {
+        let todos: Vec<&TodoItem> = self.todos().iter().filter(|todo| &todo.project == project).collect();
+        (todos.iter().filter(|todo| todo.is_closed()).count(), todos.len())
+    }
+
+    /// The projects used in the current list, sorted like the groups, with
+    /// None standing for todos without a project
+    pub(crate) fn projects(&self) -> Vec<Option<String>> {
+        let mut projects: Vec<&TodoItem> = self.todos().iter().collect();
+        projects.sort_by_cached_key(|todo| project_key(todo));
+        let mut projects: Vec<Option<String>> = projects.into_iter().map(|todo| todo.project.clone()).collect();
+        projects.dedup();
+        projects
+    }
+
+    /// Opens the project picker with the selected todo's project preselected
+    pub(crate) fn open_project_picker(&mut self) {
+        let current = self.selected_path().map(|path| item_at(self.todos(), &path[..1]).project.clone());
+        let row = current.and_then(|project| self.projects().iter().position(|p| *p == project));
+        self.project_picker.select(Some(row.unwrap_or(0)));
+        self.mode = Mode::ProjectPicker;
+    }
+
+    /// Moves the project picker selection by `delta`, wrapping like the main list
+    pub(crate) fn project_picker_move(&mut self, delta: isize) {
+        let len = self.projects().len().max(1) as isize;
+        let i = self.project_picker.selected().map_or(0, |i| i as isize);
+        self.project_picker.select(Some((i + delta).rem_euclid(len) as usize));
+    }
+
+    /// Jumps to the first visible todo of the project highlighted in the picker
+    /// A project whose todos are all filtered out leaves the selection alone
+    pub(crate) fn apply_project_picker(&mut self) {
+        self.mode = Mode::Normal;
+        let Some(project) = self.project_picker.selected().and_then(|i| self.projects().get(i).cloned()) else {
+            return;
+        };
+        let row = self.visible.iter().position(|path| item_at(self.todos(), &path[..1]).project == project);
+        if row.is_some() {
+            self.state_mut().select(row);
+        }
+    }
+
+    /// Whether the todo itself passes the tag filter, search query and completed toggle
+    fn matches_filters(&self, todo: &TodoItem, shape: Option<&Shape>, matches: &Matches) -> bool {
+        self.passes_filters(todo) && matches.contains(todo, shape)
+    }
+
+    /// Whether the todo passes the filters other than the search
+    fn passes_filters(&self, todo: &TodoItem) -> bool {
+        let tag_ok = match &self.tag_filter {
+            Some(tag) => todo.has_label(tag),
+            None => true,
+        };
+        tag_ok
+            && self.active_filter.as_ref().is_none_or(|filter| {
+                filter.matches(todo, Date::today()) && filter.plugin.is_none_or(|i| self.plugins.filter(i, todo))
+            })
+            && self.query.as_ref().is_none_or(|(_, query)| query.matches(todo))
+            && !(self.hide_completed && todo.is_closed())
+    }
+
+    /// Whether a command only changed todos of the current list in ways that
+    /// leave every row where it was - completing, renaming or tagging a todo
+    /// while the list is sorted and filtered by something else
+    /// Its subtasks have to be the same, so the paths all stay put too
+    fn keeps_rows(&self, command: &Command) -> bool {
+        match command {
+            Command::Replace { list, path, before, after } => {
+                *list == self.current
+                    && self.search.is_empty()
+                    && before.children == after.children
+                    && (before.collapsed, before.starred) == (after.collapsed, after.starred)
+                    && (before.snoozed_until, before.scheduled) == (after.snoozed_until, after.scheduled)
+                    && (!self.group_by_project || path.len() > 1 || before.project == after.project)
+                    && (!self.hide_completed || before.is_closed() == after.is_closed())
+                    && self.sort_mode.sorts_alike(before, after)
+                    && self.passes_filters(before) == self.passes_filters(after)
+            }
+            Command::Batch(commands) => !commands.is_empty() && commands.iter().all(|command| self.keeps_rows(command)),
+            _ => false,
+        }
+    }
+
+    /// Whether the todo or any of its descendants passes the filters
+    /// `found` holds the candidates among its subtasks
+    fn subtree_matches(&self, todo: &TodoItem, shape: Option<&Shape>, found: Found) -> bool {
+        let shapes = children(shape);
+        self.matches_filters(todo, shape, found.matches)
+            || found
+                .positions(todo.children.len())
+                .any(|i| self.subtree_matches(&todo.children[i], shapes.get(i), found.below(i)))
+    }
+
+    /// Returns the tree path of the currently selected row, if any
+    /// Every operation on "the selected todo" goes through this to respect sorting
+    pub(crate) fn selected_path(&self) -> Option<TodoPath> {
+        self.state().selected().and_then(|i| self.visible.get(i).cloned())
+    }
+
+    /// Selects the row that displays the todo at the given path
+    /// Used to keep the cursor on the same item after the view is reordered
+    fn select_path(&mut self, path: &[usize]) {
+        let row = self.visible.iter().position(|p| p == path);
+        self.state_mut().select(row);
+    }
+
+    /// Cycles the priority of the selected todo
+    /// When sorted by priority the item moves, so we follow it with the selection
+    pub(crate) fn cycle_priority(&mut self) {
+        if let Some(path) = self.selected_path() {
+            let mut after = item_at(self.todos(), &path).clone();
+            after.priority = after.priority.cycle();
+            self.replace(path, after);
+        }
+    }
+
+    /// Applies a command, records it for undo, and persists the result
+    /// This is the single entry point for every change to the todo list
+    pub(crate) fn execute(&mut self, command: Command) {
+        // Another instance saves this file, and it would never see the change
+        if self.refuse_read_only() {
+            return;
+        }
+        if let Some(refusal) = self.issue_refusal(&command) {
+            self.notice = Some(self.tr(refusal).to_string());
+            return;
+        }
+        let command = self.run_plugins(command);
+        let fired = self.hook_events(&command);
+        self.run_command(&command);
+        self.run_hooks(fired);
+        self.undo_stack.push(command);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        // A fresh change invalidates anything that was undone before it
+        self.redo_stack.clear();
+    }
+
+    /// Whether the lists can't be changed because another instance has the
+    /// file, saying so when they can't
+    fn refuse_read_only(&mut self) -> bool {
+        if self.read_only {
+            self.notice = Some(self.tr("Read-only - the file is open in another instance").to_string());
+        }
+        self.read_only
+    }
+
+    /// Applies a command from the command line, which keeps no undo history
+    /// or view - the change is still noted for the git history
+    pub fn apply_command(&mut self, command: Command) {
+        let command = self.run_plugins(command);
+        let fired = self.hook_events(&command);
+        command.apply(&mut self.lists, &mut self.archive);
+        self.run_hooks(fired);
+        self.changes.push(command.describe(&self.lists));
+    }
+
+    /// Shorthand for replacing the todo at `path` with an updated copy
+    pub(crate) fn replace(&mut self, path: TodoPath, after: TodoItem) {
+        let before = item_at(self.todos(), &path).clone();
+        let list = self.current;
+        self.execute(Command::Replace { list, path, before: Box::new(before), after: Box::new(after) });
+    }
+
+    /// Applies a command without touching the undo history
+    /// Shared by execute, undo and redo so selection and saving behave identically
+    fn run_command(&mut self, command: &Command) {
+        let affected = command.apply(&mut self.lists, &mut self.archive);
+        self.marked.clear();
+        // Undoing a change in another list switches to it so the effect is visible
+        self.current = affected.list;
+        let row = self.state().selected();
+        // Walking and sorting every todo again takes a while on a big list,
+        // so an edit that moves nothing only redraws
+        if self.keeps_rows(command) {
+            self.search_index.invalidate();
+            self.refresh_plugin_status();
+            self.row_revision += 1;
+        } else {
+            self.refresh_view();
+            match affected.path {
+                // Follow the changed todo - it may have moved due to sorting
+                Some(path) if self.visible.contains(&path) => self.select_path(&path),
+                // Otherwise stay at the same row, clamped to the shorter list
+                _ => {
+                    let new_row = if self.visible.is_empty() {
+                        None
+                    } else {
+                        Some(row.unwrap_or(0).min(self.visible.len() - 1))
+                    };
+                    self.state_mut().select(new_row);
+                }
+            }
+        }
+
+        // Save after every change - prevents data loss
+        self.changes.push(command.describe(&self.lists));
+        self.autosave(command.touches_archive());
+    }
+
+    /// Reverts the most recent change
+    pub(crate) fn undo(&mut self) {
+        if let Some(command) = self.undo_stack.pop() {
+            self.run_command(&command.inverse());
+            self.redo_stack.push(command);
+        }
+    }
+
+    /// Drops undo history and marks, for when the lists were replaced as a
+    /// whole and the paths they hold no longer point at the same todos
+    pub(crate) fn forget_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.marked.clear();
+    }
+
+    /// Re-applies the most recently undone change
+    pub(crate) fn redo(&mut self) {
+        if let Some(command) = self.redo_stack.pop() {
+            self.run_command(&command);
+            self.undo_stack.push(command);
+        }
+    }
+
+    /// Moves the selected todo one place up (-1) or down (+1) among its siblings
+    /// Only allowed in manual order - in sorted views position is decided by the sort
+    pub(crate) fn move_selected(&mut self, delta: isize) {
+        if self.sort_mode != SortMode::Manual {
+            return;
+        }
+        let Some(from) = self.selected_path() else {
+            return;
+        };
+        // Subtasks only move within their parent, never out of it
+        let sibling_count = siblings_mut(self.todos_mut(), &from).len();
+        let target = from[from.len() - 1] as isize + delta;
+        if target < 0 || target as usize >= sibling_count {
+            return;
+        }
+        let mut to = from.clone();
+        *to.last_mut().unwrap() = target as usize;
+        let list = self.current;
+        self.execute(Command::Move { list, from, to });
+    }
+
+    /// Expands or collapses the subtasks of the selected todo
+    /// Folding is view state, so it's saved but deliberately not undoable
+    pub(crate) fn set_collapsed(&mut self, collapsed: bool) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let todo = item_at_mut(self.todos_mut(), &path);
+        if todo.children.is_empty() || todo.collapsed == collapsed {
+            return;
+        }
+        todo.collapsed = collapsed;
+        self.refresh_view();
+        self.select_path(&path);
+        self.autosave(false);
+    }
+
+    /// Handles "left" in the tree: collapse an open parent, otherwise jump to the parent
+    /// Mirrors how file explorers behave so it feels familiar
+    pub(crate) fn collapse_or_parent(&mut self) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let todo = item_at(self.todos(), &path);
+        if !todo.children.is_empty() && !todo.collapsed {
+            self.set_collapsed(true);
+        } else if path.len() > 1 {
+            self.select_path(&path[..path.len() - 1]);
+        }
+    }
+
+    /// Collects every distinct tag and context in use, sorted alphabetically
+    /// Computed on demand because tags change whenever todos are added or deleted
+    pub(crate) fn all_tags(&self) -> Vec<String> {
+        /// Walks the whole tree so subtask tags show up in the picker too
+        fn collect(todos: &[TodoItem], tags: &mut Vec<String>) {
+            for todo in todos {
+                tags.extend(todo.labels());
+                collect(&todo.children, tags);
+            }
+        }
+        let mut tags = Vec::new();
+        for list in &self.lists {
+            collect(&list.todos, &mut tags);
+        }
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Restricts the visible list to one tag, or shows everything with None
+    /// Tries to keep the current todo selected if it's still visible
+    fn set_tag_filter(&mut self, tag: Option<String>) {
+        let selected = self.selected_path();
+        self.tag_filter = tag;
+        self.refresh_and_reselect(selected);
+    }
+
+    /// Rebuilds the view after a filter change and restores the selection
+    /// Keeps `selected` if it's still visible, otherwise falls back to the first
+    /// row so there's always something to act on
+    pub(crate) fn refresh_and_reselect(&mut self, selected: Option<TodoPath>) {
+        self.refresh_view();
+        self.reselect(selected);
+    }
+
+    /// Selects the todo at the path if it's still shown, or else the first row
+    fn reselect(&mut self, selected: Option<TodoPath>) {
+        match selected {
+            Some(path) if self.visible.contains(&path) => self.select_path(&path),
+            _ => {
+                let row = if self.visible.is_empty() { None } else { Some(0) };
+                self.state_mut().select(row);
+            }
+        }
+    }
+
+    /// Enters search mode, continuing from any query that's already active
+    pub(crate) fn start_search(&mut self) {
+        self.mode = Mode::Search;
+    }
+
+    /// Adds or removes a character from the search query and refilters immediately
+    /// This is what makes the search incremental - the list updates per keystroke
+    pub(crate) fn edit_search(&mut self, c: Option<char>) {
+        let selected = self.selected_path();
+        match c {
+            Some(c) => self.search.push(c),
+            // Drop the last grapheme, not just its final code point
+            None => {
+                let end = self.search.grapheme_indices(true).next_back().map_or(0, |(i, _)| i);
+                self.search.truncate(end);
+            }
+        }
+        self.filter_view();
+        self.reselect(selected);
+    }
+
+    /// Drops the search query and shows all todos again (subject to the tag filter)
+    pub(crate) fn clear_search(&mut self) {
+        let selected = self.selected_path();
+        self.search.clear();
+        self.mode = Mode::Normal;
+        self.refresh_and_reselect(selected);
+    }
+
+    /// Clears the most specific active filter - search first, then tag, then
+    /// the saved filter
+    /// Lets repeated Esc presses peel filters off one at a time
+    pub(crate) fn clear_filter(&mut self) {
+        if !self.marked.is_empty() {
+            self.marked.clear();
+        } else if !self.search.is_empty() {
+            self.clear_search();
+        } else if self.query.is_some() {
+            self.set_query(None);
+        } else if self.tag_filter.is_some() {
+            self.set_tag_filter(None);
+        } else {
+            self.set_saved_filter(None);
+        }
+    }
+
+    /// Replaces the `:filter` query, or removes it with None
+    pub(crate) fn set_query(&mut self, query: Option<(String, Query)>) {
+        let selected = self.selected_path();
+        self.query = query;
+        self.refresh_and_reselect(selected);
+    }
+
+    /// Every saved filter: the config's first, then those saved in the app,
+    /// then the plugins'
+    pub(crate) fn filters(&self) -> Vec<&SavedFilter> {
+        self.config_filters.iter().chain(&self.saved_filters).chain(&self.plugin_filters).collect()
+    }
+
+    /// Switches to a saved filter, or back to all todos with None
+    fn set_saved_filter(&mut self, filter: Option<SavedFilter>) {
+        let selected = self.selected_path();
+        self.active_filter = filter;
+        self.refresh_and_reselect(selected);
+    }
+
+    /// Starts on the todos due today or overdue, for --today
+    /// The config's "Today" filter is used when it has one, so the picker
+    /// shows it as the active filter, and a list with nothing due gives way
+    /// to the first one that has something
+    pub fn show_today(&mut self) {
+        fn any_due(todos: &[TodoItem], filter: &SavedFilter, today: Date) -> bool {
+            todos.iter().any(|todo| filter.matches(todo, today) || any_due(&todo.children, filter, today))
+        }
+        let filter = self
+            .filters()
+            .into_iter()
+            .find(|f| f.name.eq_ignore_ascii_case("today") && f.due == Some(DueFilter::Today))
+            .cloned()
+            .unwrap_or_else(|| SavedFilter { due: Some(DueFilter::Today), ..SavedFilter::named("Today") });
+        let today = Date::today();
+        if !any_due(self.todos(), &filter, today)
+            && let Some(index) = self.lists.iter().position(|list| any_due(&list.todos, &filter, today))
+        {
+            self.switch_list(index);
+        }
+        self.set_saved_filter(Some(filter));
+    }
+
+    /// Closes the welcome popup for good
+    pub(crate) fn dismiss_welcome(&mut self) {
+        self.mode = Mode::Normal;
+        self.welcomed = true;
+        self.save_view_state();
+    }
+
+    /// Starts the daily review on the first start of a day: the open todos
+    /// that fell due since the last one, one at a time in a popup
+    /// The first review only looks back to yesterday, so whoever has a long
+    /// tail of old due dates isn't greeted by all of them at once
+    /// A read-only instance leaves the review to the one that has the file
+    pub fn start_review(&mut self) {
+        let today = Date::today();
+        let reviewed = self.reviewed.is_some_and(|day| day >= today);
+        if !self.daily_review || self.read_only || self.mode != Mode::Normal || reviewed {
+            return;
+        }
+        let since = self.reviewed.unwrap_or(today.add_days(-1));
+        let now = Timestamp::now();
+        for (index, list) in self.lists.iter().enumerate().filter(|(_, list)| !list.someday) {
+            let mut paths = Vec::new();
+            collect_stale(&list.todos, since, today, now, &mut Vec::new(), &mut paths);
+            self.review.extend(paths.into_iter().map(|path| (index, path)));
+        }
+        self.reviewed = Some(today);
+        self.save_view_state();
+        if !self.review.is_empty() {
+            self.show_review();
+        }
+    }
+
+    /// Shows the todo the review asks about next, selected in its list when
+    /// it's in view - or after the last one, goes back to the list
+    fn show_review(&mut self) {
+        match self.review.front().cloned() {
+            Some((list, path)) => {
+                self.switch_list(list);
+                if self.visible.contains(&path) {
+                    self.select_path(&path);
+                }
+                self.mode = Mode::Review;
+            }
+            None => {
+                self.mode = Mode::Normal;
+                self.toast(ToastKind::Success, self.tr("All caught up").to_string());
+            }
+        }
+    }
+
+    /// Leaves the todo the review asks about as it is and moves on
+    pub(crate) fn next_review(&mut self) {
+        self.review.pop_front();
+        self.show_review();
+    }
+
+    /// Stops the review, leaving the todos it hasn't asked about yet as they are
+    pub(crate) fn end_review(&mut self) {
+        self.review.clear();
+        self.mode = Mode::Normal;
+    }
+
+    /// Moves the todo the review asks about to today, without its old time,
+    /// which would most likely have passed again
+    pub(crate) fn review_today(&mut self) {
+        if self.refuse_read_only() {
+            return;
+        }
+        if let Some((list, path)) = self.review.front().cloned() {
+            self.switch_list(list);
+            let after = TodoItem { due: Some(Date::today()), due_time: None, ..item_at(self.todos(), &path).clone() };
+            self.replace(path, after);
+            self.next_review();
+        }
+    }
+
+    /// Opens the due date prompt for the todo the review asks about
+    /// It starts out empty since the old date is the one being replaced, and
+    /// submitting it goes on to the next todo
+    pub(crate) fn review_reschedule(&mut self) {
+        if self.refuse_read_only() {
+            return;
+        }
+        if let Some((list, path)) = self.review.front().cloned() {
+            self.switch_list(list);
+            self.input_target = InputTarget::Due(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Completes the todo the review asks about - a recurring one moves on to
+    /// its next date
+    pub(crate) fn review_complete(&mut self) {
+        if self.refuse_read_only() {
+            return;
+        }
+        if let Some((list, path)) = self.review.front().cloned() {
+            self.switch_list(list);
+            self.toggle_completed_at(vec![path]);
+            self.next_review();
+        }
+    }
+
+    /// Moves the todo the review asks about to the trash
+    /// The todos after it in the same list move up a place, and its subtasks
+    /// go along with it, so the paths still to come are fixed up to match
+    pub(crate) fn review_drop(&mut self) {
+        if self.refuse_read_only() {
+            return;
+        }
+        let Some((list, path)) = self.review.pop_front() else {
+            return;
+        };
+        self.switch_list(list);
+        self.execute(Command::Trash { list, path: path.clone(), index: 0 });
+        let depth = path.len() - 1;
+        self.review.retain(|(l, p)| *l != list || !p.starts_with(&path));
+        for (l, p) in &mut self.review {
+            if *l == list && p.len() > depth && p[..depth] == path[..depth] && p[depth] > path[depth] {
+                p[depth] -= 1;
+            }
+        }
+        self.show_review();
+    }
+
+    /// How long the screen can go without a redraw while nothing happens
+    /// A running pomodoro or time tracking counts seconds on screen; otherwise
+    /// only due dates move on by themselves, which is a matter of minutes
+    pub(crate) fn redraw_interval(&self) -> Duration {
+        if self.pomodoro.is_some() || self.pomodoro_flash.is_some() || self.tracked_todo().is_some() {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(60)
+        }
+    }
+
+    /// Shows a toast, which goes away by itself after TOAST_DURATION
+    pub(crate) fn toast(&mut self, kind: ToastKind, text: String) {
+        // The same message again, like the next save, starts its time over
+        // rather than stacking up
+        self.toasts.retain(|toast| toast.text != text);
+        if self.toasts.len() == TOAST_LIMIT {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast { text, kind, until: Instant::now() + TOAST_DURATION });
+    }
+
+    /// Takes away the toasts whose time is up, returning whether any went
+    pub(crate) fn expire_toasts(&mut self) -> bool {
+        let now = Instant::now();
+        let before = self.toasts.len();
+        self.toasts.retain(|toast| toast.until > now);
+        self.toasts.len() != before
+    }
+
+    /// Remembers how long a frame took to draw, for the debug overlay
+    pub(crate) fn record_frame(&mut self, took: Duration) {
+        // About two seconds of frames, enough to see a stutter
+        if self.frame_times.len() == 120 {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(took);
+    }
+
+    /// Opens the filter picker with the active filter preselected
+    pub(crate) fn open_filter_picker(&mut self) {
+        let row = match &self.active_filter {
+            Some(active) => self.filters().iter().position(|f| f.name == active.name).map_or(0, |p| p + 1),
+            None => 0,
+        };
+        self.filter_picker.select(Some(row));
+        self.mode = Mode::FilterPicker;
+    }
+
+    /// Moves the filter picker selection by `delta`, wrapping like the main list
+    pub(crate) fn filter_picker_move(&mut self, delta: isize) {
+        let len = self.filters().len() as isize + 1;
+        let i = self.filter_picker.selected().map_or(0, |i| i as isize);
+        self.filter_picker.select(Some((i + delta).rem_euclid(len) as usize));
+    }
+
+    /// Applies the filter in row `row` of the picker and closes it
+    /// Row 0, or a row past the end, turns the filter off
+    pub(crate) fn apply_filter_picker(&mut self, row: usize) {
+        let filter = row.checked_sub(1).and_then(|i| self.filters().get(i).map(|f| (*f).clone()));
+        self.mode = Mode::Normal;
+        self.set_saved_filter(filter);
+    }
+
+    /// Opens the prompt that saves the current search and tag filter as a new filter
+    pub(crate) fn start_save_filter(&mut self) {
+        if self.search.is_empty() && self.tag_filter.is_none() {
+            return;
+        }
+        self.input_target = InputTarget::SaveFilter;
+        self.mode = Mode::Input;
+    }
+
+    /// Saves the current search and tag filter under the typed name and makes it active
+    /// Saving under an existing name replaces that filter
+    pub(crate) fn apply_save_filter(&mut self) {
+        let name = self.input.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        self.cancel_input();
+        let filter = SavedFilter {
+            name,
+            tag: self.tag_filter.take(),
+            text: std::mem::take(&mut self.search),
+            ..SavedFilter::default()
+        };
+        self.saved_filters.retain(|f| f.name != filter.name);
+        self.saved_filters.push(filter.clone());
+        self.save_view_state();
+        self.set_saved_filter(Some(filter));
+    }
+
+    /// Deletes the filter highlighted in the picker, if it was saved in the app
+    /// Filters from the config have to be removed there
+    pub(crate) fn delete_saved_filter(&mut self) {
+        let Some(row) = self.filter_picker.selected().and_then(|row| row.checked_sub(1)) else {
+            return;
+        };
+        let Some(index) = row.checked_sub(self.config_filters.len()) else {
+            return;
+        };
+        if index < self.saved_filters.len() {
+            self.saved_filters.remove(index);
+            self.save_view_state();
+            self.filter_picker_move(-1);
+        }
+    }
+
+    /// How a tag is drawn, None for plain `#tag`
+    pub(crate) fn tag_style(&self, tag: &str) -> Option<&TagStyle> {
+        self.tag_styles.get(tag).or_else(|| self.config_tag_styles.get(tag))
+    }
+
+    /// Opens the prompt for the color and icon of the tag highlighted in the
+    /// picker, holding its current ones to edit
+    pub(crate) fn start_tag_style(&mut self) {
+        let Some(row) = self.tag_picker.selected().and_then(|row| row.checked_sub(1)) else {
+            return;
+        };
+        let Some(tag) = self.all_tags().into_iter().nth(row) else {
+            return;
+        };
+        self.input = self.tag_style(&tag).map(|style| style.to_string()).unwrap_or_default();
+        self.input_target = InputTarget::TagStyle(tag);
+        self.mode = Mode::Input;
+    }
+
+    /// Gives the tag the typed color and icon, remembered for the next session
+    /// An empty prompt takes away the one given in the app, leaving the config's
+    pub(crate) fn apply_tag_style(&mut self, tag: String) {
+        if self.input.trim().is_empty() {
+            self.tag_styles.remove(&tag);
+        } else {
+            match TagStyle::parse(&self.input) {
+                Ok(style) => self.tag_styles.insert(tag, style),
+                Err(err) => {
+                    self.input_error = Some(err);
+                    return;
+                }
+            };
+        }
+        self.row_revision += 1;
+        self.cancel_input();
+        self.save_view_state();
+        self.mode = Mode::TagPicker;
+    }
+
+    /// Opens the tag picker with the currently active filter preselected
+    pub(crate) fn open_tag_picker(&mut self) {
+        let row = match &self.tag_filter {
+            Some(tag) => self.all_tags().iter().position(|t| t == tag).map_or(0, |p| p + 1),
+            None => 0,
+        };
+        self.tag_picker.select(Some(row));
+        self.mode = Mode::TagPicker;
+    }
+
+    /// Moves the tag picker selection down, wrapping like the main list
+    pub(crate) fn tag_picker_next(&mut self) {
+        let len = self.all_tags().len() + 1;
+        let i = self.tag_picker.selected().map_or(0, |i| (i + 1) % len);
+        self.tag_picker.select(Some(i));
+    }
+
+    /// Moves the tag picker selection up, wrapping like the main list
+    pub(crate) fn tag_picker_previous(&mut self) {
+        let len = self.all_tags().len() + 1;
+        let i = self.tag_picker.selected().map_or(0, |i| (i + len - 1) % len);
+        self.tag_picker.select(Some(i));
+    }
+
+    /// Applies the tag highlighted in the picker as the filter and closes it
+    pub(crate) fn apply_tag_picker(&mut self) {
+        let tag = match self.tag_picker.selected() {
+            Some(row) if row > 0 => self.all_tags().get(row - 1).cloned(),
+            _ => None,
+        };
+        self.set_tag_filter(tag);
+        self.mode = Mode::Normal;
+    }
+
+    /// The trash of the list currently shown
+    pub(crate) fn trash(&self) -> &Vec<TodoItem> {
+        &self.lists[self.current].trash
+    }
+
+    /// Builds the command that archives the todo at `path` in the current list
+    /// `offset` counts entries that earlier commands of the same batch will add
+    fn archive_command(&self, path: TodoPath, offset: usize) -> Command {
+        let entry = ArchivedTodo {
+            list: self.lists[self.current].name.clone(),
+            archived_at: Timestamp::now(),
+            todo: item_at(self.todos(), &path).clone(),
+        };
+        let index = self.archive.len() + offset;
+        Command::Archive { list: self.current, path, index, entry }
+    }
+
+    /// Moves the selected todo and its subtasks to the archive
+    pub(crate) fn archive_selected(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.execute(self.archive_command(path, 0));
+        }
+    }
+
+    /// Indices of the current list's completed top-level todos
+    /// Completed subtasks of open todos don't count, they're part of their
+    /// parent's checklist
+    fn completed_top_level(&self) -> Vec<usize> {
+        (0..self.todos().len()).filter(|&i| self.todos()[i].is_closed()).collect()
+    }
+
+    /// Asks to clear all completed todos out of the current list at once, into
+    /// the archive or the trash, with the count in the confirmation
+    pub(crate) fn clear_done(&mut self, archive: bool) {
+        if self.completed_top_level().is_empty() {
+            self.notice = Some(self.tr("No completed todos to clear").to_string());
+            return;
+        }
+        self.request(Confirm::ClearDone { archive });
+    }
+
+    /// Moves every completed top-level todo of the current list to the archive
+    fn archive_completed(&mut self) {
+        let done = self.completed_top_level();
+        if done.is_empty() {
+            return;
+        }
+        // Remove from the back so the earlier indices stay valid
+        let commands = done
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(n, &i)| self.archive_command(vec![i], n))
+            .collect();
+        self.execute(Command::Batch(commands));
+    }
+
+    /// Moves every completed top-level todo of the current list to the trash,
+    /// as a single undo step
+    fn trash_completed(&mut self) {
+        let list = self.current;
+        // Remove from the back so the earlier indices stay valid
+        let commands = self
+            .completed_top_level()
+            .into_iter()
+            .rev()
+            .map(|i| Command::Trash { list, path: vec![i], index: 0 })
+            .collect();
+        self.execute_all(commands);
+    }
+
+    /// The todos in each board column, in the order the list shows them
+    /// The board follows the list, so filters, sorting and folding apply to it too
+    pub(crate) fn board_columns(&self) -> Vec<Vec<TodoPath>> {
+        self.board_statuses
+            .iter()
+            .map(|&status| {
+                self.visible.iter().filter(|path| item_at(self.todos(), path).status == status).cloned().collect()
+            })
+            .collect()
+    }
+
+    /// Switches to the board with the todo selected in the list as the current card
+    pub(crate) fn open_board(&mut self) {
+        self.board_column = 0;
+        self.board_row = 0;
+        if let Some(path) = self.selected_path() {
+            self.select_board_card(&path);
+        }
+        self.mode = Mode::Board;
+    }
+
+    /// Goes back to the list, selecting the card that was current on the board
+    pub(crate) fn close_board(&mut self) {
+        if let Some(path) = self.board_selected() {
+            self.select_path(&path);
+        }
+        self.mode = Mode::Normal;
+    }
+
+    /// Makes the card of the todo at `path` the current one
+    fn select_board_card(&mut self, path: &[usize]) {
+        for (column, paths) in self.board_columns().iter().enumerate() {
+            if let Some(row) = paths.iter().position(|p| p == path) {
+                self.board_column = column;
+                self.board_row = row;
+            }
+        }
+    }
+
+    /// Path of the todo on the current card, None in an empty column
+    fn board_selected(&self) -> Option<TodoPath> {
+        self.board_columns().get(self.board_column)?.get(self.board_row).cloned()
+    }
+
+    /// Moves the card selection by rows within a column and by whole columns,
+    /// stopping at the edges
+    pub(crate) fn board_move_selection(&mut self, rows: isize, columns: isize) {
+        let last_column = self.board_statuses.len() as isize - 1;
+        self.board_column = (self.board_column as isize + columns).clamp(0, last_column) as usize;
+        let len = self.board_columns()[self.board_column].len() as isize;
+        self.board_row = (self.board_row as isize + rows).clamp(0, (len - 1).max(0)) as usize;
+    }
+
+    /// Moves the current card to the column `delta` steps away, changing its status
+    /// Like completing from the list, moving a recurring todo to Done schedules
+    /// its next occurrence instead, which brings it back to the first column
+    pub(crate) fn board_move_card(&mut self, delta: isize) {
+        let Some(path) = self.board_selected() else {
+            return;
+        };
+        let target = self.board_column as isize + delta;
+        let Some(&status) = usize::try_from(target).ok().and_then(|i| self.board_statuses.get(i)) else {
+            return;
+        };
+        let before = item_at(self.todos(), &path).clone();
+        let mut after = before.clone();
+        after.change_status(status);
+        let list = self.current;
+        self.execute(Command::Replace { list, path: path.clone(), before: Box::new(before), after: Box::new(after) });
+        self.select_board_card(&path);
+    }
+
+    /// Opens the archive popup with the newest entry selected
+    pub(crate) fn open_archive(&mut self) {
+        self.archive_state.select(if self.archive.is_empty() { None } else { Some(0) });
+        self.mode = Mode::Archive;
+    }
+
+    /// Moves the archive selection down (1) or up (-1), wrapping like the main list
+    pub(crate) fn archive_move(&mut self, delta: isize) {
+        let len = self.archive.len() as isize;
+        if len > 0 {
+            let i = self.archive_state.selected().map_or(0, |i| (i as isize + delta).rem_euclid(len));
+            self.archive_state.select(Some(i as usize));
+        }
+    }
+
+    /// Keeps the archive selection on an existing entry after the archive changed
+    pub(crate) fn clamp_archive_selection(&mut self) {
+        let len = self.archive.len();
+        let row = self.archive_state.selected().unwrap_or(0).min(len.saturating_sub(1));
+        self.archive_state.select(if len == 0 { None } else { Some(row) });
+    }
+
+    /// Puts the selected archive entry back at the end of the list it came from
+    /// Falls back to the current list when that list no longer exists
+    pub(crate) fn restore_archived(&mut self) {
+        if let Some(row) = self.archive_state.selected() {
+            let index = self.archive.len() - 1 - row;
+            let entry = self.archive[index].clone();
+            let list = self.lists.iter().position(|l| l.name == entry.list).unwrap_or(self.current);
+            let path = vec![self.lists[list].todos.len()];
+            self.execute(Command::Unarchive { list, path, index, entry });
+            self.clamp_archive_selection();
+        }
+    }
+
+    /// Opens the trash popup with the most recently deleted todo selected
+    pub(crate) fn open_trash(&mut self) {
+        self.trash_state.select(if self.trash().is_empty() { None } else { Some(0) });
+        self.mode = Mode::Trash;
+    }
+
+    /// Moves the trash selection down (1) or up (-1), wrapping like the main list
+    pub(crate) fn trash_move(&mut self, delta: isize) {
+        let len = self.trash().len() as isize;
+        if len > 0 {
+            let i = self.trash_state.selected().map_or(0, |i| (i as isize + delta).rem_euclid(len));
+            self.trash_state.select(Some(i as usize));
+        }
+    }
+
+    /// Opens the popup with the snoozed todos, the one coming back first selected
+    pub(crate) fn open_snoozed(&mut self) {
+        self.snoozed_state.select(if self.snoozed().is_empty() { None } else { Some(0) });
+        self.mode = Mode::Snoozed;
+    }
+
+    /// Moves the snoozed todos selection down (1) or up (-1), wrapping like the main list
+    pub(crate) fn snoozed_move(&mut self, delta: isize) {
+        let len = self.snoozed().len() as isize;
+        if len > 0 {
+            let i = self.snoozed_state.selected().map_or(0, |i| (i as isize + delta).rem_euclid(len));
+            self.snoozed_state.select(Some(i as usize));
+        }
+    }
+
+    /// Keeps the snoozed todos selection on an existing entry after some came back
+    pub(crate) fn clamp_snoozed_selection(&mut self) {
+        let len = self.snoozed().len();
+        let row = self.snoozed_state.selected().unwrap_or(0).min(len.saturating_sub(1));
+        self.snoozed_state.select(if len == 0 { None } else { Some(row) });
+    }
+
+    /// Brings the selected snoozed todo back into the list right away
+    pub(crate) fn wake_selected(&mut self) {
+        if let Some(path) = self.snoozed_state.selected().and_then(|i| self.snoozed().get(i).cloned()) {
+            let after = TodoItem { snoozed_until: None, ..item_at(self.todos(), &path).clone() };
+            self.replace(path, after);
+            self.clamp_snoozed_selection();
+        }
+    }
+
+    /// Keeps the trash selection on an existing entry after the trash changed
+    pub(crate) fn clamp_trash_selection(&mut self) {
+        let len = self.trash().len();
+        let row = self.trash_state.selected().unwrap_or(0).min(len.saturating_sub(1));
+        self.trash_state.select(if len == 0 { None } else { Some(row) });
+    }
+
+    /// Puts the selected trash entry back at the end of the list
+    /// Its original spot may have shifted or vanished since, so we don't try to guess it
+    pub(crate) fn restore_selected(&mut self) {
+        if let Some(index) = self.trash_state.selected() {
+            let list = self.current;
+            let path = vec![self.todos().len()];
+            self.execute(Command::Restore { list, index, path });
+            self.clamp_trash_selection();
+        }
+    }
+
+    /// Asks to permanently delete the selected trash entry
+    pub(crate) fn purge_selected(&mut self) {
+        if let Some(index) = self.trash_state.selected() {
+            self.request(Confirm::Purge(index));
+        }
+    }
+
+    /// Asks to permanently delete everything in the trash
+    pub(crate) fn empty_trash(&mut self) {
+        if !self.trash().is_empty() {
+            self.request(Confirm::EmptyTrash);
+        }
+    }
+
+    /// Switches to the next sort order and remembers it for the next session
+    /// The selected todo stays selected so the user doesn't lose their place
+    pub(crate) fn cycle_sort(&mut self) {
+        let selected = self.selected_path();
+        self.sort_mode = self.sort_mode.next();
+        self.refresh_and_reselect(selected);
+        self.save_view_state();
+    }
+
+    /// Sorts by a table column, or goes back to the manual order when the list
+    /// is already sorted by it
+    pub(crate) fn sort_by_column(&mut self, column: TableColumn) {
+        let selected = self.selected_path();
+        self.sort_mode = if self.sort_mode == column.sort() { SortMode::Manual } else { column.sort() };
+        self.refresh_and_reselect(selected);
+        self.save_view_state();
+    }
+
+    /// Turns grouping by project on or off and remembers the choice for the next session
+    pub(crate) fn toggle_group_by_project(&mut self) {
+        let selected = self.selected_path();
+        self.group_by_project = !self.group_by_project;
+        self.refresh_and_reselect(selected);
+        self.save_view_state();
+    }
+
+    /// Shows or hides completed todos and remembers the choice for the next session
+    /// Completing a todo while they're hidden makes it disappear, like a filter
+    pub(crate) fn toggle_hide_completed(&mut self) {
+        let selected = self.selected_path();
+        self.hide_completed = !self.hide_completed;
+        self.refresh_and_reselect(selected);
+        self.save_view_state();
+    }
+
+    /// Shows or hides the todos whose start date is still ahead, and
+    /// remembers the choice for the next session
+    pub(crate) fn toggle_unstarted(&mut self) {
+        let selected = self.selected_path();
+        self.show_unstarted = !self.show_unstarted;
+        self.refresh_and_reselect(selected);
+        self.save_view_state();
+    }
+
+    /// Moves the selection by a number of rows without wrapping, clamped to the list
+    /// Used for page jumps, where wrapping around would lose the user's place
+    pub(crate) fn move_selection_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() as isize - 1;
+        let row = self.state().selected().unwrap_or(0) as isize;
+        self.state_mut().select(Some((row + delta).clamp(0, last) as usize));
+    }
+
+    /// Moves a screenful down (+1) or up (-1)
+    pub(crate) fn page(&mut self, direction: isize) {
+        let page = self.list_height.max(1) as isize;
+        self.move_selection_by(direction * page);
+    }
+
+    /// Moves half a screenful down (+1) or up (-1), like vim's Ctrl+d and Ctrl+u
+    pub(crate) fn half_page(&mut self, direction: isize) {
+        let half = (self.list_height / 2).max(1) as isize;
+        self.move_selection_by(direction * half);
+    }
+
+    /// Jumps to the row with this 1-based number, clamped to the last row
+    /// What a count in front of `gg` or `G` means, as in vim
+    pub(crate) fn select_row(&mut self, number: usize) {
+        if !self.visible.is_empty() {
+            let row = number.clamp(1, self.visible.len()) - 1;
+            self.state_mut().select(Some(row));
+        }
+    }
+
+    /// Jumps to the first row
+    pub(crate) fn select_first(&mut self) {
+        if !self.visible.is_empty() {
+            self.state_mut().select(Some(0));
+        }
+    }
+
+    /// Jumps to the last row
+    pub(crate) fn select_last(&mut self) {
+        if !self.visible.is_empty() {
+            let last = self.visible.len() - 1;
+            self.state_mut().select(Some(last));
+        }
+    }
+
+    /// Moves selection to the next todo item
+    /// Wraps around to the start for continuous navigation (circular list pattern)
+    pub(crate) fn next(&mut self) {
+        // Early return if empty to prevent index out of bounds
+        if self.visible.is_empty() {
+            return;
+        }
+        
+        let i = match self.state().selected() {
+            Some(i) => {
+                // Wrap to beginning if at end - provides better UX than stopping at bottom
+                if i >= self.visible.len() - 1 {
+                    0
+                } else {
+                    i + 1
+                }
+            }
+            // If nothing selected (shouldn't happen), start at beginning
+            None => 0,
+        };
+        self.state_mut().select(Some(i));
+    }
+
+    /// Moves selection to the previous todo item
+    /// Wraps around to the end for continuous navigation (circular list pattern)
+    pub(crate) fn previous(&mut self) {
+        // Early return if empty to prevent index out of bounds
+        if self.visible.is_empty() {
+            return;
+        }
+        
+        let i = match self.state().selected() {
+            Some(i) => {
+                // Wrap to end if at beginning - provides better UX than stopping at top
+                if i == 0 {
+                    self.visible.len() - 1
+                } else {
+                    i - 1
+                }
+            }
+            // If nothing selected (shouldn't happen), start at end
+            None => 0,
+        };
+        self.state_mut().select(Some(i));
+    }
+
+    /// Toggles completion of the marked todos, or the selected one if none are marked
+    /// Only the todo itself changes - a parent's progress counter shows its subtasks
+    /// Recurring todos are never marked done, they move to their next due date
+    /// A mixed selection is completed first, so one press finishes them all
+    pub(crate) fn toggle_completed(&mut self) {
+        self.toggle_completed_at(self.targets());
+    }
+
+    /// Toggles completion of the todos at the given paths as one undo step
+    pub(crate) fn toggle_completed_at(&mut self, targets: Vec<TodoPath>) {
+        let complete = targets.iter().any(|path| !item_at(self.todos(), path).is_done());
+        let list = self.current;
+        let commands = targets
+            .into_iter()
+            .map(|path| {
+                let before = item_at(self.todos(), &path).clone();
+                let mut after = before.clone();
+                // Completing a recurring todo schedules the next occurrence instead
+                after.change_status(if complete { Status::Done } else { Status::Pending });
+                Command::Replace { list, path, before: Box::new(before), after: Box::new(after) }
+            })
+            .collect();
+        self.execute_all(commands);
+    }
+
+    /// Stars the marked todos, or the selected one - when they all have a
+    /// star already, it's taken away instead
+    pub(crate) fn toggle_star(&mut self) {
+        let list = self.current;
+        let targets = self.targets();
+        let starred = !targets.iter().all(|path| item_at(self.todos(), path).starred);
+        let commands = targets
+            .into_iter()
+            .filter(|path| item_at(self.todos(), path).starred != starred)
+            .map(|path| {
+                let before = item_at(self.todos(), &path).clone();
+                let after = TodoItem { starred, ..before.clone() };
+                Command::Replace { list, path, before: Box::new(before), after: Box::new(after) }
+            })
+            .collect();
+        self.execute_all(commands);
+    }
+
+    /// Moves the marked todos, or the selected one, on to their next status
+    pub(crate) fn cycle_status(&mut self) {
+        let list = self.current;
+        let commands = self
+            .targets()
+            .into_iter()
+            .map(|path| {
+                let before = item_at(self.todos(), &path).clone();
+                let mut after = before.clone();
+                after.change_status(before.status.next());
+                Command::Replace { list, path, before: Box::new(before), after: Box::new(after) }
+            })
+            .collect();
+        self.execute_all(commands);
+    }
+
+    /// Paths a bulk-capable action applies to: the marked todos, or else the selected one
+    /// Marked subtasks of a marked parent are dropped since the parent carries them along
+    fn targets(&self) -> Vec<TodoPath> {
+        if self.marked.is_empty() {
+            return self.selected_path().into_iter().collect();
+        }
+        self.marked
+            .iter()
+            .filter(|path| !self.marked.iter().any(|other| other.len() < path.len() && path.starts_with(other)))
+            .cloned()
+            .collect()
+    }
+
+    /// Executes several commands as one undo step - a single one runs as itself
+    fn execute_all(&mut self, mut commands: Vec<Command>) {
+        match commands.len() {
+            0 => {}
+            1 => self.execute(commands.remove(0)),
+            _ => self.execute(Command::Batch(commands)),
+        }
+    }
+
+    /// Marks or unmarks the selected todo and moves on to the next one
+    /// Moving on lets a run of todos be marked by pressing `v` repeatedly
+    pub(crate) fn toggle_mark(&mut self) {
+        if let Some(path) = self.selected_path() {
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+            self.move_selection_by(1);
+        }
+    }
+
+    /// Opens the prompt for adding a tag to the marked or selected todos
+    pub(crate) fn start_tag(&mut self) {
+        if !self.targets().is_empty() {
+            self.input_target = InputTarget::Tag;
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Adds the typed tag to every target, or removes it when written as `-tag`
+    /// `@name` adds or removes a context the same way
+    pub(crate) fn apply_tag(&mut self) {
+        let input = self.input.trim().trim_start_matches('#').to_string();
+        self.cancel_input();
+        let (remove, tag) = match input.strip_prefix('-') {
+            Some(tag) => (true, tag.trim_start_matches('#').to_string()),
+            None => (false, input),
+        };
+        if tag.trim_start_matches('@').is_empty() || tag.contains(char::is_whitespace) {
+            return;
+        }
+        let list = self.current;
+        let commands = self
+            .targets()
+            .into_iter()
+            .filter_map(|path| {
+                let before = item_at(self.todos(), &path).clone();
+                let mut after = before.clone();
+                // `@name` is a context
+                let (labels, label) = match tag.strip_prefix('@') {
+                    Some(context) => (&mut after.contexts, context),
+                    None => (&mut after.tags, tag.as_str()),
+                };
+                if remove {
+                    labels.retain(|l| l != label);
+                } else if !labels.iter().any(|l| l == label) {
+                    labels.push(label.to_string());
+                }
+                // Skip todos that already have (or lack) the tag so undo stays clean
+                let changed = after.tags != before.tags || after.contexts != before.contexts;
+                changed.then_some(Command::Replace { list, path, before: Box::new(before), after: Box::new(after) })
+            })
+            .collect();
+        self.execute_all(commands);
+    }
+
+    /// Opens the prompt for moving the marked or selected todos to another list
+    pub(crate) fn start_move_to_list(&mut self) {
+        if self.lists.len() > 1 && !self.targets().is_empty() {
+            self.input_target = InputTarget::MoveToList;
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Moves every target to the end of the list given by number or name
+    /// The todos keep their relative order and take their subtasks along
+    pub(crate) fn apply_move_to_list(&mut self) {
+        let input = self.input.trim().to_string();
+        self.cancel_input();
+        let target = match input.parse::<usize>() {
+            Ok(n) if (1..=self.lists.len()).contains(&n) => Some(n - 1),
+            _ => self.lists.iter().position(|l| l.name.eq_ignore_ascii_case(&input)),
+        };
+        let Some(to) = target.filter(|&to| to != self.current) else {
+            return;
+        };
+        let commands = self.move_commands(to, self.lists[to].todos.len());
+        self.execute_all(commands);
+    }
+
+    /// The commands that move the marked todos, or the selected one, to the
+    /// end of list `to`, which has `len` todos by the time they run
+    fn move_commands(&self, to: usize, len: usize) -> Vec<Command> {
+        let from = self.current;
+        let paths = self.targets();
+        let mut commands = Vec::new();
+        for (n, path) in paths.iter().enumerate() {
+            let item = item_at(self.todos(), path).clone();
+            commands.push(Command::Insert { list: to, path: vec![len + n], item });
+        }
+        // Remove from the back so the remaining paths stay valid, and last so the
+        // current list stays in view
+        for path in paths.into_iter().rev() {
+            let item = item_at(self.todos(), &path).clone();
+            commands.push(Command::Remove { list: from, path, item });
+        }
+        commands
+    }
+
+    /// Parks the marked todos, or the selected one, on the someday list,
+    /// which is made the first time - on the someday list itself they go back
+    /// to the first of the other lists instead
+    pub(crate) fn toggle_someday(&mut self) {
+        if self.targets().is_empty() {
+            return;
+        }
+        let mut commands = Vec::new();
+        let to = if self.lists[self.current].someday {
+            match self.lists.iter().position(|list| !list.someday) {
+                Some(to) => to,
+                None => return,
+            }
+        } else {
+            match self.lists.iter().position(|list| list.someday) {
+                Some(to) => to,
+                None => {
+                    let index = self.lists.len();
+                    let list = TodoList { someday: true, ..TodoList::new(self.tr("Someday")) };
+                    commands.push(Command::InsertList { index, list });
+                    index
+                }
+            }
+        };
+        let len = self.lists.get(to).map_or(0, |list| list.todos.len());
+        commands.extend(self.move_commands(to, len));
+        let name = self.lists.get(to).map_or(self.tr("Someday"), |list| &list.name);
+        let text = self.trf("Moved to {}", &[&name]);
+        self.execute_all(commands);
+        self.toast(ToastKind::Info, text);
+    }
+
+    /// Moves the marked todos, or the selected one, to the trash with their subtasks
+    /// No confirmation needed since they can be restored from the trash or undone
+    pub(crate) fn delete_selected(&mut self) {
+        let list = self.current;
+        // Remove from the back so the remaining paths stay valid
+        let commands = self
+            .targets()
+            .into_iter()
+            .rev()
+            .map(|path| Command::Trash { list, path, index: 0 })
+            .collect::<Vec<_>>();
+        let text = match commands.as_slice() {
+            [] => return,
+            [Command::Trash { path, .. }] => self.trf("Deleted '{}'", &[&item_at(self.todos(), path).text]),
+            commands => self.trn("Deleted {} todo", "Deleted {} todos", commands.len(), &[&commands.len()]),
+        };
+        self.execute_all(commands);
+        let text = self.trf("{} - press {} to undo", &[&text, &self.keymap.label(Action::Undo)]);
+        self.toast(ToastKind::Info, text);
+    }
+
+    /// Runs a destructive action, going through the confirmation popup if enabled
+    fn request(&mut self, action: Confirm) {
+        if self.confirm_delete {
+            self.pending = Some(action);
+            self.mode = Mode::Confirm;
+        } else {
+            self.perform(action);
+        }
+    }
+
+    /// Answers the confirmation popup - only an explicit yes performs the action
+    pub(crate) fn resolve_confirm(&mut self, yes: bool) {
+        // Trash actions are confirmed from the trash popup, so go back there
+        self.mode = match self.pending {
+            Some(Confirm::Purge(_) | Confirm::EmptyTrash) => Mode::Trash,
+            _ => Mode::Normal,
+        };
+        match self.pending.take() {
+            Some(action) if yes => self.perform(action),
+            Some(Confirm::Reload) => self.keep_local_changes(),
+            _ => {}
+        }
+    }
+
+    /// Carries out a confirmed destructive action
+    /// Undo brings deleted lists and trash entries back at their original position
+    pub(crate) fn perform(&mut self, action: Confirm) {
+        let list = self.current;
+        match action {
+            Confirm::DeleteList => {
+                let index = self.current;
+                let list = self.lists[index].clone();
+                self.execute(Command::RemoveList { index, list });
+            }
+            Confirm::Purge(index) => {
+                let before = self.lists[list].trash.clone();
+                let mut after = before.clone();
+                after.remove(index);
+                self.execute(Command::SetTrash { list, before, after });
+                self.clamp_trash_selection();
+            }
+            Confirm::EmptyTrash => {
+                let before = self.lists[list].trash.clone();
+                self.execute(Command::SetTrash { list, before, after: Vec::new() });
+                self.clamp_trash_selection();
+            }
+            Confirm::ClearDone { archive } => {
+                let count = self.completed_top_level().len();
+                if archive {
+                    self.archive_completed();
+                    self.notice = Some(self.trn("Archived {} completed todo (u: undo)", "Archived {} completed todos (u: undo)", count, &[&count]));
+                } else {
+                    self.trash_completed();
+                    self.notice = Some(self.trn(
+                        "Moved {} completed todo to the trash (u: undo)",
+                        "Moved {} completed todos to the trash (u: undo)",
+                        count,
+                        &[&count],
+                    ));
+                }
+            }
+            Confirm::Reload => self.reload_from_disk(),
+        }
+    }
+
+    /// Question shown in the confirmation popup for the pending action
+    pub(crate) fn confirm_prompt(&self) -> String {
+        match &self.pending {
+            Some(Confirm::DeleteList) => {
+                let list = &self.lists[self.current];
+                self.trf("Delete list '{}' with {} todos?", &[&list.name, &list.todos.len()])
+            }
+            Some(Confirm::Purge(index)) => {
+                self.trf("Delete '{}' permanently?", &[&self.lists[self.current].trash[*index].text])
+            }
+            Some(Confirm::EmptyTrash) => {
+                self.trf("Permanently delete all {} todos in the trash?", &[&self.lists[self.current].trash.len()])
+            }
+            // The singular is its own text, since languages differ in more than an "s"
+            Some(Confirm::ClearDone { archive }) => {
+                let count = self.completed_top_level().len();
+                match (*archive, count) {
+                    (true, 1) => self.tr("Archive 1 completed todo?").to_string(),
+                    (true, _) => self.trf("Archive {} completed todos?", &[&count]),
+                    (false, 1) => self.tr("Move 1 completed todo to the trash?").to_string(),
+                    (false, _) => self.trf("Move {} completed todos to the trash?", &[&count]),
+                }
+            }
+            Some(Confirm::Reload) => {
+                let name = self.save_path.file_name().unwrap_or_default().to_string_lossy();
+                self.trf("{} changed on disk. Reload it and drop your unsaved changes?", &[&name])
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Enters input mode pre-filled with the selected todo's text and tags
+    /// Tags are written back as #words so they can be edited in the same line
+    pub(crate) fn start_edit(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input = item_at(self.todos(), &path).quick_add_text();
+            self.input_target = InputTarget::Edit(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Starts editing the selected todo's notes in the detail pane
+    /// The pane is opened automatically since that's where the notes are edited
+    pub(crate) fn start_notes(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input = item_at(self.todos(), &path).notes.clone();
+            self.input_target = InputTarget::Notes(path);
+            self.show_details = true;
+            self.mode = Mode::Notes;
+        }
+    }
+
+    /// Asks the event loop to open the selected todo in `$EDITOR`
+    pub(crate) fn start_external_edit(&mut self) {
+        self.external_edit = self.selected_path();
+    }
+
+    /// The file handed to the external editor: the todo as a quick-add line,
+    /// then a blank line and the notes
+    pub(crate) fn external_edit_text(&self, path: &[usize]) -> String {
+        let todo = item_at(self.todos(), path);
+        let mut text = todo.quick_add_text();
+        text.push_str("\n\n");
+        if !todo.notes.is_empty() {
+            text.push_str(&todo.notes);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Reads back the file the external editor saved, in the format
+    /// `external_edit_text` writes, as one undoable change
+    /// The first line is parsed like the edit prompt, so tags and due dates can
+    /// be changed too; an empty first line leaves the todo alone
+    pub(crate) fn apply_external_edit(&mut self, path: TodoPath, contents: &str) {
+        // Blank lines the editor or the user left above the todo don't count
+        let contents = contents.trim_start();
+        let (first, notes) = contents.split_once('\n').unwrap_or((contents, ""));
+        let first = first.trim();
+        if first.is_empty() {
+            self.notice = Some(self.tr("The first line was empty - todo not changed").to_string());
+            return;
+        }
+        let parsed = TodoItem::parse(first);
+        let mut after = item_at(self.todos(), &path).clone();
+        after.text = parsed.text;
+        after.tags = parsed.tags;
+        after.contexts = parsed.contexts;
+        after.project = parsed.project;
+        after.priority = parsed.priority;
+        after.due = parsed.due;
+        after.due_time = parsed.due_time;
+        // Editors add a trailing newline and the separating blank line is ours
+        after.notes = notes.trim_matches(['\n', '\r']).trim_end().to_string();
+        // Saving without changes shouldn't leave an empty step in the undo history
+        let before = item_at(self.todos(), &path);
+        if after.quick_add_text() != before.quick_add_text() || after.notes != before.notes {
+            self.replace(path, after);
+        }
+    }
+
+    /// Stores the edited notes on the todo
+    /// Unlike titles, empty notes are allowed - that's how notes get cleared
+    pub(crate) fn apply_notes(&mut self, path: TodoPath) {
+        let mut after = item_at(self.todos(), &path).clone();
+        // Trailing blank lines are almost always accidental Enter presses
+        after.notes = self.input.trim_end().to_string();
+        self.cancel_input();
+        if after.notes != item_at(self.todos(), &path).notes {
+            self.replace(path, after);
+        }
+    }
+
+    /// Opens the due date prompt for the selected todo, pre-filled with the current date
+    pub(crate) fn start_due(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input = item_at(self.todos(), &path).due_label().unwrap_or_default();
+            self.input_target = InputTarget::Due(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Sets or clears (with empty input) the due date and time of the todo
+    /// Invalid dates keep the prompt open so the user can fix the typo
+    pub(crate) fn apply_due(&mut self, path: TodoPath) {
+        let (due, due_time) = if self.input.trim().is_empty() {
+            (None, None)
+        } else {
+            match parse_due(&self.input, Date::today()) {
+                Some((date, time)) => (Some(date), time),
+                None => return,
+            }
+        };
+        let mut after = item_at(self.todos(), &path).clone();
+        after.due = due;
+        after.due_time = due_time;
+        self.cancel_input();
+        self.replace(path, after);
+    }
+
+    /// Opens the snooze prompt for the selected todo
+    pub(crate) fn start_snooze(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input_target = InputTarget::Snooze(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Hides the todo until the typed moment, or with empty input brings it back
+    /// A moment that has already passed wouldn't hide anything, so like an
+    /// invalid date it keeps the prompt open
+    pub(crate) fn apply_snooze(&mut self, path: TodoPath) {
+        let until = if self.input.trim().is_empty() {
+            None
+        } else {
+            match parse_due(&self.input, Date::today()) {
+                Some((date, time)) if Timestamp::at(date, time) > Timestamp::now() => Some(Timestamp::at(date, time)),
+                Some(_) => {
+                    self.input_error = Some(self.tr("that's already past").to_string());
+                    return;
+                }
+                None => return,
+            }
+        };
+        let mut after = item_at(self.todos(), &path).clone();
+        after.snoozed_until = until;
+        self.cancel_input();
+        if let Some(until) = until {
+            let text = self.trf("Snoozed '{}' until {}", &[&after.text, &self.dates.moment(until)]);
+            self.toast(ToastKind::Info, text);
+        }
+        self.replace(path, after);
+    }
+
+    /// Opens the recurrence prompt for the selected todo
+    pub(crate) fn start_recurrence(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input = item_at(self.todos(), &path)
+                .recurrence
+                .map(|r| r.to_string())
+                .unwrap_or_default();
+            self.input_target = InputTarget::Recurrence(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Sets or clears (with empty input) the recurrence rule of the todo
+    /// A recurring todo needs a due date, so one is filled in for today if missing
+    pub(crate) fn apply_recurrence(&mut self, path: TodoPath) {
+        let recurrence = if self.input.trim().is_empty() {
+            None
+        } else {
+            match Recurrence::parse(&self.input) {
+                Some(rule) => Some(rule),
+                None => return,
+            }
+        };
+        let mut after = item_at(self.todos(), &path).clone();
+        after.recurrence = recurrence;
+        if recurrence.is_some() && after.due.is_none() {
+            after.due = Some(Date::today());
+        }
+        self.cancel_input();
+        self.replace(path, after);
+    }
+
+    /// Shows the list at `index`, keeping filters as they are
+    pub(crate) fn switch_list(&mut self, index: usize) {
+        if index < self.lists.len() && index != self.current {
+            // Marks are paths into the list being left
+            self.marked.clear();
+            self.current = index;
+            let selected = self.selected_path();
+            self.refresh_and_reselect(selected);
+        }
+    }
+
+    /// Cycles to the next (+1) or previous (-1) list, wrapping around
+    pub(crate) fn cycle_list(&mut self, delta: isize) {
+        let len = self.lists.len() as isize;
+        self.switch_list((self.current as isize + delta).rem_euclid(len) as usize);
+    }
+
+    /// Opens the prompt for naming a new list
+    pub(crate) fn start_new_list(&mut self) {
+        self.input_target = InputTarget::NewList;
+        self.mode = Mode::Input;
+    }
+
+    /// Opens the prompt for renaming the current list, pre-filled with its name
+    pub(crate) fn start_rename_list(&mut self) {
+        self.input = self.lists[self.current].name.clone();
+        self.input_target = InputTarget::RenameList;
+        self.mode = Mode::Input;
+    }
+
+    /// Creates a list named after the input buffer, placed after the current one
+    pub(crate) fn add_list(&mut self) {
+        let name = self.input.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        self.cancel_input();
+        let index = self.current + 1;
+        self.execute(Command::InsertList { index, list: TodoList::new(&name) });
+    }
+
+    /// Renames the current list from the input buffer
+    pub(crate) fn apply_rename_list(&mut self) {
+        let after = self.input.trim().to_string();
+        if after.is_empty() {
+            return;
+        }
+        self.cancel_input();
+        let index = self.current;
+        let before = self.lists[index].name.clone();
+        if before != after {
+            self.execute(Command::RenameList { index, before, after });
+        }
+    }
+
+    /// Deletes the current list with all its todos, after confirmation
+    /// The last remaining list can't be deleted - the app always shows one
+    pub(crate) fn delete_list(&mut self) {
+        if self.lists.len() > 1 {
+            self.request(Confirm::DeleteList);
+        }
+    }
+
+    /// Enters input mode to add a subtask under the selected todo
+    pub(crate) fn start_add_child(&mut self) {
+        if let Some(path) = self.selected_path() {
+            self.input_target = InputTarget::Child(path);
+            self.mode = Mode::Input;
+        }
+    }
+
+    /// Copies the selected todo's text to the system clipboard
+    pub(crate) fn copy_selected(&mut self) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let text = item_at(self.todos(), &path).text.clone();
+        if let Err(err) = self.copy_to_clipboard(&text) {
+            let text = self.trf("Copy failed: {}", &[&err]);
+            self.toast(ToastKind::Error, text);
+        }
+    }
+
+    /// Puts text on the desktop clipboard, or through the terminal where there
+    /// is none, like over SSH or on a console without a display
+    fn copy_to_clipboard(&mut self, text: &str) -> io::Result<()> {
+        if self.clipboard.is_none() {
+            self.clipboard = arboard::Clipboard::new().ok();
+        }
+        match &mut self.clipboard {
+            Some(clipboard) => clipboard.set_text(text).map_err(|err| io::Error::other(err.to_string())),
+            None => copy_through_terminal(text),
+        }
+    }
+
+    /// Replaces the text, tags, priority and due date of the todo with ones
+    /// written in quick-add syntax
+    /// Completion, notes and subtasks are kept - editing only fixes what was typed
+    pub(crate) fn edit(&mut self, path: TodoPath, text: &str) {
+        // Reject empty edits the same way `add` rejects empty todos
+        if text.is_empty() {
+            return;
+        }
+        let parsed = TodoItem::parse(text);
+        let mut after = item_at(self.todos(), &path).clone();
+        after.text = parsed.text;
+        after.tags = parsed.tags;
+        after.contexts = parsed.contexts;
+        after.project = parsed.project;
+        after.priority = parsed.priority;
+        after.due = parsed.due;
+        after.due_time = parsed.due_time;
+        after.scheduled = parsed.scheduled;
+        self.replace(path, after);
+    }
+
+    /// Adds a todo written in quick-add syntax as the last subtask of the todo
+    /// at `parent`
+    /// The parent is expanded first so the new subtask is visible right away
+    pub(crate) fn add_child(&mut self, parent: TodoPath, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let item = TodoItem::parse(text);
+        let parent_item = item_at_mut(self.todos_mut(), &parent);
+        parent_item.collapsed = false;
+        let mut path = parent;
+        path.push(parent_item.children.len());
+        let list = self.current;
+        self.execute(Command::Insert { list, path, item });
+    }
+
+    /// Adds a todo written in quick-add syntax at the end of the current list
+    /// We only add if the text is non-empty to prevent blank todos
+    /// Saves after modification to persist changes immediately
+    pub(crate) fn add(&mut self, text: &str) {
+        if !text.is_empty() {
+            // Parse inline #tags out of the text so they become real tags
+            let item = TodoItem::parse(text);
+
+            // Append at the end - run_command selects the new item for immediate feedback
+            let path = vec![self.todos().len()];
+            let list = self.current;
+            self.execute(Command::Insert { list, path, item });
+        }
+    }
+}
+
+/// Formats a tree path as the 1-based dotted number shown to the user
+pub fn format_todo_number(path: &[usize]) -> String {
+    let parts: Vec<String> = path.iter().map(|i| (i + 1).to_string()).collect();
+    parts.join(".")
+}
+
+/// Parses a 1-based todo number like "3" or "3.1" into a tree path
+/// The dotted form addresses subtasks, matching `format_todo_number`
+pub fn parse_todo_number(s: &str) -> Result<TodoPath, String> {
+    s.split('.')
+        .map(|part| match part.parse::<usize>() {
+            Ok(n) if n >= 1 => Ok(n - 1),
+            _ => Err(format!("'{}' is not a todo number", s)),
+        })
+        .collect()
+}
+
+/// Returns the todo at `path` if it exists
+/// Unlike `item_at` this is safe for paths typed by the user
+pub fn find_item<'a>(todos: &'a [TodoItem], path: &[usize]) -> Option<&'a TodoItem> {
+    let (first, rest) = path.split_first()?;
+    rest.iter().try_fold(todos.get(*first)?, |item, &i| item.children.get(i))
+}
+
+/// Formats tracked time for display: `2h 05m`, or `12m` under an hour
+pub fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        hours => format!("{}h {:02}m", hours, minutes % 60),
+    }
+}
+
+/// Compact single-word form of a duration for todo.txt and Markdown: `2h5m`
+pub(crate) fn format_duration_key(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h{}m", hours, minutes),
+    }
+}
+
+/// Parses the compact form `format_duration_key` writes, like `2h5m` or `90m`
+pub(crate) fn parse_duration_key(value: &str) -> Option<u64> {
+    let (hours, minutes) = match value.split_once('h') {
+        Some((hours, rest)) => (hours.parse().ok()?, rest),
+        None => (0, value),
+    };
+    let minutes = match minutes {
+        "" => 0,
+        minutes => minutes.strip_suffix('m')?.parse().ok()?,
+    };
+    Some((hours * 60 + minutes) * 60)
+}
+
+/// Appends the paths of all todos with a running time tracking session
+fn collect_tracked(todos: &[TodoItem], prefix: &mut TodoPath, out: &mut Vec<TodoPath>) {
+    for (i, todo) in todos.iter().enumerate() {
+        prefix.push(i);
+        if todo.tracking_since.is_some() {
+            out.push(prefix.clone());
+        }
+        collect_tracked(&todo.children, prefix, out);
+        prefix.pop();
+    }
+}
+
+/// Appends the text (with due date) of every open todo whose due moment passes
+/// `when`, searching subtasks too
+pub fn collect_due(todos: &[TodoItem], dates: &DateStyle, when: impl Fn(Timestamp) -> bool + Copy, out: &mut Vec<String>) {
+    for todo in todos {
+        if !todo.is_closed()
+            && let Some(at) = todo.due_at()
+            && when(at)
+        {
+            out.push(format!("{} (due {})", todo.text, dates.due_label(todo).unwrap_or_default()));
+        }
+        collect_due(&todo.children, dates, when, out);
+    }
+}
+
+/// The placeholders `status --format` fills in
+pub const STATUS_FIELDS: [&str; 10] =
+    ["active", "pending", "in_progress", "blocked", "done", "total", "due_today", "overdue", "tracking", "tracked"];
+
+/// Fills in a one-line summary like "{active} due:{due_today}" for status
+/// bars, counting subtasks too - `{{` and `}}` are literal braces
+/// An unknown placeholder is an error rather than printed as it is, so a typo
+/// doesn't go unnoticed in a status bar
+pub fn format_status(lists: &[TodoList], template: &str) -> Result<String, String> {
+    fn walk<'a>(todos: &'a [TodoItem], out: &mut Vec<&'a TodoItem>) {
+        for todo in todos {
+            out.push(todo);
+            walk(&todo.children, out);
+        }
+    }
+    let mut todos = Vec::new();
+    for list in lists {
+        walk(&list.todos, &mut todos);
+    }
+    let now = Timestamp::now();
+    let count = |keep: &dyn Fn(&TodoItem) -> bool| todos.iter().filter(|todo| keep(todo)).count().to_string();
+    let tracking = todos.iter().find(|todo| todo.tracking_since.is_some());
+    let value = |field: &str| {
+        Some(match field {
+            "active" => count(&|todo| !todo.is_closed()),
+            "pending" => count(&|todo| todo.status == Status::Pending),
+            "in_progress" => count(&|todo| todo.status == Status::InProgress),
+            "blocked" => count(&|todo| todo.status == Status::Blocked),
+            "done" => count(&|todo| todo.status == Status::Done),
+            "total" => todos.len().to_string(),
+            "due_today" => count(&|todo| !todo.is_closed() && todo.due == Some(now.date())),
+            "overdue" => count(&|todo| todo.is_overdue()),
+            // Empty while nothing is tracked, so the bar can leave it out
+            "tracking" => tracking.map(|todo| todo.text.clone()).unwrap_or_default(),
+            "tracked" => tracking.map(|todo| format_duration(todo.tracked_seconds(now))).unwrap_or_default(),
+            _ => return None,
+        })
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        out.push_str(&rest[..at]);
+        let brace = &rest[at..at + 1];
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            out.push_str(brace);
+            rest = after;
+            continue;
+        }
+        let (field, after) = match brace {
+            "{" => rest.split_once('}').ok_or("a '{' in the format isn't closed - write '{{' for a brace")?,
+            _ => return Err("a '}' in the format isn't opened - write '}}' for a brace".to_string()),
+        };
+        let field = field.trim();
+        out.push_str(&value(field).ok_or_else(|| format!("unknown field {{{}}} - the fields are {}", field, STATUS_FIELDS.map(|f| format!("{{{}}}", f)).join(" ")))?);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The agenda `today` prints: every open todo that's overdue, then those due
+/// today, each with its list and number so `done` can complete it
+/// Empty when nothing is due
+pub fn format_agenda(lists: &[TodoList], dates: &DateStyle) -> String {
+    fn walk<'a>(list: &'a str, todos: &'a [TodoItem], path: &mut TodoPath, out: &mut Vec<(&'a str, String, &'a TodoItem)>) {
+        for (i, todo) in todos.iter().enumerate() {
+            path.push(i);
+            if !todo.is_closed() && todo.due.is_some_and(|due| due <= Date::today()) {
+                out.push((list, format_todo_number(path), todo));
+            }
+            walk(list, &todo.children, path, out);
+            path.pop();
+        }
+    }
+    let mut due = Vec::new();
+    for list in lists {
+        walk(&list.name, &list.todos, &mut Vec::new(), &mut due);
+    }
+    // Columns line up across both sections
+    let list_width = due.iter().map(|(list, _, _)| list.chars().count()).max().unwrap_or(0);
+    let number_width = due.iter().map(|(_, number, _)| number.len()).max().unwrap_or(0);
+    let (overdue, today): (Vec<_>, Vec<_>) = due.into_iter().partition(|(_, _, todo)| todo.is_overdue());
+
+    let mut out = String::new();
+    for (heading, todos) in [("Overdue", overdue), ("Due today", today)] {
+        if todos.is_empty() {
+            continue;
+        }
+        out.push_str(heading);
+        out.push('\n');
+        for (list, number, todo) in todos {
+            out.push_str(&format!("  {:<list_width$}  {:>number_width$}  {}{}", list, number, todo.priority.marker(), todo.text));
+            // The day only matters for overdue todos, the time for all of them
+            match (todo.is_overdue() && todo.due != Some(Date::today()), todo.due_time) {
+                (true, _) => out.push_str(&format!(" (due {})", dates.due_label(todo).unwrap_or_default())),
+                (false, Some(time)) => out.push_str(&format!(" (at {})", dates.time(time))),
+                (false, None) => {}
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Shows a desktop notification - through the notification service over
+/// D-Bus on Linux and BSDs, Notification Center on macOS and toasts on Windows
+pub fn send_notification(summary: &str, body: &str) -> io::Result<()> {
+    notify_rust::Notification::new()
+        .appname("todo-tui")
+        .summary(summary)
+        .body(body)
+        .show()
+        .map(drop)
+        .map_err(|err| io::Error::other(err.to_string()))
+}
+
+/// Puts text on the system clipboard through the terminal with an OSC 52 escape
+/// The terminal owns the clipboard, so this works over SSH and inside tmux
+/// (with set-clipboard on), where there's no desktop clipboard to reach
+fn copy_through_terminal(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))?;
+    stdout.flush()
+}
+
+/// Standard base64 with padding, as OSC 52 expects
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        // Pack up to three bytes into 24 bits, then emit them six bits at a time
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64 as written by `base64_encode`, None if it isn't
+pub(crate) fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        // A lone trailing character can't hold a whole byte
+        if chunk.len() == 1 {
+            return None;
+        }
+        let n = chunk.iter().enumerate().try_fold(0u32, |n, (i, &c)| Some(n | (value(c)? as u32) << (18 - 6 * i)))?;
+        out.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monthly_todos_keep_their_day_through_short_months() {
+        let year = Date::today().year + 1;
+        let mut todo = TodoItem { due: Date::new(year, 1, 31), recurrence: Some(Recurrence::Monthly), ..TodoItem::new("pay rent") };
+        todo.reschedule(Recurrence::Monthly);
+        assert_eq!(todo.due, Date::new(year, 2, days_in_month(year, 2)));
+        assert_eq!(todo.repeat_day, Some(31));
+        todo.reschedule(Recurrence::Monthly);
+        assert_eq!(todo.due, Date::new(year, 3, 31));
+        assert_eq!(todo.repeat_day, None);
+        // Moved by hand, the todo keeps to its new day
+        todo.due = Date::new(year, 4, 15);
+        todo.repeat_day = Some(31);
+        todo.reschedule(Recurrence::Monthly);
+        assert_eq!(todo.due, Date::new(year, 5, 15));
+    }
+
+    /// An app with these todos on its first list and the first one selected
+    /// Nothing sends a Tick, so nothing is ever saved to the path
+    fn app_with(texts: &[&str]) -> App {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-app-test.json"));
+        app.lists[0].todos = texts.iter().map(|text| TodoItem::new(text)).collect();
+        app.refresh_view();
+        app.state_mut().select(Some(0));
+        app
+    }
+
+    /// The texts of the visible rows, top to bottom
+    fn shown(app: &App) -> Vec<&str> {
+        app.visible.iter().map(|path| item_at(app.todos(), path).text.as_str()).collect()
+    }
+
+    #[test]
+    fn stars_lift_todos_above_their_own_siblings() {
+        let mut app = app_with(&["c", "a", "b"]);
+        app.lists[0].todos[0].children = vec![TodoItem::new("c1"), TodoItem::new("c2")];
+        app.lists[0].todos[0].children[1].starred = true;
+        app.lists[0].todos[2].starred = true;
+        app.refresh_view();
+        // A starred subtask goes to the top of its parent's subtasks, not the list
+        assert_eq!(shown(&app), ["b", "c", "c2", "c1", "a"]);
+
+        // The sort still orders the starred todos among themselves
+        app.lists[0].todos[0].starred = true;
+        app.sort_mode = SortMode::Alphabetical;
+        app.refresh_view();
+        assert_eq!(shown(&app), ["b", "c", "c2", "c1", "a"]);
+        app.lists[0].todos[0].children[0].starred = true;
+        app.refresh_view();
+        assert_eq!(shown(&app), ["b", "c", "c1", "c2", "a"]);
+    }
+
+    #[test]
+    fn starring_toggles_and_undoes_like_any_change() {
+        let mut app = app_with(&["a", "b", "c"]);
+        app.state_mut().select(Some(2));
+        app.toggle_star();
+        assert!(app.lists[0].todos[2].starred);
+        assert_eq!(shown(&app), ["c", "a", "b"]);
+
+        // Marked todos all get a star unless they all have one already
+        app.marked = [vec![0], vec![2]].into();
+        app.toggle_star();
+        assert_eq!(app.lists[0].todos.iter().map(|todo| todo.starred).collect::<Vec<_>>(), [true, false, true]);
+        app.marked = [vec![0], vec![2]].into();
+        app.toggle_star();
+        assert!(app.lists[0].todos.iter().all(|todo| !todo.starred));
+
+        // Each press is one undo step
+        app.undo();
+        assert_eq!(app.lists[0].todos.iter().map(|todo| todo.starred).collect::<Vec<_>>(), [true, false, true]);
+        app.undo();
+        app.undo();
+        assert!(app.lists[0].todos.iter().all(|todo| !todo.starred));
+        assert_eq!(shown(&app), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn edits_that_move_nothing_leave_the_rows_as_a_full_refresh_would() {
+        let mut app = app_with(&["b", "a", "c", "d"]);
+        app.lists[0].todos[1].children = vec![TodoItem::new("a1"), TodoItem::new("a2")];
+        app.lists[0].todos[2].project = Some("home".to_string());
+        app.lists[0].todos[3].tags = vec!["work".to_string()];
+        for sort in SortMode::ALL {
+            for (hide, group, tag) in [(false, false, None), (true, false, None), (false, true, None), (false, false, Some("work"))] {
+                app.sort_mode = sort;
+                app.hide_completed = hide;
+                app.group_by_project = group;
+                app.tag_filter = tag.map(str::to_string);
+                app.refresh_view();
+                for row in 0..app.visible.len() {
+                    app.state_mut().select(Some(row));
+                    // Completing, reprioritising, renaming and tagging, each
+                    // moving the todo in some of the sorts and filters
+                    app.toggle_completed();
+                    app.cycle_priority();
+                    if let Some(path) = app.selected_path() {
+                        let mut after = item_at(app.todos(), &path).clone();
+                        after.text.insert(0, 'z');
+                        after.tags.push("work".to_string());
+                        app.replace(path, after);
+                    }
+                    let (visible, rows, hidden) = (app.visible.clone(), app.list_rows(), app.hidden_completed);
+                    app.refresh_view();
+                    let context = format!("{:?} hide {} group {} tag {:?} row {}", sort, hide, group, tag, row);
+                    assert_eq!((&visible, &rows, hidden), (&app.visible, &app.rows, app.hidden_completed), "{}", context);
+                    for _ in 0..3 {
+                        app.undo();
+                    }
+                }
+            }
+        }
+    }
+
+    /// The texts of the current list's todos in their manual order
+    fn texts(app: &App) -> Vec<&str> {
+        app.todos().iter().map(|todo| todo.text.as_str()).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_adds_deletes_moves_and_edits() {
+        let mut app = app_with(&["a", "b", "c"]);
+        app.add("d");
+        app.state_mut().select(Some(0));
+        app.delete_selected();
+        app.state_mut().select(Some(0));
+        app.move_selected(1);
+        app.edit(vec![2], "d edited");
+        let states: [&[&str]; 5] = [&["a", "b", "c"], &["a", "b", "c", "d"], &["b", "c", "d"], &["c", "b", "d"], &["c", "b", "d edited"]];
+        assert_eq!(texts(&app), states[4]);
+        assert_eq!(app.lists[0].trash.len(), 1);
+
+        // Back one change at a time, the deleted todo coming out of the trash
+        for state in states[..4].iter().rev() {
+            app.undo();
+            assert_eq!(texts(&app), *state);
+        }
+        assert!(app.lists[0].trash.is_empty());
+        app.undo();
+        assert_eq!(texts(&app), states[0], "undoing with nothing left to undo changes nothing");
+
+        // And forward again the same way
+        for state in &states[1..] {
+            app.redo();
+            assert_eq!(texts(&app), *state);
+        }
+        app.redo();
+        assert_eq!(texts(&app), states[4]);
+        assert_eq!(app.lists[0].trash[0].text, "a");
+    }
+
+    #[test]
+    fn a_new_change_clears_what_could_be_redone() {
+        let mut app = app_with(&["a"]);
+        app.add("b");
+        app.add("c");
+        app.undo();
+        app.undo();
+        assert_eq!(app.redo_stack.len(), 2);
+
+        app.edit(vec![0], "a edited");
+        assert!(app.redo_stack.is_empty());
+        app.redo();
+        assert_eq!(texts(&app), ["a edited"]);
+        // The new change is undone like any other, and the undone adds stay gone
+        app.undo();
+        assert_eq!(texts(&app), ["a"]);
+        app.undo();
+        assert_eq!(texts(&app), ["a"]);
+    }
+
+    #[test]
+    fn snoozing_takes_a_moment_still_to_come() {
+        let mut app = app_with(&["water plants", "call mum"]);
+        let tomorrow = Date::today().add_days(1);
+        app.start_snooze();
+        app.input = "tomorrow 9:30".to_string();
+        app.apply_snooze(vec![0]);
+        let until = Timestamp::at(tomorrow, Some(Time { hour: 9, minute: 30 }));
+        assert_eq!(app.lists[0].todos[0].snoozed_until, Some(until));
+        assert_eq!((app.mode, shown(&app)), (Mode::Normal, vec!["call mum"]));
+        assert_eq!(app.snoozed(), [vec![0]]);
+
+        // A moment that's gone, or no moment at all, keeps the prompt open
+        for input in ["2000-01-01 12:00", "someday soon"] {
+            app.state_mut().select(Some(0));
+            app.start_snooze();
+            app.input = input.to_string();
+            app.apply_snooze(vec![1]);
+            assert_eq!((app.mode, app.lists[0].todos[1].snoozed_until), (Mode::Input, None), "{}", input);
+            assert_eq!(app.input_error.is_some(), input.starts_with("2000"), "{}", input);
+            app.cancel_input();
+        }
+
+        // Nothing typed wakes the todo up again
+        app.input.clear();
+        app.apply_snooze(vec![0]);
+        assert_eq!(app.lists[0].todos[0].snoozed_until, None);
+        assert_eq!(shown(&app), ["water plants", "call mum"]);
+    }
+
+    #[test]
+    fn the_next_wake_is_the_soonest_moment_still_ahead() {
+        let now = Timestamp::now();
+        let later = |seconds| Some(Timestamp(now.0 + seconds));
+        let mut todos = vec![TodoItem::new("a"), TodoItem::new("b")];
+        assert_eq!(next_wake(&todos, now), None);
+
+        // Moments already past wake nothing
+        todos[0].snoozed_until = Some(Timestamp(now.0 - 60));
+        todos[1].scheduled = Some(Date::today().add_days(-3));
+        assert_eq!(next_wake(&todos, now), None);
+
+        todos[0].snoozed_until = later(7200);
+        todos[1].scheduled = Some(Date::today().add_days(2));
+        assert_eq!(next_wake(&todos, now), later(7200));
+        // Subtasks count too, and a start date wakes at its midnight
+        todos[1].children = vec![TodoItem { snoozed_until: later(60), ..TodoItem::new("b1") }];
+        assert_eq!(next_wake(&todos, now), later(60));
+        todos[1].children[0].snoozed_until = None;
+        todos[0].snoozed_until = None;
+        assert_eq!(next_wake(&todos, now), Some(Timestamp::from_date(Date::today().add_days(2))));
+    }
+
+    #[test]
+    fn todos_wait_out_of_sight_until_their_start_date() {
+        let today = Date::today();
+        let todo = |scheduled| TodoItem { scheduled, ..TodoItem::new("file taxes") };
+        assert!(todo(None).is_started(today));
+        assert!(todo(Some(today)).is_started(today));
+        assert!(todo(Some(today.add_days(-1))).is_started(today));
+        assert!(!todo(Some(today.add_days(1))).is_started(today));
+
+        // A todo that hasn't started takes its subtasks out of the list with it
+        let mut app = app_with(&["a", "b", "c"]);
+        app.lists[0].todos[0].scheduled = Some(today.add_days(1));
+        app.lists[0].todos[1].children = vec![TodoItem::new("b1"), todo(Some(today.add_days(7)))];
+        app.lists[0].todos[1].children[0].children = vec![todo(Some(today.add_days(2)))];
+        app.lists[0].todos[2].scheduled = Some(today);
+        app.refresh_view();
+        assert_eq!(shown(&app), ["b", "b1", "c"]);
+        assert_eq!(app.unstarted_count, 3);
+
+        app.show_unstarted = true;
+        app.refresh_view();
+        assert_eq!(shown(&app), ["a", "b", "b1", "file taxes", "file taxes", "c"]);
+    }
+
+    #[test]
+    fn unstarted_todos_are_counted_at_every_level() {
+        let today = Date::today();
+        let later = |text, days| TodoItem { scheduled: Some(today.add_days(days)), ..TodoItem::new(text) };
+        assert_eq!(count_unstarted(&[], today), 0);
+        let mut todos = vec![later("a", 1), later("b", 0), later("c", -4), TodoItem::new("d")];
+        assert_eq!(count_unstarted(&todos, today), 1);
+        // Subtasks of a todo that has or hasn't started count on their own
+        todos[0].children = vec![later("a1", 3), later("a2", 0)];
+        todos[3].children = vec![later("d1", 1)];
+        todos[3].children[0].children = vec![later("d1a", 2)];
+        assert_eq!(count_unstarted(&todos, today), 4);
+        assert_eq!(count_unstarted(&todos, today.add_days(3)), 0);
+    }
+
+    /// A todo that was due yesterday, which the daily review asks about
+    fn due_yesterday(text: &str) -> TodoItem {
+        TodoItem { due: Some(Date::today().add_days(-1)), ..TodoItem::new(text) }
+    }
+
+    /// The texts of the todos the review has still to ask about, in order
+    fn reviewing(app: &App) -> Vec<&str> {
+        app.review.iter().map(|(list, path)| item_at(&app.lists[*list].todos, path).text.as_str()).collect()
+    }
+
+    #[test]
+    fn dropping_a_todo_in_the_review_keeps_the_paths_to_come_right() {
+        let mut app = app_with(&[]);
+        app.lists[0].todos = vec![due_yesterday("a"), due_yesterday("b"), TodoItem::new("c"), due_yesterday("d")];
+        app.lists[0].todos[0].children = vec![due_yesterday("a1"), TodoItem::new("a2")];
+        app.lists[0].todos[1].children = vec![due_yesterday("b1"), due_yesterday("b2")];
+        app.lists.push(TodoList { todos: vec![due_yesterday("e")], ..TodoList::new("Work") });
+        app.refresh_view();
+        app.start_review();
+        assert_eq!(reviewing(&app), ["a", "a1", "b", "b1", "b2", "d", "e"]);
+
+        // Its subtasks go with it, and the todos after it move up a place
+        app.review_drop();
+        assert_eq!(reviewing(&app), ["b", "b1", "b2", "d", "e"]);
+        let paths: Vec<TodoPath> = app.review.iter().map(|(_, path)| path.clone()).collect();
+        assert_eq!(paths, [vec![0], vec![0, 0], vec![0, 1], vec![2], vec![0]]);
+        // A subtask only moves up its own siblings
+        app.next_review();
+        app.review_drop();
+        assert_eq!(reviewing(&app), ["b2", "d", "e"]);
+        assert_eq!(app.review[0].1, [0, 0]);
+        app.review_drop();
+        app.review_drop();
+        assert_eq!(reviewing(&app), ["e"]);
+        assert_eq!(app.review[0], (1, vec![0]));
+        let texts = |todos: &[TodoItem]| todos.iter().map(|todo| todo.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts(&app.lists[0].todos), ["b", "c"]);
+        assert!(app.lists[0].todos[0].children.is_empty());
+    }
+
+    #[test]
+    fn a_read_only_instance_leaves_the_review_alone() {
+        let mut app = app_with(&[]);
+        app.lists[0].todos = vec![due_yesterday("a"), due_yesterday("b")];
+        app.refresh_view();
+        app.read_only = true;
+        app.start_review();
+        assert_eq!((app.mode, app.reviewed), (Mode::Normal, None));
+        assert!(app.review.is_empty());
+
+        // Another instance taking the file midway stops the changes, not the review
+        app.read_only = false;
+        app.start_review();
+        assert_eq!(app.mode, Mode::Review);
+        app.read_only = true;
+        app.review_drop();
+        app.review_complete();
+        app.review_today();
+        app.review_reschedule();
+        assert_eq!((app.mode, reviewing(&app)), (Mode::Review, vec!["a", "b"]));
+        assert!(app.lists[0].todos.iter().all(|todo| !todo.is_closed() && todo.due != Some(Date::today())));
+        assert_eq!(app.lists[0].todos.len(), 2);
+        assert!(app.notice.as_deref().is_some_and(|notice| notice.starts_with("Read-only")));
+        app.next_review();
+        assert_eq!(reviewing(&app), ["b"]);
+    }
+
+    /// Shorthand for the dates the parsing tests expect
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date::new(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn due_dates_parse_the_way_people_type_them() {
+        // A Friday at the end of a long month
+        let today = date(2025, 1, 31);
+        let cases = [
+            ("today", date(2025, 1, 31)),
+            ("tod", date(2025, 1, 31)),
+            ("Tomorrow", date(2025, 2, 1)),
+            ("tom", date(2025, 2, 1)),
+            ("next week", date(2025, 2, 7)),
+            ("next month", date(2025, 2, 28)),
+            ("in 3 days", date(2025, 2, 3)),
+            ("in 1 day", date(2025, 2, 1)),
+            ("in 2 weeks", date(2025, 2, 14)),
+            ("in 13 months", date(2026, 2, 28)),
+            ("mon", date(2025, 2, 3)),
+            ("next monday", date(2025, 2, 3)),
+            // The day it is today means the one a week later
+            ("friday", date(2025, 2, 7)),
+            ("2025-06-01", date(2025, 6, 1)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_due(text, today), Some((expected, None)), "{}", text);
+        }
+        let at = |hour, minute| Some(Time { hour, minute });
+        assert_eq!(parse_due("5pm", today), Some((today, at(17, 0))));
+        assert_eq!(parse_due("noon", today), Some((today, at(12, 0))));
+        assert_eq!(parse_due("fri 5pm", today), Some((date(2025, 2, 7), at(17, 0))));
+        assert_eq!(parse_due("tomorrow at 9:30", today), Some((date(2025, 2, 1), at(9, 30))));
+        assert_eq!(parse_due("2025-06-01 17:00", today), Some((date(2025, 6, 1), at(17, 0))));
+        assert_eq!(parse_due("12am", today), Some((today, at(0, 0))));
+    }
+
+    #[test]
+    fn nonsense_and_huge_due_dates_are_refused() {
+        let today = date(2025, 1, 31);
+        for text in ["", "someday", "in x days", "in 3 fortnights", "next year", "2025-02-30", "2025-13-01", "mo", "25:00", "13pm", "fri 5:7"] {
+            assert_eq!(parse_due(text, today), None, "{}", text);
+        }
+        // Counts too big for the parser, or for the calendar, are an answer of none
+        // rather than a panic
+        assert_eq!(parse_due("in 99999999999 months", today), None);
+        assert!(parse_due("in 2147483647 months", today).is_some());
+        assert_eq!(Date { year: i32::MAX, month: 12, day: 1 }.add_months(1), None);
+        assert_eq!(Date { year: i32::MIN, month: 1, day: 1 }.add_months(-1), None);
+    }
+
+    /// The query for a single condition
+    fn condition(condition: Condition) -> Query {
+        Query::Condition(condition)
+    }
+
+    fn and(left: Query, right: Query) -> Query {
+        Query::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Query, right: Query) -> Query {
+        Query::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(query: Query) -> Query {
+        Query::Not(Box::new(query))
+    }
+
+    #[test]
+    fn queries_bind_not_before_and_before_or() {
+        let today = date(2025, 1, 31);
+        let parse = |input| Query::parse(input, today).unwrap();
+        let tag = |name: &str| condition(Condition::Tag(name.to_string()));
+        let done = condition(Condition::Done);
+        assert_eq!(parse("#a or #b #c"), or(tag("a"), and(tag("b"), tag("c"))));
+        assert_eq!(parse("#a AND #b or #c"), or(and(tag("a"), tag("b")), tag("c")));
+        assert_eq!(parse("not done #a"), and(not(done.clone()), tag("a")));
+        assert_eq!(parse("not (done or #a)"), not(or(done.clone(), tag("a"))));
+        assert_eq!(parse("NOT NOT done"), not(not(done.clone())));
+        assert_eq!(parse("(#a or #b) and (#c)"), and(or(tag("a"), tag("b")), tag("c")));
+        assert_eq!(parse("#a #b #c"), and(and(tag("a"), tag("b")), tag("c")));
+        // Keywords only count as whole words
+        assert_eq!(parse("order"), condition(Condition::Text("order".to_string())));
+    }
+
+    #[test]
+    fn query_fields_take_their_operators() {
+        let today = date(2025, 1, 31);
+        let parse = |input| Query::parse(input, today).unwrap();
+        let cases = [
+            ("tag:errands", Condition::Tag("errands".to_string())),
+            ("@phone", Condition::Context("phone".to_string())),
+            ("context:phone", Condition::Context("phone".to_string())),
+            ("+garden", Condition::Project("garden".to_string())),
+            ("project:garden", Condition::Project("garden".to_string())),
+            ("status:in_progress", Condition::Status(Status::InProgress)),
+            ("open", Condition::Open),
+            ("OVERDUE", Condition::Overdue),
+            ("priority>=medium", Condition::Priority(Comparison::GreaterOrEqual, Priority::Medium)),
+            ("prio=none", Condition::Priority(Comparison::Equal, Priority::None)),
+            ("due<2025-02-01", Condition::Due(Comparison::Less, date(2025, 2, 1))),
+            ("due<=tomorrow", Condition::Due(Comparison::LessOrEqual, date(2025, 2, 1))),
+            ("due>today", Condition::Due(Comparison::Greater, today)),
+            ("due:any", Condition::HasDue(true)),
+            ("due:none", Condition::HasDue(false)),
+            ("text:\"Buy milk\"", Condition::Text("buy milk".to_string())),
+            ("Milk", Condition::Text("milk".to_string())),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input), condition(expected), "{}", input);
+        }
+        let todo = TodoItem { priority: Priority::High, tags: vec!["errands".to_string()], ..TodoItem::new("Buy milk") };
+        assert!(parse("#errands and priority>low and not done and milk").matches(&todo));
+        assert!(!parse("#errands and (done or due:any)").matches(&todo));
+    }
+
+    #[test]
+    fn query_errors_point_at_their_column() {
+        let today = date(2025, 1, 31);
+        let error = |input| Query::parse(input, today).unwrap_err();
+        assert_eq!(error(""), "empty query");
+        assert_eq!(error("   "), "empty query");
+        assert_eq!(error("done tag:"), "column 6: missing value after 'tag:'");
+        assert_eq!(error("(done"), "column 1: '(' is never closed");
+        assert_eq!(error("done)"), "column 5: unmatched ')'");
+        assert_eq!(error("done and"), "column 9: expected a condition at the end");
+        assert_eq!(error("or done"), "column 1: expected a condition before OR");
+        assert_eq!(error("done and ()"), "column 11: expected a condition before ')'");
+        assert_eq!(error("text:\"buy milk"), "column 6: unterminated quote");
+        assert_eq!(error("tag<a"), "column 1: 'tag' can only be compared with ':'");
+        assert_eq!(error("due>any"), "column 1: due:none and due:any can't be compared");
+        assert_eq!(error("due<someday"), "column 1: invalid date 'someday'");
+        assert!(error("#a status:later").starts_with("column 4: unknown status 'later'"));
+        assert!(error("priority:urgent").starts_with("column 1: unknown priority 'urgent'"));
+        assert!(error("size>3").starts_with("column 1: unknown field 'size'"));
+    }
+
+    #[test]
+    fn deeply_nested_queries_are_an_error_rather_than_a_crash() {
+        let today = date(2025, 1, 31);
+        let parens = format!("{}done{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(Query::parse(&parens, today).unwrap_err(), "column 65: nested more than 64 deep");
+        let nots = "not ".repeat(100) + "done";
+        assert_eq!(Query::parse(&nots, today).unwrap_err(), "column 257: nested more than 64 deep");
+        let words = vec!["done"; 100_000].join(" ");
+        assert_eq!(Query::parse(&words, today).unwrap_err(), "column 1281: more than 256 words");
+        // Right at the limits it's still a query
+        assert!(Query::parse(&format!("{}done{}", "(".repeat(64), ")".repeat(64)), today).is_ok());
+        assert!(Query::parse(&vec!["done"; 256].join(" "), today).is_ok());
+    }
+
+    #[test]
+    fn repeating_every_zero_days_is_refused() {
+        assert_eq!(Recurrence::parse("every 0 days"), None);
+        assert_eq!(Recurrence::parse("1d"), Some(Recurrence::Daily));
+        assert_eq!(Recurrence::every(0), None);
+        assert!(serde_json::from_str::<Recurrence>(r#"{"EveryNDays":0}"#).is_err());
+        assert_eq!(serde_json::from_str::<Recurrence>(r#"{"EveryNDays":3}"#).unwrap(), Recurrence::EveryNDays(3));
+        assert_eq!(serde_json::from_str::<Recurrence>(r#""Monthly""#).unwrap(), Recurrence::Monthly);
+    }
+
+    #[test]
+    fn contexts_are_kept_apart_from_tags() {
+        let todo = TodoItem::parse("call the plumber @phone #home @ @phone");
+        assert_eq!(todo.text, "call the plumber @");
+        assert_eq!(todo.tags, ["home"]);
+        assert_eq!(todo.contexts, ["phone"]);
+        assert_eq!(todo.quick_add_text(), "call the plumber @ #home @phone");
+        assert_eq!(todo.labels(), ["home", "@phone"]);
+        assert!(todo.has_label("@phone") && todo.has_label("home"));
+        assert!(!todo.has_label("phone") && !todo.has_label("@home"));
+
+        let today = date(2025, 1, 31);
+        let query = Query::parse("@phone", today).unwrap();
+        assert!(query.matches(&todo));
+        assert!(!Query::parse("#phone", today).unwrap().matches(&todo));
+        let filter = SavedFilter { tag: Some("@phone".to_string()), ..SavedFilter::named("Calls") };
+        assert!(filter.matches(&todo, today));
+        let inbox = SavedFilter { untagged: true, ..SavedFilter::named("Inbox") };
+        assert!(!inbox.matches(&TodoItem::parse("call @phone"), today));
+    }
+}
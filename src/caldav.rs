@@ -0,0 +1,801 @@
+//! Two-way sync of the lists with a CalDAV server, like Nextcloud or Radicale,
+//! so the todos show up in the task app on a phone
+//! Each list is a calendar of VTODOs under the calendar home in the config.
+//! The sync is `reconcile` in sync.rs, as it is for Todoist, only the
+//! requests are WebDAV's and the tasks iCalendar
+
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    app::{Date, Priority, Recurrence, Status, Time, Timestamp, TodoItem, TodoList},
+    import::parse_timestamp,
+    storage::{ics_calendar, ics_fold, ics_status, ics_vtodo},
+    sync::{curl, curl_quote, reconcile, Backend, RemoteTask, Service, SyncOutcome, SyncState},
+};
+
+/// The "caldav" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct CalDavConfig {
+    /// Address of the calendar home, the collection the calendars are in -
+    /// like https://cloud.example.com/remote.php/dav/calendars/me/
+    pub(crate) url: String,
+    pub(crate) username: String,
+    /// An app password where the server has them
+    pub(crate) password: String,
+    /// Minutes between syncs while the app runs, 0 to sync only when asked
+    pub(crate) interval: u64,
+}
+
+impl Default for CalDavConfig {
+    fn default() -> CalDavConfig {
+        CalDavConfig { url: String::new(), username: String::new(), password: String::new(), interval: 5 }
+    }
+}
+
+/// The VTODO properties the app writes - the rest, like alarms set on the
+/// phone, is kept when a todo goes back to the server
+/// PERCENT-COMPLETE is dropped, so a todo reopened here isn't still 100% done
+const OWN_PROPERTIES: [&str; 14] = [
+    "UID", "DTSTAMP", "SUMMARY", "DESCRIPTION", "DUE", "RRULE", "STATUS", "PRIORITY", "CATEGORIES", "CREATED",
+    "LAST-MODIFIED", "COMPLETED", "RELATED-TO", "PERCENT-COMPLETE",
+];
+
+/// Whether a todo and a VTODO agree on everything that's synced
+/// Blocked todos are NEEDS-ACTION there, so they're the same as not started
+fn same(todo: &TodoItem, task: &TodoItem) -> bool {
+    let tags = |todo: &TodoItem| todo.labels().into_iter().collect::<BTreeSet<_>>();
+    todo.text == task.text
+        && todo.notes == task.notes
+        && todo.priority == task.priority
+        && ics_status(todo.status) == ics_status(task.status)
+        && (todo.due, todo.due_time) == (task.due, task.due_time)
+        && tags(todo) == tags(task)
+}
+
+/// Takes the synced fields of a VTODO for a todo
+pub(crate) fn pull(todo: &mut TodoItem, task: &TodoItem) {
+    todo.text = task.text.clone();
+    todo.notes = task.notes.clone();
+    todo.priority = task.priority;
+    todo.due = task.due;
+    todo.due_time = task.due_time;
+    todo.recurrence = task.recurrence;
+    todo.tags = task.tags.clone();
+    todo.contexts = task.contexts.clone();
+    if ics_status(todo.status) != ics_status(task.status) {
+        todo.set_status(task.status);
+    }
+}
+
+/// Content lines with the folded ones joined back up
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// A content line split into its name (upper case), parameters and value
+fn property(line: &str) -> (String, &str, &str) {
+    // The value starts at the first colon that isn't in a quoted parameter
+    let mut quoted = false;
+    let colon = line
+        .char_indices()
+        .find(|&(_, c)| {
+            quoted ^= c == '"';
+            c == ':' && !quoted
+        })
+        .map_or(line.len(), |(i, _)| i);
+    let head = &line[..colon];
+    let value = line.get(colon + 1..).unwrap_or("");
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    (name.to_ascii_uppercase(), params, value)
+}
+
+/// Undoes the escaping of a text value
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// The items of a list value like CATEGORIES, split at unescaped commas
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => items.push(String::new()),
+            _ => {
+                escaped = c == '\\' && !escaped;
+                items.last_mut().unwrap().push(c);
+            }
+        }
+    }
+    items.iter().map(|item| unescape(item).trim().to_string()).filter(|item| !item.is_empty()).collect()
+}
+
+/// A DUE value: a date, or a date and time - local unless it ends in Z
+/// A TZID is taken as local time too, which is right for most people
+fn parse_due(value: &str) -> Option<(Date, Option<Time>)> {
+    let date = value.get(..8).filter(|date| date.bytes().all(|b| b.is_ascii_digit()))?;
+    let date = Date::new(date[..4].parse().ok()?, date[4..6].parse().ok()?, date[6..].parse().ok()?)?;
+    let Some(time) = value.get(8..).and_then(|rest| rest.strip_prefix('T')) else {
+        return Some((date, None));
+    };
+    if time.ends_with('Z') {
+        let at = parse_timestamp(value)?;
+        return Some((at.date(), Some(at.time())));
+    }
+    let hour = time.get(..2)?.parse().ok().filter(|h| *h < 24)?;
+    let minute = time.get(2..4)?.parse().ok().filter(|m| *m < 60)?;
+    Some((date, Some(Time { hour, minute })))
+}
+
+/// The repeats todos can have, from an RRULE - anything fancier is None
+fn parse_rrule(value: &str) -> Option<Recurrence> {
+    let mut freq = None;
+    let mut interval = 1;
+    for part in value.split(';') {
+        match part.split_once('=')? {
+            ("FREQ", value) => freq = Some(value),
+            ("INTERVAL", value) => interval = value.parse().ok()?,
+            // Ends and by-rules change which days it's on, which todos can't say
+            _ => return None,
+        }
+    }
+    // An interval of 0 would never move the todo on
+    match (freq?, interval) {
+        ("DAILY", n) => Recurrence::every(n),
+        ("WEEKLY", 1) => Some(Recurrence::Weekly),
+        ("WEEKLY", n) => Recurrence::every(n.checked_mul(7)?),
+        ("MONTHLY", 1) => Some(Recurrence::Monthly),
+        _ => None,
+    }
+}
+
+/// A VTODO read from a calendar object
+struct Vtodo {
+    uid: String,
+    parent: Option<String>,
+    todo: TodoItem,
+    modified: Option<Timestamp>,
+    /// Whether it repeats in a way todos can't, so the RRULE is left alone
+    odd_rule: bool,
+}
+
+/// Reads the VTODO in a calendar object - the main one, not the changed
+/// occurrences of a repeating one
+fn parse_vtodo(ics: &str) -> Option<Vtodo> {
+    let mut found = None;
+    // How deep in components below the VTODO, None outside of one
+    let mut depth: Option<usize> = None;
+    let mut current: Option<(Vtodo, bool)> = None;
+    for line in unfold(ics) {
+        let (name, params, value) = property(&line);
+        match (name.as_str(), depth) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                depth = Some(0);
+                let todo = TodoItem { created_at: None, ..TodoItem::new("") };
+                current = Some((Vtodo { uid: String::new(), parent: None, todo, modified: None, odd_rule: false }, false));
+            }
+            ("BEGIN", Some(d)) => depth = Some(d + 1),
+            ("END", Some(0)) => {
+                depth = None;
+                if let Some((vtodo, false)) = current.take()
+                    && !vtodo.uid.is_empty()
+                    && found.is_none()
+                {
+                    found = Some(vtodo);
+                }
+            }
+            ("END", Some(d)) => depth = Some(d - 1),
+            (_, Some(0)) => {
+                let Some((vtodo, occurrence)) = &mut current else {
+                    continue;
+                };
+                let todo = &mut vtodo.todo;
+                match name.as_str() {
+                    "UID" => vtodo.uid = value.to_string(),
+                    "RECURRENCE-ID" => *occurrence = true,
+                    "SUMMARY" => todo.text = unescape(value),
+                    "DESCRIPTION" => todo.notes = unescape(value),
+                    // Contexts go as categories starting with '@', like they're typed
+                    "CATEGORIES" => {
+                        for category in split_list(value) {
+                            match category.strip_prefix('@').filter(|context| !context.is_empty()) {
+                                Some(context) => todo.contexts.push(context.to_string()),
+                                None => todo.tags.push(category),
+                            }
+                        }
+                    }
+                    "PRIORITY" => {
+                        todo.priority = match value.trim().parse::<u32>() {
+                            Ok(1..=4) => Priority::High,
+                            Ok(5) => Priority::Medium,
+                            Ok(6..=9) => Priority::Low,
+                            _ => Priority::None,
+                        }
+                    }
+                    "STATUS" => {
+                        todo.status = match value.trim().to_ascii_uppercase().as_str() {
+                            "COMPLETED" => Status::Done,
+                            "CANCELLED" => Status::Cancelled,
+                            "IN-PROCESS" => Status::InProgress,
+                            _ => Status::Pending,
+                        }
+                    }
+                    "DUE" => {
+                        if let Some((date, time)) = parse_due(value.trim()) {
+                            todo.due = Some(date);
+                            todo.due_time = time.filter(|_| !params.to_ascii_uppercase().contains("VALUE=DATE"));
+                        }
+                    }
+                    "RRULE" => {
+                        todo.recurrence = parse_rrule(value.trim());
+                        vtodo.odd_rule = todo.recurrence.is_none();
+                    }
+                    "CREATED" => todo.created_at = parse_timestamp(value),
+                    "COMPLETED" => todo.completed_at = parse_timestamp(value),
+                    "LAST-MODIFIED" => vtodo.modified = parse_timestamp(value),
+                    // Other relations, like siblings, aren't the parent
+                    "RELATED-TO" if params.is_empty() || params.to_ascii_uppercase().contains("RELTYPE=PARENT") => {
+                        vtodo.parent = Some(value.trim().to_string())
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut vtodo = found?;
+    if vtodo.todo.status != Status::Done {
+        vtodo.todo.completed_at = None;
+    } else if vtodo.todo.completed_at.is_none() {
+        vtodo.todo.completed_at = vtodo.modified.or(Some(Timestamp::now()));
+    }
+    Some(vtodo)
+}
+
+/// A calendar object with the VTODO's own properties swapped for `ours`
+/// Alarms, time zones and other apps' properties stay as they were
+fn merge(original: &str, ours: &[String], odd_rule: bool) -> String {
+    let lines = unfold(original);
+    // Where the main VTODO begins and ends
+    let mut todo = None;
+    let mut depth = 0;
+    let mut start = 0;
+    let mut occurrence = false;
+    for (i, line) in lines.iter().enumerate() {
+        let (name, _, value) = property(line);
+        match name.as_str() {
+            "BEGIN" => {
+                depth += 1;
+                if depth == 2 && value.eq_ignore_ascii_case("VTODO") {
+                    (start, occurrence) = (i, false);
+                }
+            }
+            "END" => {
+                if depth == 2 && value.eq_ignore_ascii_case("VTODO") && !occurrence && todo.is_none() {
+                    todo = Some((start, i));
+                }
+                depth -= 1;
+            }
+            "RECURRENCE-ID" if depth == 2 => occurrence = true,
+            _ => {}
+        }
+    }
+    let Some((start, end)) = todo else {
+        return ics_calendar(ours.to_vec());
+    };
+
+    let mut out: Vec<String> = lines[..=start].to_vec();
+    out.extend(ours[1..ours.len() - 1].iter().cloned());
+    let mut depth = 0;
+    for line in &lines[start + 1..end] {
+        let (name, params, _) = property(line);
+        let own = depth == 0
+            && OWN_PROPERTIES.contains(&name.as_str())
+            && !(name == "RRULE" && odd_rule)
+            && !(name == "RELATED-TO" && !params.is_empty() && !params.to_ascii_uppercase().contains("RELTYPE=PARENT"));
+        match name.as_str() {
+            "BEGIN" => depth += 1,
+            "END" => depth -= 1,
+            _ => {}
+        }
+        if !own {
+            out.push(line.clone());
+        }
+    }
+    out.extend(lines[end..].iter().cloned());
+    out.iter().map(|line| ics_fold(line) + "\r\n").collect()
+}
+
+/// An element of an XML reply: its name without the namespace prefix, its
+/// attributes as written and the text in it
+struct Element {
+    name: String,
+    attributes: String,
+    text: String,
+}
+
+/// The elements of one `<response>` of a WebDAV multistatus reply
+struct Response(Vec<Element>);
+
+impl Response {
+    /// Text of the first element with this name
+    fn text(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|element| element.name == name).map(|element| element.text.trim())
+    }
+
+    fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.0.iter().filter(move |element| element.name == name)
+    }
+}
+
+/// The responses in a multistatus reply
+/// Not a real XML parser - just enough of one for what CalDAV servers send
+fn responses(xml: &str) -> Vec<Response> {
+    let mut responses: Vec<Response> = Vec::new();
+    // Which element of the current response each open tag is
+    let mut open: Vec<Option<usize>> = Vec::new();
+    let mut rest = xml;
+    let append = |responses: &mut Vec<Response>, open: &[Option<usize>], text: &str| {
+        if let (Some(Some(i)), Some(response)) = (open.last(), responses.last_mut()) {
+            response.0[*i].text.push_str(text);
+        }
+    };
+    while let Some(at) = rest.find('<') {
+        append(&mut responses, &open, &unescape_xml(&rest[..at]));
+        rest = &rest[at..];
+        if let Some(data) = rest.strip_prefix("<![CDATA[") {
+            let end = data.find("]]>").unwrap_or(data.len());
+            append(&mut responses, &open, &data[..end]);
+            rest = data.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[1..end];
+        rest = rest.get(end + 1..).unwrap_or("");
+        if tag.starts_with(['?', '!']) {
+            continue;
+        }
+        if tag.starts_with('/') {
+            open.pop();
+            continue;
+        }
+        let closed = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let name = name.rsplit(':').next().unwrap_or(name);
+        let index = if name == "response" {
+            responses.push(Response(Vec::new()));
+            None
+        } else {
+            responses.last_mut().map(|response| {
+                response.0.push(Element { name: name.to_string(), attributes: attributes.to_string(), text: String::new() });
+                response.0.len() - 1
+            })
+        };
+        if !closed {
+            open.push(index);
+        }
+    }
+    responses
+}
+
+/// Undoes XML's entities
+fn unescape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let entity = rest.find(';').map(|end| &rest[1..end]);
+        let c = entity.and_then(|entity| match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity.strip_prefix('#').and_then(|n| n.parse().ok()).and_then(char::from_u32),
+            },
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                out.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escapes text for XML
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A new UID, unique enough without a random number generator
+pub(crate) fn new_uid() -> String {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    format!("{:x}-{:x}-{}@todo-tui", nanos, std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A collection in the calendar home
+struct Collection {
+    href: String,
+    name: String,
+    /// Whether it's a calendar that can hold VTODOs, rather than only events
+    tasks: bool,
+}
+
+/// A VTODO on the server
+struct Item {
+    href: String,
+    etag: Option<String>,
+    calendar: String,
+    vtodo: Vtodo,
+    /// The whole calendar object, for keeping what isn't ours on the way back
+    ics: String,
+}
+
+/// The CalDAV server, called through curl
+struct Server<'a> {
+    config: &'a CalDavConfig,
+}
+
+impl Server<'_> {
+    /// Full address of an href, which servers usually give as just a path
+    fn url(&self, href: &str) -> String {
+        if href.contains("://") {
+            return href.to_string();
+        }
+        let base = &self.config.url;
+        let host = base.find("://").map_or(0, |i| i + 3);
+        let origin = base[host..].find('/').map_or(base.len(), |i| host + i);
+        format!("{}{}", &base[..origin], href)
+    }
+
+    /// Path of the calendar home, which the hrefs of the calendars start with
+    fn home(&self) -> String {
+        let base = self.config.url.trim_end_matches('/');
+        let host = base.find("://").map_or(0, |i| i + 3);
+        let path = base[host..].find('/').map_or("", |i| &base[host + i..]);
+        format!("{}/", path)
+    }
+
+    /// Makes a request and returns the status code and reply, which is an
+    /// error unless it's a success, 404 Not Found or 412 Precondition Failed
+    fn request(&self, method: &str, href: &str, headers: &[&str], body: Option<&str>) -> Result<(u16, String), String> {
+        let mut options = vec![format!("url = {}", curl_quote(&self.url(href))), format!("request = {}", method)];
+        if !self.config.username.is_empty() {
+            options.push(format!("user = {}", curl_quote(&format!("{}:{}", self.config.username, self.config.password))));
+        }
+        options.extend(headers.iter().map(|header| format!("header = {}", curl_quote(header))));
+        if let Some(body) = body {
+            options.push(format!("data-raw = {}", curl_quote(body)));
+        }
+        match curl(options)? {
+            (code @ (200..=299 | 404 | 412), reply) => Ok((code, reply)),
+            (401, _) => Err("the CalDAV server refused the username or password".to_string()),
+            (code, _) => Err(format!("the CalDAV server answered {} to {} {}", code, method, href)),
+        }
+    }
+
+    /// The collections in the calendar home
+    fn collections(&self) -> Result<Vec<Collection>, String> {
+        let body = concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav"><d:prop>"#,
+            r#"<d:resourcetype/><d:displayname/><c:supported-calendar-component-set/>"#,
+            r#"</d:prop></d:propfind>"#,
+        );
+        let headers = ["Depth: 1", "Content-Type: application/xml; charset=utf-8"];
+        let (code, reply) = self.request("PROPFIND", &self.home(), &headers, Some(body))?;
+        if code == 404 {
+            return Err(format!("there's no calendar home at {}", self.config.url));
+        }
+        Ok(responses(&reply)
+            .iter()
+            .filter_map(|response| {
+                let href = response.text("href")?.to_string();
+                let calendar = response.all("calendar").next().is_some();
+                // Servers that don't say which components a calendar takes take all of them
+                let mut components = response.all("comp").peekable();
+                let tasks = calendar
+                    && (components.peek().is_none() || components.any(|comp| comp.attributes.to_ascii_uppercase().contains("\"VTODO\"")));
+                let name = match response.text("displayname") {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => href.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_string(),
+                };
+                Some(Collection { href, name, tasks })
+            })
+            .collect())
+    }
+
+    /// Makes a calendar for the list with this name and returns its href
+    fn make_calendar(&self, name: &str, taken: &[Collection]) -> Result<String, String> {
+        let slug: String = name.to_lowercase().chars().map(|c| if c.is_alphanumeric() { c } else { '-' }).collect();
+        let home = self.home();
+        // An event calendar can have the list's name already
+        let href = [format!("{}{}/", home, slug), format!("{}{}-tasks/", home, slug)]
+            .into_iter()
+            .find(|href| !taken.iter().any(|collection| collection.href.trim_end_matches('/') == href.trim_end_matches('/')))
+            .unwrap_or_else(|| format!("{}{}/", home, new_uid().replace('@', "-")));
+        let body = format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<c:mkcalendar xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav"><d:set><d:prop>"#,
+                r#"<d:displayname>{}</d:displayname>"#,
+                r#"<c:supported-calendar-component-set><c:comp name="VTODO"/></c:supported-calendar-component-set>"#,
+                r#"</d:prop></d:set></c:mkcalendar>"#,
+            ),
+            escape_xml(name)
+        );
+        match self.request("MKCALENDAR", &href, &["Content-Type: application/xml; charset=utf-8"], Some(&body))? {
+            (200..=299, _) => Ok(href),
+            _ => Err(format!("the CalDAV server didn't make a calendar for {}", name)),
+        }
+    }
+
+    /// The VTODOs in a calendar
+    fn items(&self, calendar: &str) -> Result<Vec<Item>, String> {
+        let body = concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">"#,
+            r#"<d:prop><d:getetag/><c:calendar-data/></d:prop>"#,
+            r#"<c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VTODO"/></c:comp-filter></c:filter>"#,
+            r#"</c:calendar-query>"#,
+        );
+        let headers = ["Depth: 1", "Content-Type: application/xml; charset=utf-8"];
+        let (_, reply) = self.request("REPORT", calendar, &headers, Some(body))?;
+        Ok(responses(&reply)
+            .iter()
+            .filter_map(|response| {
+                let ics = response.text("calendar-data")?.to_string();
+                Some(Item {
+                    href: response.text("href")?.to_string(),
+                    etag: response.text("getetag").filter(|etag| !etag.is_empty()).map(str::to_string),
+                    calendar: calendar.to_string(),
+                    vtodo: parse_vtodo(&ics)?,
+                    ics,
+                })
+            })
+            .collect())
+    }
+
+    /// Stores a calendar object - `etag` says which version it replaces, or
+    /// None for one that mustn't exist yet
+    /// Returns false when the version on the server isn't that one anymore
+    fn put(&self, href: &str, ics: &str, etag: Option<&str>) -> Result<bool, String> {
+        let condition = match etag {
+            Some(etag) => format!("If-Match: {}", etag),
+            None => "If-None-Match: *".to_string(),
+        };
+        let headers = ["Content-Type: text/calendar; charset=utf-8", condition.as_str()];
+        match self.request("PUT", href, &headers, Some(ics))? {
+            (200..=299, _) => Ok(true),
+            (412, _) => Ok(false),
+            _ => Err(format!("the CalDAV server has no {}", href)),
+        }
+    }
+}
+
+/// A CalDAV server as a backend for `reconcile`
+struct CalDav<'a> {
+    server: Server<'a>,
+    /// Everything in the calendar home, for names of new calendars not to clash
+    collections: Vec<Collection>,
+    /// The VTODOs on the server by UID, for what's kept of them on the way back
+    items: HashMap<String, Item>,
+}
+
+/// One sync, run on its own thread: sends what changed here to the server
+/// and returns what changed there
+pub(crate) fn sync(config: &CalDavConfig, lists: &[TodoList], state: &SyncState) -> Result<SyncOutcome, String> {
+    reconcile(&mut CalDav { server: Server { config }, collections: Vec::new(), items: HashMap::new() }, lists, state)
+}
+
+impl Backend for CalDav<'_> {
+    const SERVICE: Service = Service::CalDav;
+    const UNNAMED: &'static str = "Tasks";
+
+    fn projects(&mut self) -> Result<Vec<(String, String)>, String> {
+        self.collections = self.server.collections()?;
+        Ok(self.collections.iter().filter(|c| c.tasks).map(|c| (c.href.clone(), c.name.clone())).collect())
+    }
+
+    fn create_project(&mut self, name: &str) -> Result<String, String> {
+        let href = self.server.make_calendar(name, &self.collections)?;
+        self.collections.push(Collection { href: href.clone(), name: name.to_string(), tasks: true });
+        Ok(href)
+    }
+
+    fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String> {
+        let mut tasks = Vec::new();
+        for collection in self.collections.iter().filter(|c| c.tasks) {
+            for item in self.server.items(&collection.href)? {
+                let Vtodo { uid, parent, todo, modified, .. } = &item.vtodo;
+                let task = RemoteTask { project: item.calendar.clone(), parent: parent.clone(), todo: todo.clone(), updated: *modified };
+                tasks.push((uid.clone(), task));
+                self.items.insert(uid.clone(), item);
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool {
+        same(todo, task)
+    }
+
+    /// Stores a todo as a new VTODO, under the UID it had if it had one
+    fn create(&mut self, todo: &TodoItem, calendar: &str, parent: Option<&str>, uid: Option<&str>) -> Result<String, String> {
+        let uid = uid.map_or_else(new_uid, str::to_string);
+        let name: String = uid.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' }).collect();
+        let href = format!("{}/{}.ics", calendar.trim_end_matches('/'), name);
+        if !self.server.put(&href, &ics_calendar(ics_vtodo(todo, &uid, parent, &[])), None)? {
+            return Err(format!("the CalDAV server already has {}", href));
+        }
+        Ok(uid)
+    }
+
+    fn update(&mut self, uid: &str, todo: &TodoItem, _: &RemoteTask, _: &str, parent: Option<&str>) -> Result<(), String> {
+        let item = &self.items[uid];
+        let ics = merge(&item.ics, &ics_vtodo(todo, uid, parent, &[]), item.vtodo.odd_rule);
+        // Changed on the server since it was fetched - the next sync settles it
+        self.server.put(&item.href, &ics, item.etag.as_deref()).map(|_| ())
+    }
+
+    fn delete(&mut self, uid: &str, _: &RemoteTask) -> Result<(), String> {
+        self.server.request("DELETE", &self.items[uid].href, &[], None).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multistatus_and_vtodos_are_read() {
+        let reply = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+ <d:response>
+  <d:href>/dav/me/work/</d:href>
+  <d:propstat><d:prop>
+   <d:resourcetype><d:collection/><cal:calendar/></d:resourcetype>
+   <d:displayname>Work &amp; more</d:displayname>
+   <cal:supported-calendar-component-set><cal:comp name="VEVENT"/><cal:comp name="VTODO"/></cal:supported-calendar-component-set>
+  </d:prop></d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/dav/me/work/a.ics</d:href>
+  <d:propstat><d:prop><d:getetag>"e1"</d:getetag><cal:calendar-data><![CDATA[BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VTODO
+UID:a
+SUMMARY:Call Bob\, then Alice
+DESCRIPTION:Line one\nline two
+CATEGORIES:errand,@phone
+PRIORITY:2
+DUE;TZID=Europe/Paris:20240501T
+ 093000
+STATUS:COMPLETED
+COMPLETED:20240430T120000Z
+RELATED-TO;RELTYPE=PARENT:p
+RRULE:FREQ=WEEKLY;BYDAY=MO
+BEGIN:VALARM
+SUMMARY:Not the todo
+END:VALARM
+END:VTODO
+END:VCALENDAR
+]]></cal:calendar-data></d:prop></d:propstat>
+ </d:response>
+</d:multistatus>"#;
+        let responses = responses(reply);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].text("href"), Some("/dav/me/work/"));
+        assert_eq!(responses[0].text("displayname"), Some("Work & more"));
+        assert!(responses[0].all("calendar").next().is_some());
+        assert_eq!(responses[1].text("getetag"), Some("\"e1\""));
+
+        let vtodo = parse_vtodo(responses[1].text("calendar-data").unwrap()).unwrap();
+        let todo = &vtodo.todo;
+        assert_eq!((vtodo.uid.as_str(), vtodo.parent.as_deref()), ("a", Some("p")));
+        assert_eq!(todo.text, "Call Bob, then Alice");
+        assert_eq!(todo.notes, "Line one\nline two");
+        assert_eq!(todo.tags, ["errand"]);
+        assert_eq!(todo.contexts, ["phone"]);
+        assert_eq!(todo.priority, Priority::High);
+        assert_eq!(todo.due, Date::new(2024, 5, 1));
+        assert_eq!(todo.due_time, Some(Time { hour: 9, minute: 30 }));
+        assert_eq!(todo.status, Status::Done);
+        assert_eq!(todo.completed_at, parse_timestamp("20240430T120000Z"));
+        assert_eq!(vtodo.uid, "a");
+        // Mondays only is more than a todo can repeat by
+        assert!(todo.recurrence.is_none() && vtodo.odd_rule);
+    }
+
+    #[test]
+    fn rrules_become_repeats_only_when_todos_can_follow_them() {
+        let cases = [
+            ("FREQ=DAILY", Some(Recurrence::Daily)),
+            ("FREQ=DAILY;INTERVAL=1", Some(Recurrence::Daily)),
+            ("FREQ=DAILY;INTERVAL=3", Some(Recurrence::EveryNDays(3))),
+            ("FREQ=WEEKLY", Some(Recurrence::Weekly)),
+            ("FREQ=WEEKLY;INTERVAL=2", Some(Recurrence::EveryNDays(14))),
+            ("FREQ=MONTHLY", Some(Recurrence::Monthly)),
+            // Never moving on, overflowing or negative
+            ("FREQ=DAILY;INTERVAL=0", None),
+            ("FREQ=WEEKLY;INTERVAL=0", None),
+            ("FREQ=WEEKLY;INTERVAL=4294967295", None),
+            ("FREQ=WEEKLY;INTERVAL=613566757", None),
+            ("FREQ=DAILY;INTERVAL=-1", None),
+            ("FREQ=DAILY;INTERVAL=99999999999", None),
+            // What todos can't say
+            ("FREQ=MONTHLY;INTERVAL=2", None),
+            ("FREQ=YEARLY", None),
+            ("FREQ=WEEKLY;BYDAY=MO", None),
+            ("FREQ=DAILY;COUNT=3", None),
+            ("INTERVAL=2", None),
+            ("FREQ", None),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(parse_rrule(rule), expected, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn merging_keeps_what_isnt_ours() {
+        let original = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VTODO\r\nUID:a\r\nSUMMARY:Old\r\nPERCENT-COMPLETE:100\r\n\
+            RRULE:FREQ=MONTHLY;BYMONTHDAY=-1\r\nX-APPLE-SORT-ORDER:5\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nDESCRIPTION:Soon\r\n\
+            END:VALARM\r\nEND:VTODO\r\nBEGIN:VTODO\r\nUID:a\r\nRECURRENCE-ID:20240101\r\nSUMMARY:Moved\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let mut todo = TodoItem::new("New");
+        todo.notes = "Here".to_string();
+        let merged = merge(original, &ics_vtodo(&todo, "a", None, &[]), true);
+        let lines: Vec<&str> = merged.lines().collect();
+
+        assert!(lines.contains(&"SUMMARY:New") && lines.contains(&"DESCRIPTION:Here"));
+        assert!(!lines.contains(&"SUMMARY:Old") && !lines.contains(&"PERCENT-COMPLETE:100"));
+        // The repeat todos can't show, the alarm and the changed occurrence stay
+        assert!(lines.contains(&"RRULE:FREQ=MONTHLY;BYMONTHDAY=-1") && lines.contains(&"X-APPLE-SORT-ORDER:5"));
+        assert!(lines.contains(&"DESCRIPTION:Soon") && lines.contains(&"SUMMARY:Moved"));
+        assert_eq!(lines.iter().filter(|line| line.starts_with("UID:")).count(), 2);
+        assert_eq!(parse_vtodo(&merged).unwrap().todo.text, "New");
+    }
+}
+
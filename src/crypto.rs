@@ -0,0 +1,260 @@
+//! Encrypting the todo file and archive with a passphrase
+//! ChaCha20-Poly1305 (RFC 8439) with a key from PBKDF2-HMAC-SHA256, both as
+//! the RustCrypto crates implement them
+
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, OsRng},
+    ChaCha20Poly1305, KeyInit,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{io, ops::RangeInclusive};
+
+use crate::app::{base64_decode, base64_encode};
+
+/// Name of the cipher in the file, so a future one can be told apart
+const CIPHER_NAME: &str = "chacha20-poly1305";
+
+/// Name of the key derivation in the file
+const KDF_NAME: &str = "pbkdf2-sha256";
+
+/// PBKDF2 rounds for new files - OWASP's recommendation for SHA-256, about
+/// a third of a second on a laptop, paid once at startup
+/// Stored in each file, so raising it later still opens old files
+const KDF_ITERATIONS: u32 = 600_000;
+
+/// Rounds a file may ask for - the count is read from the file, and a
+/// damaged or tampered one asking for billions would hang the start
+#[cfg(not(test))]
+const ITERATIONS: RangeInclusive<u32> = KDF_ITERATIONS / 4..=KDF_ITERATIONS * 16;
+/// Tests seal with a few rounds to stay fast
+#[cfg(test)]
+const ITERATIONS: RangeInclusive<u32> = 1..=KDF_ITERATIONS * 16;
+
+/// What an encrypted file holds: how to derive the key and the sealed contents
+/// JSON like every other file the app writes, so it's clear what it is
+/// when opened in an editor
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Envelope {
+    encryption: String,
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    /// Ciphertext followed by the 16-byte tag, base64
+    data: String,
+}
+
+impl Envelope {
+    /// Reads the envelope of an encrypted file, None for a plain one
+    pub(crate) fn parse(contents: &str) -> Option<Envelope> {
+        // Checked first so a large plain file isn't parsed twice
+        if !contents.trim_start().starts_with('{') || !contents.contains("\"encryption\"") {
+            return None;
+        }
+        serde_json::from_str(contents).ok()
+    }
+
+    /// Whether the app can open an envelope like this at all, saying why not
+    /// Checked before deriving a key, which takes as long as the file asks
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.encryption != CIPHER_NAME || self.kdf != KDF_NAME {
+            return Err(format!("unknown encryption {} with {}", self.encryption, self.kdf));
+        }
+        if !ITERATIONS.contains(&self.iterations) {
+            return Err(format!(
+                "{} key derivation rounds is outside {}-{}",
+                self.iterations,
+                ITERATIONS.start(),
+                ITERATIONS.end()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The key a file is encrypted with, and the passphrase it came from
+/// The passphrase is kept so snapshots and archives that were written with
+/// a different salt can still be opened
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    passphrase: String,
+    salt: [u8; 16],
+    iterations: u32,
+    key: [u8; 32],
+}
+
+impl Cipher {
+    /// A key for encrypting a file for the first time, with a fresh salt
+    pub(crate) fn new(passphrase: &str) -> io::Result<Cipher> {
+        Cipher::with_iterations(passphrase, KDF_ITERATIONS)
+    }
+
+    /// Like `new` with a chosen number of rounds - tests use a few to stay fast
+    pub(crate) fn with_iterations(passphrase: &str, iterations: u32) -> io::Result<Cipher> {
+        let mut salt = [0; 16];
+        random_bytes(&mut salt)?;
+        Ok(Cipher::derive(passphrase, salt, iterations))
+    }
+
+    /// The key the file behind `envelope` was encrypted with, if the
+    /// passphrase is right - that's only known once `open` succeeds
+    pub(crate) fn for_envelope(passphrase: &str, envelope: &Envelope) -> Option<Cipher> {
+        envelope.check().ok()?;
+        let salt = base64_decode(&envelope.salt)?.try_into().ok()?;
+        Some(Cipher::derive(passphrase, salt, envelope.iterations))
+    }
+
+    fn derive(passphrase: &str, salt: [u8; 16], iterations: u32) -> Cipher {
+        let key = pbkdf2_sha256(passphrase.as_bytes(), &salt, iterations);
+        Cipher { passphrase: passphrase.to_string(), salt, iterations, key }
+    }
+
+    /// Encrypts file contents into an envelope, with a new random nonce every time
+    pub(crate) fn seal(&self, plaintext: &str) -> io::Result<String> {
+        let mut nonce = [0; 12];
+        random_bytes(&mut nonce)?;
+        let envelope = Envelope {
+            encryption: CIPHER_NAME.to_string(),
+            kdf: KDF_NAME.to_string(),
+            iterations: self.iterations,
+            salt: base64_encode(&self.salt),
+            nonce: base64_encode(&nonce),
+            data: base64_encode(&seal(&self.key, &nonce, plaintext.as_bytes())),
+        };
+        serde_json::to_string_pretty(&envelope).map_err(io::Error::other)
+    }
+
+    /// Decrypts an envelope, None when the passphrase is wrong or the file
+    /// was changed - the tag can't tell those apart
+    pub(crate) fn open(&self, envelope: &Envelope) -> Option<String> {
+        envelope.check().ok()?;
+        let salt: [u8; 16] = base64_decode(&envelope.salt)?.try_into().ok()?;
+        let nonce: [u8; 12] = base64_decode(&envelope.nonce)?.try_into().ok()?;
+        let sealed = base64_decode(&envelope.data)?;
+        let plaintext = if salt == self.salt && envelope.iterations == self.iterations {
+            open(&self.key, &nonce, &sealed)?
+        } else {
+            let key = pbkdf2_sha256(self.passphrase.as_bytes(), &salt, envelope.iterations);
+            open(&key, &nonce, &sealed)?
+        };
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+/// Fills `bytes` from the operating system's random source
+pub(crate) fn random_bytes(bytes: &mut [u8]) -> io::Result<()> {
+    OsRng.try_fill_bytes(bytes).map_err(|err| io::Error::other(err.to_string()))
+}
+
+/// SHA-256 of `data` in one go
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// PBKDF2-HMAC-SHA256 for a 32-byte key
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password, salt, iterations)
+}
+
+/// Encrypts `plaintext` and appends the 16-byte tag, with no additional data
+fn seal(key: &[u8; 32], nonce: &[u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    // Only fails past the 256 GiB a single nonce can encrypt
+    ChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), plaintext).expect("todo files are far smaller")
+}
+
+/// Checks the tag and decrypts, None if the tag doesn't match
+fn open(key: &[u8; 32], nonce: &[u8; 12], sealed: &[u8]) -> Option<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), sealed).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn keys_match_the_rfc_7914_vectors() {
+        // Section 11 lists 64 bytes, and a key is the first 32 of them
+        assert_eq!(hex(&pbkdf2_sha256(b"passwd", b"salt", 1)), "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc");
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"Password", b"NaCl", 80_000)),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56"
+        );
+        assert_eq!(hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn sealing_matches_rfc_8439() {
+        // The section 2.8.2 vector has additional data, which files don't, so
+        // it goes to the cipher directly
+        use chacha20poly1305::aead::Payload;
+        let key: [u8; 32] = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce = unhex("070000004041424344454647");
+        let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+        let msg = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let sealed = ChaCha20Poly1305::new(&key.into()).encrypt(nonce.as_slice().into(), Payload { msg, aad: &aad }).unwrap();
+        assert_eq!(
+            hex(&sealed),
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b\
+             1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+             3ff4def08e4b7a9de576d26586cec64b6116\
+             1ae10b594f09e26a7e902ecbd0600691"
+        );
+    }
+
+    #[test]
+    fn files_sealed_before_still_open() {
+        // Written by the cipher the app had before these crates, without
+        // additional data like every file
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let nonce: [u8; 12] = std::array::from_fn(|i| i as u8);
+        let message = b"Buy milk, call mom, and finish the quarterly report before Friday. ".repeat(2);
+        let sealed = seal(&key, &nonce, &message);
+        assert_eq!(
+            hex(&sealed),
+            "cb8e7120447ec92b9ba35c92f4712e0ea61d9ec7301ac99980fe41ac06a8864884e50c82937fc3ec52401aa145e6ea53\
+             11f6dd549f4ddef460ddf941e226fab700569c8f906d0c84ad7a2690381fbd9db6fe2a5d0246d05f6033d068b1136bcc\
+             25fe189b92f95681d02987d2d0fdcea09b0a2657fb29609a2a0b5bad1c1ff934c802a2c48713cb1fcb3e0ed37236a135\
+             29d68181e2d0"
+        );
+        assert_eq!(hex(&seal(&key, &nonce, b"")), "295a498b8841a1c5f55d4d606f731159");
+        assert_eq!(open(&key, &nonce, &sealed).unwrap(), message);
+
+        // Any change to the ciphertext or tag is caught
+        for at in [0, message.len() - 1, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[at] ^= 1;
+            assert!(open(&key, &nonce, &tampered).is_none());
+        }
+    }
+
+    #[test]
+    fn envelopes_open_only_with_the_right_passphrase() {
+        let cipher = Cipher::with_iterations("hunter2", 10).unwrap();
+        let sealed = cipher.seal("{\"lists\": []}").unwrap();
+        let envelope = Envelope::parse(&sealed).unwrap();
+        assert_eq!(cipher.open(&envelope).unwrap(), "{\"lists\": []}");
+        assert_eq!(Cipher::for_envelope("hunter2", &envelope).unwrap().open(&envelope).unwrap(), "{\"lists\": []}");
+        assert!(Cipher::for_envelope("hunter3", &envelope).unwrap().open(&envelope).is_none());
+        assert!(Envelope::parse("{\"version\": 1, \"lists\": []}").is_none());
+    }
+
+    #[test]
+    fn envelopes_asking_for_endless_rounds_are_refused() {
+        let cipher = Cipher::with_iterations("hunter2", 10).unwrap();
+        let sealed = cipher.seal("{}").unwrap().replace("\"iterations\": 10", "\"iterations\": 4294967295");
+        let envelope = Envelope::parse(&sealed).unwrap();
+        assert!(envelope.check().unwrap_err().contains("4294967295"));
+        // Refused before any key is derived, which would take hours
+        assert!(Cipher::for_envelope("hunter2", &envelope).is_none());
+        assert!(cipher.open(&envelope).is_none());
+    }
+}
@@ -0,0 +1,382 @@
+//! Todos from email: a folder of a mailbox is checked over IMAP, and every
+//! unread mail in it becomes a todo, the subject its title (with quick-add
+//! syntax) and the text its notes - so a task mailed to yourself ends up with
+//! the others
+//! Fetching a mail marks it as read, which is how it's only taken once
+
+use serde::Deserialize;
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::{base64_decode, App, Command, Mode, TodoItem, TodoList},
+    sync::{curl, curl_quote},
+};
+
+/// Most mails taken in one check, so a folder full of old unread mail
+/// doesn't arrive all at once - the rest come with the next checks
+const MAX_PER_CHECK: usize = 20;
+
+/// The "email" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct EmailConfig {
+    /// The folder to check, like "imaps://imap.example.com/INBOX" or a
+    /// folder of its own like ".../Todo"
+    pub(crate) url: String,
+    pub(crate) user: String,
+    /// The password, or an app password where the provider asks for one -
+    /// empty to take both from ~/.netrc
+    pub(crate) password: String,
+    /// Name of the list the todos go in, made when there's none
+    pub(crate) list: String,
+    /// Minutes between checks while the app runs, 0 to check only when asked
+    pub(crate) interval: u64,
+}
+
+impl Default for EmailConfig {
+    fn default() -> EmailConfig {
+        EmailConfig { url: String::new(), user: String::new(), password: String::new(), list: "Inbox".to_string(), interval: 5 }
+    }
+}
+
+impl EmailConfig {
+    /// Checks the settings a check can't do without
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let folder = self.url.strip_prefix("imaps://").or_else(|| self.url.strip_prefix("imap://"));
+        match folder.and_then(|rest| rest.split_once('/')) {
+            Some((host, folder)) if !host.is_empty() && !folder.trim_matches('/').is_empty() => Ok(()),
+            _ => Err("email needs a url like \"imaps://imap.example.com/INBOX\", with the folder to check".to_string()),
+        }
+    }
+}
+
+/// The check of the mail running on its own thread, and how the last one went
+#[derive(Default)]
+pub(crate) struct MailCheck {
+    receiver: Option<Receiver<Result<Vec<TodoItem>, String>>>,
+    /// When the mail was last checked, for the interval between checks
+    started: Option<Instant>,
+    /// Whether the running check was asked for, which reports how it went
+    manual: bool,
+    /// Whether the last check failed, so failing again isn't reported again
+    failed: bool,
+}
+
+/// Makes an IMAP request through curl and returns the reply
+fn imap(config: &EmailConfig, url: &str, request: Option<&str>) -> Result<String, String> {
+    let mut options = vec![format!("url = {}", curl_quote(url)), "netrc-optional".to_string()];
+    if !config.user.is_empty() {
+        options.push(format!("user = {}", curl_quote(&format!("{}:{}", config.user, config.password))));
+    }
+    // With a folder in the URL curl selects it before the request
+    if let Some(request) = request {
+        options.push(format!("request = {}", curl_quote(request)));
+    }
+    curl(options).map(|(_, reply)| reply)
+}
+
+/// One check, run on its own thread: fetches the unread mail as todos
+fn check(config: &EmailConfig) -> Result<Vec<TodoItem>, String> {
+    let reply = imap(config, &config.url, Some("UID SEARCH UNSEEN"))?;
+    let uids: Vec<&str> = reply.lines().filter_map(|line| line.trim().strip_prefix("* SEARCH")).flat_map(str::split_whitespace).collect();
+    let mut todos = Vec::new();
+    for uid in uids.into_iter().take(MAX_PER_CHECK) {
+        let message = match imap(config, &format!("{}/;UID={}", config.url.trim_end_matches('/'), uid), None) {
+            Ok(message) => message,
+            Err(err) if todos.is_empty() => return Err(err),
+            // What was fetched is read now and mustn't be lost - the rest
+            // waits for the next check
+            Err(_) => break,
+        };
+        todos.extend(todo_from_mail(&message));
+    }
+    Ok(todos)
+}
+
+/// The todo a mail becomes, None for one without a subject or text
+fn todo_from_mail(raw: &str) -> Option<TodoItem> {
+    let raw = raw.replace("\r\n", "\n");
+    let (headers, body) = split_message(&raw);
+    let mut notes = text_body(&headers, body).unwrap_or_default();
+    // The signature isn't part of the task
+    if let Some(at) = notes.find("\n-- \n") {
+        notes.truncate(at);
+    }
+    let mut notes = notes.trim().to_string();
+    let mut title = header(&headers, "subject").map(decode_words).unwrap_or_default().trim().to_string();
+    // Without a subject the first line is the title
+    if title.is_empty() {
+        let (first, rest) = notes.split_once('\n').unwrap_or((&notes, ""));
+        (title, notes) = (first.trim().to_string(), rest.trim().to_string());
+    }
+    if title.is_empty() {
+        return None;
+    }
+    let mut todo = TodoItem::parse(&title);
+    todo.notes = notes;
+    Some(todo)
+}
+
+/// Splits a message or a part of one into its headers, with folded lines
+/// joined, and what comes after them
+fn split_message(raw: &str) -> (Vec<(String, String)>, &str) {
+    let (head, body) = match raw.strip_prefix('\n') {
+        // A part without headers
+        Some(body) => ("", body),
+        None => raw.split_once("\n\n").unwrap_or((raw, "")),
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+/// The value of a header, by its lowercase name
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+}
+
+/// A parameter of a header like Content-Type, such as its charset
+fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).filter_map(|param| param.split_once('=')).find(|(key, _)| key.trim().eq_ignore_ascii_case(name)).map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+/// The plain text of a message or part - the first text/plain part of a
+/// multipart one, which is where mail programs put it
+fn text_body(headers: &[(String, String)], body: &str) -> Option<String> {
+    let content_type = header(headers, "content-type").unwrap_or("text/plain");
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if mime.starts_with("multipart/") {
+        let delimiter = format!("--{}", param(content_type, "boundary")?);
+        // What comes before the first delimiter isn't a part, and the last
+        // one ends with "--"
+        return body.split(delimiter.as_str()).skip(1).take_while(|part| !part.starts_with("--")).find_map(|part| {
+            let part = part.strip_prefix('\n').unwrap_or(part);
+            let (headers, body) = split_message(part);
+            text_body(&headers, body)
+        });
+    }
+    let attachment = header(headers, "content-disposition").is_some_and(|value| value.to_ascii_lowercase().starts_with("attachment"));
+    if mime != "text/plain" || attachment {
+        return None;
+    }
+    let bytes = match header(headers, "content-transfer-encoding").map(str::to_ascii_lowercase).as_deref() {
+        Some("base64") => base64_decode(&body.split_whitespace().collect::<String>())?,
+        Some("quoted-printable") => quoted_printable(body),
+        _ => body.as_bytes().to_vec(),
+    };
+    Some(decode_charset(&bytes, &param(content_type, "charset").unwrap_or_default()))
+}
+
+/// Decodes quoted-printable text, where `=XX` is a byte and `=` at the end
+/// of a line joins it with the next
+fn quoted_printable(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            if bytes.get(i + 1) == Some(&b'\n') {
+                i += 2;
+                continue;
+            }
+            if let Some(byte) = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Text in the given charset - UTF-8 and the Latin ones, which covers most
+/// mail, anything else is read as UTF-8 as far as it goes
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "iso-8859-15" | "latin1" | "windows-1252" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decodes the `=?charset?B?...?=` and `=?charset?Q?...?=` words non-ASCII
+/// subjects are written in
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, word) = rest.split_at(start);
+        // The space between two encoded words is only there to fold the line
+        if !(after_word && before.trim().is_empty()) {
+            out.push_str(before);
+        }
+        match decode_word(word) {
+            Some((text, len)) => {
+                out.push_str(&text);
+                rest = &word[len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str("=?");
+                rest = &word[2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes the encoded word `word` starts with, returning it and its length
+fn decode_word(word: &str) -> Option<(String, usize)> {
+    let mut parts = word[2..].splitn(3, '?');
+    let (charset, encoding, rest) = (parts.next()?, parts.next()?, parts.next()?);
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    let bytes = match encoding {
+        "B" | "b" => base64_decode(text)?,
+        "Q" | "q" => quoted_printable(&text.replace('_', " ")),
+        _ => return None,
+    };
+    Some((decode_charset(&bytes, charset), 2 + charset.len() + 1 + encoding.len() + 1 + end + 2))
+}
+
+impl App {
+    /// Called from the event loop: picks up a finished check and starts the
+    /// next one when the interval has passed
+    /// Returns whether a check started or finished
+    pub(crate) fn tick_mail(&mut self) -> bool {
+        let Some(config) = &self.email else {
+            return false;
+        };
+        let Some(receiver) = &self.mail.receiver else {
+            let interval = config.interval;
+            let due = interval > 0 && self.mail.started.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval * 60));
+            if due {
+                self.check_mail(false);
+            }
+            return due;
+        };
+        // Popups hold indexes into the lists, so the todos wait until they're closed
+        if self.mode != Mode::Normal {
+            return false;
+        }
+        match receiver.try_recv() {
+            Ok(result) => self.finish_mail(result),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => self.finish_mail(Err("the check stopped unexpectedly".to_string())),
+        }
+        true
+    }
+
+    /// Checks the mail on its own thread
+    pub(crate) fn check_mail(&mut self, manual: bool) {
+        self.mail.started = Some(Instant::now());
+        let Some(config) = self.email.clone() else {
+            return;
+        };
+        // The mail is read once fetched, so only the instance that can save
+        // the todos takes it
+        if self.read_only || self.saving_disabled {
+            return;
+        }
+        if self.mail.receiver.is_some() {
+            if manual {
+                self.notice = Some(self.tr("Already checking the mail").to_string());
+            }
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(check(&config));
+        });
+        self.mail.receiver = Some(receiver);
+        self.mail.manual = manual;
+    }
+
+    /// Adds the todos from the mail at the end of their list
+    fn finish_mail(&mut self, result: Result<Vec<TodoItem>, String>) {
+        self.mail.receiver = None;
+        let todos = match result {
+            Ok(todos) => todos,
+            Err(err) => {
+                // Once is enough while it keeps failing in the background
+                if self.mail.manual || !self.mail.failed {
+                    self.notice = Some(self.trf("Checking the mail failed: {}", &[&err]));
+                }
+                self.mail.failed = true;
+                return;
+            }
+        };
+        self.mail.failed = false;
+        let Some(config) = &self.email else {
+            return;
+        };
+        if todos.is_empty() {
+            if self.mail.manual {
+                self.notice = Some(self.tr("No new mail").to_string());
+            }
+            return;
+        }
+        let name = config.list.clone();
+        let mut commands = Vec::new();
+        let list = match self.lists.iter().position(|list| !list.github && list.name.eq_ignore_ascii_case(&name)) {
+            Some(index) => index,
+            None => {
+                commands.push(Command::InsertList { index: self.lists.len(), list: TodoList::new(&name) });
+                self.lists.len()
+            }
+        };
+        let count = todos.len();
+        let start = self.lists.get(list).map_or(0, |list| list.todos.len());
+        for (i, item) in todos.into_iter().enumerate() {
+            commands.push(Command::Insert { list, path: vec![start + i], item });
+        }
+        // Like the command line's changes it isn't undoable, and the view
+        // stays on whatever list the user is looking at
+        self.apply_command(Command::Batch(commands));
+        self.refresh_view();
+        self.autosave(false);
+        self.notice = Some(self.trn("{} todo from the mail in {}", "{} todos from the mail in {}", count, &[&count, &name]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Priority;
+
+    #[test]
+    fn mail_becomes_a_todo() {
+        let raw = "From: me@example.com\r\nSubject: =?UTF-8?Q?Caf=C3=A9_receipts?= #errands\r\n \
+                   !high\r\nContent-Type: multipart/alternative; boundary=\"b1\"\r\n\r\n\
+                   preamble\r\n--b1\r\nContent-Type: text/plain; charset=utf-8\r\n\
+                   Content-Transfer-Encoding: quoted-printable\r\n\r\nHand them in by Friday=2C=\r\n please.\r\n\
+                   -- \r\nMe\r\n--b1\r\nContent-Type: text/html\r\n\r\n<p>Hand them in</p>\r\n--b1--\r\n";
+        let todo = todo_from_mail(raw).unwrap();
+        assert_eq!(todo.text, "Café receipts");
+        assert_eq!(todo.tags, vec!["errands".to_string()]);
+        assert_eq!(todo.priority, Priority::High);
+        assert_eq!(todo.notes, "Hand them in by Friday, please.");
+
+        // No subject: the first line is the title
+        let todo = todo_from_mail("Content-Transfer-Encoding: base64\n\nQnV5IG1pbGsKMiBsaXRyZXM=\n").unwrap();
+        assert_eq!((todo.text.as_str(), todo.notes.as_str()), ("Buy milk", "2 litres"));
+        assert!(todo_from_mail("Subject: \n\n\n").is_none());
+    }
+}
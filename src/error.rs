@@ -0,0 +1,65 @@
+//! Errors from reading and writing the app's files
+
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+/// Something went wrong with one of the files the app keeps its data in
+/// Every variant names the file, since "permission denied" alone doesn't say
+/// which of the todo file, archive or view state is affected
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// The file exists but couldn't be read
+    #[error("could not read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    /// The file couldn't be written, or its directory created
+    #[error("could not save {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    /// The file was read but its contents aren't valid JSON for what it holds
+    /// The source knows where in the file the problem is
+    #[error("could not parse {}: {source}", path.display())]
+    Parse { path: PathBuf, source: serde_json::Error },
+
+    /// The file is encrypted and no passphrase was given yet
+    #[error("{} is encrypted - it needs the passphrase", path.display())]
+    Locked { path: PathBuf },
+
+    /// The file is encrypted and the passphrase doesn't open it - either
+    /// it's the wrong one or the file was changed
+    #[error("could not decrypt {}: wrong passphrase, or the file was changed", path.display())]
+    Decrypt { path: PathBuf },
+
+    /// The file is encrypted in a way the app won't open, like asking for
+    /// far more key derivation rounds than it ever writes
+    #[error("could not decrypt {}: {message}", path.display())]
+    Encryption { path: PathBuf, message: String },
+
+    /// The file was saved but committing it to git failed - the message is
+    /// git's own explanation
+    #[error("could not commit {} to git: {message}", path.display())]
+    Git { path: PathBuf, message: String },
+}
+
+impl AppError {
+    /// The file the error is about
+    pub(crate) fn path(&self) -> &PathBuf {
+        match self {
+            AppError::Read { path, .. }
+            | AppError::Write { path, .. }
+            | AppError::Parse { path, .. }
+            | AppError::Locked { path }
+            | AppError::Decrypt { path }
+            | AppError::Encryption { path, .. }
+            | AppError::Git { path, .. } => path,
+        }
+    }
+
+    /// Line and column (both from 1) of a parse error, when serde knows them
+    pub(crate) fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            AppError::Parse { source, .. } if source.line() > 0 => Some((source.line(), source.column())),
+            _ => None,
+        }
+    }
+}
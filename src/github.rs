@@ -0,0 +1,478 @@
+//! The open GitHub issues assigned to the user, in a list of their own, so
+//! the app is the one work queue
+//! The list is filled from the API and changed only by it: edits to it are
+//! refused, apart from completing an issue's todo when `close_on_complete` is
+//! set, which closes the issue on the next refresh. It's saved with the other
+//! lists, so the issues are there at startup and offline, and the replies are
+//! cached with their ETags, so a refresh where nothing changed costs nothing
+//! against GitHub's rate limit
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::{App, Command, Date, Mode, TodoItem, TodoList},
+    sync::{curl, curl_quote},
+};
+
+/// Where GitHub's API lives
+const GITHUB_URL: &str = "https://api.github.com";
+
+/// The "github" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct GitHubConfig {
+    /// Personal access token that can read the repos' issues, and write them
+    /// for `close_on_complete`
+    pub(crate) token: String,
+    /// Repos to show issues from, as "owner/name"
+    pub(crate) repos: Vec<String>,
+    /// Name of the list the issues are shown in
+    pub(crate) list: String,
+    /// Whether completing an issue's todo closes the issue - otherwise the
+    /// list is read-only
+    pub(crate) close_on_complete: bool,
+    /// Minutes between refreshes while the app runs, 0 to refresh only when asked
+    pub(crate) interval: u64,
+    /// Base URL of the API, for GitHub Enterprise
+    pub(crate) url: String,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> GitHubConfig {
+        GitHubConfig {
+            token: String::new(),
+            repos: Vec::new(),
+            list: "GitHub".to_string(),
+            close_on_complete: false,
+            interval: 5,
+            url: GITHUB_URL.to_string(),
+        }
+    }
+}
+
+impl GitHubConfig {
+    /// Checks the settings a refresh can't do without
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.token.trim().is_empty() {
+            return Err("github needs a token".to_string());
+        }
+        if self.repos.is_empty() {
+            return Err("github needs at least one repo in \"repos\"".to_string());
+        }
+        match self.repos.iter().find(|repo| !matches!(repo.split_once('/'), Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/'))) {
+            Some(repo) => Err(format!("github repo '{}' isn't written as owner/name", repo)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An open issue, as it's cached and shown
+#[derive(Clone, Serialize, Deserialize)]
+struct Issue {
+    /// "owner/name#number", which the todo is linked by
+    key: String,
+    title: String,
+    body: String,
+    url: String,
+    labels: Vec<String>,
+    /// Due date of the issue's milestone
+    due: Option<Date>,
+}
+
+impl Issue {
+    /// Reads an issue from the API, None for pull requests, which the
+    /// issues endpoint lists too
+    fn parse(repo: &str, value: &Value) -> Option<Issue> {
+        if value.get("pull_request").is_some() {
+            return None;
+        }
+        let text = |value: Option<&Value>| value.and_then(Value::as_str).unwrap_or_default().to_string();
+        Some(Issue {
+            key: format!("{}#{}", repo, value.get("number")?.as_u64()?),
+            title: text(value.get("title")),
+            body: text(value.get("body")),
+            url: text(value.get("html_url")),
+            labels: value
+                .get("labels")
+                .and_then(Value::as_array)
+                .map(|labels| {
+                    // Tags are single words
+                    let names = labels.iter().filter_map(|label| label.get("name")?.as_str());
+                    names.map(|name| name.split_whitespace().collect::<Vec<_>>().join("-")).collect()
+                })
+                .unwrap_or_default(),
+            due: value.pointer("/milestone/due_on").and_then(Value::as_str).and_then(|due| Date::parse(due.get(..10)?)),
+        })
+    }
+
+    /// The issue's fields on its todo - the rest of the todo is left alone,
+    /// so priorities and time tracked here survive a refresh
+    fn fill(&self, todo: &mut TodoItem) {
+        let (repo, number) = self.key.split_once('#').unwrap_or((&self.key, ""));
+        todo.text = format!("{} #{}", self.title.trim(), number);
+        todo.notes = if self.body.trim().is_empty() { self.url.clone() } else { format!("{}\n\n{}", self.url, self.body.trim()) };
+        todo.tags = self.labels.clone();
+        todo.project = Some(repo.rsplit('/').next().unwrap_or(repo).to_string());
+        todo.due = self.due;
+        todo.github_issue = Some(self.key.clone());
+    }
+}
+
+/// Whether two todos agree on the fields that come from the issue
+fn same_issue(a: &TodoItem, b: &TodoItem) -> bool {
+    (&a.text, &a.notes, &a.tags, &a.project, a.due, &a.github_issue) == (&b.text, &b.notes, &b.tags, &b.project, b.due, &b.github_issue)
+}
+
+/// What the last refresh got, kept in todos.github.json next to the todo file
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct IssueCache {
+    /// Who the token belongs to, whose issues are shown
+    login: Option<String>,
+    /// The open issues of each repo
+    repos: BTreeMap<String, RepoIssues>,
+}
+
+/// The open issues of a repo, with the ETag GitHub sent them with
+#[derive(Default, Serialize, Deserialize)]
+struct RepoIssues {
+    etag: Option<String>,
+    issues: Vec<Issue>,
+}
+
+/// What a refresh sends back to the app
+pub(crate) struct Refreshed {
+    cache: IssueCache,
+    /// Issues closed because their todos were completed
+    closed: usize,
+}
+
+/// GitHub's API, called through curl
+struct Api<'a> {
+    config: &'a GitHubConfig,
+}
+
+impl Api<'_> {
+    /// Makes a request and returns the status code, the ETag and the reply
+    fn call(&self, method: &str, path: &str, etag: Option<&str>, body: Option<&Value>) -> Result<(u16, Option<String>, Value), String> {
+        let mut options = vec![
+            format!("url = {}", curl_quote(&format!("{}{}", self.config.url.trim_end_matches('/'), path))),
+            format!("request = {}", method),
+            format!("header = {}", curl_quote(&format!("Authorization: Bearer {}", self.config.token))),
+            format!("header = {}", curl_quote("Accept: application/vnd.github+json")),
+            "include".to_string(),
+        ];
+        if let Some(etag) = etag {
+            options.push(format!("header = {}", curl_quote(&format!("If-None-Match: {}", etag))));
+        }
+        if let Some(body) = body {
+            options.push(format!("header = {}", curl_quote("Content-Type: application/json")));
+            options.push(format!("data-raw = {}", curl_quote(&body.to_string())));
+        }
+        let (code, reply) = curl(options)?;
+        // The headers come first with "include", for the ETag
+        let (headers, body) = reply.split_once("\r\n\r\n").unwrap_or((&reply, ""));
+        let etag = headers.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("etag").then(|| value.trim().to_string())
+        });
+        let reply = serde_json::from_str(body).unwrap_or(Value::Null);
+        let message = || reply.get("message").and_then(Value::as_str).unwrap_or_default().to_string();
+        match code {
+            200..=299 | 304 | 404 => Ok((code, etag, reply)),
+            401 => Err("GitHub refused the token - check \"token\" in the config".to_string()),
+            403 | 429 if message().contains("rate limit") => Err("GitHub's rate limit is used up for now".to_string()),
+            code => Err(format!("GitHub answered {} to {} {}: {}", code, method, path, message())),
+        }
+    }
+
+    /// The open issues of a repo assigned to `login`, or None when they're
+    /// the same as the ones cached with `etag`
+    fn issues(&self, repo: &str, login: &str, etag: Option<&str>) -> Result<Option<RepoIssues>, String> {
+        let mut issues = Vec::new();
+        let mut first_etag = None;
+        for page in 1.. {
+            let path = format!("/repos/{}/issues?state=open&assignee={}&per_page=100&page={}", repo, login, page);
+            let (code, etag, reply) = self.call("GET", &path, etag.filter(|_| page == 1), None)?;
+            match code {
+                304 => return Ok(None),
+                404 => return Err(format!("GitHub has no repo {} that the token can see", repo)),
+                _ => {}
+            }
+            let values = reply.as_array().cloned().unwrap_or_default();
+            issues.extend(values.iter().filter_map(|value| Issue::parse(repo, value)));
+            if page == 1 {
+                first_etag = etag;
+            }
+            if values.len() < 100 {
+                break;
+            }
+            // The first page's ETag doesn't cover the rest
+            first_etag = None;
+        }
+        Ok(Some(RepoIssues { etag: first_etag, issues }))
+    }
+}
+
+/// One refresh, run on its own thread: closes the issues completed here and
+/// fetches the open ones
+fn refresh(config: &GitHubConfig, mut cache: IssueCache, close: Vec<String>) -> Result<Refreshed, String> {
+    let api = Api { config };
+    let login = match cache.login.take() {
+        Some(login) => login,
+        None => {
+            let (_, _, user) = api.call("GET", "/user", None, None)?;
+            user.get("login").and_then(Value::as_str).ok_or("GitHub didn't say who the token belongs to")?.to_string()
+        }
+    };
+    let mut closed = 0;
+    for key in &close {
+        let Some((repo, number)) = key.split_once('#') else {
+            continue;
+        };
+        // One that's gone has nothing left to close
+        let (code, _, _) = api.call("PATCH", &format!("/repos/{}/issues/{}", repo, number), None, Some(&json!({ "state": "closed" })))?;
+        if code != 404 {
+            closed += 1;
+        }
+    }
+    let mut repos = BTreeMap::new();
+    for repo in &config.repos {
+        let cached = cache.repos.remove(repo);
+        // What was just closed is in the cached reply, so that's not asked about
+        let etag = cached.as_ref().filter(|_| closed == 0).and_then(|cached| cached.etag.as_deref());
+        let issues = match api.issues(repo, &login, etag)? {
+            Some(fetched) => fetched,
+            None => cached.unwrap_or_default(),
+        };
+        repos.insert(repo.clone(), issues);
+    }
+    Ok(Refreshed { cache: IssueCache { login: Some(login), repos }, closed })
+}
+
+impl App {
+    /// Where the replies of the last refresh are cached, next to the todo file
+    fn issue_cache_path(&self) -> PathBuf {
+        self.save_path.with_extension("github.json")
+    }
+
+    /// Whether the list at `index` is the one the issues are shown in
+    /// Once "github" is taken out of the config it's an ordinary list
+    pub(crate) fn is_issue_list(&self, index: usize) -> bool {
+        self.github.is_some() && self.lists.get(index).is_some_and(|list| list.github)
+    }
+
+    /// Why a command can't be run, when it changes the issues in a way
+    /// GitHub wouldn't follow
+    pub(crate) fn issue_refusal(&self, command: &Command) -> Option<&'static str> {
+        let config = self.github.as_ref()?;
+        let refused = match command {
+            Command::Batch(commands) => return commands.iter().find_map(|command| self.issue_refusal(command)),
+            // Time tracked, priorities and folding are this app's own
+            Command::Replace { list, before, after, .. } if self.is_issue_list(*list) => {
+                !same_issue(before, after) || (before.status != after.status && !config.close_on_complete)
+            }
+            Command::Insert { list, .. }
+            | Command::Remove { list, .. }
+            | Command::Move { list, .. }
+            | Command::Trash { list, .. }
+            | Command::Restore { list, .. }
+            | Command::SetTrash { list, .. }
+            | Command::Archive { list, .. }
+            | Command::Unarchive { list, .. } => self.is_issue_list(*list),
+            Command::RemoveList { index, .. } | Command::RenameList { index, .. } => self.is_issue_list(*index),
+            Command::Replace { .. } | Command::InsertList { .. } | Command::SetLists { .. } => false,
+        };
+        refused.then_some(if config.close_on_complete {
+            "Issues change on GitHub - only completing them works here"
+        } else {
+            "Issues change on GitHub - the list is read-only here"
+        })
+    }
+
+    /// Called from the event loop: picks up a finished refresh and starts
+    /// the next one when the interval has passed
+    /// Returns whether a refresh started or finished
+    pub(crate) fn tick_issues(&mut self) -> bool {
+        let Some(config) = &self.github else {
+            return false;
+        };
+        let Some(receiver) = &self.issues else {
+            let interval = config.interval;
+            let due = interval > 0 && self.issues_started.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval * 60));
+            if due {
+                self.refresh_issues(false);
+            }
+            return due;
+        };
+        // Popups hold indexes into the lists, so the result waits until they're closed
+        if self.mode != Mode::Normal {
+            return false;
+        }
+        match receiver.try_recv() {
+            Ok(result) => self.finish_issues(result),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => self.finish_issues(Err("the refresh stopped unexpectedly".to_string())),
+        }
+        true
+    }
+
+    /// Fetches the issues on their own thread, closing the ones completed here first
+    pub(crate) fn refresh_issues(&mut self, manual: bool) {
+        self.issues_started = Some(Instant::now());
+        let Some(config) = self.github.clone() else {
+            return;
+        };
+        if self.issues.is_some() {
+            if manual {
+                self.notice = Some(self.tr("Already fetching the issues").to_string());
+            }
+            return;
+        }
+        // Closing an issue is for the instance that can save the list afterwards
+        let close = match self.lists.iter().find(|list| list.github) {
+            Some(list) if config.close_on_complete && !self.read_only => {
+                list.todos.iter().filter(|todo| todo.is_closed()).filter_map(|todo| todo.github_issue.clone()).collect()
+            }
+            _ => Vec::new(),
+        };
+        let cache: IssueCache = fs::read_to_string(self.issue_cache_path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(refresh(&config, cache, close));
+        });
+        self.issues = Some(receiver);
+        self.issues_manual = manual;
+    }
+
+    /// Puts the fetched issues in their list and reports how it went
+    fn finish_issues(&mut self, result: Result<Refreshed, String>) {
+        self.issues = None;
+        let refreshed = match result {
+            Ok(refreshed) => refreshed,
+            Err(err) => {
+                // Once is enough while it keeps failing in the background
+                if self.issues_manual || !self.issues_failed {
+                    self.notice = Some(self.trf("Fetching the issues failed: {}", &[&err]));
+                }
+                self.issues_failed = true;
+                return;
+            }
+        };
+        self.issues_failed = false;
+        if let Err(err) = serde_json::to_string(&refreshed.cache).map_err(|err| err.to_string()).and_then(|json| {
+            fs::write(self.issue_cache_path(), json).map_err(|err| err.to_string())
+        }) {
+            self.notice = Some(self.trf("Could not cache the issues: {}", &[&err]));
+        }
+        let Some(config) = &self.github else {
+            return;
+        };
+        let issues: Vec<&Issue> = config.repos.iter().filter_map(|repo| refreshed.cache.repos.get(repo)).flat_map(|repo| &repo.issues).collect();
+        let index = match self.lists.iter().position(|list| list.github) {
+            Some(index) => index,
+            None => {
+                let mut list = TodoList::new(&config.list);
+                list.github = true;
+                self.lists.push(list);
+                self.lists.len() - 1
+            }
+        };
+
+        // Each open issue keeps its todo, closed ones and ones no longer
+        // assigned go - the list is GitHub's, so this isn't undoable
+        let mut old = std::mem::take(&mut self.lists[index].todos);
+        let mut todos = Vec::new();
+        for issue in &issues {
+            let mut todo = match old.iter().position(|todo| todo.github_issue.as_ref() == Some(&issue.key)) {
+                Some(i) => old.swap_remove(i),
+                None => TodoItem::new(""),
+            };
+            issue.fill(&mut todo);
+            todos.push(todo);
+        }
+        self.lists[index].todos = todos;
+        // Undo and redo hold paths into the old todos
+        self.undo_stack.retain(|command| !touches(command, index));
+        self.redo_stack.retain(|command| !touches(command, index));
+        self.refresh_view();
+        self.autosave(false);
+        if self.issues_manual || refreshed.closed > 0 {
+            let open = self.trn("{} open issue", "{} open issues", issues.len(), &[&issues.len()]);
+            self.notice = Some(match refreshed.closed {
+                0 => self.trf("{} on GitHub", &[&open]),
+                closed => self.trf("Closed {} on GitHub, {} left", &[&closed, &open]),
+            });
+        }
+    }
+}
+
+/// Whether a command works on the todos of the list at `index`
+fn touches(command: &Command, index: usize) -> bool {
+    match command {
+        Command::Batch(commands) => commands.iter().any(|command| touches(command, index)),
+        Command::SetLists { .. } => true,
+        Command::Insert { list, .. }
+        | Command::Remove { list, .. }
+        | Command::Replace { list, .. }
+        | Command::Move { list, .. }
+        | Command::Trash { list, .. }
+        | Command::Restore { list, .. }
+        | Command::SetTrash { list, .. }
+        | Command::Archive { list, .. }
+        | Command::Unarchive { list, .. } => *list == index,
+        Command::InsertList { index: list, .. } | Command::RemoveList { index: list, .. } | Command::RenameList { index: list, .. } => {
+            *list == index
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Priority;
+
+    #[test]
+    fn issue_list_only_takes_what_github_would_follow() {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-github-test.json"));
+        app.github = Some(GitHubConfig { close_on_complete: true, ..GitHubConfig::default() });
+        let issue = Issue::parse("o/r", &json!({ "number": 7, "title": "Crash", "labels": [{ "name": "good first issue" }] })).unwrap();
+        let mut list = TodoList::new("GitHub");
+        list.github = true;
+        list.todos.push(TodoItem::new(""));
+        issue.fill(&mut list.todos[0]);
+        assert_eq!((list.todos[0].text.as_str(), list.todos[0].tags.as_slice()), ("Crash #7", &["good-first-issue".to_string()][..]));
+        app.lists.push(list);
+        let index = app.lists.len() - 1;
+        let todo = app.lists[index].todos[0].clone();
+        let replace = |change: fn(&mut TodoItem)| {
+            let mut after = todo.clone();
+            change(&mut after);
+            Command::Replace { list: index, path: vec![0], before: Box::new(todo.clone()), after: Box::new(after) }
+        };
+
+        assert!(app.issue_refusal(&replace(|todo| todo.priority = Priority::High)).is_none());
+        assert!(app.issue_refusal(&replace(|todo| todo.set_completed(true))).is_none());
+        assert!(app.issue_refusal(&replace(|todo| todo.text.push('!'))).is_some());
+        assert!(app.issue_refusal(&Command::Batch(vec![Command::Trash { list: index, path: vec![0], index: 0 }])).is_some());
+        assert!(app.issue_refusal(&Command::Trash { list: 0, path: vec![0], index: 0 }).is_none());
+
+        app.github.as_mut().unwrap().close_on_complete = false;
+        assert!(app.issue_refusal(&replace(|todo| todo.set_completed(true))).is_some());
+        // Without "github" in the config it's an ordinary list
+        app.github = None;
+        assert!(app.issue_refusal(&replace(|todo| todo.text.push('!'))).is_none());
+    }
+}
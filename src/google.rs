@@ -0,0 +1,481 @@
+//! Two-way sync of the lists with Google Tasks, so todos checked off in the
+//! phone app are checked off here too
+//! Each list is a task list and each todo a task. The sync is `reconcile` in
+//! sync.rs, as it is for Todoist - what's different is signing in: the API
+//! wants OAuth, so `todo-tui google-login` gets a refresh token once through
+//! the browser, and each sync trades it for an access token
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+};
+
+use crate::{
+    app::{base64_encode, Date, Timestamp, TodoItem, TodoList},
+    crypto::{random_bytes, sha256},
+    import::parse_timestamp,
+    storage::Config,
+    sync::{curl, curl_quote, reconcile, url_decode, url_encode, Backend, RemoteTask, Service, SyncOutcome, SyncState},
+};
+
+/// Where the Tasks API lives
+const GOOGLE_URL: &str = "https://tasks.googleapis.com/tasks/v1";
+/// Where the browser is sent to sign in
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+/// Where codes and refresh tokens are traded for access tokens
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+/// Access to the tasks and nothing else in the account
+const SCOPE: &str = "https://www.googleapis.com/auth/tasks";
+
+/// The "google" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct GoogleConfig {
+    /// OAuth client of the "Desktop app" type, made in the Google Cloud
+    /// console of a project with the Tasks API enabled
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    /// Minutes between syncs while the app runs, 0 to sync only when asked
+    pub(crate) interval: u64,
+    /// Base URL of the API, for going through a proxy
+    pub(crate) url: String,
+}
+
+impl Default for GoogleConfig {
+    fn default() -> GoogleConfig {
+        GoogleConfig { client_id: String::new(), client_secret: String::new(), interval: 5, url: GOOGLE_URL.to_string() }
+    }
+}
+
+/// What signing in leaves behind, in google-token.json next to the config
+#[derive(Serialize, Deserialize)]
+struct Token {
+    refresh_token: String,
+}
+
+/// Where the refresh token is kept - next to the config rather than the todo
+/// file, since it's the account that's signed in, not the lists
+fn token_path() -> Option<PathBuf> {
+    Config::path().map(|path| path.with_file_name("google-token.json"))
+}
+
+/// Base64 the way URLs take it, without padding
+fn base64_url(bytes: &[u8]) -> String {
+    base64_encode(bytes).replace('+', "-").replace('/', "_").trim_end_matches('=').to_string()
+}
+
+/// Trades a code or refresh token for tokens at Google's token endpoint
+fn token_request(fields: &[(&str, &str)]) -> Result<Value, String> {
+    let mut options = vec![format!("url = {}", curl_quote(TOKEN_URL))];
+    options.extend(fields.iter().map(|(name, value)| format!("data-urlencode = {}", curl_quote(&format!("{}={}", name, value)))));
+    let (code, reply) = curl(options)?;
+    let reply: Value = serde_json::from_str(&reply).map_err(|err| format!("unexpected reply from Google: {}", err))?;
+    match code {
+        200..=299 => Ok(reply),
+        _ => match reply.get("error").and_then(Value::as_str) {
+            Some("invalid_grant") => Err("Google refused the sign-in - run `todo-tui google-login` again".to_string()),
+            Some("invalid_client") => Err("Google doesn't know the client - check \"client_id\" and \"client_secret\" in the config".to_string()),
+            Some(error) => Err(format!("Google answered {} {}", code, error)),
+            None => Err(format!("Google answered {}", code)),
+        },
+    }
+}
+
+/// Signs in to Google through the browser and keeps the refresh token the
+/// sync needs, returning where it was written
+/// `show` is handed the address to open; Google sends the browser back to a
+/// port on this machine with the code, so nothing has to be copied over
+pub fn google_login(config: &Config, show: impl FnOnce(&str)) -> Result<PathBuf, String> {
+    let google = config.google.as_ref().filter(|google| !google.client_id.trim().is_empty());
+    let google = google.ok_or("set \"google\" with a \"client_id\" in the config first")?;
+    let path = token_path().ok_or("no config directory to keep the sign-in in")?;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).map_err(|err| err.to_string())?;
+    let redirect = format!("http://127.0.0.1:{}", listener.local_addr().map_err(|err| err.to_string())?.port());
+    // PKCE: the code is only good to whoever knows the verifier it was asked
+    // for with, so another program catching the redirect can't use it
+    let mut random = [0u8; 48];
+    random_bytes(&mut random).map_err(|err| err.to_string())?;
+    let verifier = base64_url(&random[..32]);
+    let state = base64_url(&random[32..]);
+    let params = [
+        ("client_id", google.client_id.as_str()),
+        ("redirect_uri", &redirect),
+        ("response_type", "code"),
+        ("scope", SCOPE),
+        ("code_challenge", &base64_url(&sha256(verifier.as_bytes()))),
+        ("code_challenge_method", "S256"),
+        ("state", &state),
+        // Without these Google hands out a refresh token only the first time
+        ("access_type", "offline"),
+        ("prompt", "consent"),
+    ];
+    let query: Vec<String> = params.iter().map(|(name, value)| format!("{}={}", name, url_encode(value))).collect();
+    show(&format!("{}?{}", AUTH_URL, query.join("&")));
+
+    let code = loop {
+        let (stream, _) = listener.accept().map_err(|err| err.to_string())?;
+        let mut line = String::new();
+        let mut reader = BufReader::new(&stream);
+        if reader.read_line(&mut line).is_err() {
+            continue;
+        }
+        // "GET /?state=...&code=... HTTP/1.1" - anything else, like the
+        // browser asking for a favicon, is turned away
+        let target = line.split_whitespace().nth(1).unwrap_or_default();
+        let query: HashMap<&str, String> = target
+            .split_once('?')
+            .map_or("", |(_, query)| query)
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name, url_decode(value)))
+            .collect();
+        let (status, page, result) = match (query.get("code"), query.get("error")) {
+            (_, Some(error)) => ("200 OK", "Signing in was cancelled.", Some(Err(format!("Google said {}", error)))),
+            (Some(_), _) if query.get("state") != Some(&state) => {
+                ("400 Bad Request", "That sign-in wasn't asked for here.", None)
+            }
+            (Some(code), _) => ("200 OK", "Signed in - you can close this tab and go back to the terminal.", Some(Ok(code.clone()))),
+            (None, None) => ("404 Not Found", "", None),
+        };
+        let page = format!("<!DOCTYPE html><title>todo-tui</title><p>{}</p>", page);
+        let _ = write!(
+            &stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            page.len(),
+            page
+        );
+        if let Some(result) = result {
+            break result?;
+        }
+    };
+
+    let reply = token_request(&[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("code_verifier", &verifier),
+        ("redirect_uri", &redirect),
+        ("client_id", &google.client_id),
+        ("client_secret", &google.client_secret),
+    ])?;
+    let refresh_token = reply.get("refresh_token").and_then(Value::as_str).ok_or("Google didn't hand out a refresh token")?;
+    let contents = serde_json::to_string_pretty(&Token { refresh_token: refresh_token.to_string() }).map_err(|err| err.to_string())?;
+    write_private(&path, &contents).map_err(|err| format!("couldn't write {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// Writes a file only its owner can read, since the token opens the account
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to a new file, so an old one is tightened too
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// An access token for this sync, from the refresh token signing in left
+fn access_token(config: &GoogleConfig) -> Result<String, String> {
+    let path = token_path().ok_or("no config directory to find the sign-in in")?;
+    let token: Token = fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .ok_or("not signed in to Google - run `todo-tui google-login` first")?;
+    let reply = token_request(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", &token.refresh_token),
+        ("client_id", &config.client_id),
+        ("client_secret", &config.client_secret),
+    ])?;
+    reply.get("access_token").and_then(Value::as_str).map(str::to_string).ok_or_else(|| "Google didn't hand out an access token".to_string())
+}
+
+/// Whether a todo and a task agree on everything that's synced
+/// Tasks have a date but no time, and no priority, tags or repeats
+fn same(todo: &TodoItem, task: &TodoItem) -> bool {
+    todo.text == task.text && todo.notes == task.notes && todo.is_closed() == task.is_closed() && todo.due == task.due
+}
+
+/// Takes the synced fields of a task for a todo
+/// A time of day is only dropped when the date moved
+pub(crate) fn pull(todo: &mut TodoItem, task: &TodoItem) {
+    todo.text = task.text.clone();
+    todo.notes = task.notes.clone();
+    if todo.due != task.due {
+        todo.due = task.due;
+        todo.due_time = None;
+    }
+    if todo.is_closed() != task.is_closed() {
+        todo.set_completed(task.is_closed());
+    }
+}
+
+/// The fields of a task as the API takes them
+/// The due date is sent as midnight UTC, which is how Google keeps dates
+fn task_fields(todo: &TodoItem) -> Value {
+    json!({
+        "title": todo.text,
+        "notes": todo.notes,
+        "status": if todo.is_closed() { "completed" } else { "needsAction" },
+        "due": todo.due.map(|date| format!("{}T00:00:00.000Z", date)),
+        // Null clears the completion time when a task is reopened
+        "completed": todo.completed_at.filter(|_| todo.is_closed()),
+    })
+}
+
+/// The Tasks API, called through curl
+struct Api<'a> {
+    config: &'a GoogleConfig,
+    token: String,
+}
+
+impl Api<'_> {
+    /// Makes a request and returns the reply, None when it was 404 Not Found
+    fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Option<Value>, String> {
+        let mut options = vec![
+            format!("url = {}", curl_quote(&format!("{}{}", self.config.url.trim_end_matches('/'), path))),
+            format!("request = {}", method),
+            format!("header = {}", curl_quote(&format!("Authorization: Bearer {}", self.token))),
+        ];
+        if let Some(body) = body {
+            options.push(format!("header = {}", curl_quote("Content-Type: application/json")));
+            options.push(format!("data-raw = {}", curl_quote(&body.to_string())));
+        }
+        let (code, reply) = curl(options)?;
+        match code {
+            404 => Ok(None),
+            200..=299 if reply.trim().is_empty() => Ok(Some(Value::Null)),
+            200..=299 => serde_json::from_str(&reply).map(Some).map_err(|err| format!("unexpected reply from Google: {}", err)),
+            401 | 403 => Err("Google refused access to the tasks - run `todo-tui google-login` again".to_string()),
+            code => {
+                let reason = serde_json::from_str::<Value>(&reply)
+                    .ok()
+                    .and_then(|reply| reply.pointer("/error/message")?.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                Err(format!("Google answered {} {}", code, reason))
+            }
+        }
+    }
+
+    /// Everything a listing endpoint has, following its pages
+    /// `path` already has a query, the page token goes on the end
+    fn list(&self, path: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let page = match &page_token {
+                Some(token) => format!("{}&pageToken={}", path, url_encode(token)),
+                None => path.to_string(),
+            };
+            let reply = self.call("GET", &page, None)?.ok_or_else(|| format!("Google has no {}", path))?;
+            items.extend(reply.get("items").and_then(Value::as_array).cloned().unwrap_or_default());
+            match reply.get("nextPageToken").and_then(Value::as_str) {
+                Some(next) => page_token = Some(next.to_string()),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// Path of a task, or of the tasks in a list with no id
+    fn task_path(list: &str, id: Option<&str>) -> String {
+        match id {
+            Some(id) => format!("/lists/{}/tasks/{}", url_encode(list), url_encode(id)),
+            None => format!("/lists/{}/tasks", url_encode(list)),
+        }
+    }
+}
+
+/// A task as the sync sees it
+struct Task {
+    id: String,
+    /// The task list it's in
+    list: String,
+    parent: Option<String>,
+    /// Its place among its siblings, as a string that sorts
+    position: String,
+    /// Its fields as a todo linked to it
+    todo: TodoItem,
+    updated: Option<Timestamp>,
+}
+
+impl Task {
+    fn parse(value: &Value, list: &str) -> Option<Task> {
+        let id = value.get("id")?.as_str()?.to_string();
+        if value.get("deleted").and_then(Value::as_bool) == Some(true) {
+            return None;
+        }
+        let text = |name: &str| value.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+        let mut todo = TodoItem::new(text("title").trim());
+        todo.notes = text("notes");
+        // Only the date part means anything, whatever the time says
+        todo.due = value.get("due").and_then(Value::as_str).and_then(|due| Date::parse(due.get(..10)?));
+        if text("status") == "completed" {
+            todo.set_completed(true);
+            if let Some(at) = parse_timestamp(&text("completed")) {
+                todo.completed_at = Some(at);
+            }
+        }
+        Some(Task {
+            id,
+            list: list.to_string(),
+            parent: value.get("parent").and_then(Value::as_str).map(str::to_string),
+            position: text("position"),
+            todo,
+            updated: parse_timestamp(&text("updated")),
+        })
+    }
+}
+
+/// Google Tasks as a backend for `reconcile`
+struct Google<'a> {
+    api: Api<'a>,
+    /// Where each task is, for putting new ones after their last sibling
+    places: Vec<Place>,
+}
+
+/// A task's list, parent and position among its siblings
+struct Place {
+    id: String,
+    list: String,
+    parent: Option<String>,
+    position: String,
+}
+
+/// One sync, run on its own thread: sends what changed here to Google and
+/// returns what changed there
+pub(crate) fn sync(config: &GoogleConfig, lists: &[TodoList], state: &SyncState) -> Result<SyncOutcome, String> {
+    let api = Api { config, token: access_token(config)? };
+    reconcile(&mut Google { api, places: Vec::new() }, lists, state)
+}
+
+impl Backend for Google<'_> {
+    const SERVICE: Service = Service::Google;
+    const UNNAMED: &'static str = "Google Tasks";
+    // Google Tasks nests only one level, so deeper subtasks go under the
+    // top-level task they're in
+    const ONE_LEVEL: bool = true;
+
+    fn projects(&mut self) -> Result<Vec<(String, String)>, String> {
+        Ok(self
+            .api
+            .list("/users/@me/lists?maxResults=100")?
+            .iter()
+            .filter_map(|list| Some((list.get("id")?.as_str()?.to_string(), list.get("title")?.as_str()?.to_string())))
+            .collect())
+    }
+
+    fn create_project(&mut self, name: &str) -> Result<String, String> {
+        let task_list = self.api.call("POST", "/users/@me/lists", Some(&json!({ "title": name })))?;
+        let id = task_list.and_then(|task_list| Some(task_list.get("id")?.as_str()?.to_string()));
+        Ok(id.ok_or("Google didn't create the task list")?)
+    }
+
+    fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String> {
+        let mut found = Vec::new();
+        for (list, _) in self.projects()? {
+            // Hidden tasks are the completed ones cleared away on the phone
+            let path = format!("{}?showCompleted=true&showHidden=true&maxResults=100", Api::task_path(&list, None));
+            let mut tasks: Vec<Task> = self.api.list(&path)?.iter().filter_map(|value| Task::parse(value, &list)).collect();
+            tasks.sort_by(|a, b| a.position.cmp(&b.position));
+            found.extend(tasks.into_iter().map(|task| self.place(task)));
+        }
+        Ok(found)
+    }
+
+    fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool {
+        same(todo, task)
+    }
+
+    /// Creates the task at the end of its siblings
+    fn create(&mut self, todo: &TodoItem, task_list: &str, parent: Option<&str>, _: Option<&str>) -> Result<String, String> {
+        let mut path = Api::task_path(task_list, None);
+        // Google puts new tasks first, so they go after the last sibling
+        let siblings = self.places.iter().filter(|place| place.list == task_list && place.parent.as_deref() == parent);
+        let previous = siblings.max_by(|a, b| a.position.cmp(&b.position)).map(|place| place.id.clone());
+        let query: Vec<String> = [("parent", parent.map(str::to_string)), ("previous", previous)]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}={}", name, url_encode(&value?))))
+            .collect();
+        if !query.is_empty() {
+            path = format!("{}?{}", path, query.join("&"));
+        }
+        let task = self.api.call("POST", &path, Some(&task_fields(todo)))?;
+        let task = task.as_ref().and_then(|task| Task::parse(task, task_list)).ok_or("Google didn't create the task")?;
+        Ok(self.place(task).0)
+    }
+
+    fn update(&mut self, id: &str, todo: &TodoItem, task: &RemoteTask, _: &str, _: Option<&str>) -> Result<(), String> {
+        self.api.call("PATCH", &Api::task_path(&task.project, Some(id)), Some(&task_fields(todo))).map(|_| ())
+    }
+
+    fn delete(&mut self, id: &str, task: &RemoteTask) -> Result<(), String> {
+        self.api.call("DELETE", &Api::task_path(&task.project, Some(id)), None).map(|_| ())
+    }
+}
+
+impl Google<'_> {
+    /// Keeps where a task is and hands it on to `reconcile`
+    fn place(&mut self, task: Task) -> (String, RemoteTask) {
+        let Task { id, list, parent, position, todo, updated } = task;
+        self.places.push(Place { id: id.clone(), list: list.clone(), parent: parent.clone(), position });
+        (id, RemoteTask { project: list, parent, todo, updated })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_are_read() {
+        let value = json!({
+            "id": "abc",
+            "title": " Water the plants ",
+            "notes": "the ones outside too",
+            "status": "completed",
+            "completed": "2024-05-02T08:30:00.000Z",
+            "due": "2024-05-01T00:00:00.000Z",
+            "parent": "top",
+            "position": "00000000000000000003",
+            "updated": "2024-05-02T08:30:00.000Z",
+        });
+        let task = Task::parse(&value, "list").unwrap();
+        assert_eq!((task.id.as_str(), task.list.as_str(), task.parent.as_deref()), ("abc", "list", Some("top")));
+        assert_eq!(task.todo.text, "Water the plants");
+        assert_eq!(task.todo.due, Date::new(2024, 5, 1));
+        assert!(task.todo.is_closed());
+        assert_eq!(task.todo.completed_at, parse_timestamp("2024-05-02T08:30:00Z"));
+        assert!(Task::parse(&json!({ "id": "gone", "deleted": true }), "list").is_none());
+
+        // What's sent back reads the same
+        let fields = task_fields(&task.todo);
+        assert_eq!(fields["due"], "2024-05-01T00:00:00.000Z");
+        assert!(same(&task.todo, &Task::parse(&json!({ "id": "abc", "title": fields["title"], "notes": fields["notes"],
+            "status": fields["status"], "due": fields["due"] }), "list").unwrap().todo));
+    }
+
+    #[test]
+    fn redirect_query_is_decoded() {
+        assert_eq!(url_decode("4%2F0Ab-x%3D%3D+y"), "4/0Ab-x== y");
+        assert_eq!(url_decode(&url_encode("ünïcode & more")), "ünïcode & more");
+        // 32 bytes of verifier make the 43 characters PKCE asks for
+        assert_eq!(base64_url(&[0xfb; 32]).len(), 43);
+        assert!(!base64_url(&[0xfb, 0xff]).contains(['+', '/', '=']));
+    }
+}
@@ -0,0 +1,252 @@
+//! Keeping the todo file's history in git, and browsing it from the app
+//! Git is run as a command rather than linked - whoever turns this on has it
+//! installed, and their own config, hooks and remotes apply as usual
+
+use ratatui::widgets::ListState;
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command as Process, Stdio},
+};
+
+use crate::{
+    app::{App, Command, Mode},
+    error::AppError,
+    storage::{unseal, Format},
+};
+
+/// When changes are committed to git, from the config
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GitMode {
+    /// Never - the default, since not every todo file wants a repository
+    #[default]
+    Off,
+    /// After every save, with the changes it saved as the message
+    Save,
+    /// Once when the app quits, with everything the session changed
+    Quit,
+}
+
+/// One committed version of the todo file
+pub(crate) struct Version {
+    /// Abbreviated commit hash, enough for `git show`
+    pub(crate) hash: String,
+    /// When it was committed, as "YYYY-MM-DD HH:MM"
+    pub(crate) date: String,
+    /// First line of the commit message
+    pub(crate) subject: String,
+}
+
+/// The history popup: the versions of the todo file, newest first, and the
+/// diff of the selected one
+pub(crate) struct HistoryView {
+    pub(crate) versions: Vec<Version>,
+    pub(crate) state: ListState,
+    /// Lines of `git show` for the selected version
+    pub(crate) diff: Vec<String>,
+    /// Lines of the diff scrolled past
+    pub(crate) scroll: u16,
+}
+
+/// The directory git runs in and the todo file's name within it
+/// Symlinks are followed, like saving does, so the commits go to the
+/// repository the real file lives in
+fn locate(path: &Path) -> (PathBuf, String) {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    (dir, name)
+}
+
+/// Runs git in `dir` and returns what it printed
+/// A failure is reported with the last line git wrote to stderr, which is
+/// where it puts the reason
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Process::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => "git not found".to_string(),
+            _ => err.to_string(),
+        })?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => line.trim().to_string(),
+        None => format!("git {} failed ({})", args[0], output.status),
+    })
+}
+
+/// Whether `dir` is inside a git work tree
+fn in_repository(dir: &Path) -> bool {
+    git(dir, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.trim() == "true")
+}
+
+/// Commits the todo file with `message`, starting a repository next to it
+/// if it isn't in one yet
+/// Only the todo file is committed, so a file kept inside a project's own
+/// repository never sweeps up the project's staged changes
+pub(crate) fn commit(path: &Path, message: &str) -> Result<(), AppError> {
+    let error = |message: String| AppError::Git { path: path.to_path_buf(), message };
+    let (dir, name) = locate(path);
+    if !in_repository(&dir) {
+        git(&dir, &["init", "--quiet"]).map_err(error)?;
+    }
+    git(&dir, &["add", "--", &name]).map_err(error)?;
+    // Undoing back to what was committed leaves nothing to commit
+    if git(&dir, &["diff", "--cached", "--name-only", "--", &name]).map_err(error)?.trim().is_empty() {
+        return Ok(());
+    }
+    // A machine that never set up git has no identity, and git refuses to
+    // commit without one
+    let mut args = Vec::new();
+    if git(&dir, &["config", "user.email"]).is_err() {
+        args.extend(["-c", "user.name=todo-tui", "-c", "user.email=todo-tui@localhost"]);
+    }
+    args.extend(["commit", "--quiet", "-m", message, "--", &name]);
+    git(&dir, &args).map_err(error)?;
+    Ok(())
+}
+
+/// Commit message for a set of changes: the first as the subject, with
+/// the rest listed in the body
+pub(crate) fn commit_message(changes: &[String]) -> String {
+    match changes {
+        [] => "Update todos".to_string(),
+        [change] => change.clone(),
+        [first, rest @ ..] => {
+            let list: Vec<String> = changes.iter().map(|change| format!("- {}", change)).collect();
+            let more = if rest.len() == 1 { "1 more change".to_string() } else { format!("{} more changes", rest.len()) };
+            format!("{} and {}\n\n{}", first, more, list.join("\n"))
+        }
+    }
+}
+
+/// The committed versions of the todo file, newest first
+fn log(path: &Path) -> Result<Vec<Version>, String> {
+    let (dir, name) = locate(path);
+    if !in_repository(&dir) {
+        return Err(format!("{} isn't in a git repository - set \"git\" in the config to start one", name));
+    }
+    let out = git(&dir, &["log", "--format=%h%x1f%cd%x1f%s", "--date=format:%Y-%m-%d %H:%M", "--", &name])?;
+    Ok(out
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\x1f');
+            Some(Version {
+                hash: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// What version `hash` changed in the todo file, as a unified diff
+/// The first version shows the whole file as added
+fn diff(path: &Path, hash: &str) -> Result<Vec<String>, String> {
+    let (dir, name) = locate(path);
+    let out = git(&dir, &["show", "--format=", "--no-color", "--no-ext-diff", hash, "--", &name])?;
+    Ok(out.lines().map(str::to_string).collect())
+}
+
+/// The todo file's contents as of version `hash`
+fn read_version(path: &Path, hash: &str) -> Result<String, String> {
+    let (dir, name) = locate(path);
+    git(&dir, &["show", &format!("{}:./{}", hash, name)])
+}
+
+// The history popup
+impl App {
+    /// Opens the history popup with the newest version selected
+    pub(crate) fn open_history(&mut self) -> Result<(), String> {
+        let versions = log(&self.save_path)?;
+        if versions.is_empty() {
+            return Err("nothing committed yet".to_string());
+        }
+        self.history = Some(HistoryView { versions, state: ListState::default(), diff: Vec::new(), scroll: 0 });
+        self.select_version(0);
+        self.mode = Mode::History;
+        Ok(())
+    }
+
+    /// Moves the selection down (1, older) or up (-1, newer), wrapping like
+    /// the other popups
+    pub(crate) fn history_move(&mut self, delta: isize) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let len = history.versions.len() as isize;
+        let row = history.state.selected().map_or(0, |row| (row as isize + delta).rem_euclid(len));
+        self.select_version(row as usize);
+    }
+
+    /// Scrolls the diff by `lines`, down when positive
+    pub(crate) fn history_scroll(&mut self, lines: i32) {
+        if let Some(history) = &mut self.history {
+            let last = history.diff.len().saturating_sub(1) as i32;
+            history.scroll = (history.scroll as i32 + lines).clamp(0, last) as u16;
+        }
+    }
+
+    /// Selects version `row` and loads its diff
+    fn select_version(&mut self, row: usize) {
+        let path = self.save_path.clone();
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        history.state.select(Some(row));
+        history.scroll = 0;
+        history.diff = if self.cipher.is_some() {
+            // The commits hold ciphertext, which makes for a meaningless diff
+            vec!["The todo file is encrypted, so only the description of each change is shown".to_string()]
+        } else {
+            diff(&path, &history.versions[row].hash).unwrap_or_else(|err| vec![err])
+        };
+    }
+
+    /// Replaces every list with the selected version, as one change that
+    /// undo takes back
+    pub(crate) fn restore_version(&mut self) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let Some(version) = history.state.selected().map(|row| &history.versions[row]) else {
+            return;
+        };
+        let (hash, date) = (version.hash.clone(), version.date.clone());
+        let lists = read_version(&self.save_path, &hash).and_then(|contents| {
+            let contents = unseal(&self.save_path, contents, self.cipher.as_ref()).map_err(|err| err.to_string())?;
+            Format::from_path(&self.save_path)
+                .read(&contents)
+                .map_err(|err| format!("could not parse version {}: {}", hash, err))
+        });
+        match lists {
+            Ok(lists) if lists.is_empty() => self.notice = Some(self.trf("Version {} has no lists", &[&hash])),
+            Ok(after) => {
+                let before = self.lists.clone();
+                self.execute(Command::SetLists { before, after });
+                // Read-only instances refuse the change and say so themselves
+                if !self.read_only {
+                    self.notice = Some(self.trf("Restored the version from {} (u: undo)", &[&date]));
+                }
+                self.mode = Mode::Normal;
+                self.history = None;
+            }
+            Err(err) => self.notice = Some(err),
+        }
+    }
+
+    /// Closes the history popup
+    pub(crate) fn close_history(&mut self) {
+        self.history = None;
+        self.mode = Mode::Normal;
+    }
+}
@@ -0,0 +1,296 @@
+//! Shell commands run when a todo is added, completed or deleted, the way
+//! Taskwarrior's hooks are - for hooking the app up to anything without it
+//! having to know about it
+//! The todo is handed over twice: as TODO_* environment variables for a
+//! one-liner, and as JSON on standard input for a script that wants all of it.
+//! Hooks run in the background and can't change the todo; a failing one is
+//! reported, but what it was reacting to has happened anyway
+
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    io::Write,
+    process::{Command as Process, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use crate::app::{item_at, App, Command, Status, TodoItem, TodoList};
+
+/// The "hooks" table of the config: the commands for each event, run with
+/// `sh -c` (`cmd /C` on Windows) one after the other
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct HooksConfig {
+    #[serde(alias = "on-add")]
+    pub(crate) on_add: Vec<String>,
+    #[serde(alias = "on-complete")]
+    pub(crate) on_complete: Vec<String>,
+    #[serde(alias = "on-delete")]
+    pub(crate) on_delete: Vec<String>,
+}
+
+impl HooksConfig {
+    fn commands(&self, event: Event) -> &[String] {
+        match event {
+            Event::Add => &self.on_add,
+            Event::Complete => &self.on_complete,
+            Event::Delete => &self.on_delete,
+        }
+    }
+}
+
+/// What happened to a todo
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Add,
+    Complete,
+    Delete,
+}
+
+impl Event {
+    /// Name in TODO_EVENT and the JSON, and in messages
+    fn name(self) -> &'static str {
+        match self {
+            Event::Add => "add",
+            Event::Complete => "complete",
+            Event::Delete => "delete",
+        }
+    }
+}
+
+/// An event waiting for its hooks, with the todo as it was then
+pub(crate) struct Fired {
+    event: Event,
+    list: String,
+    todo: TodoItem,
+}
+
+/// A hook command that failed, put into words on the interface's side so
+/// it's in the user's language
+struct Failure {
+    event: Event,
+    command: String,
+    error: String,
+}
+
+/// The hooks running in the background and the way they report back
+pub(crate) struct HookRunner {
+    running: Vec<JoinHandle<()>>,
+    sender: Sender<Failure>,
+    failures: Receiver<Failure>,
+}
+
+impl Default for HookRunner {
+    fn default() -> HookRunner {
+        let (sender, failures) = mpsc::channel();
+        HookRunner { running: Vec::new(), sender, failures }
+    }
+}
+
+/// Whether a change completes a todo - for a repeating one that's it moving
+/// on to its next date, which is what completing it does
+pub(crate) fn completes(before: &TodoItem, after: &TodoItem) -> bool {
+    if after.status == Status::Done {
+        return before.status != Status::Done;
+    }
+    match before.recurrence {
+        Some(rule) if after.recurrence == Some(rule) && after.due != before.due => {
+            let mut next = before.clone();
+            next.reschedule(rule);
+            after.due == next.due
+        }
+        _ => false,
+    }
+}
+
+/// The events of a command, worked out against the lists before it runs
+/// A batch's commands each see the lists the ones before them left
+fn collect(command: &Command, app: &App, fired: &mut Vec<Fired>) {
+    let Command::Batch(commands) = command else {
+        fired.extend(event_of(command, &app.lists));
+        return;
+    };
+    let mut lists = app.lists.clone();
+    let mut archive = app.archive.clone();
+    for command in commands {
+        fired.extend(event_of(command, &lists));
+        command.apply(&mut lists, &mut archive);
+    }
+    // Taking todos out and putting them in elsewhere is moving them, not adding
+    if commands.iter().any(|command| matches!(command, Command::Remove { .. })) {
+        fired.retain(|fired| fired.event != Event::Add);
+    }
+}
+
+/// The event of a single command, if it's one hooks are run for
+fn event_of(command: &Command, lists: &[TodoList]) -> Option<Fired> {
+    let (event, list, todo) = match command {
+        Command::Insert { list, item, .. } => (Event::Add, *list, item.clone()),
+        Command::Trash { list, path, .. } => (Event::Delete, *list, item_at(&lists[*list].todos, path).clone()),
+        Command::Replace { list, before, after, .. } if completes(before, after) => (Event::Complete, *list, after.as_ref().clone()),
+        _ => return None,
+    };
+    Some(Fired { event, list: lists[list].name.clone(), todo })
+}
+
+/// Runs one hook command and waits for it, returning what went wrong
+fn run_hook(command: &str, fired: &Fired, file: &str) -> Result<(), String> {
+    let todo = &fired.todo;
+    let due = match (todo.due, todo.due_time) {
+        (Some(date), Some(time)) => format!("{} {}", date, time),
+        (Some(date), None) => date.to_string(),
+        (None, _) => String::new(),
+    };
+    let status = match todo.status {
+        Status::Pending => "pending",
+        Status::InProgress => "in_progress",
+        Status::Blocked => "blocked",
+        Status::Done => "done",
+        Status::Cancelled => "cancelled",
+    };
+    let mut process = if cfg!(windows) {
+        let mut process = Process::new("cmd");
+        process.args(["/C", command]);
+        process
+    } else {
+        let mut process = Process::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+    // Output would draw over the interface, so only errors are kept, to report
+    let mut child = process
+        .env("TODO_EVENT", fired.event.name())
+        .env("TODO_LIST", &fired.list)
+        .env("TODO_TEXT", &todo.text)
+        .env("TODO_STATUS", status)
+        .env("TODO_PRIORITY", todo.priority.name())
+        .env("TODO_TAGS", todo.tags.join(" "))
+        .env("TODO_CONTEXTS", todo.contexts.join(" "))
+        .env("TODO_PROJECT", todo.project.as_deref().unwrap_or_default())
+        .env("TODO_DUE", due)
+        .env("TODO_NOTES", &todo.notes)
+        .env("TODO_FILE", file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+    let input = json!({ "event": fired.event.name(), "list": fired.list, "todo": todo });
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input is fine
+        let _ = stdin.write_all(format!("{}\n", input).as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => Err(format!("exited with {}: {}", output.status, line.trim())),
+        None => Err(format!("exited with {}", output.status)),
+    }
+}
+
+impl App {
+    /// The events a command is about to cause, for `run_hooks` once it has run
+    pub(crate) fn hook_events(&self, command: &Command) -> Vec<Fired> {
+        let hooks = &self.hooks;
+        if hooks.on_add.is_empty() && hooks.on_complete.is_empty() && hooks.on_delete.is_empty() {
+            return Vec::new();
+        }
+        let mut fired = Vec::new();
+        collect(command, self, &mut fired);
+        fired
+    }
+
+    /// Starts the hooks of the events, on a thread so the interface doesn't
+    /// wait for them - the hooks of one event run in order
+    pub(crate) fn run_hooks(&mut self, fired: Vec<Fired>) {
+        let file = self.save_path.display().to_string();
+        for fired in fired {
+            let commands = self.hooks.commands(fired.event).to_vec();
+            if commands.is_empty() {
+                continue;
+            }
+            let sender = self.hook_runner.sender.clone();
+            let file = file.clone();
+            self.hook_runner.running.push(thread::spawn(move || {
+                for command in commands {
+                    if let Err(err) = run_hook(&command, &fired, &file) {
+                        let _ = sender.send(Failure { event: fired.event, command, error: err });
+                    }
+                }
+            }));
+        }
+    }
+
+    /// Called from the event loop: forgets finished hooks and reports the
+    /// ones that failed
+    /// Returns whether there was a failure to show
+    pub(crate) fn tick_hooks(&mut self) -> bool {
+        self.hook_runner.running.retain(|hook| !hook.is_finished());
+        let Some(failure) = self.hook_runner.failures.try_iter().last() else {
+            return false;
+        };
+        self.notice = Some(self.describe_failure(failure));
+        true
+    }
+
+    fn describe_failure(&self, failure: Failure) -> String {
+        self.trf("The {} hook '{}' failed: {}", &[&failure.event.name(), &failure.command, &failure.error])
+    }
+
+    /// Waits for the running hooks, so quitting doesn't cut them off, and
+    /// returns the ones that failed
+    pub fn wait_for_hooks(&mut self) -> Vec<String> {
+        for hook in self.hook_runner.running.drain(..) {
+            let _ = hook.join();
+        }
+        let failures: Vec<Failure> = self.hook_runner.failures.try_iter().collect();
+        failures.into_iter().map(|failure| self.describe_failure(failure)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Date, Recurrence};
+
+    #[test]
+    fn events_come_from_the_commands() {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-hooks-test.json"));
+        app.hooks.on_add.push("true".to_string());
+        app.lists[0].todos = vec![TodoItem::new("buy milk"), TodoItem::new("call mum")];
+        let todo = app.lists[0].todos[0].clone();
+        let mut done = todo.clone();
+        done.set_completed(true);
+        let replace = |after: TodoItem| Command::Replace { list: 0, path: vec![0], before: Box::new(todo.clone()), after: Box::new(after) };
+        let events = |command: &Command| app.hook_events(command).iter().map(|fired| (fired.event.name(), fired.todo.text.clone())).collect::<Vec<_>>();
+
+        assert_eq!(events(&replace(done)), [("complete", todo.text.clone())]);
+        let mut renamed = todo.clone();
+        renamed.text.push('!');
+        assert!(events(&replace(renamed)).is_empty());
+        // Deleting the first two from the back names each as it was
+        let second = app.lists[0].todos[1].text.clone();
+        let trash = |n: usize| Command::Trash { list: 0, path: vec![n], index: 0 };
+        assert_eq!(events(&Command::Batch(vec![trash(1), trash(0)])), [("delete", second), ("delete", todo.text.clone())]);
+        // Moving to another list isn't adding
+        let insert = Command::Insert { list: 0, path: vec![0], item: TodoItem::new("new") };
+        assert_eq!(events(&insert), [("add", "new".to_string())]);
+        let remove = Command::Remove { list: 0, path: vec![1], item: todo.clone() };
+        assert!(events(&Command::Batch(vec![insert, remove])).is_empty());
+
+        // A repeating todo is completed by moving on to its next date
+        let mut weekly = TodoItem::new("water plants");
+        weekly.recurrence = Some(Recurrence::Weekly);
+        weekly.due = Some(Date::today());
+        let mut next = weekly.clone();
+        next.reschedule(Recurrence::Weekly);
+        assert!(completes(&weekly, &next));
+        let mut postponed = weekly.clone();
+        postponed.due = Some(Date::today().add_days(1));
+        assert!(!completes(&weekly, &postponed));
+    }
+}
@@ -0,0 +1,546 @@
+//! Translations of the interface's text: titles, prompts, the status bar and
+//! the help
+//! The English text is written in the code as it is and doubles as the key its
+//! translations are looked up by, like gettext, so text without a translation
+//! shows in English rather than as a key. Text with values in it, like
+//! "{} todos", is translated as a template and filled in afterwards, so a
+//! language can put the values where its grammar wants them
+//! A new language is a table like GERMAN plus a variant of `Language`
+
+use serde::Deserialize;
+use std::{collections::HashMap, fmt::Display, sync::OnceLock};
+
+/// The language the interface is shown in, from "language" in the config or
+/// else the locale environment
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "de")]
+    German,
+}
+
+impl Language {
+    /// The language the locale asks for, by the variables in the order POSIX
+    /// gives them precedence: "de_DE.UTF-8" is German
+    /// Anything without a translation is English
+    pub(crate) fn from_env() -> Language {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        match locale.split(['_', '.', '@']).next().unwrap_or_default() {
+            "de" => Language::German,
+            _ => Language::English,
+        }
+    }
+
+    /// The text in this language, or as it is when there's no translation
+    pub(crate) fn translate(self, english: &'static str) -> &'static str {
+        static GERMAN_TABLE: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+        let table = match self {
+            Language::English => return english,
+            Language::German => GERMAN_TABLE.get_or_init(|| GERMAN.iter().copied().collect()),
+        };
+        table.get(english).copied().unwrap_or(english)
+    }
+}
+
+/// Fills the `{}` in a translated template with the values, in order
+pub(crate) fn fill(template: &str, values: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut parts = template.split("{}");
+    out.push_str(parts.next().unwrap_or_default());
+    for (i, part) in parts.enumerate() {
+        if let Some(value) = values.get(i) {
+            out.push_str(&value.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// German, by the English text
+const GERMAN: &[(&str, &str)] = &[
+    // The list and its input box
+    ("📝 Todo List", "📝 Aufgaben"),
+    ("No todos yet - press {} to add one", "Noch keine Aufgaben - {} fügt eine hinzu"),
+    ("No todos match the filters", "Keine Aufgabe passt zu den Filtern"),
+    ("No project", "Kein Projekt"),
+    ("Terminal too small", "Terminal zu klein"),
+    ("{}×{}, needs {}×{}", "{}×{}, mindestens {}×{} nötig"),
+    ("New todo", "Neue Aufgabe"),
+    ("New subtask", "Neue Unteraufgabe"),
+    ("Edit todo", "Aufgabe bearbeiten"),
+    (
+        "Due (e.g. 2025-06-01, tomorrow, next monday, in 3 days, fri 5pm; empty to clear)",
+        "Fällig (z.B. 2025-06-01, tomorrow, next monday, in 3 days, fri 5pm; leer zum Entfernen)",
+    ),
+    (
+        "Snooze until (e.g. tomorrow, next week, fri 9am, 2025-06-01; empty to wake it)",
+        "Zurückstellen bis (z.B. tomorrow, next week, fri 9am, 2025-06-01; leer zum Zurückholen)",
+    ),
+    (
+        "Repeat (daily, weekly, monthly, every N days; empty to clear)",
+        "Wiederholen (daily, weekly, monthly, every N days; leer zum Entfernen)",
+    ),
+    ("New list name", "Name der neuen Liste"),
+    ("Rename list", "Liste umbenennen"),
+    ("Add tag (-tag to remove)", "Tag hinzufügen (-tag zum Entfernen)"),
+    ("Move to list (number or name)", "In Liste verschieben (Nummer oder Name)"),
+    ("Tag color and icon (e.g. red, 💼 or light blue 💼; empty to clear)", "Farbe und Symbol des Tags (z.B. red, 💼 oder light blue 💼; leer zum Entfernen)"),
+    ("Save filter as", "Filter speichern als"),
+    ("Command (Tab: complete, ↑/↓: history)", "Befehl (Tab: ergänzen, ↑/↓: Verlauf)"),
+    ("New passphrase", "Neue Passphrase"),
+    ("Repeat the passphrase", "Passphrase wiederholen"),
+    ("{}: {} (Press Enter to confirm, Esc to cancel)", "{}: {} (Enter: bestätigen, Esc: abbrechen)"),
+    ("/{} (Enter: keep filter, Esc: clear)", "/{} (Enter: Filter behalten, Esc: löschen)"),
+    ("Editing notes (Enter: new line, Esc: save)", "Notizen bearbeiten (Enter: neue Zeile, Esc: speichern)"),
+    (
+        "Press 'a' to add a new todo (use #tag to tag it), '/' to search",
+        "'a' fügt eine Aufgabe hinzu (#tag versieht sie mit einem Tag), '/' sucht",
+    ),
+    ("Input", "Eingabe"),
+    ("Input - {}", "Eingabe - {}"),
+    ("due {}", "fällig {}"),
+    ("starts {}", "ab {}"),
+    ("not a date yet", "noch kein Datum"),
+    ("until {}", "bis {}"),
+    ("that's already past", "das ist schon vorbei"),
+    // Unlocking an encrypted file
+    ("{} is encrypted.", "{} ist verschlüsselt."),
+    ("Passphrase: ", "Passphrase: "),
+    ("Enter: unlock   Esc: quit", "Enter: entsperren   Esc: beenden"),
+    ("Locked", "Gesperrt"),
+    // Git history
+    ("History (r: restore, J/K: scroll diff, Esc: close)", "Verlauf (r: wiederherstellen, J/K: Diff blättern, Esc: schließen)"),
+    ("Changes", "Änderungen"),
+    // Recovering from a broken file
+    ("A copy was saved as {}", "Eine Kopie wurde als {} gespeichert"),
+    ("Could not make a copy: {}", "Kopie fehlgeschlagen: {}"),
+    ("open the file in your editor", "die Datei im Editor öffnen"),
+    ("try loading again", "erneut laden"),
+    ("start fresh without this file", "ohne diese Datei neu anfangen"),
+    ("quit without changing anything", "beenden, ohne etwas zu ändern"),
+    ("Recovery", "Wiederherstellung"),
+    // Statistics
+    ("Statistics (Esc: close)", "Statistik (Esc: schließen)"),
+    ("Completed per day, last {} days", "Erledigt pro Tag, letzte {} Tage"),
+    ("Todos:", "Aufgaben:"),
+    ("Open:", "Offen:"),
+    ("Completion rate:", "Erledigungsquote:"),
+    ("Average open age:", "Mittleres Alter:"),
+    ("Done in {} days:", "Erledigt in {} Tagen:"),
+    ("Streak:", "Serie:"),
+    ("{} days", "{} Tage"),
+    ("{} ({} per day)", "{} ({} pro Tag)"),
+    ("{} days (longest {})", "{} Tage (längste {})"),
+    ("Overview", "Übersicht"),
+    ("{} todos", "{} Aufgaben"),
+    ("{} done", "{} erledigt"),
+    ("No tags yet", "Noch keine Tags"),
+    ("Tags", "Tags"),
+    ("Completions, last {} weeks (░ 1  ▒ 2-3  ▓ 4-5  █ 6+)", "Erledigt, letzte {} Wochen (░ 1  ▒ 2-3  ▓ 4-5  █ 6+)"),
+    ("Mo", "Mo"),
+    ("Tu", "Di"),
+    ("We", "Mi"),
+    ("Th", "Do"),
+    ("Fr", "Fr"),
+    ("Sa", "Sa"),
+    ("Su", "So"),
+    // The status bar
+    ("{} active", "{} offen"),
+    ("sort: ", "Sortierung: "),
+    (", by project", ", nach Projekt"),
+    ("{} break", "{} Pause"),
+    ("break over", "Pause vorbei"),
+    ("filter: {}", "Filter: {}"),
+    ("not saving", "speichert nicht"),
+    ("read-only", "schreibgeschützt"),
+    ("saving…", "speichert…"),
+    ("⟳ syncing", "⟳ synchronisiert"),
+    ("synced {}", "synchronisiert {}"),
+    ("sync failed", "Sync fehlgeschlagen"),
+    ("/{}: {} matches", "/{}: {} Treffer"),
+    ("{} completed hidden", "{} erledigte ausgeblendet"),
+    ("{} snoozed", "{} zurückgestellt"),
+    ("{} not started", "{} noch nicht begonnen"),
+    ("{} marked", "{} markiert"),
+    ("?: help", "?: Hilfe"),
+    ("Esc: dismiss", "Esc: ausblenden"),
+    ("NORMAL", "NORMAL"),
+    ("INSERT", "EINFÜGEN"),
+    ("SEARCH", "SUCHE"),
+    ("TAGS", "TAGS"),
+    ("PROJECTS", "PROJEKTE"),
+    ("FILTERS", "FILTER"),
+    ("CONFIRM", "BESTÄTIGEN"),
+    ("HELP", "HILFE"),
+    ("TRASH", "PAPIERKORB"),
+    ("ARCHIVE", "ARCHIV"),
+    ("STATS", "STATISTIK"),
+    ("BOARD", "BOARD"),
+    ("RECOVERY", "WIEDERHERSTELLUNG"),
+    ("LOCKED", "GESPERRT"),
+    ("WELCOME", "WILLKOMMEN"),
+    ("HISTORY", "VERLAUF"),
+    ("SNOOZED", "ZURÜCKGESTELLT"),
+    ("manual", "manuell"),
+    ("alphabetical", "alphabetisch"),
+    ("due date", "Fälligkeit"),
+    ("priority", "Priorität"),
+    ("newest first", "neueste zuerst"),
+    ("status", "Status"),
+    ("tags", "Tags"),
+    // Toasts
+    ("Saved", "Gespeichert"),
+    ("Deleted '{}'", "'{}' gelöscht"),
+    ("Deleted {} todos", "{} Aufgaben gelöscht"),
+    ("Snoozed '{}' until {}", "'{}' zurückgestellt bis {}"),
+    ("Moved to {}", "Verschoben nach {}"),
+    ("Someday", "Irgendwann"),
+    ("Deleted {} todo", "{} Aufgabe gelöscht"),
+    ("{} - press {} to undo", "{} - {} macht es rückgängig"),
+    ("Could not save the sync state: {}", "Sync-Stand konnte nicht gespeichert werden: {}"),
+    ("Synced with {}", "Mit {} synchronisiert"),
+    ("Synced with {}: {} change from there", "Mit {} synchronisiert: {} Änderung von dort"),
+    ("Synced with {}: {} changes from there", "Mit {} synchronisiert: {} Änderungen von dort"),
+    ("Sync failed: {}", "Synchronisierung fehlgeschlagen: {}"),
+    // Notices
+    ("Merged the changes made to {} elsewhere", "Änderungen an {} von anderswo übernommen"),
+    ("Reloaded {} - it changed on disk", "{} neu geladen - die Datei hat sich geändert"),
+    ("Kept your changes - they'll be saved over {}", "Änderungen behalten - sie werden über {} gespeichert"),
+    ("The other instance closed {} - changes are saved again", "Die andere Instanz hat {} geschlossen - Änderungen werden wieder gespeichert"),
+    ("{} is open in another instance{} - read-only", "{} ist in einer anderen Instanz geöffnet{} - nur lesen"),
+    ("Read-only - the file is open in another instance", "Nur lesen - die Datei ist in einer anderen Instanz geöffnet"),
+    ("Loaded {}", "{} geladen"),
+    (
+        "{} is saved encrypted now - a lost passphrase can't be recovered",
+        "{} wird jetzt verschlüsselt gespeichert - eine verlorene Passphrase lässt sich nicht wiederherstellen",
+    ),
+    ("could not encrypt: {}", "Verschlüsseln fehlgeschlagen: {}"),
+    ("{} is saved unencrypted now", "{} wird jetzt unverschlüsselt gespeichert"),
+    ("No completed todos to clear", "Keine erledigten Aufgaben zum Aufräumen"),
+    ("Archived {} completed todo (u: undo)", "{} erledigte Aufgabe archiviert (u: rückgängig)"),
+    ("Archived {} completed todos (u: undo)", "{} erledigte Aufgaben archiviert (u: rückgängig)"),
+    ("Moved {} completed todo to the trash (u: undo)", "{} erledigte Aufgabe in den Papierkorb verschoben (u: rückgängig)"),
+    ("Moved {} completed todos to the trash (u: undo)", "{} erledigte Aufgaben in den Papierkorb verschoben (u: rückgängig)"),
+    ("The first line was empty - todo not changed", "Die erste Zeile war leer - Aufgabe nicht geändert"),
+    ("Editor failed: {}", "Editor fehlgeschlagen: {}"),
+    ("Plugin failed: {}", "Plugin fehlgeschlagen: {}"),
+    ("The {} hook '{}' failed: {}", "Der {}-Hook '{}' ist fehlgeschlagen: {}"),
+    ("Exported to {}", "Exportiert nach {}"),
+    ("{}{} - :restore N to roll back", "{}{} - :restore N setzt zurück"),
+    ("Restored {} (u: undo)", "{} wiederhergestellt (u: rückgängig)"),
+    ("Version {} has no lists", "Version {} hat keine Listen"),
+    ("Restored the version from {} (u: undo)", "Version vom {} wiederhergestellt (u: rückgängig)"),
+    (
+        "Set \"todoist\", \"caldav\", \"google\", \"remote\", \"github\" or \"email\" in the config to sync",
+        "\"todoist\", \"caldav\", \"google\", \"remote\", \"github\" oder \"email\" in der Konfiguration setzen, um zu synchronisieren",
+    ),
+    ("Already syncing", "Synchronisierung läuft bereits"),
+    ("Not syncing - this session can't save the todo file", "Keine Synchronisierung - diese Sitzung kann die Aufgabendatei nicht speichern"),
+    ("Already fetching the issues", "Die Issues werden bereits geholt"),
+    ("Fetching the issues failed: {}", "Issues holen fehlgeschlagen: {}"),
+    ("Could not cache the issues: {}", "Issues konnten nicht zwischengespeichert werden: {}"),
+    ("{} open issue", "{} offenes Issue"),
+    ("{} open issues", "{} offene Issues"),
+    ("{} on GitHub", "{} auf GitHub"),
+    ("Closed {} on GitHub, {} left", "{} auf GitHub geschlossen, {} übrig"),
+    ("Already checking the mail", "Mails werden bereits abgerufen"),
+    ("Checking the mail failed: {}", "Mails abrufen fehlgeschlagen: {}"),
+    ("No new mail", "Keine neuen Mails"),
+    ("{} todo from the mail in {}", "{} Aufgabe aus den Mails in {}"),
+    ("{} todos from the mail in {}", "{} Aufgaben aus den Mails in {}"),
+    (
+        "{} changed elsewhere while your last changes waited to go up - the server's version is used, yours is kept as a snapshot (see `todo-tui restore`)",
+        "{} wurde anderswo geändert, während die letzten Änderungen auf das Hochladen warteten - die Version vom Server gilt, die eigene bleibt als Sicherung (siehe `todo-tui restore`)",
+    ),
+    (
+        "{} changed elsewhere while your last changes waited to go up, and they couldn't be kept as a snapshot ({}) - working on them offline",
+        "{} wurde anderswo geändert, während die letzten Änderungen auf das Hochladen warteten, und sie ließen sich nicht als Sicherung ablegen ({}) - offline wird mit ihnen weitergearbeitet",
+    ),
+    ("Couldn't reach {}: {} - working offline, the changes go up when you quit", "{} nicht erreichbar: {} - offline, die Änderungen werden beim Beenden hochgeladen"),
+    ("Couldn't download {}: {} - working offline on the copy from last time", "{} konnte nicht heruntergeladen werden: {} - offline mit der Kopie vom letzten Mal"),
+    ("Couldn't download {}: {} - starting empty, the todos go up when you quit", "{} konnte nicht heruntergeladen werden: {} - leerer Start, die Aufgaben werden beim Beenden hochgeladen"),
+    // Statuses, in the details and on the board
+    ("Todo", "Offen"),
+    ("In Progress", "In Arbeit"),
+    ("Blocked", "Blockiert"),
+    ("Done", "Erledigt"),
+    ("Cancelled", "Abgebrochen"),
+    // Table column titles
+    ("Status", "Status"),
+    ("Title", "Titel"),
+    ("Priority", "Priorität"),
+    ("Due", "Fällig"),
+    // The detail pane
+    ("Status:", "Status:"),
+    ("Priority:", "Priorität:"),
+    ("Due:", "Fällig:"),
+    ("Starts:", "Beginnt:"),
+    ("Repeats:", "Wiederholt:"),
+    ("Time:", "Zeit:"),
+    ("Focus:", "Fokus:"),
+    ("Project:", "Projekt:"),
+    ("Tags:", "Tags:"),
+    ("Subtasks:", "Unteraufg.:"),
+    ("Created:", "Erstellt:"),
+    ("Changed:", "Geändert:"),
+    ("Done:", "Erledigt:"),
+    ("History", "Verlauf"),
+    ("Text edited", "Text bearbeitet"),
+    ("Notes edited", "Notizen bearbeitet"),
+    ("Marked {}", "Als {} markiert"),
+    ("Priority {}", "Priorität {}"),
+    ("Starred", "Mit Stern markiert"),
+    ("Star taken away", "Stern entfernt"),
+    ("Due {}", "Fällig {}"),
+    ("Due date removed", "Fälligkeit entfernt"),
+    ("Repeats {}", "Wiederholt {}"),
+    ("No longer repeats", "Wiederholt sich nicht mehr"),
+    ("Tags changed", "Tags geändert"),
+    ("Moved to project {}", "In Projekt {} verschoben"),
+    ("Removed from its project", "Aus dem Projekt entfernt"),
+    ("None", "Keine"),
+    ("Low", "Niedrig"),
+    ("Medium", "Mittel"),
+    ("High", "Hoch"),
+    (" (tracking)", " (läuft)"),
+    ("1 pomodoro", "1 Pomodoro"),
+    ("{} pomodoros", "{} Pomodoros"),
+    ("{}/{} done", "{}/{} erledigt"),
+    ("No notes - press 'E' to add some", "Keine Notizen - 'E' fügt welche hinzu"),
+    ("Details (editing notes)", "Details (Notizen bearbeiten)"),
+    ("Details", "Details"),
+    // Help
+    ("Counts and sequences", "Zähler und Tastenfolgen"),
+    (
+        "A number in front of a key repeats it (on its own it switches list)",
+        "Eine Zahl vor einer Taste wiederholt sie (allein wechselt sie die Liste)",
+    ),
+    ("Jump to that row", "Zu dieser Zeile springen"),
+    ("Plugins", "Plugins"),
+    ("Commands (type after :)", "Befehle (nach : eingeben)"),
+    ("Help - {} keys (↑/↓: scroll, Esc: close)", "Hilfe - {}-Tasten (↑/↓: blättern, Esc: schließen)"),
+    // Popups
+    ("y: yes   n/Esc: no", "y: ja   n/Esc: nein"),
+    ("Confirm", "Bestätigen"),
+    ("Welcome to todo-tui", "Willkommen bei todo-tui"),
+    ("Press any key to start - this only shows once", "Eine beliebige Taste startet - dies erscheint nur einmal"),
+    ("Daily review ({} left)", "Tagesrückblick (noch {})"),
+    ("Was due {} on {}", "War fällig {} in {}"),
+    (
+        "t: today   d: another day   c: done   x: drop   s: skip   Esc: later",
+        "t: heute   d: anderer Tag   c: erledigt   x: verwerfen   s: überspringen   Esc: später",
+    ),
+    ("All caught up", "Alles aufgeholt"),
+    ("Todo due", "Aufgabe fällig"),
+    ("Copy failed: {}", "Kopieren fehlgeschlagen: {}"),
+    ("Desktop notification failed: {}", "Desktop-Benachrichtigung fehlgeschlagen: {}"),
+    ("Delete list '{}' with {} todos?", "Liste '{}' mit {} Aufgaben löschen?"),
+    ("Delete '{}' permanently?", "'{}' endgültig löschen?"),
+    ("Permanently delete all {} todos in the trash?", "Alle {} Aufgaben im Papierkorb endgültig löschen?"),
+    ("Archive 1 completed todo?", "1 erledigte Aufgabe archivieren?"),
+    ("Archive {} completed todos?", "{} erledigte Aufgaben archivieren?"),
+    ("Move 1 completed todo to the trash?", "1 erledigte Aufgabe in den Papierkorb verschieben?"),
+    ("Move {} completed todos to the trash?", "{} erledigte Aufgaben in den Papierkorb verschieben?"),
+    ("{} changed on disk. Reload it and drop your unsaved changes?", "{} wurde geändert. Neu laden und ungespeicherte Änderungen verwerfen?"),
+    ("(all todos)", "(alle Aufgaben)"),
+    ("Filter by tag (Enter: select, c: color and icon, Esc: close)", "Nach Tag filtern (Enter: auswählen, c: Farbe und Symbol, Esc: schließen)"),
+    (
+        "Saved filters (Enter/0-9: select, s: save current, d: delete, Esc: close)",
+        "Gespeicherte Filter (Enter/0-9: auswählen, s: aktuellen speichern, d: löschen, Esc: schließen)",
+    ),
+    ("Jump to project (Enter: select, Esc: close)", "Zu Projekt springen (Enter: auswählen, Esc: schließen)"),
+    ("Archive (r: restore, Esc: close)", "Archiv (r: wiederherstellen, Esc: schließen)"),
+    ("Nothing archived yet", "Noch nichts archiviert"),
+    (" (+{} subtasks)", " (+{} Unteraufgaben)"),
+    ("Trash (r: restore, d: delete, X: empty, Esc: close)", "Papierkorb (r: wiederherstellen, d: löschen, X: leeren, Esc: schließen)"),
+    ("The trash is empty", "Der Papierkorb ist leer"),
+    ("Snoozed (r: wake up, u: undo, Esc: close)", "Zurückgestellt (r: zurückholen, u: rückgängig, Esc: schließen)"),
+    ("Nothing is snoozed", "Nichts ist zurückgestellt"),
+    // Help sections and what the keys do
+    ("Navigation", "Navigation"),
+    ("Editing", "Bearbeiten"),
+    ("Bulk", "Mehrere"),
+    ("Archive", "Archiv"),
+    ("Filtering", "Filtern"),
+    ("Lists", "Listen"),
+    ("General", "Allgemein"),
+    ("Move selection down", "Auswahl nach unten"),
+    ("Move selection up", "Auswahl nach oben"),
+    ("Page down", "Seite nach unten"),
+    ("Page up", "Seite nach oben"),
+    ("Half a page down", "Halbe Seite nach unten"),
+    ("Half a page up", "Halbe Seite nach oben"),
+    ("Jump to first todo", "Zur ersten Aufgabe"),
+    ("Jump to first todo (with a count: to that row)", "Zur ersten Aufgabe (mit Zähler: zu dieser Zeile)"),
+    ("Jump to last todo", "Zur letzten Aufgabe"),
+    ("Expand subtasks", "Unteraufgaben aufklappen"),
+    ("Collapse subtasks / go to parent", "Unteraufgaben zuklappen / zur übergeordneten"),
+    ("Show or hide the detail pane", "Details ein- oder ausblenden"),
+    ("Wrap long todos or cut them off", "Lange Aufgaben umbrechen oder abschneiden"),
+    ("Show the list as a table (1-5: sort by column)", "Liste als Tabelle anzeigen (1-5: nach Spalte sortieren)"),
+    ("Jump to a project", "Zu einem Projekt springen"),
+    ("Show statistics", "Statistik anzeigen"),
+    ("Show the board (h/l: move card, Tab: next column)", "Board anzeigen (h/l: Karte verschieben, Tab: nächste Spalte)"),
+    ("Add a todo (#tags are parsed)", "Aufgabe hinzufügen (#tags werden erkannt)"),
+    ("Add a subtask to the selected todo", "Unteraufgabe zur ausgewählten Aufgabe hinzufügen"),
+    ("Edit the selected todo", "Ausgewählte Aufgabe bearbeiten"),
+    ("Edit notes", "Notizen bearbeiten"),
+    ("Open the todo and its notes in $EDITOR", "Aufgabe und Notizen in $EDITOR öffnen"),
+    ("Toggle completion", "Erledigt umschalten"),
+    ("Cycle priority", "Priorität wechseln"),
+    ("Star the todo, which keeps it on top, or unstar it", "Aufgabe mit Stern oben halten oder den Stern entfernen"),
+    ("Cycle status (todo, in progress, blocked, done, cancelled)", "Status wechseln (offen, in Arbeit, blockiert, erledigt, abgebrochen)"),
+    ("Set due date", "Fälligkeit setzen"),
+    ("Set recurrence", "Wiederholung setzen"),
+    ("Snooze the todo, hiding it until a date and time", "Aufgabe bis zu einem Datum und einer Uhrzeit zurückstellen"),
+    ("Show the snoozed todos (r: wake up)", "Zurückgestellte Aufgaben zeigen (r: zurückholen)"),
+    ("Show or hide todos whose start date is still ahead", "Aufgaben mit künftigem Startdatum zeigen oder ausblenden"),
+    (
+        "Park the todo on the someday list, or bring it back from there",
+        "Aufgabe auf der Irgendwann-Liste parken oder von dort zurückholen",
+    ),
+    ("Move todo down", "Aufgabe nach unten verschieben"),
+    ("Move todo up", "Aufgabe nach oben verschieben"),
+    ("Start a 25/5 pomodoro on the todo, or stop it", "25/5-Pomodoro für die Aufgabe starten oder stoppen"),
+    ("Start / stop tracking time on the todo", "Zeiterfassung für die Aufgabe starten / stoppen"),
+    ("Copy the todo's text to the clipboard", "Text der Aufgabe in die Zwischenablage kopieren"),
+    ("Move the selected todo to the trash", "Ausgewählte Aufgabe in den Papierkorb verschieben"),
+    ("Move all completed todos to the trash", "Alle erledigten Aufgaben in den Papierkorb verschieben"),
+    ("Repeat the last change", "Letzte Änderung wiederholen"),
+    ("Undo", "Rückgängig"),
+    ("Redo", "Wiederholen"),
+    ("Show the trash (r: restore, d: delete, X: empty)", "Papierkorb anzeigen (r: wiederherstellen, d: löschen, X: leeren)"),
+    ("Mark / unmark the todo for a bulk operation", "Aufgabe für eine Sammelaktion markieren / demarkieren"),
+    ("Add a tag to the marked or selected todos", "Markierten oder ausgewählten Aufgaben einen Tag geben"),
+    ("Move the marked or selected todos to another list", "Markierte oder ausgewählte Aufgaben in eine andere Liste verschieben"),
+    ("Archive the selected todo", "Ausgewählte Aufgabe archivieren"),
+    ("Archive all completed todos", "Alle erledigten Aufgaben archivieren"),
+    ("Browse the archive (r: restore)", "Archiv durchsuchen (r: wiederherstellen)"),
+    ("Search as you type", "Suchen während der Eingabe"),
+    ("Next match", "Nächster Treffer"),
+    ("Previous match", "Vorheriger Treffer"),
+    ("Hide or show completed todos", "Erledigte Aufgaben aus- oder einblenden"),
+    ("Filter by tag", "Nach Tag filtern"),
+    ("Pick a saved filter (0-9: pick, s: save current, d: delete)", "Gespeicherten Filter wählen (0-9: wählen, s: aktuellen speichern, d: löschen)"),
+    ("Cycle sort order (manual, A-Z, due, priority, newest, status, tags)", "Sortierung wechseln (manuell, A-Z, fällig, Priorität, neueste, Status, Tags)"),
+    ("Clear marks, then search, then tag filter", "Markierungen, dann Suche, dann Tag-Filter aufheben"),
+    ("Switch to list by number", "Zur Liste mit dieser Nummer wechseln"),
+    ("Next list", "Nächste Liste"),
+    ("Previous list", "Vorherige Liste"),
+    ("Create a list", "Liste anlegen"),
+    ("Rename the current list", "Aktuelle Liste umbenennen"),
+    ("Delete the current list", "Aktuelle Liste löschen"),
+    ("Enter a command (see Commands below)", "Befehl eingeben (siehe Befehle unten)"),
+    ("Cycle color theme", "Farbschema wechseln"),
+    ("Sync, fetch the GitHub issues and check the mail now", "Jetzt synchronisieren, GitHub-Issues holen und Mails abrufen"),
+    ("Show this help", "Diese Hilfe anzeigen"),
+    ("Group the list by project", "Liste nach Projekt gruppieren"),
+    // Commands
+    ("Filter with a query, or remove the query", "Mit einer Abfrage filtern oder die Abfrage entfernen"),
+    ("Sort the list", "Liste sortieren"),
+    ("Group the list by project, or stop grouping", "Liste nach Projekt gruppieren oder Gruppierung aufheben"),
+    ("Archive the selected todo, or all completed ones", "Ausgewählte Aufgabe oder alle erledigten archivieren"),
+    ("Move all completed todos to the trash, or archive them", "Alle erledigten Aufgaben in den Papierkorb verschieben oder archivieren"),
+    ("Switch to a list by number or name", "Zu einer Liste nach Nummer oder Name wechseln"),
+    (
+        "Encrypt the todo file and archive with a passphrase, or change it",
+        "Aufgabendatei und Archiv mit einer Passphrase verschlüsseln oder sie ändern",
+    ),
+    ("Save the todo file and archive unencrypted again", "Aufgabendatei und Archiv wieder unverschlüsselt speichern"),
+    ("Write all lists to a file, todo-export.EXT by default", "Alle Listen in eine Datei schreiben, standardmäßig todo-export.EXT"),
+    (
+        "Roll back to snapshot N (1 is the newest), or list the snapshots",
+        "Auf Sicherung N zurücksetzen (1 ist die neueste) oder die Sicherungen auflisten",
+    ),
+    ("Browse the versions of the todo file committed to git", "In git gespeicherte Versionen der Aufgabendatei durchsuchen"),
+    ("Sync the lists, fetch the GitHub issues and check the mail now", "Listen jetzt synchronisieren, GitHub-Issues holen und Mails abrufen"),
+    ("Switch to a color theme", "Zu einem Farbschema wechseln"),
+    ("Show keys and commands", "Tasten und Befehle anzeigen"),
+    ("Quit", "Beenden"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_without_a_translation_stays_english() {
+        assert_eq!(Language::German.translate("Next list"), "Nächste Liste");
+        assert_eq!(Language::German.translate("not in any table"), "not in any table");
+        assert_eq!(Language::English.translate("Next list"), "Next list");
+        assert_eq!(fill(Language::German.translate("Delete list '{}' with {} todos?"), &[&"Work", &3]), "Liste 'Work' mit 3 Aufgaben löschen?");
+        // Every English text is in the table once, or a later one would silently win
+        let mut seen = std::collections::HashSet::new();
+        assert!(GERMAN.iter().all(|(english, _)| seen.insert(english)));
+    }
+
+    /// Each source file of the crate with its tests cut off, by name
+    fn sources() -> Vec<(String, String)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut sources = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "rs") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                let code = source.split("#[cfg(test)]\nmod tests").next().unwrap_or_default().to_string();
+                sources.push((path.file_name().unwrap().to_string_lossy().into_owned(), code));
+            }
+        }
+        sources
+    }
+
+    /// The string literals at the start of `code`, like the keys of a call
+    /// to `tr`, `trf` or `trn`
+    fn keys(code: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut rest = code.trim_start();
+        while let Some(literal) = rest.strip_prefix('"') {
+            let mut key = String::new();
+            let mut chars = literal.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\\')) => key.extend(chars.next().map(|(_, c)| c)),
+                    Some((i, '"')) => break i + 1,
+                    Some((_, c)) => key.push(c),
+                    None => return keys,
+                }
+            };
+            keys.push(key);
+            rest = literal[end..].trim_start().trim_start_matches(',').trim_start();
+        }
+        keys
+    }
+
+    #[test]
+    fn notices_and_toasts_are_translated() {
+        let german: HashMap<&str, &str> = GERMAN.iter().copied().collect();
+        for (name, code) in sources() {
+            for (number, line) in code.lines().enumerate() {
+                // Text written straight into a notice or toast shows in
+                // English whatever the language
+                let shown = match (line.find("notice = Some("), line.find("toast(ToastKind::")) {
+                    (Some(at), _) => Some(&line[at + "notice = Some(".len()..]),
+                    (_, Some(at)) => line[at..].split_once(", ").map(|(_, text)| text),
+                    _ => None,
+                };
+                if let Some(shown) = shown {
+                    assert!(!shown.starts_with('"') && !shown.starts_with("format!"), "{}:{} bypasses tr: {}", name, number + 1, line.trim());
+                }
+            }
+            // And what goes through it has a translation
+            for call in [".tr(", ".trf(", ".trn("] {
+                for (at, _) in code.match_indices(call) {
+                    for key in keys(&code[at + call.len()..]) {
+                        assert!(german.contains_key(key.as_str()), "{} has no German for {:?}", name, key);
+                    }
+                }
+            }
+        }
+    }
+}
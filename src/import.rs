@@ -0,0 +1,669 @@
+//! Importing todos exported from other apps: Todoist, Taskwarrior and todo.txt
+//! Each format is mapped onto our fields as far as it goes - priorities,
+//! projects, labels and tags, due dates and repeats, notes and subtasks -
+//! and the rest is left behind rather than guessed at
+
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    app::{parse_due, App, Command, Date, Priority, Recurrence, Status, Time, Timestamp, TodoItem, TodoList},
+    storage::parse_todo_txt,
+};
+
+/// Formats `todo-tui import` understands
+pub(crate) const IMPORT_FORMATS: [&str; 3] = ["todoist", "taskwarrior", "todotxt"];
+
+/// Todos read from an export, with the list they belong in
+struct Imported {
+    /// Name of the project in the other app, None when it has no notion of
+    /// one and the todos go to the current list
+    list: Option<String>,
+    todos: Vec<TodoItem>,
+}
+
+impl App {
+    /// Adds the todos from the file at `path`, exported from another app in
+    /// `format`, as one change
+    /// Todos go to the list named like their project in the other app, which
+    /// is created if there's none, and to the current list when the format
+    /// has no projects or `into_current` is set
+    /// Returns a line saying how many were imported and where
+    pub fn import(&mut self, format: &str, path: &Path, into_current: bool) -> Result<String, String> {
+        let kind = match format.to_lowercase().as_str() {
+            "todoist" => "todoist",
+            "taskwarrior" | "task" => "taskwarrior",
+            "todotxt" | "todo.txt" | "txt" => "todotxt",
+            _ => return Err(format!("unknown format '{}' (expected {})", format, IMPORT_FORMATS.join(", "))),
+        };
+        let contents = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let imported = match kind {
+            // Both exports come out of Todoist, so the contents tell them apart
+            "todoist" if contents.trim_start().starts_with(['{', '[']) => parse_todoist_json(&contents)?,
+            "todoist" => parse_todoist_csv(&contents, &name)?,
+            "taskwarrior" => parse_taskwarrior(&contents)?,
+            _ => parse_todo_txt(&contents)
+                .into_iter()
+                .enumerate()
+                // The first list is the lines without a `list:` key
+                .map(|(i, list)| Imported { list: (i > 0).then_some(list.name), todos: list.todos })
+                .collect(),
+        };
+        let count: usize = imported.iter().map(|group| count_todos(&group.todos)).sum();
+        if count == 0 {
+            return Err(format!("found no todos in {}", path.display()));
+        }
+
+        // Every insert goes after what's already there, so the positions are
+        // worked out up front and the batch applies in one go
+        let mut lengths: Vec<usize> = self.lists.iter().map(|list| list.todos.len()).collect();
+        let mut names: Vec<String> = self.lists.iter().map(|list| list.name.clone()).collect();
+        let mut commands = Vec::new();
+        let mut touched = Vec::new();
+        for group in imported.into_iter().filter(|group| !group.todos.is_empty()) {
+            let list = match group.list.filter(|_| !into_current) {
+                None => self.current,
+                Some(name) => match names.iter().position(|n| n.eq_ignore_ascii_case(&name)) {
+                    Some(i) => i,
+                    None => {
+                        commands.push(Command::InsertList { index: names.len(), list: TodoList::new(&name) });
+                        names.push(name);
+                        lengths.push(0);
+                        names.len() - 1
+                    }
+                },
+            };
+            for item in group.todos {
+                commands.push(Command::Insert { list, path: vec![lengths[list]], item });
+                lengths[list] += 1;
+            }
+            if !touched.contains(&list) {
+                touched.push(list);
+            }
+        }
+        Command::Batch(commands).apply(&mut self.lists, &mut self.archive);
+
+        let lists: Vec<&str> = touched.iter().map(|&i| names[i].as_str()).collect();
+        let lists = match lists.as_slice() {
+            [.., last] if lists.len() > 1 => format!("{} and {}", lists[..lists.len() - 1].join(", "), last),
+            _ => lists.join(""),
+        };
+        let summary = format!("Imported {} todo{} into {}", count, if count == 1 { "" } else { "s" }, lists);
+        self.changes.push(format!("{} from {}", summary, path.file_name().unwrap_or_default().to_string_lossy()));
+        Ok(summary)
+    }
+}
+
+/// Number of todos in a tree, subtasks included
+fn count_todos(todos: &[TodoItem]) -> usize {
+    todos.iter().map(|todo| 1 + count_todos(&todo.children)).sum()
+}
+
+/// Splits CSV into rows of fields, with quoted fields that can hold commas,
+/// doubled quotes and line breaks
+fn parse_csv(contents: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    // Spreadsheet apps like to start the file with a byte order mark
+    let mut chars = contents.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Parses the date-times other apps write - `2024-05-01`, a floating
+/// `2024-05-01T17:00:00`, UTC `2024-05-01T17:00:00.000000Z`, Taskwarrior's
+/// `20240501T170000Z` or seconds since 1970 - into a moment
+/// Dates and floating times are taken as local
+pub(crate) fn parse_timestamp(s: &str) -> Option<Timestamp> {
+    let s = s.trim();
+    if s.len() > 8 && s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().ok().map(Timestamp);
+    }
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let date = if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+        Date::new(date[..4].parse().ok()?, date[4..6].parse().ok()?, date[6..].parse().ok()?)?
+    } else {
+        Date::parse(date)?
+    };
+    let Some(time) = time else {
+        return Some(Timestamp::from_date(date));
+    };
+    let (time, utc) = match time.strip_suffix('Z') {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    // Fractions of a second don't matter, offsets like +02:00 aren't supported
+    let time = time.split('.').next().unwrap_or(time).replace(':', "");
+    if !(4..=6).contains(&time.len()) || !time.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hour, minute, second) = (&time[..2], &time[2..4], time.get(4..).filter(|s| !s.is_empty()).unwrap_or("00"));
+    let at = Timestamp::parse(&format!("{}T{}:{}:{}Z", date, hour, minute, second))?;
+    Some(if utc { at } else { Timestamp(Timestamp::from_date(date).0 + at.0.rem_euclid(86_400)) })
+}
+
+/// A due date from an export, with its time of day unless it's due at
+/// midnight or Taskwarrior's end of day, both of which mean the whole day
+fn parse_due_moment(s: &str) -> Option<(Date, Option<Time>)> {
+    let at = parse_timestamp(s)?;
+    let time = at.time();
+    let whole_day = time == Time { hour: 0, minute: 0 } || time == Time { hour: 23, minute: 59 };
+    Some((at.date(), (!whole_day).then_some(time)))
+}
+
+/// Text with Todoist's `@label` words taken out as tags
+fn split_labels(content: &str, tags: &mut Vec<String>) -> String {
+    let mut words = Vec::new();
+    for word in content.split_whitespace() {
+        match word.strip_prefix('@').filter(|label| !label.is_empty()) {
+            Some(label) if !tags.iter().any(|tag| tag == label) => tags.push(label.to_string()),
+            Some(_) => {}
+            None => words.push(word),
+        }
+    }
+    words.join(" ")
+}
+
+/// Sets a todo's due date from what Todoist shows for it: a date, a phrase
+/// like "tomorrow 5pm", or a repeat like "every week"
+/// Anything we can't follow is kept in the notes so it isn't lost
+fn apply_todoist_date(todo: &mut TodoItem, date: &str) {
+    let date = date.trim();
+    if date.is_empty() {
+        return;
+    }
+    if let Some(rule) = date.to_lowercase().starts_with("every").then(|| Recurrence::parse(date)).flatten() {
+        todo.recurrence = Some(rule);
+        todo.due.get_or_insert_with(Date::today);
+    } else if let Some((due, time)) = parse_due(date, Date::today()) {
+        todo.due = Some(due);
+        todo.due_time = time;
+    } else {
+        if !todo.notes.is_empty() {
+            todo.notes.push('\n');
+        }
+        todo.notes.push_str(&format!("Due: {}", date));
+    }
+}
+
+/// Priority from Todoist's p1 (most urgent) to p4 (none)
+fn todoist_priority(level: u64) -> Priority {
+    match level {
+        1 => Priority::High,
+        2 => Priority::Medium,
+        3 => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+/// Adds `todo` under the last todo `depth - 1` levels down, or as deep as
+/// the tree goes
+fn push_at_depth(todos: &mut Vec<TodoItem>, depth: usize, todo: TodoItem) {
+    match todos.last_mut() {
+        Some(last) if depth > 1 => push_at_depth(&mut last.children, depth - 1, todo),
+        _ => todos.push(todo),
+    }
+}
+
+/// The todo added last by `push_at_depth`, which is always the end of the
+/// last branch
+fn last_leaf(todos: &mut [TodoItem]) -> Option<&mut TodoItem> {
+    let last = todos.last_mut()?;
+    if last.children.is_empty() {
+        return Some(last);
+    }
+    last_leaf(&mut last.children)
+}
+
+/// Parses a Todoist project exported as CSV, which is named after the project
+/// Sections become our projects, indented tasks subtasks, and notes (comments)
+/// are added to the notes of the task above them
+fn parse_todoist_csv(contents: &str, name: &str) -> Result<Vec<Imported>, String> {
+    let mut rows = parse_csv(contents).into_iter().filter(|row| row.iter().any(|field| !field.trim().is_empty()));
+    let header = rows.next().ok_or("the file is empty")?;
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (Some(kind), Some(content)) = (column("TYPE"), column("CONTENT")) else {
+        return Err("not a Todoist CSV export - there are no TYPE and CONTENT columns".to_string());
+    };
+    let (description, priority, indent, date) = (column("DESCRIPTION"), column("PRIORITY"), column("INDENT"), column("DATE"));
+
+    let mut todos = Vec::new();
+    let mut section = None;
+    for row in rows {
+        let field = |column: Option<usize>| column.and_then(|i| row.get(i)).map_or("", |field| field.trim());
+        match field(Some(kind)) {
+            "section" => section = Some(field(Some(content)).to_string()).filter(|name| !name.is_empty()),
+            "note" => {
+                if let Some(todo) = last_leaf(&mut todos) {
+                    if !todo.notes.is_empty() {
+                        todo.notes.push_str("\n\n");
+                    }
+                    todo.notes.push_str(field(Some(content)));
+                }
+            }
+            "task" => {
+                let mut todo = TodoItem::new("");
+                todo.text = split_labels(field(Some(content)), &mut todo.tags);
+                todo.notes = field(description).to_string();
+                todo.priority = todoist_priority(field(priority).parse().unwrap_or(4));
+                todo.project = section.clone();
+                apply_todoist_date(&mut todo, field(date));
+                push_at_depth(&mut todos, field(indent).parse().unwrap_or(1), todo);
+            }
+            _ => {}
+        }
+    }
+    Ok(vec![Imported { list: Some(name.to_string()), todos }])
+}
+
+/// An id as Todoist writes it - a string in newer APIs, a number in older ones
+pub(crate) fn todoist_id(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// A Todoist task from the API as a todo: content and labels, description,
+/// priority, due date and repeat, and whether it's checked off
+pub(crate) fn todoist_todo(task: &Value) -> TodoItem {
+    let text = |key: &str| task.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let mut todo = TodoItem::new("");
+    let mut tags: Vec<String> =
+        task.get("labels").and_then(Value::as_array).into_iter().flatten().filter_map(|l| Some(l.as_str()?.to_string())).collect();
+    todo.text = split_labels(&text("content"), &mut tags);
+    todo.tags = tags;
+    todo.notes = text("description");
+    // The API counts the other way round from the app: 4 is p1
+    todo.priority = todoist_priority(5 - task.get("priority").and_then(Value::as_u64).unwrap_or(1).clamp(1, 4));
+    if let Some(due) = task.get("due").filter(|due| due.is_object()) {
+        let at = due.get("datetime").or_else(|| due.get("date")).and_then(Value::as_str).unwrap_or_default();
+        if let Some((date, time)) = parse_due_moment(at) {
+            todo.due = Some(date);
+            todo.due_time = time;
+        }
+        if due.get("is_recurring").and_then(Value::as_bool) == Some(true) {
+            todo.recurrence = due.get("string").and_then(Value::as_str).and_then(Recurrence::parse);
+        }
+    }
+    if let Some(at) = ["added_at", "created_at"].iter().find_map(|key| parse_timestamp(task.get(key)?.as_str()?)) {
+        todo.created_at = Some(at);
+    }
+    if ["checked", "is_completed"].iter().any(|key| task.get(key).and_then(Value::as_bool) == Some(true)) {
+        todo.status = Status::Done;
+        todo.completed_at =
+            Some(task.get("completed_at").and_then(Value::as_str).and_then(parse_timestamp).unwrap_or_else(Timestamp::now));
+    }
+    todo
+}
+
+/// Where a Todoist task goes among its siblings
+pub(crate) fn todoist_order(task: &Value) -> i64 {
+    ["child_order", "order"].iter().find_map(|key| task.get(key)?.as_i64()).unwrap_or(0)
+}
+
+/// Parses Todoist tasks as JSON: the array the REST API returns, or a
+/// backup or sync response with `items` alongside `projects` and `sections`
+/// Projects become lists when their names are in the file, sections our projects
+fn parse_todoist_json(contents: &str) -> Result<Vec<Imported>, String> {
+    let value: Value = serde_json::from_str(contents).map_err(|err| format!("not valid JSON: {}", err))?;
+    let tasks = match &value {
+        Value::Array(tasks) => tasks.as_slice(),
+        _ => ["items", "tasks", "results"]
+            .iter()
+            .find_map(|key| value.get(key)?.as_array())
+            .map(Vec::as_slice)
+            .ok_or("no tasks found - expected an array of tasks, or \"items\" as in a sync response")?,
+    };
+    let names = |key: &str| -> HashMap<String, String> {
+        value.get(key).and_then(Value::as_array).map_or_else(HashMap::new, |entries| {
+            entries
+                .iter()
+                .filter_map(|entry| Some((todoist_id(entry.get("id"))?, entry.get("name")?.as_str()?.to_string())))
+                .collect()
+        })
+    };
+    let (projects, sections) = (names("projects"), names("sections"));
+
+    struct Task {
+        id: Option<String>,
+        parent: Option<String>,
+        project: Option<String>,
+        todo: Option<TodoItem>,
+    }
+    let mut entries: Vec<(i64, Task)> = tasks
+        .iter()
+        .filter(|task| task.get("is_deleted").and_then(Value::as_bool) != Some(true))
+        .map(|task| {
+            let mut todo = todoist_todo(task);
+            todo.project = todoist_id(task.get("section_id")).and_then(|id| sections.get(&id).cloned());
+            let order = todoist_order(task);
+            let task = Task {
+                id: todoist_id(task.get("id")),
+                parent: todoist_id(task.get("parent_id")),
+                project: todoist_id(task.get("project_id")),
+                todo: Some(todo),
+            };
+            (order, task)
+        })
+        .collect();
+    // Stable, so tasks without an order keep the order of the file
+    entries.sort_by_key(|(order, _)| *order);
+    let mut tasks: Vec<Task> = entries.into_iter().map(|(_, task)| task).collect();
+
+    // Subtasks point at their parent, so the tree is put together from the ids
+    let index: HashMap<String, usize> =
+        tasks.iter().enumerate().filter_map(|(i, task)| Some((task.id.clone()?, i))).collect();
+    let mut children = vec![Vec::new(); tasks.len()];
+    let mut roots = Vec::new();
+    for (i, task) in tasks.iter().enumerate() {
+        match task.parent.as_ref().and_then(|parent| index.get(parent)) {
+            Some(&parent) if parent != i => children[parent].push(i),
+            _ => roots.push(i),
+        }
+    }
+    fn build(i: usize, tasks: &mut [Task], children: &[Vec<usize>]) -> Option<TodoItem> {
+        let mut todo = tasks[i].todo.take()?;
+        todo.children = children[i].iter().filter_map(|&child| build(child, tasks, children)).collect();
+        Some(todo)
+    }
+
+    let mut imported: Vec<Imported> = Vec::new();
+    for i in roots {
+        let list = tasks[i].project.as_ref().and_then(|id| projects.get(id).cloned());
+        let Some(todo) = build(i, &mut tasks, &children) else {
+            continue;
+        };
+        match imported.iter_mut().find(|group| group.list == list) {
+            Some(group) => group.todos.push(todo),
+            None => imported.push(Imported { list, todos: vec![todo] }),
+        }
+    }
+    Ok(imported)
+}
+
+/// Parses Taskwarrior tasks, either its own `pending.data` / `completed.data`
+/// or the JSON `task export` prints
+/// Everything goes into one list, with Taskwarrior's projects as our projects
+fn parse_taskwarrior(contents: &str) -> Result<Vec<Imported>, String> {
+    let trimmed = contents.trim_start();
+    let is_data = trimmed.starts_with('[') && !trimmed[1..].trim_start().starts_with(['{', ']']);
+    let tasks: Vec<BTreeMap<String, String>> = if is_data {
+        contents.lines().filter(|line| !line.trim().is_empty()).map(parse_taskwarrior_line).collect::<Result<_, _>>()?
+    } else {
+        // Older versions print one object per line instead of an array
+        let objects: Vec<Value> = if trimmed.starts_with('[') {
+            serde_json::from_str(contents).map_err(|err| format!("not valid JSON: {}", err))?
+        } else {
+            contents
+                .lines()
+                .map(|line| line.trim().trim_end_matches(','))
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_str(line).map_err(|err| format!("not valid JSON: {}", err)))
+                .collect::<Result<_, _>>()?
+        };
+        objects.iter().map(flatten_taskwarrior_json).collect()
+    };
+
+    // Dependencies only block a todo while they're still open
+    let open: HashSet<&str> = tasks
+        .iter()
+        .filter(|task| matches!(task.get("status").map(String::as_str), Some("pending" | "waiting")))
+        .filter_map(|task| task.get("uuid").map(String::as_str))
+        .collect();
+    let mut todos = Vec::new();
+    let mut repeating = HashSet::new();
+    for task in &tasks {
+        // Deleted tasks are gone, and a repeating task is stored as a
+        // template plus its instances - the first instance is enough
+        if matches!(task.get("status").map(String::as_str), Some("deleted" | "recurring")) {
+            continue;
+        }
+        if let Some(parent) = task.get("parent")
+            && !repeating.insert(parent.as_str())
+        {
+            continue;
+        }
+        todos.push(taskwarrior_todo(task, &open));
+    }
+    Ok(vec![Imported { list: None, todos }])
+}
+
+/// Parses one line of Taskwarrior's data files:
+/// `[description:"Buy milk" status:"pending" tags:"errands,home"]`
+fn parse_taskwarrior_line(line: &str) -> Result<BTreeMap<String, String>, String> {
+    let error = || format!("not a Taskwarrior task: {}", line.trim());
+    let mut rest = line.trim().strip_prefix('[').and_then(|line| line.strip_suffix(']')).ok_or_else(error)?;
+    let mut task = BTreeMap::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(task);
+        }
+        let (key, after) = rest.split_once(":\"").ok_or_else(error)?;
+        let mut value = String::new();
+        let mut chars = after.char_indices();
+        let end = loop {
+            match chars.next().ok_or_else(error)? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next().ok_or_else(error)?.1 {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        // Older versions escaped brackets and quotes as entities instead
+        let value = value.replace("&open;", "[").replace("&close;", "]").replace("&dquot;", "\"");
+        task.insert(key.to_string(), value);
+        rest = &after[end + 1..];
+    }
+}
+
+/// Turns a task from `task export` into the attributes the data files hold,
+/// so both are read the same way
+fn flatten_taskwarrior_json(object: &Value) -> BTreeMap<String, String> {
+    let mut task = BTreeMap::new();
+    for (key, value) in object.as_object().into_iter().flatten() {
+        match (key.as_str(), value) {
+            ("annotations", Value::Array(annotations)) => {
+                for annotation in annotations {
+                    let entry = annotation.get("entry").and_then(Value::as_str).unwrap_or_default();
+                    let text = annotation.get("description").and_then(Value::as_str).unwrap_or_default();
+                    task.insert(format!("annotation_{}", entry), text.to_string());
+                }
+            }
+            (_, Value::Array(values)) => {
+                let values: Vec<String> = values.iter().map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string)).collect();
+                task.insert(key.clone(), values.join(","));
+            }
+            (_, Value::String(value)) => {
+                task.insert(key.clone(), value.clone());
+            }
+            (_, value) => {
+                task.insert(key.clone(), value.to_string());
+            }
+        }
+    }
+    task
+}
+
+/// Maps a Taskwarrior task's attributes onto a todo
+fn taskwarrior_todo(task: &BTreeMap<String, String>, open: &HashSet<&str>) -> TodoItem {
+    let get = |key: &str| task.get(key).map(String::as_str).filter(|value| !value.is_empty());
+    let mut todo = TodoItem::new(get("description").unwrap_or_default());
+    todo.status = match get("status") {
+        Some("completed") => Status::Done,
+        _ if get("start").is_some() => Status::InProgress,
+        _ if get("depends").is_some_and(|depends| depends.split(',').any(|uuid| open.contains(uuid))) => Status::Blocked,
+        _ => Status::Pending,
+    };
+    todo.priority = match get("priority") {
+        Some("H") => Priority::High,
+        Some("M") => Priority::Medium,
+        Some("L") => Priority::Low,
+        _ => Priority::None,
+    };
+    todo.project = get("project").map(str::to_string);
+    todo.tags = get("tags").into_iter().flat_map(|tags| tags.split(',')).filter(|tag| !tag.is_empty()).map(str::to_string).collect();
+    if let Some((date, time)) = get("due").and_then(parse_due_moment) {
+        todo.due = Some(date);
+        todo.due_time = time;
+    }
+    todo.recurrence = get("recur").and_then(Recurrence::parse);
+    if let Some(at) = get("entry").and_then(parse_timestamp) {
+        todo.created_at = Some(at);
+    }
+    if todo.status == Status::Done {
+        todo.completed_at = get("end").and_then(parse_timestamp).or_else(|| Some(Timestamp::now()));
+    }
+    // Annotations are named by when they were added, which sorts them in order
+    let annotations: Vec<&str> =
+        task.iter().filter(|(key, _)| key.starts_with("annotation_")).map(|(_, text)| text.as_str()).collect();
+    todo.notes = annotations.join("\n");
+    todo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn todoist_csv_keeps_subtasks_sections_labels_and_priorities() {
+        let csv = "\u{feff}TYPE,CONTENT,DESCRIPTION,PRIORITY,INDENT,AUTHOR,RESPONSIBLE,DATE,DATE_LANG,TIMEZONE\r\n\
+                   section,Groceries,,,,,,,,\r\n\
+                   task,Buy milk @errands,\"Whole, not skimmed\",1,1,Sam (1),,2024-05-01,en,UTC\r\n\
+                   task,\"Check the \"\"best before\"\"\",,4,2,Sam (1),,,en,UTC\r\n\
+                   note,Ask at the counter,,,,Sam (1),,,,\r\n\
+                   \r\n\
+                   task,Water plants,,3,1,Sam (1),,every 3 days,en,UTC\r\n";
+        let imported = parse_todoist_csv(csv, "Home").unwrap();
+        assert_eq!(imported[0].list.as_deref(), Some("Home"));
+
+        let todos = &imported[0].todos;
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].text, "Buy milk");
+        assert_eq!(todos[0].tags, ["errands"]);
+        assert_eq!(todos[0].notes, "Whole, not skimmed");
+        assert_eq!(todos[0].priority, Priority::High);
+        assert_eq!(todos[0].project.as_deref(), Some("Groceries"));
+        assert_eq!(todos[0].due, Date::new(2024, 5, 1));
+        assert_eq!(todos[0].children[0].text, "Check the \"best before\"");
+        assert_eq!(todos[0].children[0].notes, "Ask at the counter");
+        assert_eq!(todos[1].priority, Priority::Low);
+        assert_eq!(todos[1].recurrence, Some(Recurrence::EveryNDays(3)));
+    }
+
+    #[test]
+    fn todoist_json_builds_the_tree_and_lists_from_ids() {
+        let json = r#"{
+            "projects": [{"id": "1", "name": "Work"}],
+            "sections": [{"id": "7", "name": "Q3"}],
+            "items": [
+                {"id": "11", "project_id": "1", "parent_id": "10", "content": "Draft", "priority": 1, "child_order": 1},
+                {"id": "10", "project_id": "1", "section_id": "7", "content": "Report", "priority": 4,
+                 "labels": ["writing"], "due": {"date": "2024-05-01T17:30:00", "is_recurring": false}, "child_order": 0},
+                {"id": "12", "project_id": "2", "content": "Elsewhere", "checked": true}
+            ]
+        }"#;
+        let imported = parse_todoist_json(json).unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].list.as_deref(), Some("Work"));
+        let report = &imported[0].todos[0];
+        assert_eq!((report.text.as_str(), report.priority), ("Report", Priority::High));
+        assert_eq!(report.project.as_deref(), Some("Q3"));
+        assert_eq!(report.tags, ["writing"]);
+        assert_eq!((report.due, report.due_time), (Date::new(2024, 5, 1), Some(Time { hour: 17, minute: 30 })));
+        assert_eq!(report.children[0].text, "Draft");
+        // Project 2 isn't named in the file, so it goes to the current list
+        assert_eq!(imported[1].list, None);
+        assert_eq!(imported[1].todos[0].status, Status::Done);
+    }
+
+    #[test]
+    fn taskwarrior_data_and_export_read_the_same() {
+        let data = "[description:\"Fix &open;bug&close; \\\"now\\\"\" entry:\"1714550400\" priority:\"H\" project:\"work.api\" \
+                    status:\"pending\" tags:\"code,urgent\" uuid:\"a\" annotation_1714550500:\"see ticket\"]\n\
+                    [description:\"Deploy\" depends:\"a\" status:\"pending\" uuid:\"b\" due:\"20240501T150000Z\"]\n\
+                    [description:\"Gone\" status:\"deleted\" uuid:\"c\"]\n";
+        let export = r#"[
+            {"description": "Fix [bug] \"now\"", "entry": "20240501T080000Z", "priority": "H", "project": "work.api",
+             "status": "pending", "tags": ["code", "urgent"], "uuid": "a",
+             "annotations": [{"entry": "20240501T080140Z", "description": "see ticket"}]},
+            {"description": "Deploy", "depends": ["a"], "status": "pending", "uuid": "b", "due": "20240501T150000Z"},
+            {"description": "Gone", "status": "deleted", "uuid": "c"}
+        ]"#;
+        for contents in [data, export] {
+            let todos = parse_taskwarrior(contents).unwrap().remove(0).todos;
+            assert_eq!(todos.len(), 2);
+            assert_eq!(todos[0].text, "Fix [bug] \"now\"");
+            assert_eq!(todos[0].priority, Priority::High);
+            assert_eq!(todos[0].project.as_deref(), Some("work.api"));
+            assert_eq!(todos[0].tags, ["code", "urgent"]);
+            assert_eq!(todos[0].notes, "see ticket");
+            assert_eq!(todos[0].created_at, Some(Timestamp(1_714_550_400)));
+            assert_eq!(todos[1].status, Status::Blocked);
+            let due = Timestamp(1_714_575_600);
+            assert_eq!((todos[1].due, todos[1].due_time), (Some(due.date()), Some(due.time())));
+        }
+    }
+
+    #[test]
+    fn todo_txt_projects_and_contexts_are_imported_as_such() {
+        let dir = std::env::temp_dir().join(format!("todo-tui-test-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.txt");
+        let file = "x 2024-05-02 2024-05-01 new one @ctx +proj\n(B) call mom +Family_matters @phone tag:weekend list:Home\n";
+        fs::write(&path, file).unwrap();
+        let mut app = App::new(dir.join("todos.json"));
+        app.lists = vec![TodoList::new("Todos")];
+        assert_eq!(app.import("todotxt", &path, false).unwrap(), "Imported 2 todos into Todos and Home");
+
+        let done = &app.lists[0].todos[0];
+        assert_eq!((done.text.as_str(), done.status), ("new one", Status::Done));
+        assert_eq!(done.project.as_deref(), Some("proj"));
+        assert_eq!(done.contexts, ["ctx"]);
+        assert!(done.tags.is_empty());
+        assert_eq!(done.completed_at.map(Timestamp::date), Date::new(2024, 5, 2));
+        assert_eq!(app.lists[1].name, "Home");
+        let call = &app.lists[1].todos[0];
+        assert_eq!((call.text.as_str(), call.priority), ("call mom", Priority::Medium));
+        assert_eq!(call.project.as_deref(), Some("Family matters"));
+        assert_eq!(call.contexts, ["phone"]);
+        assert_eq!(call.tags, ["weekend"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
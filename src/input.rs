@@ -0,0 +1,1557 @@
+//! Turning key presses and mouse events into changes to the app state:
+//! the keymap, its presets, counts and the `:` commands
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Margin, Position};
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::{item_at, App, Date, InputTarget, ListRow, Mode, Query, SortMode, TableColumn},
+    storage::{snapshot_time, EXPORT_FORMATS},
+    ui::HIGHLIGHT_SYMBOL_WIDTH,
+    update::{update, Message},
+};
+
+/// Applies a single keypress to the app state, the way the current mode reads it
+/// Changes that have a Message of their own are sent on through `update`
+pub(crate) fn handle_key(app: &mut App, key: KeyEvent) {
+    let code = key.code;
+    // A notice only needs to be seen once
+    app.notice = None;
+    // The debug overlay sits on top of whatever mode is open, so it's
+    // toggled before the mode gets the key
+    if code == KeyCode::F(12) {
+        app.show_debug = !app.show_debug;
+        return;
+    }
+    // Nothing the key did could be seen, so only quitting works until the
+    // terminal is big enough again
+    if app.too_small {
+        if app.keymap.action(Key::from_event(key)) == Some(Action::Quit) {
+            update(app, Message::Quit);
+        }
+        return;
+    }
+    // Errors stay until dismissed - Esc does that before anything else it means
+    if code == KeyCode::Esc && app.mode == Mode::Normal && app.error.take().is_some() {
+        return;
+    }
+    // Different key handling based on mode - modal interface pattern
+    match app.mode {
+        Mode::Input => {
+            // In input mode, keys type into the buffer
+            match code {
+                KeyCode::Enter => app.submit_input(),
+                // Esc cancels input without saving
+                KeyCode::Esc => app.cancel_input(),
+                // The command prompt completes with Tab and recalls history like a shell
+                KeyCode::Tab if app.input_target == InputTarget::Command => app.complete_command(),
+                KeyCode::Up if app.input_target == InputTarget::Command => app.recall_command(-1),
+                KeyCode::Down if app.input_target == InputTarget::Command => app.recall_command(1),
+                _ => app.edit_input(key),
+            }
+        }
+        Mode::TagPicker => {
+            // The picker is a small modal list - only navigation and confirm/cancel apply
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.tag_picker_next(),
+                KeyCode::Up | KeyCode::Char('k') => app.tag_picker_previous(),
+                KeyCode::Enter => app.apply_tag_picker(),
+                KeyCode::Char('c') => app.start_tag_style(),
+                KeyCode::Esc | KeyCode::Char('t') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::ProjectPicker => {
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.project_picker_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.project_picker_move(-1),
+                KeyCode::Enter => app.apply_project_picker(),
+                KeyCode::Esc | KeyCode::Char('P') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::FilterPicker => {
+            // Digits pick a filter directly, like list numbers pick a list
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.filter_picker_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.filter_picker_move(-1),
+                KeyCode::Enter => app.apply_filter_picker(app.filter_picker.selected().unwrap_or(0)),
+                KeyCode::Char(c @ '0'..='9') => app.apply_filter_picker(c as usize - '0' as usize),
+                KeyCode::Char('s') => app.start_save_filter(),
+                KeyCode::Char('d') => app.delete_saved_filter(),
+                KeyCode::Esc | KeyCode::Char('F') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::Search => {
+            // Typing edits the query, the list is refiltered on every keystroke
+            match code {
+                // Enter keeps the filter but returns to navigation so n/N work
+                KeyCode::Enter => app.mode = Mode::Normal,
+                KeyCode::Esc => app.clear_search(),
+                KeyCode::Backspace => app.edit_search(None),
+                KeyCode::Char(c) => app.edit_search(Some(c)),
+                // Arrow keys still move through matches while typing
+                KeyCode::Down => app.next(),
+                KeyCode::Up => app.previous(),
+                _ => {}
+            }
+        }
+        Mode::Notes => {
+            // Enter adds a line break, so Esc is what finishes editing
+            // Saving on Esc is safe because the change can be undone with 'u'
+            match code {
+                KeyCode::Enter => app.edit_input(KeyEvent::new(KeyCode::Char('\n'), KeyModifiers::NONE)),
+                KeyCode::Esc => app.submit_input(),
+                _ => app.edit_input(key),
+            }
+        }
+        Mode::Confirm => {
+            // Anything but an explicit yes cancels - the safe default for deletes
+            app.resolve_confirm(matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')));
+        }
+        Mode::Trash => {
+            // The trash is a popup list like the tag picker, with its own few actions
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.trash_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.trash_move(-1),
+                KeyCode::Enter | KeyCode::Char('r') => app.restore_selected(),
+                KeyCode::Char('d') => app.purge_selected(),
+                KeyCode::Char('X') => app.empty_trash(),
+                // Undo can add or remove trash entries, so revalidate the selection
+                KeyCode::Char('u') => {
+                    app.undo();
+                    app.clamp_trash_selection();
+                }
+                KeyCode::Esc | KeyCode::Char('x') | KeyCode::Char('q') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::Snoozed => {
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.snoozed_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.snoozed_move(-1),
+                KeyCode::Enter | KeyCode::Char('r') => app.wake_selected(),
+                // Undo can snooze a todo again or bring one back
+                KeyCode::Char('u') => {
+                    app.undo();
+                    app.clamp_snoozed_selection();
+                }
+                KeyCode::Esc | KeyCode::Char('q') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::Archive => {
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.archive_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.archive_move(-1),
+                KeyCode::Enter | KeyCode::Char('r') => app.restore_archived(),
+                // Undo can add or remove archive entries, so revalidate the selection
+                KeyCode::Char('u') => {
+                    app.undo();
+                    app.clamp_archive_selection();
+                }
+                KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::Recovery => {
+            // There's no list behind the dialog yet, so these are the only keys
+            match code {
+                KeyCode::Char('o') => app.raw_edit = app.recovery.as_ref().map(|r| r.error.path().clone()),
+                KeyCode::Char('r') => app.retry_load(),
+                KeyCode::Char('n') => app.start_fresh(),
+                KeyCode::Esc | KeyCode::Char('q') => update(app, Message::Quit),
+                _ => {}
+            }
+        }
+        Mode::Unlock => {
+            // Only the passphrase, so no cursor movement or word editing
+            match code {
+                KeyCode::Enter => app.submit_passphrase(),
+                KeyCode::Backspace => {
+                    app.input.pop();
+                }
+                KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.input_error = None;
+                    app.input.push(c);
+                }
+                KeyCode::Esc => update(app, Message::Quit),
+                _ => {}
+            }
+        }
+        Mode::History => {
+            // j/k pick a version, the page keys scroll its diff
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.history_move(1),
+                KeyCode::Up | KeyCode::Char('k') => app.history_move(-1),
+                KeyCode::PageDown | KeyCode::Char('J') => app.history_scroll(10),
+                KeyCode::PageUp | KeyCode::Char('K') => app.history_scroll(-10),
+                KeyCode::Enter | KeyCode::Char('r') => app.restore_version(),
+                KeyCode::Esc | KeyCode::Char('q') => app.close_history(),
+                _ => {}
+            }
+        }
+        Mode::Stats => {
+            if matches!(code, KeyCode::Esc | KeyCode::Char('S') | KeyCode::Char('q')) {
+                app.mode = Mode::Normal;
+            }
+        }
+        Mode::Board => {
+            // Arrows sideways carry the card along, Tab only moves the focus
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.board_move_selection(1, 0),
+                KeyCode::Up | KeyCode::Char('k') => app.board_move_selection(-1, 0),
+                KeyCode::Tab => app.board_move_selection(0, 1),
+                KeyCode::BackTab => app.board_move_selection(0, -1),
+                KeyCode::Right | KeyCode::Char('l') => app.board_move_card(1),
+                KeyCode::Left | KeyCode::Char('h') => app.board_move_card(-1),
+                KeyCode::Char('u') => {
+                    app.undo();
+                    app.board_move_selection(0, 0);
+                }
+                KeyCode::Esc | KeyCode::Char('B') | KeyCode::Char('q') => app.close_board(),
+                _ => {}
+            }
+        }
+        Mode::Welcome => {
+            // Any key closes it, and one that does something goes on to do
+            // it, so 'a' starts the first todo straight away
+            app.dismiss_welcome();
+            if !matches!(code, KeyCode::Esc | KeyCode::Enter) {
+                handle_normal_key(app, Key::from_event(key));
+            }
+        }
+        Mode::Review => {
+            // One key per answer - Esc leaves the todos still to come as they are
+            match code {
+                KeyCode::Char('t') => app.review_today(),
+                KeyCode::Char('d') => app.review_reschedule(),
+                KeyCode::Char('c') | KeyCode::Char(' ') => app.review_complete(),
+                KeyCode::Char('x') => app.review_drop(),
+                KeyCode::Char('s') => app.next_review(),
+                KeyCode::Esc | KeyCode::Char('q') => app.end_review(),
+                _ => {}
+            }
+        }
+        Mode::Help => {
+            // The help popup scrolls like a pager and closes on the usual keys
+            match code {
+                KeyCode::Down | KeyCode::Char('j') => app.help_scroll = app.help_scroll.saturating_add(1),
+                KeyCode::Up | KeyCode::Char('k') => app.help_scroll = app.help_scroll.saturating_sub(1),
+                KeyCode::PageDown => app.help_scroll = app.help_scroll.saturating_add(10),
+                KeyCode::PageUp => app.help_scroll = app.help_scroll.saturating_sub(10),
+                KeyCode::Home | KeyCode::Char('g') => app.help_scroll = 0,
+                KeyCode::Esc | KeyCode::Char('?') | KeyCode::Char('q') => app.mode = Mode::Normal,
+                _ => {}
+            }
+        }
+        Mode::Normal => {
+            // The table's column numbers sort by their column, in place of
+            // switching lists or starting a count
+            if app.table
+                && app.pending_keys.count.is_none()
+                && app.pending_keys.prefix.is_none()
+                && key.modifiers.is_empty()
+                && let KeyCode::Char(c @ '1'..='5') = code
+            {
+                app.sort_by_column(TableColumn::ALL[c as usize - '1' as usize]);
+                return;
+            }
+            // In navigation mode, keys are looked up in the keymap table
+            handle_normal_key(app, Key::from_event(key));
+        }
+    }
+}
+
+/// Applies a mouse event to the app state
+/// Clicking a row selects it, clicking its checkbox toggles it, clicking a fold
+/// marker expands/collapses, and the wheel moves the selection
+pub(crate) fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    // Popups and text entry own the screen - clicks behind them would be surprising
+    // On a terminal too small to draw in there's nothing to click or scroll
+    if app.mode != Mode::Normal || app.too_small {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollDown => app.move_selection_by(1),
+        MouseEventKind::ScrollUp => app.move_selection_by(-1),
+        // The table's column titles sort by their column
+        MouseEventKind::Down(MouseButton::Left) if app.table && mouse.row == app.list_area.y + 1 => {
+            if let Some(i) = app.table_columns.iter().position(|&(start, end)| (start..end).contains(&mouse.column)) {
+                app.sort_by_column(TableColumn::ALL[i]);
+            }
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            let Some((row, column)) = app.hit_test(mouse.column, mouse.row) else {
+                return;
+            };
+            app.state_mut().select(Some(row));
+            // In the table the checkbox starts the status column, and a click
+            // anywhere else only selects
+            if app.table {
+                if let Some(&(start, _)) = app.table_columns.first()
+                    && (start..start + 3).contains(&mouse.column)
+                {
+                    app.toggle_completed_at(vec![app.visible[row].clone()]);
+                }
+                return;
+            }
+            // Rows are laid out as: highlight symbol, indent, fold marker, checkbox
+            let depth = app.visible[row].len() - 1;
+            let fold_start = HIGHLIGHT_SYMBOL_WIDTH + 2 * depth as u16;
+            let checkbox_start = fold_start + 2;
+            if (checkbox_start..checkbox_start + 4).contains(&column) {
+                // Only the clicked todo, even when others are marked
+                app.toggle_completed_at(vec![app.visible[row].clone()]);
+            } else if (fold_start..checkbox_start).contains(&column) {
+                let collapsed = item_at(app.todos(), &app.visible[row]).collapsed;
+                app.set_collapsed(!collapsed);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A key as written in the keymap - the key code plus the modifiers held
+/// Shift is only tracked for keys like arrows, on characters it already
+/// shows up in the character ('J' vs 'j')
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Key {
+    pub(crate) code: KeyCode,
+    pub(crate) ctrl: bool,
+    alt: bool,
+    pub(crate) shift: bool,
+}
+
+impl Key {
+    /// A plain character key
+    pub(crate) const fn char(c: char) -> Key {
+        Key::code(KeyCode::Char(c))
+    }
+
+    /// A character pressed together with Ctrl
+    pub(crate) const fn ctrl(c: char) -> Key {
+        Key { ctrl: true, ..Key::char(c) }
+    }
+
+    /// A key pressed together with Alt (Meta in emacs terms)
+    const fn alt(code: KeyCode) -> Key {
+        Key { alt: true, ..Key::code(code) }
+    }
+
+    /// A non-character key pressed together with Shift
+    pub(crate) const fn shift(code: KeyCode) -> Key {
+        Key { shift: true, ..Key::code(code) }
+    }
+
+    /// A non-character key like an arrow or Tab
+    pub(crate) const fn code(code: KeyCode) -> Key {
+        Key { code, ctrl: false, alt: false, shift: false }
+    }
+
+    /// The key as the keymap sees a key press
+    /// Terminals report Shift+Tab as BackTab with Shift held, which is
+    /// already a key of its own
+    pub(crate) fn from_event(event: KeyEvent) -> Key {
+        let shift = event.modifiers.contains(KeyModifiers::SHIFT)
+            && !matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab);
+        Key {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+            alt: event.modifiers.contains(KeyModifiers::ALT),
+            shift,
+        }
+    }
+
+    /// Parses a key as written in the config, like `j`, `ctrl+n`, `alt+<`,
+    /// `shift+down` or `f5` - modifiers and names are case-insensitive
+    pub(crate) fn parse(spec: &str) -> Result<Key, String> {
+        let mut key = Key::char(' ');
+        let mut rest = spec;
+        // A lone `+` is the plus key, so only strip modifiers that leave something
+        while let Some((modifier, name)) = rest.split_once('+')
+            && !name.is_empty()
+        {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => key.ctrl = true,
+                "alt" | "meta" => key.alt = true,
+                "shift" => key.shift = true,
+                _ => return Err(format!("unknown modifier '{}' in key '{}'", modifier, spec)),
+            }
+            rest = name;
+        }
+        let mut chars = rest.chars();
+        key.code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", spec)),
+                },
+            },
+        };
+        // Match what terminals send: Shift is folded into the character, and
+        // Ctrl+letter arrives lowercase
+        if let KeyCode::Char(c) = key.code {
+            if key.shift {
+                key.code = KeyCode::Char(c.to_ascii_uppercase());
+                key.shift = false;
+            } else if key.ctrl {
+                key.code = KeyCode::Char(c.to_ascii_lowercase());
+            }
+        }
+        Ok(key)
+    }
+
+    /// How the key is written in the help popup
+    pub(crate) fn label(self) -> String {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            other => format!("{:?}", other),
+        };
+        let mut label = String::new();
+        for (held, modifier) in [(self.ctrl, "Ctrl+"), (self.alt, "Alt+"), (self.shift, "Shift+")] {
+            if held {
+                label.push_str(modifier);
+            }
+        }
+        label + &name
+    }
+}
+
+/// Everything that can be triggered by a key in normal mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Action {
+    Quit,
+    Next,
+    Previous,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    First,
+    Last,
+    Expand,
+    CollapseOrParent,
+    MoveDown,
+    MoveUp,
+    Toggle,
+    Delete,
+    Add,
+    AddChild,
+    Edit,
+    EditNotes,
+    ExternalEdit,
+    EditDue,
+    EditRecurrence,
+    Snooze,
+    Snoozed,
+    CyclePriority,
+    CycleStatus,
+    ToggleStar,
+    GroupByProject,
+    ProjectPicker,
+    SavedFilters,
+    Command,
+    ClearDone,
+    Repeat,
+    Pomodoro,
+    TrackTime,
+    Copy,
+    Undo,
+    Redo,
+    CycleSort,
+    ToggleCompleted,
+    ToggleUnstarted,
+    TagFilter,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    ClearFilter,
+    ToggleDetails,
+    ToggleWrap,
+    ToggleTable,
+    SwitchList,
+    NextList,
+    PreviousList,
+    NewList,
+    RenameList,
+    DeleteList,
+    Trash,
+    Archive,
+    ArchiveCompleted,
+    Mark,
+    Tag,
+    MoveToList,
+    Someday,
+    BrowseArchive,
+    Stats,
+    Board,
+    CycleTheme,
+    Sync,
+    Help,
+}
+
+impl Action {
+    /// Name of the action in the config's `keys` table - the variant in
+    /// kebab-case, like `cycle-priority`
+    pub(crate) fn name(self) -> String {
+        let mut name = String::new();
+        for c in format!("{:?}", self).chars() {
+            if c.is_ascii_uppercase() && !name.is_empty() {
+                name.push('-');
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        name
+    }
+
+    /// Runs the action - `code` is passed for actions that depend on which key
+    /// triggered them, like the list number keys
+    /// Actions with a Message of their own go through `update`, so a key does
+    /// exactly what sending the message does
+    pub(crate) fn perform(self, app: &mut App, code: KeyCode) {
+        match self {
+            Action::Quit => update(app, Message::Quit),
+            Action::Next | Action::NextMatch => update(app, Message::Next),
+            Action::Previous | Action::PreviousMatch => update(app, Message::Previous),
+            Action::PageDown => app.page(1),
+            Action::PageUp => app.page(-1),
+            Action::HalfPageDown => app.half_page(1),
+            Action::HalfPageUp => app.half_page(-1),
+            Action::First => app.select_first(),
+            Action::Last => app.select_last(),
+            Action::Expand => app.set_collapsed(false),
+            Action::CollapseOrParent => app.collapse_or_parent(),
+            Action::MoveDown => update(app, Message::Move(1)),
+            Action::MoveUp => update(app, Message::Move(-1)),
+            Action::Toggle => update(app, Message::Toggle),
+            Action::Delete => update(app, Message::Delete),
+            Action::Add => app.mode = Mode::Input,
+            Action::AddChild => app.start_add_child(),
+            Action::Edit => app.start_edit(),
+            Action::EditNotes => app.start_notes(),
+            Action::ExternalEdit => app.start_external_edit(),
+            Action::EditDue => app.start_due(),
+            Action::EditRecurrence => app.start_recurrence(),
+            Action::Snooze => app.start_snooze(),
+            Action::Snoozed => app.open_snoozed(),
+            Action::CyclePriority => app.cycle_priority(),
+            Action::CycleStatus => app.cycle_status(),
+            Action::ToggleStar => app.toggle_star(),
+            Action::GroupByProject => app.toggle_group_by_project(),
+            Action::ProjectPicker => app.open_project_picker(),
+            Action::SavedFilters => app.open_filter_picker(),
+            Action::Command => app.start_command(),
+            Action::Copy => app.copy_selected(),
+            Action::Pomodoro => app.toggle_pomodoro(),
+            Action::TrackTime => app.toggle_tracking(),
+            Action::Undo => update(app, Message::Undo),
+            Action::Redo => update(app, Message::Redo),
+            Action::CycleSort => app.cycle_sort(),
+            Action::ToggleCompleted => app.toggle_hide_completed(),
+            Action::ToggleUnstarted => app.toggle_unstarted(),
+            Action::TagFilter => app.open_tag_picker(),
+            Action::Search => app.start_search(),
+            Action::ClearFilter => app.clear_filter(),
+            Action::ToggleDetails => app.show_details = !app.show_details,
+            Action::ToggleWrap => app.wrap = !app.wrap,
+            Action::ToggleTable => app.table = !app.table,
+            Action::SwitchList => {
+                if let KeyCode::Char(c @ '1'..='9') = code {
+                    update(app, Message::SwitchList(c as usize - '1' as usize));
+                }
+            }
+            Action::NextList => app.cycle_list(1),
+            Action::PreviousList => app.cycle_list(-1),
+            Action::NewList => app.start_new_list(),
+            Action::RenameList => app.start_rename_list(),
+            Action::DeleteList => app.delete_list(),
+            Action::Trash => app.open_trash(),
+            Action::Archive => app.archive_selected(),
+            Action::ArchiveCompleted => app.clear_done(true),
+            Action::ClearDone => app.clear_done(false),
+            // Only reached without a count - perform_counted handles it otherwise
+            Action::Repeat => app.repeat_last_change(None),
+            Action::Mark => app.toggle_mark(),
+            Action::Tag => app.start_tag(),
+            Action::MoveToList => app.start_move_to_list(),
+            Action::Someday => app.toggle_someday(),
+            Action::BrowseArchive => app.open_archive(),
+            Action::Stats => app.mode = Mode::Stats,
+            Action::Board => app.open_board(),
+            Action::CycleTheme => app.cycle_theme(),
+            Action::Sync => app.request_sync(),
+            Action::Help => {
+                app.help_scroll = 0;
+                app.mode = Mode::Help;
+            }
+        }
+    }
+
+    /// Runs the action with the count typed in front of its key, if any
+    /// Moves and changes repeat that many times, `gg` and `G` jump to that row,
+    /// and actions that open something run once
+    fn perform_counted(self, app: &mut App, code: KeyCode, count: Option<usize>) {
+        if self.is_repeatable() {
+            app.last_change = Some(Repeat::Action(self, count.unwrap_or(1)));
+        }
+        match (self, count) {
+            (Action::First | Action::Last, Some(number)) => app.select_row(number),
+            (Action::Repeat, Some(_)) => app.repeat_last_change(count),
+            (_, Some(times)) if self.takes_count() => {
+                for _ in 0..times {
+                    self.perform(app, code);
+                }
+            }
+            _ => self.perform(app, code),
+        }
+    }
+
+    /// Whether a count in front of the action repeats it
+    fn takes_count(self) -> bool {
+        matches!(
+            self,
+            Action::Next
+                | Action::Previous
+                | Action::PageDown
+                | Action::PageUp
+                | Action::HalfPageDown
+                | Action::HalfPageUp
+                | Action::NextMatch
+                | Action::PreviousMatch
+                | Action::Expand
+                | Action::CollapseOrParent
+                | Action::NextList
+                | Action::PreviousList
+                | Action::Undo
+                | Action::Redo
+        ) || self.is_repeatable()
+    }
+
+    /// Whether the action changes todos without a prompt, so `.` can repeat it
+    fn is_repeatable(self) -> bool {
+        matches!(
+            self,
+            Action::MoveDown
+                | Action::MoveUp
+                | Action::Toggle
+                | Action::Delete
+                | Action::CyclePriority
+                | Action::CycleStatus
+                | Action::ToggleStar
+                | Action::Archive
+        )
+    }
+}
+
+/// A change `.` can repeat
+#[derive(Clone)]
+pub(crate) enum Repeat {
+    /// An action that needs no prompt, with the count it ran with
+    Action(Action, usize),
+    /// A prompt for the selected todos and what was typed into it
+    Input(InputTarget, String),
+}
+
+/// How long a count typed on its own waits for a key before it's taken as a
+/// list number instead
+const COUNT_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// Largest count that can be typed, so `99999999j` doesn't hang the app
+const MAX_COUNT: usize = 9999;
+
+/// Keys typed so far of a multi-key command in normal mode, vim style: a count
+/// like the 5 in `5j`, then a key or a two-key sequence like `gg`
+#[derive(Default)]
+pub(crate) struct PendingKeys {
+    pub(crate) count: Option<usize>,
+    /// First key of a sequence, waiting for the second
+    pub(crate) prefix: Option<Key>,
+    /// When the last digit of the count was typed
+    pub(crate) since: Option<Instant>,
+}
+
+impl PendingKeys {
+    /// What's been typed so far, for the status bar
+    pub(crate) fn label(&self) -> Option<String> {
+        let count = self.count.map(|n| n.to_string()).unwrap_or_default();
+        let prefix = self.prefix.map(|key| key.label()).unwrap_or_default();
+        (self.count.is_some() || self.prefix.is_some()).then(|| format!("{}{}", count, prefix))
+    }
+}
+
+/// A normal-mode command that takes two keys in a row, like vim's `gg`
+pub(crate) struct Sequence {
+    pub(crate) keys: [Key; 2],
+    pub(crate) action: Action,
+    pub(crate) description: &'static str,
+}
+
+/// Two-key commands - their first keys aren't bound on their own
+pub(crate) const SEQUENCES: &[Sequence] = &[
+    Sequence { keys: [Key::char('g'), Key::char('g')], action: Action::First, description: "Jump to first todo (with a count: to that row)" },
+    Sequence { keys: [Key::char('g'), Key::char('p')], action: Action::GroupByProject, description: "Group the list by project" },
+];
+
+/// Feeds a normal-mode key through the count and sequence state machine and
+/// runs the command once it's complete
+fn handle_normal_key(app: &mut App, pressed: Key) {
+    // Keys a plugin bound win over the keymap, and cancel a count or sequence
+    if let Some(index) = app.plugins.binding(pressed) {
+        app.pending_keys = PendingKeys::default();
+        app.run_plugin_binding(index);
+        return;
+    }
+    // Only the vim keymap has counts and sequences - elsewhere digits switch lists
+    if !app.keymap.preset.counts() {
+        if let Some(action) = app.keymap.action(pressed) {
+            action.perform(app, pressed.code);
+        }
+        return;
+    }
+    let pending = std::mem::take(&mut app.pending_keys);
+    // The second key of a sequence - anything that doesn't complete one cancels it
+    if let Some(prefix) = pending.prefix {
+        if let Some(sequence) = SEQUENCES.iter().find(|s| s.keys == [prefix, pressed]) {
+            sequence.action.perform_counted(app, pressed.code, pending.count);
+        }
+        return;
+    }
+    // Digits build up a count, though a leading 0 means nothing
+    if let Key { code: KeyCode::Char(c @ '0'..='9'), ctrl: false, alt: false, .. } = pressed
+        && (c != '0' || pending.count.is_some())
+    {
+        let digit = c as usize - '0' as usize;
+        app.pending_keys.count = Some((pending.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+        app.pending_keys.since = Some(Instant::now());
+        return;
+    }
+    // A key the user bound themselves wins over starting a sequence with it
+    let action = app.keymap.action(pressed);
+    if action.is_none() && SEQUENCES.iter().any(|s| s.keys[0] == pressed) {
+        app.pending_keys = PendingKeys { prefix: Some(pressed), ..pending };
+        return;
+    }
+    if let Some(action) = action {
+        action.perform_counted(app, pressed.code, pending.count);
+    }
+}
+
+/// A set of keybindings to start from, for people used to different editors
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeymapPreset {
+    /// hjkl, counts and `gg` - the keymap as written in KEYMAP
+    #[default]
+    Vim,
+    /// Ctrl+n/p/f/b, Ctrl+v/Alt+v paging, Ctrl+s search, Alt+x commands
+    Emacs,
+    /// Arrow keys, Home/End and Shift+arrows to move todos
+    Standard,
+}
+
+impl KeymapPreset {
+    pub(crate) const ALL: [KeymapPreset; 3] = [KeymapPreset::Vim, KeymapPreset::Emacs, KeymapPreset::Standard];
+
+    /// Name used in the config and for --keymap
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            KeymapPreset::Vim => "vim",
+            KeymapPreset::Emacs => "emacs",
+            KeymapPreset::Standard => "standard",
+        }
+    }
+
+    /// Looks up a preset by name for --keymap
+    pub fn from_name(name: &str) -> Result<KeymapPreset, String> {
+        KeymapPreset::ALL.into_iter().find(|preset| preset.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = KeymapPreset::ALL.iter().map(|preset| preset.name()).collect();
+            format!("unknown keymap '{}' (expected {})", name, names.join(", "))
+        })
+    }
+
+    /// Whether digits are counts and two-key sequences like `gg` work
+    /// Without them, digits switch lists straight away
+    pub(crate) fn counts(self) -> bool {
+        self == KeymapPreset::Vim
+    }
+
+    /// Bindings the preset replaces in KEYMAP
+    pub(crate) fn changes(self) -> &'static [(Action, &'static [Key])] {
+        match self {
+            KeymapPreset::Vim => &[],
+            KeymapPreset::Emacs => EMACS_KEYS,
+            KeymapPreset::Standard => STANDARD_KEYS,
+        }
+    }
+}
+
+/// Keys the emacs preset gives actions, replacing their vim keys
+/// Letter commands like `a` and `e` stay, since emacs has nothing to say about them
+const EMACS_KEYS: &[(Action, &[Key])] = &[
+    (Action::Next, &[Key::code(KeyCode::Down), Key::ctrl('n')]),
+    (Action::Previous, &[Key::code(KeyCode::Up), Key::ctrl('p')]),
+    (Action::Expand, &[Key::code(KeyCode::Right), Key::ctrl('f')]),
+    (Action::CollapseOrParent, &[Key::code(KeyCode::Left), Key::ctrl('b')]),
+    (Action::PageDown, &[Key::code(KeyCode::PageDown), Key::ctrl('v')]),
+    (Action::PageUp, &[Key::code(KeyCode::PageUp), Key::alt(KeyCode::Char('v'))]),
+    (Action::HalfPageDown, &[]),
+    (Action::HalfPageUp, &[]),
+    (Action::First, &[Key::code(KeyCode::Home), Key::alt(KeyCode::Char('<'))]),
+    (Action::Last, &[Key::code(KeyCode::End), Key::alt(KeyCode::Char('>'))]),
+    // Org mode moves items with Meta and the arrows
+    (Action::MoveDown, &[Key::alt(KeyCode::Down)]),
+    (Action::MoveUp, &[Key::alt(KeyCode::Up)]),
+    (Action::Delete, &[Key::char('d'), Key::ctrl('k')]),
+    (Action::Search, &[Key::char('/'), Key::ctrl('s')]),
+    (Action::ClearFilter, &[Key::code(KeyCode::Esc), Key::ctrl('g')]),
+    (Action::Command, &[Key::char(':'), Key::alt(KeyCode::Char('x'))]),
+    (Action::GroupByProject, &[Key::char('g')]),
+];
+
+/// Keys the standard preset gives actions - what most non-modal programs use
+const STANDARD_KEYS: &[(Action, &[Key])] = &[
+    (Action::Next, &[Key::code(KeyCode::Down)]),
+    (Action::Previous, &[Key::code(KeyCode::Up)]),
+    (Action::Expand, &[Key::code(KeyCode::Right)]),
+    (Action::CollapseOrParent, &[Key::code(KeyCode::Left)]),
+    (Action::HalfPageDown, &[]),
+    (Action::HalfPageUp, &[]),
+    (Action::Last, &[Key::code(KeyCode::End)]),
+    (Action::MoveDown, &[Key::shift(KeyCode::Down), Key::char('J')]),
+    (Action::MoveUp, &[Key::shift(KeyCode::Up), Key::char('K')]),
+    (Action::Edit, &[Key::char('e'), Key::code(KeyCode::F(2))]),
+    (Action::Delete, &[Key::char('d'), Key::code(KeyCode::Delete)]),
+    (Action::Undo, &[Key::char('u'), Key::ctrl('z')]),
+    (Action::Redo, &[Key::ctrl('r'), Key::ctrl('y')]),
+    (Action::Search, &[Key::char('/'), Key::ctrl('f')]),
+    (Action::GroupByProject, &[Key::char('g')]),
+];
+
+/// The normal-mode keys in effect: KEYMAP with the preset's changes and then
+/// the user's own bindings applied
+/// Keys are kept in KEYMAP order so help can show each with its description
+pub(crate) struct Keymap {
+    pub(crate) preset: KeymapPreset,
+    pub(crate) keys: Vec<Vec<Key>>,
+}
+
+impl Keymap {
+    /// The preset's keymap without any user bindings
+    pub(crate) fn new(preset: KeymapPreset) -> Keymap {
+        let mut keymap = Keymap { preset, keys: KEYMAP.iter().map(|b| b.keys.to_vec()).collect() };
+        for (action, keys) in preset.changes() {
+            keymap.bind(*action, keys);
+        }
+        keymap
+    }
+
+    /// The preset's keymap with the config's `keys` table on top
+    /// Unknown action names and keys are errors so typos don't go unnoticed
+    pub(crate) fn with_overrides(preset: KeymapPreset, overrides: &BTreeMap<String, Vec<String>>) -> Result<Keymap, String> {
+        let mut keymap = Keymap::new(preset);
+        for (name, specs) in overrides {
+            let action = KEYMAP
+                .iter()
+                .map(|binding| binding.action)
+                .find(|action| action.name() == *name)
+                .ok_or_else(|| format!("unknown action '{}' in keys", name))?;
+            let keys = specs.iter().map(|spec| Key::parse(spec)).collect::<Result<Vec<_>, _>>()?;
+            keymap.bind(action, &keys);
+        }
+        Ok(keymap)
+    }
+
+    /// Gives an action exactly these keys, taking them away from any other
+    /// action so a key never does two things
+    fn bind(&mut self, action: Action, keys: &[Key]) {
+        for (binding, bound) in KEYMAP.iter().zip(&mut self.keys) {
+            if binding.action == action {
+                *bound = keys.to_vec();
+            } else {
+                bound.retain(|key| !keys.contains(key));
+            }
+        }
+    }
+
+    /// The keys bound to an action, as the help shows them - empty if none are
+    pub(crate) fn label(&self, action: Action) -> String {
+        KEYMAP
+            .iter()
+            .zip(&self.keys)
+            .find(|(binding, _)| binding.action == action)
+            .map_or_else(String::new, |(_, keys)| keys_label(keys))
+    }
+
+    /// The action a key press triggers, if any
+    pub(crate) fn action(&self, pressed: Key) -> Option<Action> {
+        KEYMAP
+            .iter()
+            .zip(&self.keys)
+            .find(|(_, keys)| keys.contains(&pressed))
+            .map(|(binding, _)| binding.action)
+    }
+}
+
+/// How many commands the `:` history keeps
+const COMMAND_HISTORY_LIMIT: usize = 100;
+
+/// A command that can be typed at the `:` prompt
+/// `run` gets the argument with surrounding whitespace trimmed and reports
+/// bad input as an error message, which keeps the prompt open
+pub(crate) struct CommandSpec {
+    pub(crate) name: &'static str,
+    /// Shorter name that also works, like vim's `:q`
+    pub(crate) alias: Option<&'static str>,
+    pub(crate) usage: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) run: fn(&mut App, &str) -> Result<(), String>,
+}
+
+/// Every `:` command - the single source of truth for running, completing and help
+pub(crate) const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "filter",
+        alias: None,
+        usage: "filter [QUERY]",
+        description: "Filter with a query, or remove the query",
+        run: |app, argument| {
+            if argument.is_empty() {
+                app.set_query(None);
+                return Ok(());
+            }
+            let query = Query::parse(argument, Date::today())?;
+            app.set_query(Some((argument.to_string(), query)));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "sort",
+        alias: None,
+        usage: "sort manual|alpha|due|priority|created",
+        description: "Sort the list",
+        run: |app, argument| {
+            let mode = SortMode::ALL
+                .into_iter()
+                .find(|m| m.name() == argument)
+                .ok_or_else(|| format!("unknown sort order '{}'", argument))?;
+            let selected = app.selected_path();
+            app.sort_mode = mode;
+            app.refresh_and_reselect(selected);
+            app.save_view_state();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "group",
+        alias: None,
+        usage: "group",
+        description: "Group the list by project, or stop grouping",
+        run: |app, _| {
+            app.toggle_group_by_project();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "archive",
+        alias: None,
+        usage: "archive [done]",
+        description: "Archive the selected todo, or all completed ones",
+        run: |app, argument| match argument {
+            "" => {
+                app.archive_selected();
+                Ok(())
+            }
+            "done" => {
+                app.clear_done(true);
+                Ok(())
+            }
+            _ => Err(format!("expected 'done', not '{}'", argument)),
+        },
+    },
+    CommandSpec {
+        name: "clear-done",
+        alias: None,
+        usage: "clear-done [archive]",
+        description: "Move all completed todos to the trash, or archive them",
+        run: |app, argument| match argument {
+            "" => {
+                app.clear_done(false);
+                Ok(())
+            }
+            "archive" => {
+                app.clear_done(true);
+                Ok(())
+            }
+            _ => Err(format!("expected 'archive', not '{}'", argument)),
+        },
+    },
+    CommandSpec {
+        name: "open",
+        alias: None,
+        usage: "open LIST",
+        description: "Switch to a list by number or name",
+        run: |app, argument| {
+            let index = match argument.parse::<usize>() {
+                Ok(n) if (1..=app.lists.len()).contains(&n) => Some(n - 1),
+                _ => app.lists.iter().position(|l| l.name.eq_ignore_ascii_case(argument)),
+            };
+            let index = index.ok_or_else(|| format!("no list '{}'", argument))?;
+            app.switch_list(index);
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "encrypt",
+        alias: None,
+        usage: "encrypt",
+        description: "Encrypt the todo file and archive with a passphrase, or change it",
+        run: |app, _| app.start_encrypt(),
+    },
+    CommandSpec {
+        name: "decrypt",
+        alias: None,
+        usage: "decrypt",
+        description: "Save the todo file and archive unencrypted again",
+        run: |app, _| app.decrypt(),
+    },
+    CommandSpec {
+        name: "export",
+        alias: None,
+        usage: "export json|txt|md|csv|ics [PATH]",
+        description: "Write all lists to a file, todo-export.EXT by default",
+        run: |app, argument| {
+            let (format, path) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+            let path = app.export(format, path.trim())?;
+            app.notice = Some(app.trf("Exported to {}", &[&path.display()]));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "restore",
+        alias: None,
+        usage: "restore [N]",
+        description: "Roll back to snapshot N (1 is the newest), or list the snapshots",
+        run: |app, argument| {
+            if argument.is_empty() {
+                let snapshots = app.snapshots();
+                if snapshots.is_empty() {
+                    return Err("no snapshots yet - one is taken before each session's first change".to_string());
+                }
+                let listed: Vec<String> = snapshots
+                    .iter()
+                    .take(5)
+                    .enumerate()
+                    .map(|(i, path)| format!("{}: {}", i + 1, snapshot_time(&app.save_path, path)))
+                    .collect();
+                let more = if snapshots.len() > 5 { ", …" } else { "" };
+                app.notice = Some(app.trf("{}{} - :restore N to roll back", &[&listed.join(", "), &more]));
+                return Ok(());
+            }
+            let number = argument.parse().map_err(|_| format!("'{}' is not a snapshot number", argument))?;
+            let path = app.restore_snapshot(number)?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            app.notice = Some(app.trf("Restored {} (u: undo)", &[&name]));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "history",
+        alias: None,
+        usage: "history",
+        description: "Browse the versions of the todo file committed to git",
+        run: |app, _| app.open_history(),
+    },
+    CommandSpec {
+        name: "sync",
+        alias: None,
+        usage: "sync",
+        description: "Sync the lists, fetch the GitHub issues and check the mail now",
+        run: |app, _| {
+            app.request_sync();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "theme",
+        alias: None,
+        usage: "theme NAME",
+        description: "Switch to a color theme",
+        run: |app, argument| {
+            app.theme = app
+                .themes
+                .iter()
+                .position(|(name, _)| name == argument)
+                .ok_or_else(|| format!("unknown theme '{}'", argument))?;
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "help",
+        alias: None,
+        usage: "help",
+        description: "Show keys and commands",
+        run: |app, _| {
+            app.help_scroll = 0;
+            app.mode = Mode::Help;
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "quit",
+        alias: Some("q"),
+        usage: "quit",
+        description: "Quit",
+        run: |app, _| {
+            update(app, Message::Quit);
+            Ok(())
+        },
+    },
+];
+
+/// One entry of the keymap: which keys trigger which action
+/// The description is what the help popup shows, so every binding documents itself
+pub(crate) struct Binding {
+    /// Heading the binding is listed under in the help popup
+    pub(crate) section: &'static str,
+    pub(crate) keys: &'static [Key],
+    pub(crate) action: Action,
+    pub(crate) description: &'static str,
+}
+
+/// The normal-mode keymap - the single source of truth for both dispatch and help
+/// Adding a binding here makes it work and shows it in the `?` popup
+pub(crate) const KEYMAP: &[Binding] = &[
+    // Support both arrow keys and vim-style navigation
+    // This accommodates different user preferences
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Down), Key::char('j')], action: Action::Next, description: "Move selection down" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Up), Key::char('k')], action: Action::Previous, description: "Move selection up" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::PageDown)], action: Action::PageDown, description: "Page down" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::PageUp)], action: Action::PageUp, description: "Page up" },
+    Binding { section: "Navigation", keys: &[Key::ctrl('d')], action: Action::HalfPageDown, description: "Half a page down" },
+    Binding { section: "Navigation", keys: &[Key::ctrl('u')], action: Action::HalfPageUp, description: "Half a page up" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Home)], action: Action::First, description: "Jump to first todo" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::End), Key::char('G')], action: Action::Last, description: "Jump to last todo" },
+    // Left/right fold and unfold subtasks like a file tree
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Right), Key::char('l')], action: Action::Expand, description: "Expand subtasks" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Left), Key::char('h')], action: Action::CollapseOrParent, description: "Collapse subtasks / go to parent" },
+    Binding { section: "Navigation", keys: &[Key::code(KeyCode::Tab)], action: Action::ToggleDetails, description: "Show or hide the detail pane" },
+    Binding { section: "Navigation", keys: &[Key::char('W')], action: Action::ToggleWrap, description: "Wrap long todos or cut them off" },
+    Binding { section: "Navigation", keys: &[Key::char('V')], action: Action::ToggleTable, description: "Show the list as a table (1-5: sort by column)" },
+    Binding { section: "Navigation", keys: &[Key::char('P')], action: Action::ProjectPicker, description: "Jump to a project" },
+    Binding { section: "Navigation", keys: &[Key::char('S')], action: Action::Stats, description: "Show statistics" },
+    Binding { section: "Navigation", keys: &[Key::char('B')], action: Action::Board, description: "Show the board (h/l: move card, Tab: next column)" },
+    Binding { section: "Editing", keys: &[Key::char('a')], action: Action::Add, description: "Add a todo (#tags are parsed)" },
+    Binding { section: "Editing", keys: &[Key::char('A')], action: Action::AddChild, description: "Add a subtask to the selected todo" },
+    Binding { section: "Editing", keys: &[Key::char('e')], action: Action::Edit, description: "Edit the selected todo" },
+    Binding { section: "Editing", keys: &[Key::char('E')], action: Action::EditNotes, description: "Edit notes" },
+    Binding { section: "Editing", keys: &[Key::char('o')], action: Action::ExternalEdit, description: "Open the todo and its notes in $EDITOR" },
+    Binding { section: "Editing", keys: &[Key::char(' ')], action: Action::Toggle, description: "Toggle completion" },
+    Binding { section: "Editing", keys: &[Key::char('p')], action: Action::CyclePriority, description: "Cycle priority" },
+    Binding { section: "Editing", keys: &[Key::char('i')], action: Action::CycleStatus, description: "Cycle status (todo, in progress, blocked, done, cancelled)" },
+    Binding { section: "Editing", keys: &[Key::char('*')], action: Action::ToggleStar, description: "Star the todo, which keeps it on top, or unstar it" },
+    Binding { section: "Editing", keys: &[Key::char('D')], action: Action::EditDue, description: "Set due date" },
+    Binding { section: "Editing", keys: &[Key::char('r')], action: Action::EditRecurrence, description: "Set recurrence" },
+    Binding { section: "Editing", keys: &[Key::char('H')], action: Action::Snooze, description: "Snooze the todo, hiding it until a date and time" },
+    Binding { section: "Editing", keys: &[Key::char('U')], action: Action::Snoozed, description: "Show the snoozed todos (r: wake up)" },
+    // Shifted navigation keys drag the selected todo along
+    Binding { section: "Editing", keys: &[Key::char('J')], action: Action::MoveDown, description: "Move todo down" },
+    Binding { section: "Editing", keys: &[Key::char('K')], action: Action::MoveUp, description: "Move todo up" },
+    Binding { section: "Editing", keys: &[Key::char('f')], action: Action::Pomodoro, description: "Start a 25/5 pomodoro on the todo, or stop it" },
+    Binding { section: "Editing", keys: &[Key::char('w')], action: Action::TrackTime, description: "Start / stop tracking time on the todo" },
+    Binding { section: "Editing", keys: &[Key::char('y')], action: Action::Copy, description: "Copy the todo's text to the clipboard" },
+    Binding { section: "Editing", keys: &[Key::char('d')], action: Action::Delete, description: "Move the selected todo to the trash" },
+    Binding { section: "Editing", keys: &[Key::char('C')], action: Action::ClearDone, description: "Move all completed todos to the trash" },
+    Binding { section: "Editing", keys: &[Key::char('.')], action: Action::Repeat, description: "Repeat the last change" },
+    Binding { section: "Editing", keys: &[Key::char('u')], action: Action::Undo, description: "Undo" },
+    Binding { section: "Editing", keys: &[Key::ctrl('r')], action: Action::Redo, description: "Redo" },
+    Binding { section: "Editing", keys: &[Key::char('x')], action: Action::Trash, description: "Show the trash (r: restore, d: delete, X: empty)" },
+    // Marked todos are what Space, d, + and m act on instead of the selection
+    Binding { section: "Bulk", keys: &[Key::char('v')], action: Action::Mark, description: "Mark / unmark the todo for a bulk operation" },
+    Binding { section: "Bulk", keys: &[Key::char('+')], action: Action::Tag, description: "Add a tag to the marked or selected todos" },
+    Binding { section: "Bulk", keys: &[Key::char('m')], action: Action::MoveToList, description: "Move the marked or selected todos to another list" },
+    Binding { section: "Archive", keys: &[Key::char('z')], action: Action::Archive, description: "Archive the selected todo" },
+    Binding { section: "Archive", keys: &[Key::char('Z')], action: Action::ArchiveCompleted, description: "Archive all completed todos" },
+    Binding { section: "Archive", keys: &[Key::char('b')], action: Action::BrowseArchive, description: "Browse the archive (r: restore)" },
+    Binding { section: "Filtering", keys: &[Key::char('/')], action: Action::Search, description: "Search as you type" },
+    // The list only contains matches, so n/N are just wrapping next/previous
+    Binding { section: "Filtering", keys: &[Key::char('n')], action: Action::NextMatch, description: "Next match" },
+    Binding { section: "Filtering", keys: &[Key::char('N')], action: Action::PreviousMatch, description: "Previous match" },
+    Binding { section: "Filtering", keys: &[Key::char('c')], action: Action::ToggleCompleted, description: "Hide or show completed todos" },
+    Binding { section: "Filtering", keys: &[Key::char('Y')], action: Action::ToggleUnstarted, description: "Show or hide todos whose start date is still ahead" },
+    Binding { section: "Filtering", keys: &[Key::char('t')], action: Action::TagFilter, description: "Filter by tag" },
+    Binding { section: "Filtering", keys: &[Key::char('F')], action: Action::SavedFilters, description: "Pick a saved filter (0-9: pick, s: save current, d: delete)" },
+    // Only vim's `gp` reaches this by default, the other presets bind it to g
+    Binding { section: "Filtering", keys: &[], action: Action::GroupByProject, description: "Group the list by project" },
+    Binding { section: "Filtering", keys: &[Key::char('s')], action: Action::CycleSort, description: "Cycle sort order (manual, A-Z, due, priority, newest, status, tags)" },
+    // Esc is the universal "get me back to everything" key
+    Binding { section: "Filtering", keys: &[Key::code(KeyCode::Esc)], action: Action::ClearFilter, description: "Clear marks, then search, then tag filter" },
+    // Number keys jump straight to a list, brackets step through them
+    Binding {
+        section: "Lists",
+        keys: &[
+            Key::char('1'), Key::char('2'), Key::char('3'), Key::char('4'), Key::char('5'),
+            Key::char('6'), Key::char('7'), Key::char('8'), Key::char('9'),
+        ],
+        action: Action::SwitchList,
+        description: "Switch to list by number",
+    },
+    Binding { section: "Lists", keys: &[Key::char(']')], action: Action::NextList, description: "Next list" },
+    Binding { section: "Lists", keys: &[Key::char('[')], action: Action::PreviousList, description: "Previous list" },
+    Binding { section: "Lists", keys: &[Key::char('L')], action: Action::NewList, description: "Create a list" },
+    Binding { section: "Lists", keys: &[Key::char('R')], action: Action::RenameList, description: "Rename the current list" },
+    Binding { section: "Lists", keys: &[Key::char('X')], action: Action::DeleteList, description: "Delete the current list" },
+    Binding { section: "Lists", keys: &[Key::char('M')], action: Action::Someday, description: "Park the todo on the someday list, or bring it back from there" },
+    Binding { section: "General", keys: &[Key::char(':')], action: Action::Command, description: "Enter a command (see Commands below)" },
+    Binding { section: "General", keys: &[Key::char('T')], action: Action::CycleTheme, description: "Cycle color theme" },
+    Binding { section: "General", keys: &[Key::code(KeyCode::F(5))], action: Action::Sync, description: "Sync, fetch the GitHub issues and check the mail now" },
+    Binding { section: "General", keys: &[Key::char('?')], action: Action::Help, description: "Show this help" },
+    Binding { section: "General", keys: &[Key::char('q')], action: Action::Quit, description: "Quit" },
+];
+
+/// Keys of a binding as shown in help - long runs like 1..9 are abbreviated
+pub(crate) fn keys_label(keys: &[Key]) -> String {
+    match keys {
+        [first, .., last] if keys.len() > 3 => format!("{}-{}", first.label(), last.label()),
+        keys => keys.iter().map(|k| k.label()).collect::<Vec<_>>().join(" / "),
+    }
+}
+
+// The parts of App behind the input line: editing it, submitting it, and the
+// `:` prompt's completion and history
+impl App {
+    /// Opens the `:` prompt for typing a command
+    fn start_command(&mut self) {
+        self.input_target = InputTarget::Command;
+        self.history_position = None;
+        self.mode = Mode::Input;
+    }
+
+    /// Runs the command typed at the `:` prompt and remembers it in the history
+    /// A command that fails keeps the prompt open with the error in its title
+    fn run_command_line(&mut self) {
+        let line = self.input.trim().to_string();
+        if line.is_empty() {
+            self.cancel_input();
+            return;
+        }
+        // Failed commands are remembered too, so they can be recalled and fixed
+        if self.command_history.last() != Some(&line) {
+            self.command_history.push(line.clone());
+            if self.command_history.len() > COMMAND_HISTORY_LIMIT {
+                self.command_history.remove(0);
+            }
+            self.save_view_state();
+        }
+        let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+        let argument = argument.trim();
+        let Some(command) = COMMANDS.iter().find(|c| c.name == name || c.alias == Some(name)) else {
+            self.input_error = Some(format!("unknown command '{}' (Tab lists them)", name));
+            return;
+        };
+        // The command may open another prompt, so leave this one first
+        self.cancel_input();
+        if let Err(err) = (command.run)(self, argument) {
+            self.start_command();
+            self.input = line;
+            self.input_error = Some(err);
+        }
+    }
+
+    /// Completions for the word being typed at the end of a command line: the
+    /// command name, or the first argument of commands with a fixed set of them
+    /// Returns the byte offset the word starts at and the candidates
+    pub(crate) fn command_completions(&self, line: &str) -> (usize, Vec<String>) {
+        let Some((name, argument)) = line.split_once(' ') else {
+            let names = COMMANDS.iter().map(|c| c.name).filter(|n| n.starts_with(line));
+            return (0, names.map(String::from).collect());
+        };
+        let options: Vec<String> = match name {
+            "sort" => SortMode::ALL.iter().map(|m| m.name().to_string()).collect(),
+            "export" => EXPORT_FORMATS.iter().map(|f| f.to_string()).collect(),
+            "archive" => vec!["done".to_string()],
+            "clear-done" => vec!["archive".to_string()],
+            "open" => self.lists.iter().map(|l| l.name.clone()).collect(),
+            "theme" => self.themes.iter().map(|(n, _)| n.clone()).collect(),
+            _ => Vec::new(),
+        };
+        let typed = argument.to_lowercase();
+        (name.len() + 1, options.into_iter().filter(|o| o.to_lowercase().starts_with(&typed)).collect())
+    }
+
+    /// Completes the command line as far as the candidates agree, like a shell
+    /// A unique command name gets a trailing space, ready for its argument
+    fn complete_command(&mut self) {
+        let (start, options) = self.command_completions(&self.input);
+        let Some(first) = options.first() else {
+            return;
+        };
+        let common = options.iter().fold(first.as_str(), |prefix, option| {
+            let len = prefix.chars().zip(option.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum();
+            &prefix[..len]
+        });
+        // Candidates match case-insensitively, so a shorter common prefix than
+        // what's typed would throw the typing away
+        if common.len() < self.input.len() - start {
+            return;
+        }
+        let mut line = format!("{}{}", &self.input[..start], common);
+        if options.len() == 1 && start == 0 {
+            line.push(' ');
+        }
+        self.input = line;
+        self.input_cursor = None;
+    }
+
+    /// Recalls an older (-1) or newer (+1) command from the history
+    /// Going past the newest entry goes back to an empty line
+    fn recall_command(&mut self, delta: isize) {
+        if self.command_history.is_empty() {
+            return;
+        }
+        let last = self.command_history.len() - 1;
+        self.history_position = match (self.history_position, delta < 0) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.input = self.history_position.map(|i| self.command_history[i].clone()).unwrap_or_default();
+        self.input_cursor = None;
+        self.input_error = None;
+    }
+
+    /// Maps a screen position to (visible row, column within the row)
+    /// Returns None for positions on the border or below the last todo
+    fn hit_test(&self, x: u16, y: u16) -> Option<(usize, u16)> {
+        let mut inner = self.list_area.inner(Margin { vertical: 1, horizontal: 1 });
+        // The table's column titles take the first line
+        if self.table {
+            inner.y += 1;
+            inner.height = inner.height.saturating_sub(1);
+        }
+        if !inner.contains(Position { x, y }) {
+            return None;
+        }
+        // Drawn rows count project headers too
+        let line = (y - inner.y) as usize;
+        let drawn = *self.list_lines.get(line)?;
+        // Further lines of a wrapped todo have no fold marker or checkbox, so a
+        // click there only selects it, like one on the highlight symbol
+        let column = if line > 0 && self.list_lines[line - 1] == drawn { 0 } else { x - inner.x };
+        match self.rows.get(drawn) {
+            Some(&ListRow::Todo(row)) => Some((row, column)),
+            _ => None,
+        }
+    }
+
+    /// Switches lists when a count was typed and no key followed it for a moment
+    /// Number keys switch lists, so a number on its own still does that
+    /// Returns whether it did anything
+    pub(crate) fn resolve_pending_count(&mut self) -> bool {
+        if self.pending_keys.prefix.is_none()
+            && let (Some(count), Some(since)) = (self.pending_keys.count, self.pending_keys.since)
+            && since.elapsed() >= COUNT_TIMEOUT
+        {
+            self.pending_keys = PendingKeys::default();
+            if (1..=self.lists.len()).contains(&count) {
+                self.switch_list(count - 1);
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Runs the last change again, `count` times if given, on the current selection
+    fn repeat_last_change(&mut self, count: Option<usize>) {
+        match self.last_change.clone() {
+            Some(Repeat::Action(action, times)) => {
+                action.perform_counted(self, KeyCode::Null, Some(count.unwrap_or(times)));
+            }
+            Some(Repeat::Input(target, text)) => {
+                // The prompt was for the todo selected then - point it at today's
+                let Some(path) = self.selected_path() else {
+                    return;
+                };
+                for _ in 0..count.unwrap_or(1) {
+                    self.input_target = match &target {
+                        InputTarget::Due(_) => InputTarget::Due(path.clone()),
+                        InputTarget::Snooze(_) => InputTarget::Snooze(path.clone()),
+                        InputTarget::Recurrence(_) => InputTarget::Recurrence(path.clone()),
+                        other => other.clone(),
+                    };
+                    self.input = text.clone();
+                    self.submit_input();
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Commits the input buffer as a new todo, a subtask, or an edit
+    /// Dispatching here keeps the Enter key handler the same for every case
+    fn submit_input(&mut self) {
+        // Prompts that change the selected todos can be repeated with `.`
+        if matches!(
+            self.input_target,
+            InputTarget::Tag | InputTarget::Due(_) | InputTarget::Snooze(_) | InputTarget::Recurrence(_)
+        ) {
+            self.last_change = Some(Repeat::Input(self.input_target.clone(), self.input.clone()));
+        }
+        match self.input_target.clone() {
+            InputTarget::New => self.submit_message(Message::Add),
+            InputTarget::Child(parent) => self.submit_message(|text| Message::AddChild(parent, text)),
+            InputTarget::Edit(path) => self.submit_message(|text| Message::Edit(path, text)),
+            InputTarget::Notes(path) => self.apply_notes(path),
+            InputTarget::Due(path) => {
+                self.apply_due(path);
+                // A date picked in the daily review answers it for that todo
+                if self.mode == Mode::Review {
+                    self.next_review();
+                }
+            }
+            InputTarget::Snooze(path) => self.apply_snooze(path),
+            InputTarget::Recurrence(path) => self.apply_recurrence(path),
+            InputTarget::NewList => self.add_list(),
+            InputTarget::RenameList => self.apply_rename_list(),
+            InputTarget::Tag => self.apply_tag(),
+            InputTarget::MoveToList => self.apply_move_to_list(),
+            InputTarget::SaveFilter => self.apply_save_filter(),
+            InputTarget::TagStyle(tag) => self.apply_tag_style(tag),
+            InputTarget::Command => self.run_command_line(),
+            InputTarget::Passphrase(first) => self.apply_passphrase(first),
+        }
+    }
+
+    /// Closes the prompt and sends what was typed as a message
+    /// An empty prompt stays open rather than adding or saving a blank todo
+    fn submit_message(&mut self, message: impl FnOnce(String) -> Message) {
+        if self.input.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.input);
+        self.cancel_input();
+        update(self, message(text));
+    }
+
+    /// Leaves input mode without changing any todos
+    pub(crate) fn cancel_input(&mut self) {
+        // The daily review's date prompt goes back to the review
+        self.mode = if self.review.is_empty() { Mode::Normal } else { Mode::Review };
+        self.input.clear();
+        self.input_error = None;
+        self.input_cursor = None;
+        self.input_target = InputTarget::New;
+    }
+
+    /// Byte offset of the cursor in the input buffer, always on a grapheme boundary
+    pub(crate) fn cursor(&self) -> usize {
+        self.input_cursor.unwrap_or(self.input.len()).min(self.input.len())
+    }
+
+    /// Applies an editing key to the input buffer: typing inserts at the cursor,
+    /// arrows move it (by word with Ctrl), Home/End jump, Backspace/Delete erase
+    /// Shared by the input bar and the notes editor
+    /// Steps are whole grapheme clusters, so an emoji with modifiers or a letter
+    /// with combining accents is moved over and erased as the single symbol it looks like
+    fn edit_input(&mut self, key: KeyEvent) {
+        // Any change means the user is fixing whatever the error was about
+        self.input_error = None;
+        let at = self.cursor();
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let before = &self.input[..at];
+        let after = &self.input[at..];
+        let prev = before.graphemes(true).next_back().map_or(at, |g| at - g.len());
+        let next = after.graphemes(true).next().map_or(at, |g| at + g.len());
+        let cursor = match key.code {
+            KeyCode::Char(c) if !ctrl => {
+                self.input.insert(at, c);
+                at + c.len_utf8()
+            }
+            KeyCode::Backspace => {
+                self.input.replace_range(prev..at, "");
+                prev
+            }
+            KeyCode::Delete => {
+                self.input.replace_range(at..next, "");
+                at
+            }
+            // Word-wise: to the start of the current or previous word
+            KeyCode::Left if ctrl => before.trim_end().rfind(char::is_whitespace).map_or(0, |i| i + 1),
+            // ...and to the start of the next word
+            KeyCode::Right if ctrl => {
+                let word_end = after.find(char::is_whitespace).unwrap_or(after.len());
+                let gap = after[word_end..].len() - after[word_end..].trim_start().len();
+                at + word_end + gap
+            }
+            KeyCode::Left => prev,
+            KeyCode::Right => next,
+            KeyCode::Home => 0,
+            KeyCode::End => self.input.len(),
+            _ => return,
+        };
+        self.input_cursor = Some(cursor);
+    }
+
+    /// Inserts pasted text where the user is typing
+    /// The input bar and search are single-line, so line breaks become spaces there;
+    /// the notes editor keeps them (normalized, since terminals often send \r)
+    pub(crate) fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.mode {
+            Mode::Notes => {
+                let at = self.cursor();
+                self.input.insert_str(at, &text);
+                self.input_cursor = Some(at + text.len());
+            }
+            Mode::Input => {
+                let text = text.trim_end_matches('\n').replace('\n', " ");
+                let at = self.cursor();
+                self.input.insert_str(at, &text);
+                self.input_cursor = Some(at + text.len());
+            }
+            Mode::Search => {
+                let selected = self.selected_path();
+                self.search.push_str(&text.trim_end_matches('\n').replace('\n', " "));
+                self.refresh_and_reselect(selected);
+            }
+            // Pasting while navigating would be surprising, so it's ignored
+            _ => {}
+        }
+    }
+}
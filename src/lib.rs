@@ -0,0 +1,283 @@
+//! A terminal todo list - the library behind the `todo-tui` binary
+//! The interface is started with `run`, the rest is the model it works on
+
+// Crossterm provides cross-platform terminal manipulation (raw mode, events, etc.)
+// We need these specific imports to handle terminal state and capture user input
+use crossterm::{
+    cursor::Show,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event,
+        KeyEventKind, MouseEventKind,
+    },
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+mod app;
+mod caldav;
+mod crypto;
+mod email;
+mod error;
+mod github;
+mod google;
+mod history;
+mod hooks;
+mod i18n;
+mod import;
+mod input;
+mod logging;
+#[cfg(feature = "lua")]
+mod lua;
+#[cfg(feature = "crdt")]
+mod oplog;
+mod plugins;
+mod remote;
+mod remote_file;
+mod row_format;
+mod search;
+mod server;
+mod storage;
+mod sync;
+mod ui;
+mod update;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+// What the binary and tests build on: the state, the todo model and the
+// pieces of it the command-line subcommands need
+pub use app::{
+    collect_due, find_item, format_agenda, format_duration, format_status, format_todo_number, parse_todo_number, send_notification, App,
+    ArchivedTodo, Command, Date, DateStyle, Priority, Recurrence, Status, Time, Timestamp, TodoItem, TodoList, TodoPath,
+};
+pub use error::AppError;
+pub use google::google_login;
+pub use input::KeymapPreset;
+pub use remote_file::{RemoteFile, RemoteNotice};
+pub use server::serve;
+pub use storage::{Config, ViewState};
+pub use ui::ui;
+pub use update::{update, Message};
+
+use crate::update::tick;
+
+/// Takes over the terminal, runs the interface until the user quits, then
+/// gives the terminal back the way it was
+pub fn run(mut app: App) -> io::Result<()> {
+    // Decided before the first frame so the status bar is right from the start
+    app.acquire_lock();
+    logging::init(app.log_level);
+    app.watch_file();
+    tracing::info!("started with {}", app.save_path.display());
+
+    // A panic message is printed by the hook, before unwinding reaches the
+    // guard - restoring first keeps it from vanishing with the alternate screen
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = leave_terminal();
+        default_hook(info);
+    }));
+
+    // CRITICAL: Always restore terminal state, even if app crashes
+    // The guard does it when this function returns, early returns and panics included
+    let guard = TerminalGuard;
+    enter_terminal()?;
+
+    // Create terminal backend - CrosstermBackend works on Windows, Linux, and macOS
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    let result = run_app(&mut terminal, &mut app);
+    // Saving waits for a pause in typing, so the last changes may not be on
+    // disk yet - they're written even if the loop ended with an error
+    let saved = app.flush();
+    // Hooks still running get to finish, and failures that came too late to
+    // show are printed once the terminal is back
+    let failures = app.wait_for_hooks();
+    drop(guard);
+    for failure in failures {
+        eprintln!("warning: {}", failure);
+    }
+    match &result {
+        Ok(()) => tracing::info!("quit"),
+        Err(err) => tracing::error!("stopped: {}", err),
+    }
+    result?;
+    saved.map_err(io::Error::other)
+}
+
+/// Restores the terminal when dropped, so no way out of `run` - an error
+/// returned with `?` or a panic unwinding - leaves the shell in raw mode
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // Nothing sensible to do if the terminal won't reset while we're exiting
+        let _ = leave_terminal();
+    }
+}
+
+/// Puts the terminal into the state the interface runs in
+fn enter_terminal() -> io::Result<()> {
+    // Enable raw mode to read input directly without waiting for Enter
+    // This is essential for responsive TUI - we need to react to every keypress
+    enable_raw_mode()?;
+    // Enter alternate screen to preserve user's terminal history
+    // Mouse capture lets us handle clicks and the scroll wheel
+    // Bracketed paste delivers a paste as one event instead of a burst of keypresses
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)
+}
+
+/// Undoes `enter_terminal` and shows the cursor ratatui hid
+/// Running it twice is harmless, which the panic hook and the guard rely on
+fn leave_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste, Show)
+}
+
+/// How long the loop waits for an event before running the timers anyway
+/// Short enough for a save to start soon after typing pauses, long enough
+/// that an idle interface costs next to nothing
+const TICK: Duration = Duration::from_millis(250);
+
+/// Main application loop - handles rendering and input
+/// We use a generic backend so this could work with different terminal implementations
+/// The screen is only drawn when something changed: after an event, when a
+/// tick had news, or when a clock on screen has moved on
+fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> io::Result<()> {
+    let mut redraw = true;
+    let mut last_frame = Instant::now();
+    loop {
+        if redraw || last_frame.elapsed() >= app.redraw_interval() {
+            // Render the UI - this closure is called with a Frame we can draw to
+            let started = Instant::now();
+            tracing::trace_span!("frame").in_scope(|| terminal.draw(|f| ui(f, app)))?;
+            app.record_frame(started.elapsed());
+            last_frame = Instant::now();
+            redraw = false;
+        }
+
+        // Timers are checked every iteration but only do work when they're due
+        redraw |= tick(app);
+
+        if let Some(path) = app.external_edit.take() {
+            edit_externally(terminal, app, path)?;
+            redraw = true;
+            continue;
+        }
+        if let Some(path) = app.raw_edit.take() {
+            edit_raw_file(terminal, app, &path)?;
+            redraw = true;
+            continue;
+        }
+
+        // Sleeps until there's an event, waking up every tick for the timers
+        if event::poll(TICK)? {
+            // Terminal events become messages - everything after that is `update`
+            let message = match event::read()? {
+                // CRITICAL: Only process key press events, not release events
+                // Some terminals send both Press and Release, which would cause double input
+                Event::Key(key) if key.kind == KeyEventKind::Press => Message::Key(key),
+                // Mouse capture reports every move of the pointer, which nothing
+                // reacts to - redrawing for each would undo the point of waiting
+                Event::Mouse(mouse) if mouse.kind == MouseEventKind::Moved => continue,
+                Event::Mouse(mouse) => Message::Mouse(mouse),
+                Event::Paste(text) => Message::Paste(text),
+                // The layout depends on the size, so it's drawn again to fit
+                Event::Resize(width, height) => Message::Resize(width, height),
+                // Key releases and focus changes don't change anything
+                _ => continue,
+            };
+            update(app, message);
+            redraw = true;
+            if app.should_quit {
+                return Ok(()); // Exit cleanly
+            }
+        }
+    }
+}
+
+/// Hands the todo to the user's editor with the terminal given back to it,
+/// then restores the interface and applies what was saved
+/// Problems with the editor itself are shown as a notice, only failing to
+/// restore the terminal is an error
+fn edit_externally<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    path: TodoPath,
+) -> io::Result<()> {
+    let text = app.external_edit_text(&path);
+    leave_terminal()?;
+    let edited = run_editor(&text);
+    enter_terminal()?;
+    // The editor drew over everything, so the next frame has to be drawn in full
+    terminal.clear()?;
+
+    match edited {
+        Ok(contents) => app.apply_external_edit(path, &contents),
+        Err(err) => app.notice = Some(app.trf("Editor failed: {}", &[&err])),
+    }
+    Ok(())
+}
+
+/// Opens a file that couldn't be loaded in the user's editor, then tries
+/// loading it again - the recovery dialog shows whatever is still wrong
+fn edit_raw_file<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App, path: &Path) -> io::Result<()> {
+    leave_terminal()?;
+    let result = open_in_editor(path);
+    enter_terminal()?;
+    terminal.clear()?;
+
+    match result {
+        Ok(()) => app.retry_load(),
+        Err(err) => app.notice = Some(app.trf("Editor failed: {}", &[&err])),
+    }
+    Ok(())
+}
+
+/// Opens text in the user's editor and returns the saved file once the
+/// editor exits
+/// The file lives in a directory of its own that only the user can open, and
+/// is made afresh rather than opened - the todo may come from an encrypted
+/// file, and a name someone else got to first mustn't be written through
+fn run_editor(text: &str) -> io::Result<String> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("todo-tui-");
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+    // Removed with everything in it when dropped, however this returns
+    let dir = builder.tempdir()?;
+    // Markdown gets notes highlighted in most editors
+    let file = dir.path().join("todo.md");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&file)?.write_all(text.as_bytes())?;
+    open_in_editor(&file)?;
+    fs::read_to_string(&file)
+}
+
+/// Runs `$VISUAL` or `$EDITOR` (falling back to vi) on a file and waits for it
+/// The variable may hold arguments too, like `code --wait`
+fn open_in_editor(file: &Path) -> io::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    match std::process::Command::new(program).args(words).arg(file).status()? {
+        status if status.success() => Ok(()),
+        status => Err(io::Error::other(format!("{} exited with {}", program, status))),
+    }
+}
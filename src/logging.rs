@@ -0,0 +1,161 @@
+//! The log file, for finding out what happened when something goes wrong
+//! The interface owns stdout, so lines go to `todo-tui.log` in the data
+//! directory instead, and the last few are kept for the debug overlay
+
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::{format::FmtSpan, format::Writer, time::FormatTime},
+    layer::SubscriberExt,
+};
+
+use crate::{app::Date, storage::data_dir};
+
+/// Lines kept in memory for the debug overlay
+const RECENT: usize = 200;
+
+/// Size past which the log starts over, keeping the previous one as
+/// `todo-tui.log.old` - a log nobody reads shouldn't grow forever
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// How much goes into the log, from nothing to every key press
+/// Key presses include the text typed into todos, so they only show up at
+/// debug and below, which the user has to ask for
+/// Saves say how long they took from info on, every frame drawn at trace
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Where the formatted lines go: the log file, and the most recent ones to
+/// memory
+struct Sink {
+    /// None when the file couldn't be opened - the overlay still gets the lines
+    file: Mutex<Option<File>>,
+    recent: Mutex<VecDeque<String>>,
+}
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// The subscriber writes each event whole, line break and all
+impl Write for &Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A panic elsewhere while holding a lock doesn't make the log useless
+        if let Some(file) = self.file.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            // Nowhere left to report a failure to write the log
+            let _ = file.write_all(buf);
+        }
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        for line in String::from_utf8_lossy(buf).lines() {
+            if recent.len() == RECENT {
+                recent.pop_front();
+            }
+            recent.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Times in UTC to the millisecond, like the save file's timestamps
+struct Utc;
+
+impl FormatTime for Utc {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", now())
+    }
+}
+
+/// Where the log is written, next to the default todo file
+pub(crate) fn log_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("todo-tui.log"))
+}
+
+/// Starts logging at the given level, for the rest of the process
+/// Only the interface logs - the command line prints what went wrong itself
+/// A log that can't be opened is no reason not to start, so it's only missed
+pub(crate) fn init(level: LogLevel) {
+    if level == LogLevel::Off || SINK.get().is_some() {
+        return;
+    }
+    let file = log_path().and_then(|path| {
+        if fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_SIZE) {
+            let _ = fs::rename(&path, path.with_extension("log.old"));
+        }
+        fs::create_dir_all(path.parent()?).ok()?;
+        OpenOptions::new().create(true).append(true).open(path).ok()
+    });
+    let sink = SINK.get_or_init(|| Sink { file: Mutex::new(file), recent: Mutex::new(VecDeque::new()) });
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || sink)
+        .with_ansi(false)
+        .with_target(false)
+        .with_timer(Utc)
+        // A span's end is logged with how long it took, which is what the
+        // spans around saving and drawing are for
+        .with_span_events(FmtSpan::CLOSE)
+        .with_max_level(level.filter())
+        .finish()
+        // Only this crate's own events - what the dependencies log is noise
+        // in a report about todo-tui
+        .with(Targets::new().with_target("todo_tui", level.filter()));
+    // Fails only if a subscriber is set already, which only ever happens here
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+/// The most recent log lines, oldest first
+pub(crate) fn recent() -> Vec<String> {
+    SINK.get().map_or_else(Vec::new, |sink| {
+        sink.recent.lock().unwrap_or_else(PoisonError::into_inner).iter().cloned().collect()
+    })
+}
+
+/// The current time in UTC to the millisecond, like the save file's timestamps
+fn now() -> String {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        Date::from_days(secs.div_euclid(86_400)),
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,289 @@
+//! Lua plugins, the trusted kind - `*.lua` files in the plugins folder
+//! Each runs once at start and hooks itself up through the `todo` table:
+//! `bind`, `on_add`, `on_complete`, `filter` and `status`. Todos are handed
+//! over as tables with the fields of the JSON file, and a function that
+//! returns one changed gets it applied. Plugins run with the whole standard
+//! library, so like hooks they're trusted
+//! Built with the `lua` feature, which is on by default
+
+use mlua::{Function, Lua, LuaSerdeExt, RegistryKey, SerializeOptions, Table, Value};
+use serde::Serialize;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    app::{TodoItem, TodoList},
+    input::Key,
+};
+
+/// A key bound with `todo.bind(key, description, fn)` - `fn` gets the
+/// selected todo, or nil when there isn't one
+struct Binding {
+    key: Key,
+    description: String,
+    function: RegistryKey,
+}
+
+/// What the plugins registered, with the functions kept in the Lua registry
+#[derive(Default)]
+struct Registry {
+    bindings: Vec<Binding>,
+    /// `todo.on_add(fn)` - `fn` gets each todo as it's added
+    on_add: Vec<RegistryKey>,
+    /// `todo.on_complete(fn)` - `fn` gets each todo as it's completed
+    on_complete: Vec<RegistryKey>,
+    /// `todo.filter(name, fn)` - a filter in the picker keeping the todos
+    /// `fn` returns true for
+    filters: Vec<(String, RegistryKey)>,
+    /// `todo.status(fn)` - `fn` gets the current list and returns text for
+    /// the status bar, or nil for none
+    status: Vec<RegistryKey>,
+}
+
+/// The Lua state the plugins run in and what they registered
+pub(crate) struct LuaPlugins {
+    lua: Lua,
+    registry: Rc<RefCell<Registry>>,
+    /// The first failure of a filter, which runs where nothing can be shown -
+    /// the view picks it up once it's built
+    failure: RefCell<Option<String>>,
+}
+
+impl Default for LuaPlugins {
+    fn default() -> LuaPlugins {
+        let lua = Lua::new();
+        let registry = Rc::new(RefCell::new(Registry::default()));
+        // Only fails when Lua is out of memory, and then nothing would work
+        install(&lua, &registry).expect("setting up the plugin API");
+        LuaPlugins { lua, registry, failure: RefCell::new(None) }
+    }
+}
+
+/// Puts the `todo` table the plugins register with into the globals
+fn install(lua: &Lua, registry: &Rc<RefCell<Registry>>) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+    let registered = registry.clone();
+    let bind = move |lua: &Lua, (key, description, function): (String, String, Function)| {
+        let key = Key::parse(&key).map_err(mlua::Error::RuntimeError)?;
+        let function = lua.create_registry_value(function)?;
+        registered.borrow_mut().bindings.push(Binding { key, description, function });
+        Ok(())
+    };
+    api.set("bind", lua.create_function(bind)?)?;
+    let registered = registry.clone();
+    let filter = move |lua: &Lua, (name, function): (String, Function)| {
+        let function = lua.create_registry_value(function)?;
+        registered.borrow_mut().filters.push((name, function));
+        Ok(())
+    };
+    api.set("filter", lua.create_function(filter)?)?;
+    register(lua, &api, "on_add", registry, |registry| &mut registry.on_add)?;
+    register(lua, &api, "on_complete", registry, |registry| &mut registry.on_complete)?;
+    register(lua, &api, "status", registry, |registry| &mut registry.status)?;
+    lua.globals().set("todo", api)
+}
+
+/// Adds a function to the `todo` table that registers a plugin function
+/// with the list `functions` picks
+fn register(
+    lua: &Lua,
+    api: &Table,
+    name: &str,
+    registry: &Rc<RefCell<Registry>>,
+    functions: fn(&mut Registry) -> &mut Vec<RegistryKey>,
+) -> mlua::Result<()> {
+    let registered = registry.clone();
+    let register = move |lua: &Lua, function: Function| {
+        let function = lua.create_registry_value(function)?;
+        functions(&mut registered.borrow_mut()).push(function);
+        Ok(())
+    };
+    api.set(name, lua.create_function(register)?)
+}
+
+/// Fields that aren't set are left out of the tables rather than set to a
+/// null value the plugins would have to know about
+fn options() -> SerializeOptions {
+    SerializeOptions::new().serialize_none_to_null(false).serialize_unit_to_null(false)
+}
+
+/// The first line of a Lua error - the traceback after it doesn't fit a notice
+fn message(err: mlua::Error) -> String {
+    err.to_string().lines().next().unwrap_or_default().to_string()
+}
+
+impl LuaPlugins {
+    /// Runs one plugin's source
+    pub(crate) fn load(&self, name: &str, source: &str) -> Result<(), String> {
+        self.lua.load(source).set_name(name).exec().map_err(message)
+    }
+
+    /// The plugin binding for a key, if there is one
+    pub(crate) fn binding(&self, key: Key) -> Option<usize> {
+        self.registry.borrow().bindings.iter().position(|binding| binding.key == key)
+    }
+
+    /// The keys the plugins bound with their descriptions, for the help
+    pub(crate) fn bindings(&self) -> Vec<(Key, String)> {
+        self.registry.borrow().bindings.iter().map(|binding| (binding.key, binding.description.clone())).collect()
+    }
+
+    /// Runs a binding's function on the selected todo, returning the todo it
+    /// changed, if it did
+    pub(crate) fn run_binding(&self, index: usize, todo: Option<&TodoItem>) -> Result<Option<TodoItem>, String> {
+        let function = self.function(|registry| &registry.bindings[index].function).map_err(message)?;
+        match todo {
+            Some(todo) => self.call(&function, todo).map_err(message),
+            None => function.call::<_, ()>(Value::Nil).map(|()| None).map_err(message),
+        }
+    }
+
+    /// The todo after the `on_add` functions, each getting what the one
+    /// before it returned
+    pub(crate) fn added(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        self.transform(|registry| &registry.on_add, todo)
+    }
+
+    /// The todo after the `on_complete` functions
+    pub(crate) fn completed(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        self.transform(|registry| &registry.on_complete, todo)
+    }
+
+    fn transform(&self, functions: fn(&Registry) -> &Vec<RegistryKey>, mut todo: TodoItem) -> Result<TodoItem, String> {
+        let count = functions(&self.registry.borrow()).len();
+        for i in 0..count {
+            let function = self.function(|registry| &functions(registry)[i]).map_err(message)?;
+            if let Some(changed) = self.call(&function, &todo).map_err(message)? {
+                todo = changed;
+            }
+        }
+        Ok(todo)
+    }
+
+    /// The names of the filters the plugins added, in the order they were added
+    pub(crate) fn filter_names(&self) -> Vec<String> {
+        self.registry.borrow().filters.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Whether a todo passes a plugin's filter
+    /// A failing filter lets nothing through, and `take_failure` has why
+    pub(crate) fn filter(&self, index: usize, todo: &TodoItem) -> bool {
+        let passes = self.function(|registry| &registry.filters[index].1).and_then(|function| {
+            let todo = self.lua.to_value_with(todo, options())?;
+            function.call::<_, bool>(todo)
+        });
+        passes.unwrap_or_else(|err| {
+            self.failure.borrow_mut().get_or_insert_with(|| message(err));
+            false
+        })
+    }
+
+    /// What went wrong in a filter since the last time this was asked
+    pub(crate) fn take_failure(&self) -> Option<String> {
+        self.failure.take()
+    }
+
+    /// The status bar segments for a list, leaving out what returned nil
+    pub(crate) fn status(&self, list: &TodoList) -> Result<Vec<String>, String> {
+        let count = self.registry.borrow().status.len();
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let list = self.to_lua(list).map_err(message)?;
+        let mut segments = Vec::new();
+        for i in 0..count {
+            let function = self.function(|registry| &registry.status[i]).map_err(message)?;
+            segments.extend(function.call::<_, Option<String>>(list.clone()).map_err(message)?);
+        }
+        Ok(segments)
+    }
+
+    /// A registered function, fetched before calling it so the registry
+    /// isn't borrowed while the plugin runs and maybe registers more
+    fn function(&self, key: impl Fn(&Registry) -> &RegistryKey) -> mlua::Result<Function<'_>> {
+        self.lua.registry_value(key(&self.registry.borrow()))
+    }
+
+    /// Calls a function with a todo, reading back the todo it returns
+    fn call(&self, function: &Function, todo: &TodoItem) -> mlua::Result<Option<TodoItem>> {
+        match function.call::<_, Value>(self.to_lua(todo)?)? {
+            Value::Nil => Ok(None),
+            changed => self.lua.from_value(changed).map(Some),
+        }
+    }
+
+    fn to_lua(&self, value: &impl Serialize) -> mlua::Result<Value<'_>> {
+        self.lua.to_value_with(value, options())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Priority;
+
+    fn plugins(source: &str) -> LuaPlugins {
+        let plugins = LuaPlugins::default();
+        plugins.load("test.lua", source).unwrap();
+        plugins
+    }
+
+    #[test]
+    fn functions_get_todos_and_return_them_changed() {
+        let plugins = plugins(
+            r#"
+            todo.on_add(function(t) t.text = t.text:upper(); return t end)
+            todo.on_add(function(t) table.insert(t.tags, "inbox") end)
+            todo.on_add(function(t) t.priority = "High"; return t end)
+            "#,
+        );
+        let added = plugins.added(TodoItem::new("call mom")).unwrap();
+        assert_eq!(added.text, "CALL MOM");
+        // Returning nothing leaves the todo as it was, changes to the table included
+        assert!(added.tags.is_empty());
+        assert!(added.priority == Priority::High);
+        // Without functions for the event the todo goes through untouched
+        assert_eq!(plugins.completed(TodoItem::new("x")).unwrap().text, "x");
+    }
+
+    #[test]
+    fn bindings_filters_and_status_segments_are_registered() {
+        let plugins = plugins(
+            r#"
+            todo.bind("ctrl+t", "Mark as today", function(t)
+                if t then t.text = t.text .. " today"; return t end
+            end)
+            todo.filter("Short", function(t) return #t.text < 5 end)
+            todo.status(function(list) return #list.todos .. " in " .. list.name end)
+            todo.status(function(list) return nil end)
+            "#,
+        );
+        let key = Key::parse("ctrl+t").unwrap();
+        assert_eq!(plugins.binding(key), Some(0));
+        assert!(plugins.binding(Key::char('t')).is_none());
+        let changed = plugins.run_binding(0, Some(&TodoItem::new("call"))).unwrap();
+        assert_eq!(changed.unwrap().text, "call today");
+        assert!(plugins.run_binding(0, None).unwrap().is_none());
+
+        assert_eq!(plugins.filter_names(), ["Short"]);
+        assert!(plugins.filter(0, &TodoItem::new("milk")));
+        assert!(!plugins.filter(0, &TodoItem::new("groceries")));
+
+        let list = TodoList { todos: vec![TodoItem::new("a"), TodoItem::new("b")], ..TodoList::new("home") };
+        assert_eq!(plugins.status(&list).unwrap(), ["2 in home"]);
+    }
+
+    #[test]
+    fn failures_are_reported_without_the_traceback() {
+        let plugins = LuaPlugins::default();
+        let err = plugins.load("broken.lua", "todo.bind('not a key', 'x', function() end)").unwrap_err();
+        assert!(err.contains("not a key") && !err.contains('\n'), "{}", err);
+
+        plugins.load("test.lua", "todo.filter('Boom', function(t) error('boom') end)").unwrap();
+        assert!(!plugins.filter(0, &TodoItem::new("x")));
+        assert!(plugins.take_failure().unwrap().contains("boom"));
+        assert!(plugins.take_failure().is_none());
+
+        plugins.load("test.lua", "todo.on_complete(function(t) return { text = {} } end)").unwrap();
+        assert!(plugins.completed(TodoItem::new("x")).is_err());
+    }
+}
@@ -1,414 +1,773 @@
-// Crossterm provides cross-platform terminal manipulation (raw mode, events, etc.)
-// We need these specific imports to handle terminal state and capture user input
+//! The `todo-tui` command: parses the arguments, then either runs a headless
+//! subcommand or hands the loaded todos to the interface in the library
+
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
 };
 
-// Ratatui is our TUI framework - it provides widgets and layout management
-// We import specific components we need rather than using glob imports for clarity
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
-    Terminal,
+use todo_tui::{
+    collect_due, find_item, format_agenda, format_duration, format_status, format_todo_number, google_login, parse_todo_number, send_notification, serve,
+    App, AppError, Command, Config, Date, DateStyle, KeymapPreset, RemoteFile, Status, Timestamp, TodoItem, TodoPath, ViewState,
 };
 
-// Serde provides serialization/deserialization for saving todos to disk
-// We import the derive macros to automatically implement these traits
-use serde::{Deserialize, Serialize};
-use std::{error::Error, fs, io, path::PathBuf};
-
-/// Represents a single todo item in our list
-/// We derive Clone because we need to copy TodoItems when rendering the UI
-/// Serialize and Deserialize allow us to save/load todos from JSON files
-#[derive(Clone, Serialize, Deserialize)]
-struct TodoItem {
-    text: String,
-    completed: bool,
+/// Usage text printed for --help and after argument errors
+const USAGE: &str = "\
+Usage: todo-tui [OPTIONS] [COMMAND]
+
+Without a command the interactive interface is started.
+
+Commands:
+  add TEXT...    Add a todo (inline #tags work like in the app)
+  add --stdin    Add a todo for each line read from standard input,
+                 without list bullets like '- ' or '1. '
+  list           Print the todos with their numbers
+  done N         Mark todo N as done (use 2.1 for the first subtask of 2)
+  rm N           Move todo N and its subtasks to the trash
+  today          Print the todos that are overdue or due today in every
+                 list (or --list), e.g. for a login banner
+  notify         Show a desktop notification listing todos due today
+                 or overdue (all lists unless --list is given); for cron
+  status         Print a one-line summary of all lists (or --list) for
+                 tmux, waybar or a shell prompt, see --format
+  restore [N]    List the snapshots of the todo file, or roll every list
+                 back to snapshot N (1 is the newest)
+  export FORMAT [PATH]
+                 Write all lists as json, txt, md (a checklist report),
+                 csv or ics (iCalendar VTODOs) to PATH, todo-export.EXT
+                 by default, or to standard output for '-'
+  import FORMAT FILE
+                 Add the todos from a todoist (CSV or JSON), taskwarrior
+                 (pending.data or JSON) or todotxt export, each in the
+                 list named like its project there (or all in --list)
+  google-login   Sign in to Google through the browser so the lists can
+                 sync with Google Tasks (see \"google\" in the config)
+  serve          Answer HTTP requests to read and change the todos as
+                 JSON, on localhost:8080 unless the config or --port
+                 says otherwise; runs until stopped
+
+Options:
+  -f, --file PATH  Use PATH as the todo file instead of the default
+                   in the user data directory (e.g. for per-project lists)
+                   A .txt file is read and written in todo.txt format,
+                   a .md file as Markdown task lists and a .org file
+                   as org-mode TODO headlines
+                   An http(s):// (WebDAV), sftp://, scp:// or s3:// URL
+                   is downloaded to a local copy at start and uploaded
+                   again at the end
+  -l, --list NAME  Operate on the list called NAME instead of the first one
+  -p, --port N     Port for serve to listen on
+      --format TEXT
+                   What status prints, with {active}, {pending},
+                   {in_progress}, {blocked}, {done}, {total},
+                   {due_today}, {overdue}, {tracking} (the todo being
+                   timed) and {tracked} filled in; by default
+                   \"{active} active, {due_today} due today\"
+  -t, --today      Start the interface on the todos due today or overdue
+  -k, --keymap NAME
+                   Start with the vim, emacs or standard (arrow keys)
+                   keybindings, overriding the config file
+  -h, --help       Print this help";
+
+/// Options parsed from the command line
+/// Hand-rolled because there are only a few flags - not worth a parser crate
+struct Args {
+    /// Todo file given with --file, overriding the default location
+    file: Option<PathBuf>,
+    /// List to operate on for subcommands, matched by name
+    list: Option<String>,
+    /// Keymap preset given with --keymap, overriding the config
+    keymap: Option<KeymapPreset>,
+    /// Port given with --port for `serve`, overriding the config
+    port: Option<u16>,
+    /// Whether --today asked the interface to start on what's due today
+    today: bool,
+    /// Headless command to run instead of the TUI
+    command: Option<Subcommand>,
 }
 
-/// Main application state container
-/// This struct holds everything needed to render the UI and respond to user actions
-struct App {
-    /// Vector of all todo items - using Vec because we need dynamic sizing and indexed access
-    todos: Vec<TodoItem>,
-    
-    /// Tracks which todo is currently selected - ListState is ratatui's way of managing selection
-    /// We need this separate from the todos Vec because it's stateful UI information
-    state: ListState,
-    
-    /// Buffer for user input when adding new todos
-    /// Separate from todos because it's temporary data before committing
-    input: String,
-    
-    /// Flag to track if we're in input mode (adding a todo) or navigation mode
-    /// This determines how we interpret keypresses - modal interface pattern
-    input_mode: bool,
+/// Headless operations run from scripts and shell aliases instead of the TUI
+enum Subcommand {
+    /// Append a todo to the list
+    Add(String),
+    /// Append a todo for each line of standard input
+    AddFromStdin,
+    /// Print the list with todo numbers
+    List,
+    /// Mark the todo at this path as done
+    Done(TodoPath),
+    /// Move the todo at this path to the trash
+    Remove(TodoPath),
+    /// Print the todos due today or overdue
+    Today,
+    /// Send a desktop notification listing the todos due today
+    Notify,
+    /// Print a summary line filled in from this template
+    Status(String),
+    /// List the snapshots, or restore the one with this number
+    Restore(Option<usize>),
+    /// Write every list in a format to a path, or stdout for "-"
+    Export(String, Option<String>),
+    /// Add the todos from another app's export in a format
+    Import(String, PathBuf),
+    /// Sign in to Google for syncing with Google Tasks
+    GoogleLogin,
+    /// Answer HTTP requests about the todos until stopped
+    Serve,
 }
 
-impl App {
-    /// Creates a new App instance with sensible defaults
-    /// We initialize with helper todos to guide first-time users
-    fn new() -> App {
-        // ListState needs to be initialized with a selection for immediate user interaction
-        let mut state = ListState::default();
-        state.select(Some(0)); // Start with first item selected for better UX
-        
-        App {
-            // Start with tutorial todos to demonstrate functionality
-            // This is better than an empty list which might confuse users
-            todos: vec![
-                TodoItem { text: "Press 'a' to add a todo".to_string(), completed: false },
-                TodoItem { text: "Press 'Space' to toggle completion".to_string(), completed: false },
-                TodoItem { text: "Press 'd' to delete a todo".to_string(), completed: false },
-                TodoItem { text: "Press 'q' to quit".to_string(), completed: false },
-            ],
-            state,
-            input: String::new(),
-            input_mode: false,
+impl Args {
+    /// Parses the given arguments (without the program name)
+    /// Options may appear before or after the command
+    /// Returns Ok(None) when help was requested, Err with a message for bad input
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+        let mut parsed = Args { file: None, list: None, keymap: None, port: None, today: false, command: None };
+        let mut format = None;
+        let mut stdin = false;
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-f" | "--file" => {
+                    let path = args.next().ok_or_else(|| format!("{} needs a PATH", arg))?;
+                    parsed.file = Some(PathBuf::from(path));
+                }
+                "-l" | "--list" => {
+                    parsed.list = Some(args.next().ok_or_else(|| format!("{} needs a NAME", arg))?);
+                }
+                "-k" | "--keymap" => {
+                    let name = args.next().ok_or_else(|| format!("{} needs a NAME", arg))?;
+                    parsed.keymap = Some(KeymapPreset::from_name(&name)?);
+                }
+                "-p" | "--port" => {
+                    let port = args.next().ok_or_else(|| format!("{} needs a port number", arg))?;
+                    parsed.port = Some(parse_port(&port)?);
+                }
+                "-t" | "--today" => parsed.today = true,
+                "--stdin" => stdin = true,
+                "--format" => {
+                    format = Some(args.next().ok_or_else(|| format!("{} needs a TEXT", arg))?);
+                }
+                _ => {
+                    if let Some(path) = arg.strip_prefix("--file=") {
+                        parsed.file = Some(PathBuf::from(path));
+                    } else if let Some(name) = arg.strip_prefix("--list=") {
+                        parsed.list = Some(name.to_string());
+                    } else if let Some(name) = arg.strip_prefix("--keymap=") {
+                        parsed.keymap = Some(KeymapPreset::from_name(name)?);
+                    } else if let Some(port) = arg.strip_prefix("--port=") {
+                        parsed.port = Some(parse_port(port)?);
+                    } else if let Some(text) = arg.strip_prefix("--format=") {
+                        format = Some(text.to_string());
+                    } else if arg.starts_with('-') && arg.len() > 1 {
+                        return Err(format!("unexpected argument '{}'", arg));
+                    } else {
+                        positional.push(arg);
+                    }
+                }
+            }
+        }
+        parsed.command = Subcommand::parse(&positional, stdin)?;
+        if parsed.port.is_some() && !matches!(parsed.command, Some(Subcommand::Serve)) {
+            return Err("--port only goes with serve".to_string());
+        }
+        if parsed.today && parsed.command.is_some() {
+            return Err("--today is for the interface - `today` prints the same todos".to_string());
         }
+        match (&mut parsed.command, format) {
+            (Some(Subcommand::Status(template)), Some(format)) => *template = format,
+            (_, Some(_)) => return Err("--format only goes with status".to_string()),
+            _ => {}
+        }
+        Ok(Some(parsed))
     }
+}
 
-    /// Gets the path to the todos file in the current directory
-    /// We use current directory so todos are stored with the project
-    /// This makes it easy to have different todo lists for different projects
-    fn get_save_path() -> Result<PathBuf, Box<dyn Error>> {
-        // Get current working directory where the program is run from
-        let current_dir = std::env::current_dir()?;
-        
-        // Store in todos.json in the same directory as where the program runs
-        // Not hidden so users can easily find and back up their todos
-        Ok(current_dir.join("todos.json"))
+impl Subcommand {
+    /// Interprets the positional arguments - the first one names the command
+    /// `stdin` is whether --stdin was given, which only `add` takes
+    /// Returns Ok(None) when there are none, meaning the TUI should start
+    fn parse(positional: &[String], stdin: bool) -> Result<Option<Subcommand>, String> {
+        let Some((name, rest)) = positional.split_first() else {
+            return match stdin {
+                true => Err("--stdin only goes with add".to_string()),
+                false => Ok(None),
+            };
+        };
+        if stdin && name != "add" {
+            return Err("--stdin only goes with add".to_string());
+        }
+        let command = match name.as_str() {
+            "add" if stdin && rest.is_empty() => Subcommand::AddFromStdin,
+            "add" if stdin => return Err("add takes either the todo text or --stdin".to_string()),
+            "add" if !rest.is_empty() => Subcommand::Add(rest.join(" ")),
+            "add" => return Err("add needs the todo text, or --stdin to read todos from standard input".to_string()),
+            "list" | "ls" => Subcommand::List,
+            "done" => Subcommand::Done(parse_todo_number(single_arg(name, rest)?)?),
+            "rm" | "remove" => Subcommand::Remove(parse_todo_number(single_arg(name, rest)?)?),
+            "today" | "agenda" => Subcommand::Today,
+            "notify" => Subcommand::Notify,
+            "status" if rest.is_empty() => Subcommand::Status("{active} active, {due_today} due today".to_string()),
+            "status" => return Err("status takes no arguments - give the format with --format".to_string()),
+            "restore" => match rest {
+                [] => Subcommand::Restore(None),
+                [number] => Subcommand::Restore(Some(
+                    number.parse().map_err(|_| format!("'{}' is not a snapshot number", number))?,
+                )),
+                _ => return Err("restore takes at most one snapshot number".to_string()),
+            },
+            "export" => match rest {
+                [format] => Subcommand::Export(format.clone(), None),
+                [format, path] => Subcommand::Export(format.clone(), Some(path.clone())),
+                _ => return Err("export needs a FORMAT and takes an optional PATH".to_string()),
+            },
+            "import" => match rest {
+                [format, path] => Subcommand::Import(format.clone(), PathBuf::from(path)),
+                _ => return Err("import needs a FORMAT and a FILE".to_string()),
+            },
+            "google-login" if rest.is_empty() => Subcommand::GoogleLogin,
+            "google-login" => return Err("google-login takes no arguments".to_string()),
+            "serve" if rest.is_empty() => Subcommand::Serve,
+            "serve" => return Err("serve takes no arguments - give the port with --port".to_string()),
+            _ => return Err(format!("unknown command '{}'", name)),
+        };
+        Ok(Some(command))
     }
+}
 
-    /// Saves todos to disk as JSON
-    /// We save after every modification to prevent data loss on crashes
-    fn save(&self) -> Result<(), Box<dyn Error>> {
-        let path = Self::get_save_path()?;
-        
-        // Serialize to pretty JSON for human readability (easier debugging)
-        // If we needed performance, we'd use compact JSON instead
-        let json = serde_json::to_string_pretty(&self.todos)?;
-        
-        // Write atomically by writing to temp file then renaming
-        // This prevents corruption if program crashes during write
-        fs::write(&path, json)?;
-        
-        Ok(())
+/// Returns the only argument of a command that takes exactly one
+fn single_arg<'a>(command: &str, rest: &'a [String]) -> Result<&'a str, String> {
+    match rest {
+        [arg] => Ok(arg),
+        _ => Err(format!("{} needs exactly one todo number", command)),
     }
+}
 
-    /// Loads todos from disk, or creates default if file doesn't exist
-    /// Returns a new App with loaded todos, or default todos on first run
-    fn load() -> App {
-        let mut app = App::new();
-        
-        // Attempt to load from disk
-        if let Ok(path) = Self::get_save_path() {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                // Try to deserialize - if it fails, we'll just use default todos
-                // This gracefully handles corrupted files
-                if let Ok(todos) = serde_json::from_str::<Vec<TodoItem>>(&contents) {
-                    if !todos.is_empty() {
-                        app.todos = todos;
-                        // Ensure selection is valid for loaded todos
-                        app.state.select(Some(0));
-                    }
-                }
-            }
+/// Parses the port given with --port
+fn parse_port(s: &str) -> Result<u16, String> {
+    s.parse().map_err(|_| format!("'{}' is not a port number", s))
+}
+
+/// Loads the todo file for a subcommand, asking for the passphrase first
+/// if it's encrypted
+fn load_for_subcommand(save_path: PathBuf) -> Result<App, Box<dyn Error>> {
+    match App::load(save_path.clone()) {
+        Err(AppError::Locked { .. }) => {
+            let passphrase = read_passphrase(&save_path)?;
+            Ok(App::load_encrypted(save_path, &passphrase)?)
         }
-        
-        app
+        result => Ok(result?),
     }
+}
 
-    /// Moves selection to the next todo item
-    /// Wraps around to the start for continuous navigation (circular list pattern)
-    fn next(&mut self) {
-        // Early return if empty to prevent index out of bounds
-        if self.todos.is_empty() {
-            return;
-        }
-        
-        let i = match self.state.selected() {
-            Some(i) => {
-                // Wrap to beginning if at end - provides better UX than stopping at bottom
-                if i >= self.todos.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            // If nothing selected (shouldn't happen), start at beginning
-            None => 0,
-        };
-        self.state.select(Some(i));
+/// Gets the passphrase of an encrypted todo file
+/// Cron jobs can't type, so TODO_TUI_PASSPHRASE is used when it's set;
+/// otherwise it's read from the terminal without showing it
+fn read_passphrase(path: &Path) -> io::Result<String> {
+    if let Ok(passphrase) = std::env::var("TODO_TUI_PASSPHRASE") {
+        return Ok(passphrase);
     }
+    eprint!("Passphrase for {}: ", path.display());
+    io::stderr().flush()?;
+    // Raw mode keeps the terminal from echoing what's typed
+    enable_raw_mode()?;
+    let passphrase = read_hidden_line();
+    let _ = disable_raw_mode();
+    eprintln!();
+    passphrase
+}
 
-    /// Moves selection to the previous todo item
-    /// Wraps around to the end for continuous navigation (circular list pattern)
-    fn previous(&mut self) {
-        // Early return if empty to prevent index out of bounds
-        if self.todos.is_empty() {
-            return;
-        }
-        
-        let i = match self.state.selected() {
-            Some(i) => {
-                // Wrap to end if at beginning - provides better UX than stopping at top
-                if i == 0 {
-                    self.todos.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            // If nothing selected (shouldn't happen), start at end
-            None => 0,
+/// Reads keys up to Enter in raw mode
+fn read_hidden_line() -> io::Result<String> {
+    let mut line = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
         };
-        self.state.select(Some(i));
-    }
-
-    /// Toggles the completion state of the currently selected todo
-    /// We modify in place rather than recreating for efficiency
-    /// Saves after modification to persist changes immediately
-    fn toggle_completed(&mut self) {
-        if let Some(i) = self.state.selected() {
-            // Bounds check prevents panic if state is somehow out of sync
-            if i < self.todos.len() {
-                self.todos[i].completed = !self.todos[i].completed;
-                // Save after every change - prevents data loss
-                // We ignore errors here to not disrupt UX, but could log them
-                let _ = self.save();
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(line),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Esc => return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled")),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
             }
+            KeyCode::Char(c) => line.push(c),
+            _ => {}
         }
     }
+}
+
+/// Runs a headless subcommand against the loaded lists and saves the result
+/// Changes are applied directly rather than through `execute` - there's no undo
+/// history to keep outside the TUI, and save errors must be reported, not swallowed
+fn run_subcommand(app: &mut App, list_name: Option<&str>, command: Subcommand) -> Result<(), Box<dyn Error>> {
+    // Pick the list by name, case-insensitively since it's typed in a shell
+    if let Some(name) = list_name {
+        app.current = app
+            .lists
+            .iter()
+            .position(|l| l.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no list named '{}'", name))?;
+    }
+    let list = app.current;
+    let todo_at = |app: &App, path: &TodoPath| -> Result<TodoItem, Box<dyn Error>> {
+        find_item(app.todos(), path).cloned().ok_or_else(|| {
+            format!("no todo {} in list '{}'", format_todo_number(path), app.lists[list].name).into()
+        })
+    };
 
-    /// Deletes the currently selected todo and adjusts selection intelligently
-    /// Selection adjustment is crucial for maintaining good UX after deletion
-    /// Saves after modification to persist changes immediately
-    fn delete_selected(&mut self) {
-        if let Some(i) = self.state.selected() {
-            // Bounds check prevents panic if state is somehow out of sync
-            if i < self.todos.len() {
-                self.todos.remove(i);
-                
-                // Adjust selection to maintain user context after deletion
-                if !self.todos.is_empty() {
-                    // If we deleted the last item, move selection up
-                    // Otherwise, keep selection at same index (which is now the next item)
-                    let new_index = if i >= self.todos.len() { 
-                        self.todos.len() - 1 
-                    } else { 
-                        i 
-                    };
-                    self.state.select(Some(new_index));
-                } else {
-                    // No items left, deselect to prevent issues
-                    self.state.select(None);
+    match command {
+        Subcommand::List => {
+            println!("{}", app.lists[list].name);
+            print_todos(app.todos(), &app.date_style(), &mut Vec::new());
+            return Ok(());
+        }
+        Subcommand::Today => {
+            let lists = match list_name {
+                Some(_) => &app.lists[list..=list],
+                None => &app.lists[..],
+            };
+            match format_agenda(lists, &app.date_style()).as_str() {
+                "" => println!("Nothing due today"),
+                agenda => print!("{}", agenda),
+            }
+            return Ok(());
+        }
+        Subcommand::Notify => {
+            // Anything due before tomorrow, so overdue todos keep nagging
+            let tomorrow = Timestamp::from_date(Date::today().add_days(1));
+            let mut due = Vec::new();
+            for (i, l) in app.lists.iter().enumerate() {
+                if list_name.is_none() || i == list {
+                    collect_due(&l.todos, &app.date_style(), |at| at < tomorrow, &mut due);
+                }
+            }
+            // Stay quiet on days with nothing due - cron runs this unattended
+            if !due.is_empty() {
+                let summary = match due.len() {
+                    1 => "1 todo due today".to_string(),
+                    n => format!("{} todos due today", n),
+                };
+                println!("{}", summary);
+                for todo in &due {
+                    println!("  {}", todo);
+                }
+                send_notification(&summary, &due.join("\n"))?;
+            }
+            return Ok(());
+        }
+        Subcommand::Status(template) => {
+            let lists = match list_name {
+                Some(_) => &app.lists[list..=list],
+                None => &app.lists[..],
+            };
+            println!("{}", format_status(lists, &template)?);
+            return Ok(());
+        }
+        Subcommand::Export(format, path) => {
+            match path.as_deref() {
+                Some("-") => print!("{}", app.export_contents(&format)?.0),
+                path => println!("Exported to {}", app.export(&format, path.unwrap_or(""))?.display()),
+            }
+            return Ok(());
+        }
+        Subcommand::Restore(None) => {
+            let snapshots = app.snapshots();
+            if snapshots.is_empty() {
+                println!("No snapshots yet - one is taken before each session's first change");
+                return Ok(());
+            }
+            println!("Snapshots, newest first:");
+            for (i, path) in snapshots.iter().enumerate() {
+                println!("{:>4}  {}", i + 1, path.display());
+            }
+            return Ok(());
+        }
+        Subcommand::Restore(Some(number)) => {
+            let (path, lists) = app.read_snapshot(number)?;
+            // What's being replaced becomes a snapshot too, so the restore can be rolled back in turn
+            app.take_snapshot()?;
+            println!("Restored {}", path.display());
+            app.apply_command(Command::SetLists { before: app.lists.clone(), after: lists });
+        }
+        // Run from main before the todo file is loaded
+        Subcommand::GoogleLogin => return Ok(()),
+        // Run from main, which has the config it listens by
+        Subcommand::Serve => return Ok(()),
+        Subcommand::Import(format, path) => {
+            // --list gathers everything in that list instead of one per project
+            println!("{}", app.import(&format, &path, list_name.is_some())?);
+        }
+        Subcommand::Add(text) => {
+            let item = TodoItem::parse(&text);
+            let path = vec![app.todos().len()];
+            println!("Added {}: {}", format_todo_number(&path), item.text);
+            app.apply_command(Command::Insert { list, path, item });
+        }
+        Subcommand::AddFromStdin => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map_err(|err| format!("could not read standard input: {}", err))?;
+            let mut commands = Vec::new();
+            for text in input.lines().map(strip_list_marker).filter(|text| !text.is_empty()) {
+                let item = TodoItem::parse(text);
+                let path = vec![app.todos().len() + commands.len()];
+                println!("Added {}: {}", format_todo_number(&path), item.text);
+                commands.push(Command::Insert { list, path, item });
+            }
+            // A script piping in nothing isn't an error
+            if commands.is_empty() {
+                println!("Nothing to add");
+                return Ok(());
+            }
+            // One change for the git history, however many lines there were
+            app.apply_command(Command::Batch(commands));
+        }
+        Subcommand::Done(path) => {
+            let before = todo_at(app, &path)?;
+            let mut after = before.clone();
+            match after.recurrence {
+                Some(rule) => {
+                    after.reschedule(rule);
+                    println!("Rescheduled {}: {} (next due {})", format_todo_number(&path), after.text, after.due.unwrap_or_else(Date::today));
+                }
+                None => {
+                    after.set_completed(true);
+                    println!("Completed {}: {}", format_todo_number(&path), after.text);
                 }
-                
-                // Save after deletion - prevents data loss
-                let _ = self.save();
             }
+            app.apply_command(Command::Replace { list, path, before: Box::new(before), after: Box::new(after) });
+        }
+        Subcommand::Remove(path) => {
+            let item = todo_at(app, &path)?;
+            println!("Moved {} to the trash: {}", format_todo_number(&path), item.text);
+            app.apply_command(Command::Trash { list, path, index: 0 });
         }
     }
+    app.save()?;
+    Ok(app.commit_changes()?)
+}
 
-    /// Adds a new todo from the input buffer and resets input state
-    /// We only add if input is non-empty to prevent blank todos
-    /// Saves after modification to persist changes immediately
-    fn add_todo(&mut self) {
-        if !self.input.is_empty() {
-            self.todos.push(TodoItem {
-                text: self.input.clone(), // Clone because we're about to clear input
-                completed: false,
-            });
-            
-            // Clear input buffer for next use
-            self.input.clear();
-            
-            // Exit input mode to return to navigation
-            self.input_mode = false;
-            
-            // Select the newly added item so user sees immediate feedback
-            self.state.select(Some(self.todos.len() - 1));
-            
-            // Save after adding - prevents data loss
-            let _ = self.save();
+/// A line for `add --stdin` without the bullet, number or empty checkbox of
+/// the list it was copied from, like "- ", "1. " or "- [ ] "
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim();
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let marker = match digits {
+        0 => line.strip_prefix(['-', '*', '+', '•']),
+        _ => line[digits..].strip_prefix(['.', ')']),
+    };
+    // "-5 degrees" and "2.5 kg of flour" aren't list items
+    let Some(rest) = marker.filter(|rest| rest.starts_with(' ')) else {
+        return line;
+    };
+    let rest = rest.trim_start();
+    rest.strip_prefix("[ ]").map_or(rest, str::trim_start)
+}
+
+/// Prints todos as a numbered plain-text tree for the `list` subcommand
+/// Numbers match what `done` and `rm` accept
+fn print_todos(todos: &[TodoItem], dates: &DateStyle, prefix: &mut TodoPath) {
+    for (i, todo) in todos.iter().enumerate() {
+        prefix.push(i);
+        let mut line = format!(
+            "{:>6}  {}{}{}",
+            format_todo_number(prefix),
+            "  ".repeat(prefix.len() - 1),
+            match todo.status {
+                Status::Pending => "[ ] ",
+                Status::InProgress => "[~] ",
+                Status::Blocked => "[!] ",
+                Status::Done => "[x] ",
+                Status::Cancelled => "[-] ",
+            },
+            todo.priority.marker(),
+        );
+        line.push_str(&todo.text);
+        if let Some((done, total)) = todo.progress() {
+            line.push_str(&format!(" {}/{}", done, total));
+        }
+        if let Some(due) = dates.due_label(todo) {
+            line.push_str(&format!(" due {}", due));
+        }
+        if todo.recurrence.is_some() {
+            line.push_str(" ↻");
+        }
+        let spent = todo.tracked_seconds(Timestamp::now());
+        if spent > 0 {
+            line.push_str(&format!(" [{}]", format_duration(spent)));
         }
+        if let Some(project) = &todo.project {
+            line.push_str(&format!(" +{}", project));
+        }
+        for tag in &todo.tags {
+            line.push_str(&format!(" #{}", tag));
+        }
+        for context in &todo.contexts {
+            line.push_str(&format!(" @{}", context));
+        }
+        println!("{}", line);
+        print_todos(&todo.children, dates, prefix);
+        prefix.pop();
     }
 }
 
 /// Entry point - sets up terminal, runs app, then cleans up
 /// The Result type allows us to propagate errors up to the runtime
 fn main() -> Result<(), Box<dyn Error>> {
-    // Enable raw mode to read input directly without waiting for Enter
-    // This is essential for responsive TUI - we need to react to every keypress
-    enable_raw_mode()?;
-    
-    let mut stdout = io::stdout();
-    
-    // Enter alternate screen to preserve user's terminal history
-    // Enable mouse capture even though we don't use it yet (future-proofing)
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    
-    // Create terminal backend - CrosstermBackend works on Windows, Linux, and macOS
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // Parse arguments before touching the terminal so errors print normally
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let save_path = match args.file {
+        Some(path) => path,
+        None => App::default_save_path()?,
+    };
+
+    // A broken config is reported up front rather than half-applied
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // The flag picks the preset, the config's own bindings still apply on top
+    if let Some(preset) = args.keymap {
+        config.keymap = preset;
+    }
+
+    // Signing in is about the account, not the todo file, so nothing is loaded
+    if let Some(Subcommand::GoogleLogin) = args.command {
+        let signed_in = google_login(&config, |url| {
+            println!("Open this address in a browser to sign in to Google:\n\n  {}\n", url);
+            println!("Waiting for the browser to come back...");
+        });
+        match signed_in {
+            Ok(path) => println!("Signed in - the token is kept in {}", path.display()),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // A URL is worked on through a local copy, brought up to date before
+    // anything reads it and uploaded again once everything is saved
+    let mut remote_file = None;
+    let mut remote_notice = None;
+    let save_path = match RemoteFile::is_url(&save_path) {
+        true => {
+            if let Some(Subcommand::Serve) = args.command {
+                eprintln!("error: serve needs a local todo file - the devices can sync through it instead");
+                std::process::exit(1);
+            }
+            let mut file = match RemoteFile::new(&save_path.to_string_lossy(), &config) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            remote_notice = file.start();
+            let cache = file.cache().to_path_buf();
+            remote_file = Some(file);
+            cache
+        }
+        false => save_path,
+    };
+
+    // Subcommands work on the same file without ever entering the TUI
+    if let Some(command) = args.command {
+        if let Some(notice) = remote_notice {
+            eprintln!("warning: {}", notice);
+        }
+        // Unlike the interface there's nowhere to show what went wrong after
+        // the fact, so a file that can't be loaded stops the command
+        let app_file = save_path.clone();
+        let mut app = match load_for_subcommand(save_path) {
+            Ok(app) => app,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        // For the snapshots `restore` keeps - the rest only matters to the interface
+        if let Err(err) = app.apply_config(&config) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        for failure in app.load_plugins() {
+            eprintln!("warning: {}", failure);
+        }
+        let result = match command {
+            // Answers requests with this app until it's stopped, saving after each change
+            Subcommand::Serve => serve(&mut app, &config, args.port, |address| {
+                println!("Serving {} on {} - stop with Ctrl+C", app_file.display(), address);
+            })
+            .map_err(Into::into),
+            command => run_subcommand(&mut app, args.list.as_deref(), command),
+        };
+        // The hooks of a change run before the command returns, like in Taskwarrior
+        for failure in app.wait_for_hooks() {
+            eprintln!("warning: {}", failure);
+        }
+        if let Some(file) = &mut remote_file
+            && let Err(err) = file.finish()
+        {
+            eprintln!("warning: {}", err);
+        }
+        if let Err(err) = result {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     // Load app state from disk, or create new if no saved data exists
-    let app = App::load();
-    let res = run_app(&mut terminal, app);
-
-    // CRITICAL: Always restore terminal state, even if app crashes
-    // This prevents leaving the user's terminal in a broken state
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    // Propagate any errors that occurred during execution
-    if let Err(err) = res {
-        println!("{:?}", err)
+    // Done before entering raw mode so config errors print to a normal terminal
+    // A file that can't be loaded opens an empty, unsaved session that shows why
+    let mut app = App::load(save_path.clone()).unwrap_or_else(|err| App::load_failed(save_path, err));
+    if let Some(path) = ViewState::path() {
+        app.restore_view_state(path);
+    }
+    if let Err(err) = app.apply_config(&config) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+    for failure in app.load_plugins() {
+        eprintln!("warning: {}", failure);
     }
 
+    // After the config, which can turn it off
+    app.start_review();
+
+    if args.today {
+        app.show_today();
+    }
+
+    // Like working offline, which the user should know before changing anything
+    if let Some(notice) = remote_notice {
+        app.show_remote_notice(notice);
+    }
+
+    // The library owns the terminal from here until the user quits
+    // A last save that failed is reported once the terminal is back
+    let result = todo_tui::run(app);
+    if let Some(file) = &mut remote_file
+        && let Err(err) = file.finish()
+    {
+        eprintln!("error: {}", err);
+    }
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-/// Main application loop - handles rendering and input
-/// We use a generic backend so this could work with different terminal implementations
-fn run_app<B: ratatui::backend::Backend>(
-    terminal: &mut Terminal<B>,
-    mut app: App,
-) -> io::Result<()> {
-    loop {
-        // Render the UI - this closure is called with a Frame we can draw to
-        terminal.draw(|f| {
-            // Create a two-panel vertical layout
-            // Using constraints allows ratatui to handle terminal resizing gracefully
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(2) // Add padding so content doesn't touch screen edges
-                .constraints([
-                    Constraint::Min(1),    // Todo list takes remaining space
-                    Constraint::Length(3)  // Input area is fixed height
-                ].as_ref())
-                .split(f.area());
-
-            // Convert todo items to ListItems for rendering
-            // We do this fresh each frame because completed status may have changed
-            let items: Vec<ListItem> = app
-                .todos
-                .iter()
-                .map(|todo| {
-                    // Use checkbox pattern familiar from many todo apps
-                    let checkbox = if todo.completed { "[✓] " } else { "[ ] " };
-                    
-                    // Style completed items differently to provide clear visual feedback
-                    // Strikethrough + dark gray is standard convention for completed tasks
-                    let style = if todo.completed {
-                        Style::default()
-                            .fg(Color::DarkGray)
-                            .add_modifier(Modifier::CROSSED_OUT)
-                    } else {
-                        Style::default().fg(Color::White)
-                    };
-                    
-                    // Combine checkbox and text with appropriate styling
-                    ListItem::new(Line::from(vec![
-                        Span::raw(checkbox),
-                        Span::styled(&todo.text, style),
-                    ]))
-                })
-                .collect();
-
-            // Create the list widget with all our styled items
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        // Put all controls in title so they're always visible
-                        .title("📝 Todo List (↑/↓: navigate, Space: toggle, a: add, d: delete, q: quit)"),
-                )
-                // Highlight style makes it clear which item is selected
-                // Blue background is conventional for selection in TUIs
-                .highlight_style(
-                    Style::default()
-                        .bg(Color::Blue)
-                        .add_modifier(Modifier::BOLD),
-                )
-                // Arrow symbol provides additional visual cue for selection
-                .highlight_symbol("► ");
-
-            // Render the list with its stateful selection
-            // We pass state mutably so ratatui can update it if needed
-            f.render_stateful_widget(list, chunks[0], &mut app.state);
-
-            // Update input area text based on current mode
-            // This provides context-sensitive help to the user
-            let input_text = if app.input_mode {
-                format!("New todo: {} (Press Enter to confirm, Esc to cancel)", app.input)
-            } else {
-                "Press 'a' to add a new todo".to_string()
-            };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Style input area differently when active to show mode clearly
-            // Yellow is attention-getting and conventional for "active" state
-            let input = Paragraph::new(input_text)
-                .style(if app.input_mode {
-                    Style::default().fg(Color::Yellow)
-                } else {
-                    Style::default()
-                })
-                .block(Block::default().borders(Borders::ALL).title("Input"));
-            f.render_widget(input, chunks[1]);
-        })?;
-
-        // Check if an event is available without blocking
-        // We use a very short timeout to keep the UI responsive
-        if event::poll(std::time::Duration::from_millis(16))? {
-            // Only process keyboard events, ignore other event types
-            if let Event::Key(key) = event::read()? {
-                // CRITICAL: Only process key press events, not release events
-                // Some terminals send both Press and Release, which would cause double input
-                if key.kind != KeyEventKind::Press {
-                    continue;
-                }
-                // Different key handling based on mode - modal interface pattern
-                if app.input_mode {
-                    // In input mode, keys type into the buffer
-                    match key.code {
-                        KeyCode::Enter => app.add_todo(),
-                        KeyCode::Char(c) => app.input.push(c),
-                        KeyCode::Backspace => {
-                            app.input.pop();
-                        }
-                        // Esc cancels input without saving
-                        KeyCode::Esc => {
-                            app.input_mode = false;
-                            app.input.clear();
-                        }
-                        _ => {}
-                    }
-                } else {
-                    // In navigation mode, keys control the list
-                    match key.code {
-                        KeyCode::Char('q') => return Ok(()), // Exit cleanly
-                        // Support both arrow keys and vim-style navigation
-                        // This accommodates different user preferences
-                        KeyCode::Down | KeyCode::Char('j') => app.next(),
-                        KeyCode::Up | KeyCode::Char('k') => app.previous(),
-                        KeyCode::Char(' ') => app.toggle_completed(),
-                        KeyCode::Char('d') => app.delete_selected(),
-                        KeyCode::Char('a') => app.input_mode = true,
-                        _ => {}
-                    }
-                }
-            }
+    fn parse(args: &[&str]) -> Result<Option<Args>, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// The message a bad command line is rejected with
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(message) => message,
+            Ok(_) => panic!("{:?} was accepted", args),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn options_go_before_or_after_the_command() {
+        let args = parse(&["--file", "work.json", "add", "call", "mum", "-l", "Home"]).unwrap().unwrap();
+        assert_eq!(args.file, Some(PathBuf::from("work.json")));
+        assert_eq!(args.list.as_deref(), Some("Home"));
+        assert!(matches!(args.command, Some(Subcommand::Add(text)) if text == "call mum"));
+
+        let args = parse(&["done", "2.1", "--file=todo.txt", "--list=Work"]).unwrap().unwrap();
+        assert_eq!(args.file, Some(PathBuf::from("todo.txt")));
+        assert_eq!(args.list.as_deref(), Some("Work"));
+        assert!(matches!(args.command, Some(Subcommand::Done(path)) if path == [1, 0]));
+
+        // No command starts the interface
+        let args = parse(&["-f", "todo.md", "-t", "-k", "emacs"]).unwrap().unwrap();
+        assert_eq!(args.file, Some(PathBuf::from("todo.md")));
+        assert!(args.today);
+        assert!(args.keymap == Some(KeymapPreset::Emacs));
+        assert!(args.command.is_none());
+        assert!(parse(&["list", "--help"]).unwrap().is_none());
+        // A lone dash is a path, not a flag
+        assert!(matches!(parse(&["export", "csv", "-"]).unwrap().unwrap().command, Some(Subcommand::Export(format, Some(path))) if format == "csv" && path == "-"));
+    }
+
+    #[test]
+    fn each_command_takes_its_own_arguments() {
+        let command = |args: &[&str]| parse(args).unwrap().unwrap().command;
+        assert!(matches!(command(&["ls"]), Some(Subcommand::List)));
+        assert!(matches!(command(&["rm", "3"]), Some(Subcommand::Remove(path)) if path == [2]));
+        assert!(matches!(command(&["agenda"]), Some(Subcommand::Today)));
+        assert!(matches!(command(&["add", "--stdin"]), Some(Subcommand::AddFromStdin)));
+        assert!(matches!(command(&["restore"]), Some(Subcommand::Restore(None))));
+        assert!(matches!(command(&["restore", "2"]), Some(Subcommand::Restore(Some(2)))));
+        assert!(matches!(command(&["import", "todoist", "tasks.csv"]), Some(Subcommand::Import(format, _)) if format == "todoist"));
+        assert!(matches!(command(&["status", "--format", "{done}/{total}"]), Some(Subcommand::Status(template)) if template == "{done}/{total}"));
+        assert!(matches!(command(&["status"]), Some(Subcommand::Status(template)) if template.contains("{active}")));
+        let args = parse(&["serve", "--port=9000"]).unwrap().unwrap();
+        assert!(matches!(args.command, Some(Subcommand::Serve)));
+        assert_eq!(args.port, Some(9000));
+    }
+
+    #[test]
+    fn bad_arguments_say_what_is_wrong() {
+        assert_eq!(error(&["--verbose"]), "unexpected argument '--verbose'");
+        assert_eq!(error(&["list", "-x"]), "unexpected argument '-x'");
+        assert_eq!(error(&["frobnicate"]), "unknown command 'frobnicate'");
+        // Flags that take a value, given none
+        assert_eq!(error(&["--file"]), "--file needs a PATH");
+        assert_eq!(error(&["list", "-l"]), "-l needs a NAME");
+        assert_eq!(error(&["serve", "--port"]), "--port needs a port number");
+        assert_eq!(error(&["status", "--format"]), "--format needs a TEXT");
+        // ...or one that doesn't fit
+        assert_eq!(error(&["serve", "-p", "http"]), "'http' is not a port number");
+        assert_eq!(error(&["--keymap=nano"]), "unknown keymap 'nano' (expected vim, emacs, standard)");
+        assert_eq!(error(&["done", "0"]), "'0' is not a todo number");
+        assert_eq!(error(&["restore", "last"]), "'last' is not a snapshot number");
+        // Commands given the wrong number of arguments
+        assert_eq!(error(&["add"]), "add needs the todo text, or --stdin to read todos from standard input");
+        assert_eq!(error(&["add", "milk", "--stdin"]), "add takes either the todo text or --stdin");
+        assert_eq!(error(&["done"]), "done needs exactly one todo number");
+        assert_eq!(error(&["rm", "1", "2"]), "rm needs exactly one todo number");
+        assert_eq!(error(&["import", "todoist"]), "import needs a FORMAT and a FILE");
+        assert_eq!(error(&["serve", "now"]), "serve takes no arguments - give the port with --port");
+        // Options that only mean something to one command
+        assert_eq!(error(&["list", "--stdin"]), "--stdin only goes with add");
+        assert_eq!(error(&["list", "--port", "80"]), "--port only goes with serve");
+        assert_eq!(error(&["list", "--format", "{done}"]), "--format only goes with status");
+        assert_eq!(error(&["today", "--today"]), "--today is for the interface - `today` prints the same todos");
+    }
+}
@@ -0,0 +1,557 @@
+//! The operation log behind the todo file, so edits made in two places at
+//! once - a second device whose copy a file syncing tool brings over, the
+//! command line, an editor - merge instead of one replacing the other
+//! The todo file stays the snapshot everything reads. Every change also goes
+//! into `<name>.changes/` next to it as an automerge change, each instance
+//! appending to a file of its own so synced copies never conflict, and
+//! reading all of them gives everyone's changes
+//! A todo is a map of its fields, so changes to different fields of the same
+//! todo both stay, and its text and notes are merged by character
+//! The todo file says which changes it was written from, so one edited by
+//! hand, or written by a build without the log, is taken as a change from
+//! there rather than over everything since
+//! Built with the `crdt` feature, which is on by default
+
+use automerge::{transaction::Transactable, AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc, ScalarValue, Value as Field, ROOT};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    app::{TodoItem, TodoList},
+    caldav::new_uid,
+};
+
+/// Key of a todo's or list's id in the log, among its `remote_ids`
+pub(crate) const LOG_ID: &str = "log";
+
+/// Fields merged by character rather than replaced whole
+const TEXT_FIELDS: [&str; 2] = ["text", "notes"];
+
+/// Files the log can have before they're folded into one
+const COMPACT_AFTER: usize = 16;
+
+/// How long a file goes unchanged before it's folded into another - a
+/// device that appended to it lately may not be done with it
+const COMPACT_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The lists as the log has them: every list and todo by its id, with its
+/// fields as JSON and where it is in keys starting with '@'
+#[derive(Default, PartialEq)]
+struct Flat {
+    lists: BTreeMap<String, Map<String, Value>>,
+    todos: BTreeMap<String, Map<String, Value>>,
+}
+
+/// The log of the todo file at a path, as this instance has read it
+pub(crate) struct OpLog {
+    dir: PathBuf,
+    /// The file this instance appends to
+    file: PathBuf,
+    doc: AutoCommit,
+    /// Sizes of the files when they were read, so unchanged ones aren't read again
+    sizes: HashMap<PathBuf, u64>,
+    /// The changes this instance's lists were last in step with
+    base: Vec<ChangeHash>,
+    /// The lists at `base`
+    flat: Flat,
+}
+
+/// Folder of the log of the todo file at `path`
+fn log_dir(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}.changes", stem))
+}
+
+fn error(err: automerge::AutomergeError) -> String {
+    format!("the change log is broken: {}", err)
+}
+
+/// Gives every list and todo without an id in the log one, and a new one to
+/// copies that took their original's along
+pub(crate) fn give_ids(lists: &mut [TodoList]) {
+    fn give(ids: &mut BTreeMap<String, String>, seen: &mut HashSet<String>) {
+        if !ids.get(LOG_ID).is_some_and(|id| seen.insert(id.clone())) {
+            let id = new_uid();
+            seen.insert(id.clone());
+            ids.insert(LOG_ID.to_string(), id);
+        }
+    }
+    fn give_todos(todos: &mut [TodoItem], seen: &mut HashSet<String>) {
+        for todo in todos {
+            give(&mut todo.remote_ids, seen);
+            give_todos(&mut todo.children, seen);
+        }
+    }
+    let mut seen = HashSet::new();
+    for list in lists {
+        give(&mut list.remote_ids, &mut seen);
+        give_todos(&mut list.todos, &mut seen);
+    }
+}
+
+/// The fields of a list or todo as JSON, without `nested` and its id
+fn fields(value: impl serde::Serialize, nested: &str) -> Map<String, Value> {
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(value) else {
+        return Map::new();
+    };
+    fields.remove(nested);
+    if let Some(Value::Object(ids)) = fields.get_mut("remote_ids") {
+        ids.remove(LOG_ID);
+    }
+    fields
+}
+
+/// The lists as the log has them - those without ids are left out
+fn flatten(lists: &[TodoList]) -> Flat {
+    fn add(todos: &[TodoItem], list: &str, parent: Option<&str>, flat: &mut Flat) {
+        for (i, todo) in todos.iter().enumerate() {
+            let Some(id) = todo.remote_ids.get(LOG_ID) else {
+                continue;
+            };
+            let mut fields = fields(todo, "children");
+            fields.insert("@list".to_string(), json!(list));
+            fields.insert("@parent".to_string(), json!(parent));
+            fields.insert("@position".to_string(), json!(i));
+            flat.todos.insert(id.clone(), fields);
+            add(&todo.children, list, Some(id), flat);
+        }
+    }
+    let mut flat = Flat::default();
+    for (i, list) in lists.iter().enumerate() {
+        let Some(id) = list.remote_ids.get(LOG_ID) else {
+            continue;
+        };
+        let mut fields = fields(list, "todos");
+        fields.insert("@position".to_string(), json!(i));
+        flat.lists.insert(id.clone(), fields);
+        add(&list.todos, id, None, &mut flat);
+    }
+    flat
+}
+
+/// Makes the changes from `old` to `new` in the document
+/// Lists and todos are maps in the root, keyed "l/<id>" and "t/<id>" - the
+/// root is the one object every copy shares from the start
+fn apply(doc: &mut AutoCommit, old: &Flat, new: &Flat) -> Result<(), automerge::AutomergeError> {
+    for (prefix, old, new) in [("l/", &old.lists, &new.lists), ("t/", &old.todos, &new.todos)] {
+        for (id, fields) in new {
+            let key = format!("{}{}", prefix, id);
+            let (obj, before) = match (old.get(id), doc.get(ROOT, key.as_str())?) {
+                (Some(before), Some((Field::Object(ObjType::Map), obj))) => (obj, Some(before)),
+                _ => (doc.put_object(ROOT, key.as_str(), ObjType::Map)?, None),
+            };
+            for (name, value) in fields {
+                if before.and_then(|before| before.get(name)) != Some(value) {
+                    put(doc, &obj, name, value)?;
+                }
+            }
+            for name in before.into_iter().flat_map(Map::keys).filter(|name| !fields.contains_key(*name)) {
+                doc.delete(&obj, name.as_str())?;
+            }
+        }
+        for id in old.keys().filter(|id| !new.contains_key(*id)) {
+            doc.delete(ROOT, format!("{}{}", prefix, id))?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets a field, editing text fields in place so the edit merges with others
+fn put(doc: &mut AutoCommit, obj: &ObjId, name: &str, value: &Value) -> Result<(), automerge::AutomergeError> {
+    match value {
+        Value::String(text) if TEXT_FIELDS.contains(&name) => {
+            let text_obj = match doc.get(obj, name)? {
+                Some((Field::Object(ObjType::Text), text_obj)) => text_obj,
+                _ => doc.put_object(obj, name, ObjType::Text)?,
+            };
+            doc.update_text(&text_obj, text)
+        }
+        value => doc.put(obj, name, value.to_string()),
+    }
+}
+
+/// The fields of a list or todo in the document
+fn read_fields(doc: &AutoCommit, obj: &ObjId) -> Result<Map<String, Value>, automerge::AutomergeError> {
+    let mut fields = Map::new();
+    for name in doc.keys(obj) {
+        let value = match doc.get(obj, name.as_str())? {
+            Some((Field::Object(ObjType::Text), text)) => Value::String(doc.text(&text)?),
+            Some((Field::Scalar(scalar), _)) => match scalar.as_ref() {
+                ScalarValue::Str(json) => serde_json::from_str(json).unwrap_or_default(),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        fields.insert(name, value);
+    }
+    Ok(fields)
+}
+
+/// Position of a list or todo among its siblings, and the rest of its fields
+/// with its id back among its links
+fn placed(mut fields: Map<String, Value>, id: &str, nested: &str) -> (f64, Map<String, Value>) {
+    let position = fields.remove("@position").and_then(|position| position.as_f64()).unwrap_or_default();
+    let ids = fields.entry("remote_ids").or_insert_with(|| json!({}));
+    if let Value::Object(ids) = ids {
+        ids.insert(LOG_ID.to_string(), json!(id));
+    }
+    fields.insert(nested.to_string(), json!([]));
+    (position, fields)
+}
+
+/// The lists the document holds
+/// Edits that can't both hold are settled here: a todo whose parent was
+/// deleted, or that ended up its own ancestor, goes to the top of its list,
+/// and one whose list was deleted to the first list
+fn materialize(doc: &AutoCommit) -> Result<Vec<TodoList>, automerge::AutomergeError> {
+    let mut lists = Vec::new();
+    let mut todos = HashMap::new();
+    for key in doc.keys(ROOT) {
+        let Some((Field::Object(ObjType::Map), obj)) = doc.get(ROOT, key.as_str())? else {
+            continue;
+        };
+        let mut fields = read_fields(doc, &obj)?;
+        match key.split_once('/') {
+            Some(("l", id)) => {
+                let (position, fields) = placed(fields, id, "todos");
+                match serde_json::from_value::<TodoList>(Value::Object(fields)) {
+                    Ok(list) => lists.push((position, id.to_string(), list)),
+                    Err(err) => tracing::warn!("left out list {} of the change log: {}", id, err),
+                }
+            }
+            Some(("t", id)) => {
+                let list = fields.remove("@list").and_then(|list| list.as_str().map(str::to_string)).unwrap_or_default();
+                let parent = fields.remove("@parent").and_then(|parent| parent.as_str().map(str::to_string));
+                let (position, fields) = placed(fields, id, "children");
+                match serde_json::from_value::<TodoItem>(Value::Object(fields)) {
+                    Ok(todo) => drop(todos.insert(id.to_string(), (list, parent, position, todo))),
+                    Err(err) => tracing::warn!("left out todo {} of the change log: {}", id, err),
+                }
+            }
+            _ => {}
+        }
+    }
+    lists.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+    if lists.is_empty() && !todos.is_empty() {
+        lists.push((0.0, String::new(), TodoList::new("Todos")));
+    }
+
+    // Under their parents, or at the top of their lists
+    let mut children: HashMap<&str, Vec<(f64, &str)>> = HashMap::new();
+    let mut tops: HashMap<String, Vec<(f64, &str)>> = HashMap::new();
+    for (id, (list, parent, position, _)) in &todos {
+        match parent.as_deref() {
+            Some(parent) if parent != id && todos.contains_key(parent) => children.entry(parent).or_default(),
+            _ => match lists.iter().any(|(_, list_id, _)| list_id == list) {
+                true => tops.entry(list.clone()).or_default(),
+                false => tops.entry(lists[0].1.clone()).or_default(),
+            },
+        }
+        .push((*position, id.as_str()));
+    }
+    for siblings in children.values_mut().chain(tops.values_mut()) {
+        siblings.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+    }
+    fn tree(id: &str, todos: &HashMap<String, (String, Option<String>, f64, TodoItem)>,
+            children: &HashMap<&str, Vec<(f64, &str)>>, placed: &mut HashSet<String>) -> TodoItem {
+        placed.insert(id.to_string());
+        let mut todo = todos[id].3.clone();
+        for (_, child) in children.get(id).into_iter().flatten() {
+            if !placed.contains(*child) {
+                todo.children.push(tree(child, todos, children, placed));
+            }
+        }
+        todo
+    }
+    let mut placed = HashSet::new();
+    for (_, id, list) in &mut lists {
+        for (_, top) in tops.get(id).into_iter().flatten() {
+            list.todos.push(tree(top, &todos, &children, &mut placed));
+        }
+    }
+    // What's left went round in a circle of parents
+    let mut left: Vec<&String> = todos.keys().filter(|id| !placed.contains(*id)).collect();
+    left.sort();
+    for id in left {
+        if !placed.contains(id) {
+            let (list, ..) = &todos[id];
+            let index = lists.iter().position(|(_, list_id, _)| list_id == list).unwrap_or(0);
+            let todo = tree(id, &todos, &children, &mut placed);
+            lists[index].2.todos.push(todo);
+        }
+    }
+    Ok(lists.into_iter().map(|(.., list)| list).collect())
+}
+
+impl OpLog {
+    /// Opens the log of the todo file at `path`, reading what's in it so far
+    /// Nothing is written until there's a change
+    pub(crate) fn open(path: &Path) -> Result<OpLog, String> {
+        let doc = AutoCommit::new();
+        let dir = log_dir(path);
+        let file = dir.join(format!("{}.automerge", doc.get_actor().to_hex_string()));
+        let mut log = OpLog { dir, file, doc, sizes: HashMap::new(), base: Vec::new(), flat: Flat::default() };
+        log.read()?;
+        log.compact().map_err(|err| format!("can't compact the change log: {}", err))?;
+        Ok(log)
+    }
+
+    /// Reads what was appended to the log since it was last read
+    fn read(&mut self) -> Result<(), String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(format!("can't read {}: {}", self.dir.display(), err)),
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "automerge"))
+            .collect();
+        files.sort();
+        for path in files {
+            let Ok(size) = fs::metadata(&path).map(|metadata| metadata.len()) else {
+                continue;
+            };
+            if self.sizes.get(&path) == Some(&size) {
+                continue;
+            }
+            // Changes already read are skipped, so the whole file is read
+            // again - a change half written last time is whole by now
+            let loaded = fs::read(&path).map_err(|err| err.to_string()).and_then(|bytes| self.doc.load_incremental(&bytes).map_err(error));
+            match loaded {
+                Ok(_) => drop(self.sizes.insert(path, size)),
+                // Another device's broken file mustn't stop this one's changes
+                Err(err) => tracing::warn!("skipped {}: {}", path.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds changes to this instance's file
+    fn append(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let written = fs::create_dir_all(&self.dir).and_then(|()| {
+            let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.file)?;
+            file.write_all(bytes)?;
+            file.sync_data()?;
+            file.metadata()
+        });
+        let metadata = written.map_err(|err| format!("can't write {}: {}", self.file.display(), err))?;
+        self.sizes.insert(self.file.clone(), metadata.len());
+        Ok(())
+    }
+
+    /// Folds the files that have been left alone a while into this
+    /// instance's, once there are too many
+    fn compact(&mut self) -> io::Result<()> {
+        if self.sizes.len() <= COMPACT_AFTER {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        let old: Vec<PathBuf> = self
+            .sizes
+            .keys()
+            .filter(|path| {
+                let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+                modified.is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > COMPACT_AGE)
+            })
+            .cloned()
+            .collect();
+        if old.len() < 2 {
+            return Ok(());
+        }
+        // Everything is in this file before any other goes, so stopping
+        // halfway loses nothing
+        let bytes = self.doc.save();
+        let mut file = fs::File::create(&self.file)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        for path in old {
+            fs::remove_file(&path)?;
+            self.sizes.remove(&path);
+        }
+        self.sizes.insert(self.file.clone(), bytes.len() as u64);
+        Ok(())
+    }
+
+    /// Makes the changes from `old` to `new` on a copy of the document at
+    /// `at` and merges them in, appending them to this instance's file
+    /// Returns the heads of the copy, which has the changes of `at` and
+    /// these and nothing else
+    fn change(&mut self, at: &[ChangeHash], old: &Flat, new: &Flat) -> Result<Vec<ChangeHash>, String> {
+        // A copy has an actor of its own, so its changes never clash with
+        // the ones made since `at`
+        let mut copy = self.doc.fork_at(at).map_err(error)?;
+        apply(&mut copy, old, new).map_err(error)?;
+        if let Some(hash) = copy.commit() {
+            let bytes = copy.get_change_by_hash(&hash).map(|change| change.raw_bytes().to_vec()).unwrap_or_default();
+            self.append(&bytes)?;
+            self.doc.merge(&mut copy).map_err(error)?;
+        }
+        Ok(copy.get_heads())
+    }
+
+    /// Records the lists as the latest change, and returns the changes
+    /// they're in step with for the todo file to say
+    /// The lists need their ids given first
+    pub(crate) fn record(&mut self, lists: &[TodoList]) -> Result<Vec<String>, String> {
+        let flat = flatten(lists);
+        if flat != self.flat {
+            let base = std::mem::take(&mut self.base);
+            let old = std::mem::take(&mut self.flat);
+            self.base = self.change(&base, &old, &flat)?;
+            self.flat = flat;
+        }
+        Ok(self.base.iter().map(ChangeHash::to_string).collect())
+    }
+
+    /// The lists with every change in the log: this instance's unsaved lists,
+    /// if any, and the todo file with the changes it says it was written from
+    /// Fails when the todo file was written from changes that haven't
+    /// reached this device yet
+    /// The lists need their ids given first
+    pub(crate) fn merge(&mut self, ours: Option<&[TodoList]>, file: &[TodoList], heads: &[String]) -> Result<Vec<TodoList>, String> {
+        self.read()?;
+        if let Some(ours) = ours {
+            self.record(ours)?;
+        }
+        let heads: Vec<ChangeHash> = heads.iter().filter_map(|head| head.parse().ok()).collect();
+        if heads.iter().any(|head| self.doc.get_change_by_hash(head).is_none()) {
+            return Err("the todo file has changes that haven't reached this device's change log yet".to_string());
+        }
+        let written = self.doc.fork_at(&heads).map_err(error)?;
+        let before = flatten(&materialize(&written).map_err(error)?);
+        let after = flatten(file);
+        if before != after {
+            self.change(&heads, &before, &after)?;
+        }
+        let lists = materialize(&self.doc).map_err(error)?;
+        self.base = self.doc.get_heads();
+        self.flat = flatten(&lists);
+        Ok(lists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Status;
+
+    /// A log of its own, as another instance or device has it
+    fn open(dir: &Path) -> OpLog {
+        OpLog::open(&dir.join("todos.json")).unwrap()
+    }
+
+    fn texts(todos: &[TodoItem]) -> Vec<String> {
+        todos.iter().map(|todo| format!("{}{:?}", todo.text, texts(&todo.children))).collect()
+    }
+
+    #[test]
+    fn edits_made_apart_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut list = TodoList::new("Home");
+        list.todos = vec![TodoItem::new("water plants"), TodoItem::new("call mom")];
+        list.todos[0].children.push(TodoItem::new("the ones outside"));
+        let mut lists = vec![list];
+        give_ids(&mut lists);
+        let mut here = open(dir.path());
+        let heads = here.record(&lists).unwrap();
+
+        // Another instance starts from the same todo file
+        let mut there = open(dir.path());
+        assert_eq!(texts(&there.merge(None, &lists, &heads).unwrap()[0].todos), texts(&lists[0].todos));
+
+        // Here the first todo is renamed and done, there its notes change and
+        // it gets one more subtask, and the second one goes
+        let mut ours = lists.clone();
+        ours[0].todos[0].text = "water the plants".to_string();
+        ours[0].todos[0].status = Status::Done;
+        here.record(&ours).unwrap();
+        let mut theirs = lists.clone();
+        theirs[0].todos[0].text = "water plants today".to_string();
+        theirs[0].todos[0].notes = "twice".to_string();
+        theirs[0].todos[0].children.push(TodoItem::new("the herbs"));
+        theirs[0].todos.remove(1);
+        give_ids(&mut theirs);
+        let written = there.record(&theirs).unwrap();
+
+        let merged = here.merge(None, &theirs, &written).unwrap();
+        let todo = &merged[0].todos[0];
+        assert_eq!(todo.text, "water the plants today");
+        assert_eq!((todo.status, todo.notes.as_str()), (Status::Done, "twice"));
+        assert_eq!(texts(&merged[0].todos), ["water the plants today[\"the ones outside[]\", \"the herbs[]\"]"]);
+        // The ids stay with the todos
+        assert_eq!(todo.remote_ids.get(LOG_ID), lists[0].todos[0].remote_ids.get(LOG_ID));
+
+        // Both end up the same
+        let heads = here.record(&merged).unwrap();
+        assert_eq!(texts(&there.merge(None, &merged, &heads).unwrap()[0].todos), texts(&merged[0].todos));
+    }
+
+    #[test]
+    fn a_todo_file_edited_by_hand_is_a_change_from_where_it_was_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut lists = vec![TodoList::new("Home")];
+        lists[0].todos = vec![TodoItem::new("a"), TodoItem::new("b"), TodoItem::new("c")];
+        give_ids(&mut lists);
+        let mut log = open(dir.path());
+        let heads = log.record(&lists).unwrap();
+
+        // This instance moves on, while the file as written loses "b" and
+        // gets a todo without an id
+        let mut ours = lists.clone();
+        ours[0].todos[2].text = "c!".to_string();
+        let mut file = lists.clone();
+        file[0].todos.remove(1);
+        file[0].todos.push(TodoItem::new("d"));
+        give_ids(&mut file);
+        let merged = log.merge(Some(&ours), &file, &heads).unwrap();
+        assert_eq!(texts(&merged[0].todos), ["a[]", "c![]", "d[]"]);
+
+        // A file from changes this device hasn't seen is left to the user
+        let mut elsewhere = OpLog::open(&dir.path().join("elsewhere").join("todos.json")).unwrap();
+        let unknown = elsewhere.record(&file).unwrap();
+        assert!(log.merge(None, &file, &unknown).is_err());
+    }
+
+    #[test]
+    fn todos_that_lost_their_place_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut lists = vec![TodoList::new("Home"), TodoList::new("Work")];
+        lists[0].todos = vec![TodoItem::new("a"), TodoItem::new("b")];
+        lists[1].todos = vec![TodoItem::new("report")];
+        give_ids(&mut lists);
+        let mut here = open(dir.path());
+        let heads = here.record(&lists).unwrap();
+        let mut there = open(dir.path());
+        there.merge(None, &lists, &heads).unwrap();
+
+        // Here "a" goes under "b" and "report" gets a subtask, there "b"
+        // goes under "a" and the Work list is deleted
+        let mut ours = lists.clone();
+        let a = ours[0].todos.remove(0);
+        ours[0].todos[0].children.push(a);
+        ours[1].todos[0].children.push(TodoItem::new("draft"));
+        give_ids(&mut ours);
+        here.record(&ours).unwrap();
+        let mut theirs = lists.clone();
+        let b = theirs[0].todos.remove(1);
+        theirs[0].todos[0].children.push(b);
+        theirs.remove(1);
+        let written = there.record(&theirs).unwrap();
+
+        let merged = here.merge(None, &theirs, &written).unwrap();
+        assert_eq!(merged.len(), 1);
+        // The circle is broken at the top of the list, and the subtask added
+        // to a todo deleted with its list moves to the first one
+        let todos = texts(&merged[0].todos);
+        assert_eq!(todos.len(), 2, "{:?}", todos);
+        assert!(todos.contains(&"draft[]".to_string()), "{:?}", todos);
+        assert!(todos.iter().any(|todo| todo == "a[\"b[]\"]" || todo == "b[\"a[]\"]"), "{:?}", todos);
+    }
+}
@@ -0,0 +1,557 @@
+//! Plugins from the plugins directory next to the config file - for what
+//! the config can't express, like a key that does something of the user's
+//! own or a status bar segment of their own
+//! Every `*.lua` and `*.wasm` file there is loaded once at start, in name
+//! order. The Lua ones are in `lua` and the sandboxed WASM ones in `wasm`;
+//! each is a cargo feature, and a build without one says so for the files
+//! it can't load. One that fails is reported, and what it was reacting to
+//! happens without it
+
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::{item_at, App, ArchivedTodo, Command, SavedFilter, TodoItem, TodoList},
+    hooks::completes,
+    input::Key,
+    storage::Config,
+};
+#[cfg(feature = "lua")]
+use crate::lua::LuaPlugins;
+#[cfg(feature = "wasm")]
+use crate::wasm::WasmPlugins;
+
+/// Both kinds of plugins, the Lua ones getting each todo first
+#[derive(Default)]
+pub(crate) struct Plugins {
+    lua: LuaPlugins,
+    wasm: RefCell<WasmPlugins>,
+}
+
+impl Plugins {
+    /// Loads every `*.lua` and `*.wasm` file in the directory in name order,
+    /// returning what went wrong with the ones that failed - the others still load
+    /// No directory just means no plugins
+    pub(crate) fn load_dir(&self, dir: &Path) -> Vec<String> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(err) => return vec![format!("can't read plugins from {}: {}", dir.display(), err)],
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "lua" || extension == "wasm"))
+            .collect();
+        files.sort();
+        files
+            .iter()
+            .filter_map(|file| {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                let loaded = fs::read(file).map_err(|err| err.to_string()).and_then(|bytes| {
+                    match file.extension().is_some_and(|extension| extension == "wasm") {
+                        true => self.wasm.borrow_mut().load(&name, &bytes),
+                        false => self.lua.load(&name, &String::from_utf8_lossy(&bytes)),
+                    }
+                });
+                loaded.err().map(|err| format!("plugin {}: {}", name, err))
+            })
+            .collect()
+    }
+
+    /// The plugin binding for a key, if there is one
+    pub(crate) fn binding(&self, key: Key) -> Option<usize> {
+        self.lua.binding(key)
+    }
+
+    /// The keys the plugins bound with their descriptions, for the help
+    pub(crate) fn bindings(&self) -> Vec<(Key, String)> {
+        self.lua.bindings()
+    }
+
+    /// Runs a binding's function on the selected todo, returning the todo it
+    /// changed, if it did
+    pub(crate) fn run_binding(&self, index: usize, todo: Option<&TodoItem>) -> Result<Option<TodoItem>, String> {
+        self.lua.run_binding(index, todo)
+    }
+
+    /// The todo after the `on_add` functions and then the WASM plugins' add event
+    pub(crate) fn added(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        let todo = self.lua.added(todo)?;
+        self.wasm.borrow_mut().event("add", todo)
+    }
+
+    /// The todo after the `on_complete` functions and the complete event
+    pub(crate) fn completed(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        let todo = self.lua.completed(todo)?;
+        self.wasm.borrow_mut().event("complete", todo)
+    }
+
+    /// The todo after the edit event - any other change to it
+    fn edited(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        self.wasm.borrow_mut().event("edit", todo)
+    }
+
+    /// The todo after the move event, to another place in its list or to
+    /// another list
+    fn moved(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        self.wasm.borrow_mut().event("move", todo)
+    }
+
+    /// Tells the WASM plugins a todo is deleted - it goes whatever they reply
+    fn deleted(&self, todo: TodoItem) -> Result<(), String> {
+        self.wasm.borrow_mut().event("delete", todo).map(drop)
+    }
+
+    /// Whether any WASM plugins are loaded, the only ones hearing of more
+    /// than adding and completing
+    fn has_wasm(&self) -> bool {
+        !self.wasm.borrow().is_empty()
+    }
+
+    /// The names of the filters the plugins added, in the order they were added
+    pub(crate) fn filter_names(&self) -> Vec<String> {
+        self.lua.filter_names()
+    }
+
+    /// Whether a todo passes a plugin's filter
+    /// A failing filter lets nothing through, and `take_failure` has why
+    pub(crate) fn filter(&self, index: usize, todo: &TodoItem) -> bool {
+        self.lua.filter(index, todo)
+    }
+
+    /// What went wrong in a filter since the last time this was asked
+    pub(crate) fn take_failure(&self) -> Option<String> {
+        self.lua.take_failure()
+    }
+
+    /// The status bar segments for a list
+    pub(crate) fn status(&self, list: &TodoList) -> Result<Vec<String>, String> {
+        self.lua.status(list)
+    }
+}
+
+/// Stands in for the Lua plugins in a build without the `lua` feature:
+/// nothing is ever registered, and a `.lua` file says why it didn't load
+#[cfg(not(feature = "lua"))]
+#[derive(Default)]
+struct LuaPlugins;
+
+#[cfg(not(feature = "lua"))]
+impl LuaPlugins {
+    fn load(&self, _name: &str, _source: &str) -> Result<(), String> {
+        Err("this build of todo-tui has no Lua plugins (the `lua` feature)".to_string())
+    }
+
+    fn binding(&self, _key: Key) -> Option<usize> {
+        None
+    }
+
+    fn bindings(&self) -> Vec<(Key, String)> {
+        Vec::new()
+    }
+
+    fn run_binding(&self, _index: usize, _todo: Option<&TodoItem>) -> Result<Option<TodoItem>, String> {
+        Ok(None)
+    }
+
+    fn added(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        Ok(todo)
+    }
+
+    fn completed(&self, todo: TodoItem) -> Result<TodoItem, String> {
+        Ok(todo)
+    }
+
+    fn filter_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn filter(&self, _index: usize, _todo: &TodoItem) -> bool {
+        true
+    }
+
+    fn take_failure(&self) -> Option<String> {
+        None
+    }
+
+    fn status(&self, _list: &TodoList) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+}
+
+/// Stands in for the WASM plugins in a build without the `wasm` feature
+#[cfg(not(feature = "wasm"))]
+#[derive(Default)]
+struct WasmPlugins;
+
+#[cfg(not(feature = "wasm"))]
+impl WasmPlugins {
+    fn load(&mut self, _name: &str, _bytes: &[u8]) -> Result<(), String> {
+        Err("this build of todo-tui has no WASM plugins (the `wasm` feature)".to_string())
+    }
+
+    fn event(&mut self, _event: &str, todo: TodoItem) -> Result<TodoItem, String> {
+        Ok(todo)
+    }
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+}
+
+/// The lists as a command finds them, for the events of todos it only
+/// names by path
+type Before<'a> = (&'a [TodoList], &'a [ArchivedTodo]);
+
+/// A command with its todos run through the plugins
+/// `moves` is true in a batch that also removes todos, which is moving them
+fn transform(command: Command, plugins: &Plugins, before: Before, moves: bool, failure: &mut Option<String>) -> Command {
+    match command {
+        Command::Insert { list, path, item } => {
+            let event = if moves { Plugins::moved } else { Plugins::added };
+            Command::Insert { list, path, item: run(event, plugins, item, failure) }
+        }
+        Command::Replace { list, path, before, after } => {
+            let event = if completes(&before, &after) { Plugins::completed } else { Plugins::edited };
+            let after = Box::new(run(event, plugins, *after, failure));
+            Command::Replace { list, path, before, after }
+        }
+        // Only WASM plugins hear of deleting and reordering, so without any
+        // the todos aren't looked up
+        Command::Trash { list, ref path, .. } if plugins.has_wasm() => {
+            if let Err(err) = plugins.deleted(item_at(&before.0[list].todos, path).clone()) {
+                failure.get_or_insert(err);
+            }
+            command
+        }
+        Command::Move { list, ref from, ref to } if plugins.has_wasm() => {
+            let todo = item_at(&before.0[list].todos, from).clone();
+            let moved = run(Plugins::moved, plugins, todo.clone(), failure);
+            // A plugin changing the todo makes its change part of the move
+            if serde_json::to_value(&moved).ok() == serde_json::to_value(&todo).ok() {
+                return command;
+            }
+            let path = to.clone();
+            Command::Batch(vec![command, Command::Replace { list, path, before: Box::new(todo), after: Box::new(moved) }])
+        }
+        Command::Batch(commands) => {
+            let moves = commands.iter().any(|command| matches!(command, Command::Remove { .. }));
+            // Each command finds the lists as the ones before it left them
+            let mut lists = plugins.has_wasm().then(|| (before.0.to_vec(), before.1.to_vec()));
+            let mut transformed = Vec::with_capacity(commands.len());
+            for command in commands {
+                let before = lists.as_ref().map_or(before, |(lists, archive)| (lists, archive));
+                let command = transform(command, plugins, before, moves, failure);
+                if let Some((lists, archive)) = &mut lists {
+                    command.apply(lists, archive);
+                }
+                transformed.push(command);
+            }
+            Command::Batch(transformed)
+        }
+        command => command,
+    }
+}
+
+/// The todo after an event's functions, or as it was when one failed
+fn run(
+    event: fn(&Plugins, TodoItem) -> Result<TodoItem, String>,
+    plugins: &Plugins,
+    todo: TodoItem,
+    failure: &mut Option<String>,
+) -> TodoItem {
+    event(plugins, todo.clone()).unwrap_or_else(|err| {
+        failure.get_or_insert(err);
+        todo
+    })
+}
+
+impl App {
+    /// Loads the plugins from the plugins directory, returning what went
+    /// wrong with the ones that failed
+    pub fn load_plugins(&mut self) -> Vec<String> {
+        let Some(dir) = Config::plugins_dir() else {
+            return Vec::new();
+        };
+        let failures = self.plugins.load_dir(&dir);
+        self.plugins_loaded();
+        failures
+    }
+
+    /// Offers the plugins' filters in the picker and shows their status bar
+    /// segments
+    pub(crate) fn plugins_loaded(&mut self) {
+        let filters = self.plugins.filter_names().into_iter().enumerate();
+        self.plugin_filters =
+            filters.map(|(i, name)| SavedFilter { plugin: Some(i), ..SavedFilter::named(&name) }).collect();
+        self.refresh_view();
+    }
+
+    /// Hands the todos a command adds, completes, edits, moves or deletes to
+    /// the plugins, and puts what they return in their place
+    pub(crate) fn run_plugins(&mut self, command: Command) -> Command {
+        let mut failure = None;
+        let command = transform(command, &self.plugins, (&self.lists, &self.archive), false, &mut failure);
+        if let Some(err) = failure {
+            self.notice = Some(self.trf("Plugin failed: {}", &[&err]));
+        }
+        command
+    }
+
+    /// Runs the plugin function bound to a key on the selected todo,
+    /// replacing the todo with what it returns
+    pub(crate) fn run_plugin_binding(&mut self, index: usize) {
+        let path = self.selected_path();
+        let todo = path.as_ref().map(|path| item_at(self.todos(), path));
+        match (self.plugins.run_binding(index, todo), path) {
+            (Ok(Some(changed)), Some(path)) => self.replace(path, changed),
+            (Ok(_), _) => {}
+            (Err(err), _) => self.notice = Some(self.trf("Plugin failed: {}", &[&err])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "lua")]
+    use crate::update::{update, Message};
+    #[cfg(feature = "lua")]
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    #[cfg(feature = "lua")]
+    use ratatui::{backend::TestBackend, Terminal};
+
+    /// An app with these todos, the first one selected, and a Lua plugin
+    #[cfg(feature = "lua")]
+    fn app_with_plugin(texts: &[&str], source: &str) -> App {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-plugins-test.json"));
+        app.lists[0].todos = texts.iter().map(|text| TodoItem::new(text)).collect();
+        app.plugins.lua.load("test.lua", source).unwrap();
+        app.plugins_loaded();
+        app.state_mut().select((!texts.is_empty()).then_some(0));
+        app
+    }
+
+    #[cfg(feature = "lua")]
+    fn press(app: &mut App, c: char, modifiers: KeyModifiers) {
+        update(app, Message::Key(KeyEvent::new(KeyCode::Char(c), modifiers)));
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn the_app_runs_what_the_plugins_registered() {
+        let mut app = app_with_plugin(
+            &["milk", "groceries"],
+            r#"
+            todo.bind("!", "Shout", function(t) t.text = t.text .. "!"; return t end)
+            todo.on_add(function(t) t.project = "inbox"; return t end)
+            todo.on_complete(function(t) t.tags = { "done" }; return t end)
+            todo.filter("Short", function(t) return #t.text < 6 end)
+            todo.status(function(list) return #list.todos .. " todos" end)
+            "#,
+        );
+        assert_eq!(app.plugin_status, ["2 todos"]);
+
+        press(&mut app, '!', KeyModifiers::NONE);
+        assert_eq!(app.lists[0].todos[0].text, "milk!");
+        app.toggle_completed();
+        assert_eq!(app.lists[0].todos[0].tags, ["done"]);
+        // The change is undone like any other, plugin's part included
+        app.undo();
+        assert!(app.lists[0].todos[0].tags.is_empty());
+
+        app.execute(Command::Insert { list: 0, path: vec![2], item: TodoItem::new("eggs") });
+        assert_eq!(app.lists[0].todos[2].project.as_deref(), Some("inbox"));
+        assert_eq!(app.plugin_status, ["3 todos"]);
+
+        let row = app.filters().iter().position(|filter| filter.name == "Short").unwrap();
+        app.apply_filter_picker(row + 1);
+        let shown: Vec<&str> = app.visible.iter().map(|path| item_at(app.todos(), path).text.as_str()).collect();
+        assert_eq!(shown, ["milk!", "eggs"]);
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn plugin_keys_reach_the_app_and_the_help() {
+        let source = r#"
+            todo.bind("ctrl+t", "Mark as today", function(t)
+                if t == nil then error("nothing selected") end
+                t.text = t.text .. " today"
+                return t
+            end)
+            todo.bind("ctrl+n", "Do nothing", function(t) end)
+        "#;
+        let mut app = app_with_plugin(&["call mom"], source);
+        press(&mut app, 't', KeyModifiers::CONTROL);
+        assert_eq!(app.lists[0].todos[0].text, "call mom today");
+        // A binding returning nothing changes nothing, and isn't an undo step
+        press(&mut app, 'n', KeyModifiers::CONTROL);
+        app.undo();
+        assert_eq!(app.lists[0].todos[0].text, "call mom");
+
+        // With nothing selected the function gets nil, and its error is a notice
+        let mut app = app_with_plugin(&[], source);
+        press(&mut app, 't', KeyModifiers::CONTROL);
+        assert!(app.notice.as_ref().unwrap().contains("nothing selected"), "{:?}", app.notice);
+
+        // The help lists the plugin's keys with their descriptions
+        press(&mut app, '?', KeyModifiers::NONE);
+        let mut terminal = Terminal::new(TestBackend::new(100, 200)).unwrap();
+        terminal.draw(|f| crate::ui::ui(f, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Ctrl+t") && screen.contains("Mark as today"), "{}", screen);
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn plugin_filters_keep_what_they_return_true_for() {
+        let mut app = app_with_plugin(
+            &["milk", "groceries", "eggs"],
+            r#"
+            todo.filter("Short", function(t) return #t.text < 5 end)
+            todo.filter("Broken", function(t) return t.missing.field end)
+            "#,
+        );
+        assert_eq!(app.plugin_filters.len(), 2);
+        let row = app.filters().iter().position(|filter| filter.name == "Short").unwrap();
+        app.apply_filter_picker(row + 1);
+        let shown: Vec<&str> = app.visible.iter().map(|path| item_at(app.todos(), path).text.as_str()).collect();
+        assert_eq!(shown, ["milk", "eggs"]);
+        assert!(app.notice.is_none());
+
+        // A failing filter shows nothing and says why, once rather than per todo
+        let row = app.filters().iter().position(|filter| filter.name == "Broken").unwrap();
+        app.apply_filter_picker(row + 1);
+        assert!(app.visible.is_empty());
+        let notice = app.notice.clone().unwrap();
+        assert!(notice.starts_with("Plugin failed: ") && notice.contains("missing"), "{}", notice);
+        assert!(app.plugins.take_failure().is_none());
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn status_segments_follow_the_list_shown() {
+        let mut app = app_with_plugin(
+            &["milk"],
+            r#"
+            todo.status(function(list) return list.name .. ": " .. #list.todos end)
+            todo.status(function(list) if #list.todos == 0 then return "empty" end end)
+            "#,
+        );
+        app.lists.push(TodoList::new("work"));
+        assert_eq!(app.plugin_status, ["Todos: 1"]);
+        app.switch_list(1);
+        assert_eq!(app.plugin_status, ["work: 0", "empty"]);
+
+        // A failing segment is a notice, and the bar keeps what it had
+        app.plugins.lua.load("broken.lua", "todo.status(function(list) error('no status') end)").unwrap();
+        app.refresh_view();
+        assert_eq!(app.plugin_status, ["work: 0", "empty"]);
+        assert!(app.notice.as_ref().unwrap().contains("no status"));
+    }
+
+    #[cfg(all(feature = "lua", feature = "wasm"))]
+    #[test]
+    fn the_folder_loads_lua_and_wasm_plugins_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("1-tag.lua"), "todo.on_add(function(t) t.tags = { 'lua' }; return t end)").unwrap();
+        fs::write(dir.path().join("2-broken.lua"), "todo.on_add(").unwrap();
+        // The WASM plugin's todo wins, having come last - its text form is
+        // read as well, which saves building a module here
+        let wasm = r#"(module (memory (export "memory") 1)
+            (data (i32.const 0) "{\"todo\":{\"text\":\"wasm\"}}")
+            (func (export "alloc") (param i32) (result i32) i32.const 1024)
+            (func (export "on_event") (param i32 i32) (result i64) i64.const 24))"#;
+        fs::write(dir.path().join("3-tag.wasm"), wasm).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a plugin").unwrap();
+
+        let plugins = Plugins::default();
+        let failures = plugins.load_dir(dir.path());
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("plugin 2-broken.lua: "), "{}", failures[0]);
+        let added = plugins.added(TodoItem::new("x")).unwrap();
+        assert_eq!(added.text, "wasm");
+        assert!(added.tags.is_empty());
+        assert!(plugins.load_dir(&dir.path().join("missing")).is_empty());
+    }
+
+    /// A WASM plugin answering the events starting with `letter` with
+    /// `reply`, and failing on deletes so they're seen to be told
+    #[cfg(feature = "wasm")]
+    fn answering(letter: char, reply: &str) -> Vec<u8> {
+        let data = reply.replace('"', "\\\"");
+        let module = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 2048) "{data}")
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "on_event") (param $ptr i32) (param i32) (result i64)
+                    (if (i32.eq (i32.load8_u offset=10 (local.get $ptr)) (i32.const 100)) (then unreachable))
+                    (if (result i64) (i32.eq (i32.load8_u offset=10 (local.get $ptr)) (i32.const {}))
+                        (then (i64.const {}))
+                        (else (i64.const 0)))))"#,
+            letter as u32,
+            2048u64 << 32 | reply.len() as u64
+        );
+        module.into_bytes()
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn wasm_plugins_hear_of_edits_moves_and_deletes() {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-plugins-test.json"));
+        app.lists[0].todos = vec![TodoItem::new("milk"), TodoItem::new("eggs")];
+        app.lists.push(TodoList::new("work"));
+        app.refresh_view();
+        app.state_mut().select(Some(0));
+        let edits = answering('e', r#"{"todo":{"text":"edited"}}"#);
+        app.plugins.wasm.borrow_mut().load("edits.wat", &edits).unwrap();
+        let moves = answering('m', r#"{"todo":{"text":"moved"}}"#);
+        app.plugins.wasm.borrow_mut().load("moves.wat", &moves).unwrap();
+
+        app.edit(vec![0], "milk and honey");
+        assert_eq!(app.lists[0].todos[0].text, "edited");
+
+        // The plugin's change is part of the move, and undone with it
+        app.move_selected(1);
+        assert_eq!(app.lists[0].todos[1].text, "moved");
+        app.undo();
+        assert_eq!(app.lists[0].todos[0].text, "edited");
+        assert_eq!(app.lists[0].todos.len(), 2);
+
+        // Moving to another list is a move, not an add
+        app.state_mut().select(Some(1));
+        app.input = "work".to_string();
+        app.apply_move_to_list();
+        assert_eq!(app.lists[1].todos[0].text, "moved");
+
+        // A delete is heard of but goes ahead whatever the plugin does
+        app.state_mut().select(Some(0));
+        app.delete_selected();
+        assert!(app.lists[0].todos.is_empty());
+        assert!(app.notice.as_ref().unwrap().starts_with("Plugin failed: edits.wat: "), "{:?}", app.notice);
+    }
+
+    #[cfg(not(feature = "lua"))]
+    #[test]
+    fn lua_plugins_need_the_lua_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("tag.lua"), "todo.on_add(function(t) return t end)").unwrap();
+        let failures = Plugins::default().load_dir(dir.path());
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("plugin tag.lua: ") && failures[0].contains("`lua` feature"), "{}", failures[0]);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn wasm_plugins_need_the_wasm_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("tag.wasm"), "(module)").unwrap();
+        let failures = Plugins::default().load_dir(dir.path());
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("plugin tag.wasm: ") && failures[0].contains("`wasm` feature"), "{}", failures[0]);
+    }
+}
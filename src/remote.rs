@@ -0,0 +1,212 @@
+//! Two-way sync of the lists with another device running `serve`, so a
+//! laptop and a desktop - or any number of them - end up with the same todos
+//! The server keeps its todo file like any other; every device syncs with it
+//! the way the other services sync, so a change made offline waits in the
+//! todo file until a sync gets through, and when a todo changed on both
+//! sides, the later change wins
+//! Todos are matched by a uid they share on every device, and lists by name
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::{
+    app::{TodoItem, TodoList},
+    caldav::new_uid,
+    sync::{curl, curl_quote, reconcile, url_encode, Backend, RemoteTask, Service, SyncOutcome, SyncState},
+};
+
+/// The "remote" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct RemoteConfig {
+    /// Address of the device running `serve`, like "http://desktop:8080"
+    pub(crate) url: String,
+    /// The token in that device's "server" config, if it has one
+    pub(crate) token: String,
+    /// Minutes between syncs while the app runs, 0 to sync only when asked
+    pub(crate) interval: u64,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> RemoteConfig {
+        RemoteConfig { url: String::new(), token: String::new(), interval: 5 }
+    }
+}
+
+/// What of a todo syncs: everything but its subtasks, which sync as todos of
+/// their own, whether they're folded, and its history and the links to other
+/// services, which belong to the device that made them
+fn synced(todo: &TodoItem) -> TodoItem {
+    TodoItem {
+        children: Vec::new(),
+        collapsed: false,
+        modified_at: None,
+        history: Vec::new(),
+        remote_ids: BTreeMap::new(),
+        github_issue: None,
+        ..todo.clone()
+    }
+}
+
+/// Whether a todo and the server's copy agree on everything that's synced
+fn same(todo: &TodoItem, task: &TodoItem) -> bool {
+    serde_json::to_value(synced(todo)).ok() == serde_json::to_value(synced(task)).ok()
+}
+
+/// Takes the synced fields of the other side's copy for a todo
+/// The server takes what's pushed to it the same way
+pub(crate) fn pull(todo: &mut TodoItem, task: &TodoItem) {
+    *todo = TodoItem {
+        children: std::mem::take(&mut todo.children),
+        collapsed: todo.collapsed,
+        history: std::mem::take(&mut todo.history),
+        remote_ids: std::mem::take(&mut todo.remote_ids),
+        github_issue: todo.github_issue.take(),
+        ..task.clone()
+    };
+}
+
+/// The server's sync endpoints, called through curl
+struct Api<'a> {
+    config: &'a RemoteConfig,
+}
+
+impl Api<'_> {
+    /// Makes a request and returns the reply, None when it was 404 Not Found
+    fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Option<Value>, String> {
+        let mut options = vec![
+            format!("url = {}", curl_quote(&format!("{}{}", self.config.url.trim_end_matches('/'), path))),
+            format!("request = {}", method),
+        ];
+        if !self.config.token.is_empty() {
+            options.push(format!("header = {}", curl_quote(&format!("Authorization: Bearer {}", self.config.token))));
+        }
+        if let Some(body) = body {
+            options.push(format!("header = {}", curl_quote("Content-Type: application/json")));
+            options.push(format!("data-raw = {}", curl_quote(&body.to_string())));
+        }
+        let (code, reply) = curl(options)?;
+        let reply: Option<Value> = serde_json::from_str(&reply).ok();
+        match code {
+            404 => Ok(None),
+            200..=299 => Ok(Some(reply.unwrap_or_default())),
+            401 => Err("the sync server refused the token - check \"token\" under \"remote\" in the config".to_string()),
+            code => match reply.as_ref().and_then(|reply| reply.get("error")).and_then(Value::as_str) {
+                Some(error) => Err(format!("the sync server answered {}: {}", code, error)),
+                None => Err(format!("the sync server answered {} - is it running `todo-tui serve`?", code)),
+            },
+        }
+    }
+}
+
+/// A device running `serve` as a backend for `reconcile`
+/// Lists go by name, and todos by a uid made by whichever side has the todo
+/// first, so a todo deleted there goes back under the same one
+struct Server<'a> {
+    api: Api<'a>,
+    /// The todos on the server by uid, parents before their subtasks, read
+    /// with the lists
+    todos: Vec<(String, RemoteTask)>,
+}
+
+/// One sync, run on its own thread: sends what changed here to the server
+/// and returns what changed there
+pub(crate) fn sync(config: &RemoteConfig, lists: &[TodoList], state: &SyncState) -> Result<SyncOutcome, String> {
+    reconcile(&mut Server { api: Api { config }, todos: Vec::new() }, lists, state)
+}
+
+impl Backend for Server<'_> {
+    const SERVICE: Service = Service::Server;
+    const UNNAMED: &'static str = "Todos";
+    // Every device has the same todos, finished or not
+    const CLOSED: bool = true;
+
+    fn projects(&mut self) -> Result<Vec<(String, String)>, String> {
+        let reply = self.api.call("GET", "/sync", None)?.ok_or("the server has no /sync - is it running `todo-tui serve`?")?;
+        let mut names = Vec::new();
+        for list in reply.get("lists").and_then(Value::as_array).ok_or("unexpected reply from the sync server")? {
+            let name = list.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+            for entry in list.get("todos").and_then(Value::as_array).into_iter().flatten() {
+                let Some(uid) = entry.get("uid").and_then(Value::as_str) else {
+                    continue;
+                };
+                let Ok(todo) = serde_json::from_value::<TodoItem>(entry.get("todo").cloned().unwrap_or_default()) else {
+                    continue;
+                };
+                let parent = entry.get("parent").and_then(Value::as_str).map(str::to_string);
+                let task = RemoteTask { project: name.clone(), parent, updated: todo.modified_at, todo };
+                self.todos.push((uid.to_string(), task));
+            }
+            names.push((name.clone(), name));
+        }
+        Ok(names)
+    }
+
+    fn create_project(&mut self, name: &str) -> Result<String, String> {
+        self.api.call("PUT", &format!("/sync/lists/{}", url_encode(name)), None)?;
+        Ok(name.to_string())
+    }
+
+    fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String> {
+        Ok(std::mem::take(&mut self.todos))
+    }
+
+    fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool {
+        same(todo, task)
+    }
+
+    fn create(&mut self, todo: &TodoItem, list: &str, parent: Option<&str>, uid: Option<&str>) -> Result<String, String> {
+        let uid = uid.map_or_else(new_uid, str::to_string);
+        self.put(&uid, todo, list, parent)?;
+        Ok(uid)
+    }
+
+    fn update(&mut self, uid: &str, todo: &TodoItem, _: &RemoteTask, list: &str, parent: Option<&str>) -> Result<(), String> {
+        self.put(uid, todo, list, parent)
+    }
+
+    fn delete(&mut self, uid: &str, _: &RemoteTask) -> Result<(), String> {
+        self.api.call("DELETE", &format!("/sync/todos/{}", url_encode(uid)), None).map(|_| ())
+    }
+}
+
+impl Server<'_> {
+    /// Creates or updates the server's copy of a todo
+    /// The time it was changed goes along, so a device that was offline
+    /// doesn't win with older changes for syncing last
+    fn put(&mut self, uid: &str, todo: &TodoItem, list: &str, parent: Option<&str>) -> Result<(), String> {
+        let todo = TodoItem { children: Vec::new(), ..todo.clone() };
+        let body = json!({ "list": list, "parent": parent, "todo": todo });
+        self.api.call("PUT", &format!("/sync/todos/{}", url_encode(uid)), Some(&body))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Timestamp;
+
+    #[test]
+    fn pulling_keeps_what_belongs_to_this_device() {
+        let mut todo = TodoItem::new("water plants");
+        todo.children.push(TodoItem::new("the ones outside"));
+        todo.collapsed = true;
+        Service::Todoist.link(&mut todo, "123");
+        Service::Server.link(&mut todo, "abc");
+        let mut task = TodoItem::new("water the plants");
+        task.notes = "twice a week".to_string();
+        Service::Google.link(&mut task, "elsewhere");
+        assert!(!same(&todo, &task));
+
+        pull(&mut todo, &task);
+        assert_eq!((todo.text.as_str(), todo.notes.as_str()), ("water the plants", "twice a week"));
+        assert_eq!((todo.children.len(), todo.collapsed), (1, true));
+        let links = [Service::Todoist, Service::Google, Service::Server].map(|service| service.id(&todo));
+        assert_eq!(links, [Some("123"), None, Some("abc")]);
+        // Subtasks, folding, links and when it changed don't make it differ
+        task.modified_at = Some(Timestamp::now());
+        assert!(same(&todo, &task));
+    }
+}
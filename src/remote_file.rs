@@ -0,0 +1,460 @@
+//! A todo file that lives on a server - WebDAV (or any HTTP server that takes
+//! PUT), S3 or SSH - given to --file as a URL
+//! The app works on a copy in the data directory like on any local file: it's
+//! downloaded when the app starts and uploaded when it quits, and when the
+//! server can't be reached the copy is used as it is and the changes wait in
+//! it for the next time
+//! Over HTTP and S3 the ETag of the downloaded version makes sure an upload
+//! doesn't overwrite changes another device made in the meantime
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::App,
+    crypto::sha256,
+    i18n::fill,
+    storage::{data_dir, locked_elsewhere, snapshot_file, Config},
+    sync::{curl, curl_quote},
+};
+
+/// The "s3" table of the config, for todo files given as s3://bucket/key
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct S3Config {
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+    /// Region the bucket is in, like "eu-central-1" - us-east-1 when empty
+    pub(crate) region: String,
+    /// Address of an S3-compatible server like MinIO, like
+    /// "https://nas:9000", None for AWS itself
+    pub(crate) endpoint: Option<String>,
+}
+
+/// What's remembered about the copy between runs, in a file next to it
+#[derive(Default, Serialize, Deserialize)]
+struct CacheState {
+    /// The server's ETag for the version the copy started from - HTTP and S3 only
+    etag: Option<String>,
+    /// Whether the copy has changes the server doesn't have yet
+    pending: bool,
+    /// SHA-256 of the copy as it was last downloaded or uploaded - a copy that
+    /// no longer matches was saved to since, even if the app never got to
+    /// quit and set `pending`
+    #[serde(default)]
+    synced: Option<String>,
+}
+
+impl CacheState {
+    /// Whether the copy was changed since it was last in step with the server
+    /// A state from before the digest was kept can't tell, and uploading
+    /// without knowing could overwrite the server's newer version
+    fn changed(&self, cache: &Path) -> bool {
+        match (&self.synced, fs::read(cache)) {
+            (Some(synced), Ok(contents)) => *synced != digest(&contents),
+            _ => false,
+        }
+    }
+}
+
+/// The SHA-256 of a file's contents, in hex
+fn digest(contents: &[u8]) -> String {
+    sha256(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Why an upload didn't happen
+enum UploadError {
+    /// The file changed on the server since the copy was downloaded
+    Changed,
+    /// The server couldn't be reached or refused it
+    Failed(String),
+}
+
+/// A todo file on a server and the local copy the app works on
+pub struct RemoteFile {
+    /// The URL as given, for messages
+    url: String,
+    /// The URL curl is given - an s3:// one becomes the bucket's https:// address
+    target: String,
+    /// Keys to sign requests with, for S3
+    s3: Option<S3Config>,
+    /// The local copy
+    cache: PathBuf,
+    /// The copy's contents once it was brought up to date, to tell whether
+    /// there's anything to upload
+    started: Option<Vec<u8>>,
+    /// Whether another instance has the copy open - it's the one to sync it
+    shared: bool,
+}
+
+impl RemoteFile {
+    /// Whether --file was given a URL rather than a path
+    pub fn is_url(file: &Path) -> bool {
+        let file = file.to_string_lossy();
+        ["http://", "https://", "sftp://", "scp://", "s3://"].iter().any(|scheme| file.starts_with(scheme))
+    }
+
+    /// Sets up the copy of the todo file at `url`
+    /// Nothing is downloaded yet - that's `start`
+    pub fn new(url: &str, config: &Config) -> Result<RemoteFile, String> {
+        let (scheme, rest) = url.split_once("://").ok_or_else(|| format!("'{}' is not a URL", url))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if path.is_empty() || path.ends_with('/') {
+            return Err(format!("'{}' names a directory - the URL has to end with the todo file's name", url));
+        }
+        // Passwords in the URL stay out of the name of the copy
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        let (target, s3) = match scheme {
+            "s3" => {
+                let s3 = config
+                    .s3
+                    .clone()
+                    .filter(|s3| !s3.access_key.is_empty() && !s3.secret_key.is_empty())
+                    .ok_or("an s3:// file needs \"access_key\" and \"secret_key\" under \"s3\" in the config")?;
+                let target = match &s3.endpoint {
+                    // Other servers mostly don't have a name per bucket
+                    Some(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), host, path),
+                    None => format!("https://{}.s3.{}.amazonaws.com/{}", host, region(&s3), path),
+                };
+                (target, Some(s3))
+            }
+            _ => (url.to_string(), None),
+        };
+        let dir = data_dir().ok_or("could not determine the data directory for the local copy")?;
+        let cache = dir.join("remote").join(cache_name(scheme, host, path));
+        Ok(RemoteFile { url: url.to_string(), target, s3, cache, started: None, shared: false })
+    }
+
+    /// The local copy the app loads and saves
+    pub fn cache(&self) -> &Path {
+        &self.cache
+    }
+
+    /// Brings the copy up to date when the app starts: changes left in it
+    /// from last time go up first, otherwise the server's version comes down
+    /// Changes count whether the app quit and said so or was killed after
+    /// saving them
+    /// Returns what the user should know, like that the server couldn't be
+    /// reached and the copy is used as it is
+    pub fn start(&mut self) -> Option<RemoteNotice> {
+        // Downloading over the copy would undo what the other instance saved
+        if locked_elsewhere(&self.cache) {
+            self.shared = true;
+            return None;
+        }
+        let mut state = self.load_state();
+        let mut message = None;
+        let mut uploaded = false;
+        if (state.pending || state.changed(&self.cache)) && self.cache.exists() {
+            match self.upload(&mut state) {
+                Ok(()) => uploaded = true,
+                // Both sides changed - the server's version is taken, and this
+                // one kept where `restore` finds it
+                Err(UploadError::Changed) => match snapshot_file(&self.cache) {
+                    Ok(_) => {
+                        state.pending = false;
+                        message = Some(RemoteNotice::new(
+                            "{} changed elsewhere while your last changes waited to go up - the server's version is used, yours is kept as a snapshot (see `todo-tui restore`)",
+                            &[&self.url],
+                        ));
+                    }
+                    Err(err) => {
+                        self.started = fs::read(&self.cache).ok();
+                        return Some(RemoteNotice::new(
+                            "{} changed elsewhere while your last changes waited to go up, and they couldn't be kept as a snapshot ({}) - working on them offline",
+                            &[&self.url, &err],
+                        ));
+                    }
+                },
+                // No point trying to download either
+                Err(UploadError::Failed(err)) => {
+                    self.started = fs::read(&self.cache).ok();
+                    return Some(RemoteNotice::new("Couldn't reach {}: {} - working offline, the changes go up when you quit", &[&self.url, &err]));
+                }
+            }
+        }
+        if !uploaded && let Err(err) = self.download(&mut state) {
+            message = Some(match self.cache.exists() {
+                true => RemoteNotice::new("Couldn't download {}: {} - working offline on the copy from last time", &[&self.url, &err]),
+                false => RemoteNotice::new("Couldn't download {}: {} - starting empty, the todos go up when you quit", &[&self.url, &err]),
+            });
+        }
+        self.save_state(&state);
+        self.started = fs::read(&self.cache).ok();
+        message
+    }
+
+    /// Uploads the copy when the app quits, if anything changed
+    /// Err says why it didn't go up - the changes stay in the copy either way
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.shared {
+            return Ok(());
+        }
+        let mut state = self.load_state();
+        let contents = fs::read(&self.cache).ok();
+        if contents.is_none() || (!state.pending && contents == self.started) {
+            return Ok(());
+        }
+        // Remembered first, so a crash on the way still uploads next time
+        state.pending = true;
+        self.save_state(&state);
+        let result = self.upload(&mut state);
+        self.save_state(&state);
+        match result {
+            Ok(()) => Ok(()),
+            Err(UploadError::Changed) => Err(format!(
+                "{} changed elsewhere since it was downloaded, so your changes weren't uploaded - the next start keeps them as a snapshot and uses the server's version",
+                self.url
+            )),
+            Err(UploadError::Failed(err)) => Err(format!(
+                "Couldn't upload to {}: {} - your changes are kept in {} and go up the next time",
+                self.url,
+                err,
+                self.cache.display()
+            )),
+        }
+    }
+
+    /// A file next to the copy, named after it
+    fn beside(&self, suffix: &str) -> PathBuf {
+        let mut name = self.cache.file_name().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        self.cache.with_file_name(name)
+    }
+
+    fn load_state(&self) -> CacheState {
+        fs::read_to_string(self.beside(".remote.json")).ok().and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default()
+    }
+
+    /// Losing the state only costs a conflict check, so failing to write it isn't an error
+    fn save_state(&self, state: &CacheState) {
+        if let Some(dir) = self.cache.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(json) = serde_json::to_string(state) {
+            let _ = fs::write(self.beside(".remote.json"), json);
+        }
+    }
+
+    /// Whether the server speaks HTTP, which has ETags
+    fn http(&self) -> bool {
+        self.target.starts_with("http://") || self.target.starts_with("https://")
+    }
+
+    /// The options every request starts with
+    fn options(&self) -> Vec<String> {
+        // Credentials can come from ~/.netrc rather than the URL
+        let mut options = vec![format!("url = {}", curl_quote(&self.target)), "netrc-optional".to_string()];
+        if let Some(s3) = &self.s3 {
+            options.push(format!("aws-sigv4 = {}", curl_quote(&format!("aws:amz:{}:s3", region(s3)))));
+            options.push(format!("user = {}", curl_quote(&format!("{}:{}", s3.access_key, s3.secret_key))));
+        }
+        options
+    }
+
+    /// Downloads the server's version over the copy
+    /// A file that isn't on the server yet leaves the copy alone
+    fn download(&self, state: &mut CacheState) -> Result<(), String> {
+        let dir = self.cache.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(|err| format!("could not create {}: {}", dir.display(), err))?;
+        // Into a file of its own, so a broken transfer can't damage the copy
+        let part = self.beside(".part");
+        let headers = self.beside(".headers");
+        let mut options = self.options();
+        options.push(format!("output = {}", curl_quote(&part.to_string_lossy())));
+        options.push(format!("dump-header = {}", curl_quote(&headers.to_string_lossy())));
+        let result = curl(options);
+        let etag = read_etag(&headers);
+        let _ = fs::remove_file(&headers);
+        let code = match result {
+            Ok((code, _)) => code,
+            // SFTP and SCP have no status codes, only curl's error for a missing file
+            Err(err) if !self.http() && err.contains("(78)") => 404,
+            Err(err) => {
+                let _ = fs::remove_file(&part);
+                return Err(err);
+            }
+        };
+        if !matches!(code, 0 | 200) {
+            let _ = fs::remove_file(&part);
+        }
+        match code {
+            0 | 200 => {
+                fs::rename(&part, &self.cache).map_err(|err| format!("could not write {}: {}", self.cache.display(), err))?;
+                state.etag = etag;
+                state.synced = fs::read(&self.cache).ok().map(|contents| digest(&contents));
+                Ok(())
+            }
+            // Uploaded as a new file the first time the app quits
+            404 => {
+                state.etag = None;
+                Ok(())
+            }
+            401 | 403 => Err("the server refused the credentials - check the URL, ~/.netrc or \"s3\" in the config".to_string()),
+            code => Err(format!("the server answered {}", code)),
+        }
+    }
+
+    /// Uploads the copy, only over the version it was downloaded from
+    fn upload(&self, state: &mut CacheState) -> Result<(), UploadError> {
+        let headers = self.beside(".headers");
+        // What goes up, for telling later saves apart from it
+        let uploaded = fs::read(&self.cache).ok().map(|contents| digest(&contents));
+        let mut options = self.options();
+        options.push(format!("upload-file = {}", curl_quote(&self.cache.to_string_lossy())));
+        options.push("ftp-create-dirs".to_string());
+        if self.http() {
+            let condition = match &state.etag {
+                Some(etag) => format!("If-Match: {}", etag),
+                // Only if nothing else created it in the meantime
+                None => "If-None-Match: *".to_string(),
+            };
+            options.push(format!("header = {}", curl_quote(&condition)));
+            options.push(format!("dump-header = {}", curl_quote(&headers.to_string_lossy())));
+        }
+        let result = curl(options);
+        let etag = read_etag(&headers);
+        let _ = fs::remove_file(&headers);
+        match result.map_err(UploadError::Failed)?.0 {
+            0 | 200..=299 => {
+                state.pending = false;
+                state.synced = uploaded;
+                // Not every server says in the reply - then it's asked for
+                state.etag = match etag {
+                    Some(etag) => Some(etag),
+                    None if self.http() => self.head(),
+                    None => None,
+                };
+                Ok(())
+            }
+            412 => Err(UploadError::Changed),
+            401 | 403 => {
+                Err(UploadError::Failed("the server refused the credentials - check the URL, ~/.netrc or \"s3\" in the config".to_string()))
+            }
+            code => Err(UploadError::Failed(format!("the server answered {}", code))),
+        }
+    }
+
+    /// The server's ETag for the file as it is now
+    fn head(&self) -> Option<String> {
+        let headers = self.beside(".headers");
+        let mut options = self.options();
+        options.push("head".to_string());
+        options.push(format!("dump-header = {}", curl_quote(&headers.to_string_lossy())));
+        let result = curl(options);
+        let etag = read_etag(&headers);
+        let _ = fs::remove_file(&headers);
+        etag.filter(|_| matches!(result, Ok((200, _))))
+    }
+}
+
+/// What bringing a remote todo file up to date turned up, as the English
+/// template and its values, so the interface can show it in the user's
+/// language and the command line prints it as it is
+pub struct RemoteNotice {
+    template: &'static str,
+    values: Vec<String>,
+}
+
+impl RemoteNotice {
+    fn new(template: &'static str, values: &[&dyn fmt::Display]) -> RemoteNotice {
+        RemoteNotice { template, values: values.iter().map(|value| value.to_string()).collect() }
+    }
+}
+
+impl fmt::Display for RemoteNotice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values: Vec<&dyn fmt::Display> = self.values.iter().map(|value| value as &dyn fmt::Display).collect();
+        f.write_str(&fill(self.template, &values))
+    }
+}
+
+impl App {
+    /// Shows what bringing a remote todo file up to date turned up, like
+    /// that the app works offline
+    pub fn show_remote_notice(&mut self, notice: RemoteNotice) {
+        let values: Vec<&dyn fmt::Display> = notice.values.iter().map(|value| value as &dyn fmt::Display).collect();
+        self.notice = Some(self.trf(notice.template, &values));
+    }
+}
+
+/// The bucket's region, AWS's first one when the config doesn't say
+fn region(s3: &S3Config) -> &str {
+    match s3.region.is_empty() {
+        true => "us-east-1",
+        false => &s3.region,
+    }
+}
+
+/// The name of the copy: where the file is, flattened to one file name that
+/// still ends in its extension, so the format is picked the same way
+fn cache_name(scheme: &str, host: &str, path: &str) -> String {
+    format!("{}_{}_{}", scheme, host, path)
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// The ETag from headers curl wrote - the last one, after any redirects
+fn read_etag(headers: &Path) -> Option<String> {
+    let headers = fs::read_to_string(headers).ok()?;
+    headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("etag"))
+        .map(|(_, value)| value.trim().to_string())
+        .next_back()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_named_after_where_the_file_is() {
+        assert_eq!(cache_name("https", "nas.local:8443", "dav/todos.json"), "https_nas.local_8443_dav_todos.json");
+        assert_eq!(cache_name("sftp", "nas", "home/me/todo.txt"), "sftp_nas_home_me_todo.txt");
+        assert!(RemoteFile::is_url(Path::new("s3://bucket/todos.md")));
+        assert!(!RemoteFile::is_url(Path::new("todos.json")));
+    }
+
+    #[test]
+    fn saves_from_a_run_that_never_quit_go_up_at_the_next_start() {
+        // curl writes file:// URLs like a server without ETags, as SFTP is
+        let dir = tempfile::tempdir().unwrap();
+        let server = dir.path().join("server.json");
+        fs::write(&server, "{\"version\": 1}").unwrap();
+        let open = || RemoteFile {
+            url: "sftp://nas/todos.json".to_string(),
+            target: format!("file://{}", server.display()),
+            s3: None,
+            cache: dir.path().join("copy").join("todos.json"),
+            started: None,
+            shared: false,
+        };
+        let mut file = open();
+        assert!(file.start().is_none());
+        assert_eq!(fs::read_to_string(file.cache()).unwrap(), "{\"version\": 1}");
+
+        // An autosave, then the terminal is closed without `finish`
+        fs::write(file.cache(), "{\"version\": 2}").unwrap();
+        drop(file);
+
+        let mut file = open();
+        assert!(file.start().is_none());
+        assert_eq!(fs::read_to_string(&server).unwrap(), "{\"version\": 2}");
+        assert_eq!(fs::read_to_string(file.cache()).unwrap(), "{\"version\": 2}");
+
+        // In step again, so the server's next version comes down
+        fs::write(&server, "{\"version\": 3}").unwrap();
+        let mut file = open();
+        assert!(file.start().is_none());
+        assert_eq!(fs::read_to_string(file.cache()).unwrap(), "{\"version\": 3}");
+    }
+}
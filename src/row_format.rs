@@ -0,0 +1,193 @@
+//! The `row_format` template that decides what a list row shows, like
+//! `{checkbox} {text} {due:>12} {tags}`
+//! Parsed once when the config is applied, so drawing a row only walks the pieces
+
+/// The row the list shows without a `row_format`
+pub(crate) const DEFAULT_ROW_FORMAT: &str = "{checkbox} {star} {priority:<3} {text} {progress} {due} {starts} {repeat} {tracking} {notes} {project} {tags}";
+
+/// Something about a todo a row can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RowField {
+    Checkbox,
+    /// ★ on starred todos
+    Star,
+    Priority,
+    Text,
+    /// Done and total subtasks, like "1/3"
+    Progress,
+    Due,
+    Starts,
+    /// ↻ on recurring todos
+    Repeat,
+    /// ⏱ while time is being tracked
+    Tracking,
+    /// ✎ on todos with notes
+    Notes,
+    /// Time tracked so far
+    Time,
+    Project,
+    Tags,
+    Created,
+}
+
+impl RowField {
+    /// The names fields go by in templates
+    const NAMES: [(&'static str, RowField); 14] = [
+        ("checkbox", RowField::Checkbox),
+        ("star", RowField::Star),
+        ("priority", RowField::Priority),
+        ("text", RowField::Text),
+        ("progress", RowField::Progress),
+        ("due", RowField::Due),
+        ("starts", RowField::Starts),
+        ("repeat", RowField::Repeat),
+        ("tracking", RowField::Tracking),
+        ("notes", RowField::Notes),
+        ("time", RowField::Time),
+        ("project", RowField::Project),
+        ("tags", RowField::Tags),
+        ("created", RowField::Created),
+    ];
+
+    fn from_name(name: &str) -> Option<RowField> {
+        RowField::NAMES.iter().find(|(n, _)| *n == name).map(|(_, field)| *field)
+    }
+}
+
+/// Which side of its column a field with a width keeps to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// A stretch of a template: text shown as written, or a field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Piece {
+    Literal(String),
+    /// A field, padded to `width` columns if it has one and cut off past it
+    Field { field: RowField, align: Align, width: Option<usize> },
+}
+
+/// A parsed `row_format`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RowFormat {
+    pub(crate) pieces: Vec<Piece>,
+}
+
+impl Default for RowFormat {
+    fn default() -> Self {
+        RowFormat::parse(DEFAULT_ROW_FORMAT).expect("the default row format parses")
+    }
+}
+
+impl RowFormat {
+    /// Parses a template of text and `{field}`, `{field:12}`, `{field:<12}`,
+    /// `{field:>12}` or `{field:^12}` placeholders - `{{` and `}}` are braces
+    /// A template needs `{text}`, since a row without it can't be told apart
+    pub(crate) fn parse(template: &str) -> Result<RowFormat, String> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err("has a '}' without a '{' - write '}}' for a brace".to_string()),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("has '{{{}' without its '}}'", placeholder)),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(parse_placeholder(&placeholder)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        if !pieces.iter().any(|piece| matches!(piece, Piece::Field { field: RowField::Text, .. })) {
+            return Err("needs a {text} field".to_string());
+        }
+        Ok(RowFormat { pieces })
+    }
+}
+
+/// Reads what's between the braces: a field name, then optionally a colon,
+/// an alignment and a width
+fn parse_placeholder(placeholder: &str) -> Result<Piece, String> {
+    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    let field = RowField::from_name(name.trim()).ok_or_else(|| {
+        let names: Vec<&str> = RowField::NAMES.iter().map(|(n, _)| *n).collect();
+        format!("has an unknown field '{}' - use one of {}", name.trim(), names.join(", "))
+    })?;
+    let (align, width) = match spec.chars().next() {
+        Some('<') => (Align::Left, &spec[1..]),
+        Some('>') => (Align::Right, &spec[1..]),
+        Some('^') => (Align::Center, &spec[1..]),
+        _ => (Align::Left, spec),
+    };
+    let width = match width {
+        "" if spec.is_empty() => None,
+        width => match width.parse() {
+            Ok(width) if width > 0 => Some(width),
+            _ => return Err(format!("has '{}' for a width in {{{}}}", width, placeholder)),
+        },
+    };
+    Ok(Piece::Field { field, align, width })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(field: RowField, align: Align, width: Option<usize>) -> Piece {
+        Piece::Field { field, align, width }
+    }
+
+    #[test]
+    fn templates_parse_into_pieces() {
+        let format = RowFormat::parse("{checkbox} {text:30} {due:>12}{{{tags:^8}}}").unwrap();
+        assert_eq!(
+            format.pieces,
+            [
+                field(RowField::Checkbox, Align::Left, None),
+                Piece::Literal(" ".to_string()),
+                field(RowField::Text, Align::Left, Some(30)),
+                Piece::Literal(" ".to_string()),
+                field(RowField::Due, Align::Right, Some(12)),
+                Piece::Literal("{".to_string()),
+                field(RowField::Tags, Align::Center, Some(8)),
+                Piece::Literal("}".to_string()),
+            ]
+        );
+        assert!(RowFormat::parse(DEFAULT_ROW_FORMAT).is_ok());
+
+        for (template, error) in [
+            ("{checkbox}", "needs a {text} field"),
+            ("{text} {colour}", "has an unknown field 'colour'"),
+            ("{text} {due", "has '{due' without its '}'"),
+            ("{text} }", "has a '}' without a '{'"),
+            ("{text:>}", "has '' for a width in {text:>}"),
+            ("{text:wide}", "has 'wide' for a width in {text:wide}"),
+        ] {
+            let message = RowFormat::parse(template).unwrap_err();
+            assert!(message.starts_with(error), "{:?} gave {:?}", template, message);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! The index behind the `/` search, so typing a query doesn't lowercase and
+//! scan the text of every todo on every key press
+//! Todos have no ids, so lowercased texts are kept by the text itself: a todo
+//! that moves, or an edit elsewhere in the list, only costs a lookup, and just
+//! new texts need lowercasing
+//! A search only looks at the texts that can contain the query and the todos
+//! that have them, so a key press costs what it finds rather than the length
+//! of the list
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::app::{TodoItem, TodoList, TodoPath};
+
+/// One to three lowercase characters in a row, the unit the index looks texts
+/// up by - shorter ones are padded with NULs so a query of one or two
+/// characters has entries to look up too
+type Gram = [char; 3];
+
+/// Indexed texts past which the ones no todo has anymore are dropped, on top
+/// of twice the number of todos
+const SLACK: usize = 1024;
+
+/// Where a todo's text is in the index, laid out like the todos themselves so
+/// the view can walk both together instead of looking every text up
+pub(crate) struct Shape {
+    entry: usize,
+    children: Vec<Shape>,
+}
+
+/// Lowercased todo texts and the trigrams they contain
+#[derive(Default)]
+pub(crate) struct SearchIndex {
+    /// Each indexed text lowercased, by entry
+    lowered: Vec<String>,
+    /// The entry for each todo text as written
+    entries: HashMap<String, usize>,
+    /// The entries whose lowercased text contains each gram, in order
+    grams: HashMap<Gram, Vec<usize>>,
+    /// The entries of every list's todos, by position
+    shapes: Vec<Vec<Shape>>,
+    /// The paths of every list's todos, by entry
+    places: Vec<HashMap<usize, Vec<TodoPath>>>,
+    /// Whether the todos may have changed since the shapes were built
+    stale: bool,
+}
+
+impl SearchIndex {
+    pub(crate) fn new() -> Self {
+        SearchIndex { stale: true, ..SearchIndex::default() }
+    }
+
+    /// Notes that the todos may have changed - the index catches up on the next
+    /// search rather than on every edit, since most edits happen without one
+    pub(crate) fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Brings the index up to date with the lists, lowercasing only new texts
+    /// Once too many texts are left over from deleted or edited todos, the
+    /// index starts over so it doesn't grow for the whole session
+    pub(crate) fn update(&mut self, lists: &[TodoList]) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+        self.build(lists);
+        let count: usize = self.shapes.iter().map(|shapes| count(shapes)).sum();
+        if self.lowered.len() > 2 * count + SLACK {
+            *self = SearchIndex::default();
+            self.build(lists);
+        }
+    }
+
+    fn build(&mut self, lists: &[TodoList]) {
+        self.shapes.clear();
+        self.places.clear();
+        for list in lists {
+            let mut places = HashMap::new();
+            let shapes = self.insert_all(&list.todos, &mut Vec::new(), &mut places);
+            self.shapes.push(shapes);
+            self.places.push(places);
+        }
+    }
+
+    /// The entries of one list's todos, once the index is up to date
+    pub(crate) fn shape(&self, list: usize) -> &[Shape] {
+        self.shapes.get(list).map_or(&[], Vec::as_slice)
+    }
+
+    fn insert_all(
+        &mut self,
+        todos: &[TodoItem],
+        prefix: &mut TodoPath,
+        places: &mut HashMap<usize, Vec<TodoPath>>,
+    ) -> Vec<Shape> {
+        let mut shapes = Vec::with_capacity(todos.len());
+        for (i, todo) in todos.iter().enumerate() {
+            prefix.push(i);
+            let entry = self.insert(&todo.text);
+            places.entry(entry).or_default().push(prefix.clone());
+            shapes.push(Shape { entry, children: self.insert_all(&todo.children, prefix, places) });
+            prefix.pop();
+        }
+        shapes
+    }
+
+    fn insert(&mut self, text: &str) -> usize {
+        if let Some(&entry) = self.entries.get(text) {
+            return entry;
+        }
+        let entry = self.lowered.len();
+        let lowered = text.to_lowercase();
+        let chars: Vec<char> = lowered.chars().collect();
+        let mut seen: Vec<Gram> = (1..=3).flat_map(|n| chars.windows(n).map(gram)).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        for gram in seen {
+            self.grams.entry(gram).or_default().push(entry);
+        }
+        self.entries.insert(text.to_string(), entry);
+        self.lowered.push(lowered);
+        entry
+    }
+
+    /// Finds the indexed texts that contain the already lowercased query
+    /// Only the texts sharing the query's rarest gram are compared - for a
+    /// query of one or two characters that's the query itself
+    pub(crate) fn search<'a>(&'a self, query: &'a str) -> Matches<'a> {
+        // Without a query every todo matches and nothing needs looking up
+        if query.is_empty() {
+            return Matches { query, hits: HashSet::new() };
+        }
+        let chars: Vec<char> = query.chars().collect();
+        let rarest = chars
+            .windows(chars.len().min(3))
+            .map(|window| self.grams.get(&gram(window)).map_or(&[][..], Vec::as_slice))
+            .min_by_key(|entries| entries.len())
+            .unwrap_or_default();
+        let hits = rarest.iter().copied().filter(|&entry| self.lowered[entry].contains(query)).collect();
+        Matches { query, hits }
+    }
+
+    /// The todos of a list a search can show: the ones whose text matched and
+    /// the todos above them, so the view walks just these
+    /// None without a query, when any todo can show
+    pub(crate) fn candidates(&self, list: usize, matches: &Matches) -> Option<Candidates> {
+        if matches.query.is_empty() {
+            return None;
+        }
+        let mut candidates = Candidates::default();
+        if let Some(places) = self.places.get(list) {
+            for path in matches.hits.iter().filter_map(|entry| places.get(entry)).flatten() {
+                candidates.insert(path);
+            }
+        }
+        Some(candidates)
+    }
+}
+
+/// Part of a list's todos, by position among their siblings
+#[derive(Default)]
+pub(crate) struct Candidates(BTreeMap<usize, Candidates>);
+
+/// The candidates below a todo that isn't one
+static NO_CANDIDATES: Candidates = Candidates(BTreeMap::new());
+
+impl Candidates {
+    fn insert(&mut self, path: &[usize]) {
+        if let Some((&first, rest)) = path.split_first() {
+            self.0.entry(first).or_default().insert(rest);
+        }
+    }
+}
+
+/// What a search found, as the view walks down the todos: the texts that
+/// matched and the candidates at the level it's at
+#[derive(Clone, Copy)]
+pub(crate) struct Found<'a> {
+    pub(crate) matches: &'a Matches<'a>,
+    candidates: Option<&'a Candidates>,
+}
+
+impl<'a> Found<'a> {
+    pub(crate) fn new(matches: &'a Matches<'a>, candidates: Option<&'a Candidates>) -> Self {
+        Found { matches, candidates }
+    }
+
+    /// The positions among `len` siblings that can show, in order - all of
+    /// them without a search
+    pub(crate) fn positions(self, len: usize) -> impl Iterator<Item = usize> + 'a {
+        let (some, all) = match self.candidates {
+            Some(candidates) => (Some(candidates.0.keys().copied()), None),
+            None => (None, Some(0..len)),
+        };
+        some.into_iter().flatten().chain(all.into_iter().flatten()).filter(move |&i| i < len)
+    }
+
+    /// The same for the subtasks of the todo at position `i`
+    pub(crate) fn below(self, i: usize) -> Found<'a> {
+        let candidates = self.candidates.map(|candidates| candidates.0.get(&i).unwrap_or(&NO_CANDIDATES));
+        Found { candidates, ..self }
+    }
+}
+
+/// The shapes of a todo's subtasks, none when the todo has no shape
+pub(crate) fn children(shape: Option<&Shape>) -> &[Shape] {
+    shape.map_or(&[], |shape| &shape.children)
+}
+
+/// How many todos the shapes stand for, subtasks included
+fn count(shapes: &[Shape]) -> usize {
+    shapes.iter().map(|shape| 1 + count(&shape.children)).sum()
+}
+
+/// The gram of up to three characters
+fn gram(chars: &[char]) -> Gram {
+    let mut gram = ['\0'; 3];
+    gram[..chars.len()].copy_from_slice(chars);
+    gram
+}
+
+/// The outcome of one search, asked per todo while the view is built
+pub(crate) struct Matches<'a> {
+    query: &'a str,
+    /// The entries whose text contains the query
+    hits: HashSet<usize>,
+}
+
+impl Matches<'_> {
+    /// Whether the todo's text contains the query, ignoring case
+    /// A todo the index has no shape for is compared directly
+    pub(crate) fn contains(&self, todo: &TodoItem, shape: Option<&Shape>) -> bool {
+        match shape {
+            _ if self.query.is_empty() => true,
+            Some(shape) => self.hits.contains(&shape.entry),
+            None => todo.text.to_lowercase().contains(self.query),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(texts: &[&str]) -> TodoList {
+        let mut list = TodoList::new("Todos");
+        list.todos = texts.iter().map(|text| TodoItem::new(text)).collect();
+        list
+    }
+
+    #[test]
+    fn search_finds_the_same_todos_as_a_scan() {
+        let mut lists = [list(&["Buy Milk", "milkshake", "Call mum", "Straße fegen", "ÉCOLE", "a", ""])];
+        lists[0].todos[2].children.push(TodoItem::new("Ask about MILK"));
+        let mut index = SearchIndex::new();
+        index.update(&lists);
+        for query in ["", "m", "mi", "milk", "ilk", "mum", "strasse", "straße", "école", "cole", "xyz", "a"] {
+            let matches = index.search(query);
+            for (todo, shape) in lists[0].todos.iter().zip(index.shape(0)) {
+                let child = todo.children.first().zip(shape.children.first());
+                for (todo, shape) in std::iter::once((todo, shape)).chain(child) {
+                    let expected = todo.text.to_lowercase().contains(query);
+                    assert_eq!(matches.contains(todo, Some(shape)), expected, "{:?} in {:?}", query, todo.text);
+                    assert_eq!(matches.contains(todo, None), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_search_only_walks_what_it_found_and_the_todos_above() {
+        let mut lists = [list(&["Buy milk", "Call mum", "Water plants"])];
+        lists[0].todos[1].children = vec![TodoItem::new("ask about MILK"), TodoItem::new("tea")];
+        lists[0].todos[1].children[0].children.push(TodoItem::new("soon"));
+        let mut index = SearchIndex::new();
+        index.update(&lists);
+        let matches = index.search("milk");
+        // Only the texts with the query are hits, not one flag per text
+        assert_eq!(matches.hits.len(), 2);
+        let candidates = index.candidates(0, &matches).unwrap();
+        let found = Found::new(&matches, Some(&candidates));
+        assert_eq!(found.positions(3).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(found.below(1).positions(2).collect::<Vec<_>>(), [0]);
+        assert_eq!(found.below(1).below(0).positions(1).count(), 0);
+        assert_eq!(found.below(2).positions(0).count(), 0);
+        // Queries shorter than a trigram are looked up too
+        assert_eq!(index.search("mu").hits.len(), 1);
+        assert_eq!(index.search("a").hits.len(), 4);
+
+        // Without a query every todo is walked
+        let everything = index.search("");
+        assert!(index.candidates(0, &everything).is_none());
+        assert_eq!(Found::new(&everything, None).positions(3).collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn edits_reach_the_index() {
+        let mut lists = [list(&["Buy milk", "Buy milk"])];
+        let mut index = SearchIndex::new();
+        index.update(&lists);
+        // The same text shares its entry
+        assert_eq!(index.lowered.len(), 1);
+
+        lists[0].todos[1].text = "Buy bread".to_string();
+        index.invalidate();
+        index.update(&lists);
+        let matches = index.search("bread");
+        assert!(!matches.contains(&lists[0].todos[0], index.shape(0).first()));
+        assert!(matches.contains(&lists[0].todos[1], index.shape(0).get(1)));
+
+        // Renaming over and over doesn't keep every old text around
+        for i in 0..3 * SLACK {
+            lists[0].todos[1].text = format!("Todo {}", i);
+            index.invalidate();
+            index.update(&lists);
+        }
+        assert!(index.lowered.len() <= 4 + SLACK);
+        assert!(index.search("todo 30").contains(&lists[0].todos[1], index.shape(0).get(1)));
+    }
+}
@@ -0,0 +1,648 @@
+//! `todo-tui serve`: the lists as JSON over HTTP, for web dashboards, phone
+//! shortcuts and scripts
+//! Each request works on the todo file the way a command line subcommand
+//! does - the file is read again if something else wrote it, and a change is
+//! saved before the reply goes out - so a running interface picks the change
+//! up like any other write to the file
+//! Requests are answered one at a time, which is plenty for one person's lists
+//! and means two of them never change the lists at once
+//! Other devices sync with the lists through the /sync endpoints (see remote.rs)
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use crate::{
+    app::{find_item, format_todo_number, item_at, item_at_mut, parse_todo_number, App, Command, TodoItem, TodoList, TodoPath},
+    caldav::new_uid,
+    remote,
+    storage::Config,
+    sync::{find_task, url_decode, Service},
+};
+
+/// Bodies bigger than this are turned away - a todo is never near it
+const MAX_BODY: usize = 1 << 20;
+
+/// Longest request line or header line that's read, since a client that
+/// never sends a newline would otherwise be buffered without end
+const MAX_LINE: u64 = 8 << 10;
+
+/// Most headers a request may have
+const MAX_HEADERS: usize = 100;
+
+/// How long a client gets to send its request before it's dropped, so one
+/// that stalls can't hold up everyone after it
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The "server" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ServerConfig {
+    /// Address to listen on - only this machine unless it's changed
+    pub(crate) host: String,
+    /// Port to listen on when `--port` isn't given
+    pub(crate) port: u16,
+    /// Secret every request has to bring as `Authorization: Bearer ...`
+    /// Required for listening on anything but this machine
+    pub(crate) token: Option<String>,
+    /// Web pages allowed to call from a browser, like "https://dash.example.com"
+    /// Only honoured with a token; without one browsers are turned away
+    pub(crate) origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig { host: "127.0.0.1".to_string(), port: 8080, token: None, origins: Vec::new() }
+    }
+}
+
+/// A request as far as the server cares
+struct Request {
+    method: String,
+    target: String,
+    authorization: Option<String>,
+    /// The page a browser is calling for, which scripts and phones don't send
+    origin: Option<String>,
+    /// The name the client reached the server by, like "localhost:8080"
+    host: Option<String>,
+    body: String,
+}
+
+/// Status code and message of a request that can't be done
+type Failure = (u16, String);
+
+/// Answers requests until the process is stopped, printing a line for each
+/// `port` overrides the config's; `show` is handed the address once it's listening
+pub fn serve(app: &mut App, config: &Config, port: Option<u16>, show: impl FnOnce(&str)) -> Result<(), String> {
+    let server = &config.server;
+    let token = server.token.as_deref().filter(|token| !token.is_empty());
+    let port = port.unwrap_or(server.port);
+    let listener = TcpListener::bind((server.host.as_str(), port))
+        .map_err(|err| format!("couldn't listen on {}:{}: {}", server.host, port, err))?;
+    let address = listener.local_addr().map_err(|err| err.to_string())?;
+    // Anyone who can reach the port could read and change every todo
+    if !address.ip().is_loopback() && token.is_none() {
+        return Err(format!(
+            "listening on {} needs a \"token\" under \"server\" in the config, so only you can use it",
+            address.ip()
+        ));
+    }
+    // A page any site can load would have the browser call in on its behalf
+    if token.is_none() && !server.origins.is_empty() {
+        return Err("\"origins\" under \"server\" in the config need a \"token\" too".to_string());
+    }
+    // On this machine only, the names it can be reached by - a page whose own
+    // name was pointed at 127.0.0.1 could otherwise read and drive it, since
+    // CORS doesn't keep a browser from sending a request. Listening on
+    // another address means it's reached by names this can't know
+    let hosts = address.ip().is_loopback().then(|| local_hosts(address.port()));
+    show(&format!("http://{}", address));
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+        let request = match read_request(&stream) {
+            Ok(request) => request,
+            Err(err) => {
+                write_reply(&stream, 400, Some(&json!({ "error": err })), None);
+                continue;
+            }
+        };
+        let origin = request.origin.as_deref().filter(|origin| token.is_some() && server.origins.iter().any(|o| o == origin));
+        let refused = wrong_host(&request, hosts.as_deref()).or_else(|| refusal(&request, token, &server.origins));
+        let (status, reply) = if let Some((status, message)) = refused {
+            (status, Some(json!({ "error": message })))
+        } else if request.method == "OPTIONS" && origin.is_some() {
+            // A browser asking whether an allowed dashboard may call
+            (204, None)
+        } else {
+            let (status, reply) = match app.catch_up() {
+                Ok(()) => handle(app, &request.method, &request.target, &request.body),
+                Err(err) => (500, json!({ "error": err.to_string() })),
+            };
+            (status, Some(reply))
+        };
+        write_reply(&stream, status, reply.as_ref(), origin);
+        println!("{} {} {}", request.method, request.target, status);
+        // The hooks of a change are done before the next request sees the lists
+        for failure in app.wait_for_hooks() {
+            eprintln!("warning: {}", failure);
+        }
+    }
+    Ok(())
+}
+
+/// The Host headers a server on this machine answers to
+fn local_hosts(port: u16) -> Vec<String> {
+    let names = ["localhost", "127.0.0.1", "[::1]"];
+    let mut hosts: Vec<String> = names.iter().map(|name| format!("{}:{}", name, port)).collect();
+    // Browsers leave out the port when it's the default one
+    if port == 80 {
+        hosts.extend(names.iter().map(|name| name.to_string()));
+    }
+    hosts
+}
+
+/// Turns away a request for a name the server isn't known by, when it only
+/// listens on this machine
+fn wrong_host(request: &Request, hosts: Option<&[String]>) -> Option<Failure> {
+    let hosts = hosts?;
+    match &request.host {
+        Some(host) if hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) => None,
+        Some(host) => Some((403, format!("this server isn't {}, only {}", host, hosts[0]))),
+        None => Some((400, "the request needs a Host header".to_string())),
+    }
+}
+
+/// Why a request is turned away before it's looked at, if it is
+/// A browser's preflight can't bring the token, so for an allowed origin the
+/// token is only asked of the request that follows it
+fn refusal(request: &Request, token: Option<&str>, origins: &[String]) -> Option<Failure> {
+    if let Some(origin) = &request.origin {
+        if token.is_none() {
+            return Some((403, "web pages can only call when the server has a \"token\"".to_string()));
+        }
+        if !origins.contains(origin) {
+            return Some((403, format!("{} isn't one of the \"origins\" in the config", origin)));
+        }
+        if request.method == "OPTIONS" {
+            return None;
+        }
+    }
+    let token = token?;
+    let given = request.authorization.as_deref().unwrap_or_default();
+    (!same_secret(given, &format!("Bearer {}", token)))
+        .then(|| (401, "a valid \"Authorization: Bearer\" token is needed".to_string()))
+}
+
+/// Compares a secret without stopping at the first difference, so how long
+/// the answer takes doesn't tell how much of a guess was right
+fn same_secret(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Reads the request line, the headers that matter and the body
+fn read_request(stream: impl Read) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let line = read_line(&mut reader)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("that's not an HTTP request".to_string());
+    };
+    let mut length = 0;
+    let mut authorization = None;
+    let mut origin = None;
+    let mut host = None;
+    for count in 0.. {
+        let header = read_line(&mut reader)?;
+        // An empty line ends the headers, as does the connection closing
+        let Some((name, value)) = header.trim_end().split_once(':') else {
+            break;
+        };
+        if count == MAX_HEADERS {
+            return Err("there are too many headers".to_string());
+        }
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse().map_err(|_| "bad Content-Length".to_string())?,
+            "authorization" => authorization = Some(value.trim().to_string()),
+            "origin" => origin = Some(value.trim().to_string()),
+            "host" => host = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if length > MAX_BODY {
+        return Err("the body is too big".to_string());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|err| err.to_string())?;
+    let body = String::from_utf8(body).map_err(|_| "the body isn't UTF-8".to_string())?;
+    Ok(Request { method: method.to_string(), target: target.to_string(), authorization, origin, host, body })
+}
+
+/// Reads a line of at most `MAX_LINE` bytes; an empty one when the
+/// connection has closed
+fn read_line(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut line).map_err(|err| err.to_string())?;
+    if line.len() as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err("a line of the request is too long".to_string());
+    }
+    Ok(line)
+}
+
+/// Writes the reply and lets the connection close
+/// `origin` is a page from the config's "origins" the browser may hand the
+/// reply to; everyone else gets no CORS headers, so browsers keep it from them
+fn write_reply(mut stream: &TcpStream, status: u16, body: Option<&Value>, origin: Option<&str>) {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = body.map(|body| format!("{}\n", body)).unwrap_or_default();
+    let challenge = if status == 401 { "WWW-Authenticate: Bearer\r\n" } else { "" };
+    let cors = origin
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: GET, POST, PUT, PATCH, DELETE\r\n\
+                 Access-Control-Allow-Headers: Authorization, Content-Type\r\nVary: Origin\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n{}{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        challenge,
+        cors,
+        body.len(),
+        body
+    );
+}
+
+/// Answers a request with a status code and JSON, changing and saving the
+/// lists if it asks for that
+fn handle(app: &mut App, method: &str, target: &str, body: &str) -> (u16, Value) {
+    match respond(app, method, target, body) {
+        Ok(reply) => reply,
+        Err((status, message)) => (status, json!({ "error": message })),
+    }
+}
+
+/// The endpoints:
+/// GET    /lists                         the lists, with how many todos each has
+/// GET    /lists/NAME/todos              a list's todos, subtasks nested
+/// POST   /lists/NAME/todos              add a todo: {"text": ..., other fields}
+/// GET    /lists/NAME/todos/N            one todo, N numbered like `list` does
+/// PATCH  /lists/NAME/todos/N            change some of a todo's fields
+/// POST   /lists/NAME/todos/N/done       complete it, like the `done` command
+/// DELETE /lists/NAME/todos/N            move it to the trash
+fn respond(app: &mut App, method: &str, target: &str, body: &str) -> Result<(u16, Value), Failure> {
+    let path = target.split('?').next().unwrap_or_default();
+    // A + in a path is a plus sign, unlike in a query
+    let segments: Vec<String> =
+        path.split('/').filter(|segment| !segment.is_empty()).map(|segment| url_decode(&segment.replace('+', "%2B"))).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    match (method, segments.as_slice()) {
+        ("GET", ["lists"]) => {
+            let lists = app.lists.iter().map(|list| json!({ "name": list.name, "todos": list.todos.len() })).collect();
+            Ok((200, Value::Array(lists)))
+        }
+        ("GET", ["lists", name, "todos"]) => {
+            let list = find_list(app, name)?;
+            let todos = app.lists[list].todos.iter().enumerate().map(|(i, todo)| numbered(todo, &mut vec![i])).collect();
+            Ok((200, Value::Array(todos)))
+        }
+        ("POST", ["lists", name, "todos"]) => {
+            let list = find_list(app, name)?;
+            let mut fields = fields(body)?;
+            let text = match fields.remove("text") {
+                Some(Value::String(text)) if !text.trim().is_empty() => text,
+                _ => return Err((400, "a todo needs a \"text\"".to_string())),
+            };
+            // The text is read like in the app, so "#tags" in it work
+            let parsed = TodoItem::parse(&text);
+            let item = overlay(&parsed, fields)?;
+            let path = vec![app.lists[list].todos.len()];
+            change(app, Command::Insert { list, path: path.clone(), item: item.clone() })?;
+            Ok((201, numbered(&item, &mut path.clone())))
+        }
+        ("GET", ["lists", name, "todos", number]) => {
+            let (_, mut path, todo) = find_todo(app, name, number)?;
+            Ok((200, numbered(&todo, &mut path)))
+        }
+        ("PATCH", ["lists", name, "todos", number]) => {
+            let (list, mut path, before) = find_todo(app, name, number)?;
+            let after = overlay(&before, fields(body)?)?;
+            change(app, Command::Replace { list, path: path.clone(), before: Box::new(before), after: Box::new(after.clone()) })?;
+            Ok((200, numbered(&after, &mut path)))
+        }
+        ("POST", ["lists", name, "todos", number, "done"]) => {
+            let (list, mut path, before) = find_todo(app, name, number)?;
+            let mut after = before.clone();
+            // A repeating todo moves on to its next date instead
+            match after.recurrence {
+                Some(rule) => after.reschedule(rule),
+                None => after.set_completed(true),
+            }
+            change(app, Command::Replace { list, path: path.clone(), before: Box::new(before), after: Box::new(after.clone()) })?;
+            Ok((200, numbered(&after, &mut path)))
+        }
+        ("DELETE", ["lists", name, "todos", number]) => {
+            let (list, mut path, todo) = find_todo(app, name, number)?;
+            change(app, Command::Trash { list, path: path.clone(), index: 0 })?;
+            Ok((200, numbered(&todo, &mut path)))
+        }
+        ("GET", ["sync"]) => sync_lists(app),
+        ("PUT", ["sync", "lists", name]) => {
+            if find_list(app, name).is_err() {
+                apply(app, Command::InsertList { index: app.lists.len(), list: TodoList::new(name) })?;
+                save(app)?;
+            }
+            Ok((200, json!({ "name": name })))
+        }
+        ("PUT", ["sync", "todos", uid]) => sync_todo(app, uid, body),
+        ("DELETE", ["sync", "todos", uid]) => {
+            let (list, path) = find_task(Service::Server, &app.lists, uid).ok_or_else(|| (404, format!("no todo {}", uid)))?;
+            change(app, Command::Trash { list, path, index: 0 })?;
+            Ok((200, json!({ "uid": uid })))
+        }
+        (
+            _,
+            ["lists"] | ["lists", _, "todos"] | ["lists", _, "todos", _] | ["lists", _, "todos", _, "done"] | ["sync"]
+            | ["sync", "lists" | "todos", _],
+        ) => {
+            Err((405, format!("{} isn't done on {}", method, path)))
+        }
+        _ => Err((404, format!("there's nothing at {}", path))),
+    }
+}
+
+/// Applies a change and saves it
+fn change(app: &mut App, command: Command) -> Result<(), Failure> {
+    apply(app, command)?;
+    save(app)
+}
+
+/// Applies a change, refusing what the interface would refuse
+fn apply(app: &mut App, command: Command) -> Result<(), Failure> {
+    if let Some(refusal) = app.issue_refusal(&command) {
+        return Err((403, refusal.to_string()));
+    }
+    app.apply_command(command);
+    Ok(())
+}
+
+/// Saves the lists before the reply goes out
+fn save(app: &mut App) -> Result<(), Failure> {
+    app.save_and_commit().map_err(|err| {
+        // The lists no longer match the file, so the next request reads it
+        // again rather than building on a change that isn't there
+        app.disk_stamp = None;
+        (500, err.to_string())
+    })
+}
+
+/// Every list with its todos flattened, parents before their subtasks, for
+/// a device to sync with
+/// Todos that have never synced get their uid here - that's a link rather
+/// than a change, like the links the sync services make
+fn sync_lists(app: &mut App) -> Result<(u16, Value), Failure> {
+    fn give_uids(todos: &mut [TodoItem]) -> bool {
+        let mut given = false;
+        for todo in todos {
+            if Service::Server.id(todo).is_none() {
+                Service::Server.link(todo, &new_uid());
+                given = true;
+            }
+            given |= give_uids(&mut todo.children);
+        }
+        given
+    }
+    fn flatten(todos: &[TodoItem], parent: Option<&str>, entries: &mut Vec<Value>) {
+        for todo in todos {
+            let shallow = TodoItem { children: Vec::new(), ..todo.clone() };
+            let uid = Service::Server.id(todo);
+            entries.push(json!({ "uid": uid, "parent": parent, "todo": shallow }));
+            flatten(&todo.children, uid, entries);
+        }
+    }
+    let mut given = false;
+    for list in &mut app.lists {
+        given |= give_uids(&mut list.todos);
+    }
+    if given {
+        save(app)?;
+    }
+    // The issues are GitHub's, and don't go anywhere else
+    let lists = (0..app.lists.len()).filter(|&i| !app.is_issue_list(i)).map(|i| {
+        let mut entries = Vec::new();
+        flatten(&app.lists[i].todos, None, &mut entries);
+        json!({ "name": app.lists[i].name, "todos": entries })
+    });
+    Ok((200, json!({ "lists": lists.collect::<Vec<_>>() })))
+}
+
+/// Creates or updates the todo with a uid from what a syncing device sent:
+/// `{"list": ..., "parent": uid or null, "todo": ...}`
+fn sync_todo(app: &mut App, uid: &str, body: &str) -> Result<(u16, Value), Failure> {
+    let fields = fields(body)?;
+    let name = fields.get("list").and_then(Value::as_str).ok_or((400, "a todo needs a \"list\"".to_string()))?;
+    let parent = fields.get("parent").and_then(Value::as_str);
+    let todo = fields.get("todo").cloned().unwrap_or_default();
+    let todo: TodoItem = serde_json::from_value(todo).map_err(|err| (400, format!("bad todo: {}", err)))?;
+    let (list, path) = match find_task(Service::Server, &app.lists, uid) {
+        Some((list, path)) => {
+            let before = item_at(&app.lists[list].todos, &path).clone();
+            let mut after = before.clone();
+            remote::pull(&mut after, &todo);
+            apply(app, Command::Replace { list, path: path.clone(), before: Box::new(before), after: Box::new(after) })?;
+            (list, path)
+        }
+        None => {
+            let list = match find_list(app, name) {
+                Ok(list) => list,
+                Err(_) => {
+                    apply(app, Command::InsertList { index: app.lists.len(), list: TodoList::new(name) })?;
+                    app.lists.len() - 1
+                }
+            };
+            // Under its parent if that's in the same list, else at the end
+            let path = match parent.and_then(|parent| find_task(Service::Server, &app.lists, parent)) {
+                Some((parent_list, mut path)) if parent_list == list => {
+                    path.push(item_at(&app.lists[list].todos, &path).children.len());
+                    path
+                }
+                _ => vec![app.lists[list].todos.len()],
+            };
+            // The device's links are its own
+            let mut item = TodoItem { children: Vec::new(), remote_ids: BTreeMap::new(), ..todo.clone() };
+            Service::Server.link(&mut item, uid);
+            apply(app, Command::Insert { list, path: path.clone(), item })?;
+            (list, path)
+        }
+    };
+    // The change keeps the time it was made on the device, so one that was
+    // offline doesn't win with older changes for syncing last
+    item_at_mut(&mut app.lists[list].todos, &path).modified_at = todo.modified_at;
+    save(app)?;
+    Ok((200, json!({ "uid": uid })))
+}
+
+/// The index of the list with this name, matched like `--list` does
+fn find_list(app: &App, name: &str) -> Result<usize, Failure> {
+    app.lists
+        .iter()
+        .position(|list| list.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| (404, format!("no list named '{}'", name)))
+}
+
+/// The list, path and todo a todo number in a list names
+fn find_todo(app: &App, name: &str, number: &str) -> Result<(usize, TodoPath, TodoItem), Failure> {
+    let list = find_list(app, name)?;
+    let path = parse_todo_number(number).map_err(|err| (404, err))?;
+    let todo = find_item(&app.lists[list].todos, &path)
+        .ok_or_else(|| (404, format!("no todo {} in list '{}'", number, app.lists[list].name)))?;
+    Ok((list, path, todo.clone()))
+}
+
+/// The JSON object a request's body has to be
+fn fields(body: &str) -> Result<Map<String, Value>, Failure> {
+    match serde_json::from_str(body) {
+        Ok(Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err((400, "the body has to be a JSON object".to_string())),
+        Err(err) => Err((400, format!("the body isn't JSON: {}", err))),
+    }
+}
+
+/// A todo with some of its fields replaced, named and written the way the
+/// todo file has them
+fn overlay(todo: &TodoItem, fields: Map<String, Value>) -> Result<TodoItem, Failure> {
+    let mut value = serde_json::to_value(todo).map_err(|err| (500, err.to_string()))?;
+    for (name, field) in fields {
+        // Subtasks are todos of their own, changed through their own numbers
+        if name == "children" || value.get(&name).is_none() {
+            return Err((400, format!("'{}' isn't a field that can be set", name)));
+        }
+        value[name] = field;
+    }
+    let mut changed: TodoItem = serde_json::from_value(value).map_err(|err| (400, format!("bad todo: {}", err)))?;
+    // Completing or reopening stamps the time like it does in the app
+    if changed.status != todo.status {
+        changed.set_status(changed.status);
+    }
+    Ok(changed)
+}
+
+/// A todo as JSON with its number, and its subtasks' numbers
+fn numbered(todo: &TodoItem, path: &mut TodoPath) -> Value {
+    let mut value = serde_json::to_value(todo).unwrap_or_default();
+    let children = todo
+        .children
+        .iter()
+        .enumerate()
+        .map(|(i, child)| {
+            path.push(i);
+            let child = numbered(child, path);
+            path.pop();
+            child
+        })
+        .collect();
+    value["children"] = Value::Array(children);
+    value["number"] = Value::String(format_todo_number(path));
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Status;
+
+    #[test]
+    fn requests_change_and_save_the_lists() {
+        let path = std::env::temp_dir().join(format!("todo-tui-server-test-{}.json", std::process::id()));
+        let mut app = App::new(path.clone());
+        app.lists[0].todos.clear();
+
+        let (status, added) = handle(&mut app, "POST", "/lists/todos/todos", r#"{"text": "buy milk #shop", "notes": "oat"}"#);
+        assert_eq!(status, 201);
+        assert_eq!((added["number"].as_str(), added["tags"][0].as_str(), added["notes"].as_str()), (Some("1"), Some("shop"), Some("oat")));
+        // Saved before the reply, so a fresh load sees it
+        assert_eq!(App::load(path.clone()).unwrap().lists[0].todos[0].text, "buy milk");
+
+        let (status, _) = handle(&mut app, "PATCH", "/lists/Todos/todos/1", r#"{"status": "done"}"#);
+        assert_eq!(status, 200);
+        assert!(app.lists[0].todos[0].status == Status::Done && app.lists[0].todos[0].completed_at.is_some());
+
+        assert_eq!(handle(&mut app, "PATCH", "/lists/Todos/todos/1", r#"{"colour": "red"}"#).0, 400);
+        assert_eq!(handle(&mut app, "GET", "/lists/Todos/todos/2", "").0, 404);
+        assert_eq!(handle(&mut app, "GET", "/lists/Nope/todos", "").0, 404);
+        assert_eq!(handle(&mut app, "PUT", "/lists/Todos/todos", "").0, 405);
+
+        assert_eq!(handle(&mut app, "DELETE", "/lists/Todos/todos/1", "").0, 200);
+        assert!(app.lists[0].todos.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn browsers_only_get_in_from_allowed_origins_with_a_token() {
+        let request = |method: &str, origin: Option<&str>, authorization: Option<&str>| Request {
+            method: method.to_string(),
+            target: "/lists".to_string(),
+            authorization: authorization.map(str::to_string),
+            origin: origin.map(str::to_string),
+            host: Some("localhost:8080".to_string()),
+            body: String::new(),
+        };
+        let origins = vec!["https://dash.example.com".to_string()];
+        let status = |request: &Request, token| refusal(request, token, &origins).map(|(status, _)| status);
+
+        // Scripts send no origin and only need the token if there is one
+        assert_eq!(status(&request("GET", None, None), None), None);
+        assert_eq!(status(&request("GET", None, None), Some("secret")), Some(401));
+        assert_eq!(status(&request("OPTIONS", None, None), Some("secret")), Some(401));
+        assert_eq!(status(&request("GET", None, Some("Bearer secret")), Some("secret")), None);
+
+        // Without a token no page may call, even one in the list
+        assert_eq!(status(&request("GET", Some("https://dash.example.com"), None), None), Some(403));
+        assert_eq!(status(&request("OPTIONS", Some("https://dash.example.com"), None), None), Some(403));
+        assert_eq!(status(&request("OPTIONS", Some("https://evil.example.com"), None), Some("secret")), Some(403));
+        // An allowed page's preflight gets through, the request itself needs the token
+        assert_eq!(status(&request("OPTIONS", Some("https://dash.example.com"), None), Some("secret")), None);
+        assert_eq!(status(&request("GET", Some("https://dash.example.com"), None), Some("secret")), Some(401));
+        let allowed = request("GET", Some("https://dash.example.com"), Some("Bearer secret"));
+        assert_eq!(status(&allowed, Some("secret")), None);
+    }
+
+    #[test]
+    fn a_server_on_this_machine_only_answers_to_its_own_names() {
+        let request = |host: Option<&str>| {
+            let host = host.map(|host| format!("Host: {}\r\n", host)).unwrap_or_default();
+            read_request(format!("GET /lists HTTP/1.1\r\n{}\r\n", host).as_bytes()).unwrap()
+        };
+        let hosts = local_hosts(8080);
+        let status = |host| wrong_host(&request(host), Some(&hosts)).map(|(status, _)| status);
+        assert_eq!(status(Some("localhost:8080")), None);
+        assert_eq!(status(Some("LocalHost:8080")), None);
+        assert_eq!(status(Some("127.0.0.1:8080")), None);
+        assert_eq!(status(Some("[::1]:8080")), None);
+        // A page that pointed its own name at 127.0.0.1, or another port's
+        assert_eq!(status(Some("rebind.example.com:8080")), Some(403));
+        assert_eq!(status(Some("localhost:8081")), Some(403));
+        assert_eq!(status(Some("localhost")), Some(403));
+        assert_eq!(status(None), Some(400));
+        assert!(local_hosts(80).contains(&"localhost".to_string()));
+        // Listening on another address the names can't be known
+        assert!(wrong_host(&request(Some("todo.lan:8080")), None).is_none());
+    }
+
+    #[test]
+    fn requests_without_end_are_turned_away() {
+        let request = read_request(&b"POST /lists HTTP/1.1\r\nOrigin: https://a.example\r\nContent-Length: 2\r\n\r\nhi"[..]).unwrap();
+        assert_eq!((request.method.as_str(), request.origin.as_deref(), request.body.as_str()), ("POST", Some("https://a.example"), "hi"));
+
+        let endless_line = format!("GET /{}", "a".repeat(MAX_LINE as usize));
+        assert!(read_request(endless_line.as_bytes()).is_err());
+        let endless_header = format!("GET / HTTP/1.1\r\nX-Pad: {}", "a".repeat(MAX_LINE as usize));
+        assert!(read_request(endless_header.as_bytes()).is_err());
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Pad: a\r\n".repeat(MAX_HEADERS + 1));
+        assert!(read_request(many_headers.as_bytes()).is_err());
+        let enough_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Pad: a\r\n".repeat(MAX_HEADERS));
+        assert!(read_request(enough_headers.as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,3520 @@
+//! Reading and writing todos, the archive, view state and the config
+//! in JSON, todo.txt, Markdown and org-mode
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    app::{
+        format_duration_key, format_todo_number, item_at_mut, parse_duration_key, App, ArchivedTodo, Clock, Command, Confirm, Date,
+        DateFormat, DueFilter, InputTarget, Mode, Priority, Recurrence, SavedFilter, SortMode, Status, Time, Timestamp, ToastKind,
+        TodoItem, TodoList, TodoPath, Weekday,
+    },
+    caldav::CalDavConfig,
+    crypto::{Cipher, Envelope},
+    email::EmailConfig,
+    i18n::Language,
+    logging::LogLevel,
+    error::AppError,
+    github::GitHubConfig,
+    google::GoogleConfig,
+    history::{self, GitMode},
+    hooks::HooksConfig,
+    input::KeymapPreset,
+    remote::RemoteConfig,
+    remote_file::S3Config,
+    server::ServerConfig,
+    sync::TodoistConfig,
+    ui::{IconSet, TagStyle, Theme},
+};
+#[cfg(feature = "crdt")]
+pub(crate) use crate::oplog::{give_ids, OpLog};
+
+/// User settings read from config.json in the config directory
+/// Every field is optional so an empty or partial file is fine
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Name of the theme to start with
+    pub(crate) theme: Option<String>,
+    /// User-defined themes by name - they can also override the built-in ones
+    pub(crate) themes: BTreeMap<String, Theme>,
+    /// Ask before deleting a todo or list - power users can turn this off
+    pub(crate) confirm_delete: bool,
+    /// How dates are shown: iso, dd.mm.yyyy, dd/mm/yyyy, mm/dd/yyyy or relative
+    pub(crate) date_format: DateFormat,
+    /// Whether times are shown on a 24h or 12h clock
+    pub(crate) clock: Clock,
+    /// The day weeks start on in the completions calendar
+    pub(crate) week_start: Weekday,
+    /// Language of the interface, like "de", None to go by $LANG
+    pub(crate) language: Option<Language>,
+    /// Send a desktop notification when a todo falls due while the app runs
+    /// Off by default since not every desktop has a notification daemon
+    pub(crate) notifications: bool,
+    /// Statuses shown as columns on the board, left to right
+    pub(crate) board_columns: Vec<Status>,
+    /// Saved filters offered by `F`, before the ones saved from the app
+    pub(crate) filters: Vec<SavedFilter>,
+    /// Symbols rows are drawn with: auto, unicode, nerd or ascii
+    pub(crate) icons: IconSet,
+    /// Colors and icons of tags by name, like `"work": { "color": "blue" }`
+    pub(crate) tags: BTreeMap<String, TagStyle>,
+    /// Keymap preset the bindings start from
+    pub keymap: KeymapPreset,
+    /// The user's own bindings by action name, like `"next": ["ctrl+j"]`,
+    /// replacing the preset's keys for that action
+    pub(crate) keys: BTreeMap<String, Vec<String>>,
+    /// How many snapshots of the todo file to keep, 0 to take none
+    pub(crate) backups: usize,
+    /// When to commit the todo file to a git repository next to it
+    pub(crate) git: GitMode,
+    /// Todoist account to sync the lists with, None to not sync
+    pub(crate) todoist: Option<TodoistConfig>,
+    /// CalDAV server to sync the lists with, None to not sync
+    pub(crate) caldav: Option<CalDavConfig>,
+    /// Google account to sync the lists with through Google Tasks, None to not sync
+    pub(crate) google: Option<GoogleConfig>,
+    /// GitHub repos whose issues assigned to the user are shown as a list,
+    /// None for no such list
+    pub(crate) github: Option<GitHubConfig>,
+    /// Mail folder whose unread mail becomes todos, None to not check mail
+    pub(crate) email: Option<EmailConfig>,
+    /// Shell commands run when a todo is added, completed or deleted
+    pub(crate) hooks: HooksConfig,
+    /// Where `serve` listens and the token it asks for
+    pub(crate) server: ServerConfig,
+    /// Another device running `serve` to sync the lists with, None to not sync
+    pub(crate) remote: Option<RemoteConfig>,
+    /// Keys for a todo file given as an s3:// URL, None when there's none
+    pub(crate) s3: Option<S3Config>,
+    /// How much the interface writes to todo-tui.log: off, error, warn,
+    /// info, debug or trace
+    pub(crate) log: LogLevel,
+    /// Go through the todos left overdue on the first start of a day
+    pub(crate) daily_review: bool,
+    /// Wrap long todos onto more lines instead of cutting them off at the edge
+    pub(crate) wrap: bool,
+    /// What a list row shows and where, None for the built-in row
+    pub(crate) row_format: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            theme: None,
+            themes: BTreeMap::new(),
+            confirm_delete: true,
+            date_format: DateFormat::Iso,
+            clock: Clock::TwentyFourHour,
+            week_start: Weekday::Monday,
+            language: None,
+            notifications: false,
+            board_columns: vec![Status::Pending, Status::InProgress, Status::Blocked, Status::Done],
+            filters: vec![
+                SavedFilter { due: Some(DueFilter::Today), ..SavedFilter::named("Today") },
+                SavedFilter { due: Some(DueFilter::Week), ..SavedFilter::named("This week") },
+                SavedFilter { untagged: true, ..SavedFilter::named("Inbox") },
+            ],
+            icons: IconSet::Auto,
+            tags: BTreeMap::new(),
+            keymap: KeymapPreset::Vim,
+            keys: BTreeMap::new(),
+            backups: DEFAULT_BACKUPS,
+            git: GitMode::Off,
+            todoist: None,
+            caldav: None,
+            google: None,
+            github: None,
+            email: None,
+            hooks: HooksConfig::default(),
+            server: ServerConfig::default(),
+            remote: None,
+            s3: None,
+            log: LogLevel::Info,
+            daily_review: true,
+            wrap: false,
+            row_format: None,
+        }
+    }
+}
+
+impl Config {
+    /// Path of the config file, next to other per-user settings
+    pub(crate) fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.json"))
+    }
+
+    /// Directory the Lua plugins are loaded from, next to the config file
+    pub(crate) fn plugins_dir() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("plugins"))
+    }
+
+    /// Loads the config file, using defaults when there isn't one
+    /// A file that exists but can't be parsed is an error - silently ignoring
+    /// it would leave the user wondering why their settings don't apply
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|err| format!("invalid config file {}: {}", path.display(), err).into()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(format!("could not read {}: {}", path.display(), err).into()),
+        }
+    }
+
+    /// Built-in themes followed by user themes, in the order `T` cycles through them
+    /// A user theme with a built-in's name replaces it in place
+    pub(crate) fn themes(&self) -> Vec<(String, Theme)> {
+        let mut themes = vec![
+            ("default".to_string(), Theme::default()),
+            ("light".to_string(), Theme::light()),
+            ("solarized".to_string(), Theme::solarized()),
+        ];
+        for (name, theme) in &self.themes {
+            match themes.iter_mut().find(|(n, _)| n == name) {
+                Some(entry) => entry.1 = theme.clone(),
+                None => themes.push((name.clone(), theme.clone())),
+            }
+        }
+        themes
+    }
+}
+
+/// View settings the app remembers between sessions by itself
+/// Unlike Config this is written by the app, so it lives in the data directory
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    pub(crate) sort_mode: SortMode,
+    pub(crate) hide_completed: bool,
+    pub(crate) show_unstarted: bool,
+    pub(crate) group_by_project: bool,
+    /// Filters saved from the app with `s` in the filter picker
+    pub(crate) filters: Vec<SavedFilter>,
+    /// Tag colors and icons set from the app with `c` in the tag picker
+    pub(crate) tags: BTreeMap<String, TagStyle>,
+    /// Commands typed at the `:` prompt, oldest first
+    pub(crate) command_history: Vec<String>,
+    /// Whether the welcome popup was dismissed
+    pub(crate) welcomed: bool,
+    /// Day the daily review last ran
+    pub(crate) reviewed: Option<Date>,
+}
+
+impl ViewState {
+    /// Path of the view state file, next to the default todo file
+    pub fn path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join("view.json"))
+    }
+
+    /// Reads the saved view state
+    /// A missing or broken file just means defaults - it's not worth an error
+    pub(crate) fn load(path: &Path) -> ViewState {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the view state, creating the directory on first use
+    pub(crate) fn save(&self, path: &Path) -> Result<(), AppError> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| write_error(path, err))?;
+        write_file(path, json)
+    }
+}
+
+/// On-disk layout of archive.json
+#[derive(Default, Serialize, Deserialize)]
+struct ArchiveFile {
+    /// Oldest first - new entries are appended
+    pub(crate) archived: Vec<ArchivedTodo>,
+}
+
+/// On-disk layout of the save file - all lists live in one JSON document
+/// The version comes first so it's the first thing seen in the file
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    /// Missing from files written before versions, which `save_version` reads
+    /// as the version they were
+    #[serde(default)]
+    pub(crate) version: u64,
+    pub(crate) lists: Vec<TodoList>,
+    /// The changes of the log the lists were written from, see `oplog`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) heads: Vec<String>,
+}
+
+/// Whether two sets of lists would be saved the same
+fn same_lists(a: &[TodoList], b: &[TodoList]) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Just the heads of a save file
+#[derive(Deserialize)]
+struct SaveFileHeads {
+    #[serde(default)]
+    heads: Vec<String>,
+}
+
+/// The changes of the log a JSON save file says it was written from, none
+/// when it doesn't say or isn't JSON
+fn save_file_heads(contents: &str) -> Vec<String> {
+    serde_json::from_str::<SaveFileHeads>(contents).map(|file| file.heads).unwrap_or_default()
+}
+
+/// Layout version written to new save files - the number of migrations,
+/// since every change to the layout adds one
+const SAVE_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Upgrades from each older layout to the next, in order: entry `n` turns a
+/// version `n` file into version `n + 1`
+/// They work on plain JSON, so old layouts never need Rust types of their own
+/// New fields with a sensible default don't need one - `#[serde(default)]`
+/// covers them - but anything renamed, moved or reinterpreted does
+const MIGRATIONS: &[fn(Value) -> Value] = &[
+    // 0 -> 1: files written before multiple lists existed are a bare array
+    // of todos, which becomes the one list
+    |todos| json!({ "lists": [{ "name": "Todos", "todos": todos }] }),
+    // 1 -> 2: contexts were tags starting with '@' before they had a field
+    |mut file| {
+        for list in file["lists"].as_array_mut().into_iter().flatten() {
+            for todo in list["todos"].as_array_mut().into_iter().flatten() {
+                split_contexts(todo);
+            }
+        }
+        file
+    },
+    // 2 -> 3: links to synced tasks were a field per service before they
+    // went in one map
+    |mut file| {
+        for list in file["lists"].as_array_mut().into_iter().flatten() {
+            move_remote_ids(list, &[("todoist_id", "todoist"), ("caldav_url", "caldav"), ("google_id", "google")]);
+            for todos in ["todos", "trash"] {
+                for todo in list.get_mut(todos).and_then(Value::as_array_mut).into_iter().flatten() {
+                    move_todo_remote_ids(todo);
+                }
+            }
+        }
+        file
+    },
+];
+
+/// Moves the link fields an object had, by their old names, into its
+/// `remote_ids` under the keys they're given
+fn move_remote_ids(object: &mut Value, fields: &[(&str, &str)]) {
+    let Some(object) = object.as_object_mut() else {
+        return;
+    };
+    let mut ids = serde_json::Map::new();
+    for (field, key) in fields {
+        if let Some(Value::String(id)) = object.remove(*field) {
+            ids.insert(key.to_string(), json!(id));
+        }
+    }
+    if !ids.is_empty() {
+        object.insert("remote_ids".to_string(), Value::Object(ids));
+    }
+}
+
+/// `move_remote_ids` for a todo and its subtasks
+fn move_todo_remote_ids(todo: &mut Value) {
+    move_remote_ids(todo, &[("todoist_id", "todoist"), ("caldav_uid", "caldav"), ("google_id", "google"), ("uid", "server")]);
+    for child in todo.get_mut("children").and_then(Value::as_array_mut).into_iter().flatten() {
+        move_todo_remote_ids(child);
+    }
+}
+
+/// Moves a todo's `@context` tags, and its subtasks', to its contexts
+fn split_contexts(todo: &mut Value) {
+    if let Some(tags) = todo.get_mut("tags").and_then(Value::as_array_mut) {
+        let (contexts, tags): (Vec<Value>, Vec<Value>) =
+            tags.drain(..).partition(|tag| tag.as_str().is_some_and(|tag| tag.len() > 1 && tag.starts_with('@')));
+        todo["tags"] = json!(tags);
+        if !contexts.is_empty() {
+            todo["contexts"] = contexts.iter().filter_map(|context| Some(json!(context.as_str()?[1..]))).collect();
+        }
+    }
+    for child in todo.get_mut("children").and_then(Value::as_array_mut).into_iter().flatten() {
+        split_contexts(child);
+    }
+}
+
+/// `split_contexts` for todos in the archive, which has no version to
+/// migrate by - a current one has no tags starting with '@' to move
+fn split_archived_contexts(todo: &mut TodoItem) {
+    let (contexts, tags) =
+        std::mem::take(&mut todo.tags).into_iter().partition(|tag| tag.len() > 1 && tag.starts_with('@'));
+    todo.tags = tags;
+    todo.contexts.extend(contexts.into_iter().map(|context: String| context[1..].to_string()));
+    todo.children.iter_mut().for_each(split_archived_contexts);
+}
+
+/// Version of a save file's layout
+/// The bare array of the first version has nowhere to put a number, and the
+/// object that replaced it went without one until versions were introduced
+fn save_version(value: &Value) -> u64 {
+    match value {
+        Value::Array(_) => 0,
+        Value::Object(fields) => fields.get("version").and_then(Value::as_u64).unwrap_or(1),
+        // Not a save file at all - parsing it as the current layout says why
+        _ => SAVE_VERSION,
+    }
+}
+
+/// The app's per-user config directory: `$XDG_CONFIG_HOME/todo-tui` (or
+/// ~/.config/todo-tui) on Linux and BSDs, ~/Library/Application Support/todo-tui
+/// on macOS and %APPDATA%\todo-tui\config on Windows
+fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "todo-tui").map(|dirs| settled(dirs.config_dir(), cfg!(windows)))
+}
+
+/// The app's per-user data directory: `$XDG_DATA_HOME/todo-tui` (or
+/// ~/.local/share/todo-tui) on Linux and BSDs, the same folder as the config on
+/// macOS and %APPDATA%\todo-tui\data on Windows
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "todo-tui").map(|dirs| settled(dirs.data_dir(), cfg!(windows)))
+}
+
+/// On Windows the files used to be straight in %APPDATA%\todo-tui, where the
+/// `config` and `data` folders go now - while it still holds files of its own
+/// and the new folder isn't there, it's used on, so an update doesn't lose them
+fn settled(dir: &Path, windows: bool) -> PathBuf {
+    let Some(old) = dir.parent().filter(|_| windows && !dir.exists()) else {
+        return dir.to_path_buf();
+    };
+    let entries = fs::read_dir(old).into_iter().flatten().flatten();
+    match entries.into_iter().any(|entry| !matches!(entry.file_name().to_str(), Some("config" | "data"))) {
+        true => old.to_path_buf(),
+        false => dir.to_path_buf(),
+    }
+}
+
+/// A todo file or archive that couldn't be loaded, waiting in the recovery
+/// dialog for the user to decide what to do about it
+pub(crate) struct Recovery {
+    /// Why loading failed - usually a parse error with its location
+    pub(crate) error: AppError,
+
+    /// Where the broken file was copied to, or why it couldn't be
+    pub(crate) backup: io::Result<PathBuf>,
+
+    /// The line of the file the parse error points at, to show it in context
+    pub(crate) line: Option<String>,
+}
+
+impl Recovery {
+    fn new(error: AppError, backup: io::Result<PathBuf>) -> Recovery {
+        let line = error.location().and_then(|(line, _)| {
+            let contents = fs::read_to_string(error.path()).ok()?;
+            contents.lines().nth(line - 1).map(str::to_string)
+        });
+        Recovery { error, backup, line }
+    }
+}
+
+/// Copies a broken file to `NAME.corrupt-YYYY-MM-DD-HHMMSS` next to it
+/// A copy rather than a rename, so the user can still fix the file in place
+fn back_up_corrupt(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let backup = path.with_file_name(format!("{}.corrupt-{}", name, file_timestamp()));
+    fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+/// The local time as `YYYY-MM-DD-HHMMSS`, for file names that sort by age
+fn file_timestamp() -> String {
+    let now = Timestamp::now();
+    let time = now.time();
+    format!("{}-{:02}{:02}{:02}", now.date(), time.hour, time.minute, now.0.rem_euclid(60))
+}
+
+/// When a snapshot of the todo file at `path` was taken, as its name says -
+/// the time is all that tells snapshots apart
+pub(crate) fn snapshot_time(path: &Path, snapshot: &Path) -> String {
+    let (stem, _) = snapshot_name_parts(path);
+    let name = snapshot.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    name.strip_prefix(&format!("{}-", stem)).map_or(name.clone(), str::to_string)
+}
+
+/// Snapshots kept unless the config says otherwise - a week or two of
+/// sessions for most people
+pub(crate) const DEFAULT_BACKUPS: usize = 10;
+
+/// Directory the snapshots of the todo file at `path` go to, next to it
+fn backup_dir(path: &Path) -> PathBuf {
+    path.with_file_name("backups")
+}
+
+/// Copies the todo file at `path` into its snapshots, named for now
+pub(crate) fn snapshot_file(path: &Path) -> io::Result<PathBuf> {
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    let (stem, extension) = snapshot_name_parts(path);
+    let snapshot = dir.join(format!("{}-{}{}", stem, file_timestamp(), extension));
+    fs::copy(path, &snapshot)?;
+    Ok(snapshot)
+}
+
+/// Splits `todos.json` into `todos` and `.json`, the parts snapshot names are
+/// built from - the extension stays last so a snapshot reads like the file
+fn snapshot_name_parts(path: &Path) -> (String, String) {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (stem, extension)
+}
+
+/// How long the user has to pause before changes are saved
+const SAVE_DELAY: Duration = Duration::from_millis(300);
+
+/// Changes are saved after this long even if the user never pauses
+const SAVE_MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// How often the todo file is checked for changes made outside the app when
+/// there's no `DiskWatcher` to say so, and how often a read-only instance
+/// looks whether the other one has quit
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Changes made in the interface since the last save was started
+pub(crate) struct Unsaved {
+    /// When the first of them was made, for `SAVE_MAX_DELAY`
+    first: Instant,
+
+    /// When the latest was made, for `SAVE_DELAY`
+    last: Instant,
+
+    /// Whether any of them touched the archive, which is its own file
+    archive: bool,
+}
+
+/// A file write handed to the background saver, with a snapshot of the
+/// data to write so the interface can carry on changing it
+/// The cipher goes along when the files are encrypted
+enum SaveJob {
+    Lists(PathBuf, Vec<TodoList>, Vec<String>, Option<Cipher>),
+    /// The lists, recorded in the log first - never encrypted
+    Logged(PathBuf, Vec<TodoList>, Arc<Mutex<OpLog>>),
+    Archive(PathBuf, Vec<ArchivedTodo>, Option<Cipher>),
+    /// Commit the todo file to git with this message, after it's written
+    Commit(PathBuf, String),
+}
+
+impl SaveJob {
+    /// Writes the file - for the todo file, also reports how it looks on disk
+    /// afterwards so the app doesn't take its own save for someone else's
+    fn run(self) -> Result<Option<FileStamp>, AppError> {
+        // Its end is logged with how long the job took
+        let _span = tracing::info_span!("save").entered();
+        let (what, result) = match self {
+            SaveJob::Lists(path, lists, heads, cipher) => {
+                let todos: usize = lists.iter().map(|list| list.todos.len()).sum();
+                let what = format!("saved {} todos to {}", todos, path.display());
+                (what, write_lists(&path, &lists, &heads, cipher.as_ref()).map(|()| FileStamp::of(&path)))
+            }
+            SaveJob::Logged(path, lists, log) => {
+                let todos: usize = lists.iter().map(|list| list.todos.len()).sum();
+                let what = format!("saved {} todos to {} and its change log", todos, path.display());
+                let heads = log.lock().unwrap_or_else(|err| err.into_inner()).record(&lists);
+                let heads = heads.map_err(|err| AppError::Write { path: path.clone(), source: io::Error::other(err) });
+                (what, heads.and_then(|heads| write_lists(&path, &lists, &heads, None)).map(|()| FileStamp::of(&path)))
+            }
+            SaveJob::Archive(path, archived, cipher) => {
+                let what = format!("saved {} archived todos to {}", archived.len(), path.display());
+                (what, write_archive(&path, archived, cipher.as_ref()).map(|()| None))
+            }
+            SaveJob::Commit(path, message) => {
+                (format!("committed {}", path.display()), history::commit(&path, &message).map(|()| None))
+            }
+        };
+        match &result {
+            Ok(_) => tracing::info!("{}", what),
+            Err(err) => tracing::error!("{}", err),
+        }
+        result
+    }
+}
+
+/// Stand-in for the log without the `crdt` feature, which never opens
+#[cfg(not(feature = "crdt"))]
+pub(crate) struct OpLog;
+
+#[cfg(not(feature = "crdt"))]
+impl OpLog {
+    fn open(_path: &Path) -> Result<OpLog, String> {
+        Err("this build of todo-tui has no change log (the `crdt` feature)".to_string())
+    }
+
+    fn record(&mut self, _lists: &[TodoList]) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    fn merge(&mut self, _ours: Option<&[TodoList]>, file: &[TodoList], _heads: &[String]) -> Result<Vec<TodoList>, String> {
+        Ok(file.to_vec())
+    }
+}
+
+#[cfg(not(feature = "crdt"))]
+fn give_ids(_lists: &mut [TodoList]) {}
+
+/// How a file looked on disk, to notice when something else changes it
+/// Modification time alone can miss a quick second write on filesystems
+/// with coarse timestamps, so the size is compared too
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    /// The file's current stamp, None if it doesn't exist
+    fn of(path: &Path) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+/// Tells when something happens to the todo file, so an outside change is
+/// picked up the moment it's made rather than on the next poll
+/// The folder is watched rather than the file, since editors save by writing
+/// a new file and renaming it over the old one
+pub(crate) struct DiskWatcher {
+    /// Stops watching when dropped
+    _watcher: notify::RecommendedWatcher,
+
+    /// Set by the watcher's thread for every event on the file
+    changed: Arc<AtomicBool>,
+}
+
+impl DiskWatcher {
+    /// Starts watching the file's folder
+    fn new(path: &Path) -> notify::Result<DiskWatcher> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let name = path.file_name().map(|name| name.to_os_string());
+        let flag = Arc::clone(&changed);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            // An error may mean events were lost, so it counts as a change
+            if event.is_err() || event.is_ok_and(|event| event.paths.iter().any(|p| p.file_name() == name.as_deref())) {
+                flag.store(true, Ordering::Relaxed);
+            }
+        })?;
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        notify::Watcher::watch(&mut watcher, dir, notify::RecursiveMode::NonRecursive)?;
+        Ok(DiskWatcher { _watcher: watcher, changed })
+    }
+
+    /// Whether the file was touched since the last call
+    fn take(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Has the file looked at again on the next tick
+    fn again(&self) {
+        self.changed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Writes files on a background thread so a slow disk or network mount never
+/// holds up a key press
+/// The thread starts with the first save, so an app that never autosaves -
+/// like the one behind the command line subcommands - doesn't get one
+#[derive(Default)]
+pub(crate) struct Saver {
+    worker: Option<Worker>,
+
+    /// Jobs sent but not reported back yet
+    pending: usize,
+}
+
+/// The saver thread and the channels to it
+struct Worker {
+    jobs: Sender<SaveJob>,
+    results: Receiver<Result<Option<FileStamp>, AppError>>,
+    thread: JoinHandle<()>,
+}
+
+impl Saver {
+    /// Queues a write - jobs run one at a time, in order
+    fn send(&mut self, job: SaveJob) {
+        let worker = self.worker.get_or_insert_with(|| {
+            let (jobs, job_receiver) = mpsc::channel::<SaveJob>();
+            let (result_sender, results) = mpsc::channel();
+            let thread = thread::spawn(move || {
+                // Ends once the app drops its sender in `finish`
+                for job in job_receiver {
+                    let _ = result_sender.send(job.run());
+                }
+            });
+            Worker { jobs, results, thread }
+        });
+        // The thread only stops once the sender is dropped, so this can't fail
+        let _ = worker.jobs.send(job);
+        self.pending += 1;
+    }
+
+    /// Results of the jobs finished since the last call, without waiting
+    fn finished(&mut self) -> Vec<Result<Option<FileStamp>, AppError>> {
+        let Some(worker) = &self.worker else {
+            return Vec::new();
+        };
+        let results: Vec<_> = worker.results.try_iter().collect();
+        self.pending -= results.len();
+        results
+    }
+
+    /// Waits for every queued job and stops the thread
+    fn finish(&mut self) -> Vec<Result<Option<FileStamp>, AppError>> {
+        let Some(Worker { jobs, results, thread }) = self.worker.take() else {
+            return Vec::new();
+        };
+        drop(jobs);
+        let _ = thread.join();
+        self.pending = 0;
+        results.try_iter().collect()
+    }
+
+    /// Whether a write is queued or in progress
+    fn busy(&self) -> bool {
+        self.pending > 0
+    }
+}
+
+/// Writes lists to `path` in the format its extension asks for, so todo.txt
+/// files stay todo.txt
+/// A JSON file also says which changes of the log it's from
+fn write_lists(path: &Path, lists: &[TodoList], heads: &[String], cipher: Option<&Cipher>) -> Result<(), AppError> {
+    let contents = match Format::from_path(path) {
+        Format::Json if !heads.is_empty() => {
+            serde_json::to_string_pretty(&SaveFile { version: SAVE_VERSION, lists: lists.to_vec(), heads: heads.to_vec() })
+        }
+        format => format.write(lists, path),
+    };
+    let contents = contents.map_err(|err| write_error(path, err))?;
+    write_file(path, seal(path, contents, cipher)?)
+}
+
+/// Writes the archive file
+fn write_archive(path: &Path, archived: Vec<ArchivedTodo>, cipher: Option<&Cipher>) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(&ArchiveFile { archived }).map_err(|err| write_error(path, err))?;
+    write_file(path, seal(path, json, cipher)?)
+}
+
+/// Encrypts the contents of a file when there's a cipher, leaves them be otherwise
+fn seal(path: &Path, contents: String, cipher: Option<&Cipher>) -> Result<String, AppError> {
+    match cipher {
+        Some(cipher) => cipher.seal(&contents).map_err(|source| AppError::Write { path: path.to_path_buf(), source }),
+        None => Ok(contents),
+    }
+}
+
+/// Decrypts the contents of a file if it's encrypted
+/// A plain file reads the same with or without a cipher, so turning
+/// encryption on or off never needs the old passphrase
+pub(crate) fn unseal(path: &Path, contents: String, cipher: Option<&Cipher>) -> Result<String, AppError> {
+    let Some(envelope) = Envelope::parse(&contents) else {
+        return Ok(contents);
+    };
+    let cipher = cipher.ok_or_else(|| AppError::Locked { path: path.to_path_buf() })?;
+    envelope.check().map_err(|message| AppError::Encryption { path: path.to_path_buf(), message })?;
+    cipher.open(&envelope).ok_or_else(|| AppError::Decrypt { path: path.to_path_buf() })
+}
+
+/// Outcome of trying to become the instance that writes the todo file
+enum Lock {
+    /// This instance has it, for as long as the file stays open
+    Held(fs::File),
+
+    /// Another instance does - with its process id, if the lock file says
+    Taken(Option<u32>),
+}
+
+/// The lock file for the todo file at `path`, hidden next to it
+fn lock_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!(".{}.lock", name))
+}
+
+/// Tries to lock the todo file at `path` for this instance
+/// The lock is the operating system's, taken on a separate file: it goes away
+/// when the process exits, crashes included, so there's never a stale lock to
+/// clean up, and atomic saves can still replace the todo file itself
+fn try_lock(path: &Path) -> io::Result<Lock> {
+    let lock_path = lock_path(path);
+    if let Some(dir) = lock_path.parent()
+        && !dir.as_os_str().is_empty()
+    {
+        fs::create_dir_all(dir)?;
+    }
+    // Not truncated on open - until we hold the lock, the pid in it is the owner's
+    let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&lock_path)?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            Ok(Lock::Held(file))
+        }
+        Err(fs::TryLockError::WouldBlock) => {
+            let mut contents = String::new();
+            let _ = file.read_to_string(&mut contents);
+            Ok(Lock::Taken(contents.trim().parse().ok()))
+        }
+        Err(fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// Whether another running instance has the todo file at `path` locked
+pub(crate) fn locked_elsewhere(path: &Path) -> bool {
+    matches!(try_lock(path), Ok(Lock::Taken(_)))
+}
+
+/// Reads a whole file, or None if it doesn't exist yet
+fn read_file(path: &Path) -> Result<Option<String>, AppError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(AppError::Read { path: path.to_path_buf(), source }),
+    }
+}
+
+/// Writes a whole file atomically, creating its directory first - the data
+/// directory doesn't exist on first run
+/// The contents go to a temporary file next to the target, are flushed to
+/// disk and then renamed over it, so a crash at any point leaves either the
+/// old file or the new one - never a truncated mix of both
+fn write_file(path: &Path, contents: String) -> Result<(), AppError> {
+    let error = |source| AppError::Write { path: path.to_path_buf(), source };
+    // Renaming over a symlink would replace the link itself, so write
+    // through to the file it points at (a file that doesn't exist yet is
+    // written where asked)
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&dir).map_err(error)?;
+
+    let temp = temp_path(&target);
+    let result = write_temp(&temp, &target, contents.as_bytes()).and_then(|()| fs::rename(&temp, &target));
+    if let Err(err) = result {
+        // Don't leave a half-written temp file lying around
+        let _ = fs::remove_file(&temp);
+        return Err(error(err));
+    }
+
+    // The rename itself only survives a power cut once the directory entry
+    // is on disk too - not every filesystem supports syncing a directory,
+    // and the data is already safe, so failing here isn't an error
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Where `write_file` stages the new contents: a hidden file in the same
+/// directory, since a rename across filesystems wouldn't be atomic
+/// The process id keeps two running instances from sharing one
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Writes and flushes the temp file, with the permissions of the file it
+/// will replace so saving doesn't make a private file world-readable
+fn write_temp(temp: &Path, target: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(temp)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Serializing our own types only fails on a bug, but it still means the
+/// file wasn't written
+fn write_error(path: &Path, err: serde_json::Error) -> AppError {
+    AppError::Write { path: path.to_path_buf(), source: err.into() }
+}
+
+/// Parses the save file contents into lists, upgrading older layouts
+/// A file that's already current is parsed straight from the text, so errors
+/// in it come with the line and column to fix
+/// A file from a newer version is refused rather than half understood - saving
+/// it back would drop whatever that version added
+fn parse_save_file(contents: &str) -> Result<Vec<TodoList>, serde_json::Error> {
+    let mut value: Value = serde_json::from_str(contents)?;
+    let version = save_version(&value);
+    if version > SAVE_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "written by a newer version of todo-tui (format {}, this one reads up to {})",
+            version, SAVE_VERSION
+        )));
+    }
+    if version == SAVE_VERSION {
+        return Ok(serde_json::from_str::<SaveFile>(contents)?.lists);
+    }
+    for migrate in &MIGRATIONS[version as usize..] {
+        value = migrate(value);
+    }
+    // Versions from before the field existed don't have one to overwrite
+    value["version"] = json!(SAVE_VERSION);
+    Ok(serde_json::from_value::<SaveFile>(value)?.lists)
+}
+
+/// File formats the todos can be stored in
+/// JSON keeps everything; todo.txt, Markdown and org-mode trade some features
+/// for compatibility with tools and notes the user already has
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    TodoTxt,
+    Markdown,
+    Org,
+}
+
+impl Format {
+    /// Picks the format from the file extension - `.txt` means todo.txt,
+    /// `.md` Markdown task lists, `.org` org-mode headlines
+    pub(crate) fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("txt") => Format::TodoTxt,
+            Some(ext) if ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown") => Format::Markdown,
+            Some(ext) if ext.eq_ignore_ascii_case("org") => Format::Org,
+            _ => Format::Json,
+        }
+    }
+
+    /// Parses file contents into lists, or says what's wrong with them
+    /// todo.txt, Markdown and org read any text, only JSON can be malformed
+    pub(crate) fn read(self, contents: &str) -> Result<Vec<TodoList>, serde_json::Error> {
+        match self {
+            Format::Json => parse_save_file(contents),
+            Format::TodoTxt => Ok(parse_todo_txt(contents)),
+            Format::Markdown => Ok(parse_markdown(contents).0),
+            Format::Org => Ok(parse_org(contents).0),
+        }
+    }
+
+    /// Renders the lists as the new contents of the file at `path`
+    pub(crate) fn write(self, lists: &[TodoList], path: &Path) -> Result<String, serde_json::Error> {
+        match self {
+            // Serialize to pretty JSON for human readability (easier debugging)
+            // If we needed performance, we'd use compact JSON instead
+            Format::Json => Ok(serde_json::to_string_pretty(&SaveFile { version: SAVE_VERSION, lists: lists.to_vec(), heads: Vec::new() })?),
+            Format::TodoTxt => Ok(write_todo_txt(lists)),
+            // Re-read the file so prose edited outside the app since loading is kept
+            Format::Markdown => Ok(write_markdown(lists, &fs::read_to_string(path).unwrap_or_default())),
+            Format::Org => Ok(write_org(lists, &fs::read_to_string(path).unwrap_or_default())),
+        }
+    }
+}
+
+/// Parses a todo.txt file, one todo per non-empty line
+/// See https://github.com/todotxt/todo.txt for the format:
+/// `x (A) 2024-05-02 2024-05-01 call mom +family @phone due:2024-05-03`
+/// Lines carrying a `list:NAME` key go to that list, all others to the first one
+/// Earlier versions wrote tags as `+tag` and projects as `project:NAME`, so
+/// a file with that key is read the old way - saving it writes the new one
+pub(crate) fn parse_todo_txt(contents: &str) -> Vec<TodoList> {
+    let legacy = contents.split_whitespace().any(|word| word.strip_prefix("project:").is_some_and(|n| !n.is_empty()));
+    let mut lists = vec![TodoList::new("Todos")];
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let (todo, list_name) = parse_todo_txt_line(line, legacy);
+        let index = match list_name {
+            None => 0,
+            Some(name) => match lists.iter().position(|l| l.name == name) {
+                Some(i) => i,
+                None => {
+                    lists.push(TodoList::new(&name));
+                    lists.len() - 1
+                }
+            },
+        };
+        lists[index].todos.push(todo);
+    }
+    lists
+}
+
+/// Parses one todo.txt line into a todo and the name of its list, if given
+/// Anything we don't model - unknown `key:value` pairs and a second
+/// `+project` included - stays in the text
+/// `legacy` reads `+tag` as a tag, as files from before projects had them
+fn parse_todo_txt_line(line: &str, legacy: bool) -> (TodoItem, Option<String>) {
+    let mut words = line.split_whitespace().peekable();
+    let mut todo = TodoItem::new("");
+
+    // The optional prefix, in this fixed order: x, completion date, priority, creation date
+    if words.peek() == Some(&"x") {
+        words.next();
+        todo.status = Status::Done;
+        todo.completed_at = words.peek().and_then(|w| Date::parse(w)).map(Timestamp::from_date);
+        if todo.completed_at.is_some() {
+            words.next();
+        }
+    }
+    if let Some(word) = words.peek()
+        && let [b'(', letter @ b'A'..=b'Z', b')'] = word.as_bytes()
+    {
+        // todo.txt has 26 levels and we have three, so D and below share Low
+        todo.priority = match letter {
+            b'A' => Priority::High,
+            b'B' => Priority::Medium,
+            _ => Priority::Low,
+        };
+        words.next();
+    }
+    // A missing creation date stays missing rather than becoming "now" on every load
+    todo.created_at = words.peek().and_then(|w| Date::parse(w)).map(Timestamp::from_date);
+    if todo.created_at.is_some() {
+        words.next();
+    }
+
+    let mut text = Vec::new();
+    let mut list = None;
+    // The last +project is the todo's, and where it was in the text - a todo
+    // has one, so earlier ones stay in the text where they were
+    let mut project: Option<(usize, &str, &str)> = None;
+    for word in words {
+        if let Some(tag) = word.strip_prefix('+').filter(|t| legacy && !t.is_empty()) {
+            todo.tags.push(tag.to_string());
+        } else if let Some(name) = word.strip_prefix('+').filter(|n| !n.is_empty()) {
+            if let Some((at, earlier, _)) = project.take() {
+                text.insert(at, earlier);
+            }
+            project = Some((text.len(), word, name));
+        } else if let Some(tag) = word.strip_prefix("tag:").filter(|t| !t.is_empty()) {
+            todo.tags.push(tag.to_string());
+        } else if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            todo.contexts.push(context.to_string());
+        } else if let Some(due) = word.strip_prefix("due:").and_then(Date::parse) {
+            todo.due = Some(due);
+        } else if let Some(rule) = word.strip_prefix("rec:").and_then(parse_todo_txt_recurrence) {
+            todo.recurrence = Some(rule);
+        } else if let Some(spent) = word.strip_prefix("spent:").and_then(parse_duration_key) {
+            todo.time_spent = spent;
+        } else if let Some(status) = word.strip_prefix("status:").and_then(Status::from_key) {
+            todo.status = status;
+        } else if let Some(name) = word.strip_prefix("project:").filter(|n| !n.is_empty()) {
+            project = Some((text.len(), word, name));
+        } else if let Some(name) = word.strip_prefix("list:").filter(|n| !n.is_empty()) {
+            // List names may contain spaces, which a key:value word can't
+            list = Some(name.replace('_', " "));
+        } else {
+            text.push(word);
+        }
+    }
+    if let Some((_, _, name)) = project {
+        // Project names may contain spaces, which a +project can't
+        todo.project = Some(name.replace('_', " "));
+    }
+    todo.text = text.join(" ");
+    (todo, list)
+}
+
+/// Parses a `rec:` value in the common todo.txt extension syntax: 1d, 2w, 1m
+/// A leading '+' (strict recurrence in some tools) is accepted and ignored
+fn parse_todo_txt_recurrence(value: &str) -> Option<Recurrence> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let unit = value.chars().last()?;
+    let count: u32 = value[..value.len() - unit.len_utf8()].parse().ok().filter(|&n| n > 0)?;
+    match (unit, count) {
+        ('d', n) => Recurrence::every(n),
+        ('w', 1) => Some(Recurrence::Weekly),
+        ('w', n) => Recurrence::every(n.checked_mul(7)?),
+        ('m', 1) => Some(Recurrence::Monthly),
+        _ => None,
+    }
+}
+
+/// Formats a recurrence rule as a `rec:` value, the inverse of `parse_todo_txt_recurrence`
+fn format_todo_txt_recurrence(rule: Recurrence) -> String {
+    match rule {
+        Recurrence::Daily => "1d".to_string(),
+        Recurrence::Weekly => "1w".to_string(),
+        Recurrence::Monthly => "1m".to_string(),
+        Recurrence::EveryNDays(n) => format!("{}d", n),
+    }
+}
+
+/// Renders all lists as todo.txt
+/// todo.txt is flat, so subtasks are written as their own lines after their parent
+/// Notes, folding and the trash have no todo.txt equivalent and aren't saved
+fn write_todo_txt(lists: &[TodoList]) -> String {
+    let mut out = String::new();
+    for (i, list) in lists.iter().enumerate() {
+        // The first list is the plain todo.txt file, later ones are tagged by name
+        let list_key = (i > 0).then(|| format!("list:{}", list.name.replace(' ', "_")));
+        for todo in &list.todos {
+            write_todo_txt_item(todo, list_key.as_deref(), &mut out);
+        }
+    }
+    out
+}
+
+/// Appends one todo and its subtasks as todo.txt lines
+fn write_todo_txt_item(todo: &TodoItem, list_key: Option<&str>, out: &mut String) {
+    let mut words: Vec<String> = Vec::new();
+    // Cancelled todos are finished as far as other todo.txt tools are concerned
+    if todo.is_closed() {
+        words.push("x".to_string());
+        // The format only allows a completion date when there's a creation date
+        if let (Some(done), Some(_)) = (todo.completed_at, todo.created_at) {
+            words.push(done.date().to_string());
+        }
+    }
+    match todo.priority {
+        Priority::None => {}
+        Priority::Low => words.push("(C)".to_string()),
+        Priority::Medium => words.push("(B)".to_string()),
+        Priority::High => words.push("(A)".to_string()),
+    }
+    if let Some(created) = todo.created_at {
+        words.push(created.date().to_string());
+    }
+    words.push(todo.text.clone());
+    if let Some(project) = &todo.project {
+        words.push(format!("+{}", project.replace(' ', "_")));
+    }
+    for context in &todo.contexts {
+        words.push(format!("@{}", context));
+    }
+    // todo.txt has no tags, so they're a key other tools leave alone
+    for tag in &todo.tags {
+        words.push(format!("tag:{}", tag));
+    }
+    if let Some(due) = todo.due {
+        words.push(format!("due:{}", due));
+    }
+    if let Some(rule) = todo.recurrence {
+        words.push(format!("rec:{}", format_todo_txt_recurrence(rule)));
+    }
+    // A running session is written as time spent so far - the file can't hold it
+    let spent = todo.tracked_seconds(Timestamp::now());
+    if spent >= 60 {
+        words.push(format!("spent:{}", format_duration_key(spent)));
+    }
+    // Only done and pending are native to todo.txt, the rest need a key
+    if !matches!(todo.status, Status::Pending | Status::Done) {
+        words.push(format!("status:{}", todo.status.key()));
+    }
+    if let Some(key) = list_key {
+        words.push(key.to_string());
+    }
+    out.push_str(&words.join(" "));
+    out.push('\n');
+    for child in &todo.children {
+        write_todo_txt_item(child, list_key, out);
+    }
+}
+
+/// A piece of a Markdown file as far as task lists are concerned
+enum Segment {
+    /// A line that isn't part of a task list, kept verbatim
+    Text(String),
+    /// A run of task list items
+    Tasks(Run),
+}
+
+/// A run of task list items, all belonging to one list
+/// A list can have several runs with prose between them, and each todo is
+/// written back to the one it came from
+struct Run {
+    /// The name of the list
+    list: String,
+    /// The text of each of its top-level todos, to tell them by when writing
+    texts: Vec<String>,
+    /// The bullet its items were written with, at each depth
+    bullets: Vec<char>,
+}
+
+/// Name of the list holding tasks that come before any heading
+const MARKDOWN_DEFAULT_LIST: &str = "Todos";
+
+/// Parses a Markdown file's GitHub-style task lists (`- [ ] todo`, `- [x] done`)
+/// Each heading starts a new list named after it, nested items become subtasks,
+/// and indented lines under an item become its notes
+/// Fenced code blocks are prose, whatever they hold
+/// Also returns the file split into prose and task runs so it can be rewritten
+fn parse_markdown(contents: &str) -> (Vec<TodoList>, Vec<Segment>) {
+    let mut lists = vec![TodoList::new(MARKDOWN_DEFAULT_LIST)];
+    let mut segments = Vec::new();
+    let mut list = 0;
+    // Indentation and path of the items the next line could be nested under
+    let mut stack: Vec<(usize, TodoPath)> = Vec::new();
+    // The fence of the code block the line is in, like "```"
+    let mut fence: Option<&str> = None;
+
+    for line in contents.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let marker = markdown_fence(line);
+        let fenced = match fence {
+            // Closed by a fence of the same character at least as long, on its own
+            Some(open) => {
+                if marker.is_some_and(|m| m.starts_with(&open[..1]) && m.len() >= open.len() && m == line.trim()) {
+                    fence = None;
+                }
+                true
+            }
+            None => {
+                fence = marker;
+                fence.is_some()
+            }
+        };
+        if !fenced && let Some((bullet, status, text)) = parse_markdown_task(line) {
+            while stack.last().is_some_and(|(i, _)| *i >= indent) {
+                stack.pop();
+            }
+            // Continue the current run of tasks or start a new one
+            if stack.is_empty() && !matches!(segments.last(), Some(Segment::Tasks(_))) {
+                let run = Run { list: lists[list].name.clone(), texts: Vec::new(), bullets: Vec::new() };
+                segments.push(Segment::Tasks(run));
+            }
+            let mut todo = parse_markdown_text(text);
+            todo.status = status;
+            if let Some(Segment::Tasks(run)) = segments.last_mut() {
+                if stack.is_empty() {
+                    run.texts.push(todo.text.clone());
+                }
+                if run.bullets.len() == stack.len() {
+                    run.bullets.push(bullet);
+                }
+            }
+            let todos = &mut lists[list].todos;
+            let path = match stack.last() {
+                Some((_, parent)) => {
+                    let children = &mut item_at_mut(todos, parent).children;
+                    children.push(todo);
+                    let mut path = parent.clone();
+                    path.push(children.len() - 1);
+                    path
+                }
+                None => {
+                    todos.push(todo);
+                    vec![todos.len() - 1]
+                }
+            };
+            stack.push((indent, path));
+            continue;
+        }
+        // Indented text right under an item is that item's notes
+        if let Some((item_indent, path)) = stack.last()
+            && indent > *item_indent
+            && !line.trim().is_empty()
+        {
+            let todo = item_at_mut(&mut lists[list].todos, path);
+            if !todo.notes.is_empty() {
+                todo.notes.push('\n');
+            }
+            todo.notes.push_str(line.trim());
+            continue;
+        }
+        // Anything else ends the run of tasks and is kept as prose
+        stack.clear();
+        if let Some(heading) = parse_markdown_heading(line).filter(|_| !fenced) {
+            // Repeating a heading adds to the list it already started
+            list = match lists.iter().position(|l| l.name == heading) {
+                Some(i) => i,
+                None => {
+                    lists.push(TodoList::new(heading));
+                    lists.len() - 1
+                }
+            };
+        }
+        segments.push(Segment::Text(line.to_string()));
+    }
+
+    // Headings without tasks are just prose, not lists
+    lists.retain(|l| !l.todos.is_empty());
+    if lists.is_empty() {
+        lists.push(TodoList::new(MARKDOWN_DEFAULT_LIST));
+    }
+    (lists, segments)
+}
+
+/// The fence opening or closing a code block, three or more backticks or
+/// tildes at the start of the line
+fn markdown_fence(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    (len >= 3).then(|| &line[..len])
+}
+
+/// Splits a task list line into its bullet, checkbox state and text
+/// Accepts the three bullet characters Markdown allows and an upper-case X
+fn parse_markdown_task(line: &str) -> Option<(char, Status, &str)> {
+    let line = line.trim_start();
+    let bullet = line.chars().next().filter(|c| matches!(c, '-' | '*' | '+'))?;
+    let rest = line[1..].strip_prefix(' ')?;
+    // "[/]" and "[-]" are the in-progress and cancelled checkboxes of Obsidian
+    // and other editors; "[!]" for blocked is our own
+    let status = match rest.get(..3)? {
+        "[ ]" => Status::Pending,
+        "[/]" => Status::InProgress,
+        "[!]" => Status::Blocked,
+        "[x]" | "[X]" => Status::Done,
+        "[-]" => Status::Cancelled,
+        _ => return None,
+    };
+    let text = &rest[3..];
+    // "- [ ]" must be followed by a space or end the line to be a checkbox
+    if text.is_empty() || text.starts_with(' ') {
+        Some((bullet, status, text.trim()))
+    } else {
+        None
+    }
+}
+
+/// Returns the text of an ATX heading like `## Work`
+fn parse_markdown_heading(line: &str) -> Option<&str> {
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    let text = line[hashes..].strip_prefix(' ')?;
+    (1..=6).contains(&hashes).then(|| text.trim().trim_end_matches('#').trim())
+}
+
+/// Builds a todo from a task item's text
+/// Quick-add syntax (`#tags`, `!high`, `due:`) works like in the app, plus the
+/// todo.txt style `rec:` key for recurrence and `spent:` for tracked time
+fn parse_markdown_text(text: &str) -> TodoItem {
+    // Tags, priority and due date are quick-add syntax, so only rec: and spent:
+    // need handling here
+    let mut recurrence = None;
+    let mut spent = 0;
+    let mut words = Vec::new();
+    for word in text.split_whitespace() {
+        if let Some(rule) = word.strip_prefix("rec:").and_then(parse_todo_txt_recurrence) {
+            recurrence = Some(rule);
+        } else if let Some(seconds) = word.strip_prefix("spent:").and_then(parse_duration_key) {
+            spent = seconds;
+        } else {
+            words.push(word);
+        }
+    }
+    let mut todo = TodoItem::parse(&words.join(" "));
+    todo.recurrence = recurrence;
+    todo.time_spent = spent;
+    // Markdown has nowhere to keep timestamps, so don't invent them on every load
+    todo.created_at = None;
+    todo
+}
+
+/// Rewrites a Markdown file with the current todos
+/// Prose is copied from `previous` unchanged and each todo goes back to the
+/// run of tasks it came from, found by its text - a todo that isn't in any
+/// goes to the run of the one before it, so a new todo lands next to the one
+/// it was added after. Lists without a run are appended under a new heading
+fn write_markdown(lists: &[TodoList], previous: &str) -> String {
+    let (_, segments) = parse_markdown(previous);
+    let runs: Vec<&Run> = segments
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Tasks(run) => Some(run),
+            Segment::Text(_) => None,
+        })
+        .collect();
+    let mut placed: Vec<Vec<&TodoItem>> = vec![Vec::new(); runs.len()];
+    let mut written = vec![false; lists.len()];
+    for (list, written) in lists.iter().zip(&mut written) {
+        let own: Vec<usize> = (0..runs.len()).filter(|&r| runs[r].list == list.name).collect();
+        // A run whose list was deleted is dropped along with it
+        if own.is_empty() {
+            continue;
+        }
+        *written = true;
+        // The texts each run still has to find a todo for
+        let mut unclaimed: Vec<Vec<&str>> =
+            own.iter().map(|&r| runs[r].texts.iter().map(String::as_str).collect()).collect();
+        let mut run = 0;
+        for todo in &list.todos {
+            let claimed = unclaimed.iter().enumerate().find_map(|(i, texts)| {
+                texts.iter().position(|text| *text == todo.text).map(|at| (i, at))
+            });
+            if let Some((i, at)) = claimed {
+                unclaimed[i].remove(at);
+                run = i;
+            }
+            placed[own[run]].push(todo);
+        }
+    }
+
+    let mut out = String::new();
+    let mut run = 0;
+    for segment in &segments {
+        match segment {
+            Segment::Text(line) => {
+                out.push_str(line);
+                out.push('\n');
+            }
+            Segment::Tasks(tasks) => {
+                write_markdown_tasks(placed[run].iter().copied(), 0, &tasks.bullets, &mut out);
+                run += 1;
+            }
+        }
+    }
+    for (list, _) in lists.iter().zip(written).filter(|(l, written)| !written && !l.todos.is_empty()) {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(&format!("## {}\n\n", list.name));
+        write_markdown_tasks(&list.todos, 0, &[], &mut out);
+    }
+    out
+}
+
+/// Appends todos as task list items, subtasks and notes indented below them
+/// Each depth keeps the bullet it was written with, `-` where there was none
+fn write_markdown_tasks<'a>(
+    todos: impl IntoIterator<Item = &'a TodoItem>,
+    depth: usize,
+    bullets: &[char],
+    out: &mut String,
+) {
+    let indent = "  ".repeat(depth);
+    let bullet = bullets.get(depth).or(bullets.last()).copied().unwrap_or('-');
+    for todo in todos {
+        // The quick-add form keeps tags, priority and due date readable in the file
+        let checkbox = match todo.status {
+            Status::Pending => ' ',
+            Status::InProgress => '/',
+            Status::Blocked => '!',
+            Status::Done => 'x',
+            Status::Cancelled => '-',
+        };
+        let mut line = format!("{}{} [{}] {}", indent, bullet, checkbox, todo.quick_add_text());
+        if let Some(rule) = todo.recurrence {
+            line.push_str(&format!(" rec:{}", format_todo_txt_recurrence(rule)));
+        }
+        let spent = todo.tracked_seconds(Timestamp::now());
+        if spent >= 60 {
+            line.push_str(&format!(" spent:{}", format_duration_key(spent)));
+        }
+        out.push_str(&line);
+        out.push('\n');
+        // A blank line would end the item, so paragraphs in notes are joined up
+        for note in todo.notes.lines().filter(|l| !l.trim().is_empty()) {
+            out.push_str(&format!("{}  {}\n", indent, note));
+        }
+        write_markdown_tasks(&todo.children, depth + 1, bullets, out);
+    }
+}
+
+/// A piece of an org file as far as todos are concerned
+enum OrgSegment {
+    /// A line that isn't part of a todo, kept verbatim
+    Text(String),
+    /// A run of todo headlines of the named list, at the level of the first
+    Todos(String, usize),
+}
+
+/// The keywords an org file marks todos with: the open ones and the done
+/// ones, from its `#+TODO:` lines plus the ones this app writes
+struct OrgKeywords {
+    open: Vec<String>,
+    done: Vec<String>,
+}
+
+impl OrgKeywords {
+    /// Reads the `#+TODO: TODO NEXT | DONE` style lines of a file
+    fn from_file(contents: &str) -> OrgKeywords {
+        let mut keywords = OrgKeywords { open: Vec::new(), done: Vec::new() };
+        for line in contents.lines() {
+            let upper = line.trim_start().to_ascii_uppercase();
+            let Some(words) = ["#+TODO:", "#+SEQ_TODO:", "#+TYP_TODO:"].iter().find_map(|prefix| upper.strip_prefix(prefix)) else {
+                continue;
+            };
+            // Fast-access keys like "TODO(t)" aren't part of the keyword
+            let words: Vec<&str> = words.split_whitespace().map(|word| word.split('(').next().unwrap_or(word)).collect();
+            // Without a bar the last keyword is the done one
+            let bar = words.iter().position(|word| *word == "|").unwrap_or(words.len().saturating_sub(1));
+            keywords.open.extend(words[..bar].iter().map(|word| word.to_string()));
+            keywords.done.extend(words[bar..].iter().filter(|word| **word != "|").map(|word| word.to_string()));
+        }
+        for (status, keyword) in ORG_KEYWORDS {
+            let side = if matches!(status, Status::Done | Status::Cancelled) { &mut keywords.done } else { &mut keywords.open };
+            if !side.iter().any(|known| known == keyword) {
+                side.push(keyword.to_string());
+            }
+        }
+        keywords
+    }
+
+    /// The status a headline keyword stands for, None if it isn't one
+    fn status(&self, keyword: &str) -> Option<Status> {
+        let done = if self.open.iter().any(|open| open == keyword) {
+            false
+        } else if self.done.iter().any(|done| done == keyword) {
+            true
+        } else {
+            return None;
+        };
+        Some(match keyword {
+            "NEXT" | "STARTED" | "DOING" => Status::InProgress,
+            "WAITING" | "HOLD" | "BLOCKED" => Status::Blocked,
+            "CANCELLED" | "CANCELED" | "KILL" => Status::Cancelled,
+            _ if done => Status::Done,
+            _ => Status::Pending,
+        })
+    }
+
+    /// The keyword to write for a status: the file's own where it has one
+    fn keyword(&self, status: Status) -> &str {
+        let default = ORG_KEYWORDS.iter().find(|(s, _)| *s == status).map_or("TODO", |(_, keyword)| keyword);
+        self.open.iter().chain(&self.done).find(|keyword| self.status(keyword) == Some(status)).map_or(default, String::as_str)
+    }
+}
+
+/// The keywords written for each status when the file doesn't have its own
+/// Emacs only knows TODO and DONE unless the file says
+/// `#+TODO: TODO STARTED WAITING | DONE CANCELLED`
+const ORG_KEYWORDS: [(Status, &str); 5] = [
+    (Status::Pending, "TODO"),
+    (Status::InProgress, "STARTED"),
+    (Status::Blocked, "WAITING"),
+    (Status::Done, "DONE"),
+    (Status::Cancelled, "CANCELLED"),
+];
+
+/// Name of the list holding todos that come before any headline
+const ORG_DEFAULT_LIST: &str = "Todos";
+
+/// Splits an org headline like `** TODO [#A] Title :tag:` into its level and text
+fn parse_org_headline(line: &str) -> Option<(usize, &str)> {
+    let level = line.len() - line.trim_start_matches('*').len();
+    let text = line[level..].strip_prefix(' ')?;
+    (level > 0).then_some((level, text.trim()))
+}
+
+/// Parses an org file's headlines with TODO keywords into todos
+/// Each other headline starts a new list named after it, todo headlines under
+/// a todo become its subtasks, and the text under one becomes its notes
+/// Also returns the file split into other text and todo runs so it can be rewritten
+fn parse_org(contents: &str) -> (Vec<TodoList>, Vec<OrgSegment>) {
+    let keywords = OrgKeywords::from_file(contents);
+    let mut lists = vec![TodoList::new(ORG_DEFAULT_LIST)];
+    let mut segments = Vec::new();
+    let mut list = 0;
+    // Level and path of the todos the next line could belong to
+    let mut stack: Vec<(usize, TodoPath)> = Vec::new();
+    // Blank lines under a todo, which only count as notes if more follow
+    let mut blanks = 0;
+
+    for line in contents.lines() {
+        if let Some((level, text)) = parse_org_headline(line) {
+            while stack.last().is_some_and(|(l, _)| *l >= level) {
+                stack.pop();
+            }
+            if let Some(todo) = parse_org_todo(text, &keywords) {
+                if stack.is_empty() && !matches!(segments.last(), Some(OrgSegment::Todos(..))) {
+                    segments.push(OrgSegment::Todos(lists[list].name.clone(), level));
+                }
+                blanks = 0;
+                let todos = &mut lists[list].todos;
+                let path = match stack.last() {
+                    Some((_, parent)) => {
+                        let children = &mut item_at_mut(todos, parent).children;
+                        children.push(todo);
+                        let mut path = parent.clone();
+                        path.push(children.len() - 1);
+                        path
+                    }
+                    None => {
+                        todos.push(todo);
+                        vec![todos.len() - 1]
+                    }
+                };
+                stack.push((level, path));
+                continue;
+            }
+        }
+        if let Some((_, path)) = stack.last() {
+            // Text under a todo, headlines below its level included, is its notes
+            // - except the planning line right under the headline
+            let todo = item_at_mut(&mut lists[list].todos, path);
+            if line.trim().is_empty() {
+                blanks += 1;
+                continue;
+            }
+            if !(todo.notes.is_empty() && parse_org_planning(line, todo)) {
+                if !todo.notes.is_empty() {
+                    todo.notes.push_str(&"\n".repeat(blanks + 1));
+                }
+                todo.notes.push_str(line);
+            }
+            blanks = 0;
+            continue;
+        }
+        // Anything else ends the run of todos and is kept as it is
+        if matches!(segments.last(), Some(OrgSegment::Todos(..))) {
+            segments.extend((0..blanks).map(|_| OrgSegment::Text(String::new())));
+        }
+        blanks = 0;
+        if let Some((_, text)) = parse_org_headline(line) {
+            let (name, _) = split_org_tags(text);
+            // Repeating a headline adds to the list it already started
+            list = match lists.iter().position(|l| l.name == name) {
+                Some(i) => i,
+                None => {
+                    lists.push(TodoList::new(name));
+                    lists.len() - 1
+                }
+            };
+        }
+        segments.push(OrgSegment::Text(line.to_string()));
+    }
+
+    for list in &mut lists {
+        dedent_org_notes(&mut list.todos);
+    }
+    // Headlines without todos are just text, not lists
+    lists.retain(|l| !l.todos.is_empty());
+    if lists.is_empty() {
+        lists.push(TodoList::new(ORG_DEFAULT_LIST));
+    }
+    (lists, segments)
+}
+
+/// Builds a todo from a headline's text if it starts with a TODO keyword
+fn parse_org_todo(text: &str, keywords: &OrgKeywords) -> Option<TodoItem> {
+    let (keyword, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let status = keywords.status(keyword)?;
+    let mut rest = rest.trim_start();
+    let mut priority = Priority::None;
+    for (cookie, level) in [("[#A]", Priority::High), ("[#B]", Priority::Medium), ("[#C]", Priority::Low)] {
+        if let Some(after) = rest.strip_prefix(cookie) {
+            (priority, rest) = (level, after.trim_start());
+        }
+    }
+    let (title, tags) = split_org_tags(rest);
+    let mut todo = TodoItem::new(title);
+    todo.status = status;
+    todo.priority = priority;
+    // `:@phone:` is how org writes a context
+    for tag in tags {
+        match tag.strip_prefix('@').filter(|context| !context.is_empty()) {
+            Some(context) => todo.contexts.push(context.to_string()),
+            None => todo.tags.push(tag),
+        }
+    }
+    // Timestamps other than the planning ones aren't kept, so don't invent
+    // one on every load
+    todo.created_at = None;
+    Some(todo)
+}
+
+/// Splits the `:tag:other:` at the end of a headline off its title
+fn split_org_tags(text: &str) -> (&str, Vec<String>) {
+    let text = text.trim_end();
+    if let Some((title, tags)) = text.rsplit_once([' ', '\t'])
+        && tags.len() > 2
+        && tags.starts_with(':')
+        && tags.ends_with(':')
+    {
+        let tags = tags.split(':').filter(|tag| !tag.is_empty()).map(str::to_string).collect();
+        return (title.trim_end(), tags);
+    }
+    (text, Vec::new())
+}
+
+/// Reads a planning line like `DEADLINE: <2024-05-03 Fri 17:00 +1w>` into the
+/// todo, false if the line isn't one
+/// DEADLINE is the due date, SCHEDULED the start and CLOSED when it was done
+fn parse_org_planning(line: &str, todo: &mut TodoItem) -> bool {
+    // Filled in on a copy, so a line that turns out not to be one changes nothing
+    let mut planned = todo.clone();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let Some((keyword, after)) = rest.split_once(':') else {
+            return false;
+        };
+        let after = after.trim_start();
+        let close = match after.chars().next() {
+            Some('<') => '>',
+            Some('[') => ']',
+            _ => return false,
+        };
+        let Some(end) = after.find(close) else {
+            return false;
+        };
+        let Some((date, time, repeat)) = parse_org_timestamp(&after[1..end]) else {
+            return false;
+        };
+        match keyword.trim() {
+            "DEADLINE" => {
+                (planned.due, planned.due_time) = (Some(date), time);
+                planned.recurrence = repeat.or(planned.recurrence);
+            }
+            "SCHEDULED" => {
+                planned.scheduled = Some(date);
+                planned.recurrence = planned.recurrence.or(repeat);
+            }
+            "CLOSED" => {
+                let seconds = time.map_or(0, |t| i64::from(t.hour * 3600 + t.minute * 60));
+                planned.completed_at = Some(Timestamp(Timestamp::from_date(date).0 + seconds));
+            }
+            _ => return false,
+        }
+        rest = after[end + 1..].trim_start();
+    }
+    *todo = planned;
+    !line.trim().is_empty()
+}
+
+/// Parses the inside of an org timestamp: `2024-05-03 Fri 17:00 +1w`
+/// The weekday is optional and a time range counts from its start
+fn parse_org_timestamp(text: &str) -> Option<(Date, Option<Time>, Option<Recurrence>)> {
+    let mut words = text.split_whitespace();
+    let date = Date::parse(words.next()?)?;
+    let mut time = None;
+    let mut repeat = None;
+    for word in words {
+        if word.starts_with(['+', '.']) {
+            repeat = parse_todo_txt_recurrence(word.trim_start_matches(['+', '.']));
+        } else if let Some(start) = word.split('-').next().filter(|start| start.contains(':')) {
+            time = Time::parse(start);
+        }
+    }
+    Some((date, time, repeat))
+}
+
+/// Takes away the indentation the notes of todos share, which org files
+/// indent to the headline or not at all
+fn dedent_org_notes(todos: &mut [TodoItem]) {
+    for todo in todos {
+        let indent = todo.notes.lines().filter(|l| !l.trim().is_empty()).map(|l| l.len() - l.trim_start().len()).min().unwrap_or(0);
+        if indent > 0 {
+            todo.notes = todo.notes.lines().map(|l| l.get(indent..).unwrap_or("")).collect::<Vec<_>>().join("\n");
+        }
+        dedent_org_notes(&mut todo.children);
+    }
+}
+
+/// Rewrites an org file with the current todos
+/// Other text is copied from `previous` unchanged and each list's todos replace
+/// its first run of todos there - lists without one are appended under a new
+/// headline
+fn write_org(lists: &[TodoList], previous: &str) -> String {
+    let keywords = OrgKeywords::from_file(previous);
+    let (_, segments) = parse_org(previous);
+    let mut written = vec![false; lists.len()];
+    let mut out = String::new();
+    for segment in segments {
+        match segment {
+            OrgSegment::Text(line) => {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            // Later runs of the same list were merged into the first one
+            // A run whose list was deleted is dropped along with it
+            OrgSegment::Todos(name, level) => {
+                if let Some(i) = lists.iter().position(|l| l.name == name)
+                    && !written[i]
+                {
+                    written[i] = true;
+                    write_org_todos(&lists[i].todos, level, &keywords, &mut out);
+                }
+            }
+        }
+    }
+    for (list, _) in lists.iter().zip(written).filter(|(l, written)| !written && !l.todos.is_empty()) {
+        out.push_str(&format!("* {}\n", list.name));
+        write_org_todos(&list.todos, 2, &keywords, &mut out);
+    }
+    out
+}
+
+/// Appends todos as headlines at `level`, with their planning line and notes
+/// under them and subtasks one level down
+fn write_org_todos(todos: &[TodoItem], level: usize, keywords: &OrgKeywords, out: &mut String) {
+    for todo in todos {
+        let mut line = format!("{} {}", "*".repeat(level), keywords.keyword(todo.status));
+        match todo.priority {
+            Priority::High => line.push_str(" [#A]"),
+            Priority::Medium => line.push_str(" [#B]"),
+            Priority::Low => line.push_str(" [#C]"),
+            Priority::None => {}
+        }
+        line.push(' ');
+        line.push_str(&todo.text);
+        let tags = todo.labels();
+        if !tags.is_empty() {
+            line.push_str(&format!(" :{}:", tags.join(":")));
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        // The repeat goes on the date that moves, which is the due date here
+        let repeat = |rule: Option<Recurrence>| rule.map(|rule| format!(" +{}", format_todo_txt_recurrence(rule))).unwrap_or_default();
+        let mut planning = Vec::new();
+        if let Some(closed) = todo.completed_at.filter(|_| todo.is_closed()) {
+            planning.push(format!("CLOSED: [{} {} {}]", closed.date(), closed.date().weekday_name(), closed.time()));
+        }
+        if let Some(due) = todo.due {
+            let time = todo.due_time.map(|time| format!(" {}", time)).unwrap_or_default();
+            planning.push(format!("DEADLINE: <{} {}{}{}>", due, due.weekday_name(), time, repeat(todo.recurrence)));
+        }
+        if let Some(scheduled) = todo.scheduled {
+            let rule = todo.recurrence.filter(|_| todo.due.is_none());
+            planning.push(format!("SCHEDULED: <{} {}{}>", scheduled, scheduled.weekday_name(), repeat(rule)));
+        }
+        if !planning.is_empty() {
+            out.push_str(&planning.join(" "));
+            out.push('\n');
+        }
+        for note in todo.notes.lines() {
+            // A line that reads as a headline at this level or above would
+            // end the todo, so it's indented a little
+            if parse_org_headline(note).is_some_and(|(l, _)| l <= level) {
+                out.push(' ');
+            }
+            out.push_str(note);
+            out.push('\n');
+        }
+        write_org_todos(&todo.children, level + 1, keywords, out);
+    }
+}
+
+/// Formats `:export` writes
+pub(crate) const EXPORT_FORMATS: [&str; 5] = ["json", "txt", "md", "csv", "ics"];
+
+/// Renders every todo as a CSV row for spreadsheets, subtasks after their parent
+/// The number column is the todo's number in its list, as the CLI shows it
+fn write_csv(lists: &[TodoList]) -> String {
+    /// Appends the rows of one level of todos and their subtasks
+    fn rows(list: &str, todos: &[TodoItem], prefix: &mut TodoPath, out: &mut String) {
+        for (i, todo) in todos.iter().enumerate() {
+            prefix.push(i);
+            let fields = [
+                list.to_string(),
+                format_todo_number(prefix),
+                todo.text.clone(),
+                todo.status.key().to_string(),
+                todo.priority.name().to_string(),
+                todo.project.clone().unwrap_or_default(),
+                todo.tags.join(" "),
+                todo.contexts.join(" "),
+                todo.due_label().unwrap_or_default(),
+                todo.created_at.map(|t| t.to_string()).unwrap_or_default(),
+                todo.completed_at.map(|t| t.to_string()).unwrap_or_default(),
+                (todo.tracked_seconds(Timestamp::now()) / 60).to_string(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+            rows(list, &todo.children, prefix, out);
+            prefix.pop();
+        }
+    }
+    let mut out =
+        String::from("list,number,text,status,priority,project,tags,contexts,due,created,completed,minutes_spent\n");
+    for list in lists {
+        rows(&list.name, &list.todos, &mut Vec::new(), &mut out);
+    }
+    out
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The Markdown export: every list as a checklist under a heading saying
+/// when it was made and how far along the todos are
+/// It's a regular Markdown todo file too, so it can be opened with --file
+fn write_markdown_report(lists: &[TodoList]) -> String {
+    fn count(todos: &[TodoItem], open: &mut usize, finished: &mut usize) {
+        for todo in todos {
+            if todo.is_closed() {
+                *finished += 1;
+            } else {
+                *open += 1;
+            }
+            count(&todo.children, open, finished);
+        }
+    }
+    let (mut open, mut finished) = (0, 0);
+    for list in lists {
+        count(&list.todos, &mut open, &mut finished);
+    }
+    // Passed as the previous contents, which the lists are written after
+    let heading = format!("# Todos\n\nExported {}: {} open, {} finished.\n", Date::today(), open, finished);
+    write_markdown(lists, &heading)
+}
+
+/// Renders every todo as an iCalendar VTODO, for calendar and task apps
+/// Subtasks point at their parent with RELATED-TO, and the list and project
+/// become categories along with the tags
+fn write_ics(lists: &[TodoList]) -> String {
+    /// Appends the VTODOs of one level of todos and their subtasks
+    fn vtodos(list: &str, todos: &[TodoItem], prefix: &mut TodoPath, out: &mut Vec<String>) {
+        let parent = (!prefix.is_empty()).then(|| ics_uid(list, prefix));
+        for (i, todo) in todos.iter().enumerate() {
+            prefix.push(i);
+            let categories: Vec<&str> = std::iter::once(list).chain(todo.project.as_deref()).collect();
+            out.extend(ics_vtodo(todo, &ics_uid(list, prefix), parent.as_deref(), &categories));
+            vtodos(list, &todo.children, prefix, out);
+            prefix.pop();
+        }
+    }
+    let mut lines = Vec::new();
+    for list in lists {
+        vtodos(&list.name, &list.todos, &mut Vec::new(), &mut lines);
+    }
+    ics_calendar(lines)
+}
+
+/// Wraps content lines in a VCALENDAR
+/// Lines end in CRLF and are folded after 75 bytes, as RFC 5545 asks
+pub(crate) fn ics_calendar(components: Vec<String>) -> String {
+    let mut lines = vec!["BEGIN:VCALENDAR".to_string(), "VERSION:2.0".to_string(), "PRODID:-//todo-tui//EN".to_string()];
+    lines.extend(components);
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| ics_fold(line) + "\r\n").collect()
+}
+
+/// The content lines of one todo as a VTODO, without its subtasks
+/// `categories` go before the tags, for what the todo belongs to
+pub(crate) fn ics_vtodo(todo: &TodoItem, uid: &str, parent: Option<&str>, categories: &[&str]) -> Vec<String> {
+    let mut out = vec!["BEGIN:VTODO".to_string()];
+    out.push(format!("UID:{}", uid));
+    out.push(format!("DTSTAMP:{}", ics_timestamp(Timestamp::now())));
+    out.push(format!("SUMMARY:{}", ics_text(&todo.text)));
+    if !todo.notes.is_empty() {
+        out.push(format!("DESCRIPTION:{}", ics_text(&todo.notes)));
+    }
+    // A due time is the user's local time, which iCalendar calls floating
+    match (todo.due, todo.due_time) {
+        (Some(date), Some(time)) => out.push(format!("DUE:{}T{:02}{:02}00", ics_date(date), time.hour, time.minute)),
+        (Some(date), None) => out.push(format!("DUE;VALUE=DATE:{}", ics_date(date))),
+        _ => {}
+    }
+    if let Some(rule) = todo.recurrence {
+        out.push(format!("RRULE:{}", match rule {
+            Recurrence::Daily => "FREQ=DAILY".to_string(),
+            Recurrence::Weekly => "FREQ=WEEKLY".to_string(),
+            Recurrence::Monthly => "FREQ=MONTHLY".to_string(),
+            Recurrence::EveryNDays(n) => format!("FREQ=DAILY;INTERVAL={}", n),
+        }));
+    }
+    out.push(format!("STATUS:{}", ics_status(todo.status)));
+    // 1 is the highest priority, 9 the lowest and 0 none
+    match todo.priority {
+        Priority::High => out.push("PRIORITY:1".to_string()),
+        Priority::Medium => out.push("PRIORITY:5".to_string()),
+        Priority::Low => out.push("PRIORITY:9".to_string()),
+        Priority::None => {}
+    }
+    let labels = todo.labels();
+    let categories: Vec<String> = categories
+        .iter()
+        .copied()
+        .chain(labels.iter().map(String::as_str))
+        .map(ics_text)
+        .collect();
+    if !categories.is_empty() {
+        out.push(format!("CATEGORIES:{}", categories.join(",")));
+    }
+    if let Some(created) = todo.created_at {
+        out.push(format!("CREATED:{}", ics_timestamp(created)));
+    }
+    if let Some(modified) = todo.modified_at {
+        out.push(format!("LAST-MODIFIED:{}", ics_timestamp(modified)));
+    }
+    if let Some(completed) = todo.completed_at.filter(|_| todo.status == Status::Done) {
+        out.push(format!("COMPLETED:{}", ics_timestamp(completed)));
+    }
+    if let Some(parent) = parent {
+        out.push(format!("RELATED-TO:{}", parent));
+    }
+    out.push("END:VTODO".to_string());
+    out
+}
+
+/// A status as iCalendar has it - there's no "blocked", so blocked todos
+/// are just not started
+pub(crate) fn ics_status(status: Status) -> &'static str {
+    match status {
+        Status::Pending | Status::Blocked => "NEEDS-ACTION",
+        Status::InProgress => "IN-PROCESS",
+        Status::Done => "COMPLETED",
+        Status::Cancelled => "CANCELLED",
+    }
+}
+
+/// A UID that stays the same across exports as long as the todo keeps its place
+fn ics_uid(list: &str, path: &[usize]) -> String {
+    let list: String = list.chars().map(|c| if c.is_alphanumeric() { c } else { '-' }).collect();
+    format!("{}-{}@todo-tui", list, format_todo_number(path))
+}
+
+/// A date in iCalendar's basic form, `YYYYMMDD`
+fn ics_date(date: Date) -> String {
+    format!("{:04}{:02}{:02}", date.year, date.month, date.day)
+}
+
+/// A moment in UTC, `YYYYMMDDTHHMMSSZ`
+fn ics_timestamp(timestamp: Timestamp) -> String {
+    let secs = timestamp.0.rem_euclid(86_400);
+    let date = Date::from_days(timestamp.0.div_euclid(86_400));
+    format!("{}T{:02}{:02}{:02}Z", ics_date(date), secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+/// Escapes a text value - backslashes, separators and line breaks
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits a content line into 75-byte pieces, each continued line starting
+/// with a space - never inside a UTF-8 character
+pub(crate) fn ics_fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / 74 * 3);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            // The space counts towards the next line's 75
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out
+}
+
+// The parts of App that touch the disk: the todo file, the archive, view state
+// and exports
+impl App {
+    /// Gets the default path of the todos file in the user's data directory
+    /// One fixed location means the same list shows up no matter where the app
+    /// is started from - project-local lists are opt-in via --file
+    pub fn default_save_path() -> Result<PathBuf, Box<dyn Error>> {
+        let dir = data_dir().ok_or("could not determine a data directory, use --file PATH")?;
+        Ok(dir.join("todos.json"))
+    }
+
+    /// Saves todos to disk right away, in the format the file name asks for
+    /// The command line uses this - the interface saves in the background
+    pub fn save(&self) -> Result<(), AppError> {
+        write_lists(&self.save_path, &self.lists, &self.heads, self.cipher.as_ref())
+    }
+
+    /// Saves right away like `save`, then commits when the config asks for it
+    /// How the file looks afterwards is remembered, so `catch_up` doesn't
+    /// take this app's own write for someone else's
+    pub(crate) fn save_and_commit(&mut self) -> Result<(), AppError> {
+        self.save()?;
+        self.disk_stamp = FileStamp::of(&self.save_path);
+        self.commit_changes()
+    }
+
+    /// Reads the files again if something else wrote the todo file since this
+    /// app last read or wrote it
+    /// For `serve`, which keeps its lists between requests while the interface
+    /// and the command line go on writing the file
+    pub(crate) fn catch_up(&mut self) -> Result<(), AppError> {
+        if FileStamp::of(&self.save_path) != self.disk_stamp {
+            self.read_files()?;
+        }
+        Ok(())
+    }
+
+    /// Notes a change made in the interface, to be saved once the user
+    /// pauses - holding `j` on a toggle shouldn't write the file every frame
+    pub(crate) fn autosave(&mut self, archive: bool) {
+        // Whatever is in the file couldn't be loaded, so writing this
+        // session's todos over it would lose it for good
+        if self.saving_disabled || self.read_only {
+            return;
+        }
+        self.snapshot_before_first_change();
+        let now = Instant::now();
+        let unsaved = self.unsaved.get_or_insert(Unsaved { first: now, last: now, archive: false });
+        unsaved.last = now;
+        unsaved.archive |= archive;
+    }
+
+    /// Called from the event loop: starts a save once changes have settled,
+    /// and picks up how earlier saves went
+    /// A failed save mustn't interrupt editing, but the user has to find out
+    /// the change isn't on disk, so the error goes to the error bar
+    /// The todo file is also watched here, since a save must never go over
+    /// a change made by an editor or a second instance without asking
+    /// Returns whether any of that shows on screen
+    pub(crate) fn tick_saving(&mut self) -> bool {
+        let finished = self.saver.finished();
+        // A save that went fine still takes the saving indicator away
+        let mut changed = !finished.is_empty();
+        for result in finished {
+            match result {
+                Ok(Some(stamp)) => {
+                    self.disk_stamp = Some(stamp);
+                    self.toast(ToastKind::Success, self.tr("Saved").to_string());
+                }
+                Ok(None) => {}
+                Err(err) => self.error = Some(err),
+            }
+        }
+        // While our own write is under way the file is expected to change,
+        // and while the user decides about a conflict, saving would decide for them
+        if self.saving_disabled || self.saver.busy() || self.pending == Some(Confirm::Reload) {
+            return changed;
+        }
+
+        let save_due = self
+            .unsaved
+            .as_ref()
+            .is_some_and(|unsaved| unsaved.last.elapsed() >= SAVE_DELAY || unsaved.first.elapsed() >= SAVE_MAX_DELAY);
+        let poll_due = self.last_disk_check.elapsed() >= DISK_CHECK_INTERVAL;
+        // Taken before the file is looked at, so a change landing meanwhile
+        // is looked at again; without a watcher the file is polled
+        let touched = match &self.watcher {
+            Some(watcher) => watcher.take(),
+            None => poll_due,
+        };
+        // Checked right before every save too, to keep the window for a lost
+        // change as small as possible
+        if save_due || touched || self.read_only && poll_due {
+            self.last_disk_check = Instant::now();
+            // The instance that has the file may have quit since
+            if self.read_only {
+                self.acquire_lock();
+                changed |= !self.read_only;
+            }
+            let stamp = FileStamp::of(&self.save_path);
+            // A deleted file has nothing to reload - the next save recreates it
+            if stamp.is_some() && stamp != self.disk_stamp {
+                // Popups hold indexes into the lists, so wait until they're closed
+                if self.mode != Mode::Normal {
+                    if let Some(watcher) = &self.watcher {
+                        watcher.again();
+                    }
+                    return changed;
+                }
+                if self.oplog.is_some() && self.merge_changes() {
+                    return true;
+                }
+                if self.unsaved.is_some() {
+                    self.pending = Some(Confirm::Reload);
+                    self.mode = Mode::Confirm;
+                } else {
+                    self.reload_from_disk();
+                }
+                return true;
+            }
+        }
+        // The indicator already shows from the change on, so starting the save
+        // changes nothing on screen
+        if save_due {
+            self.start_save();
+        }
+        changed
+    }
+
+    /// Replaces the lists with the todo file's new contents after it changed
+    /// on disk, keeping the selection where it was as far as possible
+    /// Undo history refers to the old lists, so it goes
+    pub(crate) fn reload_from_disk(&mut self) {
+        // Remembered first, so a file that's broken now is reported once
+        // rather than on every check
+        self.disk_stamp = FileStamp::of(&self.save_path);
+        self.unsaved = None;
+        // Whoever changed the file commits their own changes
+        self.changes.clear();
+        let row = self.state().selected();
+        match self.read_files() {
+            Ok(()) => {
+                self.forget_history();
+                if let Some(row) = row
+                    && !self.visible.is_empty()
+                {
+                    let last = self.visible.len() - 1;
+                    self.state_mut().select(Some(row.min(last)));
+                }
+                let name = self.save_path.file_name().unwrap_or_default().to_string_lossy();
+                tracing::info!("reloaded {}, which changed on disk", self.save_path.display());
+                self.notice = Some(self.trf("Reloaded {} - it changed on disk", &[&name]));
+            }
+            Err(err) => {
+                tracing::error!("{}", err);
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// Starts keeping the log of the todo file, see `oplog`, once this is the
+    /// instance that saves it
+    /// The log merges JSON, so todo.txt, Markdown and org files go without,
+    /// and an encrypted file would leave its todos readable in the log
+    fn open_log(&mut self) {
+        if self.oplog.is_some() || self.cipher.is_some() || Format::from_path(&self.save_path) != Format::Json {
+            return;
+        }
+        let mut log = match OpLog::open(&self.save_path) {
+            Ok(log) => log,
+            Err(err) => {
+                tracing::warn!("{}", err);
+                return;
+            }
+        };
+        let mut lists = self.lists.clone();
+        give_ids(&mut lists);
+        // Changes to the log made while this instance was away are in the
+        // lists from here on
+        match log.merge(None, &lists, &self.heads) {
+            Ok(merged) => {
+                if !same_lists(&merged, &lists) {
+                    self.adopt_merged(merged);
+                    self.autosave(false);
+                }
+                self.oplog = Some(Arc::new(Mutex::new(log)));
+            }
+            Err(err) => tracing::warn!("not keeping a change log for {}: {}", self.save_path.display(), err),
+        }
+    }
+
+    /// Merges the todo file, changed on disk, with this session's changes
+    /// through the log, saving the result when it differs from the file
+    /// Returns false when it can't, to ask the user as without a log
+    fn merge_changes(&mut self) -> bool {
+        let Some(log) = self.oplog.clone() else {
+            return false;
+        };
+        let stamp = FileStamp::of(&self.save_path);
+        let SaveFile { lists: mut file, heads, .. } = match self.read_todo_file() {
+            Ok(Some(file)) => file,
+            Ok(None) => return false,
+            Err(err) => {
+                tracing::warn!("{}", err);
+                return false;
+            }
+        };
+        give_ids(&mut file);
+        let ours = self.unsaved.is_some().then(|| {
+            give_ids(&mut self.lists);
+            self.lists.clone()
+        });
+        let merged = log.lock().unwrap_or_else(|err| err.into_inner()).merge(ours.as_deref(), &file, &heads);
+        let merged = match merged {
+            Ok(merged) => merged,
+            Err(err) => {
+                tracing::warn!("can't merge {}: {}", self.save_path.display(), err);
+                return false;
+            }
+        };
+        self.disk_stamp = stamp;
+        // Whoever changed the file commits their own changes
+        self.changes.clear();
+        if !same_lists(&merged, &file) {
+            self.autosave(false);
+        }
+        if !same_lists(&merged, &self.lists) {
+            self.adopt_merged(merged);
+        }
+        let name = self.save_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        tracing::info!("merged {}, which changed on disk", self.save_path.display());
+        self.notice = Some(self.trf("Merged the changes made to {} elsewhere", &[&name]));
+        true
+    }
+
+    /// Takes merged lists in place of this session's, keeping each list's
+    /// selection as far as it goes
+    /// Undo history refers to the old lists, so it goes
+    fn adopt_merged(&mut self, mut merged: Vec<TodoList>) {
+        for (list, old) in merged.iter_mut().zip(&self.lists) {
+            list.state = old.state.clone();
+        }
+        self.lists = merged;
+        self.current = self.current.min(self.lists.len() - 1);
+        self.forget_history();
+        self.refresh_view();
+        if let Some(row) = self.state().selected() {
+            let row = (!self.visible.is_empty()).then(|| row.min(self.visible.len() - 1));
+            self.state_mut().select(row);
+        }
+    }
+
+    /// Answers "no" to the reload prompt: the file's new contents are
+    /// accepted as seen, and the next save writes this session's lists over them
+    pub(crate) fn keep_local_changes(&mut self) {
+        self.disk_stamp = FileStamp::of(&self.save_path);
+        let name = self.save_path.file_name().unwrap_or_default().to_string_lossy();
+        self.notice = Some(self.trf("Kept your changes - they'll be saved over {}", &[&name]));
+    }
+
+    /// Hands a snapshot of the unsaved data to the background saver
+    fn start_save(&mut self) {
+        let Some(unsaved) = self.unsaved.take() else {
+            return;
+        };
+        match &self.oplog {
+            Some(log) => {
+                give_ids(&mut self.lists);
+                self.saver.send(SaveJob::Logged(self.save_path.clone(), self.lists.clone(), log.clone()));
+            }
+            None => self.saver.send(SaveJob::Lists(self.save_path.clone(), self.lists.clone(), self.heads.clone(), self.cipher.clone())),
+        }
+        if unsaved.archive {
+            self.saver.send(SaveJob::Archive(self.archive_path(), self.archive.clone(), self.cipher.clone()));
+        }
+        if self.git == GitMode::Save && !self.changes.is_empty() {
+            let message = history::commit_message(&self.changes);
+            self.changes.clear();
+            self.saver.send(SaveJob::Commit(self.save_path.clone(), message));
+        }
+    }
+
+    /// Commits the todo file to git with the changes made since the last
+    /// commit, when the config asks for it
+    /// The command line calls this after saving; the interface does it in the
+    /// background after each save, or here on the way out
+    pub fn commit_changes(&mut self) -> Result<(), AppError> {
+        if self.git == GitMode::Off || self.saving_disabled || self.changes.is_empty() {
+            return Ok(());
+        }
+        let message = history::commit_message(&self.changes);
+        self.changes.clear();
+        history::commit(&self.save_path, &message)
+    }
+
+    /// Watches the todo file for changes made outside the app, falling back
+    /// to polling it where the platform or filesystem can't be watched
+    pub(crate) fn watch_file(&mut self) {
+        match DiskWatcher::new(&self.save_path) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(err) => tracing::warn!("can't watch {}, polling it instead: {}", self.save_path.display(), err),
+        }
+    }
+
+    /// Makes this the instance that saves the todo file, or else opens it
+    /// read-only until the instance that has it quits
+    /// Only the interface locks - the command line subcommands write once and
+    /// leave, and a running interface reloads what they wrote
+    pub(crate) fn acquire_lock(&mut self) {
+        let name = self.save_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        match try_lock(&self.save_path) {
+            Ok(Lock::Held(file)) => {
+                self.lock = Some(file);
+                self.open_log();
+                if self.read_only {
+                    self.read_only = false;
+                    self.notice = Some(self.trf("The other instance closed {} - changes are saved again", &[&name]));
+                }
+            }
+            Ok(Lock::Taken(pid)) if !self.read_only => {
+                self.read_only = true;
+                let owner = pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+                self.notice = Some(self.trf("{} is open in another instance{} - read-only", &[&name, &owner]));
+            }
+            Ok(Lock::Taken(_)) => {}
+            // A filesystem without locks shouldn't keep anyone from their todos
+            Err(_) => {}
+        }
+    }
+
+    /// Snapshots of the todo file, newest first
+    /// Their names end in a timestamp, so sorting by name sorts by age
+    pub fn snapshots(&self) -> Vec<PathBuf> {
+        let (stem, extension) = snapshot_name_parts(&self.save_path);
+        let prefix = format!("{}-", stem);
+        let Ok(entries) = fs::read_dir(backup_dir(&self.save_path)) else {
+            return Vec::new();
+        };
+        let mut snapshots: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.starts_with(&prefix) && name.ends_with(&extension)
+            })
+            .collect();
+        snapshots.sort();
+        snapshots.reverse();
+        snapshots
+    }
+
+    /// Copies the todo file to a new snapshot and deletes the oldest ones
+    /// beyond the number the config keeps
+    /// Returns None when there's nothing to keep - no file yet, or backups
+    /// turned off
+    pub fn take_snapshot(&self) -> io::Result<Option<PathBuf>> {
+        if self.backups == 0 || !self.save_path.exists() {
+            return Ok(None);
+        }
+        let snapshot = snapshot_file(&self.save_path)?;
+        for old in self.snapshots().iter().skip(self.backups) {
+            fs::remove_file(old)?;
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Takes the session's snapshot, the first time anything changes
+    /// Taking it at startup would fill the backups with copies of sessions
+    /// that only looked
+    fn snapshot_before_first_change(&mut self) {
+        if self.snapshot_taken {
+            return;
+        }
+        self.snapshot_taken = true;
+        if let Err(source) = self.take_snapshot() {
+            let err = AppError::Write { path: backup_dir(&self.save_path), source };
+            tracing::error!("{}", err);
+            self.error = Some(err);
+        }
+    }
+
+    /// Reads the lists in snapshot `number`, 1 being the newest
+    pub fn read_snapshot(&self, number: usize) -> Result<(PathBuf, Vec<TodoList>), String> {
+        let snapshots = self.snapshots();
+        let path = number
+            .checked_sub(1)
+            .and_then(|index| snapshots.get(index))
+            .ok_or_else(|| match snapshots.len() {
+                0 => "there are no snapshots yet".to_string(),
+                n => format!("no snapshot {} (1 to {})", number, n),
+            })?;
+        let contents = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        // Snapshots of an encrypted file are encrypted too
+        let contents = unseal(path, contents, self.cipher.as_ref()).map_err(|err| err.to_string())?;
+        let lists = Format::from_path(path)
+            .read(&contents)
+            .map_err(|err| format!("could not parse {}: {}", path.display(), err))?;
+        if lists.is_empty() {
+            return Err(format!("{} has no lists", path.display()));
+        }
+        Ok((path.clone(), lists))
+    }
+
+    /// Replaces every list with snapshot `number`, as one change that undo
+    /// takes back - and that's itself snapshotted like any other change
+    pub(crate) fn restore_snapshot(&mut self, number: usize) -> Result<PathBuf, String> {
+        if self.read_only {
+            return Err("read-only - the file is open in another instance".to_string());
+        }
+        let (path, after) = self.read_snapshot(number)?;
+        let before = self.lists.clone();
+        self.execute(Command::SetLists { before, after });
+        Ok(path)
+    }
+
+    /// Whether there are changes that aren't on disk yet
+    pub(crate) fn is_saving(&self) -> bool {
+        self.unsaved.is_some() || self.saver.busy()
+    }
+
+    /// Saves whatever is still unsaved and waits until it's on disk
+    /// Called on the way out, so quitting right after a change loses nothing
+    pub fn flush(&mut self) -> Result<(), AppError> {
+        // A running sync may have created tasks that need linking here
+        self.wait_for_sync();
+        self.start_save();
+        let mut result = Ok(());
+        for finished in self.saver.finish() {
+            if let Err(err) = finished {
+                result = Err(err);
+            }
+        }
+        // A file that didn't save mustn't be committed as if it had
+        result?;
+        self.commit_changes()
+    }
+
+    /// Loads todos from disk, or starts with an empty list on first run
+    /// A file that can't be read or parsed is an error rather than being
+    /// quietly replaced - the next save would overwrite whatever was in it
+    pub fn load(save_path: PathBuf) -> Result<App, AppError> {
+        let mut app = App::new(save_path);
+        app.read_files()?;
+        Ok(app)
+    }
+
+    /// Replaces the lists and archive with what's on disk
+    /// Files that don't exist leave what's there, so first run keeps the
+    /// empty list
+    fn read_files(&mut self) -> Result<(), AppError> {
+        let (lists, heads) = self.read_todo_file()?.map(|file| (file.lists, file.heads)).unzip();
+
+        // The archive is saved alongside the todos, so it gets the same care
+        let archive_path = self.archive_path();
+        let archive = match self.read_contents(&archive_path)? {
+            Some(contents) => {
+                // It has no version, so old link fields are moved wherever
+                // they turn up
+                let mut value: Value =
+                    serde_json::from_str(&contents).map_err(|source| AppError::Parse { path: archive_path.clone(), source })?;
+                for entry in value.get_mut("archived").and_then(Value::as_array_mut).into_iter().flatten() {
+                    if let Some(todo) = entry.get_mut("todo") {
+                        move_todo_remote_ids(todo);
+                    }
+                }
+                let mut archived = serde_json::from_value::<ArchiveFile>(value)
+                    .map_err(|source| AppError::Parse { path: archive_path, source })?
+                    .archived;
+                archived.iter_mut().for_each(|entry| split_archived_contexts(&mut entry.todo));
+                Some(archived)
+            }
+            None => None,
+        };
+
+        // Nothing changes until both files are read, so a failure leaves
+        // the app as it was
+        // An empty file has no lists, and the app always needs one
+        if let Some(lists) = lists.filter(|lists| !lists.is_empty()) {
+            self.lists = lists;
+            // Ensure selection is valid for loaded todos
+            for list in &mut self.lists {
+                list.state.select(if list.todos.is_empty() { None } else { Some(0) });
+            }
+        }
+        if let Some(archive) = archive {
+            self.archive = archive;
+        }
+        self.heads = heads.unwrap_or_default();
+        // The file may have fewer lists than the one being looked at
+        self.current = self.current.min(self.lists.len() - 1);
+        self.disk_stamp = FileStamp::of(&self.save_path);
+        self.refresh_view();
+        Ok(())
+    }
+
+    /// Reads and parses the todo file, along with the changes of the log it
+    /// says it's from whatever its format
+    fn read_todo_file(&self) -> Result<Option<SaveFile>, AppError> {
+        let Some(contents) = self.read_contents(&self.save_path)? else {
+            return Ok(None);
+        };
+        let format = Format::from_path(&self.save_path);
+        let lists = format.read(&contents).map_err(|source| AppError::Parse { path: self.save_path.clone(), source })?;
+        let heads = if format == Format::Json { save_file_heads(&contents) } else { Vec::new() };
+        Ok(Some(SaveFile { version: SAVE_VERSION, lists, heads }))
+    }
+
+    /// Reads one of the app's files, decrypting it if it's encrypted
+    fn read_contents(&self, path: &Path) -> Result<Option<String>, AppError> {
+        match read_file(path)? {
+            Some(contents) => unseal(path, contents, self.cipher.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads an encrypted todo file with the passphrase from the command line
+    pub fn load_encrypted(save_path: PathBuf, passphrase: &str) -> Result<App, AppError> {
+        let mut app = App::new(save_path);
+        app.unlock(passphrase)?;
+        Ok(app)
+    }
+
+    /// Derives the key for the todo file from `passphrase` and loads the
+    /// files with it
+    /// The key comes from the todo file's own salt, so a wrong passphrase
+    /// fails here rather than producing garbage
+    pub(crate) fn unlock(&mut self, passphrase: &str) -> Result<(), AppError> {
+        let path = self.save_path.clone();
+        let contents = read_file(&path)?.unwrap_or_default();
+        let envelope = Envelope::parse(&contents).ok_or_else(|| AppError::Decrypt { path: path.clone() })?;
+        envelope.check().map_err(|message| AppError::Encryption { path: path.clone(), message })?;
+        let cipher = Cipher::for_envelope(passphrase, &envelope).ok_or(AppError::Decrypt { path })?;
+        self.cipher = Some(cipher);
+        let result = self.read_files();
+        if result.is_err() {
+            self.cipher = None;
+        }
+        result
+    }
+
+    /// Unlocks from the passphrase dialog shown at startup
+    /// A wrong passphrase keeps the dialog open to try again
+    pub(crate) fn submit_passphrase(&mut self) {
+        let passphrase = std::mem::take(&mut self.input);
+        match self.unlock(&passphrase) {
+            Ok(()) => {
+                self.saving_disabled = false;
+                self.input_error = None;
+                self.mode = Mode::Normal;
+            }
+            Err(AppError::Decrypt { .. }) => self.input_error = Some("wrong passphrase".to_string()),
+            // Decrypted fine but something else is wrong - nothing gets saved,
+            // same as any other file that doesn't load
+            Err(error) => {
+                self.input_error = None;
+                self.error = Some(error);
+                self.mode = Mode::Normal;
+            }
+        }
+    }
+
+    /// Opens the prompt for a new passphrase, the first step of `:encrypt`
+    pub(crate) fn start_encrypt(&mut self) -> Result<(), String> {
+        if self.read_only || self.saving_disabled {
+            return Err("the todo file isn't being saved right now".to_string());
+        }
+        if Format::from_path(&self.save_path) != Format::Json {
+            return Err("only JSON todo files can be encrypted".to_string());
+        }
+        self.input_target = InputTarget::Passphrase(None);
+        self.input.clear();
+        self.mode = Mode::Input;
+        Ok(())
+    }
+
+    /// Takes the passphrase typed at the `:encrypt` prompt - the first time
+    /// asks for it again, the second time encrypts if both match
+    pub(crate) fn apply_passphrase(&mut self, first: Option<String>) {
+        let passphrase = std::mem::take(&mut self.input);
+        let Some(first) = first else {
+            if passphrase.is_empty() {
+                self.input_error = Some("the passphrase can't be empty".to_string());
+                return;
+            }
+            self.input_target = InputTarget::Passphrase(Some(passphrase));
+            return;
+        };
+        if passphrase != first {
+            self.input_target = InputTarget::Passphrase(None);
+            self.input_error = Some("the passphrases didn't match - type a new one".to_string());
+            return;
+        }
+        self.cancel_input();
+        match Cipher::new(&passphrase) {
+            Ok(cipher) => {
+                self.cipher = Some(cipher);
+                // The log would go on keeping the todos readable
+                self.oplog = None;
+                self.autosave(true);
+                let name = self.save_path.file_name().unwrap_or_default().to_string_lossy();
+                self.notice = Some(self.trf("{} is saved encrypted now - a lost passphrase can't be recovered", &[&name]));
+            }
+            Err(err) => self.notice = Some(self.trf("could not encrypt: {}", &[&err])),
+        }
+    }
+
+    /// Saves the files as plain JSON again, for `:decrypt`
+    pub(crate) fn decrypt(&mut self) -> Result<(), String> {
+        if self.cipher.is_none() {
+            return Err("the todo file isn't encrypted".to_string());
+        }
+        if self.read_only || self.saving_disabled {
+            return Err("the todo file isn't being saved right now".to_string());
+        }
+        self.cipher = None;
+        if self.lock.is_some() {
+            self.open_log();
+        }
+        self.autosave(true);
+        let name = self.save_path.file_name().unwrap_or_default().to_string_lossy();
+        self.notice = Some(self.trf("{} is saved unencrypted now", &[&name]));
+        Ok(())
+    }
+
+    /// Starts the interface with an empty list after `load` failed
+    /// Nothing is saved so the file stays as it was for the user to fix -
+    /// a broken file is copied aside and the recovery dialog explains the
+    /// way out, other errors are shown in the error bar
+    pub fn load_failed(save_path: PathBuf, error: AppError) -> App {
+        let mut app = App::new(save_path);
+        app.lists = vec![TodoList::new("Todos")];
+        app.saving_disabled = true;
+        if let AppError::Locked { .. } = &error {
+            // Unlocking loads the files and turns saving back on
+            app.mode = Mode::Unlock;
+        } else if let AppError::Parse { path, .. } = &error {
+            // A copy is kept however the user resolves this, starting fresh included
+            let backup = back_up_corrupt(path);
+            app.recovery = Some(Recovery::new(error, backup));
+            app.mode = Mode::Recovery;
+        } else {
+            app.error = Some(error);
+        }
+        if let Some(error) = app.error.as_ref().or(app.recovery.as_ref().map(|recovery| &recovery.error)) {
+            tracing::error!("{}", error);
+        }
+        app.refresh_view();
+        app
+    }
+
+    /// Loads the files again from the recovery dialog, after the user fixed
+    /// the broken one - a new error just updates the dialog
+    pub(crate) fn retry_load(&mut self) {
+        let Some(recovery) = self.recovery.take() else {
+            return;
+        };
+        match self.read_files() {
+            Ok(()) => {
+                self.saving_disabled = false;
+                self.mode = Mode::Normal;
+                self.notice = Some(self.trf("Loaded {}", &[&self.save_path.display()]));
+            }
+            // Fixing the todo file can bring up a broken archive, which needs
+            // a copy of its own
+            Err(error) => {
+                let backup = if error.path() == recovery.error.path() {
+                    recovery.backup
+                } else {
+                    back_up_corrupt(error.path())
+                };
+                self.recovery = Some(Recovery::new(error, backup));
+            }
+        }
+    }
+
+    /// Moves the broken file out of the way and loads without it
+    /// Only offered once the backup exists, since the file is gone afterwards
+    pub(crate) fn start_fresh(&mut self) {
+        let Some(recovery) = &mut self.recovery else {
+            return;
+        };
+        if recovery.backup.is_err() {
+            return;
+        }
+        let path = recovery.error.path().clone();
+        if let Err(source) = fs::remove_file(&path) {
+            recovery.error = AppError::Write { path, source };
+            recovery.line = None;
+            return;
+        }
+        self.retry_load();
+    }
+
+    /// Path of the archive file, next to the todo file
+    fn archive_path(&self) -> PathBuf {
+        self.save_path.with_file_name("archive.json")
+    }
+
+
+    /// Renders every list in one of `EXPORT_FORMATS`, along with the
+    /// extension its file gets
+    pub fn export_contents(&self, format: &str) -> Result<(String, &'static str), String> {
+        Ok(match format {
+            "json" => {
+                let file = SaveFile { version: SAVE_VERSION, lists: self.lists.clone(), heads: Vec::new() };
+                (serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?, "json")
+            }
+            "txt" | "todotxt" => (write_todo_txt(&self.lists), "txt"),
+            "md" | "markdown" => (write_markdown_report(&self.lists), "md"),
+            "csv" => (write_csv(&self.lists), "csv"),
+            "ics" | "ical" => (write_ics(&self.lists), "ics"),
+            _ => return Err(format!("unknown format '{}' (expected {})", format, EXPORT_FORMATS.join(", "))),
+        })
+    }
+
+    /// Writes every list to `path` in one of `EXPORT_FORMATS`
+    /// Without a path the file goes to the current directory
+    pub fn export(&self, format: &str, path: &str) -> Result<PathBuf, String> {
+        let (contents, extension) = self.export_contents(format)?;
+        let path = if path.is_empty() { PathBuf::from(format!("todo-export.{}", extension)) } else { PathBuf::from(path) };
+        fs::write(&path, contents).map_err(|err| format!("could not write {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+
+    /// Restores the view settings saved at `path` and saves future changes there
+    pub fn restore_view_state(&mut self, path: PathBuf) {
+        let state = ViewState::load(&path);
+        self.sort_mode = state.sort_mode;
+        self.hide_completed = state.hide_completed;
+        self.show_unstarted = state.show_unstarted;
+        self.group_by_project = state.group_by_project;
+        self.saved_filters = state.filters;
+        self.tag_styles = state.tags;
+        self.command_history = state.command_history;
+        self.welcomed = state.welcomed;
+        self.reviewed = state.reviewed;
+        self.view_state_path = Some(path);
+        // Only someone without any todos yet needs the introduction - people
+        // whose view state predates the popup have long found their way
+        if !self.welcomed && self.mode == Mode::Normal && self.lists.iter().all(|list| list.todos.is_empty()) {
+            self.mode = Mode::Welcome;
+        }
+        self.refresh_view();
+    }
+
+    /// Persists the view settings, if there's somewhere to put them
+    /// Failures go to the error bar like for the todo file - losing a sort
+    /// order isn't worth interrupting anything for, but it's worth knowing
+    pub(crate) fn save_view_state(&mut self) {
+        if let Some(path) = &self.view_state_path {
+            let state = ViewState {
+                sort_mode: self.sort_mode,
+                hide_completed: self.hide_completed,
+                show_unstarted: self.show_unstarted,
+                group_by_project: self.group_by_project,
+                filters: self.saved_filters.clone(),
+                tags: self.tag_styles.clone(),
+                command_history: self.command_history.clone(),
+                welcomed: self.welcomed,
+                reviewed: self.reviewed,
+            };
+            if let Err(err) = state.save(path) {
+                tracing::error!("{}", err);
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{
+        collection::vec,
+        option::weighted,
+        prelude::*,
+        sample::select,
+        strategy::ValueTree,
+        test_runner::TestRunner,
+    };
+    use std::{sync::atomic::{AtomicBool, Ordering}, sync::Arc, thread};
+
+    /// A fresh, empty directory for one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("todo-tui-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// An app saving to `path` with `count` todos in its only list
+    fn app_with_todos(path: &Path, count: usize) -> App {
+        let mut app = App::new(path.to_path_buf());
+        app.lists = vec![TodoList::new("Todos")];
+        for i in 0..count {
+            app.lists[0].todos.push(TodoItem::new(&format!("todo {}", i)));
+        }
+        app
+    }
+
+    fn texts(app: &App) -> Vec<String> {
+        app.lists[0].todos.iter().map(|todo| todo.text.clone()).collect()
+    }
+
+    #[test]
+    fn files_from_before_the_windows_folders_stay_where_they_are() {
+        let dir = test_dir("settled");
+        let app = dir.join("todo-tui");
+        let data = app.join("data");
+        // Elsewhere, and on a fresh Windows install, the folder is the one asked for
+        assert_eq!(settled(&data, false), data);
+        assert_eq!(settled(&data, true), data);
+        // A config folder made first doesn't count as files of the old layout
+        fs::create_dir_all(app.join("config")).unwrap();
+        assert_eq!(settled(&data, true), data);
+        // The todo file straight in %APPDATA%\todo-tui keeps being used
+        fs::write(app.join("todos.json"), "[]").unwrap();
+        assert_eq!(settled(&data, true), app);
+        assert_eq!(settled(&data, false), data);
+        // Until the new folder exists
+        fs::create_dir_all(&data).unwrap();
+        assert_eq!(settled(&data, true), data);
+        assert!(data_dir().is_none_or(|dir| dir.ends_with("todo-tui")));
+    }
+
+    #[test]
+    fn save_leaves_no_temp_files() {
+        let dir = test_dir("no-temp");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 3).save().unwrap();
+        app_with_todos(&path, 5).save().unwrap();
+
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec!["todos.json"]);
+        assert_eq!(texts(&App::load(path).unwrap()).len(), 5);
+    }
+
+    #[test]
+    fn failed_save_keeps_old_contents() {
+        let dir = test_dir("failed-save");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 2).save().unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        // A directory where the temp file should go makes the write fail
+        // before the target is touched
+        fs::create_dir(temp_path(&path)).unwrap();
+        let err = app_with_todos(&path, 7).save().unwrap_err();
+        assert!(matches!(err, AppError::Write { .. }));
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn crash_before_rename_keeps_old_contents() {
+        let dir = test_dir("crash");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 2).save().unwrap();
+        let new = Format::Json.write(&app_with_todos(&path, 9).lists, &path).unwrap();
+
+        // A crash can stop the temp file at any length - whatever was
+        // written, the todo file itself still loads as it was
+        for cut in (0..new.len()).step_by(7) {
+            fs::write(temp_path(&path), &new[..cut]).unwrap();
+            assert_eq!(texts(&App::load(path.clone()).unwrap()), vec!["todo 0", "todo 1"]);
+        }
+
+        // And the leftover temp file doesn't get in the way of the next save
+        app_with_todos(&path, 4).save().unwrap();
+        assert_eq!(texts(&App::load(path).unwrap()).len(), 4);
+    }
+
+    #[test]
+    fn readers_never_see_a_partial_file() {
+        let dir = test_dir("readers");
+        let path = dir.join("todos.json");
+        // Large enough that a plain write would be caught halfway
+        let small = app_with_todos(&path, 10);
+        let large = app_with_todos(&path, 500);
+        small.save().unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (path, done) = (path.clone(), done.clone());
+            thread::spawn(move || {
+                let mut reads = 0;
+                while !done.load(Ordering::Relaxed) || reads < 20 {
+                    let contents = fs::read_to_string(&path).unwrap();
+                    let lists = Format::Json.read(&contents).expect("saw a partial file");
+                    assert!(matches!(lists[0].todos.len(), 10 | 500));
+                    reads += 1;
+                }
+            })
+        };
+        for i in 0..40 {
+            if i % 2 == 0 { large.save().unwrap() } else { small.save().unwrap() }
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn save_round_trips_generated_lists() {
+        let dir = test_dir("round-trip");
+        let path = dir.join("todos.json");
+        // A tiny linear congruential generator keeps the fuzzing repeatable
+        // without a dependency
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |max: u64| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) % max
+        };
+        let alphabet: Vec<char> = "abc xyz \"\\\n\t{}[],:é✓🍅".chars().collect();
+        for _ in 0..50 {
+            let mut app = app_with_todos(&path, 0);
+            for _ in 0..next(20) {
+                let text: String = (0..next(40)).map(|_| alphabet[next(alphabet.len() as u64) as usize]).collect();
+                app.lists[0].todos.push(TodoItem::new(&text));
+            }
+            app.save().unwrap();
+            assert_eq!(texts(&App::load(path.clone()).unwrap()), texts(&app));
+        }
+    }
+
+    #[test]
+    fn autosave_waits_for_a_pause_and_flushes() {
+        let dir = test_dir("autosave");
+        let path = dir.join("todos.json");
+        let mut app = app_with_todos(&path, 1);
+
+        app.autosave(true);
+        app.tick_saving();
+        assert!(app.is_saving());
+        assert!(!path.exists());
+
+        // Quitting right after the change still writes it
+        app.flush().unwrap();
+        assert!(!app.is_saving());
+        assert_eq!(texts(&App::load(path).unwrap()), vec!["todo 0"]);
+        assert!(dir.join("archive.json").exists());
+    }
+
+    #[test]
+    fn outside_changes_reload_or_ask() {
+        let dir = test_dir("watch");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 2).save().unwrap();
+        let mut app = App::load(path.clone()).unwrap();
+        let check_now = |app: &mut App| {
+            app.last_disk_check = Instant::now() - DISK_CHECK_INTERVAL;
+            app.tick_saving();
+        };
+
+        // Nothing unsaved: the new contents just show up
+        app_with_todos(&path, 3).save().unwrap();
+        check_now(&mut app);
+        assert_eq!(texts(&app).len(), 3);
+
+        // Unsaved changes: the user is asked, and nothing is written meanwhile
+        app.autosave(false);
+        app_with_todos(&path, 5).save().unwrap();
+        check_now(&mut app);
+        assert!(app.pending == Some(Confirm::Reload));
+        app.unsaved.as_mut().unwrap().first -= SAVE_MAX_DELAY;
+        check_now(&mut app);
+        assert!(!app.saver.busy());
+
+        // Keeping ours saves over the file
+        app.resolve_confirm(false);
+        check_now(&mut app);
+        app.flush().unwrap();
+        assert_eq!(texts(&App::load(path).unwrap()).len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "crdt")]
+    fn outside_changes_merge_through_the_log() {
+        let dir = test_dir("oplog");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 2).save().unwrap();
+        let mut app = App::load(path.clone()).unwrap();
+        app.acquire_lock();
+        assert!(app.oplog.is_some());
+        let check_now = |app: &mut App| {
+            app.last_disk_check = Instant::now() - DISK_CHECK_INTERVAL;
+            app.tick_saving();
+        };
+        app.lists[0].todos[0].text = "todo 0 here".to_string();
+        app.autosave(false);
+        app.flush().unwrap();
+
+        // The command line edits the file while the interface has a change
+        // of its own unsaved
+        let mut cli = App::load(path.clone()).unwrap();
+        cli.lists[0].todos[1].text = "todo 1 there".to_string();
+        cli.lists[0].todos.push(TodoItem::new("todo 2"));
+        app.lists[0].todos[0].status = Status::Done;
+        app.autosave(false);
+        cli.save().unwrap();
+
+        // Both stay, without asking
+        check_now(&mut app);
+        assert!(app.pending.is_none());
+        assert_eq!(texts(&app), ["todo 0 here", "todo 1 there", "todo 2"]);
+        assert_eq!(app.lists[0].todos[0].status, Status::Done);
+        app.flush().unwrap();
+        assert_eq!(texts(&App::load(path).unwrap()), ["todo 0 here", "todo 1 there", "todo 2"]);
+        assert!(dir.join("todos.changes").read_dir().unwrap().count() > 0);
+    }
+
+    #[test]
+    fn outside_changes_are_seen_without_polling() {
+        let dir = test_dir("watcher");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 1).save().unwrap();
+        let mut app = App::load(path.clone()).unwrap();
+        app.watch_file();
+        assert!(app.watcher.is_some());
+
+        // The poll isn't due for a while, so only the watcher can notice
+        app_with_todos(&path, 2).save().unwrap();
+        let waited = Instant::now();
+        while texts(&app).len() != 2 && waited.elapsed() < Duration::from_secs(5) {
+            app.last_disk_check = Instant::now();
+            app.tick_saving();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(texts(&app).len(), 2);
+
+        // Other files in the folder aren't the todo file
+        thread::sleep(Duration::from_millis(100));
+        app.watcher.as_ref().unwrap().take();
+        fs::write(dir.join("notes.txt"), "x").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!app.watcher.as_ref().unwrap().take());
+    }
+
+    #[test]
+    fn second_instance_is_read_only_until_the_first_quits() {
+        let dir = test_dir("lock");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 1).save().unwrap();
+        let mut first = App::load(path.clone()).unwrap();
+        let mut second = App::load(path.clone()).unwrap();
+        first.acquire_lock();
+        second.acquire_lock();
+        assert!(!first.read_only);
+        assert!(second.read_only);
+        assert!(second.notice.as_ref().unwrap().contains(&std::process::id().to_string()));
+
+        // Changes are refused rather than kept where they'd never be saved
+        second.add("not saved");
+        assert_eq!(texts(&second).len(), 1);
+
+        // Once the first instance is gone, the next check takes over
+        drop(first);
+        second.last_disk_check = Instant::now() - DISK_CHECK_INTERVAL;
+        second.tick_saving();
+        assert!(!second.read_only);
+        second.add("saved");
+        second.flush().unwrap();
+        assert_eq!(texts(&App::load(path).unwrap()).len(), 2);
+    }
+
+    #[test]
+    fn old_save_files_are_migrated() {
+        // Before multiple lists: a bare array, with the old `completed` bool
+        let lists = Format::Json.read(r#"[{"text": "old", "completed": true}]"#).unwrap();
+        assert_eq!(lists[0].name, "Todos");
+        assert_eq!(lists[0].todos[0].status, Status::Done);
+
+        // Lists, but from before versions were written
+        let lists = Format::Json.read(r#"{"lists": [{"name": "Work", "todos": [{"text": "a"}]}]}"#).unwrap();
+        assert_eq!(lists[0].name, "Work");
+
+        // Contexts from when they were tags starting with '@', subtasks' too
+        let old = r#"{"version": 1, "lists": [{"name": "Work", "todos": [
+            {"text": "call", "tags": ["work", "@phone"], "children": [
+                {"text": "dial", "tags": ["@"]},
+                {"text": "x", "tags": ["@home"]}
+            ]}
+        ]}]}"#;
+        let todo = &Format::Json.read(old).unwrap()[0].todos[0];
+        assert_eq!(todo.tags, ["work"]);
+        assert_eq!(todo.contexts, ["phone"]);
+        assert_eq!((todo.children[0].tags.as_slice(), todo.children[0].contexts.len()), (&["@".to_string()][..], 0));
+        assert_eq!(todo.children[1].contexts, ["home"]);
+
+        // Links from when each service had a field of its own, in the trash too
+        let old = r#"{"version": 2, "lists": [{"name": "Work", "todoist_id": "p", "caldav_url": "/cal/", "todos": [
+            {"text": "a", "todoist_id": "1", "uid": "u", "children": [{"text": "b", "google_id": "g"}]}
+        ], "trash": [{"text": "c", "caldav_uid": "c@x"}]}]}"#;
+        let list = &Format::Json.read(old).unwrap()[0];
+        let ids = |ids: &BTreeMap<String, String>| ids.iter().map(|(key, id)| format!("{}={}", key, id)).collect::<Vec<_>>();
+        assert_eq!(ids(&list.remote_ids), ["caldav=/cal/", "todoist=p"]);
+        assert_eq!(ids(&list.todos[0].remote_ids), ["server=u", "todoist=1"]);
+        assert_eq!(ids(&list.todos[0].children[0].remote_ids), ["google=g"]);
+        assert_eq!(ids(&list.trash[0].remote_ids), ["caldav=c@x"]);
+
+        // Saving writes the current version, which reads back as it is
+        let written = Format::Json.write(&lists, Path::new("todos.json")).unwrap();
+        let value: Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(value["version"], json!(SAVE_VERSION));
+        assert_eq!(Format::Json.read(&written).unwrap()[0].todos[0].text, "a");
+    }
+
+    #[test]
+    fn newer_save_files_are_refused() {
+        let newer = format!(r#"{{"version": {}, "lists": []}}"#, SAVE_VERSION + 1);
+        let err = Format::Json.read(&newer).err().unwrap();
+        assert!(err.to_string().contains("newer version"));
+    }
+
+    #[test]
+    fn current_save_file_errors_have_a_location() {
+        let contents = format!("{{\n  \"version\": {},\n  \"lists\": [{{\"name\": 3}}]\n}}", SAVE_VERSION);
+        let err = Format::Json.read(&contents).err().unwrap();
+        assert_eq!(err.line(), 3);
+    }
+
+    #[test]
+    fn snapshots_are_taken_once_per_session_and_restored() {
+        let dir = test_dir("snapshots");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 1).save().unwrap();
+
+        // Older snapshots beyond the configured number are dropped
+        fs::create_dir_all(backup_dir(&path)).unwrap();
+        for day in 1..=3 {
+            fs::copy(&path, backup_dir(&path).join(format!("todos-2020-01-0{}-120000.json", day))).unwrap();
+        }
+        let mut app = App::load(path.clone()).unwrap();
+        app.backups = 2;
+        app.add("second");
+        app.add("third");
+        app.flush().unwrap();
+        let snapshots = app.snapshots();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshot_time(&path, &snapshots[1]), "2020-01-03-120000");
+
+        // The newest is the file as it was before this session changed it
+        app.restore_snapshot(1).unwrap();
+        assert_eq!(texts(&app), vec!["todo 0"]);
+        app.undo();
+        assert_eq!(texts(&app), vec!["todo 0", "second", "third"]);
+        assert!(app.restore_snapshot(3).is_err());
+    }
+
+    #[test]
+    fn ics_export_escapes_folds_and_links_subtasks() {
+        let mut list = TodoList::new("Home");
+        let mut todo = TodoItem::new("Call mom, about; the party");
+        todo.due = Date::new(2024, 5, 1);
+        todo.priority = Priority::High;
+        todo.tags = vec!["family".to_string()];
+        todo.notes = "x".repeat(100);
+        todo.children.push(TodoItem::new("Buy cake"));
+        list.todos.push(todo);
+        let ics = write_ics(&[list]);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n") && ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.lines().all(|line| line.trim_end_matches('\r').len() <= 75));
+        // Unfolding gives back the whole description
+        let unfolded = ics.replace("\r\n ", "");
+        for line in [
+            "SUMMARY:Call mom\\, about\\; the party",
+            "DUE;VALUE=DATE:20240501",
+            "PRIORITY:1",
+            "CATEGORIES:Home,family",
+            &format!("DESCRIPTION:{}", "x".repeat(100)),
+            "UID:Home-1.1@todo-tui",
+            "RELATED-TO:Home-1@todo-tui",
+        ] {
+            assert!(unfolded.lines().any(|l| l.trim_end_matches('\r') == line), "missing {}", line);
+        }
+    }
+
+    #[test]
+    fn org_files_keep_their_other_text() {
+        let file = "#+TITLE: Notes\n#+TODO: TODO NEXT | DONE\n\n* Work :job:\nSome prose.\n** NEXT [#A] Review PR :code:review:\n   DEADLINE: <2024-05-03 Fri 17:00 +1w> SCHEDULED: <2024-05-01 Wed>\n   Check the tests\n\n   *** Not a headline\n*** DONE Read the diff\n    CLOSED: [2024-05-02 Thu 10:00]\n** Meeting notes\n\n* Ideas\n";
+        let (lists, _) = parse_org(file);
+        assert_eq!(lists.iter().map(|l| l.name.as_str()).collect::<Vec<_>>(), ["Work"]);
+        let todo = &lists[0].todos[0];
+        assert_eq!((todo.text.as_str(), todo.status, todo.priority), ("Review PR", Status::InProgress, Priority::High));
+        assert_eq!(todo.tags, ["code", "review"]);
+        assert_eq!((todo.due, todo.due_time, todo.recurrence), (Date::new(2024, 5, 3), Time::parse("17:00"), Some(Recurrence::Weekly)));
+        assert_eq!(todo.scheduled, Date::new(2024, 5, 1));
+        assert_eq!(todo.notes, "Check the tests\n\n*** Not a headline");
+        assert_eq!((todo.children[0].status, todo.children[0].completed_at.map(Timestamp::date)), (Status::Done, Date::new(2024, 5, 2)));
+
+        // Written back, only the todos' own lines change
+        let mut lists = lists;
+        lists.push(TodoList::new("Errands"));
+        lists[1].todos.push(TodoItem::new("Buy milk"));
+        let written = write_org(&lists, file);
+        assert!(written.starts_with("#+TITLE: Notes\n#+TODO: TODO NEXT | DONE\n\n* Work :job:\nSome prose.\n** NEXT [#A] Review PR :code:review:\n"));
+        assert!(written.contains("DEADLINE: <2024-05-03 Fri 17:00 +1w> SCHEDULED: <2024-05-01 Wed>\nCheck the tests\n\n*** Not a headline\n*** DONE Read the diff\nCLOSED: [2024-05-02 Thu 10:00]\n** Meeting notes\n\n* Ideas\n"));
+        assert!(written.ends_with("* Errands\n** TODO Buy milk\n"));
+        let (again, _) = parse_org(&written);
+        assert_eq!(again[0].todos[0].notes, "Check the tests\n\n*** Not a headline");
+        assert_eq!(write_org(&again, &written), written);
+    }
+
+    #[test]
+    fn markdown_code_blocks_are_not_tasks_or_headings() {
+        let file = "## Work\n\n- [ ] Write docs\n\n```sh\n# install\n- [ ] not a todo\n```\n\n- [x] Ship it\n~~~~\n```\n# still code\n~~~~\n";
+        let (lists, segments) = parse_markdown(file);
+        assert_eq!(lists.iter().map(|l| l.name.as_str()).collect::<Vec<_>>(), ["Work"]);
+        let texts: Vec<_> = lists[0].todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, ["Write docs", "Ship it"]);
+        assert_eq!(segments.iter().filter(|s| matches!(s, Segment::Tasks(_))).count(), 2);
+        assert_eq!(write_markdown(&lists, file), file);
+
+        // Indented under an item, the block is that item's notes
+        let (lists, _) = parse_markdown("- [ ] Run\n  ```\n  - [ ] cargo test\n  ```\n");
+        assert_eq!(lists[0].todos.len(), 1);
+        assert_eq!(lists[0].todos[0].notes, "```\n- [ ] cargo test\n```");
+    }
+
+    #[test]
+    fn markdown_todos_go_back_to_the_run_they_came_from() {
+        let file = "# Todos\n\n- [ ] one\n- [ ] two\n\nSome prose.\n\n- [ ] three\n\n# Todos\n\n- [ ] four\n";
+        let (mut lists, _) = parse_markdown(file);
+        assert_eq!(lists.len(), 1);
+        assert_eq!(write_markdown(&lists, file), file);
+
+        // A new todo lands in the run of the one before it, and a deleted
+        // one leaves the rest of its run in place
+        lists[0].todos.insert(3, TodoItem::new("three and a half"));
+        lists[0].todos.remove(0);
+        let written = write_markdown(&lists, file);
+        assert_eq!(
+            written,
+            "# Todos\n\n- [ ] two\n\nSome prose.\n\n- [ ] three\n- [ ] three and a half\n\n# Todos\n\n- [ ] four\n"
+        );
+
+        // Emptying a run leaves the prose around it
+        lists[0].todos.retain(|t| t.text != "four");
+        assert!(write_markdown(&lists, &written).ends_with("- [ ] three and a half\n\n# Todos\n\n"));
+    }
+
+    #[test]
+    fn markdown_bullets_are_kept() {
+        let file = "* [ ] one\n  + [x] nested\n* [ ] two\n\n+ [ ] three\n";
+        let (mut lists, _) = parse_markdown(file);
+        assert_eq!(write_markdown(&lists, file), file);
+        lists[0].todos[1].children.push(TodoItem::new("new"));
+        lists.push(TodoList::new("Errands"));
+        lists[1].todos.push(TodoItem::new("Buy milk"));
+        assert_eq!(
+            write_markdown(&lists, file),
+            "* [ ] one\n  + [x] nested\n* [ ] two\n  + [ ] new\n\n+ [ ] three\n\n## Errands\n\n- [ ] Buy milk\n"
+        );
+    }
+
+    #[test]
+    fn todo_txt_projects_and_contexts_are_the_ones_other_tools_see() {
+        let file = "(A) 2024-05-01 call mom +Family_matters @phone tag:weekend due:2024-05-03\nfix +cli in +Work\n";
+        let lists = parse_todo_txt(file);
+        let todo = &lists[0].todos[0];
+        assert_eq!((todo.text.as_str(), todo.project.as_deref()), ("call mom", Some("Family matters")));
+        assert_eq!((todo.contexts.join(" "), todo.tags.join(" ")), ("phone".to_string(), "weekend".to_string()));
+        let second = &lists[0].todos[1];
+        assert_eq!((second.text.as_str(), second.project.as_deref()), ("fix +cli in", Some("Work")));
+        assert_eq!(write_todo_txt(&lists), file);
+
+        // Earlier versions wrote tags as +tag and the project as a key
+        let old = "call mom +family +weekend @phone project:Family_matters\nbuy milk +errands\n";
+        let lists = parse_todo_txt(old);
+        let todo = &lists[0].todos[0];
+        assert_eq!((todo.text.as_str(), todo.project.as_deref()), ("call mom", Some("Family matters")));
+        assert_eq!((todo.tags.join(" "), todo.contexts.join(" ")), ("family weekend".to_string(), "phone".to_string()));
+        let second = &lists[0].todos[1];
+        assert_eq!((second.tags.join(" "), second.project.as_ref()), ("errands".to_string(), None));
+        let written = write_todo_txt(&lists);
+        assert_eq!(written, "call mom +Family_matters @phone tag:family tag:weekend\nbuy milk tag:errands\n");
+        assert_eq!(as_json(&parse_todo_txt(&written)), as_json(&lists));
+    }
+
+    #[test]
+    fn encrypted_files_open_only_with_the_passphrase() {
+        let dir = test_dir("encrypted");
+        let path = dir.join("todos.json");
+        let mut app = app_with_todos(&path, 2);
+        // A few rounds keep the test fast - the real count is in the file either way
+        app.cipher = Some(Cipher::with_iterations("hunter2", 10).unwrap());
+        app.save().unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("todo 0"));
+        assert!(matches!(App::load(path.clone()), Err(AppError::Locked { .. })));
+        assert!(matches!(App::load_encrypted(path.clone(), "hunter3"), Err(AppError::Decrypt { .. })));
+
+        // The startup dialog lets the user try again
+        let mut app = App::load_failed(path.clone(), AppError::Locked { path: path.clone() });
+        assert!(app.mode == Mode::Unlock);
+        app.input = "hunter3".to_string();
+        app.submit_passphrase();
+        assert!(app.mode == Mode::Unlock && app.input_error.is_some());
+        app.input = "hunter2".to_string();
+        app.submit_passphrase();
+        assert!(app.mode == Mode::Normal && !app.saving_disabled);
+        assert_eq!(texts(&app), vec!["todo 0", "todo 1"]);
+
+        // Saves stay encrypted until it's turned off
+        app.add("secret");
+        app.flush().unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("secret"));
+        app.decrypt().unwrap();
+        app.flush().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("secret"));
+        assert_eq!(texts(&App::load(path).unwrap()), vec!["todo 0", "todo 1", "secret"]);
+    }
+
+    #[test]
+    fn changes_are_committed_to_git_and_restored_from_history() {
+        let dir = test_dir("git");
+        let path = dir.join("todos.json");
+        app_with_todos(&path, 1).save().unwrap();
+        let mut app = App::load(path.clone()).unwrap();
+
+        // Each save is its own commit
+        app.git = GitMode::Save;
+        app.add("second");
+        app.flush().unwrap();
+        // On quit, the whole session is one
+        app.git = GitMode::Quit;
+        app.add("third");
+        app.add("fourth");
+        app.tick_saving();
+        app.flush().unwrap();
+
+        app.open_history().unwrap();
+        let history = app.history.as_ref().unwrap();
+        let subjects: Vec<&str> = history.versions.iter().map(|version| version.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["Add \"third\" and 1 more change", "Add \"second\""]);
+        assert!(history.diff.iter().any(|line| line.starts_with('+') && line.contains("fourth")));
+
+        // Restoring is a change like any other, so undo takes it back
+        app.history_move(1);
+        app.restore_version();
+        assert!(app.mode == Mode::Normal);
+        assert_eq!(texts(&app), vec!["todo 0", "second"]);
+        app.undo();
+        assert_eq!(texts(&app), vec!["todo 0", "second", "third", "fourth"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = test_dir("permissions");
+        let real = dir.join("real.json");
+        let link = dir.join("todos.json");
+        app_with_todos(&real, 1).save().unwrap();
+        fs::set_permissions(&real, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&real, &link).unwrap();
+
+        app_with_todos(&link, 3).save().unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::metadata(&real).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(texts(&App::load(real).unwrap()).len(), 3);
+    }
+
+    /// Text mixing ASCII with what JSON has to escape and what takes more
+    /// than one byte, one code point or one column
+    fn text(max: usize) -> impl Strategy<Value = String> {
+        const PIECES: &[&str] = &[
+            "a", "Z", "7", " ", "#", "@", ":", "\"", "\\", "/", "\n", "\t", "\r", "\u{0}", "\u{7f}", "é", "ß", "ø", "日", "本", "ש",
+            "\u{301}", "\u{200d}", "👍", "👨‍👩‍👧", "🇩🇪", "\u{feff}", "\u{fffd}",
+        ];
+        vec(select(PIECES), 0..=max).prop_map(|pieces| pieces.concat())
+    }
+
+    fn date() -> impl Strategy<Value = Date> {
+        // 1900 to 2100, either side of the epoch
+        (-25_567..47_433i64).prop_map(Date::from_days)
+    }
+
+    fn timestamp() -> impl Strategy<Value = Timestamp> {
+        (-2_000_000_000..4_000_000_000i64).prop_map(Timestamp)
+    }
+
+    /// A todo with any of its fields set, and subtasks three deep
+    fn todo() -> impl Strategy<Value = TodoItem> {
+        let recurrence = prop_oneof![
+            Just(Recurrence::Daily),
+            Just(Recurrence::Weekly),
+            Just(Recurrence::Monthly),
+            (1..=400u32).prop_map(Recurrence::EveryNDays),
+        ];
+        let what = (
+            text(20),
+            select(&Status::ALL[..]),
+            select(&[Priority::None, Priority::Low, Priority::Medium, Priority::High][..]),
+            // Tags starting with '@' were contexts before those had a field
+            vec(text(6).prop_filter("a context", |tag| !tag.starts_with('@')), 0..3),
+            vec(text(6), 0..2),
+            weighted(0.33, text(8)),
+            prop_oneof![Just(String::new()), text(40)],
+            weighted(0.25, Just(true)),
+        );
+        let when = (
+            weighted(0.5, date()),
+            weighted(0.33, (0..24u32, 0..60u32).prop_map(|(hour, minute)| Time { hour, minute })),
+            weighted(0.25, recurrence),
+            weighted(0.25, date()),
+            weighted(0.5, timestamp()),
+            weighted(0.33, timestamp()),
+            weighted(0.33, timestamp()),
+            0..50u32,
+            0..1_000_000u64,
+            weighted(0.33, any::<u64>().prop_map(|n| format!("{:016x}", n))),
+        );
+        let leaf = (what, when).prop_map(|(what, when)| {
+            let (text, status, priority, tags, contexts, project, notes, collapsed) = what;
+            let (due, due_time, recurrence, scheduled, created_at, completed_at, modified_at, pomodoros, time_spent, uid) = when;
+            let mut todo = TodoItem::new(&text);
+            todo.status = status;
+            todo.priority = priority;
+            todo.tags = tags;
+            todo.contexts = contexts;
+            todo.project = project;
+            todo.notes = notes;
+            todo.collapsed = collapsed.is_some();
+            todo.due = due;
+            todo.due_time = due_time;
+            todo.recurrence = recurrence;
+            todo.scheduled = scheduled;
+            todo.created_at = created_at;
+            todo.completed_at = completed_at;
+            todo.modified_at = modified_at;
+            todo.pomodoros = pomodoros;
+            todo.time_spent = time_spent;
+            if let Some(uid) = uid {
+                todo.remote_ids.insert("server".to_string(), uid);
+            }
+            todo
+        });
+        leaf.prop_recursive(3, 16, 3, |inner| {
+            (inner.clone(), vec(inner, 0..4)).prop_map(|(mut todo, children)| {
+                todo.children = children;
+                todo
+            })
+        })
+    }
+
+    /// Empty lists turn up as often as full ones
+    fn lists() -> impl Strategy<Value = Vec<TodoList>> {
+        let list = (text(10), vec(todo(), 0..=8), vec(todo(), 0..=2)).prop_map(|(name, todos, trash)| {
+            let mut list = TodoList::new(&name);
+            list.todos = todos;
+            list.trash = trash;
+            list
+        });
+        vec(list, 0..4)
+    }
+
+    /// Compared as JSON, since that's everything the file keeps
+    fn as_json<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn random_lists_survive_saving_and_loading(lists in lists()) {
+            let written = Format::Json.write(&lists, Path::new("todos.json")).unwrap();
+            let read = Format::Json.read(&written).unwrap();
+            prop_assert_eq!(as_json(&read), as_json(&lists));
+            // Writing what was read changes nothing, so nothing drifts from save to save
+            prop_assert_eq!(Format::Json.write(&read, Path::new("todos.json")).unwrap(), written);
+        }
+
+        #[test]
+        fn random_todos_from_every_old_layout_are_migrated(todos in vec(todo(), 0..=8)) {
+            let expected = as_json(&vec![TodoList { todos: todos.clone(), ..TodoList::new("Todos") }]);
+
+            // Version 0 is the bare array, from before lists, whose todos
+            // were done or not
+            let mut old = as_json(&todos);
+            for todo in old.as_array_mut().unwrap() {
+                let done = todo["status"] == json!("done");
+                let fields = todo.as_object_mut().unwrap();
+                fields.remove("status");
+                fields.insert("completed".to_string(), json!(done));
+            }
+            let read = Format::Json.read(&old.to_string()).unwrap();
+            // Anything between pending and done couldn't be written back then
+            let mut done_or_not = todos.clone();
+            for todo in &mut done_or_not {
+                if todo.status != Status::Done {
+                    todo.status = Status::Pending;
+                }
+            }
+            let migrated = as_json(&vec![TodoList { todos: done_or_not, ..TodoList::new("Todos") }]);
+            prop_assert_eq!(as_json(&read), migrated);
+
+            // Version 1 has lists but no version number
+            let old = json!({ "lists": expected });
+            let read = Format::Json.read(&old.to_string()).unwrap();
+            prop_assert_eq!(as_json(&read), expected);
+            let written = Format::Json.write(&read, Path::new("todos.json")).unwrap();
+            prop_assert_eq!(&serde_json::from_str::<Value>(&written).unwrap()["version"], &json!(SAVE_VERSION));
+        }
+    }
+
+    #[test]
+    fn a_huge_list_survives_the_save_file() {
+        let dir = test_dir("huge");
+        let path = dir.join("todos.json");
+        let mut app = App::new(path.clone());
+        app.lists = vec![TodoList::new("Huge"), TodoList::new("Empty")];
+        app.lists[0].todos = vec(todo(), 5_000).new_tree(&mut TestRunner::deterministic()).unwrap().current();
+        app.save().unwrap();
+        let loaded = App::load(path).unwrap();
+        assert_eq!(as_json(&loaded.lists), as_json(&app.lists));
+    }
+}
@@ -0,0 +1,1144 @@
+//! Two-way sync of the lists with Todoist through its API, or with a CalDAV
+//! server (see caldav.rs), Google Tasks (see google.rs) or another device
+//! running `serve` (see remote.rs)
+//! curl does the HTTP the way git does the history: there's no TLS stack to
+//! build in, and whatever proxy and certificate setup curl has applies
+//! Each list is a Todoist project and each todo a task; when both sides
+//! changed a todo since the last sync, the later change wins
+//! That part is the same for every service: `reconcile` does it against a
+//! `Backend`, which only maps tasks and makes the calls
+//! The sync runs on its own thread against a copy of the lists and hands back
+//! the changes to make here, which the app applies as one undoable change
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::{Command as Process, Stdio},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::{find_item, item_at, item_at_mut, App, Command, Mode, Priority, Recurrence, Timestamp, ToastKind, TodoItem, TodoList, TodoPath},
+    caldav::{self, CalDavConfig},
+    google::{self, GoogleConfig},
+    remote::{self, RemoteConfig},
+    import::{parse_timestamp, todoist_id, todoist_order, todoist_todo},
+};
+
+/// Where Todoist's API lives
+const TODOIST_URL: &str = "https://api.todoist.com/api/v1";
+
+/// The "todoist" table of the config
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct TodoistConfig {
+    /// API token, from Settings > Integrations > Developer in Todoist
+    pub(crate) token: String,
+    /// Minutes between syncs while the app runs, 0 to sync only when asked
+    pub(crate) interval: u64,
+    /// Base URL of the API, for going through a proxy
+    pub(crate) url: String,
+}
+
+impl Default for TodoistConfig {
+    fn default() -> TodoistConfig {
+        TodoistConfig { token: String::new(), interval: 5, url: TODOIST_URL.to_string() }
+    }
+}
+
+/// Where the lists sync to, with its settings from the config
+#[derive(Clone)]
+pub(crate) enum Remote {
+    Todoist(TodoistConfig),
+    CalDav(CalDavConfig),
+    Google(GoogleConfig),
+    Server(RemoteConfig),
+}
+
+impl Remote {
+    fn service(&self) -> Service {
+        match self {
+            Remote::Todoist(_) => Service::Todoist,
+            Remote::CalDav(_) => Service::CalDav,
+            Remote::Google(_) => Service::Google,
+            Remote::Server(_) => Service::Server,
+        }
+    }
+
+    /// Minutes between syncs, 0 for only when asked
+    fn interval(&self) -> u64 {
+        match self {
+            Remote::Todoist(config) => config.interval,
+            Remote::CalDav(config) => config.interval,
+            Remote::Google(config) => config.interval,
+            Remote::Server(config) => config.interval,
+        }
+    }
+}
+
+/// Which kind of server a sync was with, for finding the todos linked to it
+/// Each keeps its own links, so switching between them starts over cleanly
+#[derive(Clone, Copy)]
+pub(crate) enum Service {
+    Todoist,
+    CalDav,
+    Google,
+    Server,
+}
+
+impl Service {
+    /// Name for notices
+    fn name(self) -> &'static str {
+        match self {
+            Service::Todoist => "Todoist",
+            Service::CalDav => "the CalDAV server",
+            Service::Google => "Google Tasks",
+            Service::Server => "the sync server",
+        }
+    }
+
+    /// Key of the service's links in `remote_ids`
+    fn key(self) -> &'static str {
+        match self {
+            Service::Todoist => "todoist",
+            Service::CalDav => "caldav",
+            Service::Google => "google",
+            Service::Server => "server",
+        }
+    }
+
+    /// Id of the task a todo is linked to here
+    pub(crate) fn id(self, todo: &TodoItem) -> Option<&str> {
+        todo.remote_ids.get(self.key()).map(String::as_str)
+    }
+
+    /// Links a todo to the task with this id
+    pub(crate) fn link(self, todo: &mut TodoItem, id: &str) {
+        todo.remote_ids.insert(self.key().to_string(), id.to_string());
+    }
+
+    /// Id of the project or calendar a list is linked to
+    /// Lists on a sync server go by their names
+    pub(crate) fn list_id(self, list: &TodoList) -> Option<&str> {
+        match self {
+            Service::Server => Some(&list.name),
+            _ => list.remote_ids.get(self.key()).map(String::as_str),
+        }
+    }
+
+    /// Whether lists go by their names here rather than by a link
+    fn by_name(self) -> bool {
+        matches!(self, Service::Server)
+    }
+
+    /// Links a list to the project or calendar with this id, and returns
+    /// whether it took - where the name is the link there's nothing to keep
+    fn link_list(self, list: &mut TodoList, id: &str) -> bool {
+        if !self.by_name() {
+            list.remote_ids.insert(self.key().to_string(), id.to_string());
+        }
+        !self.by_name()
+    }
+
+    /// Takes the synced fields of a task for a todo
+    fn pull(self, todo: &mut TodoItem, task: &TodoItem) {
+        match self {
+            Service::Todoist => pull(todo, task),
+            Service::CalDav => caldav::pull(todo, task),
+            Service::Google => google::pull(todo, task),
+            Service::Server => remote::pull(todo, task),
+        }
+    }
+
+    /// Where the sync state of a todo file is kept, next to it
+    fn state_path(self, app: &App) -> PathBuf {
+        app.save_path.with_extension(match self {
+            Service::Todoist => "todoist.json",
+            Service::CalDav => "caldav.json",
+            Service::Google => "google.json",
+            Service::Server => "server.json",
+        })
+    }
+}
+
+/// How the last sync went, for the status bar
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SyncStatus {
+    /// Not set up, or nothing has run yet
+    #[default]
+    Idle,
+    Syncing,
+    Synced(Timestamp),
+    Failed,
+}
+
+/// What the last sync left behind, kept next to the todo file
+/// A task that was synced before and is missing on one side now was deleted
+/// there, while one that never was is new
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct SyncState {
+    /// When the last sync started - changes after it are what the next one sends
+    pub(crate) last_sync: Option<Timestamp>,
+    /// Ids of the tasks both sides had
+    pub(crate) tasks: BTreeSet<String>,
+}
+
+/// A change the sync makes to the lists here, found by the ids on the other
+/// side since the lists may have changed while it ran
+pub(crate) enum Change {
+    /// Link the list with this name to a project or calendar
+    LinkList { name: String, id: String },
+    /// Add a list for a project or calendar that has new tasks
+    AddList { id: String, name: String },
+    /// Link the todo created as a task, found at its path or by its text
+    Link { list: String, path: TodoPath, text: String, id: String },
+    /// Link the todo whose task was deleted on the other side to the new
+    /// task it went back as
+    Relink { id: String, new_id: String },
+    /// Take the task's fields for the todo linked to it
+    Pull { id: String, task: TodoItem },
+    /// Add a task that's new on the other side, linked already
+    Add { project: String, parent: Option<String>, task: TodoItem },
+    /// Trash the todo whose task was deleted on the other side
+    Remove { id: String },
+}
+
+/// What a sync sends back to the app
+pub(crate) struct SyncOutcome {
+    pub(crate) service: Service,
+    pub(crate) started: Timestamp,
+    pub(crate) changes: Vec<Change>,
+    /// The tasks linked on both sides afterwards
+    pub(crate) tasks: BTreeSet<String>,
+}
+
+impl App {
+    /// Called from the event loop: picks up a finished sync and starts the
+    /// next one when the interval has passed
+    /// Returns whether a sync started or finished, which the status bar shows
+    pub(crate) fn tick_sync(&mut self) -> bool {
+        let Some(remote) = &self.remote else {
+            return false;
+        };
+        let Some(receiver) = &self.sync else {
+            let interval = remote.interval();
+            let due = interval > 0 && self.sync_started.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval * 60));
+            if due {
+                self.start_sync(false);
+            }
+            return due;
+        };
+        // Popups hold indexes into the lists, so the result waits until they're closed
+        if self.mode != Mode::Normal {
+            return false;
+        }
+        match receiver.try_recv() {
+            Ok(result) => self.finish_sync(result, false),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => self.finish_sync(Err("the sync stopped unexpectedly".to_string()), false),
+        }
+        true
+    }
+
+    /// Syncs now, for the sync key and `:sync` - the GitHub issues are
+    /// fetched again and the mail checked too
+    pub(crate) fn request_sync(&mut self) {
+        if self.github.is_some() {
+            self.refresh_issues(true);
+        }
+        if self.email.is_some() {
+            self.check_mail(true);
+        }
+        if self.remote.is_none() {
+            if self.github.is_none() && self.email.is_none() {
+                self.notice = Some(self.tr("Set \"todoist\", \"caldav\", \"google\", \"remote\", \"github\" or \"email\" in the config to sync").to_string());
+            }
+        } else if self.sync.is_some() {
+            self.notice = Some(self.tr("Already syncing").to_string());
+        } else {
+            self.start_sync(true);
+        }
+    }
+
+    /// Hands a copy of the lists to a sync on its own thread
+    fn start_sync(&mut self, manual: bool) {
+        self.sync_started = Some(Instant::now());
+        // Another instance syncs this file, and this one couldn't save the result
+        if self.read_only || self.saving_disabled {
+            if manual {
+                self.notice = Some(self.tr("Not syncing - this session can't save the todo file").to_string());
+            }
+            return;
+        }
+        let Some(remote) = self.remote.clone() else {
+            return;
+        };
+        let state: SyncState = fs::read_to_string(remote.service().state_path(self))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        // The issues are GitHub's, and don't go anywhere else
+        let lists: Vec<TodoList> = (0..self.lists.len()).filter(|&i| !self.is_issue_list(i)).map(|i| self.lists[i].clone()).collect();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = match &remote {
+                Remote::Todoist(config) => sync(config, &lists, &state),
+                Remote::CalDav(config) => caldav::sync(config, &lists, &state),
+                Remote::Google(config) => google::sync(config, &lists, &state),
+                Remote::Server(config) => remote::sync(config, &lists, &state),
+            };
+            let _ = sender.send(result);
+        });
+        self.sync = Some(receiver);
+        self.sync_manual = manual;
+        self.sync_status = SyncStatus::Syncing;
+    }
+
+    /// Waits for a running sync and applies it, so quitting doesn't drop
+    /// the links to tasks it created
+    pub(crate) fn wait_for_sync(&mut self) {
+        if let Some(receiver) = &self.sync {
+            let result = receiver.recv().unwrap_or_else(|_| Err("the sync stopped unexpectedly".to_string()));
+            self.finish_sync(result, true);
+        }
+    }
+
+    /// Applies what a sync brought back and reports how it went
+    fn finish_sync(&mut self, result: Result<SyncOutcome, String>, quitting: bool) {
+        self.sync = None;
+        match result {
+            Ok(outcome) => {
+                let service = outcome.service;
+                let state = SyncState { last_sync: Some(outcome.started), tasks: outcome.tasks.clone() };
+                let pulled = self.apply_sync(outcome, quitting);
+                let written = serde_json::to_string_pretty(&state)
+                    .map_err(io::Error::other)
+                    .and_then(|json| fs::write(service.state_path(self), json));
+                if let Err(err) = written {
+                    let text = self.trf("Could not save the sync state: {}", &[&err]);
+                    self.toast(ToastKind::Error, text);
+                } else if self.sync_manual || pulled > 0 {
+                    let text = match pulled {
+                        0 => self.trf("Synced with {}", &[&service.name()]),
+                        n => self.trn("Synced with {}: {} change from there", "Synced with {}: {} changes from there", n, &[&service.name(), &n]),
+                    };
+                    self.toast(ToastKind::Success, text);
+                }
+                self.sync_status = SyncStatus::Synced(Timestamp::now());
+            }
+            Err(err) => {
+                tracing::warn!("sync failed: {}", err);
+                // A background sync that keeps failing says so once
+                if self.sync_manual || self.sync_status != SyncStatus::Failed {
+                    let text = self.trf("Sync failed: {}", &[&err]);
+                    self.toast(ToastKind::Error, text);
+                }
+                self.sync_status = SyncStatus::Failed;
+            }
+        }
+    }
+
+    /// Makes the changes a sync found on the other side here, as one change
+    /// undo takes back, and returns how many there were
+    /// Links to tasks aren't changes of their own, so they're made in place
+    fn apply_sync(&mut self, outcome: SyncOutcome, quitting: bool) -> usize {
+        let service = outcome.service;
+        let mut linked = false;
+        for change in &outcome.changes {
+            match change {
+                Change::LinkList { name, id } => {
+                    if let Some(list) = self.lists.iter_mut().find(|list| list.name == *name) {
+                        linked |= service.link_list(list, id);
+                    }
+                }
+                Change::Link { list, path, text, id } => {
+                    let todo = self
+                        .lists
+                        .iter_mut()
+                        .find(|l| l.name == *list)
+                        .and_then(|list| link_target(service, &mut list.todos, path, text));
+                    if let Some(todo) = todo {
+                        service.link(todo, id);
+                        linked = true;
+                    }
+                }
+                Change::Relink { id, new_id } => {
+                    if let Some((list, path)) = find_task(service, &self.lists, id) {
+                        service.link(item_at_mut(&mut self.lists[list].todos, &path), new_id);
+                        linked = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Commands are worked out against a copy, since each one's paths
+        // depend on the ones before it
+        let mut lists = self.lists.clone();
+        let mut archive = Vec::new();
+        let mut commands = Vec::new();
+        let mut run = |command: Command, lists: &mut Vec<TodoList>| {
+            command.apply(lists, &mut archive);
+            commands.push(command);
+        };
+        // A todo changed here while the sync ran keeps that change, and the
+        // next sync settles it
+        let untouched = |todo: &TodoItem| todo.modified_at.is_none_or(|at| at <= outcome.started);
+        let mut added = Vec::new();
+        for change in outcome.changes {
+            match change {
+                Change::AddList { id, name } => {
+                    let mut list = TodoList::new(&name);
+                    service.link_list(&mut list, &id);
+                    run(Command::InsertList { index: lists.len(), list }, &mut lists);
+                }
+                Change::Pull { id, task } => {
+                    if let Some((list, path)) = find_task(service, &lists, &id)
+                        && untouched(item_at(&lists[list].todos, &path))
+                    {
+                        let before = item_at(&lists[list].todos, &path).clone();
+                        let mut after = before.clone();
+                        service.pull(&mut after, &task);
+                        run(Command::Replace { list, path, before: Box::new(before), after: Box::new(after) }, &mut lists);
+                    }
+                }
+                Change::Remove { id } => {
+                    if let Some((list, path)) = find_task(service, &lists, &id)
+                        && untouched(item_at(&lists[list].todos, &path))
+                    {
+                        run(Command::Trash { list, path, index: 0 }, &mut lists);
+                    }
+                }
+                Change::Add { project, parent, task } => added.push((project, parent, task)),
+                _ => {}
+            }
+        }
+        // Subtasks can come before their parent, so adding goes round until
+        // nothing more fits, and what's left goes to the top level
+        let pending: HashSet<String> = added.iter().filter_map(|(_, _, task)| service.id(task).map(str::to_string)).collect();
+        let mut rounds = 0;
+        while !added.is_empty() {
+            let last_round = rounds == pending.len();
+            rounds += 1;
+            added.retain(|(project, parent, task)| {
+                let parent_path = parent.as_ref().and_then(|parent| find_task(service, &lists, parent));
+                if parent_path.is_none() && parent.as_ref().is_some_and(|parent| pending.contains(parent)) && !last_round {
+                    return true;
+                }
+                let (list, path) = match parent_path {
+                    Some((list, mut path)) => {
+                        path.push(item_at(&lists[list].todos, &path).children.len());
+                        (list, path)
+                    }
+                    None => {
+                        let list = lists.iter().position(|list| service.list_id(list) == Some(project));
+                        let list = list.or_else(|| (0..lists.len()).find(|&i| !self.is_issue_list(i))).unwrap_or(self.current);
+                        (list, vec![lists[list].todos.len()])
+                    }
+                };
+                run(Command::Insert { list, path, item: task.clone() }, &mut lists);
+                false
+            });
+        }
+
+        let pulled = commands.len();
+        if commands.is_empty() {
+            if linked {
+                self.autosave(false);
+            }
+        } else if quitting {
+            // Nothing to undo after quitting, and the view doesn't matter
+            Command::Batch(commands).apply(&mut self.lists, &mut self.archive);
+            self.autosave(false);
+        } else {
+            // The sync shouldn't move the view to whatever list it touched last
+            let current = self.current;
+            self.execute(Command::Batch(commands));
+            self.current = current.min(self.lists.len() - 1);
+            self.refresh_view();
+        }
+        pulled
+    }
+}
+
+/// The todo a Link change is about: still at its path with the same text,
+/// or else the first unlinked todo with that text
+fn link_target<'a>(service: Service, todos: &'a mut [TodoItem], path: &[usize], text: &str) -> Option<&'a mut TodoItem> {
+    let at_path = find_item(todos, path).is_some_and(|todo| todo.text == text && service.id(todo).is_none());
+    if at_path {
+        return Some(item_at_mut(todos, path));
+    }
+    fn search<'a>(service: Service, todos: &'a mut [TodoItem], text: &str) -> Option<&'a mut TodoItem> {
+        for todo in todos {
+            if todo.text == text && service.id(todo).is_none() {
+                return Some(todo);
+            }
+            if let Some(found) = search(service, &mut todo.children, text) {
+                return Some(found);
+            }
+        }
+        None
+    }
+    search(service, todos, text)
+}
+
+/// Where the todo linked to task `id` is, as a list index and path
+pub(crate) fn find_task(service: Service, lists: &[TodoList], id: &str) -> Option<(usize, TodoPath)> {
+    fn search(service: Service, todos: &[TodoItem], id: &str, path: &mut TodoPath) -> bool {
+        for (i, todo) in todos.iter().enumerate() {
+            path.push(i);
+            if service.id(todo) == Some(id) || search(service, &todo.children, id, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+    lists.iter().enumerate().find_map(|(list, l)| {
+        let mut path = Vec::new();
+        search(service, &l.todos, id, &mut path).then_some((list, path))
+    })
+}
+
+/// Every task id linked in a tree of todos
+pub(crate) fn collect_ids(service: Service, todos: &[TodoItem], ids: &mut HashSet<String>) {
+    for todo in todos {
+        if let Some(id) = service.id(todo) {
+            ids.insert(id.to_string());
+        }
+        collect_ids(service, &todo.children, ids);
+    }
+}
+
+/// Whether something changed after the last sync - always, before the first
+pub(crate) fn changed_since(at: Option<Timestamp>, since: Option<Timestamp>) -> bool {
+    match (at, since) {
+        (Some(at), Some(since)) => at > since,
+        _ => since.is_none(),
+    }
+}
+
+/// Quotes a value for curl's config syntax
+pub(crate) fn curl_quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+/// Makes a request with curl and returns the status code and the reply
+/// `options` are lines of curl's config, like `url = "..."` - they go to curl
+/// on stdin rather than the command line, where other users could see the
+/// credentials in them
+pub(crate) fn curl(mut options: Vec<String>) -> Result<(u16, String), String> {
+    options.extend([
+        "silent".to_string(),
+        "show-error".to_string(),
+        "max-time = 30".to_string(),
+        format!("write-out = {}", curl_quote("\n%{http_code}")),
+    ]);
+    let mut child = Process::new("curl")
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => "curl not found".to_string(),
+            _ => err.to_string(),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(options.join("\n").as_bytes()).map_err(|err| err.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("curl failed").trim().to_string());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (reply, code) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let code = code.trim().parse().map_err(|_| "no reply from the server".to_string())?;
+    Ok((code, reply.to_string()))
+}
+
+/// Tags and contexts as Todoist labels, which make no difference between them
+fn labels(todo: &TodoItem) -> BTreeSet<&str> {
+    todo.tags.iter().chain(&todo.contexts).map(String::as_str).collect()
+}
+
+/// Whether a todo and a task agree on everything that's synced
+fn same(todo: &TodoItem, task: &TodoItem) -> bool {
+    todo.text == task.text
+        && todo.notes == task.notes
+        && todo.priority == task.priority
+        && todo.is_closed() == task.is_closed()
+        && (todo.due, todo.due_time) == (task.due, task.due_time)
+        && labels(todo) == labels(task)
+}
+
+/// Takes the synced fields of a task for a todo
+fn pull(todo: &mut TodoItem, task: &TodoItem) {
+    todo.text = task.text.clone();
+    todo.notes = task.notes.clone();
+    todo.priority = task.priority;
+    todo.due = task.due;
+    todo.due_time = task.due_time;
+    todo.recurrence = task.recurrence;
+    // Contexts stay contexts as long as the labels are the same
+    if labels(todo) != labels(task) {
+        todo.tags = task.tags.clone();
+        todo.contexts.clear();
+    }
+    if todo.is_closed() != task.is_closed() {
+        todo.set_completed(task.is_closed());
+    }
+}
+
+/// The phrase Todoist reads a repeat from
+fn repeat_phrase(rule: Recurrence) -> String {
+    match rule {
+        Recurrence::Daily => "every day".to_string(),
+        Recurrence::Weekly => "every week".to_string(),
+        Recurrence::Monthly => "every month".to_string(),
+        Recurrence::EveryNDays(n) => format!("every {} days", n),
+    }
+}
+
+/// The fields of a task as the API takes them
+/// Repeats are only sent when a task is created, since setting a date on a
+/// repeating task would stop it repeating
+fn task_fields(todo: &TodoItem) -> Value {
+    let priority = match todo.priority {
+        Priority::High => 4,
+        Priority::Medium => 3,
+        Priority::Low => 2,
+        Priority::None => 1,
+    };
+    let mut fields = json!({
+        "content": todo.text,
+        "description": todo.notes,
+        "labels": labels(todo),
+        "priority": priority,
+    });
+    match (todo.due, todo.due_time) {
+        (Some(date), None) => fields["due_date"] = json!(date.to_string()),
+        (Some(date), Some(time)) => {
+            let at = Timestamp(Timestamp::from_date(date).0 + i64::from(time.hour * 3600 + time.minute * 60));
+            fields["due_datetime"] = json!(at);
+        }
+        (None, _) => fields["due_string"] = json!("no date"),
+    }
+    fields
+}
+
+/// Percent-encodes a query parameter
+pub(crate) fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Undoes the percent-encoding of a query parameter
+pub(crate) fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Todoist's API, called through curl
+struct Api<'a> {
+    config: &'a TodoistConfig,
+}
+
+impl Api<'_> {
+    /// Makes a request and returns the reply, None when it was 404 Not Found
+    fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Option<Value>, String> {
+        let mut options = vec![
+            format!("url = {}", curl_quote(&format!("{}{}", self.config.url.trim_end_matches('/'), path))),
+            format!("request = {}", method),
+            format!("header = {}", curl_quote(&format!("Authorization: Bearer {}", self.config.token))),
+        ];
+        if let Some(body) = body {
+            options.push(format!("header = {}", curl_quote("Content-Type: application/json")));
+            options.push(format!("data-raw = {}", curl_quote(&body.to_string())));
+        }
+        let (code, reply) = curl(options)?;
+        match code {
+            404 => Ok(None),
+            200..=299 if reply.trim().is_empty() => Ok(Some(Value::Null)),
+            200..=299 => serde_json::from_str(&reply).map(Some).map_err(|err| format!("unexpected reply from Todoist: {}", err)),
+            401 | 403 => Err("Todoist refused the token - check \"token\" in the config".to_string()),
+            code => {
+                let reason: String = reply.trim().chars().take(120).collect();
+                Err(format!("Todoist answered {} {}", code, reason))
+            }
+        }
+    }
+
+    /// Everything a listing endpoint has, following its pages
+    fn list(&self, path: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = match &cursor {
+                Some(cursor) => format!("{}?limit=200&cursor={}", path, url_encode(cursor)),
+                None => format!("{}?limit=200", path),
+            };
+            match self.call("GET", &page, None)?.ok_or_else(|| format!("Todoist has no {}", path))? {
+                // Older versions of the API answer with everything at once
+                Value::Array(values) => {
+                    items.extend(values);
+                    return Ok(items);
+                }
+                reply => {
+                    items.extend(reply.get("results").and_then(Value::as_array).cloned().unwrap_or_default());
+                    match reply.get("next_cursor").and_then(Value::as_str) {
+                        Some(next) => cursor = Some(next.to_string()),
+                        None => return Ok(items),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes a request whose reply doesn't matter
+    fn send(&self, method: &str, path: &str, body: Option<&Value>) -> Result<(), String> {
+        self.call(method, path, body).map(|_| ())
+    }
+}
+
+/// A task as Todoist's API has it
+struct Task {
+    id: String,
+    task: RemoteTask,
+    deleted: bool,
+}
+
+impl Task {
+    fn parse(value: &Value) -> Option<Task> {
+        let id = todoist_id(value.get("id"))?;
+        let task = RemoteTask {
+            project: todoist_id(value.get("project_id")).unwrap_or_default(),
+            parent: todoist_id(value.get("parent_id")),
+            todo: todoist_todo(value),
+            updated: value.get("updated_at").and_then(Value::as_str).and_then(parse_timestamp),
+        };
+        Some(Task { id, task, deleted: value.get("is_deleted").and_then(Value::as_bool) == Some(true) })
+    }
+}
+
+/// A task on the other side as `reconcile` sees it, whatever the service
+pub(crate) struct RemoteTask {
+    /// Id of the project, calendar or list it's in
+    pub(crate) project: String,
+    pub(crate) parent: Option<String>,
+    /// Its fields as a todo linked to it
+    pub(crate) todo: TodoItem,
+    /// When it last changed there
+    pub(crate) updated: Option<Timestamp>,
+}
+
+/// What a service has to answer for `reconcile`: its projects and tasks, and
+/// making, changing and deleting them - all of it mapped to todos and lists
+/// Which side wins and what goes where is decided once, in `reconcile`
+pub(crate) trait Backend {
+    const SERVICE: Service;
+    /// Name for the list of tasks whose project has none
+    const UNNAMED: &'static str;
+    /// Whether todos finished before they were ever synced go over too
+    const CLOSED: bool = false;
+    /// Whether `tasks` has the finished ones - where it doesn't, a finished
+    /// todo missing from it is taken to be finished there too
+    const LISTS_CLOSED: bool = true;
+    /// Whether subtasks of subtasks go under their top-level task, for
+    /// services that nest only one level
+    const ONE_LEVEL: bool = false;
+
+    /// The projects there, by id and name
+    fn projects(&mut self) -> Result<Vec<(String, String)>, String>;
+    /// Makes a project for a list and returns its id
+    fn create_project(&mut self, name: &str) -> Result<String, String>;
+    /// Every task there by id, parents before their subtasks
+    fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String>;
+    /// A task `tasks` left out, None if it's gone
+    fn find(&mut self, _id: &str) -> Result<Option<RemoteTask>, String> {
+        Ok(None)
+    }
+    /// Whether a todo and a task agree on everything that's synced
+    fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool;
+    /// Creates a task for a todo and returns its id
+    /// `id` is the one it had before it was deleted there - services whose
+    /// ids are made here give it the same one again
+    fn create(&mut self, todo: &TodoItem, project: &str, parent: Option<&str>, id: Option<&str>) -> Result<String, String>;
+    /// Sends a todo's fields to its task
+    fn update(&mut self, id: &str, todo: &TodoItem, task: &RemoteTask, project: &str, parent: Option<&str>) -> Result<(), String>;
+    /// Deletes a task whose todo was deleted here
+    fn delete(&mut self, id: &str, task: &RemoteTask) -> Result<(), String>;
+}
+
+/// A sync in progress, with what it found and what it decided
+struct Reconciler<'a, B: Backend> {
+    backend: &'a mut B,
+    /// The tasks there by id
+    remote: HashMap<String, RemoteTask>,
+    /// What the last sync left behind
+    state: &'a SyncState,
+    changes: Vec<Change>,
+    /// Tasks linked on both sides so far
+    synced: BTreeSet<String>,
+}
+
+/// One sync, run on its own thread: sends what changed here to the service
+/// and returns what changed there
+/// Every list has a project there, found by name the first time; when both
+/// sides changed a todo since the last sync, the later change wins
+pub(crate) fn reconcile<B: Backend>(backend: &mut B, lists: &[TodoList], state: &SyncState) -> Result<SyncOutcome, String> {
+    let started = Timestamp::now();
+    let service = B::SERVICE;
+    let mut projects = backend.projects()?;
+    let tasks = backend.tasks()?;
+    let order: Vec<String> = tasks.iter().map(|(id, _)| id.clone()).collect();
+    let remote = tasks
+        .into_iter()
+        .map(|(id, mut task)| {
+            service.link(&mut task.todo, &id);
+            (id, task)
+        })
+        .collect();
+    let mut sync = Reconciler { backend, remote, state, changes: Vec::new(), synced: BTreeSet::new() };
+
+    let mut linked = Vec::new();
+    for list in lists {
+        let known = service.list_id(list).filter(|id| projects.iter().any(|(project, _)| project == id));
+        let named = projects.iter().find(|(_, name)| name.eq_ignore_ascii_case(&list.name)).map(|(id, _)| id.as_str());
+        let id = match known.or(named) {
+            Some(id) => id.to_string(),
+            None => {
+                let id = sync.backend.create_project(&list.name)?;
+                projects.push((id.clone(), list.name.clone()));
+                id
+            }
+        };
+        if service.list_id(list) != Some(id.as_str()) {
+            sync.changes.push(Change::LinkList { name: list.name.clone(), id: id.clone() });
+        }
+        sync.todos(list, &list.todos, &id, None, &mut Vec::new())?;
+        linked.push((id, list));
+    }
+
+    // What's left there is new, or was deleted here
+    let mut here = HashSet::new();
+    for list in lists {
+        collect_ids(service, &list.todos, &mut here);
+    }
+    let mut new_projects = HashSet::new();
+    for id in order {
+        let task = &sync.remote[&id];
+        if here.contains(&id) {
+            continue;
+        }
+        if state.tasks.contains(&id) {
+            // Subtasks go with their parent, after which they're not found -
+            // which is fine
+            sync.backend.delete(&id, task)?;
+            continue;
+        }
+        // Finished before this app ever saw it, or left blank
+        if (task.todo.is_closed() && !B::CLOSED) || task.todo.text.is_empty() {
+            continue;
+        }
+        let project = match linked.iter().find(|(project, _)| *project == task.project) {
+            // Where lists go by name, the one here can be spelled differently
+            Some((_, list)) if service.by_name() => list.name.clone(),
+            Some(_) => task.project.clone(),
+            None => {
+                if new_projects.insert(task.project.clone()) {
+                    let name = projects.iter().find(|(project, _)| *project == task.project).map_or(B::UNNAMED, |(_, name)| name);
+                    sync.changes.push(Change::AddList { id: task.project.clone(), name: name.to_string() });
+                }
+                task.project.clone()
+            }
+        };
+        sync.changes.push(Change::Add { project, parent: task.parent.clone(), task: task.todo.clone() });
+        sync.synced.insert(id);
+    }
+    Ok(SyncOutcome { service, started, changes: sync.changes, tasks: sync.synced })
+}
+
+impl<B: Backend> Reconciler<'_, B> {
+    /// Syncs a tree of todos, parents before their subtasks
+    fn todos(&mut self, list: &TodoList, todos: &[TodoItem], project: &str, parent: Option<&str>, path: &mut TodoPath) -> Result<(), String> {
+        for (i, todo) in todos.iter().enumerate() {
+            path.push(i);
+            let id = self.todo(list, todo, project, parent, path)?;
+            let parent = if B::ONE_LEVEL { parent.or(id.as_deref()) } else { id.as_deref() };
+            self.todos(list, &todo.children, project, parent, path)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Syncs one todo and returns the id of its task, if it has one
+    fn todo(&mut self, list: &TodoList, todo: &TodoItem, project: &str, parent: Option<&str>, path: &TodoPath) -> Result<Option<String>, String> {
+        let changed_here = changed_since(todo.modified_at, self.state.last_sync);
+        let Some(id) = B::SERVICE.id(todo).map(str::to_string) else {
+            // Finished before it was ever synced - the other side doesn't need it
+            if todo.is_closed() && !B::CLOSED {
+                return Ok(None);
+            }
+            let id = self.backend.create(todo, project, parent, None)?;
+            self.synced.insert(id.clone());
+            self.changes.push(Change::Link { list: list.name.clone(), path: path.clone(), text: todo.text.clone(), id: id.clone() });
+            return Ok(Some(id));
+        };
+
+        if !self.remote.contains_key(&id) {
+            // Finished on both sides, as far as a service listing only open tasks says
+            if todo.is_closed() && !B::LISTS_CLOSED {
+                return Ok(Some(id));
+            }
+            if let Some(mut task) = self.backend.find(&id)? {
+                B::SERVICE.link(&mut task.todo, &id);
+                self.remote.insert(id.clone(), task);
+            }
+        }
+        match self.remote.get(&id) {
+            Some(task) => {
+                self.synced.insert(id.clone());
+                if !self.backend.same(todo, &task.todo) {
+                    let changed_there = changed_since(task.updated, self.state.last_sync);
+                    if changed_here && (!changed_there || todo.modified_at >= task.updated) {
+                        self.backend.update(&id, todo, task, project, parent)?;
+                    } else {
+                        self.changes.push(Change::Pull { id: id.clone(), task: task.todo.clone() });
+                    }
+                }
+                Ok(Some(id))
+            }
+            // Deleted there - unless it changed here since, then it goes back
+            // as a new task, though only open ones do where finished todos
+            // aren't synced
+            None if self.state.tasks.contains(&id) && (!changed_here || (todo.is_closed() && !B::CLOSED)) => {
+                self.changes.push(Change::Remove { id: id.clone() });
+                Ok(Some(id))
+            }
+            // Its subtasks can't go under a task that isn't there
+            None if todo.is_closed() && !B::CLOSED => Ok(None),
+            None => {
+                let new_id = self.backend.create(todo, project, parent, Some(&id))?;
+                self.synced.insert(new_id.clone());
+                if new_id != id {
+                    self.changes.push(Change::Relink { id, new_id: new_id.clone() });
+                }
+                Ok(Some(new_id))
+            }
+        }
+    }
+}
+
+/// Todoist as a backend for `reconcile`
+/// The API lists only open tasks, so finished ones are looked up one by one
+struct Todoist<'a> {
+    api: Api<'a>,
+}
+
+/// One sync with Todoist, run on its own thread
+fn sync(config: &TodoistConfig, lists: &[TodoList], state: &SyncState) -> Result<SyncOutcome, String> {
+    reconcile(&mut Todoist { api: Api { config } }, lists, state)
+}
+
+impl Backend for Todoist<'_> {
+    const SERVICE: Service = Service::Todoist;
+    const UNNAMED: &'static str = "Todoist";
+    const LISTS_CLOSED: bool = false;
+
+    fn projects(&mut self) -> Result<Vec<(String, String)>, String> {
+        Ok(self
+            .api
+            .list("/projects")?
+            .iter()
+            .filter_map(|project| Some((todoist_id(project.get("id"))?, project.get("name")?.as_str()?.to_string())))
+            .collect())
+    }
+
+    fn create_project(&mut self, name: &str) -> Result<String, String> {
+        let project = self.api.call("POST", "/projects", Some(&json!({ "name": name })))?;
+        Ok(project.and_then(|project| todoist_id(project.get("id"))).ok_or("Todoist didn't create the project")?)
+    }
+
+    fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String> {
+        let mut tasks: Vec<(i64, Task)> =
+            self.api.list("/tasks")?.iter().filter_map(|value| Some((todoist_order(value), Task::parse(value)?))).collect();
+        tasks.sort_by_key(|(order, _)| *order);
+        Ok(tasks.into_iter().map(|(_, task)| (task.id, task.task)).collect())
+    }
+
+    fn find(&mut self, id: &str) -> Result<Option<RemoteTask>, String> {
+        let task = self.api.call("GET", &format!("/tasks/{}", id), None)?;
+        Ok(task.as_ref().and_then(Task::parse).filter(|task| !task.deleted).map(|task| task.task))
+    }
+
+    fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool {
+        same(todo, task)
+    }
+
+    fn create(&mut self, todo: &TodoItem, project: &str, parent: Option<&str>, _: Option<&str>) -> Result<String, String> {
+        let mut fields = task_fields(todo);
+        fields["project_id"] = json!(project);
+        if let Some(parent) = parent {
+            fields["parent_id"] = json!(parent);
+        }
+        if let Some(object) = fields.as_object_mut() {
+            object.remove("due_string");
+            if let Some(rule) = todo.recurrence {
+                object.remove("due_date");
+                object.remove("due_datetime");
+                let start = todo.due.map(|date| format!(" starting {}", date)).unwrap_or_default();
+                object.insert("due_string".to_string(), json!(format!("{}{}", repeat_phrase(rule), start)));
+            }
+        }
+        let task = self.api.call("POST", "/tasks", Some(&fields))?;
+        Ok(task.and_then(|task| todoist_id(task.get("id"))).ok_or("Todoist didn't create the task")?)
+    }
+
+    fn update(&mut self, id: &str, todo: &TodoItem, task: &RemoteTask, _: &str, _: Option<&str>) -> Result<(), String> {
+        let task = &task.todo;
+        let repeating = todo.recurrence.is_some() && task.recurrence.is_some();
+        let mut fields = task_fields(todo);
+        if repeating && let Some(object) = fields.as_object_mut() {
+            object.retain(|key, _| !key.starts_with("due_"));
+        }
+        self.api.send("POST", &format!("/tasks/{}", id), Some(&fields))?;
+        match (todo.is_closed(), task.is_closed()) {
+            (true, false) => self.api.send("POST", &format!("/tasks/{}/close", id), None),
+            (false, true) => self.api.send("POST", &format!("/tasks/{}/reopen", id), None),
+            // Completing a repeating todo here moved it on - closing the task
+            // moves it on in Todoist too
+            _ if repeating && todo.due > task.due => self.api.send("POST", &format!("/tasks/{}/close", id), None),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete(&mut self, id: &str, _: &RemoteTask) -> Result<(), String> {
+        self.api.send("DELETE", &format!("/tasks/{}", id), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A service kept in memory, logging what it's asked to do
+    struct Fake {
+        tasks: Vec<(&'static str, TodoItem, Option<Timestamp>)>,
+        log: Vec<String>,
+    }
+
+    impl Backend for Fake {
+        const SERVICE: Service = Service::Todoist;
+        const UNNAMED: &'static str = "Fake";
+
+        fn projects(&mut self) -> Result<Vec<(String, String)>, String> {
+            Ok(vec![("p1".to_string(), "home".to_string())])
+        }
+
+        fn create_project(&mut self, name: &str) -> Result<String, String> {
+            Err(format!("made {}", name))
+        }
+
+        fn tasks(&mut self) -> Result<Vec<(String, RemoteTask)>, String> {
+            let task = |(id, todo, updated): &(&str, TodoItem, _)| {
+                (id.to_string(), RemoteTask { project: "p1".to_string(), parent: None, todo: todo.clone(), updated: *updated })
+            };
+            Ok(self.tasks.iter().map(task).collect())
+        }
+
+        fn same(&self, todo: &TodoItem, task: &TodoItem) -> bool {
+            todo.text == task.text
+        }
+
+        fn create(&mut self, todo: &TodoItem, _: &str, _: Option<&str>, _: Option<&str>) -> Result<String, String> {
+            self.log.push(format!("create {}", todo.text));
+            Ok("new".to_string())
+        }
+
+        fn update(&mut self, id: &str, todo: &TodoItem, _: &RemoteTask, _: &str, _: Option<&str>) -> Result<(), String> {
+            self.log.push(format!("update {} {}", id, todo.text));
+            Ok(())
+        }
+
+        fn delete(&mut self, id: &str, _: &RemoteTask) -> Result<(), String> {
+            self.log.push(format!("delete {}", id));
+            Ok(())
+        }
+    }
+
+    fn linked(text: &str, id: &str, modified: i64) -> TodoItem {
+        let mut todo = TodoItem::new(text);
+        Service::Todoist.link(&mut todo, id);
+        todo.modified_at = Some(Timestamp(modified));
+        todo
+    }
+
+    #[test]
+    fn both_sides_are_reconciled_by_what_changed_since_the_last_sync() {
+        let mut list = TodoList::new("Home");
+        list.todos = vec![
+            linked("pulled", "t1", 50),
+            linked("pushed", "t2", 150),
+            TodoItem::new("new here"),
+            linked("deleted there", "t4", 50),
+        ];
+        let mut fake = Fake {
+            tasks: vec![
+                ("t1", TodoItem::new("changed there"), Some(Timestamp(200))),
+                ("t2", TodoItem::new("stale"), Some(Timestamp(50))),
+                ("t3", TodoItem::new("deleted here"), None),
+                ("t5", TodoItem::new("new there"), None),
+            ],
+            log: Vec::new(),
+        };
+        let state = SyncState { last_sync: Some(Timestamp(100)), tasks: ["t1", "t2", "t3", "t4"].map(String::from).into() };
+        let Ok(outcome) = reconcile(&mut fake, std::slice::from_ref(&list), &state) else {
+            panic!("the sync failed");
+        };
+
+        let changes: Vec<String> = outcome
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::LinkList { name, id } => format!("link list {} {}", name, id),
+                Change::Pull { id, task } => format!("pull {} {}", id, task.text),
+                Change::Link { text, id, .. } => format!("link {} {}", text, id),
+                Change::Remove { id } => format!("remove {}", id),
+                Change::Add { project, task, .. } => format!("add {} {}", project, task.text),
+                Change::AddList { .. } | Change::Relink { .. } => "unexpected".to_string(),
+            })
+            .collect();
+        assert_eq!(changes, ["link list Home p1", "pull t1 changed there", "link new here new", "remove t4", "add p1 new there"]);
+        assert_eq!(fake.log, ["update t2 pushed", "create new here", "delete t3"]);
+        assert_eq!(outcome.tasks, ["new", "t1", "t2", "t5"].map(String::from).into());
+    }
+}
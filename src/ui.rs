@@ -0,0 +1,2762 @@
+//! Drawing the interface - every function here is a pure function of App state
+
+// Ratatui is our TUI framework - it provides widgets and layout management
+// We import specific components we need rather than using glob imports for clarity
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        Bar, BarChart, BarGroup, Block, Borders, Cell, Clear, Gauge, HighlightSpacing, List, ListItem, ListState,
+        Padding, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table, TableState, Tabs, Wrap,
+    },
+    Frame,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Range,
+    path::Path,
+};
+
+use crate::{
+    app::{
+        format_duration, item_at, parse_due, App, ArchivedTodo, Confirm, Date, Field, InputTarget, ListRow, Mode,
+        Pomodoro, PomodoroPhase, Priority, Status, TableColumn, Time, Timestamp, ToastKind, TodoItem, TodoList,
+    },
+    input::{keys_label, Action, COMMANDS, KEYMAP, SEQUENCES},
+    logging::LogLevel,
+    row_format::{Align, Piece, RowField},
+    sync::SyncStatus,
+};
+
+/// Every color the interface uses, so the whole look can be swapped at once
+/// Fields missing from a user theme fall back to the default theme's values
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct Theme {
+    /// Regular todo text
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) text: Color,
+    /// Text of completed todos (also struck through)
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) completed: Color,
+    /// Background of the selected row
+    #[serde(deserialize_with = "deserialize_color")]
+    selection_bg: Color,
+    /// Text color of the selected row - "reset" keeps each span's own color
+    #[serde(deserialize_with = "deserialize_color")]
+    selection_fg: Color,
+    /// Active input, focused borders and the current list tab
+    #[serde(deserialize_with = "deserialize_color")]
+    accent: Color,
+    /// Secondary text like labels and unfinished progress counters
+    #[serde(deserialize_with = "deserialize_color")]
+    muted: Color,
+    /// Finished progress counters
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) success: Color,
+    /// Tags after the todo text
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) tag: Color,
+    /// Due dates and the recurrence marker
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) due: Color,
+    /// Due dates that have passed
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) overdue: Color,
+    /// Background of rows marked for a bulk operation
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) marked: Color,
+    /// Text of todos that are in progress
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) in_progress: Color,
+    /// Text of blocked todos
+    #[serde(deserialize_with = "deserialize_color")]
+    pub(crate) blocked: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    priority_low: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    priority_medium: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    priority_high: Color,
+}
+
+impl Default for Theme {
+    /// The original dark look - traffic light priorities on a blue selection
+    fn default() -> Theme {
+        Theme {
+            text: Color::White,
+            completed: Color::DarkGray,
+            selection_bg: Color::Blue,
+            selection_fg: Color::Reset,
+            accent: Color::Yellow,
+            muted: Color::Gray,
+            success: Color::Green,
+            tag: Color::Cyan,
+            due: Color::Magenta,
+            overdue: Color::Red,
+            marked: Color::DarkGray,
+            in_progress: Color::LightCyan,
+            blocked: Color::LightRed,
+            priority_low: Color::Green,
+            priority_medium: Color::Yellow,
+            priority_high: Color::Red,
+        }
+    }
+}
+
+impl Theme {
+    /// Dark text for terminals with a light background
+    pub(crate) fn light() -> Theme {
+        Theme {
+            text: Color::Black,
+            completed: Color::Gray,
+            selection_bg: Color::LightBlue,
+            selection_fg: Color::Black,
+            accent: Color::Blue,
+            muted: Color::DarkGray,
+            tag: Color::Blue,
+            due: Color::Magenta,
+            marked: Color::Gray,
+            in_progress: Color::Cyan,
+            blocked: Color::Red,
+            ..Theme::default()
+        }
+    }
+
+    /// Solarized dark palette, using RGB values so it looks the same everywhere
+    pub(crate) fn solarized() -> Theme {
+        Theme {
+            text: Color::Rgb(0x93, 0xa1, 0xa1),
+            completed: Color::Rgb(0x58, 0x6e, 0x75),
+            selection_bg: Color::Rgb(0x07, 0x36, 0x42),
+            selection_fg: Color::Reset,
+            accent: Color::Rgb(0xb5, 0x89, 0x00),
+            muted: Color::Rgb(0x65, 0x7b, 0x83),
+            success: Color::Rgb(0x85, 0x99, 0x00),
+            tag: Color::Rgb(0x2a, 0xa1, 0x98),
+            due: Color::Rgb(0x6c, 0x71, 0xc4),
+            overdue: Color::Rgb(0xdc, 0x32, 0x2f),
+            marked: Color::Rgb(0x2e, 0x2a, 0x4a),
+            in_progress: Color::Rgb(0x26, 0x8b, 0xd2),
+            blocked: Color::Rgb(0xcb, 0x4b, 0x16),
+            priority_low: Color::Rgb(0x85, 0x99, 0x00),
+            priority_medium: Color::Rgb(0xcb, 0x4b, 0x16),
+            priority_high: Color::Rgb(0xdc, 0x32, 0x2f),
+        }
+    }
+
+    /// Color of the marker for a priority level
+    pub(crate) fn priority(&self, priority: Priority) -> Color {
+        match priority {
+            Priority::None => Color::Reset,
+            Priority::Low => self.priority_low,
+            Priority::Medium => self.priority_medium,
+            Priority::High => self.priority_high,
+        }
+    }
+
+    /// Style of a todo's text by status: closed todos are struck through,
+    /// cancelled ones also in italics to tell them apart from done ones
+    pub(crate) fn status(&self, status: Status) -> Style {
+        match status {
+            Status::Pending => Style::default().fg(self.text),
+            Status::InProgress => Style::default().fg(self.in_progress),
+            Status::Blocked => Style::default().fg(self.blocked),
+            Status::Done => Style::default().fg(self.completed).add_modifier(Modifier::CROSSED_OUT),
+            Status::Cancelled => {
+                Style::default().fg(self.completed).add_modifier(Modifier::CROSSED_OUT | Modifier::ITALIC)
+            }
+        }
+    }
+
+    /// Style applied on top of the selected row
+    pub(crate) fn selection(&self) -> Style {
+        let style = Style::default().bg(self.selection_bg).add_modifier(Modifier::BOLD);
+        // Reset means "leave the row's own colors alone" rather than forcing default
+        if self.selection_fg == Color::Reset { style } else { style.fg(self.selection_fg) }
+    }
+}
+
+/// Reads a color from a string - names like "light blue", "#ff8800" hex RGB,
+/// or a 0-255 palette index, using ratatui's own parser
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(|_| serde::de::Error::custom(format!("invalid color '{}'", s)))
+}
+
+/// Which symbols rows are drawn with, from "icons" in the config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IconSet {
+    /// Unicode where the locale is UTF-8, ASCII elsewhere
+    #[default]
+    Auto,
+    /// Checkmarks and arrows every UTF-8 terminal font has
+    Unicode,
+    /// Glyphs from a Nerd Font - they show as boxes in other fonts
+    Nerd,
+    /// Plain ASCII, for terminals and fonts that can't show anything else
+    Ascii,
+}
+
+impl IconSet {
+    /// The set `Auto` stands for, going by the locale the way the terminal
+    /// does: LC_ALL, then LC_CTYPE, then LANG
+    pub(crate) fn resolve(self) -> IconSet {
+        if self != IconSet::Auto {
+            return self;
+        }
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        if locale.contains("utf-8") || locale.contains("utf8") { IconSet::Unicode } else { IconSet::Ascii }
+    }
+
+    /// The symbols of the set - `Auto` has to be resolved first, and is
+    /// drawn as Unicode otherwise
+    pub(crate) fn icons(self) -> &'static Icons {
+        match self {
+            IconSet::Auto | IconSet::Unicode => &UNICODE_ICONS,
+            IconSet::Nerd => &NERD_ICONS,
+            IconSet::Ascii => &ASCII_ICONS,
+        }
+    }
+}
+
+/// The symbols of one icon set
+pub(crate) struct Icons {
+    /// A checkbox for each status, in the order of `Status::ALL`
+    checkboxes: [&'static str; 5],
+    /// Markers for low, medium and high priority
+    priorities: [&'static str; 3],
+    /// On starred todos
+    pub(crate) star: &'static str,
+    /// On the someday list's tab
+    pub(crate) someday: &'static str,
+    /// On recurring todos
+    pub(crate) repeat: &'static str,
+    /// While time is being tracked
+    pub(crate) tracking: &'static str,
+    /// On todos with notes attached
+    pub(crate) notes: &'static str,
+    /// Fold markers of parents with their subtasks hidden and shown, two
+    /// columns wide like the blank in front of todos without subtasks
+    pub(crate) collapsed: &'static str,
+    pub(crate) expanded: &'static str,
+    /// In front of the selected row, HIGHLIGHT_SYMBOL_WIDTH wide
+    pub(crate) selection: &'static str,
+}
+
+impl Icons {
+    pub(crate) fn checkbox(&self, status: Status) -> &'static str {
+        self.checkboxes[Status::ALL.iter().position(|&s| s == status).unwrap_or(0)]
+    }
+
+    /// The marker for a priority, empty for none
+    pub(crate) fn priority(&self, priority: Priority) -> &'static str {
+        match priority {
+            Priority::None => "",
+            Priority::Low => self.priorities[0],
+            Priority::Medium => self.priorities[1],
+            Priority::High => self.priorities[2],
+        }
+    }
+}
+
+const UNICODE_ICONS: Icons = Icons {
+    checkboxes: ["[ ]", "[~]", "[!]", "[✓]", "[✗]"],
+    priorities: ["!", "!!", "!!!"],
+    star: "★",
+    someday: "☾",
+    repeat: "↻",
+    tracking: "⏱",
+    notes: "✎",
+    collapsed: "▸ ",
+    expanded: "▾ ",
+    selection: "► ",
+};
+
+/// Font Awesome glyphs as Nerd Fonts number them
+const NERD_ICONS: Icons = Icons {
+    // Square, dotted circle, ban, checked square, cross
+    checkboxes: ["\u{f096}", "\u{f192}", "\u{f05e}", "\u{f046}", "\u{f00d}"],
+    // One flag per level, like the exclamation marks
+    priorities: ["\u{f024}", "\u{f024}\u{f024}", "\u{f024}\u{f024}\u{f024}"],
+    // Star, moon, repeat, clock, paperclip
+    star: "\u{f005}",
+    someday: "\u{f186}",
+    repeat: "\u{f01e}",
+    tracking: "\u{f017}",
+    notes: "\u{f0c6}",
+    // Angles right and down, chevron right
+    collapsed: "\u{f105} ",
+    expanded: "\u{f107} ",
+    selection: "\u{f054} ",
+};
+
+const ASCII_ICONS: Icons = Icons {
+    checkboxes: ["[ ]", "[~]", "[!]", "[x]", "[-]"],
+    priorities: ["!", "!!", "!!!"],
+    star: "*",
+    someday: "~",
+    repeat: "(r)",
+    tracking: "(t)",
+    notes: "(n)",
+    collapsed: "+ ",
+    expanded: "- ",
+    selection: "> ",
+};
+
+/// How a tag is drawn: as a chip in a color of its own, with an icon in front
+/// of its name, or both - tags without one are `#tag` in the theme's tag color
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct TagStyle {
+    #[serde(
+        deserialize_with = "deserialize_optional_color",
+        serialize_with = "serialize_optional_color",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) color: Option<Color>,
+    /// An emoji or symbol, shown instead of the #
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) icon: Option<String>,
+}
+
+impl TagStyle {
+    /// Reads a style as typed in the tag picker: a color, an icon, or a color
+    /// and then an icon, like "red", "💼" or "light blue 💼"
+    pub(crate) fn parse(text: &str) -> Result<TagStyle, String> {
+        let text = text.trim();
+        if let Ok(color) = text.parse() {
+            return Ok(TagStyle { color: Some(color), icon: None });
+        }
+        let (color, icon) = text.rsplit_once(char::is_whitespace).unwrap_or(("", text));
+        // A word isn't an icon, so it's taken for a misspelled color
+        if icon.chars().all(|c| c.is_alphanumeric()) {
+            return Err(format!("unknown color '{}'", text));
+        }
+        let color = match color.trim() {
+            "" => None,
+            color => Some(color.parse().map_err(|_| format!("unknown color '{}'", color))?),
+        };
+        Ok(TagStyle { color, icon: Some(icon.to_string()) })
+    }
+}
+
+impl fmt::Display for TagStyle {
+    /// The style the way `parse` reads it, for editing it in the prompt
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color = self.color.map(|color| color.to_string().to_lowercase());
+        let words: Vec<&str> = color.as_deref().into_iter().chain(self.icon.as_deref()).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+fn deserialize_optional_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map(Some).map_err(|_| serde::de::Error::custom(format!("invalid color '{}'", s)))
+}
+
+fn serialize_optional_color<S: Serializer>(color: &Option<Color>, serializer: S) -> Result<S::Ok, S::Error> {
+    color.map(|color| color.to_string()).serialize(serializer)
+}
+
+/// Width of the "► " selection marker in front of every list row
+/// Mouse hit testing needs it to find where the checkbox starts
+pub(crate) const HIGHLIGHT_SYMBOL_WIDTH: u16 = 2;
+
+/// Smallest terminal the interface is drawn in: the list needs room for its
+/// border and a couple of todos, the status bar for the mode and a count
+const MIN_WIDTH: u16 = 24;
+const MIN_HEIGHT: u16 = 8;
+
+/// Below either of these the margin and the input box's border go, which
+/// together take eight rows and columns a small terminal can't spare
+const COMPACT_WIDTH: u16 = 60;
+const COMPACT_HEIGHT: u16 = 20;
+
+/// Width of the completion gauge at the end of the tab bar
+const GAUGE_WIDTH: u16 = 24;
+
+/// Whether a terminal of this size is below the minimum, where only a notice
+/// saying so is drawn
+pub(crate) fn too_small(width: u16, height: u16) -> bool {
+    width < MIN_WIDTH || height < MIN_HEIGHT
+}
+
+/// Draws the whole interface for one frame
+/// Kept separate from the event loop so rendering stays a pure function of App state
+pub fn ui(f: &mut Frame, app: &mut App) {
+    // Cloned so the theme can be used while app is borrowed mutably below
+    let theme = app.theme().clone();
+
+    // The size is checked again here, not only on Resize, since the first
+    // frame is drawn before any resize arrives
+    let area = f.area();
+    app.too_small = too_small(area.width, area.height);
+    if app.too_small {
+        // Nothing is drawn where a click could land or a page could scroll
+        app.list_height = 0;
+        app.list_area = Rect::default();
+        render_too_small(f, app);
+        return;
+    }
+    let compact = area.width < COMPACT_WIDTH || area.height < COMPACT_HEIGHT;
+
+    // Create a two-panel vertical layout
+    // Using constraints allows ratatui to handle terminal resizing gracefully
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        // Padding so content doesn't touch screen edges, when there's room for it
+        .margin(if compact { 0 } else { 2 })
+        .constraints([
+            Constraint::Length(1), // List tabs are a single line
+            Constraint::Min(1),    // Todo list takes remaining space
+            // The input box, or a single-line prompt on a small terminal
+            Constraint::Length(if compact { 1 } else { 3 }),
+            Constraint::Length(1), // Status bar
+            // File errors get a line of their own until dismissed
+            Constraint::Length(u16::from(app.error.is_some())),
+        ].as_ref())
+        .split(f.area());
+
+    // Tab bar with every list, numbered to match the keys that switch to them
+    // The someday list is marked, since it's a place to park todos rather than a list
+    let titles: Vec<String> = app
+        .lists
+        .iter()
+        .enumerate()
+        .map(|(i, list)| match list.someday {
+            true => format!("{} {} {}", i + 1, app.icons().someday, list.name),
+            false => format!("{} {}", i + 1, list.name),
+        })
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(app.current)
+        .highlight_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD));
+    // How much of the list is done sits at the end of the tab bar, when
+    // there's room for it next to the tabs
+    let (total, done) = count_todos(app.todos());
+    if total > 0 && chunks[0].width >= COMPACT_WIDTH {
+        let [tabs_area, gauge_area] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(GAUGE_WIDTH)]).spacing(1).areas(chunks[0]);
+        let label = format!("{} · {}%", app.trf("{}/{} done", &[&done, &total]), percent(done, total));
+        let gauge = Gauge::default()
+            .ratio(done as f64 / total as f64)
+            .label(label)
+            .gauge_style(Style::default().fg(theme.success).bg(theme.marked));
+        f.render_widget(tabs, tabs_area);
+        f.render_widget(gauge, gauge_area);
+    } else {
+        f.render_widget(tabs, chunks[0]);
+    }
+
+    // When the detail pane is open, the list shares the middle area with it
+    let (list_area, detail_area) = if app.show_details {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(chunks[1]);
+        (columns[0], Some(columns[1]))
+    } else {
+        (chunks[1], None)
+    };
+
+    // Remember how many rows fit so page up/down can move by a screenful
+    // The border takes one row at the top and bottom, the table's column
+    // titles another
+    app.list_height = list_area.height.saturating_sub(2 + u16::from(app.table)) as usize;
+    app.list_area = list_area;
+
+    // Scroll first, so only the rows that fit on screen are turned into
+    // ListItems - a list of a hundred thousand todos draws as fast as ten
+    // Headers take rows of their own, so the selection is translated to the
+    // drawn row; the offset is kept in drawn rows throughout
+    // Wrapped todos can take several lines, so the window is measured in
+    // lines, building only the rows near it to find out how many they take
+    // Built rows are kept for the next frames until they may look different
+    let wrap = (app.wrap && !app.table).then(|| list_area.width.saturating_sub(2 + HIGHLIGHT_SYMBOL_WIDTH) as usize);
+    let row_count = app.rows.len();
+    let selected = app.state().selected().and_then(|i| app.row_of.get(i).copied());
+    let mut cache = std::mem::take(&mut app.row_cache);
+    cache.start_frame(app, wrap);
+    let mut lines_of = |row: usize| match wrap {
+        Some(_) => cache.lines(app, &theme, row).len(),
+        None => 1,
+    };
+    let offset = scroll_offset(app.state().offset(), selected, app.list_height, row_count, &mut lines_of);
+    // The drawn row on each line, for finding what a click landed on
+    let mut list_lines = Vec::new();
+    let mut end = offset;
+    while end < row_count && list_lines.len() < app.list_height {
+        let lines = lines_of(end);
+        // The list leaves out a row that doesn't fit whole, unless it's the only one
+        if list_lines.len() + lines > app.list_height && end > offset {
+            break;
+        }
+        list_lines.extend(std::iter::repeat_n(end, lines));
+        end += 1;
+    }
+    list_lines.truncate(app.list_height);
+    app.list_lines = list_lines;
+
+    // The items are only the window on screen, so the selection is counted
+    // from its top and the list itself never scrolls
+    // A terminal too small to show any rows leaves the selection outside the window
+    let selected_on_screen = selected.and_then(|row| row.checked_sub(offset));
+    if app.table {
+        render_table(f, app, &theme, list_area, offset..end, selected_on_screen);
+    } else {
+        // Marked rows get a background so they stand out from the selection
+        let items: Vec<ListItem> = (offset..end)
+            .map(|row| {
+                let item = ListItem::new(cache.lines(app, &theme, row).to_vec());
+                let marked = matches!(&app.rows[row], ListRow::Todo(i) if app.marked.contains(&app.visible[*i]));
+                if marked { item.style(Style::default().bg(theme.marked)) } else { item }
+            })
+            .collect();
+
+        // Create the list widget with all our styled items
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    // Counts, sort and filters live in the status bar below
+                    .title(app.tr("📝 Todo List")),
+            )
+            // Highlight style makes it clear which item is selected
+            // The default theme's blue background is conventional for selection in TUIs
+            .highlight_style(theme.selection())
+            // Arrow symbol provides additional visual cue for selection
+            // Its width is HIGHLIGHT_SYMBOL_WIDTH, which mouse hit testing relies on
+            .highlight_symbol(app.icons().selection);
+
+        // Render the list with its stateful selection
+        // The list items borrow the todos, so the scrolling is stored back after
+        let mut state = ListState::default().with_selected(selected_on_screen);
+        f.render_stateful_widget(list, list_area, &mut state);
+    }
+    app.row_cache = cache;
+    *app.state_mut().offset_mut() = offset;
+
+    // An empty list says how to fill it, and an empty view why it's empty
+    if row_count == 0 {
+        let hint = match app.todos().is_empty() {
+            true => app.trf("No todos yet - press {} to add one", &[&app.keymap.label(Action::Add)]),
+            false => app.tr("No todos match the filters").to_string(),
+        };
+        let hint = Paragraph::new(Span::styled(hint, Style::default().fg(theme.muted))).alignment(Alignment::Center);
+        let inner = list_area.inner(Margin { vertical: 1, horizontal: 1 });
+        f.render_widget(hint, Rect { y: inner.y + inner.height / 2, height: inner.height.min(1), ..inner });
+    }
+
+    // Only show a scrollbar when there's something to scroll
+    // It sits on the right border so it doesn't take space from the todos
+    if row_count > app.list_height {
+        let mut scrollbar_state = ScrollbarState::new(row_count.saturating_sub(app.list_height))
+            .position(app.state().offset());
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(Some("▲"))
+            .end_symbol(Some("▼"))
+            .thumb_style(Style::default().fg(theme.accent));
+        f.render_stateful_widget(
+            scrollbar,
+            list_area.inner(Margin { vertical: 1, horizontal: 0 }),
+            &mut scrollbar_state,
+        );
+    }
+
+    if let Some(area) = detail_area {
+        render_details(f, app, area);
+    }
+
+    // The board takes the place of the list and detail pane
+    if app.mode == Mode::Board {
+        render_board(f, app, chunks[1]);
+    }
+
+    // Update input area text based on current mode
+    // This provides context-sensitive help to the user
+    let input_text = match app.mode {
+        Mode::Input => {
+            let label = app.tr(match app.input_target {
+                InputTarget::New => "New todo",
+                InputTarget::Child(_) => "New subtask",
+                // Notes use their own mode, so this arm is only reached for edits
+                InputTarget::Edit(_) | InputTarget::Notes(_) => "Edit todo",
+                InputTarget::Due(_) => "Due (e.g. 2025-06-01, tomorrow, next monday, in 3 days, fri 5pm; empty to clear)",
+                InputTarget::Snooze(_) => "Snooze until (e.g. tomorrow, next week, fri 9am, 2025-06-01; empty to wake it)",
+                InputTarget::Recurrence(_) => "Repeat (daily, weekly, monthly, every N days; empty to clear)",
+                InputTarget::NewList => "New list name",
+                InputTarget::RenameList => "Rename list",
+                InputTarget::Tag => "Add tag (-tag to remove)",
+                InputTarget::MoveToList => "Move to list (number or name)",
+                InputTarget::SaveFilter => "Save filter as",
+                InputTarget::TagStyle(_) => "Tag color and icon (e.g. red, 💼 or light blue 💼; empty to clear)",
+                InputTarget::Command => "Command (Tab: complete, ↑/↓: history)",
+                InputTarget::Passphrase(None) => "New passphrase",
+                InputTarget::Passphrase(Some(_)) => "Repeat the passphrase",
+            });
+            // A passphrase shows as one dot per character, never as typed
+            let (typed, shown) = match app.input_target {
+                InputTarget::Passphrase(_) => (
+                    mask(&app.input[..app.cursor()]),
+                    mask(&app.input),
+                ),
+                _ => (app.input[..app.cursor()].to_string(), app.input.clone()),
+            };
+            // Put the terminal cursor where typing will insert, past the border and label
+            // Measured in display columns, since CJK and emoji take two cells each
+            let column = format!("{}: {}", label, typed).width() as u16;
+            let inner = if compact { chunks[2] } else { chunks[2].inner(Margin { vertical: 1, horizontal: 1 }) };
+            if column < inner.width {
+                f.set_cursor_position(Position { x: inner.x + column, y: inner.y });
+            }
+            app.trf("{}: {} (Press Enter to confirm, Esc to cancel)", &[&label, &shown])
+        }
+        Mode::Search => app.trf("/{} (Enter: keep filter, Esc: clear)", &[&app.search]),
+        Mode::Notes => app.tr("Editing notes (Enter: new line, Esc: save)").to_string(),
+        _ => app.tr("Press 'a' to add a new todo (use #tag to tag it), '/' to search").to_string(),
+    };
+
+    // Style input area differently when active to show mode clearly
+    // Yellow is attention-getting and conventional for "active" state
+    let input_style = if matches!(app.mode, Mode::Input | Mode::Search | Mode::Notes) {
+        Style::default().fg(theme.accent)
+    } else {
+        Style::default()
+    };
+    if compact {
+        // Without a border there's no title for previews and hints, but an
+        // input error still needs showing, so it follows what was typed
+        let mut prompt = vec![Span::styled(input_text, input_style)];
+        if let Some(err) = app.input_error.as_ref().filter(|_| app.mode != Mode::Unlock) {
+            prompt.push(Span::styled(format!(" - {}", err), Style::default().fg(theme.overdue)));
+        }
+        f.render_widget(Paragraph::new(Line::from(prompt)), chunks[2]);
+    } else {
+        let input = Paragraph::new(input_text)
+            .style(input_style)
+            .block(Block::default().borders(Borders::ALL).title(match (&app.input_error, due_preview(app)) {
+                // The unlock dialog shows its own error
+                (Some(_), _) if app.mode == Mode::Unlock => Line::from(app.tr("Input")),
+                (Some(err), _) => Line::styled(app.trf("Input - {}", &[err]), Style::default().fg(theme.overdue)),
+                (None, Some(preview)) => Line::from(app.trf("Input - {}", &[&preview])),
+                (None, None) => match command_hint(app) {
+                    Some(hint) => Line::from(app.trf("Input - {}", &[&hint])),
+                    None => Line::from(app.tr("Input")),
+                },
+            }));
+        f.render_widget(input, chunks[2]);
+    }
+
+    render_status_bar(f, app, chunks[3]);
+    render_error_bar(f, app, chunks[4]);
+
+    // Popups are drawn last so they sit on top of everything else
+    if app.mode == Mode::TagPicker {
+        render_tag_picker(f, app);
+    }
+    if app.mode == Mode::ProjectPicker {
+        render_project_picker(f, app);
+    }
+    if app.mode == Mode::FilterPicker {
+        render_filter_picker(f, app);
+    }
+    // The trash stays visible behind the popup confirming a permanent delete
+    if app.mode == Mode::Trash || matches!(app.pending, Some(Confirm::Purge(_) | Confirm::EmptyTrash)) {
+        render_trash(f, app);
+    }
+    if app.mode == Mode::Archive {
+        render_archive(f, app);
+    }
+    if app.mode == Mode::Snoozed {
+        render_snoozed(f, app);
+    }
+    if app.mode == Mode::Confirm {
+        render_confirm(f, app);
+    }
+    if app.mode == Mode::Stats {
+        render_stats(f, app);
+    }
+    if app.mode == Mode::Help {
+        render_help(f, app);
+    }
+    if app.mode == Mode::Recovery {
+        render_recovery(f, app);
+    }
+    if app.mode == Mode::History {
+        render_history(f, app);
+    }
+    if app.mode == Mode::Unlock {
+        render_unlock(f, app);
+    }
+    if app.mode == Mode::Welcome {
+        render_welcome(f, app);
+    }
+    if app.mode == Mode::Review {
+        render_review(f, app);
+    }
+    // Toasts are short and in a corner, so they can sit over a popup too
+    // Inside the border, which stays whole around them
+    render_toasts(f, app, chunks[1].inner(Margin { vertical: 1, horizontal: 1 }));
+    // Over the popups too, since they're often what a report is about
+    if app.show_debug {
+        render_debug(f, app);
+    }
+}
+
+/// Drawn instead of the interface on a terminal below the minimum size, with
+/// how big it is and how big it needs to be
+fn render_too_small(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let size = app.trf("{}×{}, needs {}×{}", &[&area.width, &area.height, &MIN_WIDTH, &MIN_HEIGHT]);
+    let text = vec![
+        Line::styled(app.tr("Terminal too small"), Style::default().fg(theme.overdue).add_modifier(Modifier::BOLD)),
+        Line::styled(size, Style::default().fg(theme.muted)),
+    ];
+    // Centered on its two lines, wrapping downwards if it's narrower than them
+    let top = area.height.saturating_sub(2) / 2;
+    let area = Rect { y: area.y + top, height: area.height - top, ..area };
+    f.render_widget(Paragraph::new(text).alignment(Alignment::Center).wrap(Wrap { trim: true }), area);
+}
+
+/// Draws the debug overlay: how long frames take and the latest log lines
+/// It's for troubleshooting reports rather than everyday use, so it stays in
+/// English like the log itself
+fn render_debug(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let frames = &app.frame_times;
+    let millis = |duration: std::time::Duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+    let timing = match frames.back() {
+        Some(last) => format!(
+            "frames: last {} · avg {} · max {} over {}",
+            millis(*last),
+            millis(frames.iter().sum::<std::time::Duration>() / frames.len() as u32),
+            millis(frames.iter().max().copied().unwrap_or_default()),
+            frames.len()
+        ),
+        None => "frames: none drawn yet".to_string(),
+    };
+    let log = match crate::logging::log_path() {
+        Some(path) if app.log_level != LogLevel::Off => format!("log: {} ({:?})", path.display(), app.log_level),
+        _ => "log: off".to_string(),
+    };
+
+    let area = f.area();
+    let area = Rect { x: area.width / 3, width: area.width - area.width / 3, ..area };
+    let mut text = vec![Line::raw(timing), Line::styled(log, muted), Line::raw("")];
+    // The newest lines at the bottom, as many as fit
+    let room = (area.height as usize).saturating_sub(text.len() + 2);
+    let recent = crate::logging::recent();
+    text.extend(recent[recent.len().saturating_sub(room)..].iter().map(|line| Line::raw(line.clone())));
+
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .title("Debug (F12: close)"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// The lines of the list's rows drawn lately, so scrolling and moving the
+/// selection don't style and wrap the same todos on every frame
+/// They're dropped once the rows may look different: the view was rebuilt,
+/// the list got another width, theme or icons, or a new minute moved due
+/// dates and tracked time on
+#[derive(Default)]
+pub(crate) struct RowCache {
+    key: Option<RowKey>,
+    lines: HashMap<usize, Vec<Line<'static>>>,
+}
+
+/// What the cached rows were built for
+#[derive(Clone, Copy, PartialEq)]
+struct RowKey {
+    revision: u64,
+    wrap: Option<usize>,
+    minute: i64,
+    theme: usize,
+    icons: IconSet,
+}
+
+impl RowCache {
+    /// Drops the rows when this frame draws them differently
+    fn start_frame(&mut self, app: &App, wrap: Option<usize>) {
+        let minute = Timestamp::now().0.div_euclid(60);
+        let key = RowKey { revision: app.row_revision, wrap, minute, theme: app.theme, icons: app.icon_set };
+        if self.key != Some(key) {
+            self.key = Some(key);
+            self.lines.clear();
+        }
+    }
+
+    /// The lines of a drawn row, built the first time they're asked for
+    fn lines(&mut self, app: &App, theme: &Theme, row: usize) -> &[Line<'static>] {
+        let wrap = self.key.and_then(|key| key.wrap);
+        self.lines.entry(row).or_insert_with(|| row_lines(app, theme, &app.rows[row], wrap))
+    }
+}
+
+/// The lines of one drawn row: a project header, or a todo with its
+/// checkbox, priority, dates and tags
+/// With a width to wrap at, the todo takes as many lines as its text needs,
+/// the lines after the first starting under the text rather than the checkbox
+fn row_lines(app: &App, theme: &Theme, row: &ListRow, wrap: Option<usize>) -> Vec<Line<'static>> {
+    let path = match row {
+        ListRow::Todo(i) => &app.visible[*i],
+        ListRow::Header(project) => {
+            let (done, total) = app.project_progress(project);
+            let name = project.as_deref().map_or(app.tr("No project").to_string(), |p| format!("+{}", p));
+            let color = if done == total { theme.success } else { theme.muted };
+            return vec![Line::from(vec![
+                Span::styled(name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                Span::styled(format!(" {}/{} · {}%", done, total, percent(done, total)), Style::default().fg(color)),
+            ])];
+        }
+    };
+    let todo = item_at(app.todos(), path);
+
+    // Indent subtasks by depth and show a fold marker on parents - the tree
+    // is drawn whatever the row format says, so subtasks stay recognizable
+    let (indent, fold) = tree_prefix(app.icons(), path, todo);
+    let mut spans = vec![Span::raw(indent), Span::raw(fold)];
+    // Wrapped lines hang under the text, so it's remembered where that starts
+    let mut hang = 0;
+    // Text in front of a field belongs to it, so a field the todo doesn't
+    // have leaves no gap behind
+    let mut before: Option<&str> = None;
+    for piece in &app.row_format.pieces {
+        match piece {
+            Piece::Literal(text) => before = Some(text),
+            Piece::Field { field, align, width } => {
+                let content = field_spans(app, theme, todo, *field);
+                if content.is_empty() && width.is_none() {
+                    before = None;
+                    continue;
+                }
+                spans.extend(before.take().map(Span::raw));
+                if *field == RowField::Text {
+                    hang = spans.iter().map(Span::width).sum();
+                }
+                match width {
+                    Some(width) => spans.extend(fit(content, *width, *align)),
+                    None => spans.extend(content),
+                }
+            }
+        }
+    }
+    spans.extend(before.map(Span::raw));
+    match wrap {
+        Some(width) => wrap_line(spans, width, hang),
+        // Kept past this frame, so the spans own their text
+        None => {
+            let spans: Vec<Span<'static>> =
+                spans.into_iter().map(|span| Span::styled(span.content.into_owned(), span.style)).collect();
+            vec![Line::from(spans)]
+        }
+    }
+}
+
+/// Draws the list as a table with a column per field, the column the list is
+/// sorted by marked in its title
+fn render_table(f: &mut Frame, app: &mut App, theme: &Theme, area: Rect, rows: Range<usize>, selected: Option<usize>) {
+    let block = Block::default().borders(Borders::ALL).title(app.tr("📝 Todo List"));
+    // The columns are laid out here rather than by the table, so clicks on
+    // their titles can be matched to them
+    let inner = block.inner(area);
+    // The title gets most of what's left over, the tags the rest
+    let widths = [
+        Constraint::Length(15),
+        Constraint::Fill(2),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Fill(1),
+    ];
+    let columns = Layout::horizontal(widths).spacing(1).split(Rect {
+        x: inner.x + HIGHLIGHT_SYMBOL_WIDTH,
+        width: inner.width.saturating_sub(HIGHLIGHT_SYMBOL_WIDTH),
+        ..inner
+    });
+    app.table_columns = columns.iter().map(|column| (column.x, column.right())).collect();
+
+    let titles = TableColumn::ALL.iter().map(|column| {
+        let sorted = if app.sort_mode == column.sort() { " ▾" } else { "" };
+        format!("{}{}", app.tr(column.title()), sorted)
+    });
+    let header = Row::new(titles).style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD));
+    let rows: Vec<Row> = app.rows[rows].iter().map(|row| table_row(app, theme, row)).collect();
+    let table = Table::new(rows, columns.iter().map(|column| Constraint::Length(column.width)))
+        .header(header)
+        .block(block)
+        .column_spacing(1)
+        .row_highlight_style(theme.selection())
+        // The symbol's room is kept when nothing is selected, so the columns
+        // stay where `table_columns` says
+        .highlight_symbol(app.icons().selection)
+        .highlight_spacing(HighlightSpacing::Always);
+    let mut state = TableState::default().with_selected(selected);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// The table row for one drawn row: a project header in the title column, or
+/// a todo's status, text, priority, due date and tags
+fn table_row<'a>(app: &'a App, theme: &Theme, row: &ListRow) -> Row<'a> {
+    let path = match row {
+        ListRow::Todo(i) => &app.visible[*i],
+        ListRow::Header(project) => {
+            let (done, total) = app.project_progress(project);
+            let name = project.as_deref().map_or(app.tr("No project").to_string(), |p| format!("+{}", p));
+            let color = if done == total { theme.success } else { theme.muted };
+            let title = Line::from(vec![
+                Span::styled(name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                Span::styled(format!(" {}/{} · {}%", done, total, percent(done, total)), Style::default().fg(color)),
+            ]);
+            return Row::new([Cell::default(), Cell::from(title)]);
+        }
+    };
+    let todo = item_at(app.todos(), path);
+    let icons = app.icons();
+    let status = Line::from(vec![Span::raw(icons.checkbox(todo.status)), Span::raw(" "), Span::raw(app.tr(todo.status.label()))]);
+    let (indent, fold) = tree_prefix(icons, path, todo);
+    let mut title = vec![Span::raw(indent), Span::raw(fold)];
+    if let Some(star) = field_spans(app, theme, todo, RowField::Star).pop() {
+        title.extend([star, Span::raw(" ")]);
+    }
+    title.push(Span::styled(&todo.text, theme.status(todo.status)));
+    let priority = match todo.priority {
+        Priority::None => Span::raw(""),
+        priority => Span::styled(
+            app.tr(priority.label()),
+            Style::default().fg(theme.priority(priority)).add_modifier(Modifier::BOLD),
+        ),
+    };
+    let color = if todo.is_overdue() { theme.overdue } else { theme.due };
+    let due = Span::styled(app.dates.due_label(todo).unwrap_or_default(), Style::default().fg(color));
+    let tags = Line::from(field_spans(app, theme, todo, RowField::Tags));
+    let row = Row::new([Cell::from(status), Cell::from(Line::from(title)), Cell::from(priority), Cell::from(due), Cell::from(tags)]);
+    // Marked rows get a background so they stand out from the selection
+    if app.marked.contains(path) {
+        row.style(Style::default().bg(theme.marked))
+    } else {
+        row
+    }
+}
+
+/// The indent that puts a subtask under its parent and the fold marker that
+/// shows whether a parent's subtasks are shown
+fn tree_prefix(icons: &Icons, path: &[usize], todo: &TodoItem) -> (String, &'static str) {
+    let fold = match (todo.children.is_empty(), todo.collapsed) {
+        (true, _) => "  ",
+        (false, true) => icons.collapsed,
+        (false, false) => icons.expanded,
+    };
+    ("  ".repeat(path.len() - 1), fold)
+}
+
+/// What a row shows for one field of a todo, nothing when the todo doesn't
+/// have it
+fn field_spans<'a>(app: &'a App, theme: &Theme, todo: &'a TodoItem, field: RowField) -> Vec<Span<'a>> {
+    let span = match field {
+        // Use checkbox pattern familiar from many todo apps
+        RowField::Checkbox => Span::raw(app.icons().checkbox(todo.status)),
+        // Priority marker sits between checkbox and text, colored by urgency
+        RowField::Priority => Span::styled(
+            app.icons().priority(todo.priority),
+            Style::default().fg(theme.priority(todo.priority)).add_modifier(Modifier::BOLD),
+        ),
+        // Strikethrough + dark gray is standard convention for completed tasks
+        RowField::Text => Span::styled(&todo.text, theme.status(todo.status)),
+        // Parents show how many of their subtasks are done
+        RowField::Progress => match todo.progress() {
+            Some((done, total)) => {
+                let color = if done == total { theme.success } else { theme.muted };
+                Span::styled(format!("{}/{}", done, total), Style::default().fg(color))
+            }
+            None => return Vec::new(),
+        },
+        // Due date turns red once it's passed so overdue work stands out
+        RowField::Due => match app.dates.due_label(todo) {
+            Some(due) => {
+                let color = if todo.is_overdue() { theme.overdue } else { theme.due };
+                Span::styled(app.trf("due {}", &[&due]), Style::default().fg(color))
+            }
+            None => return Vec::new(),
+        },
+        RowField::Starts => match todo.scheduled {
+            Some(starts) => Span::styled(app.trf("starts {}", &[&app.dates.date(starts)]), Style::default().fg(theme.muted)),
+            None => return Vec::new(),
+        },
+        RowField::Star if todo.starred => {
+            Span::styled(app.icons().star, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD))
+        }
+        // Recurring todos get a loop arrow so they're recognizable at a glance
+        RowField::Repeat if todo.recurrence.is_some() => Span::styled(app.icons().repeat, Style::default().fg(theme.due)),
+        RowField::Tracking if todo.tracking_since.is_some() => Span::styled(app.icons().tracking, Style::default().fg(theme.accent)),
+        RowField::Notes if !todo.notes.trim().is_empty() => Span::styled(app.icons().notes, Style::default().fg(theme.muted)),
+        RowField::Time => match todo.tracked_seconds(Timestamp::now()) {
+            0 => return Vec::new(),
+            spent => Span::styled(format_duration(spent), Style::default().fg(theme.muted)),
+        },
+        // The group header already names the project
+        RowField::Project => match todo.project.as_ref().filter(|_| !app.group_by_project) {
+            Some(project) => Span::styled(format!("+{}", project), Style::default().fg(theme.accent)),
+            None => return Vec::new(),
+        },
+        // Tags trail the text in a muted color so they don't compete with it
+        RowField::Tags => {
+            let mut spans = Vec::new();
+            for tag in &todo.labels() {
+                if !spans.is_empty() {
+                    spans.push(Span::raw(" "));
+                }
+                spans.push(tag_span(app, theme, tag));
+            }
+            return spans;
+        }
+        RowField::Created => match todo.created_at {
+            Some(created) => Span::styled(app.dates.date(created.date()), Style::default().fg(theme.muted)),
+            None => return Vec::new(),
+        },
+        RowField::Star | RowField::Repeat | RowField::Tracking | RowField::Notes => return Vec::new(),
+    };
+    if span.content.is_empty() { Vec::new() } else { vec![span] }
+}
+
+/// A tag as rows and the tag picker show it: a chip in the tag's own color
+/// with its icon in front when it has them, otherwise `#tag` or `@context`
+fn tag_span(app: &App, theme: &Theme, tag: &str) -> Span<'static> {
+    let style = app.tag_style(tag);
+    let name = match style.and_then(|style| style.icon.as_ref()) {
+        Some(icon) => format!("{} {}", icon, tag),
+        None => label_name(tag),
+    };
+    match style.and_then(|style| style.color) {
+        Some(color) => Span::styled(format!(" {} ", name), Style::default().fg(chip_text(color)).bg(color)),
+        None => Span::styled(name, Style::default().fg(theme.tag)),
+    }
+}
+
+/// A tag as `#tag`, and a context label as the `@context` it already is
+fn label_name(label: &str) -> String {
+    if label.starts_with('@') { label.to_string() } else { format!("#{}", label) }
+}
+
+/// Text color that stays readable on a chip of this color: white on dark
+/// colors, black on light ones
+fn chip_text(color: Color) -> Color {
+    let dark = match color {
+        Color::Black | Color::Red | Color::Green | Color::Blue | Color::Magenta | Color::DarkGray => true,
+        // Perceived brightness, weighting green most like the eye does
+        Color::Rgb(r, g, b) => 299 * r as u32 + 587 * g as u32 + 114 * b as u32 <= 128_000,
+        Color::Indexed(i) => matches!(i, 0..=6 | 8 | 16..=21 | 232..=243),
+        _ => false,
+    };
+    if dark { Color::White } else { Color::Black }
+}
+
+/// Pads a field to exactly `width` columns on the side `align` says, or cuts
+/// it off with an ellipsis when it's wider, so the fields after it line up
+fn fit(spans: Vec<Span<'_>>, width: usize, align: Align) -> Vec<Span<'_>> {
+    let used: usize = spans.iter().map(Span::width).sum();
+    if used > width {
+        let mut fitted = Vec::new();
+        let mut left = width - 1;
+        for span in spans {
+            let mut cut = 0;
+            for (i, grapheme) in span.content.grapheme_indices(true) {
+                if grapheme.width() > left {
+                    break;
+                }
+                left -= grapheme.width();
+                cut = i + grapheme.len();
+            }
+            fitted.push(Span::styled(span.content[..cut].to_string(), span.style));
+            if cut < span.content.len() {
+                fitted.push(Span::styled("…", span.style));
+                break;
+            }
+        }
+        // Wide characters may leave a column over
+        fitted.push(Span::raw(" ".repeat(left)));
+        return fitted;
+    }
+    let padding = width - used;
+    let (left, right) = match align {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+    let mut fitted = vec![Span::raw(" ".repeat(left))];
+    fitted.extend(spans);
+    fitted.push(Span::raw(" ".repeat(right)));
+    fitted
+}
+
+/// Breaks a row's spans into lines at most `width` columns wide, at spaces
+/// where there are any and inside words longer than a whole line
+/// Lines after the first start `hang` columns in, unless that leaves no room
+fn wrap_line(spans: Vec<Span>, width: usize, hang: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let hang = if hang + 8 <= width { hang } else { 0 };
+    let mut lines = Vec::new();
+    let mut line: Vec<Span<'static>> = Vec::new();
+    let mut used = 0;
+    for span in spans {
+        for word in span.content.split_inclusive(' ') {
+            let mut rest = word;
+            loop {
+                // Trailing spaces may run past the edge, where they're cut off unseen
+                let needed = rest.trim_end_matches(' ').width();
+                if used + needed <= width {
+                    break;
+                }
+                // Moving to a new line only helps if there's text on this one
+                if used > hang {
+                    lines.push(Line::from(std::mem::take(&mut line)));
+                    line.push(Span::raw(" ".repeat(hang)));
+                    used = hang;
+                    continue;
+                }
+                // Even a line of its own is too short, so the word is cut at the edge
+                let mut cut = 0;
+                let mut cut_width = 0;
+                for (i, grapheme) in rest.grapheme_indices(true) {
+                    if used + cut_width + grapheme.width() > width && cut > 0 {
+                        break;
+                    }
+                    cut = i + grapheme.len();
+                    cut_width += grapheme.width();
+                }
+                line.push(Span::styled(rest[..cut].to_string(), span.style));
+                lines.push(Line::from(std::mem::take(&mut line)));
+                line.push(Span::raw(" ".repeat(hang)));
+                used = hang;
+                rest = &rest[cut..];
+            }
+            if !rest.is_empty() {
+                used += rest.width();
+                line.push(Span::styled(rest.to_string(), span.style));
+            }
+        }
+    }
+    lines.push(Line::from(line));
+    lines
+}
+
+/// Rows of context kept above and below the selection while scrolling
+const SCROLL_PADDING: usize = 2;
+
+/// The first row to draw, moved as little as possible from the last frame's
+/// so the selection is on screen with SCROLL_PADDING rows around it, where
+/// the list has them
+/// `lines` is how many lines a row takes, only asked for rows near the window
+fn scroll_offset(
+    offset: usize,
+    selected: Option<usize>,
+    height: usize,
+    rows: usize,
+    mut lines: impl FnMut(usize) -> usize,
+) -> usize {
+    // Scrolled past the end once the list got shorter: the last rows that
+    // fit are as far as it goes
+    let mut last = rows;
+    let mut used = 0;
+    while last > 0 && used + lines(last - 1) <= height {
+        last -= 1;
+        used += lines(last);
+    }
+    let Some(selected) = selected else {
+        return offset.min(last);
+    };
+    // A list only a few rows high would have no room left for the selection
+    let padding = SCROLL_PADDING.min(height.saturating_sub(1) / 2);
+    // Every row takes a line at least, so this far down is a start
+    let bottom = (selected + padding).min(rows.saturating_sub(1));
+    let mut first = offset.min(selected.saturating_sub(padding)).max((bottom + 1).saturating_sub(height));
+    // From there on down until the rows up to the padding below fit
+    let mut used: usize = (first..=bottom).map(&mut lines).sum();
+    while used > height && first < selected {
+        used -= lines(first);
+        first += 1;
+    }
+    first.min(last)
+}
+
+/// Draws the welcome popup of the first run: the handful of keys needed to
+/// get going, as the keymap in effect binds them
+fn render_welcome(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let mut text = vec![
+        Line::styled(app.tr("Welcome to todo-tui"), Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+        Line::from(""),
+    ];
+    for action in [Action::Add, Action::Toggle, Action::Delete, Action::Command, Action::Help, Action::Quit] {
+        let Some(binding) = KEYMAP.iter().find(|binding| binding.action == action) else {
+            continue;
+        };
+        text.push(Line::from(vec![
+            Span::styled(format!("  {:<12}", app.keymap.label(action)), Style::default().fg(theme.tag)),
+            Span::raw(app.tr(binding.description)),
+        ]));
+    }
+    text.push(Line::from(""));
+    text.push(Line::styled(app.tr("Press any key to start - this only shows once"), muted));
+
+    let area = centered_rect(60, 100, f.area());
+    let height = (text.len() as u16 + 2).min(area.height);
+    let area = Rect { y: area.y + (area.height - height) / 2, height, ..area };
+    let popup = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.accent)));
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws the daily review popup: the overdue todo it asks about, when it was
+/// due and the answers to pick from
+fn render_review(f: &mut Frame, app: &App) {
+    let Some((list, path)) = app.review.front() else {
+        return;
+    };
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let todo = item_at(&app.lists[*list].todos, path);
+    let due = app.dates.due_label(todo).unwrap_or_default();
+    let text = vec![
+        Line::styled(todo.text.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+        Line::styled(app.trf("Was due {} on {}", &[&due, &app.lists[*list].name]), Style::default().fg(theme.overdue)),
+        Line::from(""),
+        Line::styled(app.tr("t: today   d: another day   c: done   x: drop   s: skip   Esc: later"), muted),
+    ];
+
+    let area = centered_rect(60, 100, f.area());
+    let height = (text.len() as u16 + 2).min(area.height);
+    let area = Rect { y: area.y + (area.height - height) / 2, height, ..area };
+    let title = app.trf("Daily review ({} left)", &[&app.review.len()]);
+    let popup = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(title).border_style(Style::default().fg(theme.accent)));
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Hides a passphrase behind one dot per character
+fn mask(text: &str) -> String {
+    "•".repeat(text.chars().count())
+}
+
+/// Draws the passphrase dialog for an encrypted todo file
+/// The list behind it is empty until the file is unlocked
+fn render_unlock(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let name = app.save_path.file_name().unwrap_or_default().to_string_lossy();
+    let mut text = vec![
+        Line::from(app.trf("{} is encrypted.", &[&name])),
+        Line::from(""),
+        Line::from(vec![Span::styled(app.tr("Passphrase: "), muted), Span::raw(mask(&app.input))]),
+    ];
+    if let Some(err) = &app.input_error {
+        text.push(Line::styled(err.as_str(), Style::default().fg(theme.overdue)));
+    }
+    text.push(Line::from(""));
+    text.push(Line::styled(app.tr("Enter: unlock   Esc: quit"), muted));
+
+    let area = centered_rect(50, 30, f.area());
+    f.render_widget(Clear, area);
+    let dialog = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(app.tr("Locked")).border_style(Style::default().fg(theme.accent)));
+    f.render_widget(dialog, area);
+    // Cursor after the dots, past the border and label
+    let column = format!("{}{}", app.tr("Passphrase: "), mask(&app.input)).width() as u16;
+    if column + 2 < area.width {
+        f.set_cursor_position(Position { x: area.x + 1 + column, y: area.y + 3 });
+    }
+}
+
+/// Draws the history popup: the committed versions on top and what the
+/// selected one changed below, colored like `git diff`
+fn render_history(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    let language = app.language;
+    let icons = app.icons();
+    let Some(history) = &mut app.history else {
+        return;
+    };
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, area);
+    let [versions_area, diff_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(history.versions.len().min(8) as u16 + 2), Constraint::Min(3)])
+        .areas(area);
+
+    let items: Vec<ListItem> = history
+        .versions
+        .iter()
+        .map(|version| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", version.hash), Style::default().fg(theme.due)),
+                Span::styled(format!("{}  ", version.date), Style::default().fg(theme.muted)),
+                Span::styled(version.subject.as_str(), Style::default().fg(theme.text)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(language.translate("History (r: restore, J/K: scroll diff, Esc: close)")))
+        .highlight_style(theme.selection())
+        .highlight_symbol(icons.selection);
+    f.render_stateful_widget(list, versions_area, &mut history.state);
+
+    let lines: Vec<Line> = history
+        .diff
+        .iter()
+        .map(|line| {
+            let color = if line.starts_with("+++") || line.starts_with("---") || line.starts_with("diff ") {
+                theme.muted
+            } else if line.starts_with('+') {
+                theme.success
+            } else if line.starts_with('-') {
+                theme.overdue
+            } else if line.starts_with("@@") {
+                theme.accent
+            } else {
+                theme.text
+            };
+            Line::styled(line.as_str(), Style::default().fg(color))
+        })
+        .collect();
+    let diff = Paragraph::new(lines)
+        .scroll((history.scroll, 0))
+        .block(Block::default().borders(Borders::ALL).title(language.translate("Changes")));
+    f.render_widget(diff, diff_area);
+}
+
+/// Widest part of a broken line shown in the recovery dialog - long lines
+/// are cut down to the part around the error
+const SNIPPET_WIDTH: usize = 60;
+
+/// Draws the recovery dialog for a file that couldn't be loaded: what's
+/// wrong and where, where the copy went, and the ways out
+fn render_recovery(f: &mut Frame, app: &App) {
+    let Some(recovery) = &app.recovery else {
+        return;
+    };
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let error = Style::default().fg(theme.overdue);
+
+    let mut text = vec![Line::styled(recovery.error.to_string(), error.add_modifier(Modifier::BOLD)), Line::from("")];
+
+    // The offending line with a caret under the column serde stopped at
+    if let (Some((number, column)), Some(line)) = (recovery.error.location(), &recovery.line) {
+        let start = column.saturating_sub(SNIPPET_WIDTH / 2);
+        let snippet: String = line.chars().skip(start).take(SNIPPET_WIDTH).map(|c| if c == '\t' { ' ' } else { c }).collect();
+        let gutter = format!("{:>5} │ ", number);
+        text.push(Line::from(vec![Span::styled(gutter.clone(), muted), Span::raw(snippet)]));
+        let caret = " ".repeat(gutter.width() + column.saturating_sub(1) - start);
+        text.push(Line::styled(format!("{}^", caret), error));
+        text.push(Line::from(""));
+    }
+
+    match &recovery.backup {
+        Ok(path) => text.push(Line::styled(app.trf("A copy was saved as {}", &[&display_path(path)]), Style::default().fg(theme.success))),
+        Err(err) => text.push(Line::styled(app.trf("Could not make a copy: {}", &[err]), error)),
+    }
+    text.push(Line::from(""));
+
+    // Starting fresh removes the file, so it needs the copy to exist
+    let mut keys = vec![("o", "open the file in your editor"), ("r", "try loading again")];
+    if recovery.backup.is_ok() {
+        keys.push(("n", "start fresh without this file"));
+    }
+    keys.push(("q", "quit without changing anything"));
+    for (key, description) in keys {
+        text.push(Line::from(vec![
+            Span::styled(format!("  {:<3}", key), Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            Span::raw(app.tr(description)),
+        ]));
+    }
+
+    let area = centered_rect(70, 60, f.area());
+    let popup = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.tr("Recovery"))
+                .border_style(error),
+        );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws the board: one column of cards per status, the focused column
+/// outlined in the accent color
+fn render_board(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    f.render_widget(Clear, area);
+    let areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(app.board_statuses.iter().map(|_| Constraint::Ratio(1, app.board_statuses.len() as u32)))
+        .split(area);
+    for (column, (status, paths)) in app.board_statuses.iter().zip(app.board_columns()).enumerate() {
+        let items: Vec<ListItem> = paths
+            .iter()
+            .map(|path| {
+                let todo = item_at(app.todos(), path);
+                let marker = app.icons().priority(todo.priority);
+                let mut spans = vec![
+                    Span::styled(format!("{:<4}", marker), Style::default().fg(theme.priority(todo.priority))),
+                    Span::styled(todo.text.clone(), theme.status(todo.status)),
+                ];
+                if let Some(due) = app.dates.due_label(todo) {
+                    let color = if todo.is_overdue() { theme.overdue } else { theme.due };
+                    spans.push(Span::styled(format!(" {}", app.trf("due {}", &[&due])), Style::default().fg(color)));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let focused = column == app.board_column;
+        let mut state = ListState::default();
+        if focused && !paths.is_empty() {
+            state.select(Some(app.board_row));
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} ({})", app.tr(status.label()), paths.len()))
+            .border_style(if focused { Style::default().fg(theme.accent) } else { Style::default() });
+        let list = List::new(items).block(block).highlight_style(theme.selection()).highlight_symbol(app.icons().selection);
+        f.render_stateful_widget(list, areas[column], &mut state);
+    }
+}
+
+/// Number of days shown in the completed-per-day chart
+const STATS_DAYS: usize = 21;
+
+/// Figures for the statistics dashboard, over all lists
+#[derive(Default)]
+pub(crate) struct Stats {
+    pub(crate) total: usize,
+    pub(crate) done: usize,
+    /// Todos completed on each of the last `STATS_DAYS` days, oldest first
+    /// Archived todos count too - archiving shouldn't erase your history
+    completed_per_day: Vec<u64>,
+    /// Todos completed per local day, for every day with at least one
+    pub(crate) completed_on: BTreeMap<Date, u64>,
+    /// Average age in days of open todos with a known creation time
+    average_open_age: Option<f64>,
+    /// (total, done) todos per tag
+    pub(crate) tags: BTreeMap<String, (usize, usize)>,
+}
+
+impl Stats {
+    /// Gathers the statistics from all lists and the archive
+    fn compute(lists: &[TodoList], archive: &[ArchivedTodo], now: Timestamp) -> Stats {
+        let mut stats = Stats::default();
+        let mut ages = Vec::new();
+        // Walks a subtree, counting everything; archived todos only add to the history
+        fn visit(todo: &TodoItem, archived: bool, now: Timestamp, stats: &mut Stats, ages: &mut Vec<f64>) {
+            if let Some(done_at) = todo.completed_at.filter(|_| todo.is_done()) {
+                *stats.completed_on.entry(done_at.date()).or_default() += 1;
+            }
+            if !archived {
+                stats.total += 1;
+                stats.done += usize::from(todo.is_done());
+                if !todo.is_closed() && let Some(created) = todo.created_at {
+                    ages.push((now.0 - created.0).max(0) as f64 / 86_400.0);
+                }
+                for tag in todo.labels() {
+                    let entry = stats.tags.entry(tag).or_default();
+                    entry.0 += 1;
+                    entry.1 += usize::from(todo.is_done());
+                }
+            }
+            for child in &todo.children {
+                visit(child, archived, now, stats, ages);
+            }
+        }
+        for todo in lists.iter().flat_map(|list| &list.todos) {
+            visit(todo, false, now, &mut stats, &mut ages);
+        }
+        for entry in archive {
+            visit(&entry.todo, true, now, &mut stats, &mut ages);
+        }
+        let today = now.date();
+        stats.completed_per_day = (0..STATS_DAYS as i64)
+            .map(|i| stats.completed(today.add_days(i + 1 - STATS_DAYS as i64)))
+            .collect();
+        if !ages.is_empty() {
+            stats.average_open_age = Some(ages.iter().sum::<f64>() / ages.len() as f64);
+        }
+        stats
+    }
+
+    /// Number of todos completed on the day
+    pub(crate) fn completed(&self, day: Date) -> u64 {
+        self.completed_on.get(&day).copied().unwrap_or(0)
+    }
+
+    /// Days in a row with at least one completion, ending today - or yesterday,
+    /// since a streak isn't broken until the day is over
+    fn current_streak(&self, today: Date) -> usize {
+        let end = if self.completed(today) > 0 { today } else { today.add_days(-1) };
+        (0..).take_while(|&i| self.completed(end.add_days(-i)) > 0).count()
+    }
+
+    /// The most days in a row with at least one completion
+    fn longest_streak(&self) -> usize {
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous: Option<Date> = None;
+        for &day in self.completed_on.keys() {
+            run = if previous.is_some_and(|p| p.add_days(1) == day) { run + 1 } else { 1 };
+            longest = longest.max(run);
+            previous = Some(day);
+        }
+        longest
+    }
+}
+
+/// Draws the statistics dashboard: a chart of completions per day on top,
+/// overall figures and per-tag counts below it
+fn render_stats(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let now = Timestamp::now();
+    let stats = Stats::compute(&app.lists, &app.archive, now);
+    let area = centered_rect(90, 90, f.area());
+    f.render_widget(Clear, area);
+    f.render_widget(Block::default().borders(Borders::ALL).title(app.tr("Statistics (Esc: close)")), area);
+    let inner = area.inner(Margin { vertical: 1, horizontal: 1 });
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(8), Constraint::Length(9), Constraint::Length(10)])
+        .split(inner);
+
+    // Bars are labelled with the day of the month, today being the last one
+    let today = now.date();
+    let bars: Vec<Bar> = stats
+        .completed_per_day
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            let day = today.add_days(i as i64 + 1 - STATS_DAYS as i64);
+            Bar::default()
+                .value(count)
+                .label(Line::from(format!("{:02}", day.day)))
+                .style(Style::default().fg(theme.success))
+                .value_style(Style::default().fg(Color::Black).bg(theme.success))
+        })
+        .collect();
+    // Bars share the width evenly, leaving a one column gap between them
+    let bar_width = (rows[0].width.saturating_sub(2) / STATS_DAYS as u16).saturating_sub(1).max(1);
+    let chart = BarChart::default()
+        .block(Block::default().borders(Borders::ALL).title(app.trf("Completed per day, last {} days", &[&STATS_DAYS])))
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(bar_width)
+        .bar_gap(1);
+    f.render_widget(chart, rows[0]);
+    render_heatmap(f, app, &stats, today, rows[1]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[2]);
+    let label = Style::default().fg(theme.muted);
+    let rate = if stats.total == 0 { 0.0 } else { stats.done as f64 * 100.0 / stats.total as f64 };
+    let recent: u64 = stats.completed_per_day.iter().sum();
+    // The values line up after the longest label, which depends on the language
+    let labels = [
+        app.tr("Todos:").to_string(),
+        app.tr("Open:").to_string(),
+        app.tr("Completion rate:").to_string(),
+        app.tr("Average open age:").to_string(),
+        app.trf("Done in {} days:", &[&STATS_DAYS]),
+        app.tr("Streak:").to_string(),
+    ];
+    let width = labels.iter().map(|l| l.width()).max().unwrap_or(0) + 1;
+    let [todos, open, completion, age, recently, streak] = labels.map(|l| Span::styled(format!("{:<width$}", l), label));
+    let summary = vec![
+        Line::from(vec![todos, Span::raw(stats.total.to_string())]),
+        Line::from(vec![open, Span::raw((stats.total - stats.done).to_string())]),
+        Line::from(vec![completion, Span::styled(format!("{:.0}%", rate), Style::default().fg(theme.success))]),
+        Line::from(vec![
+            age,
+            Span::raw(stats.average_open_age.map_or("-".to_string(), |days| app.trf("{} days", &[&format!("{:.1}", days)]))),
+        ]),
+        Line::from(vec![
+            recently,
+            Span::raw(app.trf("{} ({} per day)", &[&recent, &format!("{:.1}", recent as f64 / STATS_DAYS as f64)])),
+        ]),
+        Line::from(vec![
+            streak,
+            Span::raw(app.trf("{} days (longest {})", &[&stats.current_streak(today), &stats.longest_streak()])),
+        ]),
+    ];
+    f.render_widget(
+        Paragraph::new(summary).block(Block::default().borders(Borders::ALL).title(app.tr("Overview"))),
+        columns[0],
+    );
+
+    // Busiest tags first, ties alphabetically
+    let mut tags: Vec<(&String, &(usize, usize))> = stats.tags.iter().collect();
+    tags.sort_by_key(|(_, (total, _))| std::cmp::Reverse(*total));
+    let tag_lines: Vec<Line> = tags
+        .iter()
+        .map(|(tag, (total, done))| {
+            let name = label_name(tag);
+            Line::from(vec![
+                Span::styled(format!("{:<20}", name), Style::default().fg(theme.tag)),
+                Span::raw(app.trf("{} todos", &[&format!("{:>4}", total)])),
+                Span::styled(app.trf("{} done", &[&format!("{:>5}", done)]), label),
+            ])
+        })
+        .collect();
+    let tag_lines = if tag_lines.is_empty() { vec![Line::styled(app.tr("No tags yet"), label)] } else { tag_lines };
+    f.render_widget(
+        Paragraph::new(tag_lines).block(Block::default().borders(Borders::ALL).title(app.tr("Tags"))),
+        columns[1],
+    );
+}
+
+/// Draws a GitHub-style calendar of completions: one column per week, the
+/// config's first day of the week on top, today in the last column, shaded by
+/// how much got done that day
+/// As many weeks are shown as fit, up to a year
+fn render_heatmap(f: &mut Frame, app: &App, stats: &Stats, today: Date, area: Rect) {
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let filled = Style::default().fg(theme.success);
+    // Each week takes two columns, after the three column weekday labels
+    let weeks = (area.width.saturating_sub(5) / 2).min(53) as i64;
+    let start = app.dates.week_start.index();
+    let this_week = today.add_days(-i64::from((today.weekday() + 7 - start) % 7));
+    let first_week = this_week.add_days(-7 * (weeks - 1));
+    let lines: Vec<Line> = (0..7)
+        .map(|row| {
+            // Every other row is labelled, like "Mo", "We" and "Fr" when weeks start on Monday
+            let label = match row % 2 {
+                0 if row < 6 => {
+                    let name = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"][first_week.add_days(row).weekday() as usize];
+                    format!("{} ", app.tr(name))
+                }
+                _ => "   ".to_string(),
+            };
+            let mut spans = vec![Span::styled(label, muted)];
+            for week in 0..weeks {
+                let day = first_week.add_days(week * 7 + row);
+                let cell = match stats.completed(day) {
+                    _ if day > today => Span::raw("  "),
+                    0 => Span::styled("· ", muted),
+                    1 => Span::styled("░ ", filled),
+                    2..=3 => Span::styled("▒ ", filled),
+                    4..=5 => Span::styled("▓ ", filled),
+                    _ => Span::styled("█ ", filled),
+                };
+                spans.push(cell);
+            }
+            Line::from(spans)
+        })
+        .collect();
+    let title = app.trf("Completions, last {} weeks (░ 1  ▒ 2-3  ▓ 4-5  █ 6+)", &[&weeks]);
+    f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+/// Where the due date being typed will land, shown before it's confirmed
+/// so "next fri" or "in 2 weeks" can be checked at a glance
+fn due_preview(app: &App) -> Option<String> {
+    if app.mode != Mode::Input {
+        return None;
+    }
+    let describe = |date: Date, time: Option<Time>| app.trf("due {}", &[&format!("{} {}", date.weekday_name(), app.dates.due(date, time))]);
+    match app.input_target {
+        InputTarget::Due(_) if !app.input.trim().is_empty() => Some(match parse_due(&app.input, Date::today()) {
+            Some((date, time)) => describe(date, time),
+            None => app.tr("not a date yet").to_string(),
+        }),
+        InputTarget::Snooze(_) if !app.input.trim().is_empty() => Some(match parse_due(&app.input, Date::today()) {
+            Some((date, time)) => {
+                let until = Timestamp::at(date, time);
+                app.trf("until {}", &[&format!("{} {}", until.date().weekday_name(), app.dates.moment(until))])
+            }
+            None => app.tr("not a date yet").to_string(),
+        }),
+        InputTarget::New | InputTarget::Child(_) | InputTarget::Edit(_) => {
+            let todo = TodoItem::parse(&app.input);
+            todo.due.map(|date| describe(date, todo.due_time))
+        }
+        _ => None,
+    }
+}
+
+/// Draws the toasts stacked up from the bottom right corner of the area,
+/// newest at the bottom, each in a box colored by what it reports
+fn render_toasts(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let mut bottom = area.bottom();
+    for toast in app.toasts.iter().rev() {
+        // A toast that doesn't fit whole is left out, along with the older ones
+        if bottom < area.y + 3 {
+            break;
+        }
+        let color = match toast.kind {
+            ToastKind::Info => theme.accent,
+            ToastKind::Success => theme.success,
+            ToastKind::Error => theme.overdue,
+        };
+        let width = (toast.text.width() as u16 + 4).min(area.width);
+        let rect = Rect { x: area.right() - width, y: bottom - 3, width, height: 3 };
+        let block =
+            Block::default().borders(Borders::ALL).border_style(Style::default().fg(color)).padding(Padding::horizontal(1));
+        f.render_widget(Clear, rect);
+        f.render_widget(Paragraph::new(toast.text.as_str()).block(block), rect);
+        bottom -= 3;
+    }
+}
+
+/// Draws the one-line status bar: mode, counts, sort and filters on the left,
+/// the todo file on the right
+/// Draws the last file error in the overdue color, with how to get rid of it
+fn render_error_bar(f: &mut Frame, app: &App, area: Rect) {
+    let Some(error) = &app.error else {
+        return;
+    };
+    let theme = app.theme();
+    let line = Line::from(vec![
+        Span::styled(format!(" ✗ {}", error), Style::default().fg(theme.overdue).add_modifier(Modifier::BOLD)),
+        Span::styled(format!("  {}", app.tr("Esc: dismiss")), Style::default().fg(theme.muted)),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+}
+
+fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let muted = Style::default().fg(theme.muted);
+    let (total, done) = count_todos(app.todos());
+
+    let mut left = vec![
+        Span::styled(
+            format!(" {} ", app.tr(app.mode.label())),
+            Style::default().fg(Color::Black).bg(theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            app.pending_keys.label().map(|keys| format!(" {}", keys)).unwrap_or_default(),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!(" {}", app.trf("{} todos", &[&total]))),
+        Span::styled(" · ", muted),
+        Span::raw(app.trf("{} active", &[&(total - done)])),
+        Span::styled(" · ", muted),
+        Span::styled(app.trf("{} done", &[&done]), Style::default().fg(theme.success)),
+        Span::styled(format!("   {}", app.tr("sort: ")), muted),
+        Span::raw(app.tr(app.sort_mode.label())),
+    ];
+    if app.group_by_project {
+        left.push(Span::styled(app.tr(", by project"), muted));
+    }
+    // Running time tracking, wherever the todo is, so it isn't forgotten
+    if let Some(todo) = app.tracked_todo() {
+        let seconds = todo.tracked_seconds(Timestamp::now());
+        left.push(Span::styled(
+            format!("   ⏱ {}:{:02}:{:02} {}", seconds / 3600, seconds / 60 % 60, seconds % 60, todo.text),
+            Style::default().fg(theme.accent),
+        ));
+    }
+    // The pomodoro countdown flashes in reverse video when an interval ends
+    let timer = match app.pomodoro_flash {
+        Some(_) => Style::default().fg(Color::Black).bg(theme.accent).add_modifier(Modifier::BOLD),
+        None => Style::default().fg(theme.accent),
+    };
+    let countdown = |end: Timestamp| {
+        let seconds = (end.0 - Timestamp::now().0).max(0);
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    };
+    match &app.pomodoro {
+        Some(Pomodoro { phase: PomodoroPhase::Focus, ends_at, text, .. }) => {
+            left.push(Span::raw("   "));
+            left.push(Span::styled(format!("🍅 {} {}", countdown(*ends_at), text), timer));
+        }
+        Some(Pomodoro { phase: PomodoroPhase::Break, ends_at, .. }) => {
+            left.push(Span::raw("   "));
+            left.push(Span::styled(format!("☕ {}", app.trf("{} break", &[&countdown(*ends_at)])), timer));
+        }
+        None if app.pomodoro_flash.is_some() => {
+            left.push(Span::raw("   "));
+            left.push(Span::styled(format!("🍅 {}", app.tr("break over")), timer));
+        }
+        None => {}
+    }
+    for segment in &app.plugin_status {
+        left.push(Span::raw(format!("   {}", segment)));
+    }
+    // Filters get the accent color since they explain why todos are missing
+    let filter = Style::default().fg(theme.accent);
+    if let Some(saved) = &app.active_filter {
+        left.push(Span::styled(format!("   {}", app.trf("filter: {}", &[&saved.name])), filter));
+    }
+    if let Some((text, _)) = &app.query {
+        left.push(Span::styled(format!("   :filter {}", text), filter));
+    }
+    if app.saving_disabled {
+        left.push(Span::styled(
+            format!("   {}", app.tr("not saving")),
+            Style::default().fg(theme.overdue).add_modifier(Modifier::BOLD),
+        ));
+    } else if app.read_only {
+        left.push(Span::styled(format!("   {}", app.tr("read-only")), Style::default().fg(theme.due).add_modifier(Modifier::BOLD)));
+    } else if app.is_saving() {
+        left.push(Span::styled(format!("   {}", app.tr("saving…")), muted));
+    }
+    match app.sync_status {
+        SyncStatus::Idle => {}
+        SyncStatus::Syncing => left.push(Span::styled(format!("   {}", app.tr("⟳ syncing")), muted)),
+        SyncStatus::Synced(at) => left.push(Span::styled(format!("   {}", app.trf("synced {}", &[&app.dates.time(at.time())])), muted)),
+        SyncStatus::Failed => left.push(Span::styled(format!("   {}", app.tr("sync failed")), Style::default().fg(theme.overdue))),
+    }
+    if let Some(notice) = &app.notice {
+        left.push(Span::styled(format!("   {}", notice), Style::default().fg(theme.success)));
+    }
+    if let Some(tag) = &app.tag_filter {
+        left.push(Span::styled(format!("   {}", label_name(tag)), filter));
+    }
+    if !app.search.is_empty() {
+        left.push(Span::styled(format!("   {}", app.trf("/{}: {} matches", &[&app.search, &app.visible.len()])), filter));
+    }
+    if app.hide_completed {
+        left.push(Span::styled(format!("   {}", app.trf("{} completed hidden", &[&app.hidden_completed])), filter));
+    }
+    if !app.show_unstarted && app.unstarted_count > 0 {
+        left.push(Span::styled(format!("   {}", app.trf("{} not started", &[&app.unstarted_count])), filter));
+    }
+    if !app.snoozed().is_empty() {
+        left.push(Span::styled(format!("   {}", app.trf("{} snoozed", &[&app.snoozed().len()])), filter));
+    }
+    if !app.marked.is_empty() {
+        left.push(Span::styled(
+            format!("   {}", app.trf("{} marked", &[&app.marked.len()])),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let left = Line::from(left);
+
+    // The file path is the least important part, so it shrinks to the file name
+    // and then disappears when the terminal is too narrow
+    let file_name = app.save_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let right = [display_path(&app.save_path), file_name]
+        .into_iter()
+        .map(|path| Line::from(Span::styled(format!("{}  {} ", path, app.tr("?: help")), muted)))
+        .find(|right| left.width() + right.width() < area.width as usize);
+    if let Some(right) = right {
+        f.render_widget(Paragraph::new(right).alignment(Alignment::Right), area);
+    }
+    f.render_widget(Paragraph::new(left), area);
+}
+
+/// Counts (total, completed) todos in a tree, subtasks included
+fn count_todos(todos: &[TodoItem]) -> (usize, usize) {
+    todos.iter().fold((0, 0), |(total, done), todo| {
+        let (sub_total, sub_done) = count_todos(&todo.children);
+        (total + 1 + sub_total, done + usize::from(todo.is_closed()) + sub_done)
+    })
+}
+
+/// How much `done` is of `total`, in whole percent rounded down, so a list
+/// only shows 100% once everything is done
+fn percent(done: usize, total: usize) -> usize {
+    (done * 100).checked_div(total).unwrap_or(0)
+}
+
+/// Shortens a path for display by writing the home directory as ~
+fn display_path(path: &Path) -> String {
+    if let Some(home) = std::env::var_os("HOME").filter(|h| !h.is_empty())
+        && let Ok(rest) = path.strip_prefix(&home)
+    {
+        return Path::new("~").join(rest).display().to_string();
+    }
+    path.display().to_string()
+}
+
+/// Draws the detail pane for the selected todo
+/// Shows everything that doesn't fit in a list row, most importantly the notes
+fn render_details(f: &mut Frame, app: &App, area: Rect) {
+    let editing = app.mode == Mode::Notes;
+    let mut lines: Vec<Line> = Vec::new();
+
+    if let Some(path) = app.selected_path() {
+        let todo = item_at(app.todos(), &path);
+        let theme = app.theme();
+        let label = Style::default().fg(theme.muted);
+        // Values line up after the longest label, which depends on the language
+        const LABELS: [&str; 13] = [
+            "Status:", "Priority:", "Due:", "Starts:", "Repeats:", "Time:", "Focus:", "Project:", "Tags:", "Subtasks:", "Created:",
+            "Changed:", "Done:",
+        ];
+        let width = LABELS.iter().map(|l| app.tr(l).width()).max().unwrap_or(0) + 1;
+        let field = |english: &'static str| Span::styled(format!("{:<width$}", app.tr(english)), label);
+        let mut title = field_spans(app, theme, todo, RowField::Star);
+        if !title.is_empty() {
+            title.push(Span::raw(" "));
+        }
+        title.push(Span::styled(todo.text.clone(), Style::default().add_modifier(Modifier::BOLD)));
+        lines.push(Line::from(title));
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![
+            field("Status:"),
+            Span::raw(app.tr(todo.status.label())),
+        ]));
+        lines.push(Line::from(vec![
+            field("Priority:"),
+            Span::styled(app.tr(todo.priority.label()), Style::default().fg(theme.priority(todo.priority))),
+        ]));
+        if let Some(due) = app.dates.due_label(todo) {
+            lines.push(Line::from(vec![field("Due:"), Span::raw(due)]));
+        }
+        if let Some(scheduled) = todo.scheduled {
+            lines.push(Line::from(vec![field("Starts:"), Span::raw(app.dates.date(scheduled))]));
+        }
+        if let Some(rule) = todo.recurrence {
+            lines.push(Line::from(vec![field("Repeats:"), Span::raw(rule.to_string())]));
+        }
+        let spent = todo.tracked_seconds(Timestamp::now());
+        if spent > 0 || todo.tracking_since.is_some() {
+            let running = if todo.tracking_since.is_some() { app.tr(" (tracking)") } else { "" };
+            lines.push(Line::from(vec![
+                field("Time:"),
+                Span::raw(format!("{}{}", format_duration(spent), running)),
+            ]));
+        }
+        if todo.pomodoros > 0 {
+            let count = if todo.pomodoros == 1 { app.tr("1 pomodoro").to_string() } else { app.trf("{} pomodoros", &[&todo.pomodoros]) };
+            lines.push(Line::from(vec![field("Focus:"), Span::raw(count)]));
+        }
+        if let Some(project) = &todo.project {
+            lines.push(Line::from(vec![field("Project:"), Span::raw(project.clone())]));
+        }
+        let labels = todo.labels();
+        if !labels.is_empty() {
+            let mut spans = vec![field("Tags:")];
+            for (i, tag) in labels.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::raw(" "));
+                }
+                spans.push(tag_span(app, theme, tag));
+            }
+            lines.push(Line::from(spans));
+        }
+        if let Some((done, total)) = todo.progress() {
+            lines.push(Line::from(vec![
+                field("Subtasks:"),
+                Span::raw(app.trf("{}/{} done", &[&done, &total])),
+            ]));
+        }
+        if let Some(created) = todo.created_at {
+            lines.push(Line::from(vec![field("Created:"), Span::raw(app.dates.timestamp(created))]));
+        }
+        if let Some(modified) = todo.modified_at {
+            lines.push(Line::from(vec![field("Changed:"), Span::raw(app.dates.timestamp(modified))]));
+        }
+        if let Some(completed) = todo.completed_at.filter(|_| todo.is_done()) {
+            lines.push(Line::from(vec![field("Done:"), Span::raw(app.dates.timestamp(completed))]));
+        }
+        lines.push(Line::from(""));
+
+        // While editing we show the buffer with a block cursor at the end
+        let notes = if editing {
+            let (before, after) = app.input.split_at(app.cursor());
+            format!("{}█{}", before, after)
+        } else {
+            todo.notes.clone()
+        };
+        if notes.is_empty() {
+            lines.push(Line::from(Span::styled(app.tr("No notes - press 'E' to add some"), label)));
+        } else {
+            lines.extend(notes.lines().map(|l| Line::from(l.to_string())));
+        }
+
+        // After the notes, which matter more, and newest first, since what
+        // happened last is what's usually looked for
+        if !todo.history.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(app.tr("History"), label.add_modifier(Modifier::BOLD))));
+            for change in todo.history.iter().rev() {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{}  ", app.dates.timestamp(change.at)), label),
+                    Span::raw(change_label(app, &change.field)),
+                ]));
+            }
+        }
+    }
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(app.tr(if editing { "Details (editing notes)" } else { "Details" }))
+        .border_style(if editing { Style::default().fg(app.theme().accent) } else { Style::default() });
+    let details = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    f.render_widget(details, area);
+}
+
+/// One history entry in words, with the new value where there is one
+fn change_label(app: &App, field: &Field) -> String {
+    match field {
+        Field::Text => app.tr("Text edited").to_string(),
+        Field::Notes => app.tr("Notes edited").to_string(),
+        Field::Status(status) => app.trf("Marked {}", &[&app.tr(status.label())]),
+        Field::Priority(priority) => app.trf("Priority {}", &[&app.tr(priority.label())]),
+        Field::Starred(true) => app.tr("Starred").to_string(),
+        Field::Starred(false) => app.tr("Star taken away").to_string(),
+        Field::Due(Some(due)) => app.trf("Due {}", &[&app.dates.date(*due)]),
+        Field::Due(None) => app.tr("Due date removed").to_string(),
+        Field::Recurrence(Some(rule)) => app.trf("Repeats {}", &[rule]),
+        Field::Recurrence(None) => app.tr("No longer repeats").to_string(),
+        Field::Tags => app.tr("Tags changed").to_string(),
+        Field::Project(Some(project)) => app.trf("Moved to project {}", &[project]),
+        Field::Project(None) => app.tr("Removed from its project").to_string(),
+    }
+}
+
+/// Draws the scrollable keybinding help, generated from the keymap in effect
+fn render_help(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let heading = Style::default().fg(theme.accent).add_modifier(Modifier::BOLD);
+    let mut lines: Vec<Line> = Vec::new();
+    let mut section = "";
+    // Actions the preset or config left without a key aren't worth listing
+    for (binding, keys) in KEYMAP.iter().zip(&app.keymap.keys).filter(|(_, keys)| !keys.is_empty()) {
+        // Bindings are grouped in the table, so a new section name starts a new heading
+        if binding.section != section {
+            if !section.is_empty() {
+                lines.push(Line::from(""));
+            }
+            section = binding.section;
+            lines.push(Line::from(Span::styled(app.tr(section), heading)));
+        }
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<18}", keys_label(keys)), Style::default().fg(theme.tag)),
+            Span::raw(app.tr(binding.description)),
+        ]));
+    }
+    if app.keymap.preset.counts() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(app.tr("Counts and sequences"), heading)));
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<18}", "5j, 3J, 2."), Style::default().fg(theme.tag)),
+            Span::raw(app.tr("A number in front of a key repeats it (on its own it switches list)")),
+        ]));
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<18}", "12G"), Style::default().fg(theme.tag)),
+            Span::raw(app.tr("Jump to that row")),
+        ]));
+        for sequence in SEQUENCES {
+            let keys = format!("{}{}", sequence.keys[0].label(), sequence.keys[1].label());
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<18}", keys), Style::default().fg(theme.tag)),
+                Span::raw(app.tr(sequence.description)),
+            ]));
+        }
+    }
+    let plugin_bindings = app.plugins.bindings();
+    if !plugin_bindings.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(app.tr("Plugins"), heading)));
+        for (key, description) in plugin_bindings {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<18}", key.label()), Style::default().fg(theme.tag)),
+                Span::raw(description),
+            ]));
+        }
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(app.tr("Commands (type after :)"), heading)));
+    for command in COMMANDS {
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<40}", command.usage), Style::default().fg(theme.tag)),
+            Span::raw(app.tr(command.description)),
+        ]));
+    }
+
+    let area = centered_rect(80, 80, f.area());
+    // Don't scroll past the last line - clamp against the visible height
+    let max_scroll = (lines.len() as u16).saturating_sub(area.height.saturating_sub(2));
+    app.help_scroll = app.help_scroll.min(max_scroll);
+
+    let help = Paragraph::new(lines)
+        .scroll((app.help_scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.trf("Help - {} keys (↑/↓: scroll, Esc: close)", &[&app.keymap.preset.name()])),
+        );
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+/// Draws the y/n confirmation popup centered over the list
+/// A fixed small height looks like a dialog rather than a half-screen panel
+fn render_confirm(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = centered_rect(50, 100, f.area());
+    let area = Rect { y: area.y + area.height.saturating_sub(5) / 2, height: area.height.min(5), ..area };
+    let text = vec![
+        Line::from(app.confirm_prompt()),
+        Line::from(""),
+        Line::from(Span::styled(app.tr("y: yes   n/Esc: no"), Style::default().fg(theme.muted))),
+    ];
+    let popup = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.tr("Confirm"))
+                .border_style(Style::default().fg(theme.overdue)),
+        );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws the tag filter picker as a centered popup
+/// The first entry clears the filter so the picker doubles as the way back
+fn render_tag_picker(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    let tags = app.all_tags();
+    let mut items = vec![ListItem::new(app.tr("(all todos)"))];
+    items.extend(tags.iter().map(|tag| ListItem::new(tag_span(app, &theme, tag))));
+
+    let area = centered_rect(40, 50, f.area());
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.tr("Filter by tag (Enter: select, c: color and icon, Esc: close)")),
+        )
+        .highlight_style(app.theme().selection())
+        .highlight_symbol(app.icons().selection);
+
+    // Clear first so list rows underneath don't bleed through the popup
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut app.tag_picker);
+}
+
+/// What the command being typed at the `:` prompt could complete to, for the
+/// input box title - the usage once the command name is typed out
+fn command_hint(app: &App) -> Option<String> {
+    if app.mode != Mode::Input || app.input_target != InputTarget::Command {
+        return None;
+    }
+    let (start, options) = app.command_completions(&app.input);
+    if start > 0 {
+        let name = app.input.split(' ').next().unwrap_or_default();
+        let usage = COMMANDS.iter().find(|c| c.name == name || c.alias == Some(name))?.usage;
+        return Some(if options.is_empty() { usage.to_string() } else { options.join(" | ") });
+    }
+    (!options.is_empty()).then(|| options.join(" | "))
+}
+
+/// Draws the saved filter picker as a centered popup
+/// Rows are numbered with the digit that picks them, 0 turning the filter off
+fn render_filter_picker(f: &mut Frame, app: &mut App) {
+    let muted = Style::default().fg(app.theme().muted);
+    let mut items = vec![ListItem::new(Line::from(vec![Span::styled("0 ", muted), Span::raw(app.tr("(all todos)"))]))];
+    items.extend(app.filters().iter().enumerate().map(|(i, filter)| {
+        // Only the first nine can be picked with a digit
+        let number = if i < 9 { format!("{} ", i + 1) } else { "  ".to_string() };
+        ListItem::new(Line::from(vec![Span::styled(number, muted), Span::raw(filter.name.clone())]))
+    }));
+
+    let area = centered_rect(40, 50, f.area());
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.tr("Saved filters (Enter/0-9: select, s: save current, d: delete, Esc: close)")),
+        )
+        .highlight_style(app.theme().selection())
+        .highlight_symbol(app.icons().selection);
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut app.filter_picker);
+}
+
+/// Draws the project picker as a centered popup with each project's progress
+fn render_project_picker(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    let items: Vec<ListItem> = app
+        .projects()
+        .iter()
+        .map(|project| {
+            let (done, total) = app.project_progress(project);
+            let name = project.as_deref().map_or(app.tr("No project").to_string(), |p| format!("+{}", p));
+            ListItem::new(Line::from(vec![
+                Span::raw(name),
+                Span::styled(format!(" {}/{}", done, total), Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+
+    let area = centered_rect(40, 50, f.area());
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.tr("Jump to project (Enter: select, Esc: close)")),
+        )
+        .highlight_style(theme.selection())
+        .highlight_symbol(app.icons().selection);
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut app.project_picker);
+}
+
+/// Draws the archive as a centered popup, newest entries first
+/// Each entry shows the list it came from and when it was archived
+fn render_archive(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    let items: Vec<ListItem> = app
+        .archive
+        .iter()
+        .rev()
+        .map(|entry| {
+            let style = if entry.todo.is_closed() {
+                Style::default().fg(theme.completed)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(&entry.todo.text, style),
+                Span::styled(format!("  [{}]", entry.list), Style::default().fg(theme.tag)),
+                Span::styled(format!(" {}", app.dates.date(entry.archived_at.date())), Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+    let empty = items.is_empty();
+
+    let area = centered_rect(70, 70, f.area());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(app.tr("Archive (r: restore, Esc: close)"));
+    f.render_widget(Clear, area);
+    if empty {
+        let text = Paragraph::new(Span::styled(app.tr("Nothing archived yet"), Style::default().fg(theme.muted)))
+            .alignment(Alignment::Center)
+            .block(block);
+        f.render_widget(text, area);
+    } else {
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(theme.selection())
+            .highlight_symbol(app.icons().selection);
+        f.render_stateful_widget(list, area, &mut app.archive_state);
+    }
+}
+
+/// Draws the trash of the current list as a centered popup
+/// Entries show their subtask count since restoring brings the whole subtree back
+fn render_trash(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    // Borrow the trash field directly so trash_state can be borrowed mutably below
+    let items: Vec<ListItem> = app.lists[app.current]
+        .trash
+        .iter()
+        .map(|todo| {
+            let mut spans = vec![Span::styled(&todo.text, Style::default().fg(theme.text))];
+            if !todo.children.is_empty() {
+                spans.push(Span::styled(
+                    app.trf(" (+{} subtasks)", &[&todo.children.len()]),
+                    Style::default().fg(theme.muted),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let empty = items.is_empty();
+
+    let area = centered_rect(60, 60, f.area());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(app.tr("Trash (r: restore, d: delete, X: empty, Esc: close)"));
+    f.render_widget(Clear, area);
+    if empty {
+        let text = Paragraph::new(Span::styled(app.tr("The trash is empty"), Style::default().fg(theme.muted)))
+            .alignment(Alignment::Center)
+            .block(block);
+        f.render_widget(text, area);
+    } else {
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(theme.selection())
+            .highlight_symbol(app.icons().selection);
+        f.render_stateful_widget(list, area, &mut app.trash_state);
+    }
+}
+
+/// Draws the snoozed todos of the current list with when each comes back
+fn render_snoozed(f: &mut Frame, app: &mut App) {
+    let theme = app.theme().clone();
+    let items: Vec<ListItem> = app
+        .snoozed()
+        .iter()
+        .map(|path| {
+            let todo = item_at(app.todos(), path);
+            let until = todo.snoozed_until.map(|until| app.dates.moment(until)).unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                Span::styled(todo.text.clone(), Style::default().fg(theme.text)),
+                Span::styled(format!("  {}", app.trf("until {}", &[&until])), Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+
+    let area = centered_rect(60, 60, f.area());
+    let block = Block::default().borders(Borders::ALL).title(app.tr("Snoozed (r: wake up, u: undo, Esc: close)"));
+    f.render_widget(Clear, area);
+    if items.is_empty() {
+        let text = Paragraph::new(Span::styled(app.tr("Nothing is snoozed"), Style::default().fg(theme.muted)))
+            .alignment(Alignment::Center)
+            .block(block);
+        f.render_widget(text, area);
+    } else {
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(theme.selection())
+            .highlight_symbol(app.icons().selection);
+        f.render_stateful_widget(list, area, &mut app.snoozed_state);
+    }
+}
+
+/// Computes a rectangle centered in `area` taking the given percentage of its size
+/// Used for all popups so they scale with the terminal instead of using fixed sizes
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::{SortMode, TodoItem, TOAST_LIMIT}, update::{update, Message}};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::path::PathBuf;
+
+    /// An app on a fixed path, so the status bar reads the same on every machine
+    fn app_with(count: usize) -> App {
+        let mut app = App::new(PathBuf::from("todos.json"));
+        app.lists[0].todos = (1..=count).map(|n| TodoItem::new(&format!("todo number {}", n))).collect();
+        app.refresh_view();
+        app.state_mut().select((count > 0).then_some(0));
+        app
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        update(app, Message::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+    }
+
+    /// Draws the app on a terminal of the given size and returns its rows as text
+    fn screen(width: u16, height: u16, app: &mut App) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y| {
+            let mut row = String::new();
+            let mut x = 0;
+            while x < width {
+                let symbol = buffer[(x, y)].symbol();
+                row.push_str(symbol);
+                // A wide character covers the cell after it as well
+                x += (symbol.width() as u16).max(1);
+            }
+            row
+        };
+        (0..height).map(row).collect()
+    }
+
+    /// Clicks the left button at a cell of the terminal
+    fn click(app: &mut App, column: u16, row: u16) {
+        let mouse = MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column, row, modifiers: KeyModifiers::NONE };
+        update(app, Message::Mouse(mouse));
+    }
+
+    /// Compares a frame, text and styles both, with the snapshot saved in
+    /// `src/snapshots/NAME.snap`
+    /// A missing or changed one fails the test; `cargo insta review` shows
+    /// the difference and accepts it
+    fn assert_snapshot(name: &str, app: &mut App) {
+        assert_snapshot_sized(name, app, 80, 24);
+    }
+
+    fn assert_snapshot_sized(name: &str, app: &mut App, width: u16, height: u16) {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        insta::with_settings!({ prepend_module_to_snapshot => false, omit_expression => true }, {
+            insta::assert_debug_snapshot!(name, terminal.backend().buffer());
+        });
+    }
+
+    #[test]
+    fn empty_list() {
+        assert_snapshot("empty_list", &mut app_with(0));
+    }
+
+    #[test]
+    fn long_list_with_selection() {
+        let mut app = app_with(40);
+        // Far enough down that the list has scrolled
+        for _ in 0..25 {
+            press(&mut app, KeyCode::Char('j'));
+        }
+        app.lists[0].todos[3].status = Status::Done;
+        app.lists[0].todos[5].priority = Priority::High;
+        app.lists[0].todos[25].tags = vec!["work".to_string()];
+        assert_snapshot("long_list_with_selection", &mut app);
+    }
+
+    #[test]
+    fn input_mode() {
+        let mut app = app_with(3);
+        press(&mut app, KeyCode::Char('a'));
+        for c in "call mum #family".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_snapshot("input_mode", &mut app);
+    }
+
+    #[test]
+    fn input_edits_whole_graphemes_and_the_cursor_counts_cells() {
+        let mut app = app_with(1);
+        press(&mut app, KeyCode::Char('a'));
+        // Where the terminal cursor ends up after drawing a frame
+        let cursor = |app: &mut App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+            terminal.draw(|f| ui(f, app)).unwrap();
+            terminal.get_cursor_position().unwrap().x
+        };
+        let start = cursor(&mut app);
+        // Typed a code point at a time, the way a terminal sends them: CJK,
+        // a family emoji joined by ZWJs, and an e with a combining accent
+        let family = "👨\u{200d}👩\u{200d}👧";
+        for c in format!("日本 {} cafe\u{301}", family).chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        // Two cells for each CJK character and for the emoji
+        assert_eq!(cursor(&mut app) - start, 2 + 2 + 1 + 2 + 1 + 4);
+
+        // The accent goes with its letter
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(app.input, format!("日本 {} caf", family));
+        assert_eq!(cursor(&mut app) - start, 11);
+        // The arrows step over the emoji as one symbol
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Left);
+        }
+        assert_eq!(app.cursor(), "日本 ".len());
+        assert_eq!(cursor(&mut app) - start, 5);
+        press(&mut app, KeyCode::Right);
+        assert_eq!(app.cursor(), format!("日本 {}", family).len());
+        assert_eq!(cursor(&mut app) - start, 7);
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(app.input, "日本  caf");
+        assert_eq!(cursor(&mut app) - start, 5);
+
+        // Delete takes the character after the cursor, however many bytes it is
+        press(&mut app, KeyCode::Home);
+        press(&mut app, KeyCode::Right);
+        assert_eq!(cursor(&mut app) - start, 2);
+        press(&mut app, KeyCode::Delete);
+        assert_eq!(app.input, "日  caf");
+        // Typing goes in at the cursor, not at the end
+        press(&mut app, KeyCode::Char('語'));
+        assert_eq!(app.input, "日語  caf");
+        assert_eq!(cursor(&mut app) - start, 4);
+        // Quick-add tidies the gap left behind when the todo is saved
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.lists[0].todos[1].text, "日語 caf");
+    }
+
+    #[test]
+    fn scrolling_keeps_the_selection_in_view() {
+        let one = |_| 1;
+        // Moving down scrolls only once the selection nears the bottom
+        assert_eq!(scroll_offset(0, Some(7), 10, 100, one), 0);
+        assert_eq!(scroll_offset(0, Some(8), 10, 100, one), 1);
+        // Moving up keeps two rows above it, except at the top
+        assert_eq!(scroll_offset(50, Some(51), 10, 100, one), 49);
+        assert_eq!(scroll_offset(5, Some(1), 10, 100, one), 0);
+        // A jump to the end shows the last rows, with nothing to pad below
+        assert_eq!(scroll_offset(0, Some(99), 10, 100, one), 90);
+        // A list that got shorter than the offset scrolls back
+        assert_eq!(scroll_offset(90, None, 10, 20, one), 10);
+        assert_eq!(scroll_offset(3, Some(0), 0, 5, one), 1);
+
+        // A huge list draws just the window around the selection
+        let mut app = app_with(100_000);
+        app.state_mut().select(Some(54_321));
+        assert!(screen(80, 24, &mut app).iter().any(|row| row.contains("► ") && row.contains("todo number 54322")));
+        assert!(app.state().offset() > 54_000);
+
+        // Rows taking three lines each: the selection still fits with its padding
+        assert_eq!(scroll_offset(0, Some(3), 10, 100, |_| 3), 3);
+        assert_eq!(scroll_offset(0, Some(99), 10, 100, |_| 3), 97);
+        // A row taller than the list is shown from its top
+        assert_eq!(scroll_offset(0, Some(5), 4, 10, |_| 6), 5);
+    }
+
+    #[test]
+    fn drawn_rows_are_kept_until_the_todos_or_the_width_change() {
+        let mut app = app_with(3);
+        screen(40, 12, &mut app);
+        assert_eq!(app.row_cache.lines.len(), 3);
+        // A change the view wasn't rebuilt for doesn't reach the kept rows
+        app.lists[0].todos[0].text = "a todo with text enough to wrap in the list".to_string();
+        assert!(screen(40, 12, &mut app).iter().any(|row| row.contains("todo number 1")));
+        app.refresh_view();
+        assert!(screen(40, 12, &mut app).iter().any(|row| row.contains("a todo with")));
+
+        // Wrapped rows are built again for another width
+        app.wrap = true;
+        let rows = screen(40, 12, &mut app);
+        let first = rows.iter().position(|row| row.contains("a todo with")).unwrap();
+        assert!(rows[first + 1].contains("wrap in the list"));
+        let rows = screen(80, 12, &mut app);
+        assert!(rows.iter().any(|row| row.contains("a todo with text enough to wrap in the list")));
+    }
+
+    #[test]
+    fn wrapping_breaks_at_spaces() {
+        let text = |lines: Vec<Line>| -> Vec<String> { lines.iter().map(|line| line.to_string()).collect() };
+        let spans = |text: &'static str| vec![Span::raw("[ ] "), Span::raw(text)];
+        assert_eq!(text(wrap_line(spans("buy milk and eggs"), 14, 4)), ["[ ] buy milk ", "    and eggs"]);
+        // Words longer than a line are cut where the line ends
+        assert_eq!(
+            text(wrap_line(spans("supercalifragilistic"), 12, 4)),
+            ["[ ] supercal", "    ifragili", "    stic"]
+        );
+        // Wide characters aren't split across the edge
+        assert_eq!(text(wrap_line(spans("日本語の文"), 12, 4)), ["[ ] 日本語の", "    文"]);
+        // Short text stays on one line
+        assert_eq!(text(wrap_line(spans("tea"), 40, 4)), ["[ ] tea"]);
+    }
+
+    #[test]
+    fn long_todos_wrap() {
+        let mut app = app_with(3);
+        app.lists[0].todos[0].text = "a todo with far too much text to fit on one line of the list".to_string();
+        app.wrap = true;
+        let rows = screen(40, 16, &mut app);
+        let first = rows.iter().position(|row| row.contains("a todo with")).unwrap();
+        assert!(rows[first + 1].contains("text to fit") && !rows[first + 1].contains("[ ]"));
+        assert!(rows.iter().any(|row| row.contains("todo number 2")));
+
+        // A click on the second line of the wrapped todo selects it, and one on
+        // the line after selects the next todo
+        app.state_mut().select(Some(2));
+        click(&mut app, 8, first as u16 + 1);
+        assert_eq!(app.state().selected(), Some(0));
+        assert!(!app.todos()[0].is_closed());
+        let next = rows.iter().position(|row| row.contains("todo number 2")).unwrap();
+        click(&mut app, 8, next as u16);
+        assert_eq!(app.state().selected(), Some(1));
+
+        // W cuts long todos off again
+        press(&mut app, KeyCode::Char('W'));
+        assert!(!app.wrap);
+    }
+
+    #[test]
+    fn table_view_sorts_by_column() {
+        let mut app = app_with(3);
+        app.lists[0].todos[0].tags = vec!["work".to_string()];
+        app.lists[0].todos[2].tags = vec!["home".to_string()];
+        press(&mut app, KeyCode::Char('V'));
+        assert!(app.table);
+        assert_snapshot("table_view", &mut app);
+
+        // The number of the tags column sorts by tags, and again goes back
+        press(&mut app, KeyCode::Char('5'));
+        assert_eq!(app.sort_mode, SortMode::Tags);
+        assert_eq!(app.visible, [vec![2], vec![0], vec![1]]);
+        press(&mut app, KeyCode::Char('5'));
+        assert_eq!(app.sort_mode, SortMode::Manual);
+
+        // A click on a column's title sorts by it, one on a row selects it
+        let (title, top) = (app.table_columns[1].0, app.list_area.y);
+        click(&mut app, title + 1, top + 1);
+        assert_eq!(app.sort_mode, SortMode::Alphabetical);
+        click(&mut app, title + 1, top + 3);
+        assert_eq!(app.state().selected(), Some(1));
+    }
+
+    #[test]
+    fn icon_sets_replace_every_symbol() {
+        let mut app = app_with(2);
+        app.lists[0].todos[0].children = vec![TodoItem::new("a subtask")];
+        app.lists[0].todos[0].collapsed = true;
+        app.lists[0].todos[0].notes = "some notes".to_string();
+        app.lists[0].todos[1].status = Status::Done;
+        app.refresh_view();
+        let rows = screen(60, 12, &mut app);
+        assert!(rows.iter().any(|row| row.contains("► ▸ [ ]     todo number 1 0/1 ✎")));
+        assert!(rows.iter().any(|row| row.contains("[✓]     todo number 2")));
+
+        app.icon_set = IconSet::Ascii;
+        let rows = screen(60, 12, &mut app);
+        assert!(rows.iter().any(|row| row.contains("> + [ ]     todo number 1 0/1 (n)")));
+        assert!(rows.iter().any(|row| row.contains("[x]     todo number 2")));
+        // Nothing in the rows is outside ASCII but the list's border
+        let todos = rows.iter().filter(|row| row.contains("todo number"));
+        assert!(todos.flat_map(|row| row.chars()).all(|c| c.is_ascii() || c == '│'));
+    }
+
+    #[test]
+    fn completion_shows_as_a_gauge_and_per_project() {
+        let mut app = app_with(3);
+        app.lists[0].todos[0].project = Some("home".to_string());
+        app.lists[0].todos[1].project = Some("home".to_string());
+        app.lists[0].todos[0].status = Status::Done;
+        app.group_by_project = true;
+        app.refresh_view();
+        let rows = screen(80, 12, &mut app);
+        assert!(rows[0].contains("1/3 done · 33%"));
+        assert!(rows.iter().any(|row| row.contains("+home 1/2 · 50%")));
+        assert!(rows.iter().any(|row| row.contains("No project 0/1 · 0%")));
+        assert_eq!(percent(0, 0), 0);
+    }
+
+    #[test]
+    fn toasts_stack_in_the_corner_and_go_away() {
+        let mut app = app_with(3);
+        press(&mut app, KeyCode::Char('d'));
+        app.toast(ToastKind::Error, "Sync failed: offline".to_string());
+        let rows = screen(80, 16, &mut app);
+        let deleted = rows.iter().position(|row| row.contains("Deleted 'todo number 1' - press u to undo")).unwrap();
+        let failed = rows.iter().position(|row| row.contains("Sync failed: offline")).unwrap();
+        // Newest at the bottom, right-aligned inside the list's border
+        assert_eq!(failed, deleted + 3);
+        assert!(rows[failed].ends_with("│ Sync failed: offline ││"));
+
+        // The same message again doesn't stack, and only a few show at once
+        app.toast(ToastKind::Error, "Sync failed: offline".to_string());
+        assert_eq!(app.toasts.len(), 2);
+        for n in 0..TOAST_LIMIT {
+            app.toast(ToastKind::Success, format!("Saved {}", n));
+        }
+        assert_eq!(app.toasts.len(), TOAST_LIMIT);
+        assert_eq!(app.toasts[0].text, "Saved 0");
+
+        // They go on the tick after their time is up
+        assert!(!app.expire_toasts());
+        app.toasts[0].until = std::time::Instant::now();
+        assert!(app.expire_toasts());
+        assert_eq!(app.toasts.len(), TOAST_LIMIT - 1);
+        assert!(!screen(80, 16, &mut app).iter().any(|row| row.contains("Saved 0")));
+    }
+
+    #[test]
+    fn tags_with_a_style_are_chips() {
+        assert_eq!(TagStyle::parse("red"), Ok(TagStyle { color: Some(Color::Red), icon: None }));
+        assert_eq!(TagStyle::parse("💼"), Ok(TagStyle { color: None, icon: Some("💼".to_string()) }));
+        let style = TagStyle::parse("light blue 💼").unwrap();
+        assert_eq!(style, TagStyle { color: Some(Color::LightBlue), icon: Some("💼".to_string()) });
+        assert_eq!(TagStyle::parse(&style.to_string()), Ok(style));
+        assert_eq!(TagStyle::parse("redd"), Err("unknown color 'redd'".to_string()));
+        assert_eq!(TagStyle::parse("purplish 💼"), Err("unknown color 'purplish'".to_string()));
+
+        // Set from the tag picker, the tag turns into a chip in the list
+        let mut app = app_with(1);
+        app.lists[0].todos[0].tags = vec!["work".to_string()];
+        app.refresh_view();
+        press(&mut app, KeyCode::Char('t'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('c'));
+        for c in "blue 💼".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::TagPicker);
+        press(&mut app, KeyCode::Esc);
+        let theme = app.theme().clone();
+        let chip = tag_span(&app, &theme, "work");
+        assert_eq!(chip.content, " 💼 work ");
+        assert_eq!(chip.style, Style::default().fg(Color::White).bg(Color::Blue));
+        assert!(screen(60, 8, &mut app).iter().any(|row| row.contains("todo number 1  💼 work ")));
+    }
+
+    #[test]
+    fn row_format_lines_up_fields() {
+        let mut app = app_with(2);
+        app.lists[0].todos[0].text = "a todo longer than its column".to_string();
+        app.lists[0].todos[1].tags = vec!["home".to_string()];
+        app.row_format = crate::row_format::RowFormat::parse("{text:14}|{tags:>6}| {checkbox}").unwrap();
+        let rows = screen(40, 8, &mut app);
+        // Text past its width is cut off, and fields with a width keep their
+        // columns even when the todo has nothing to show in them
+        assert!(rows.iter().any(|row| row.contains("a todo longer…|      | [ ]")));
+        assert!(rows.iter().any(|row| row.contains("todo number 2 | #home| [ ]")));
+    }
+
+    #[test]
+    fn help_overlay() {
+        let mut app = app_with(3);
+        press(&mut app, KeyCode::Char('?'));
+        assert_eq!(app.mode, Mode::Help);
+        assert_snapshot("help_overlay", &mut app);
+    }
+
+    #[test]
+    fn small_terminals() {
+        // No margin, and typing happens on a single line
+        let mut app = app_with(12);
+        press(&mut app, KeyCode::Char('a'));
+        for c in "call mum".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_snapshot_sized("compact_input", &mut app, 40, 12);
+
+        // Below the minimum only the notice is drawn, and only quitting works
+        let mut app = app_with(3);
+        update(&mut app, Message::Resize(20, 6));
+        assert!(app.too_small);
+        assert_snapshot_sized("too_small", &mut app, 20, 6);
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.todos().len(), 3);
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.should_quit);
+
+        // Growing again brings the list back
+        let mut app = app_with(3);
+        update(&mut app, Message::Resize(20, 6));
+        update(&mut app, Message::Resize(80, 24));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.state().selected(), Some(1));
+    }
+}
@@ -0,0 +1,498 @@
+//! The single entry point for changing the app: everything the event loop
+//! reads becomes a Message, and `update` applies it
+//! Rendering never changes state and `update` never draws, so the logic can be
+//! driven without a terminal
+
+use crossterm::event::{KeyEvent, MouseEvent};
+
+use crate::{
+    app::{App, TodoPath},
+    input::{handle_key, handle_mouse, Key},
+    ui::too_small,
+};
+
+/// Something that happened, or a change to make, as a plain value
+/// Keys are read by the mode the app is in and usually end up as one of the
+/// other messages, which can also be sent directly
+#[derive(Clone, Debug)]
+pub enum Message {
+    /// A key press from the terminal
+    Key(KeyEvent),
+    /// A click or scroll in the terminal
+    Mouse(MouseEvent),
+    /// Text pasted into the terminal
+    Paste(String),
+    /// The terminal changed size, to this many columns and rows
+    Resize(u16, u16),
+    /// Time has passed - fires due notifications, the pomodoro timer and
+    /// counts that were typed on their own
+    Tick,
+    /// Adds a todo written in quick-add syntax at the end of the current list
+    Add(String),
+    /// Adds a todo written in quick-add syntax as the last subtask of another
+    AddChild(TodoPath, String),
+    /// Rewrites a todo's text, tags, priority and due date from quick-add syntax
+    Edit(TodoPath, String),
+    /// Toggles completion of the marked todos, or the selected one
+    Toggle,
+    /// Moves the marked todos, or the selected one, to the trash
+    Delete,
+    /// Moves the selected todo down (positive) or up among its siblings
+    Move(isize),
+    /// Selects the next todo, wrapping around at the end
+    Next,
+    /// Selects the previous todo, wrapping around at the start
+    Previous,
+    /// Shows the list at this index
+    SwitchList(usize),
+    Undo,
+    Redo,
+    /// Asks the event loop to exit
+    Quit,
+}
+
+/// Applies a message to the app state
+pub fn update(app: &mut App, message: Message) {
+    match message {
+        Message::Key(key) => {
+            // Typed text ends up in here too, which is why it's only debug
+            tracing::debug!("key {} in {}", Key::from_event(key).label(), app.mode.label());
+            handle_key(app, key)
+        }
+        Message::Mouse(mouse) => handle_mouse(app, mouse),
+        Message::Paste(text) => app.paste(&text),
+        Message::Resize(width, height) => app.too_small = too_small(width, height),
+        Message::Tick => {
+            tick(app);
+        }
+        Message::Add(text) => app.add(&text),
+        Message::AddChild(parent, text) => app.add_child(parent, &text),
+        Message::Edit(path, text) => app.edit(path, &text),
+        Message::Toggle => app.toggle_completed(),
+        Message::Delete => app.delete_selected(),
+        Message::Move(delta) => app.move_selected(delta),
+        Message::Next => app.next(),
+        Message::Previous => app.previous(),
+        Message::SwitchList(index) => app.switch_list(index),
+        Message::Undo => app.undo(),
+        Message::Redo => app.redo(),
+        Message::Quit => app.should_quit = true,
+    }
+}
+
+/// What a Tick does: fires due notifications, runs the timers, takes down
+/// toasts that have been up long enough, brings back snoozed todos whose
+/// time has come and picks up background work
+/// Returns whether any of it shows on screen - the event loop redraws after
+/// every other message, but an idle interface is left alone until a timer
+/// goes off or something comes back
+pub(crate) fn tick(app: &mut App) -> bool {
+    // Each only does work when its moment has come, and they all run -
+    // `|` rather than `||`, so one with news doesn't hold up the next
+    app.check_due_notifications();
+    app.tick_pomodoro()
+        | app.expire_toasts()
+        | app.tick_snoozes()
+        | app.resolve_pending_count()
+        | app.tick_saving()
+        | app.tick_sync()
+        | app.tick_issues()
+        | app.tick_mail()
+        | app.tick_hooks()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Date, Field, Mode, Priority, Status, Timestamp, TodoItem, HISTORY_LIMIT};
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    /// An app with these todos and the first one selected, the way the
+    /// interface shows a list it just loaded
+    /// Nothing sends a Tick, so nothing is ever saved to the path
+    fn app_with(texts: &[&str]) -> App {
+        let mut app = App::new(std::env::temp_dir().join("todo-tui-update-test.json"));
+        app.lists[0].todos = texts.iter().map(|text| TodoItem::new(text)).collect();
+        app.refresh_view();
+        app.state_mut().select(Some(0));
+        app
+    }
+
+    /// Sends a key press the way `run_app` does
+    fn press(app: &mut App, code: KeyCode) {
+        update(app, Message::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    /// Draws a frame and returns it as one string per row
+    fn render(app: &mut App) -> Vec<String> {
+        render_sized(app, 80, 20)
+    }
+
+    fn render_sized(app: &mut App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| crate::ui::ui(f, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    /// The row a todo is drawn on, if it's drawn at all
+    fn row_of<'a>(screen: &'a [String], text: &str) -> Option<&'a String> {
+        screen.iter().find(|row| row.contains(text))
+    }
+
+    fn texts(app: &App) -> Vec<&str> {
+        app.lists[0].todos.iter().map(|todo| todo.text.as_str()).collect()
+    }
+
+    #[test]
+    fn navigation_moves_the_highlight() {
+        let mut app = app_with(&["buy milk", "call mum", "water plants"]);
+        assert!(row_of(&render(&mut app), "buy milk").unwrap().contains("► "));
+
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected_path(), Some(vec![2]));
+        let screen = render(&mut app);
+        assert!(row_of(&screen, "water plants").unwrap().contains("► "));
+        assert!(!row_of(&screen, "buy milk").unwrap().contains("► "));
+
+        // Moving past either end wraps around
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected_path(), Some(vec![0]));
+        press(&mut app, KeyCode::Char('k'));
+        assert_eq!(app.selected_path(), Some(vec![2]));
+        // A count repeats the motion
+        type_text(&mut app, "2k");
+        assert_eq!(app.selected_path(), Some(vec![0]));
+        assert!(row_of(&render(&mut app), "buy milk").unwrap().contains("► "));
+    }
+
+    #[test]
+    fn vim_counts_and_sequences_build_up_over_several_keys() {
+        let mut app = app_with(&["a", "b", "c", "d", "e", "f"]);
+        type_text(&mut app, "3j");
+        assert_eq!(app.selected_path(), Some(vec![3]));
+        type_text(&mut app, "gg");
+        assert_eq!(app.selected_path(), Some(vec![0]));
+        // With a count, gg and G go to that row rather than an end
+        type_text(&mut app, "4gg");
+        assert_eq!(app.selected_path(), Some(vec![3]));
+        type_text(&mut app, "2G");
+        assert_eq!(app.selected_path(), Some(vec![1]));
+        type_text(&mut app, "99G");
+        assert_eq!(app.selected_path(), Some(vec![5]));
+        type_text(&mut app, "G");
+        assert_eq!(app.selected_path(), Some(vec![5]));
+        // A leading 0 isn't a count, and counts stop growing at the cap
+        press(&mut app, KeyCode::Char('0'));
+        assert_eq!(app.pending_keys.label(), None);
+        type_text(&mut app, "123456");
+        assert_eq!(app.pending_keys.count, Some(9999));
+        type_text(&mut app, "gg");
+        assert_eq!(app.selected_path(), Some(vec![5]));
+        type_text(&mut app, "gg");
+
+        // Half a page is half the rows the list has on screen
+        app.list_height = 4;
+        update(&mut app, Message::Key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+        assert_eq!(app.selected_path(), Some(vec![2]));
+        update(&mut app, Message::Key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL)));
+        assert_eq!(app.selected_path(), Some(vec![0]));
+
+        // The status bar shows what's been typed, and Esc drops it unfinished
+        type_text(&mut app, "2g");
+        assert_eq!(app.pending_keys.label().as_deref(), Some("2g"));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.pending_keys.label(), None);
+        assert_eq!(app.selected_path(), Some(vec![0]));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected_path(), Some(vec![1]));
+        // A second key that completes no sequence is swallowed with the first
+        type_text(&mut app, "gj");
+        assert_eq!(app.selected_path(), Some(vec![1]));
+        assert_eq!(app.pending_keys.label(), None);
+
+        // A count repeats a change, and . repeats it with the same count
+        type_text(&mut app, "2d");
+        assert_eq!(texts(&app), ["a", "d", "e", "f"]);
+        press(&mut app, KeyCode::Char('.'));
+        assert_eq!(texts(&app), ["a", "f"]);
+        // Each repeat is a step of its own to undo
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(texts(&app), ["a", "e", "f"]);
+        type_text(&mut app, "3u");
+        assert_eq!(texts(&app), ["a", "b", "c", "d", "e", "f"]);
+    }
+
+    #[test]
+    fn a_count_typed_on_its_own_switches_lists_once_it_times_out() {
+        let mut app = app_with(&["buy milk"]);
+        app.lists.push(crate::app::TodoList::new("Work"));
+        press(&mut app, KeyCode::Char('2'));
+        // Nothing happens while another key could still follow
+        update(&mut app, Message::Tick);
+        assert_eq!(app.current, 0);
+        app.pending_keys.since = Some(std::time::Instant::now() - std::time::Duration::from_secs(1));
+        update(&mut app, Message::Tick);
+        assert_eq!(app.current, 1);
+        assert_eq!(app.pending_keys.label(), None);
+    }
+
+    #[test]
+    fn input_mode_adds_a_todo_or_cancels() {
+        let mut app = app_with(&["buy milk"]);
+        press(&mut app, KeyCode::Char('a'));
+        assert_eq!(app.mode, Mode::Input);
+        type_text(&mut app, "call mum #family");
+        // Typed keys go into the input box instead of running commands
+        assert_eq!(texts(&app), ["buy milk"]);
+        let screen = render(&mut app);
+        assert!(screen.iter().any(|row| row.contains(" INSERT ")));
+        assert!(row_of(&screen, "call mum #family").is_some());
+
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(texts(&app), ["buy milk", "call mum"]);
+        assert_eq!(app.lists[0].todos[1].tags, ["family"]);
+        let screen = render(&mut app);
+        assert!(screen.iter().any(|row| row.contains(" NORMAL ")));
+        assert!(row_of(&screen, "call mum").is_some());
+
+        // Esc throws the text away
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "never mind");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(texts(&app), ["buy milk", "call mum"]);
+        assert!(row_of(&render(&mut app), "never mind").is_none());
+    }
+
+    #[test]
+    fn deleting_moves_the_todo_to_the_trash() {
+        let mut app = app_with(&["buy milk", "call mum", "water plants"]);
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('d'));
+        assert_eq!(texts(&app), ["buy milk", "water plants"]);
+        assert_eq!(app.trash()[0].text, "call mum");
+        // The selection stays on the row, which is now the next todo
+        let screen = render(&mut app);
+        // Only the toast saying it went still mentions it
+        assert!(row_of(&screen, "call mum").unwrap().contains("Deleted 'call mum' - press u to undo"));
+        assert!(row_of(&screen, "water plants").unwrap().contains("► "));
+
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(texts(&app), ["buy milk", "call mum", "water plants"]);
+        assert!(app.trash().is_empty());
+        assert!(row_of(&render(&mut app), "call mum").is_some());
+
+        // Deleting the last todo leaves the hint for an empty list
+        let mut app = app_with(&["buy milk"]);
+        press(&mut app, KeyCode::Char('d'));
+        assert!(texts(&app).is_empty());
+        assert_eq!(app.selected_path(), None);
+        assert!(row_of(&render(&mut app), "No todos yet").is_some());
+    }
+
+    #[test]
+    fn starred_todos_come_first() {
+        let mut app = app_with(&["buy milk", "call mum", "water plants"]);
+        press(&mut app, KeyCode::Char('G'));
+        press(&mut app, KeyCode::Char('*'));
+        // The list itself keeps its order, only the view puts the star on top
+        assert_eq!(texts(&app), ["buy milk", "call mum", "water plants"]);
+        assert_eq!(app.visible, [vec![2], vec![0], vec![1]]);
+        // The selection goes along with the todo
+        assert_eq!(app.selected_path(), Some(vec![2]));
+        let screen = render(&mut app);
+        assert!(row_of(&screen, "water plants").unwrap().contains("[ ] ★ "));
+        assert!(!row_of(&screen, "buy milk").unwrap().contains('★'));
+
+        // Stars beat any sort order
+        press(&mut app, KeyCode::Char('s'));
+        app.lists[0].todos[1].text = "a call".to_string();
+        app.refresh_view();
+        assert_eq!(app.visible, [vec![2], vec![1], vec![0]]);
+
+        // Marked todos get a star together, and lose it together once all have one
+        press(&mut app, KeyCode::Home);
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('*'));
+        assert!(app.lists[0].todos[1..].iter().all(|todo| todo.starred));
+        press(&mut app, KeyCode::Char('u'));
+        assert!(!app.lists[0].todos[1].starred);
+        assert!(app.lists[0].todos[2].starred);
+    }
+
+    #[test]
+    fn snoozed_todos_leave_the_list_until_they_come_back() {
+        let mut app = app_with(&["buy milk", "call mum"]);
+        press(&mut app, KeyCode::Char('H'));
+        type_text(&mut app, "tomorrow");
+        assert!(row_of(&render(&mut app), "until ").is_some());
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Normal);
+        let tomorrow = Timestamp::at(Date::today().add_days(1), None);
+        assert_eq!(app.lists[0].todos[0].snoozed_until, Some(tomorrow));
+        assert_eq!(app.visible, [vec![1]]);
+        let screen = render(&mut app);
+        assert!(row_of(&screen, "1 snoozed").is_some());
+        assert!(row_of(&screen, "Snoozed 'buy milk' until").is_some());
+
+        // A moment that has passed wouldn't hide anything
+        press(&mut app, KeyCode::Char('H'));
+        type_text(&mut app, "2000-01-01");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Input);
+        assert!(app.input_error.is_some());
+        press(&mut app, KeyCode::Esc);
+
+        // The popup lists what's snoozed, and r brings it back early
+        press(&mut app, KeyCode::Char('U'));
+        assert_eq!(app.mode, Mode::Snoozed);
+        assert!(row_of(&render(&mut app), "buy milk").unwrap().contains("until "));
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(app.mode, Mode::Snoozed);
+        assert_eq!(app.visible, [vec![0], vec![1]]);
+        assert!(row_of(&render(&mut app), "Nothing is snoozed").is_some());
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(app.visible, [vec![1]]);
+        press(&mut app, KeyCode::Esc);
+
+        // Once its time comes, the next tick shows it again
+        assert!(!app.tick_snoozes());
+        let now = Timestamp::now();
+        app.lists[0].todos[0].snoozed_until = Some(now);
+        app.next_wake = Some(now);
+        assert!(app.tick_snoozes());
+        assert_eq!(app.visible, [vec![0], vec![1]]);
+        assert_eq!(app.next_wake, None);
+    }
+
+    #[test]
+    fn start_dates_and_the_someday_list_keep_todos_out_of_the_way() {
+        let mut app = app_with(&["buy milk"]);
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "plan trip start:next week");
+        press(&mut app, KeyCode::Enter);
+        let next_week = Date::today().add_days(7);
+        assert_eq!(app.lists[0].todos[1].text, "plan trip");
+        assert_eq!(app.lists[0].todos[1].scheduled, Some(next_week));
+        assert_eq!(app.lists[0].todos[1].quick_add_text(), format!("plan trip start:{}", next_week));
+        // Not actionable yet, so only the status bar mentions it
+        assert_eq!(app.visible, [vec![0]]);
+        assert_eq!(app.next_wake, Some(Timestamp::from_date(next_week)));
+        let screen = render_sized(&mut app, 120, 20);
+        assert!(row_of(&screen, "plan trip").is_none());
+        assert!(row_of(&screen, "1 not started").is_some());
+        press(&mut app, KeyCode::Char('Y'));
+        assert_eq!(app.visible, [vec![0], vec![1]]);
+        assert!(row_of(&render(&mut app), "plan trip").unwrap().contains("starts "));
+        press(&mut app, KeyCode::Char('Y'));
+
+        // M parks a todo on the someday list, made the first time
+        press(&mut app, KeyCode::Char('M'));
+        assert_eq!(app.lists.len(), 2);
+        assert!(app.lists[1].someday);
+        assert_eq!(app.lists[1].todos[0].text, "buy milk");
+        assert!(app.lists[0].todos.iter().all(|todo| todo.text != "buy milk"));
+        assert_eq!(app.current, 0);
+        assert!(row_of(&render(&mut app), "2 ☾ Someday").is_some());
+        // From there it goes back, and undo takes the list away again
+        update(&mut app, Message::SwitchList(1));
+        press(&mut app, KeyCode::Char('M'));
+        assert!(app.lists[1].todos.is_empty());
+        assert_eq!(app.lists[0].todos.last().unwrap().text, "buy milk");
+        press(&mut app, KeyCode::Char('u'));
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(app.lists.len(), 1);
+        assert_eq!(app.lists[0].todos[0].text, "buy milk");
+    }
+
+    #[test]
+    fn the_daily_review_asks_about_each_todo_left_overdue() {
+        let mut app = app_with(&["pay rent", "call mum", "water plants", "file taxes", "old thing", "book flights"]);
+        let today = Date::today();
+        for todo in &mut app.lists[0].todos {
+            todo.due = Some(today.add_days(-1));
+        }
+        app.lists[0].todos[2].children.push(TodoItem { due: Some(today.add_days(-1)), ..TodoItem::new("buy soil") });
+        // Due before the last review or not open, so not asked about
+        app.lists[0].todos[4].due = Some(today.add_days(-5));
+        app.lists[0].todos[5].status = Status::Done;
+        app.start_review();
+        assert_eq!(app.mode, Mode::Review);
+        assert_eq!(app.reviewed, Some(today));
+        assert_eq!(app.review.len(), 5);
+        let screen = render(&mut app);
+        assert!(row_of(&screen, "Daily review (5 left)").is_some());
+        assert!(row_of(&screen, "Was due").is_some());
+
+        // t moves it to today, x drops it along with the subtasks asked about later
+        press(&mut app, KeyCode::Char('t'));
+        assert_eq!(app.lists[0].todos[0].due, Some(today));
+        press(&mut app, KeyCode::Char('s'));
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.lists[0].trash[0].text, "water plants");
+        assert_eq!(app.review.len(), 1);
+        // d asks for the day, then goes on - and after the last one it's over
+        assert_eq!(app.review[0], (0, vec![2]));
+        press(&mut app, KeyCode::Char('d'));
+        assert_eq!(app.mode, Mode::Input);
+        type_text(&mut app, "tomorrow");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.lists[0].todos[2].text, "file taxes");
+        assert_eq!(app.lists[0].todos[2].due, Some(today.add_days(1)));
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.lists[0].todos[1].due, Some(today.add_days(-1)));
+
+        // Once a day is enough
+        app.start_review();
+        assert_eq!(app.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn changes_show_in_the_history() {
+        let mut app = app_with(&["buy milk"]);
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Char('p'));
+        // Undoing is a change of its own rather than taking one back
+        press(&mut app, KeyCode::Char('u'));
+        let fields: Vec<_> = app.lists[0].todos[0].history.iter().map(|change| change.field.clone()).collect();
+        assert_eq!(
+            fields,
+            [Field::Status(Status::Done), Field::Priority(Priority::Low), Field::Priority(Priority::None)]
+        );
+
+        press(&mut app, KeyCode::Tab);
+        let screen = render_sized(&mut app, 100, 30);
+        let history = screen.iter().position(|row| row.contains("History")).unwrap();
+        // Newest first
+        assert!(screen[history + 1].contains("Priority None"));
+        assert!(screen[history + 3].contains("Marked Done"));
+        assert!(row_of(&screen, "Changed:").is_some());
+
+        // Folding and the like aren't changes, and the oldest ones drop off
+        let mut todo = app.lists[0].todos[0].clone();
+        let before = todo.clone();
+        todo.collapsed = true;
+        todo.record(&before, Timestamp::now());
+        assert_eq!(todo.history.len(), 3);
+        for i in 0..HISTORY_LIMIT {
+            let before = todo.clone();
+            todo.text = format!("buy milk {}", i);
+            todo.record(&before, Timestamp::now());
+        }
+        assert_eq!(todo.history.len(), HISTORY_LIMIT);
+        assert!(todo.history.iter().all(|change| change.field == Field::Text));
+    }
+}
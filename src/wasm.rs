@@ -0,0 +1,216 @@
+//! WASM plugins, the sandboxed kind - `*.wasm` files in the plugins folder,
+//! written in any language that compiles to WebAssembly
+//! A module gets each todo as it's added, completed, edited, moved or
+//! deleted and can hand back a changed one. Nothing is linked in, so it has no way to the filesystem,
+//! the network or anything else outside its own memory, and every event has
+//! a budget of fuel and memory - a stuck or greedy plugin fails rather than
+//! hanging the app
+//! The module exports:
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, room for `len` bytes the event is written to
+//! - `on_event(ptr: i32, len: i32) -> i64`, given the event as JSON,
+//!   `{"event": "add" | "complete" | "edit" | "move" | "delete", "todo": {...}}`,
+//!   and returning where its reply is as `ptr << 32 | len`, or 0 to leave the
+//!   todo alone
+//!
+//! The reply is `{"todo": {...}}`, the todo as it should be - except after a
+//! delete, which goes ahead whatever the reply
+//! Built with the `wasm` feature, which is on by default
+
+use serde::Deserialize;
+use serde_json::json;
+use wasmtime::{Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::app::TodoItem;
+
+/// Fuel for an event, roughly the instructions it may run - far more than
+/// changing a todo takes, and still over in a moment
+const FUEL: u64 = 100_000_000;
+
+/// Memory a plugin can grow to
+const MEMORY: usize = 64 << 20;
+
+/// Longest reply read back
+const MAX_REPLY: usize = 1 << 20;
+
+/// What a plugin answers an event with
+#[derive(Deserialize)]
+struct Reply {
+    todo: Option<TodoItem>,
+}
+
+/// A loaded module with the exports of the ABI
+/// The instance lives as long as the app, so a plugin can keep state
+/// between events
+struct WasmPlugin {
+    name: String,
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_event: TypedFunc<(i32, i32), i64>,
+}
+
+/// The WASM plugins, in the order they were loaded
+#[derive(Default)]
+pub(crate) struct WasmPlugins {
+    /// Made with the first plugin, so without any nothing is set up
+    engine: Option<Engine>,
+    plugins: Vec<WasmPlugin>,
+}
+
+/// The cause of a wasmtime error, which says what went wrong where the
+/// error itself often only says where
+fn message(err: wasmtime::Error) -> String {
+    err.root_cause().to_string().lines().next().unwrap_or_default().to_string()
+}
+
+impl WasmPlugins {
+    /// Compiles and starts a module, `.wasm` or its text form
+    pub(crate) fn load(&mut self, name: &str, bytes: &[u8]) -> Result<(), String> {
+        let engine = match &self.engine {
+            Some(engine) => engine.clone(),
+            None => {
+                let engine = Engine::new(Config::new().consume_fuel(true)).map_err(message)?;
+                self.engine.insert(engine).clone()
+            }
+        };
+        let module = Module::new(&engine, bytes).map_err(message)?;
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY).instances(1).build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        // A start function runs while instantiating, so it needs fuel too
+        store.set_fuel(FUEL).map_err(message)?;
+        // An empty linker - a module importing anything, like WASI's files, doesn't load
+        let instance = Linker::new(&engine).instantiate(&mut store, &module).map_err(message)?;
+        let memory = instance.get_memory(&mut store, "memory").ok_or("doesn't export its memory")?;
+        let alloc = instance.get_typed_func(&mut store, "alloc").map_err(message)?;
+        let on_event = instance.get_typed_func(&mut store, "on_event").map_err(message)?;
+        self.plugins.push(WasmPlugin { name: name.to_string(), store, memory, alloc, on_event });
+        Ok(())
+    }
+
+    /// Whether no plugins are loaded
+    pub(crate) fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// The todo after every plugin had the event, each getting what the one
+    /// before it returned
+    pub(crate) fn event(&mut self, event: &str, mut todo: TodoItem) -> Result<TodoItem, String> {
+        for plugin in &mut self.plugins {
+            if let Some(changed) = plugin.event(event, &todo).map_err(|err| format!("{}: {}", plugin.name, err))? {
+                todo = changed;
+            }
+        }
+        Ok(todo)
+    }
+}
+
+impl WasmPlugin {
+    /// Hands the plugin an event and reads back the todo it returns, if any
+    fn event(&mut self, event: &str, todo: &TodoItem) -> Result<Option<TodoItem>, String> {
+        let event = json!({ "event": event, "todo": todo }).to_string();
+        let len = i32::try_from(event.len()).map_err(|_| "the todo is too big to hand over")?;
+        self.store.set_fuel(FUEL).map_err(message)?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(message)?;
+        self.memory.write(&mut self.store, ptr as u32 as usize, event.as_bytes()).map_err(|err| err.to_string())?;
+        let reply = self.on_event.call(&mut self.store, (ptr, len)).map_err(message)? as u64;
+        if reply == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = ((reply >> 32) as usize, (reply & 0xffff_ffff) as usize);
+        if len > MAX_REPLY {
+            return Err(format!("its reply of {} bytes is too long", len));
+        }
+        let mut bytes = vec![0; len];
+        self.memory.read(&self.store, ptr, &mut bytes).map_err(|err| err.to_string())?;
+        let reply: Reply = serde_json::from_slice(&bytes).map_err(|err| format!("bad reply: {}", err))?;
+        Ok(reply.todo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A plugin that answers every event with the same todo, kept at 2048,
+    /// and takes the events at 1024
+    fn replying(reply: &str) -> String {
+        let data = reply.replace('"', "\\\"");
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 2048) "{data}")
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "on_event") (param i32 i32) (result i64)
+                    i64.const {}))"#,
+            2048 << 32 | reply.len()
+        )
+    }
+
+    #[test]
+    fn plugins_change_todos_through_their_memory() {
+        let mut plugins = WasmPlugins::default();
+        plugins.load("rename.wat", replying(r#"{"todo":{"text":"renamed","tags":["wasm"]}}"#).as_bytes()).unwrap();
+        let changed = plugins.event("add", TodoItem::new("call mom")).unwrap();
+        assert_eq!(changed.text, "renamed");
+        assert_eq!(changed.tags, ["wasm"]);
+
+        // Returning 0 leaves the todo alone, as does a reply without a todo
+        let mut plugins = WasmPlugins::default();
+        let untouched = r#"(module (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "on_event") (param i32 i32) (result i64) i64.const 0))"#;
+        plugins.load("untouched.wat", untouched.as_bytes()).unwrap();
+        plugins.load("empty.wat", replying("{}").as_bytes()).unwrap();
+        assert_eq!(plugins.event("complete", TodoItem::new("call mom")).unwrap().text, "call mom");
+    }
+
+    #[test]
+    fn plugins_are_told_which_event_it_is() {
+        // The event's name starts ten bytes in, after `{"event":"`
+        let moves = r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 2048) "{\"todo\":{\"text\":\"moved\"}}")
+            (func (export "alloc") (param i32) (result i32) i32.const 1024)
+            (func (export "on_event") (param $ptr i32) (param i32) (result i64)
+                (if (result i64) (i32.eq (i32.load8_u offset=10 (local.get $ptr)) (i32.const 109))
+                    (then (i64.const 0x80000000019))
+                    (else (i64.const 0)))))"#;
+        let mut plugins = WasmPlugins::default();
+        plugins.load("moves.wat", moves.as_bytes()).unwrap();
+        for event in ["add", "complete", "edit", "delete"] {
+            assert_eq!(plugins.event(event, TodoItem::new("x")).unwrap().text, "x", "{}", event);
+        }
+        assert_eq!(plugins.event("move", TodoItem::new("x")).unwrap().text, "moved");
+    }
+
+    #[test]
+    fn plugins_get_nothing_from_outside_and_only_so_much_time() {
+        let mut plugins = WasmPlugins::default();
+        let wasi = r#"(module
+            (import "wasi_snapshot_preview1" "path_open"
+                (func (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (memory (export "memory") 1))"#;
+        let err = plugins.load("files.wat", wasi.as_bytes()).unwrap_err();
+        assert!(err.contains("path_open"), "{}", err);
+        assert!(plugins.load("no exports.wat", b"(module)").is_err());
+
+        let stuck = r#"(module (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "on_event") (param i32 i32) (result i64) (loop (br 0)) i64.const 0))"#;
+        plugins.load("stuck.wat", stuck.as_bytes()).unwrap();
+        let Err(err) = plugins.event("add", TodoItem::new("x")) else {
+            panic!("a plugin that never stops ran to the end");
+        };
+        assert!(err.starts_with("stuck.wat: ") && err.contains("fuel"), "{}", err);
+
+        // A reply pointing past the end of memory is an error, not a crash
+        let mut plugins = WasmPlugins::default();
+        let wild = r#"(module (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "on_event") (param i32 i32) (result i64) i64.const 0xffff00000010))"#;
+        plugins.load("wild.wat", wild.as_bytes()).unwrap();
+        assert!(plugins.event("add", TodoItem::new("x")).is_err());
+    }
+}